@@ -284,8 +284,14 @@ class ContextCompressorAdapter:
     def __init__(self, compression_ratio: float = 0.3):
         self._impl = _RustContextCompressor(compression_ratio)
 
-    def compress_conversation(self, messages): return self._impl.compress_conversation(messages)
-    def extract_key_points(self, text): return self._impl.extract_key_points(text)
+    def compress_conversation(self, messages, legacy=True):
+        if RUST_AVAILABLE:
+            return self._impl.compress_conversation(messages, legacy=legacy)
+        return self._impl.compress_conversation(messages)
+    def extract_key_points(self, text):
+        if RUST_AVAILABLE:
+            return [sentence for sentence, _score, _offset in self._impl.extract_key_points(text)]
+        return self._impl.extract_key_points(text)
     def summarize_episodes(self, episodes, max_length=500): return self._impl.summarize_episodes(episodes, max_length)
     def estimate_tokens(self, text): return self._impl.estimate_tokens(text)
     def truncate_to_tokens(self, text, max_tokens): return self._impl.truncate_to_tokens(text, max_tokens)
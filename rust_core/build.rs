@@ -0,0 +1,600 @@
+//! Генерация kristina_core.pyi из исходников
+//!
+//! - Классы и функции — из регистрации в lib.rs (add_class / wrap_pyfunction)
+//! - Сигнатуры — из #[pyo3(signature = ...)] и типов аргументов Rust;
+//!   значения по умолчанию — литералы и константы, остальное → `...`
+//! - Типы: PyResult<T> → T, Option<T> → T | None, Vec → list, кортежи → tuple,
+//!   псевдонимы типов раскрываются, enum с IntoPyObject → объединение вариантов
+//! - *_async → asyncio.Future с результатом синхронного метода
+//! - Исключения — из create_exception!
+//! - Каждая сборка пишет стаб только в OUT_DIR: исходники не меняются
+//!   (сборка из read-only или vendored каталога, чистый git)
+//! - KRISTINA_CORE_STUBS=1 (`KRISTINA_CORE_STUBS=1 cargo build`) — явное
+//!   обновление kristina_core.pyi в каталоге крейта; maturin кладёт его в wheel
+//!   вместе с py.typed. Устаревший файл ловит тест stub_is_current в lib.rs
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use syn::{
+    Attribute, Expr, Fields, FnArg, GenericArgument, ImplItem, Item, Lit, Meta, Pat, PathArguments,
+    ReturnType, Type,
+};
+
+const MODULE: &str = "kristina_core";
+/// Переменная окружения: обновить kristina_core.pyi в каталоге крейта
+const STUBS_ENV: &str = "KRISTINA_CORE_STUBS";
+
+struct Param {
+    name: String,
+    ty: Type,
+}
+
+enum Kind {
+    Method,
+    Static,
+    New,
+    Getter,
+}
+
+struct Method {
+    name: String,
+    doc: String,
+    kind: Kind,
+    params: Vec<Param>,
+    signature: Option<TokenStream>,
+    ret: Option<Type>,
+}
+
+#[derive(Default)]
+struct Class {
+    doc: String,
+    fields: Vec<(String, Type, String)>,
+    methods: Vec<Method>,
+}
+
+#[derive(Default)]
+struct Source {
+    classes: HashMap<String, Class>,
+    functions: HashMap<String, Method>,
+    aliases: HashMap<String, Type>,
+    consts: HashMap<String, String>,
+    /// enum с собственным IntoPyObject → типы полей вариантов
+    unions: HashMap<String, Vec<Type>>,
+    converted: Vec<String>,
+    /// create_exception!: (имя, базовый класс, описание)
+    exceptions: Vec<(String, String, String)>,
+}
+
+// ── Разбор атрибутов ──
+
+fn doc(attrs: &[Attribute]) -> String {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) if nv.path.is_ident("doc") => match &nv.value {
+                Expr::Lit(lit) => match &lit.lit {
+                    Lit::Str(s) => Some(s.value()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').unwrap_or(&line).trim_end().to_string())
+        .collect();
+    lines.join("\n").trim().to_string()
+}
+
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().any(|attr| attr.path().is_ident(name))
+}
+
+fn pyo3_tokens(attrs: &[Attribute]) -> Vec<TokenTree> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("pyo3") || attr.path().is_ident("pyclass"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::List(list) => Some(list.tokens.clone()),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+/// Значение опции pyo3 вида `key = value` (сигнатура, name)
+fn pyo3_option(attrs: &[Attribute], key: &str) -> Option<TokenTree> {
+    let tokens = pyo3_tokens(attrs);
+    tokens.windows(3).find_map(|w| match (&w[0], &w[1]) {
+        (TokenTree::Ident(ident), TokenTree::Punct(p)) if ident == key && p.as_char() == '=' => Some(w[2].clone()),
+        _ => None,
+    })
+}
+
+/// Флаг pyo3 (`get`, `get_all`)
+fn pyo3_flag(attrs: &[Attribute], flag: &str) -> bool {
+    pyo3_tokens(attrs).iter().any(|t| matches!(t, TokenTree::Ident(ident) if ident == flag))
+}
+
+fn python_name(attrs: &[Attribute], rust_name: &str) -> String {
+    match pyo3_option(attrs, "name") {
+        Some(TokenTree::Literal(lit)) => lit.to_string().trim_matches('"').to_string(),
+        _ => rust_name.to_string(),
+    }
+}
+
+fn signature(attrs: &[Attribute]) -> Option<TokenStream> {
+    match pyo3_option(attrs, "signature") {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => Some(group.stream()),
+        _ => None,
+    }
+}
+
+// ── Сбор из исходников ──
+
+fn method(name: String, attrs: &[Attribute], sig: &syn::Signature, kind: Kind) -> Method {
+    let params = sig
+        .inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(typed) => match &*typed.pat {
+                Pat::Ident(ident) => Some(Param { name: ident.ident.to_string(), ty: (*typed.ty).clone() }),
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        })
+        .filter(|param| param.name != "slf" && param.name != "py" && !is_python(&param.ty))
+        .collect();
+    let ret = match &sig.output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => Some((**ty).clone()),
+    };
+    Method { name, doc: doc(attrs), kind, params, signature: signature(attrs), ret }
+}
+
+fn is_python(ty: &Type) -> bool {
+    matches!(last_segment(ty), Some((name, _)) if name == "Python")
+}
+
+fn literal(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Str(s) => Some(format!("{:?}", s.value())),
+            Lit::Char(c) => Some(format!("{:?}", c.value().to_string())),
+            Lit::Int(i) => Some(i.base10_digits().to_string()),
+            Lit::Float(f) => Some(f.base10_digits().to_string()),
+            Lit::Bool(b) => Some(if b.value { "True" } else { "False" }.to_string()),
+            _ => None,
+        },
+        Expr::Unary(unary) if matches!(unary.op, syn::UnOp::Neg(_)) => literal(&unary.expr).map(|v| format!("-{}", v)),
+        _ => None,
+    }
+}
+
+fn collect(items: &[Item], source: &mut Source) {
+    for item in items {
+        match item {
+            Item::Struct(item) if has_attr(&item.attrs, "pyclass") => {
+                let class = source.classes.entry(item.ident.to_string()).or_default();
+                class.doc = doc(&item.attrs);
+                let all = pyo3_flag(&item.attrs, "get_all");
+                if let Fields::Named(fields) = &item.fields {
+                    for field in &fields.named {
+                        if all || pyo3_flag(&field.attrs, "get") {
+                            let name = field.ident.as_ref().map(|i| i.to_string()).unwrap_or_default();
+                            class.fields.push((name, field.ty.clone(), doc(&field.attrs)));
+                        }
+                    }
+                }
+            }
+            Item::Impl(item) if has_attr(&item.attrs, "pymethods") => {
+                let Some((class_name, _)) = last_segment(&item.self_ty) else { continue };
+                let class = source.classes.entry(class_name).or_default();
+                for inner in &item.items {
+                    let ImplItem::Fn(f) = inner else { continue };
+                    let kind = if has_attr(&f.attrs, "new") {
+                        Kind::New
+                    } else if has_attr(&f.attrs, "getter") {
+                        Kind::Getter
+                    } else if has_attr(&f.attrs, "staticmethod") {
+                        Kind::Static
+                    } else {
+                        Kind::Method
+                    };
+                    let name = python_name(&f.attrs, &f.sig.ident.to_string());
+                    class.methods.push(method(name, &f.attrs, &f.sig, kind));
+                }
+            }
+            Item::Impl(item) => {
+                let converts = item
+                    .trait_
+                    .as_ref()
+                    .and_then(|(_, path, _)| path.segments.last())
+                    .is_some_and(|segment| segment.ident == "IntoPyObject");
+                if let (true, Some((name, _))) = (converts, last_segment(&item.self_ty)) {
+                    source.converted.push(name);
+                }
+            }
+            Item::Enum(item) => {
+                let types = item
+                    .variants
+                    .iter()
+                    .filter_map(|variant| match &variant.fields {
+                        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Some(fields.unnamed[0].ty.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                source.unions.insert(item.ident.to_string(), types);
+            }
+            Item::Fn(item) if has_attr(&item.attrs, "pyfunction") => {
+                let name = python_name(&item.attrs, &item.sig.ident.to_string());
+                source.functions.insert(item.sig.ident.to_string(), method(name, &item.attrs, &item.sig, Kind::Static));
+            }
+            Item::Macro(item) if item.mac.path.segments.last().is_some_and(|s| s.ident == "create_exception") => {
+                let tokens: Vec<TokenTree> = item.mac.tokens.clone().into_iter().collect();
+                let parts: Vec<&[TokenTree]> = tokens
+                    .split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','))
+                    .collect();
+                if let [_, [TokenTree::Ident(name)], base, rest @ ..] = parts.as_slice() {
+                    let base = match base.last() {
+                        Some(TokenTree::Ident(base)) if base == "PyException" => "Exception".to_string(),
+                        Some(base) => base.to_string(),
+                        None => "Exception".to_string(),
+                    };
+                    let doc = match rest.first().and_then(|t| t.first()) {
+                        Some(TokenTree::Literal(lit)) => lit.to_string().trim_matches('"').to_string(),
+                        _ => String::new(),
+                    };
+                    source.exceptions.push((name.to_string(), base, doc));
+                }
+            }
+            Item::Type(item) => {
+                source.aliases.insert(item.ident.to_string(), (*item.ty).clone());
+            }
+            Item::Const(item) => {
+                if let Some(value) = literal(&item.expr) {
+                    source.consts.insert(item.ident.to_string(), value);
+                }
+            }
+            // Тесты не входят в модуль
+            Item::Mod(item) if item.ident != "tests" => {
+                if let Some((_, items)) = &item.content {
+                    collect(items, source);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// ── Типы Rust → Python ──
+
+fn last_segment(ty: &Type) -> Option<(String, Vec<Type>)> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    let args = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    Some((segment.ident.to_string(), args))
+}
+
+struct Types<'a> {
+    source: &'a Source,
+    class: &'a str,
+}
+
+impl Types<'_> {
+    fn arg(&self, args: &[Type], i: usize) -> String {
+        args.get(i).map(|ty| self.py(ty)).unwrap_or_else(|| "Any".to_string())
+    }
+
+    fn py(&self, ty: &Type) -> String {
+        match ty {
+            Type::Reference(r) => self.py(&r.elem),
+            // &[u8] извлекается pyo3 только из bytes
+            Type::Slice(s) if matches!(&*s.elem, Type::Path(p) if p.path.is_ident("u8")) => "bytes".to_string(),
+            Type::Slice(s) => format!("list[{}]", self.py(&s.elem)),
+            Type::Array(a) => format!("list[{}]", self.py(&a.elem)),
+            Type::Tuple(t) if t.elems.is_empty() => "None".to_string(),
+            Type::Tuple(t) => {
+                let elems: Vec<String> = t.elems.iter().map(|ty| self.py(ty)).collect();
+                format!("tuple[{}]", elems.join(", "))
+            }
+            Type::Paren(p) => self.py(&p.elem),
+            Type::Group(g) => self.py(&g.elem),
+            Type::Path(_) => {
+                let (name, args) = last_segment(ty).unwrap_or_default();
+                self.path(&name, &args)
+            }
+            _ => "Any".to_string(),
+        }
+    }
+
+    fn path(&self, name: &str, args: &[Type]) -> String {
+        match name {
+            "String" | "str" | "Cow" | "char" | "PathBuf" | "Path" | "PyString" | "PyBackedStr" => "str".into(),
+            "bool" | "PyBool" => "bool".into(),
+            "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128" | "usize"
+            | "PyInt" => "int".into(),
+            "f32" | "f64" | "PyFloat" => "float".into(),
+            "Vec" | "VecDeque" => format!("list[{}]", self.arg(args, 0)),
+            "HashSet" | "BTreeSet" => format!("set[{}]", self.arg(args, 0)),
+            "HashMap" | "BTreeMap" | "DashMap" => format!("dict[{}, {}]", self.arg(args, 0), self.arg(args, 1)),
+            "Option" => {
+                let inner = self.arg(args, 0);
+                if inner == "Any" { inner } else { format!("{} | None", inner) }
+            }
+            "PyResult" | "Result" | "Box" | "Arc" | "Py" | "Bound" | "Borrowed" | "PyRef" | "PyRefMut" => {
+                self.arg(args, 0)
+            }
+            "PyDict" => "dict[str, Any]".into(),
+            "PyList" => "list[Any]".into(),
+            "PyTuple" => "tuple[Any, ...]".into(),
+            "PyBytes" => "bytes".into(),
+            "PyType" => "type".into(),
+            "Self" => self.class.to_string(),
+            name if self.source.classes.contains_key(name) => name.to_string(),
+            name if self.source.converted.iter().any(|c| c == name) => match self.source.unions.get(name) {
+                Some(types) if !types.is_empty() => {
+                    let types: Vec<String> = types.iter().map(|ty| self.py(ty)).collect();
+                    types.join(" | ")
+                }
+                _ => "Any".into(),
+            },
+            name => match self.source.aliases.get(name) {
+                Some(ty) => self.py(ty),
+                None => "Any".into(),
+            },
+        }
+    }
+}
+
+// ── Вывод ──
+
+fn docstring(out: &mut String, indent: &str, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    let raw = if text.contains('\\') { "r" } else { "" };
+    let text = text.replace("\"\"\"", "\\\"\\\"\\\"");
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or_default();
+    let rest: Vec<&str> = lines.collect();
+    // Кавычка в конце слилась бы с закрывающими """
+    if rest.is_empty() && !first.ends_with('"') {
+        let _ = writeln!(out, "{indent}{raw}\"\"\"{first}\"\"\"");
+        return;
+    }
+    let _ = writeln!(out, "{indent}{raw}\"\"\"{first}");
+    for line in rest {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            let _ = writeln!(out, "{indent}{line}");
+        }
+    }
+    let _ = writeln!(out, "{indent}\"\"\"");
+}
+
+fn default_value(tokens: &[TokenTree], source: &Source) -> String {
+    let text: String = tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>().join("");
+    if let Ok(expr) = syn::parse_str::<Expr>(&text) {
+        if let Some(value) = literal(&expr) {
+            return value;
+        }
+        if let Expr::Path(path) = &expr {
+            if path.path.is_ident("None") {
+                return "None".into();
+            }
+            if let Some(value) = path.path.get_ident().and_then(|ident| source.consts.get(&ident.to_string())) {
+                return value.clone();
+            }
+        }
+    }
+    "...".into()
+}
+
+/// Параметры Python: по сигнатуре pyo3 либо по аргументам функции
+fn params(method: &Method, types: &Types) -> Vec<String> {
+    let typed = |name: &str| {
+        method
+            .params
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| types.py(&p.ty))
+            .unwrap_or_else(|| "Any".into())
+    };
+    let Some(signature) = &method.signature else {
+        // Без сигнатуры хвостовые Option-аргументы необязательны
+        let optional = method
+            .params
+            .iter()
+            .rev()
+            .take_while(|p| matches!(last_segment(&p.ty), Some((name, _)) if name == "Option"))
+            .count();
+        let required = method.params.len() - optional;
+        return method
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let default = if i >= required { " = None" } else { "" };
+                format!("{}: {}{}", p.name, types.py(&p.ty), default)
+            })
+            .collect();
+    };
+
+    let tokens: Vec<TokenTree> = signature.clone().into_iter().collect();
+    tokens
+        .split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','))
+        .filter(|item| !item.is_empty())
+        .map(|item| match item {
+            [TokenTree::Punct(a), TokenTree::Punct(b), TokenTree::Ident(name)]
+                if a.as_char() == '*' && b.as_char() == '*' =>
+            {
+                format!("**{}: Any", name)
+            }
+            [TokenTree::Punct(star), TokenTree::Ident(name)] if star.as_char() == '*' => format!("*{}: Any", name),
+            [TokenTree::Punct(p)] => p.as_char().to_string(),
+            [TokenTree::Ident(name), TokenTree::Punct(eq), default @ ..] if eq.as_char() == '=' => {
+                let name = name.to_string();
+                format!("{}: {} = {}", name, typed(&name), default_value(default, types.source))
+            }
+            [TokenTree::Ident(name)] => {
+                let name = name.to_string();
+                format!("{}: {}", name, typed(&name))
+            }
+            other => other.iter().map(|t| t.to_string()).collect::<String>(),
+        })
+        .collect()
+}
+
+fn write_method(out: &mut String, indent: &str, method: &Method, class: Option<&Class>, types: &Types) {
+    let mut args = params(method, types);
+    let ret = match method.kind {
+        Kind::New => "None".to_string(),
+        _ if method.name.ends_with("_async") => {
+            // Результат future — как у синхронного варианта
+            let sync_name = method.name.trim_end_matches("_async");
+            let sync = class.and_then(|c| c.methods.iter().find(|m| m.name == sync_name));
+            let value = sync.and_then(|m| m.ret.as_ref()).map(|ty| types.py(ty)).unwrap_or_else(|| "None".into());
+            format!("asyncio.Future[{}]", value)
+        }
+        _ => method.ret.as_ref().map(|ty| types.py(ty)).unwrap_or_else(|| "None".into()),
+    };
+    let name = match method.kind {
+        Kind::New => "__init__",
+        _ => method.name.as_str(),
+    };
+    match (class.is_some(), &method.kind) {
+        (true, Kind::Static) => {
+            let _ = writeln!(out, "{indent}@staticmethod");
+        }
+        (true, Kind::Getter) => {
+            let _ = writeln!(out, "{indent}@property");
+            args.clear();
+        }
+        _ => {}
+    }
+    if class.is_some() && !matches!(method.kind, Kind::Static) {
+        args.insert(0, "self".into());
+    }
+    let _ = write!(out, "{indent}def {}({}) -> {}:", name, args.join(", "), ret);
+    if method.doc.is_empty() {
+        out.push_str(" ...\n");
+    } else {
+        out.push('\n');
+        docstring(out, &format!("{indent}    "), &method.doc);
+    }
+}
+
+fn render(source: &Source, module_doc: &str, classes: &[String], functions: &[String]) -> String {
+    let mut out = String::new();
+    out.push_str("# Сгенерировано build.rs из исходников rust_core — не редактировать вручную\n\n");
+    docstring(&mut out, "", module_doc);
+    out.push_str("\nimport asyncio\nfrom typing import Any\n");
+
+    for (name, base, doc) in &source.exceptions {
+        let _ = writeln!(out, "\n\nclass {}({}):", name, base);
+        if doc.is_empty() {
+            out.push_str("    ...\n");
+        } else {
+            docstring(&mut out, "    ", doc);
+        }
+    }
+
+    for name in classes {
+        let Some(class) = source.classes.get(name) else { continue };
+        let types = Types { source, class: name };
+        let _ = writeln!(out, "\n\nclass {}:", name);
+        docstring(&mut out, "    ", &class.doc);
+        let mut members = 0;
+        for (field, ty, field_doc) in &class.fields {
+            let _ = write!(out, "    @property\n    def {}(self) -> {}:", field, types.py(ty));
+            if field_doc.is_empty() {
+                out.push_str(" ...\n");
+            } else {
+                out.push('\n');
+                docstring(&mut out, "        ", field_doc);
+            }
+            members += 1;
+        }
+        for method in &class.methods {
+            write_method(&mut out, "    ", method, Some(class), &types);
+            members += 1;
+        }
+        if members == 0 && class.doc.is_empty() {
+            out.push_str("    ...\n");
+        }
+    }
+    for name in functions {
+        let Some(function) = source.functions.get(name) else { continue };
+        out.push_str("\n\n");
+        write_method(&mut out, "", function, None, &Types { source, class: "" });
+    }
+    out
+}
+
+/// Имена из регистрации модуля: `add_class::<path::Name>`, `wrap_pyfunction!(path::name`
+fn registered(lib: &str, marker: &str, end: char) -> Vec<String> {
+    lib.split(marker)
+        .skip(1)
+        .filter_map(|rest| rest.split(end).next())
+        .map(|path| path.rsplit("::").next().unwrap_or(path).trim().to_string())
+        .collect()
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed={}", STUBS_ENV);
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut source = Source::default();
+    let mut paths: Vec<_> = fs::read_dir(root.join("src"))
+        .expect("нет каталога src")
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        .collect();
+    paths.sort();
+
+    let mut module_doc = String::new();
+    let mut lib = String::new();
+    for path in &paths {
+        let text = fs::read_to_string(path).expect("не удалось прочитать исходник");
+        let file = syn::parse_file(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        if path.file_name().is_some_and(|name| name == "lib.rs") {
+            module_doc = doc(&file.attrs);
+            lib = text.clone();
+        }
+        collect(&file.items, &mut source);
+    }
+
+    let classes = registered(&lib, "add_class::<", '>');
+    let functions = registered(&lib, "wrap_pyfunction!(", ',');
+    let stub = render(&source, &module_doc, &classes, &functions);
+
+    let name = format!("{}.pyi", MODULE);
+    let out_dir = std::env::var_os("OUT_DIR").expect("cargo задаёт OUT_DIR");
+    fs::write(Path::new(&out_dir).join(&name), &stub).expect("не удалось записать .pyi в OUT_DIR");
+
+    if std::env::var_os(STUBS_ENV).is_some_and(|v| v != "0") {
+        let target = root.join(&name);
+        if fs::read_to_string(&target).ok().as_deref() != Some(stub.as_str()) {
+            if let Err(e) = fs::write(&target, &stub) {
+                println!("cargo:warning=Не удалось обновить {}: {}", target.display(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,1417 @@
+# Сгенерировано build.rs из исходников rust_core — не редактировать вручную
+
+"""Кристина 6.0 — Высокопроизводительное Rust-ядро
+
+PyO3 модуль, предоставляющий:
+- CoreConfig: общая конфигурация из TOML/JSON, передаётся конструкторам через config=
+- Storage: общее хранилище SQLite (kristina.db, WAL, пул соединений, vacuum/backup)
+- MemoryEngine: управление памятью (working/episodic/semantic), шифрование на диске по паролю
+- EmbeddingCache: lock-free кэш эмбеддингов
+- EmotionAnalyzer: Aho-Corasick анализ эмоций
+- IntentClassifier: намерения по шаблонам фраз (Aho-Corasick + стемминг)
+- EntityRecognizer: сущности по справочникам и эвристикам заглавных букв
+- ToolCallParser: парсер вызовов инструментов
+- ContextCompressor: сжатие контекста
+- ThreadTracker: отслеживание нитей разговора
+- MultiThreadTracker: нити по пользователям с общими настройками
+- Stemmer / stem: стемминг RU/EN (Snowball) — общий для всех модулей
+- Bm25Index: полнотекстовый BM25-индекс со стеммингом RU/EN
+- TextSplitter: рекурсивная нарезка документов на чанки с перекрытием
+- StreamAccumulator: потоковый вывод LLM по готовым предложениям, блокам кода и строкам ACTION
+- Deduplicator: почти-дубликаты через MinHash/SimHash и LSH
+- KeywordExtractor: ключевые фразы (RAKE) со стеммингом RU/EN
+- TfIdfVectorizer: разреженные TF-IDF векторы и косинусный поиск
+- PiiScrubber: поиск и маскирование персональных данных
+- ProfanityFilter: обнаружение и цензура мата (RU/EN, маски, транслит)
+- PromptTemplate: шаблоны промптов с условиями, циклами и бюджетами слотов
+- RateLimiter: token bucket на пользователя, общий для всех потоков Python
+- UserProfile: профиль пользователя (факты, эмоции, темы, часы активности) для промпта
+- SessionManager: сессии пользователей (рабочая память, нити, настроение) с выгрузкой по простою
+- KnowledgeGraph: граф знаний из троек (subject, predicate, object) с обходом
+- Pipeline: эмоции, нити, память и сжатие контекста за один вызов без GIL
+- EventBus: события компонентов (эпизоды, кэш, нити, всплески эмоций) для Python
+- Maintenance: фоновые сохранение, истечение нитей и сессий, очистка, checkpoint WAL по расписанию
+- Metrics: счётчики и гистограммы всех модулей, snapshot и экспорт в Prometheus
+- repair_json: починка JSON из вывода LLM (запятые, кавычки, обрезанный вывод)
+- strip_markdown / extract_structure: текст без markdown и структура документа со спанами
+- normalize: NFC, двойники латиница/кириллица, невидимые символы, пробелы
+- SpellChecker: исправление опечаток по частотным словарям RU/EN (SymSpell)
+- split_sentences: сегментация на предложения (сокращения, числа, URL)
+- transliterate: транслитерация RU ↔ латиница ("privet" ↔ "привет")
+- parse_datetime_ru: даты и время на русском ("завтра в 7 вечера", "через 15 минут")
+- detect_language: язык текста (ru/uk/en) по символьным триграммам
+- cosine_similarity / batch_cosine_similarity: векторные операции
+
+Методы ввода-вывода (save/load) имеют варианты *_async → asyncio.Future
+
+Несколько процессов-воркеров: shared=True (или runtime.shared в CoreConfig)
+у MemoryEngine, EmbeddingCache, ThreadTracker и MultiThreadTracker — рабочая
+память, нити и кэш общие через Storage в одном каталоге данных
+
+Логирование: записи ядра идут в Python logging, логгеры kristina_core.<модуль>
+(уровни кэшируются; после смены — refresh_log_levels())
+
+Исключения: KristinaError и его подклассы ParseError, MemoryError,
+CacheError, PersistenceError; некорректные аргументы — ValueError
+
+Free-threaded CPython (3.13t): модуль объявлен как не использующий GIL.
+Все классы frozen и Sync — состояние под DashMap / RwLock / атомиками,
+вызовы Python-колбэков идут без удерживаемых блокировок ядра
+"""
+
+import asyncio
+from typing import Any
+
+
+class KristinaError(Exception):
+    """Базовое исключение ядра Кристины"""
+
+
+class ParseError(KristinaError):
+    """Не удалось разобрать входные данные"""
+
+
+class MemoryError(KristinaError):
+    """Нет запрошенных данных памяти"""
+
+
+class CacheError(KristinaError):
+    """Некорректные данные кэша эмбеддингов"""
+
+
+class PersistenceError(KristinaError):
+    """Ошибка чтения или записи на диск"""
+
+
+class CoreConfig:
+    """Конфигурация компонентов: пути, размеры, таймауты, словари, потоки"""
+    def __init__(self, values: dict[str, Any] | None = None) -> None:
+        """values — разделы словарём, как в to_dict(); недостающее — по умолчанию"""
+    @staticmethod
+    def from_dict(values: dict[str, Any]) -> CoreConfig: ...
+    @staticmethod
+    def from_toml(text: str) -> CoreConfig: ...
+    @staticmethod
+    def from_json(text: str) -> CoreConfig: ...
+    @staticmethod
+    def from_file(path: str) -> CoreConfig:
+        """Формат по расширению: .toml или .json"""
+    def replace(self, values: dict[str, Any]) -> CoreConfig:
+        """Копия с изменёнными полями: config.replace({"memory": {"working_size": 20}})"""
+    def to_dict(self) -> Any: ...
+    def to_json(self) -> str: ...
+    def apply_runtime(self) -> None:
+        """Потоки пулов из раздела runtime; только до первого *_async и пакетной операции"""
+    @property
+    def data_dir(self) -> str | None: ...
+    def __eq__(self, other: CoreConfig) -> bool: ...
+    def __repr__(self) -> str: ...
+
+
+class Storage:
+    """Хранилище SQLite: пространства имён с записями ключ → байты"""
+    def __init__(self, data_dir: str | None = None, *, config: CoreConfig | None = None) -> None:
+        """data_dir — каталог файла kristina.db (по умолчанию data_dir из config)"""
+    @property
+    def path(self) -> str: ...
+    def get(self, namespace: str, key: str) -> bytes | None: ...
+    def put(self, namespace: str, key: str, value: bytes) -> None: ...
+    def delete(self, namespace: str, key: str) -> bool:
+        """True — запись была"""
+    def keys(self, namespace: str) -> list[str]:
+        """Ключи в порядке записи"""
+    def namespaces(self) -> dict[str, int]:
+        """{пространство имён: число записей}"""
+    def vacuum(self) -> None:
+        """Сжимает файл после удалений и сбрасывает журнал WAL"""
+    def vacuum_async(self) -> asyncio.Future[None]: ...
+    def backup(self, path: str) -> None:
+        """Согласованная копия в path (файла быть не должно); запись во время копии не блокируется"""
+    def backup_async(self, path: str) -> asyncio.Future[None]: ...
+    def __repr__(self) -> str: ...
+
+
+class MemoryEngine:
+    def __init__(self, memory_dir: str | None = None, working_size: int | None = None, max_episodic: int | None = None, *, storage: Storage | None = None, shared: bool | None = None, autosave_secs: float | None = None, ttl_by_importance: dict[int, float] | None = None, passphrase: str | None = None, migrate_plaintext: bool = False, config: CoreConfig | None = None) -> None:
+        """Незаданные параметры берутся из config (раздел memory), затем по умолчанию;
+        memory_dir можно опустить, если config задаёт memory.dir или data_dir.
+        storage — общее хранилище вместо kristina.db в memory_dir;
+        shared=True — рабочая память и факты общие для процессов (runtime.shared);
+        autosave_secs > 0 — фоновое сохранение при изменениях (memory.autosave_secs),
+        несохранённое дописывает close() или выход из with;
+        ttl_by_importance — срок жизни эпизодов по importance, часы: {1: 24}
+        (memory.ttl_by_importance); passphrase — шифрование эпизодов, фактов и
+        экспорта на диске (ChaCha20-Poly1305), зашифрованное без него не читается;
+        migrate_plaintext=True — один раз зашифровать память, сохранённую без
+        passphrase (без флага открытые записи → PersistenceError)
+        """
+    def add_to_working(self, role: str, content: str) -> None: ...
+    def get_working_memory(self) -> list[tuple[str, str, str]]: ...
+    def clear_working(self) -> None: ...
+    def add_episode(self, user_input: str, response: str, emotion: str, importance: int = 1, embedding: list[float] | None = None, ttl_hours: float | None = None) -> int:
+        """embedding — вектор для search_by_embedding; размерность у всех эпизодов
+        одна, иначе (и для нулевого вектора) — ValueError. ttl_hours — срок жизни
+        эпизода (иначе по ttl_by_importance). Возвращает id эпизода
+        """
+    def get_relevant_context(self, query: str, max_items: int = 3) -> list[tuple[str, str, int]]: ...
+    def find_episodes(self, query: str, max_items: int = 3) -> list[tuple[int, str, str, int]]:
+        """get_relevant_context с id: [(id, timestamp, preview, score)] — для
+        delete_episode / update_episode
+        """
+    def delete_episode(self, id: int) -> bool:
+        """Удалить эпизод; keyword- и векторный индексы правятся точечно.
+        False — эпизода нет (удалён или вытеснен)
+        """
+    def purge_expired(self) -> int:
+        """Удалить эпизоды с истёкшим сроком жизни (ttl_hours эпизода или
+        ttl_by_importance); вызывается и при загрузке, и перед вытеснением.
+        Возвращает число удалённых
+        """
+    def update_episode(self, id: int, importance: int | None = None, emotion: str | None = None) -> bool:
+        """Исправить importance и / или emotion; текст и индексы не меняются.
+        False — эпизода нет
+        """
+    def search_by_embedding(self, query_vec: list[float], top_k: int = 3, query: str | None = None, keyword_weight: float = 0.3) -> list[tuple[str, str, float]]:
+        """[(timestamp, preview, score)] по эмбеддингу запроса: эпизоды с эмбеддингом —
+        по косинусу, query (текст) добавляет keyword-балл с долей keyword_weight;
+        score в [0, 1], по убыванию
+        """
+    def add_semantic(self, key: str, value: str) -> None:
+        """В режиме shared факт сразу пишется в хранилище (сбой → PersistenceError)"""
+    def get_semantic(self, key: str) -> str | None:
+        """В режиме shared — из хранилища (факт мог записать другой процесс)"""
+    def consolidate(self, extractor: Any = None, *, min_importance: int = 3, min_retrievals: int = 3) -> list[tuple[str, str]]:
+        """Перенос устойчивого из эпизодов в факты. Кандидаты — не консолидированные
+        эпизоды с importance >= min_importance или найденные поиском не менее
+        min_retrievals раз. extractor(user_input, response) → dict / [(key, value)] /
+        None; без него — встроенные шаблоны ("меня зовут …", "я живу в …",
+        "мне N лет", "мой любимый X — …"). Эпизод консолидируется один раз.
+        Возвращает добавленные факты [(key, value)]
+        """
+    def save(self) -> None:
+        """Эпизоды и факты заменяются одной транзакцией SQLite (WAL): сбой или
+        падение процесса посреди записи оставляют прежнее сохранённое состояние;
+        затем обновляется копия kristina.db.bak (сбой копии — RuntimeWarning)
+        """
+    def load(self) -> None: ...
+    def export_jsonl(self, path: str) -> tuple[int, int]:
+        """Эпизоды и факты построчно в JSONL (запись в path.tmp, затем переименование):
+        память не сериализуется целиком в строку; с passphrase файл зашифрован
+        (заголовок и кадр на строку). Возвращает (эпизодов, фактов)
+        """
+    def import_jsonl(self, path: str, merge: bool = True) -> tuple[int, int]:
+        """Эпизоды и факты из JSONL export_jsonl, файл читается построчно
+        (зашифрованный — по заголовку, нужен тот же passphrase).
+        merge=True — добавить к памяти (эпизоды получают новые id, уже
+        имеющиеся — с тем же timestamp и user_input — пропускаются, факты
+        перезаписываются); merge=False — заменить память. Битая строка или
+        эмбеддинг другой размерности → PersistenceError, память не меняется.
+        Итог сразу пишется в хранилище одной транзакцией (в режиме shared
+        merge=False заменяет и общие факты); ошибка записи → PersistenceError,
+        память и хранилище прежние. Возвращает (добавлено эпизодов, фактов)
+        """
+    @property
+    def dirty(self) -> bool:
+        """Есть изменения эпизодов или фактов после последнего save"""
+    def close(self) -> None:
+        """Остановить автосохранение и сохранить несохранённое; повторный вызов безопасен"""
+    def __enter__(self) -> MemoryEngine: ...
+    def __exit__(self, _exc_type: Any = None, _exc: Any = None, _traceback: Any = None) -> bool:
+        """Выход из with — close(); исключение блока не подавляется"""
+    def save_async(self) -> asyncio.Future[None]:
+        """save без блокировки цикла asyncio: `await memory.save_async()`"""
+    def load_async(self) -> asyncio.Future[None]: ...
+    def set_event_bus(self, bus: EventBus | None = None) -> None:
+        """Публиковать события в EventBus (None — отключить)"""
+    def get_stats(self) -> tuple[int, int, int]: ...
+
+
+class EmbeddingCache:
+    def __init__(self, cache_dir: str | None = None, max_size: int | None = None, *, storage: Storage | None = None, shared: bool | None = None, config: CoreConfig | None = None) -> None:
+        """cache_dir можно опустить, если config задаёт cache.dir или data_dir;
+        storage — общее хранилище вместо kristina.db в cache_dir;
+        shared=True — записи общие для процессов (runtime.shared)
+        """
+    def get(self, text: str) -> list[float] | None: ...
+    def put(self, text: str, embedding: list[float]) -> None: ...
+    def contains(self, text: str) -> bool: ...
+    def len(self) -> int: ...
+    def get_stats(self) -> tuple[int, int, int]: ...
+    def save(self) -> None: ...
+    def save_async(self) -> asyncio.Future[None]:
+        """save без блокировки цикла asyncio"""
+    def set_event_bus(self, bus: EventBus | None = None) -> None:
+        """Публиковать события в EventBus (None — отключить)"""
+    def clear(self) -> None:
+        """В режиме shared очищает и записи в хранилище"""
+
+
+class EmotionAnalyzer:
+    def __init__(self, translit: bool = True) -> None:
+        """translit — искать русские паттерны и в тексте, набранном латиницей"""
+    def analyze(self, text: str) -> str: ...
+    def analyze_detailed(self, text: str) -> tuple[str, float, list[str]]: ...
+    def set_event_bus(self, bus: EventBus | None = None, spike_matches: int = 3) -> None:
+        """Публиковать emotion_spike в EventBus (None — отключить): реплики с
+        positive/negative и не меньше spike_matches совпадений
+        """
+
+
+class IntentClassifier:
+    def __init__(self, intents: dict[str, list[str]] | None = None, threshold: float = 0.0, translit: bool = True) -> None:
+        """intents — {intent: [pattern, ...]}; threshold — минимальная уверенность для predict;
+        translit — распознавать русские шаблоны в тексте, набранном латиницей
+        """
+    def add_intent(self, intent: str, patterns: list[str], weight: float = 1.0) -> None:
+        """Добавить шаблоны намерению (создаётся, если его нет)"""
+    def remove_intent(self, intent: str) -> bool: ...
+    def list_intents(self) -> list[str]: ...
+    def classify(self, text: str, top_k: int = 3) -> list[tuple[str, float]]:
+        """[(intent, confidence)] по убыванию уверенности"""
+    def predict(self, text: str) -> str | None:
+        """Самое вероятное намерение, если его уверенность >= threshold"""
+
+
+class EntityRecognizer:
+    def __init__(self, gazetteers: dict[str, list[str]] | None = None, require_capital: bool = False) -> None:
+        """gazetteers — {label: [имя, ...]}; require_capital — имена из справочника
+        с заглавной буквы не совпадают со строчными словами ("Роза" ≠ "роза")
+        """
+    def add_gazetteer(self, label: str, names: list[str]) -> None:
+        """Добавить имена в справочник label (создаётся, если его нет)"""
+    def remove_gazetteer(self, label: str) -> bool: ...
+    def get_gazetteers(self) -> dict[str, list[str]]: ...
+    def recognize(self, text: str) -> list[Entity]:
+        """Сущности по возрастанию start"""
+
+
+class Entity:
+    """Найденная сущность; start/end — смещения в символах, canonical —
+    имя из справочника (для эвристик совпадает с text)
+    """
+    @property
+    def text(self) -> str: ...
+    @property
+    def label(self) -> str: ...
+    @property
+    def canonical(self) -> str: ...
+    @property
+    def start(self) -> int: ...
+    @property
+    def end(self) -> int: ...
+    def __repr__(self) -> str: ...
+
+
+class ToolCallParser:
+    def __init__(self, known_tools: list[str] | None = None, aliases: dict[str, str] | None = None, tolerant: bool = False, cli_fallback: bool = False, case_insensitive: bool = False, strict: bool = False) -> None: ...
+    def parse(self, input: str) -> ToolCall:
+        """Разбирает вызов → ToolCall: каноническое имя, aliased_from (имя до
+        алиаса) и прочие метаданные. Распаковывается как прежний кортеж:
+        `name, args, kwargs = parser.parse(...)`
+        """
+    def parse_detailed(self, input: str) -> ToolCall:
+        """То же, что parse (оставлено для совместимости)"""
+    def parse_response(self, json_str: str) -> list[ToolCall]:
+        """Разбирает JSON-ответ провайдера {"content": "...", "tool_calls": [...]}.
+        Вызовы из tool_calls идут первыми, затем ACTION-строки из content;
+        дубликаты (то же имя и аргументы) отбрасываются.
+        """
+    def parse_plan(self, text: str) -> tuple[list[PlanStep], list[list[int]]]:
+        """Разбирает план с зависимостями → (шаги, группы).
+        Текст: `ACTION[1]: a()`, `ACTION[2 after 1]: b()`, `ACTION[3 after 1,2]: c()`;
+        ACTION: без номера зависит от предыдущего шага.
+        JSON: [{"id": 1, "call": "a()", "after": []}, ...] или {"steps": [...]},
+        вместо "call" допустимы "name"/"arguments".
+        Группы — уровни топологической сортировки: шаги внутри группы
+        независимы и могут выполняться параллельно.
+        """
+    def parse_actions(self, text: str) -> list[ToolCall]:
+        """Разбирает все ACTION-строки текста в ToolCall с диапазонами
+        относительно text — чтобы вырезать вызовы из видимого ответа
+        """
+    def detect_intent(self, text: str, tools: dict[str, str], min_score: float = 0.5) -> tuple[str, float, str] | None:
+        """Распознаёт попытку вызвать инструмент без синтаксиса ("let me search for X").
+        tools: {имя: описание}. Возвращает (инструмент, score 0..1, запрос)
+        для лучшего кандидата со score >= min_score. Без маркера действия
+        ("let me", "поищу") намерения нет: упоминание имени инструмента само
+        по себе ("web_search сегодня тормозит") вызовом не считается.
+        """
+    def check_syntax(self, input: str) -> tuple[int, int, str] | None:
+        """Проверка по формальной грамматике без разбора:
+        None, если вызов корректен, иначе (строка, столбец, сообщение), с 1.
+        """
+    def extract_action(self, text: str) -> str | None: ...
+    def extract_actions(self, text: str) -> list[tuple[str, int]]:
+        """Все ACTION-строки многошагового плана: [(action, line_no)], line_no с 1"""
+    def extract_final_answer(self, text: str) -> str | None: ...
+    def is_final_answer(self, text: str) -> bool: ...
+    def is_action(self, text: str) -> bool: ...
+    def set_known_tools(self, tools: list[str], aliases: dict[str, str] | None = None) -> None:
+        """Заменяет реестр. aliases: {"google": "web_search", "old_name": "new_name"}"""
+    def add_tool(self, name: str, schema: dict[str, str] | None = None) -> None:
+        """Добавляет инструмент в реестр (или обновляет схему существующего)"""
+    def remove_tool(self, name: str) -> bool:
+        """Удаляет инструмент вместе со схемой, политикой и алиасами на него.
+        Возвращает False, если инструмента не было.
+        """
+    def get_known_tools(self) -> list[str]: ...
+    def set_tool_schema(self, tool: str, params: dict[str, str]) -> None:
+        """Схема параметров инструмента: {"limit": "int", "exact": "bool"}.
+        kwargs из схемы приводятся к типу в ToolCall.typed_kwargs.
+        """
+    def set_tool_policy(self, tool: str, checks: list[str], mode: str = "flag") -> None:
+        """Политика санитайзера для инструмента (имя, "fs.*" или "*").
+        checks: "shell" | "path" | "injection"; mode: "flag" | "strip" | "reject".
+        Пустой checks снимает политику.
+        """
+    def get_aliases(self) -> dict[str, str]: ...
+    def tools_in_namespace(self, namespace: str) -> list[str]:
+        """Инструменты пространства имён: tools_in_namespace("fs") → ["fs.read", ...]"""
+
+
+class ToolCall:
+    """Результат разбора вызова инструмента"""
+    @property
+    def name(self) -> str: ...
+    @property
+    def args(self) -> list[str]: ...
+    @property
+    def kwargs(self) -> dict[str, str]: ...
+    @property
+    def aliased_from(self) -> str | None:
+        """Исходное имя, если вызов пришёл через алиас"""
+    @property
+    def diagnostics(self) -> list[str]:
+        """Что было исправлено при разборе (толерантный режим)"""
+    @property
+    def sanitizer_flags(self) -> list[str]:
+        """Срабатывания санитайзера: "arg[0]: shell-метасимволы"
+        """
+    @property
+    def typed_kwargs(self) -> dict[str, str | int | float | bool]:
+        """kwargs, приведённые к типам схемы (int/float/bool); без схемы — str"""
+    @property
+    def coercions(self) -> list[str]:
+        """Выполненные приведения: "limit: '5' → int"
+        """
+    @property
+    def span(self) -> tuple[int, int] | None:
+        """Диапазон вызова в исходном тексте [start, end) в символах (для срезов в Python);
+        None для вызовов из структурированного JSON
+        """
+    @property
+    def byte_span(self) -> tuple[int, int] | None:
+        """Тот же диапазон в байтах UTF-8"""
+    def __iter__(self) -> Any:
+        """Распаковка как прежний кортеж: `name, args, kwargs = parser.parse(...)`"""
+    def __len__(self) -> int: ...
+    def __getitem__(self, index: int) -> Any:
+        """call[0] — name, call[1] — args, call[2] — kwargs"""
+    def __repr__(self) -> str: ...
+
+
+class PlanStep:
+    """Шаг плана: вызов + id шагов, которые должны завершиться раньше"""
+    @property
+    def id(self) -> int: ...
+    @property
+    def call(self) -> ToolCall: ...
+    @property
+    def after(self) -> list[int]: ...
+    @property
+    def line_no(self) -> int:
+        """Номер строки ACTION (с 1); 0 для JSON-плана"""
+    def __repr__(self) -> str: ...
+
+
+class ContextCompressor:
+    def __init__(self, compression_ratio: float | None = None, important_words: dict[str, float] | None = None, preview_chars: int | None = None, recent_messages: int | None = None, ellipsis: str | None = None, episode_preview_chars: int | None = None, collapse_repeats: bool | None = None, token_ratios: dict[str, float] | None = None, *, config: CoreConfig | None = None) -> None:
+        """important_words: {слово/фраза: вес}; None — встроенный словарь с весом 1.0.
+        preview_chars / episode_preview_chars — длина превью (вместе с ellipsis),
+        recent_messages — окно последних сообщений compress_conversation.
+        collapse_repeats — сливать подряд идущие (почти) одинаковые сообщения в одно с "(×N)".
+        token_ratios — символов на токен по письменностям, например {"cjk": 0.8};
+        ключи: latin, cyrillic, cjk, emoji, code, other.
+        Незаданные параметры берутся из config (compressor, lexicons.important_words).
+        """
+    def set_important_words(self, words: dict[str, float]) -> None:
+        """Заменяет словарь важных слов: {"deploy": 2.0, "сервер упал": 3.0}"""
+    def get_important_words(self) -> dict[str, float]: ...
+    def compress_conversation(self, messages: list[Any], analyzer: EmotionAnalyzer | None = None, protect: list[int] | None = None, protect_roles: list[str] | None = None, protect_markers: list[str] | None = None, legacy: bool = False) -> Any:
+        """Сжимает историю разговора до ~compression_ratio от исходного объёма токенов.
+        Принимает List[Tuple[str,str,str]] ИЛИ List[Dict] с ключами role/content/timestamp.
+        Какие старые сообщения уцелеют, решает score_message (analyzer — опционально).
+        protect / protect_roles / protect_markers — индексы, роли и подстроки
+        (без учёта регистра) сообщений, которые сохраняются дословно.
+        Возвращает список {"role", "content"} (сводка старой части — system-сообщение);
+        legacy=True — прежнюю строку "role: content" через перевод строки.
+        """
+    def compress_conversation_detailed(self, messages: list[Any], analyzer: EmotionAnalyzer | None = None, protect: list[int] | None = None, protect_roles: list[str] | None = None, protect_markers: list[str] | None = None, legacy: bool = False) -> tuple[Any, CompressionReport]:
+        """То же, что compress_conversation, плюс CompressionReport:
+        сколько сообщений сохранено/обрезано/выброшено и какие ключевые пункты взяты
+        """
+    def score_message(self, text: str, analyzer: EmotionAnalyzer | None = None) -> float:
+        """Важность сообщения: веса важных слов + вопросы + длина,
+        с analyzer — ещё и эмоциональность (уверенность не-нейтральной эмоции)
+        """
+    def compress_messages(self, messages: list[Any], budget_tokens: int, keep_last: int = 4, embeddings: list[list[float]] | None = None, cache: EmbeddingCache | None = None, dedup_threshold: float = 0.95, protect: list[int] | None = None, protect_roles: list[str] | None = None, protect_markers: list[str] | None = None) -> list[dict[str, str]]:
+        """Сжатие с учётом ролей → список {"role", "content"}:
+        system-сообщения сохраняются дословно, последние keep_last реплик
+        user/assistant — целиком, середина сворачивается в TextRank-сводку
+        (одно system-сообщение). Последнее сообщение пользователя не теряется никогда.
+        С embeddings (по одному на сообщение) или cache перед сжатием
+        отбрасываются семантические повторы (cos >= dedup_threshold).
+        Защищённые сообщения (protect*, см. compress_conversation) не сворачиваются
+        и не удаляются дедупликацией.
+        """
+    def collapse_duplicates(self, messages: list[Any]) -> list[dict[str, str]]:
+        """Сливает подряд идущие одинаковые/почти одинаковые сообщения одной роли:
+        ["?", "?", "??"] → ["? (×3)"]
+        """
+    def compress_working_memory(self, engine: MemoryEngine, budget_tokens: int, keep_last: int = 4) -> str:
+        """Сжатие рабочей памяти MemoryEngine напрямую в Rust (без копирования кортежей
+        через Python): role-aware сжатие как в compress_messages, результат —
+        строки "role: content"
+        """
+    def window(self, messages: list[Any], window_tokens: int, overlap_tokens: int = 0) -> list[tuple[int, int, list[dict[str, str]]]]:
+        """Перекрывающиеся окна разговора для длинного анализа (тренды эмоций,
+        сегментация тем) → [(start, end, messages)], [start, end) — индексы сообщений.
+        Окно не больше window_tokens (кроме одиночного длинного сообщения),
+        соседние окна делят хвост примерно в overlap_tokens.
+        """
+    def compress_many(self, conversations: list[list[Any]], budget_tokens: int, keep_last: int = 4) -> list[list[dict[str, str]]]:
+        """Пакетное сжатие многих разговоров (как compress_messages, без дедупликации):
+        разговоры обрабатываются параллельно через Rayon с отпущенным GIL
+        """
+    def dedup_messages(self, messages: list[Any], embeddings: list[list[float]] | None = None, cache: EmbeddingCache | None = None, threshold: float = 0.95) -> list[dict[str, str]]:
+        """Убирает семантические повторы: из пары сообщений одной роли
+        с cos >= threshold остаётся более новое. system не трогается,
+        сообщения без эмбеддинга сохраняются.
+        """
+    def extract_key_points(self, text: str, max_points: int = 3, min_score: float = 0.0, max_tokens: int | None = None) -> list[tuple[str, float, int]]:
+        """Ключевые предложения по весам важных слов → [(sentence, score, char_offset)],
+        по убыванию score; не больше max_points, со score >= min_score (и > 0).
+        max_tokens — жадно берутся лучшие предложения, пока помещаются в бюджет.
+        """
+    def summarize(self, text: str, max_sentences: int = 3) -> list[str]:
+        """TextRank: граф сходства предложений + PageRank.
+        Возвращает max_sentences самых центральных предложений в исходном порядке.
+        """
+    def chunk_text(self, text: str, chunk_tokens: int, overlap_tokens: int = 0, respect_sentences: bool = True) -> list[tuple[str, int, int]]:
+        """Разбивка текста на чанки для RAG → [(text, start, end)], смещения в символах.
+        Чанк не превышает chunk_tokens (оценка estimate_tokens), соседние чанки
+        перекрываются примерно на overlap_tokens. При respect_sentences границы
+        проходят по предложениям (слишком длинные режутся по словам, затем по символам).
+        """
+    def plan_summarization(self, messages: list[Any], chunk_budget: int) -> list[tuple[str, int, int]]:
+        """Map-шаг иерархической суммаризации: история режется на чанки по
+        chunk_budget токенов → [(text, first, end)], где [first, end) — индексы
+        сообщений. Суммаризация чанков — на стороне Python (LLM), итог собирает
+        merge_summaries. Сообщение больше бюджета делится по предложениям.
+        """
+    def merge_summaries(self, summaries: list[str], budget: int) -> str:
+        """Reduce-шаг: объединяет сводки чанков, убирая повторяющиеся предложения;
+        если результат не помещается в budget, остаются самые центральные (TextRank)
+        """
+    def summarize_episodes(self, episodes: list[tuple[str, str, int]], max_length: int = 500) -> str:
+        """Суммаризует эпизоды. Вход: [(timestamp, user_input, importance)]"""
+    def summarize_episodes_items(self, episodes: list[tuple[str, str, int]], budget_tokens: int = 200, max_items: int | None = None) -> list[dict[str, Any]]:
+        """Структурный вариант summarize_episodes: [{"timestamp", "preview", "importance",
+        "tokens"}] по убыванию важности; эпизоды, не влезающие в budget_tokens
+        (по токенам превью), пропускаются по одному
+        """
+    def estimate_tokens(self, text: str) -> int:
+        """BPE-эвристика по письменностям: ~4 chars/token латиница, ~3 код,
+        ~2 кириллица, ~1 CJK, ~0.5 эмодзи (настраивается token_ratios)
+        """
+    def get_token_ratios(self) -> dict[str, float]:
+        """Текущие коэффициенты символов на токен"""
+    def truncate_to_tokens(self, text: str, max_tokens: int) -> str:
+        """Обрезает текст до N токенов с учётом разметки: режет предпочтительно
+        по абзацам, затем по строкам, предложениям, словам; строки таблиц
+        не разрываются, незакрытый ``` / ~~~ блок кода закрывается.
+        """
+
+
+class CompressionReport:
+    """Отчёт о сжатии: почему из промпта пропал контекст"""
+    @property
+    def original_tokens(self) -> int: ...
+    @property
+    def compressed_tokens(self) -> int: ...
+    @property
+    def kept(self) -> int:
+        """Сообщения, вошедшие дословно"""
+    @property
+    def truncated(self) -> int:
+        """Сообщения, сокращённые до превью"""
+    @property
+    def dropped(self) -> int:
+        """Сообщения, от которых остались только ключевые пункты (или ничего)"""
+    @property
+    def key_points(self) -> list[str]: ...
+    @property
+    def collapsed(self) -> int:
+        """Сообщения, слитые с соседним повтором ("(×N)")"""
+    @property
+    def elapsed_ms(self) -> float: ...
+    def __repr__(self) -> str: ...
+
+
+class ConversationBuffer:
+    """Потоковый буфер разговора: сообщения добавляются по одному, оценка
+    токенов и ключевые предложения кэшируются, так что render(budget)
+    не пересчитывает всю историю на каждом ходе.
+    """
+    def __init__(self, important_words: dict[str, float] | None = None, *, config: CoreConfig | None = None) -> None:
+        """Словарь и параметры превью — как у ContextCompressor с тем же config"""
+    def push(self, role: str, content: str) -> None: ...
+    def render(self, budget: int) -> str:
+        """Контекст в пределах budget токенов: свежие сообщения дословно
+        (самое новое — всегда), перед ними — ключевые пункты более старых
+        """
+    @property
+    def total_tokens(self) -> int:
+        """Оценка токенов всей истории (обновляется при push)"""
+    def clear(self) -> None: ...
+    def __len__(self) -> int: ...
+
+
+class ThreadTracker:
+    def __init__(self, timeout_secs: int | None = None, max_open: int | None = None, archive_messages: int | None = None, max_thread_messages: int | None = None, max_archived: int | None = None, data_dir: str | None = None, *, storage: Storage | None = None, shared: bool | None = None, config: CoreConfig | None = None) -> None:
+        """data_dir — каталог хранилища для архивных нитей сверх max_archived
+        (создаётся при необходимости), storage — общее хранилище вместо него;
+        shared=True — нити общие для процессов с тем же хранилищем (runtime.shared);
+        незаданные параметры берутся из раздела threads config
+        """
+    def start_thread(self, topic: str, entities: list[str] | None = None, timeout_secs: int | None = None) -> int:
+        """Открывает новую нить и делает её активной; прежние остаются открытыми.
+        timeout_secs — собственный таймаут нити (по умолчанию — трекера). Возвращает id.
+        """
+    def add_message(self, user_input: str, response: str) -> None:
+        """Добавляет обмен репликами в активную нить (с извлечением сущностей)"""
+    def update(self, user_input: str, response: str) -> None:
+        """Закрывает просроченные нити и направляет сообщение в самую подходящую
+        открытую нить (при равенстве — в активную); если подходящих нет —
+        в активную, а без неё — в новую
+        """
+    def start_subtopic(self, topic: str) -> None:
+        """Открывает вложенную под-тему активной нити"""
+    def end_subtopic(self) -> str | None:
+        """Закрывает текущую под-тему (возврат на уровень выше); возвращает её"""
+    def get_topic_path(self) -> list[str]:
+        """[тема, под-тема, ...] активной нити"""
+    def detect_drift(self, window: int = 5) -> float:
+        """Насколько разговор ушёл от начала активной нити, [0, 1]: сравнение
+        слов и сущностей последних window сообщений с первыми window и темой
+        """
+    def has_drifted(self, window: int = 5, threshold: float = 0.8) -> bool:
+        """detect_drift(window) >= threshold — пора закрыть нить и открыть новую"""
+    def drift_score(self, text: str) -> float:
+        """Дрейф темы для текста относительно активной нити, [0, 1]"""
+    def set_subtopic_detection(self, threshold: float | None = None) -> None:
+        """Автоопределение под-тем в update(): сообщение без совпадений с нитями
+        и с дрейфом >= threshold открывает под-тему. None — выключить.
+        """
+    def extract_entities(self, text: str) -> list[str]:
+        """Сущности, которые были бы извлечены из текста"""
+    def set_gazetteer(self, names: list[str]) -> None:
+        """Заменяет справочник известных имён"""
+    def set_entity_recognizer(self, recognizer: EntityRecognizer | None = None) -> None:
+        """Подключает EntityRecognizer (None — отключить); его справочники
+        остаются живыми: add_gazetteer сразу виден трекеру
+        """
+    def set_translit_matching(self, enabled: bool) -> None:
+        """Сопоставлять темы и маркеры и с транслитом ("pro pereezd"); по умолчанию включено"""
+    def get_entities(self) -> list[str]:
+        """Сущности активной нити (заданные и накопленные)"""
+    def export_thread(self, thread_id: int, format: str = "json") -> str:
+        """Транскрипт нити (открытой или архивной) для отправки пользователю:
+        format="json" — объект с id, topic, entities, started, archived, summary, messages;
+        format="markdown" — читаемый текст с временем реплик
+        """
+    def expire_if_idle(self) -> bool:
+        """Архивирует просроченные нити сразу, не дожидаясь следующего сообщения
+        (для планировщика); True, если что-то было архивировано
+        """
+    def seconds_until_timeout(self) -> float | None:
+        """Секунд до ближайшего таймаута среди открытых нитей (None — нитей нет)"""
+    def switch_to(self, thread_id: int) -> None:
+        """Делает открытую нить активной"""
+    def set_thread_timeout(self, thread_id: int, timeout_secs: int) -> None:
+        """Таймаут бездействия для конкретной нити"""
+    def list_threads(self) -> list[tuple[int, str, int, bool]]:
+        """Открытые нити: [(id, topic, message_count, is_active)]"""
+    def get_current_thread_id(self) -> int | None: ...
+    def relatedness(self, text: str) -> float:
+        """Насколько текст связан с активной нитью, [0, 1]: взвешенная сумма
+        совпадения темы, сущностей, контекстных маркеров и свежести нити.
+        0 — нет активной нити или она просрочена.
+        """
+    def is_related(self, text: str, threshold: float = 0.12) -> bool:
+        """relatedness(text) >= threshold"""
+    def set_relatedness_weights(self, weights: dict[str, float]) -> None:
+        """Меняет веса relatedness (частично): {"topic": 0.5, "recency": 0.0}"""
+    def get_relatedness_weights(self) -> dict[str, float]: ...
+    def get_context(self, max_exchanges: int = 3, user_chars: int = 60, assistant_chars: int = 80) -> str | None:
+        """Контекст активной нити для промпта: тема, сущности, сжатое содержание
+        и последние max_exchanges обменов. Реплики обрезаются до user_chars /
+        assistant_chars символов; assistant_chars=0 — без ответов ассистента.
+        """
+    def has_active_thread(self) -> bool: ...
+    def get_current_topic(self) -> str | None: ...
+    def get_past_threads(self, limit: int = 5) -> list[tuple[str, float, int]]: ...
+    def get_archived_thread(self, thread_id: int) -> list[tuple[str, str, str]] | None:
+        """Транскрипт архивной нити: [(user, assistant, timestamp RFC 3339)]"""
+    def search_threads(self, query: str, limit: int = 5) -> list[tuple[int, str, int, bool]]:
+        """Поиск по открытым и архивным нитям (темы, сущности, сообщения):
+        [(id, topic, score, archived)], лучшие первыми
+        """
+    def find_resumable(self, text: str, limit: int = 3) -> list[tuple[int, str, int]]:
+        """Архивные нити, к которым может относиться текст: [(id, topic, score)],
+        лучшие первыми, при равенстве — более свежие. Контекстный маркер
+        ("вернёмся к", "помнишь") добавляет 1 всем нитям, так что без
+        совпадений по теме предлагаются последние закрытые.
+        """
+    def resume(self, thread_id: int) -> None:
+        """Возвращает архивную нить в открытые (с сообщениями и сущностями)
+        и делает её активной
+        """
+    def summarize_current(self, compressor: ContextCompressor, max_tokens: int = 120) -> str | None:
+        """Краткое содержание активной нити в пределах max_tokens"""
+    def set_auto_summary(self, compressor: ContextCompressor | None, max_tokens: int = 120) -> None:
+        """Включает (compressor) или выключает (None) суммирование нитей при архивации"""
+    def get_thread_summary(self, thread_id: int) -> str | None:
+        """Краткое содержание архивной нити (если было авто-суммирование)"""
+    def end_thread(self) -> None:
+        """Закрывает активную нить (в архив); остальные открытые не трогаются"""
+    def set_on_open(self, callback: Any = None) -> None:
+        """callable(dict) при открытии нити: {"event": "open", "reason", "id", "topic",
+        "entities", "message_count", "duration_secs"}; reason — started / auto / resumed.
+        None — отключить. Исключения колбэка не прерывают трекер (sys.unraisablehook).
+        """
+    def set_on_close(self, callback: Any = None) -> None:
+        """callable(dict) при закрытии нити (тот же формат, "event": "close");
+        reason — ended / timeout / evicted
+        """
+    def set_event_bus(self, bus: EventBus | None = None) -> None:
+        """Публиковать thread_opened / thread_closed в EventBus (None — отключить)"""
+    def get_timeline(self, limit: int = 50) -> list[tuple[str, str, int, str]]:
+        """Последние limit событий сессии в хронологическом порядке:
+        [(timestamp RFC 3339, kind, thread_id, detail)]. kind — started / auto /
+        resumed (открытие, detail — тема), message (detail — превью реплики), subtopic,
+        ended / timeout / evicted (архивация, detail — тема)
+        """
+    def get_stats(self) -> dict[str, Any]:
+        """Статистика: current_thread, open_threads, active_topic, active_age_secs,
+        active_messages, archived_threads, avg_duration_secs (по архиву),
+        total_messages, messages_per_thread, top_entities [(entity, threads)]
+        """
+
+
+class MultiThreadTracker:
+    """Трекеры нитей по user_id с общей конфигурацией. Методы те же,
+    что у ThreadTracker, с user_id первым аргументом; трекер пользователя
+    создаётся при первом изменяющем вызове.
+    """
+    def __init__(self, timeout_secs: int | None = None, max_open: int | None = None, archive_messages: int | None = None, max_thread_messages: int | None = None, max_archived: int | None = None, data_dir: str | None = None, *, storage: Storage | None = None, shared: bool | None = None, config: CoreConfig | None = None) -> None:
+        """Незаданные параметры берутся из раздела threads config;
+        storage — общее хранилище архива вместо data_dir; shared=True — нити
+        общие для процессов (пользователь подключается при первом обращении)
+        """
+    def start_thread(self, user_id: str, topic: str, entities: list[str] | None = None, timeout_secs: int | None = None) -> int: ...
+    def add_message(self, user_id: str, user_input: str, response: str) -> None: ...
+    def update(self, user_id: str, user_input: str, response: str) -> None: ...
+    def switch_to(self, user_id: str, thread_id: int) -> None: ...
+    def resume(self, user_id: str, thread_id: int) -> None: ...
+    def end_thread(self, user_id: str) -> None: ...
+    def list_threads(self, user_id: str) -> list[tuple[int, str, int, bool]]: ...
+    def get_current_thread_id(self, user_id: str) -> int | None: ...
+    def relatedness(self, user_id: str, text: str) -> float: ...
+    def is_related(self, user_id: str, text: str, threshold: float = 0.12) -> bool: ...
+    def expire_idle(self) -> list[str]:
+        """expire_if_idle для всех пользователей; возвращает user_id, у которых
+        были архивированы нити
+        """
+    def export_thread(self, user_id: str, thread_id: int, format: str = "json") -> str: ...
+    def seconds_until_timeout(self, user_id: str) -> float | None: ...
+    def start_subtopic(self, user_id: str, topic: str) -> None: ...
+    def end_subtopic(self, user_id: str) -> str | None: ...
+    def detect_drift(self, user_id: str, window: int = 5) -> float: ...
+    def get_topic_path(self, user_id: str) -> list[str]: ...
+    def get_context(self, user_id: str, max_exchanges: int = 3, user_chars: int = 60, assistant_chars: int = 80) -> str | None: ...
+    def has_active_thread(self, user_id: str) -> bool: ...
+    def get_current_topic(self, user_id: str) -> str | None: ...
+    def get_past_threads(self, user_id: str, limit: int = 5) -> list[tuple[str, float, int]]: ...
+    def get_archived_thread(self, user_id: str, thread_id: int) -> list[tuple[str, str, str]] | None: ...
+    def find_resumable(self, user_id: str, text: str, limit: int = 3) -> list[tuple[int, str, int]]: ...
+    def search_threads(self, user_id: str, query: str, limit: int = 5) -> list[tuple[int, str, int, bool]]: ...
+    def get_entities(self, user_id: str) -> list[str]: ...
+    def set_gazetteer(self, names: list[str]) -> None:
+        """Общий справочник имён — для всех пользователей, включая будущих"""
+    def set_entity_recognizer(self, recognizer: EntityRecognizer | None = None) -> None:
+        """Общий EntityRecognizer — для всех пользователей, включая будущих"""
+    def set_translit_matching(self, enabled: bool) -> None:
+        """Транслит при сопоставлении — для всех пользователей, включая будущих"""
+    def set_event_bus(self, bus: EventBus | None = None) -> None:
+        """Общий EventBus — для всех пользователей, включая будущих;
+        в событиях есть user_id
+        """
+    def get_timeline(self, user_id: str, limit: int = 50) -> list[tuple[str, str, int, str]]: ...
+    def get_stats(self, user_id: str) -> dict[str, Any]: ...
+    def users(self) -> list[str]:
+        """user_id всех пользователей с трекером"""
+    def remove_user(self, user_id: str) -> bool:
+        """Забывает пользователя вместе с его нитями; True, если он был"""
+    def __len__(self) -> int: ...
+
+
+class Stemmer:
+    def __init__(self, lang: str = "auto") -> None: ...
+    @property
+    def lang(self) -> str: ...
+    def stem(self, word: str) -> str: ...
+    def stem_many(self, words: list[str]) -> list[str]: ...
+    def stem_text(self, text: str) -> list[str]:
+        """Основы всех слов текста по порядку"""
+
+
+class Bm25Index:
+    def __init__(self, k1: float = 1.2, b: float = 0.75) -> None: ...
+    def add_document(self, doc_id: str, text: str) -> None:
+        """Добавить документ (существующий с тем же doc_id заменяется)"""
+    def remove(self, doc_id: str) -> bool: ...
+    def search(self, query: str, top_k: int = 10) -> list[tuple[str, float]]:
+        """[(doc_id, score)] по убыванию релевантности"""
+    def contains(self, doc_id: str) -> bool: ...
+    def clear(self) -> None: ...
+    def __len__(self) -> int: ...
+    def save(self, path: str) -> None: ...
+    def load(self, path: str) -> None:
+        """Загрузить индекс из файла, заменив текущее содержимое"""
+    def save_async(self, path: str) -> asyncio.Future[None]:
+        """save / load без блокировки цикла asyncio"""
+    def load_async(self, path: str) -> asyncio.Future[None]: ...
+
+
+class TextSplitter:
+    def __init__(self, chunk_tokens: int = 512, overlap_tokens: int = 0, token_ratios: dict[str, float] | None = None) -> None:
+        """token_ratios — символов на токен по письменностям, как в ContextCompressor"""
+    def split(self, text: str) -> list[TextChunk]: ...
+    def split_many(self, texts: list[str]) -> list[list[TextChunk]]:
+        """Нарезка многих документов параллельно, GIL отпущен"""
+    def estimate_tokens(self, text: str) -> int: ...
+    @property
+    def chunk_tokens(self) -> int: ...
+    @property
+    def overlap_tokens(self) -> int: ...
+
+
+class TextChunk:
+    """Чанк документа; start/end — смещения в символах, text == документ[start:end]"""
+    @property
+    def text(self) -> str: ...
+    @property
+    def index(self) -> int: ...
+    @property
+    def start(self) -> int: ...
+    @property
+    def end(self) -> int: ...
+    @property
+    def tokens(self) -> int: ...
+    def __repr__(self) -> str: ...
+
+
+class StreamAccumulator:
+    """Накопитель потокового ответа LLM: отдаёт готовые предложения/абзацы,
+    блоки кода и строки ACTION по мере поступления чанков
+    """
+    def __init__(self, mode: str = "sentence", token_ratios: dict[str, float] | None = None) -> None:
+        """mode — "sentence" (для TTS) или "paragraph";
+        token_ratios — символов на токен по письменностям, как в ContextCompressor
+        """
+    def push(self, chunk: str) -> list[StreamSegment]:
+        """Добавить чанк; возвращает сегменты, завершённые этим чанком"""
+    def finish(self) -> list[StreamSegment]:
+        """Конец ответа: остаток хвоста (незакрытый блок кода — целиком)"""
+    def reset(self) -> None:
+        """Сбросить всё к началу нового ответа"""
+    @property
+    def pending(self) -> str:
+        """Ещё не отданный текст"""
+    @property
+    def tokens(self) -> int:
+        """Оценка токенов всего полученного текста"""
+    @property
+    def chars(self) -> int:
+        """Символов получено"""
+    @property
+    def in_code(self) -> bool:
+        """Открыт блок кода — хвост не озвучивать"""
+    @property
+    def mode(self) -> str: ...
+    def __repr__(self) -> str: ...
+
+
+class StreamSegment:
+    """Готовый фрагмент потока; start/end — смещения в символах от начала ответа"""
+    @property
+    def text(self) -> str: ...
+    @property
+    def kind(self) -> str:
+        """Вид: sentence, paragraph, code или action"""
+    @property
+    def index(self) -> int: ...
+    @property
+    def start(self) -> int: ...
+    @property
+    def end(self) -> int: ...
+    @property
+    def tokens(self) -> int: ...
+    def __repr__(self) -> str: ...
+
+
+class Deduplicator:
+    def __init__(self, num_perm: int = 128, bands: int = 32, shingle_size: int = 3) -> None: ...
+    def minhash(self, text: str) -> list[int]:
+        """MinHash-сигнатура (num_perm значений); пустой текст — пустая сигнатура"""
+    def simhash(self, text: str) -> int: ...
+    @staticmethod
+    def hamming_distance(a: int, b: int) -> int:
+        """Число различающихся бит двух SimHash"""
+    def similarity(self, a: str, b: str) -> float:
+        """Оценка сходства Жаккара по MinHash (0.0–1.0)"""
+    def is_duplicate(self, a: str, b: str, threshold: float = 0.8) -> bool: ...
+    def find_duplicates(self, corpus: list[str], threshold: float = 0.8) -> list[tuple[int, int, float]]:
+        """Пары почти-дубликатов корпуса → [(i, j, similarity)], i < j"""
+    def unique(self, corpus: list[str], threshold: float = 0.8) -> list[int]:
+        """Индексы текстов, оставшихся после удаления дубликатов (первое вхождение)"""
+
+
+class KeywordExtractor:
+    def __init__(self, max_words: int = 3, min_chars: int = 3, stop_words: list[str] | None = None, *, config: CoreConfig | None = None) -> None:
+        """max_words — фразы длиннее отбрасываются; min_chars — более короткие
+        слова работают как разделители; стоп-слова из lexicons.stop_words config
+        добавляются к stop_words
+        """
+    def add_stop_words(self, words: list[str]) -> None: ...
+    def extract(self, text: str, top_n: int = 10) -> list[tuple[str, float]]:
+        """[(фраза, вес)] по убыванию веса"""
+    def extract_many(self, texts: list[str], top_n: int = 10) -> list[list[tuple[str, float]]]:
+        """Пакетная версия extract через Rayon без GIL"""
+
+
+class TfIdfVectorizer:
+    def __init__(self, sublinear_tf: bool = False, min_df: int = 1) -> None: ...
+    def fit(self, corpus: list[str]) -> None:
+        """Строит словарь и IDF по корпусу, запоминает векторы документов для search"""
+    def transform(self, texts: list[str]) -> list[list[tuple[int, float]]]:
+        """Векторы текстов → [[(term_index, weight)]]"""
+    def fit_transform(self, corpus: list[str]) -> list[list[tuple[int, float]]]: ...
+    def search(self, query: str, top_k: int = 5) -> list[tuple[int, float]]:
+        """Ближайшие документы корпуса fit → [(doc_index, cosine)], нулевые не возвращаются"""
+    def similarity(self, a: str, b: str) -> float:
+        """Косинусное сходство двух текстов в пространстве словаря"""
+    def get_vocabulary(self) -> dict[str, int]:
+        """Словарь: терм (основа) → индекс"""
+    def __len__(self) -> int: ...
+
+
+class PiiScrubber:
+    def __init__(self, kinds: list[str] | None = None) -> None:
+        """kinds — какие типы искать (по умолчанию все)"""
+    def scan(self, text: str) -> list[PiiMatch]:
+        """Все находки по возрастанию start"""
+    def contains_pii(self, text: str) -> bool: ...
+    def mask(self, text: str, placeholder: str | None = None) -> str:
+        """Текст для логов: находки заменены на "[EMAIL]", "[PHONE]" и т.п.
+        или на placeholder, если он задан
+        """
+
+
+class PiiMatch:
+    """Найденный фрагмент: kind — тип данных, start/end — смещения в символах"""
+    @property
+    def kind(self) -> str: ...
+    @property
+    def text(self) -> str: ...
+    @property
+    def start(self) -> int: ...
+    @property
+    def end(self) -> int: ...
+    def __repr__(self) -> str: ...
+
+
+class ProfanityFilter:
+    def __init__(self, extra_words: list[str] | None = None, exceptions: list[str] | None = None, *, config: CoreConfig | None = None) -> None:
+        """extra_words — дополнительные корни; exceptions — слова, которые не считать матом;
+        lexicons.profanity_words / profanity_exceptions config добавляются к ним
+        """
+    def add_words(self, words: list[str], exact: bool = False) -> None:
+        """Добавить слова: exact=True — только целые слова, иначе корни"""
+    def add_exceptions(self, words: list[str]) -> None: ...
+    def contains_profanity(self, text: str) -> bool: ...
+    def find(self, text: str) -> list[tuple[str, int, int]]:
+        """Найденные слова → [(word, start, end)], смещения в символах"""
+    def censor(self, text: str, mask_char: str = "*", keep_first: bool = False) -> str:
+        """Заменяет найденные слова символом mask_char; keep_first — оставить первую букву"""
+
+
+class DateTimeMatch:
+    """Найденное выражение; start/end — смещения в символах, text — охваченный фрагмент"""
+    @property
+    def timestamp(self) -> str:
+        """RFC 3339 в часовом поясе now"""
+    @property
+    def unix(self) -> int: ...
+    @property
+    def text(self) -> str: ...
+    @property
+    def start(self) -> int: ...
+    @property
+    def end(self) -> int: ...
+    def __repr__(self) -> str: ...
+
+
+class PromptTemplate:
+    def __init__(self, template: str, token_ratios: dict[str, float] | None = None, strict: bool = False) -> None:
+        """token_ratios — символов на токен по письменностям, как в ContextCompressor;
+        strict — ошибка при отсутствующей переменной
+        """
+    def render(self, values: dict[str, Any] | None = None, **kwargs: Any) -> str:
+        """Значения — словарём и/или именованными аргументами (они важнее)"""
+    def variables(self) -> list[str]:
+        """Имена переменных верхнего уровня в порядке появления"""
+    def estimate_tokens(self, text: str) -> int: ...
+
+
+class RateLimiter:
+    def __init__(self, capacity: float | None = None, refill_per_sec: float | None = None, idle_ttl_secs: float | None = None, *, config: CoreConfig | None = None) -> None:
+        """capacity — размер ведра (всплеск); refill_per_sec — скорость пополнения;
+        незаданные параметры берутся из раздела rate_limit config
+        """
+    def try_acquire(self, user_id: str, cost: float = 1.0) -> bool:
+        """Списывает cost токенов, если они есть; иначе False и ничего не списывается"""
+    def acquire(self, user_id: str, cost: float = 1.0, timeout: float | None = None) -> bool:
+        """Ждёт токены с отпущенным GIL; timeout=None — без ограничения (но если
+        cost больше ёмкости ведра — сразу False)
+        """
+    def retry_after(self, user_id: str, cost: float = 1.0) -> float:
+        """Через сколько секунд будет доступно cost токенов (0 — сейчас, inf — никогда)"""
+    def available(self, user_id: str) -> float:
+        """Доступные токены пользователя сейчас"""
+    def set_limit(self, user_id: str, capacity: float, refill_per_sec: float) -> None:
+        """Индивидуальный лимит; текущие токены обрезаются до новой ёмкости"""
+    def set_default_limit(self, capacity: float, refill_per_sec: float) -> None:
+        """Лимит по умолчанию — для новых и всех вёдер без индивидуального лимита"""
+    def reset(self, user_id: str) -> bool:
+        """Полное ведро и лимит по умолчанию; True, если пользователь был"""
+    def cleanup(self) -> int:
+        """Удаляет вёдра, не использовавшиеся дольше idle_ttl_secs и уже полные
+        (индивидуальные лимиты сохраняются); возвращает число удалённых
+        """
+    def __len__(self) -> int: ...
+
+
+class SessionManager:
+    def __init__(self, idle_timeout_secs: int | None = None, max_sessions: int | None = None, working_size: int | None = None, thread_timeout_secs: int | None = None, data_dir: str | None = None, *, config: CoreConfig | None = None) -> None:
+        """Незаданные параметры берутся из раздела sessions config;
+        data_dir — sessions.dir или data_dir/sessions
+        """
+    def get_or_create(self, user_id: str) -> Session:
+        """Сессия пользователя: живая, восстановленная из снимка или новая"""
+    def get(self, user_id: str) -> Session | None:
+        """Живая сессия без создания и без продления активности"""
+    def remove(self, user_id: str) -> bool:
+        """Забывает пользователя вместе со снимком; True, если сессия была"""
+    def expire_idle(self) -> list[str]:
+        """Выгружает простаивающие сессии (со снимком при data_dir);
+        возвращает их user_id
+        """
+    def save(self) -> int:
+        """Снимки всех живых сессий; возвращает их число"""
+    def save_async(self) -> asyncio.Future[int]:
+        """save без блокировки цикла asyncio"""
+    def users(self) -> list[str]: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, user_id: str) -> bool: ...
+    def get_stats(self) -> dict[str, Any]:
+        """active, created, restored, expired (по простою), evicted (сверх max_sessions),
+        messages (в живых сессиях)
+        """
+
+
+class Session:
+    """Сессия пользователя; живёт, пока её держит SessionManager или Python"""
+    @property
+    def user_id(self) -> str: ...
+    @property
+    def created_at(self) -> str:
+        """Время создания (RFC 3339); у восстановленной сессии — исходное"""
+    @property
+    def last_active(self) -> str: ...
+    def idle_secs(self) -> float:
+        """Секунд с последней активности"""
+    def add_message(self, role: str, content: str) -> None:
+        """Сообщение в рабочую память; реплики role="user" обновляют настроение"""
+    def record_exchange(self, user_input: str, response: str) -> None:
+        """Обмен репликами: обе — в рабочую память, пара — в трекер нитей"""
+    def get_working_memory(self) -> list[tuple[str, str, str]]:
+        """[(role, content, timestamp)] — от старых к новым"""
+    def clear_working(self) -> None: ...
+    def get_mood(self) -> tuple[str, float, str]:
+        """(label, valence, last_emotion)"""
+    def set_mood(self, label: str, valence: float | None = None) -> None:
+        """Явное настроение (например, из внешней модели); valence в [-1, 1],
+        None — оставить текущую
+        """
+    def get_current_topic(self) -> str | None: ...
+    def get_entities(self) -> list[str]: ...
+    def get_thread_context(self, max_exchanges: int = 3) -> str | None:
+        """Контекст активной нити для промпта (см. ThreadTracker.get_context)"""
+    def end_thread(self) -> None: ...
+    def __repr__(self) -> str: ...
+
+
+class UserProfile:
+    """Профиль пользователя для промпта; наполняется observe_* по ходу разговора"""
+    def __init__(self, user_id: str, memory: MemoryEngine | None = None, *, storage: Storage | None = None, data_dir: str | None = None, config: CoreConfig | None = None) -> None:
+        """Хранилище: memory (рядом с его данными), storage, data_dir или
+        memory.dir / data_dir из config; ничего из этого — профиль только в памяти
+        """
+    @property
+    def user_id(self) -> str: ...
+    @property
+    def messages(self) -> int:
+        """Сообщений учтено в часах активности"""
+    def observe_emotion(self, emotion: str, confidence: float = 1.0) -> None:
+        """Эмоция реплики пользователя с уверенностью в [0, 1]"""
+    def observe_topic(self, topic: str | None, entities: list[str] | None = None) -> None:
+        """Тема и сущности нити (ThreadTracker.get_current_topic / get_entities)"""
+    def observe_activity(self, timestamp: str | None = None) -> None:
+        """Сообщение пользователя в момент timestamp (RFC 3339; час — по его смещению),
+        по умолчанию — сейчас по местному времени
+        """
+    def observe_session(self, session: Session) -> None:
+        """Все сигналы сессии за реплику: эмоция, тема и сущности нити, час активности"""
+    def render(self, max_tokens: int = 120) -> str:
+        """Блок профиля для промпта не длиннее max_tokens; "" — профиль пуст"""
+    def top_topics(self, n: int = 5) -> list[tuple[str, int]]:
+        """[(тема, счёт)] — самые частые"""
+    def top_entities(self, n: int = 5) -> list[tuple[str, int]]: ...
+    def emotion_stats(self) -> dict[str, float]:
+        """{эмоция: доля}; доли в сумме дают 1"""
+    def active_hours(self, n: int = 3) -> list[int]:
+        """Самые активные часы суток, по возрастанию"""
+    def clear(self) -> None: ...
+    def save(self) -> None:
+        """Сбой записи → PersistenceError; без хранилища → ValueError"""
+    def load(self) -> None:
+        """Перечитать из хранилища; записи нет — профиль не меняется,
+        битая запись → PersistenceError
+        """
+    def save_async(self) -> asyncio.Future[None]:
+        """save без блокировки цикла asyncio"""
+    def load_async(self) -> asyncio.Future[None]: ...
+    def __repr__(self) -> str: ...
+
+
+class KnowledgeGraph:
+    def __init__(self, path: str | None = None) -> None:
+        """path — JSON-файл графа; загружается, если существует"""
+    def add(self, subject: str, predicate: str, object: str) -> bool:
+        """Добавляет тройку; False, если такая уже есть"""
+    def add_many(self, triples: list[tuple[str, str, str]]) -> int:
+        """Добавляет тройки; возвращает число новых"""
+    def remove(self, subject: str, predicate: str, object: str) -> bool: ...
+    def remove_matching(self, subject: str | None = None, predicate: str | None = None, object: str | None = None) -> int:
+        """Удаляет тройки по шаблону (None — любое значение); возвращает их число"""
+    def remove_node(self, node: str) -> int:
+        """Удаляет узел со всеми его рёбрами; возвращает число удалённых троек"""
+    def contains(self, subject: str, predicate: str, object: str) -> bool: ...
+    def query(self, subject: str | None = None, predicate: str | None = None, object: str | None = None, limit: int | None = None) -> list[tuple[str, str, str]]:
+        """Тройки по шаблону: query(subject="кот_Барсик"), query(predicate="принадлежит",
+        object="пользователь"); без аргументов — все
+        """
+    def neighbors(self, node: str, predicate: str | None = None, direction: str = "both") -> list[tuple[str, str, str]]:
+        """Соседи узла: [(predicate, node, direction)], direction — "out" или "in"
+        """
+    def traverse(self, start: str, max_depth: int = 2, predicate: str | None = None, direction: str = "both") -> list[tuple[str, int]]:
+        """Обход в ширину от start: [(node, depth)] без самого start"""
+    def path(self, source: str, target: str, max_depth: int = 4) -> list[tuple[str, str, str]] | None:
+        """Кратчайший путь между узлами (рёбра в любую сторону) как список троек;
+        None — пути нет в пределах max_depth
+        """
+    def nodes(self) -> list[str]:
+        """Все узлы (субъекты и объекты), отсортированные"""
+    def predicates(self) -> list[tuple[str, int]]:
+        """Предикаты с числом троек, по убыванию"""
+    def clear(self) -> None: ...
+    def save(self, path: str | None = None) -> None:
+        """Сохраняет граф в JSON (path — иначе путь из конструктора)"""
+    def load(self, path: str | None = None) -> None:
+        """Заменяет граф содержимым JSON-файла"""
+    def save_async(self, path: str | None = None) -> asyncio.Future[None]:
+        """save / load без блокировки цикла asyncio"""
+    def load_async(self, path: str | None = None) -> asyncio.Future[None]: ...
+    def __len__(self) -> int: ...
+
+
+class Pipeline:
+    def __init__(self, analyzer: EmotionAnalyzer, tracker: ThreadTracker, memory: MemoryEngine, compressor: ContextCompressor, memory_items: int | None = None, budget_tokens: int | None = None, keep_last: int | None = None, add_to_working: bool | None = None, *, config: CoreConfig | None = None) -> None:
+        """memory_items — сколько эпизодов искать; budget_tokens / keep_last —
+        сжатие рабочей памяти (см. ContextCompressor.compress_working_memory);
+        add_to_working — класть реплики в рабочую память MemoryEngine;
+        незаданные параметры берутся из раздела pipeline config
+        """
+    def process(self, user_input: str, response: str | None = None) -> PipelineResult:
+        """Обрабатывает сообщение пользователя. Без response — анализ до ответа:
+        нить только оценивается (relatedness), в рабочую память идёт реплика
+        пользователя. С response — обмен целиком: маршрутизация по нитям
+        (ThreadTracker.update) и обе реплики в рабочую память.
+        """
+
+
+class PipelineResult:
+    @property
+    def emotion(self) -> str: ...
+    @property
+    def emotion_confidence(self) -> float: ...
+    @property
+    def emotion_matches(self) -> list[str]: ...
+    @property
+    def relatedness(self) -> float:
+        """Связанность с активной нитью до маршрутизации сообщения"""
+    @property
+    def is_related(self) -> bool: ...
+    @property
+    def thread_id(self) -> int | None: ...
+    @property
+    def topic(self) -> str | None: ...
+    @property
+    def entities(self) -> list[str]: ...
+    @property
+    def thread_context(self) -> str | None:
+        """Контекст активной нити (ThreadTracker.get_context)"""
+    @property
+    def memories(self) -> list[tuple[str, str, int]]:
+        """[(timestamp, preview, score)] — как MemoryEngine.get_relevant_context"""
+    @property
+    def context(self) -> str:
+        """Сжатая рабочая память строками "role: content"
+        """
+    @property
+    def context_tokens(self) -> int: ...
+    @property
+    def elapsed_ms(self) -> float: ...
+    def __repr__(self) -> str: ...
+
+
+class EventBus:
+    def __init__(self, max_queue: int | None = None, *, config: CoreConfig | None = None) -> None: ...
+    def subscribe(self, callback: Any, kinds: list[str] | None = None) -> int:
+        """callable(Event) на события kinds (None — все); возвращает id подписки"""
+    def unsubscribe(self, subscription_id: int) -> bool: ...
+    def emit(self, kind: str, payload: dict[str, Any] | None = None, source: str = "python") -> None:
+        """Событие из Python (плагины): payload — dict с JSON-совместимыми значениями"""
+    def drain_events(self, limit: int | None = None) -> list[Event]:
+        """Забирает накопленные события (старые первыми); limit=None — все"""
+    def pending(self) -> int:
+        """Событий в очереди"""
+    def get_stats(self) -> tuple[int, int, int]:
+        """(опубликовано, вытеснено из очереди, подписчиков)"""
+
+
+class Event:
+    @property
+    def seq(self) -> int: ...
+    @property
+    def kind(self) -> str: ...
+    @property
+    def source(self) -> str: ...
+    @property
+    def timestamp(self) -> str:
+        """RFC 3339"""
+    @property
+    def payload(self) -> dict[str, Any]: ...
+    def to_dict(self) -> dict[str, Any]:
+        """{"seq", "kind", "source", "timestamp", "payload"}"""
+    def __repr__(self) -> str: ...
+
+
+class Metrics:
+    def __init__(self) -> None: ...
+    def snapshot(self) -> dict[str, Any]:
+        """{"counters": {name: int}, "ratios": {name: float},
+         "histograms": {name: {"count", "sum", "mean", "buckets": [(le, cumulative)]}}}
+        """
+    def to_prometheus(self) -> str:
+        """Текстовый формат Prometheus (exposition format 0.0.4)"""
+    def inc(self, name: str, value: int = 1) -> None:
+        """Счётчик из Python: inc("llm_requests_total")"""
+    def observe(self, name: str, value: float) -> None:
+        """Наблюдение в гистограмму из Python (секунды): observe("llm_latency_seconds", 0.8)"""
+    def reset(self) -> None:
+        """Обнуляет все метрики"""
+    def set_enabled(self, enabled: bool) -> None: ...
+    def is_enabled(self) -> bool: ...
+
+
+class Maintenance:
+    """Планировщик фонового обслуживания: сохранение, истечение, очистка, checkpoint"""
+    def __init__(self, *, save_secs: float | None = None, expire_secs: float | None = None, prune_secs: float | None = None, checkpoint_secs: float | None = None, config: CoreConfig | None = None) -> None:
+        """Интервалы в секундах (0 — вид отключён); незаданные — из раздела
+        maintenance config, затем по умолчанию
+        """
+    def add(self, component: Any) -> list[str]:
+        """Зарегистрировать задачи компонента; возвращает их имена.
+        Неподдерживаемый тип → TypeError
+        """
+    def start(self) -> bool:
+        """Запустить фоновый поток; False — уже запущен"""
+    def stop(self) -> bool:
+        """Остановить поток и дождаться текущей задачи; False — не был запущен"""
+    @property
+    def running(self) -> bool: ...
+    def run_now(self, kind: str | None = None) -> list[str]:
+        """Выполнить задачи сейчас (все или вида kind: save, expire, prune, checkpoint);
+        возвращает имена выполненных, итоги — в status()
+        """
+    def status(self) -> dict[str, Any]:
+        """{имя: {kind, interval_secs, runs, failures, last_run, duration_ms, result, error, next_in_secs}}"""
+    def __len__(self) -> int: ...
+    def __repr__(self) -> str: ...
+
+
+class JsonRepair:
+    @property
+    def json(self) -> str | None:
+        """Валидный компактный JSON; None — починить не удалось"""
+    @property
+    def fixes(self) -> list[str]:
+        """Что было исправлено: "висячая запятая", "одинарные кавычки", ..."""
+    @property
+    def error(self) -> str | None:
+        """Ошибка разбора после починки"""
+    @property
+    def position(self) -> tuple[int, int] | None:
+        """(строка, столбец) ошибки в исправленном тексте, с 1"""
+    @property
+    def ok(self) -> bool: ...
+    @property
+    def unchanged(self) -> bool:
+        """Исправлений не понадобилось"""
+    def value(self) -> Any:
+        """Разобранное значение (dict / list / ...); ParseError, если починить не удалось"""
+    def __repr__(self) -> str: ...
+
+
+class MarkdownElement:
+    @property
+    def kind(self) -> str:
+        """heading / list_item / code_block / link / image"""
+    @property
+    def text(self) -> str:
+        """Текст без разметки; для code_block — код"""
+    @property
+    def level(self) -> int:
+        """Уровень заголовка (1–6) или вложенность пункта списка (с 1); иначе 0"""
+    @property
+    def ordered(self) -> bool:
+        """Нумерованный пункт списка"""
+    @property
+    def url(self) -> str | None:
+        """URL ссылки / картинки (None для ссылок по метке [text][ref])"""
+    @property
+    def language(self) -> str | None:
+        """Язык блока кода (```python)"""
+    @property
+    def start(self) -> int: ...
+    @property
+    def end(self) -> int: ...
+    def __repr__(self) -> str: ...
+
+
+class SpellChecker:
+    def __init__(self, max_edit_distance: int | None = None, prefix_length: int | None = None, *, config: CoreConfig | None = None) -> None: ...
+    def load_dictionary(self, path: str) -> int:
+        """Частотный словарь: строки "слово частота" (или просто "слово" — частота 1);
+        возвращает число прочитанных строк
+        """
+    def load_dictionary_async(self, path: str) -> asyncio.Future[int]:
+        """load_dictionary без блокировки цикла asyncio"""
+    def add_word(self, word: str, frequency: int = 1) -> None:
+        """Добавляет слово (частота суммируется с имеющейся)"""
+    def add_text(self, text: str) -> None:
+        """Пополняет словарь словами текста (частота — число вхождений)"""
+    def contains(self, word: str) -> bool: ...
+    def frequency(self, word: str) -> int:
+        """Частота слова в словаре (0 — нет)"""
+    def suggest(self, word: str, max_distance: int | None = None, limit: int = 5) -> list[tuple[str, int, int]]:
+        """Кандидаты [(слово, расстояние, частота)] — лучшие первыми"""
+    def correct(self, word: str, max_distance: int | None = None) -> str:
+        """Лучшее исправление слова; без кандидатов — слово как есть"""
+    def correct_text(self, text: str, max_distance: int | None = None) -> str:
+        """Исправляет все слова текста; пунктуация, пробелы и числа сохраняются"""
+    def __len__(self) -> int: ...
+
+
+def cosine_similarity(a: list[float], b: list[float]) -> float:
+    """Косинусное сходство двух векторов.
+    Возвращает 0.0 при несовпадении размерностей или нулевых нормах.
+    """
+
+
+def batch_cosine_similarity(query: list[float], documents: list[list[float]], top_k: int = 5) -> list[tuple[int, float]]:
+    """Batch cosine similarity: query vs N документов.
+    Возвращает top_k пар (index, similarity), отсортированных по убыванию.
+    Использует Rayon для параллелизма при > 32 документах.
+    """
+
+
+def split_sentences(text: str) -> list[str]:
+    """Разбивает текст на предложения (с завершающей пунктуацией)"""
+
+
+def detect_language(text: str) -> tuple[str, float]:
+    """Язык текста по символьным триграммам → (lang_code, confidence);
+    lang_code: "ru", "uk", "en" или "unknown"
+    """
+
+
+def stem(word: str, lang: str = "auto") -> str:
+    """Основа слова; lang — "ru", "en" или "auto" (по письменности)"""
+
+
+def transliterate(text: str, direction: str = "auto") -> str:
+    """Транслитерация: direction — "to_latin", "to_cyrillic" или "auto"
+    (кириллицы больше — в латиницу, иначе в кириллицу)
+    """
+
+
+def parse_datetime_ru(text: str, now: str | None = None) -> DateTimeMatch | None:
+    """Первое выражение даты/времени в тексте → DateTimeMatch или None;
+    now — ISO 8601 (по умолчанию текущее время UTC), его часовой пояс сохраняется
+    """
+
+
+def repair_json(text: str) -> JsonRepair:
+    """Чинит типичные повреждения JSON в выводе LLM → JsonRepair"""
+
+
+def strip_markdown(text: str, keep_code: bool = True) -> str:
+    """Текст без markdown-разметки; keep_code=False — без содержимого блоков кода"""
+
+
+def extract_structure(text: str) -> list[MarkdownElement]:
+    """Заголовки, пункты списков, блоки кода и ссылки в порядке появления"""
+
+
+def normalize(text: str, fold_homoglyphs: bool = True, remove_zero_width: bool = True, collapse_whitespace: bool = True, yo_to_e: bool = False, lowercase: bool = False) -> str:
+    """Нормализует текст: NFC, двойники, невидимые символы, пробелы;
+    yo_to_e — ё → е, lowercase — нижний регистр
+    """
+
+
+def refresh_log_levels() -> None:
+    """Перечитать уровни логгеров kristina_core.* после изменения конфигурации logging"""
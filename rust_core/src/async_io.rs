@@ -0,0 +1,152 @@
+//! Асинхронные варианты блокирующих методов (*_async)
+//!
+//! - Возвращают asyncio.Future запущенного цикла событий:
+//!   `await memory.save_async()` не останавливает бота на время записи
+//! - Работа выполняется в фоновом пуле потоков ввода-вывода (DEFAULT_IO_THREADS,
+//!   CoreConfig.runtime.io_threads до первого запуска); rayon не используется —
+//!   его потоки заняты вычислениями
+//! - Результат (или исключение) передаётся в цикл через call_soon_threadsafe;
+//!   у отменённого future результат отбрасывается
+//! - Вызывать из корутины: без запущенного цикла — RuntimeError asyncio
+
+use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
+use pyo3::exceptions::PyRuntimeError;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, OnceLock};
+
+/// Потоков ввода-вывода: диск не ускоряется от большего числа
+pub(crate) const DEFAULT_IO_THREADS: usize = 4;
+
+static IO_THREADS: AtomicUsize = AtomicUsize::new(DEFAULT_IO_THREADS);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Executor {
+    sender: Sender<Job>,
+}
+
+impl Executor {
+    /// Ошибка запуска потока — пул не создаётся (уже запущенные потоки
+    /// завершаются вместе с закрытым каналом)
+    fn new(threads: usize) -> std::io::Result<Self> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..threads {
+            let receiver = Arc::clone(&receiver);
+            std::thread::Builder::new()
+                .name(format!("kristina-io-{}", i))
+                .spawn(move || loop {
+                    let job = receiver.lock().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })?;
+        }
+        Ok(Self { sender })
+    }
+
+    fn submit(&self, job: Job) {
+        // Потоки живут до конца процесса: получатель не закрывается
+        let _ = self.sender.send(job);
+    }
+}
+
+static EXECUTOR: OnceLock<Executor> = OnceLock::new();
+
+fn executor() -> PyResult<&'static Executor> {
+    if let Some(executor) = EXECUTOR.get() {
+        return Ok(executor);
+    }
+    let executor = Executor::new(IO_THREADS.load(Ordering::Relaxed))
+        .map_err(|e| PyRuntimeError::new_err(format!("Не удалось запустить пул ввода-вывода: {}", e)))?;
+    // Параллельный первый вызов мог успеть раньше — лишний пул закроется при drop
+    Ok(EXECUTOR.get_or_init(|| executor))
+}
+
+/// Размер пула; после первого *_async менять поздно
+pub(crate) fn set_threads(threads: usize) -> Result<(), String> {
+    if threads == 0 {
+        return Err("Число потоков ввода-вывода должно быть > 0".to_string());
+    }
+    if EXECUTOR.get().is_some() {
+        return Err("Пул ввода-вывода уже запущен".to_string());
+    }
+    IO_THREADS.store(threads, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Завершение future в потоке цикла событий (через call_soon_threadsafe)
+#[pyclass(frozen)]
+struct Completion {
+    future: PyObject,
+    outcome: Mutex<Option<PyResult<PyObject>>>,
+}
+
+#[pymethods]
+impl Completion {
+    fn __call__(&self, py: Python<'_>) -> PyResult<()> {
+        let future = self.future.bind(py);
+        // Отменён, пока работа шла
+        if future.call_method0("done")?.extract::<bool>()? {
+            return Ok(());
+        }
+        match self.outcome.lock().take() {
+            Some(Ok(value)) => future.call_method1("set_result", (value,))?,
+            Some(Err(err)) => future.call_method1("set_exception", (err.into_value(py),))?,
+            None => return Ok(()),
+        };
+        Ok(())
+    }
+}
+
+/// Выполняет job в пуле ввода-вывода → asyncio.Future с его результатом.
+/// Ошибки job возвращает как PyResult: паника в release (panic = "abort")
+/// завершила бы процесс, перехватить её нельзя
+pub(crate) fn spawn<'py, T, F>(py: Python<'py>, job: F) -> PyResult<Bound<'py, PyAny>>
+where
+    F: FnOnce() -> PyResult<T> + Send + 'static,
+    T: for<'a> IntoPyObject<'a> + Send + 'static,
+{
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    let future = event_loop.call_method0("create_future")?;
+    let (event_loop, target) = (event_loop.unbind(), future.clone().unbind());
+    let executor = executor()?;
+    executor.submit(Box::new(move || {
+        let result = job();
+        Python::with_gil(|py| {
+            let outcome = result.and_then(|value| value.into_py_any(py));
+            let completion = Completion { future: target, outcome: Mutex::new(Some(outcome)) };
+            // Цикл уже закрыт — результат некому отдавать
+            let _ = Py::new(py, completion)
+                .and_then(|completion| event_loop.call_method1(py, "call_soon_threadsafe", (completion,)));
+        });
+    }));
+    Ok(future)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_executor_runs_jobs_concurrently() {
+        let executor = Executor::new(2).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        for i in 0..2 {
+            let (tx, barrier) = (tx.clone(), Arc::clone(&barrier));
+            // Обе задачи ждут друг друга: пройдут, только если потоков два
+            executor.submit(Box::new(move || {
+                barrier.wait();
+                tx.send(i).unwrap();
+            }));
+        }
+        let mut done: Vec<i32> = (0..2).map(|_| rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap()).collect();
+        done.sort();
+        assert_eq!(done, vec![0, 1]);
+    }
+}
@@ -0,0 +1,275 @@
+//! Bm25Index — полнотекстовый индекс Okapi BM25
+//!
+//! - Токенизация: слова из букв/цифр, нижний регистр, ё→е, без стоп-слов
+//! - Стемминг RU/EN (Snowball) — "переезды" находит "переезд"
+//! - Инвертированный индекс term → {doc_id: tf}, длины документов для нормализации
+//! - Персистентность: JSON {doc_id: {term: tf}}, индекс восстанавливается при загрузке;
+//!   ошибки чтения и записи → PersistenceError
+
+use pyo3::prelude::*;
+use parking_lot::RwLock;
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::async_io;
+use crate::errors::PersistenceError;
+use crate::stemmer::stem_word;
+
+// ── Стоп-слова (RU + EN) ──
+
+pub(crate) const STOP_WORDS: &[&str] = &[
+    "и", "в", "во", "не", "что", "он", "на", "я", "с", "со", "как", "а", "то", "все", "она", "так", "его",
+    "но", "да", "ты", "к", "у", "же", "вы", "за", "бы", "по", "только", "ее", "мне", "было", "вот", "от",
+    "меня", "еще", "нет", "о", "из", "ему", "теперь", "когда", "даже", "ну", "ли", "если", "уже", "или",
+    "ни", "быть", "был", "него", "до", "вас", "нибудь", "уж", "вам", "ведь", "там", "потом", "себя",
+    "ничего", "ей", "может", "они", "тут", "где", "есть", "надо", "ней", "для", "мы", "тебя", "их", "чем",
+    "была", "сам", "чтоб", "без", "будто", "чего", "раз", "тоже", "себе", "под", "будет", "ж", "тогда",
+    "кто", "этот", "того", "потому", "этого", "какой", "совсем", "ним", "здесь", "этом", "один", "почти",
+    "мой", "тем", "чтобы", "нее", "были", "куда", "зачем", "всех", "можно", "при", "об", "это", "эти",
+    "the", "a", "an", "and", "or", "but", "is", "are", "was", "were", "be", "been", "in", "on", "at", "to",
+    "of", "for", "with", "by", "from", "as", "it", "its", "this", "that", "these", "those", "i", "you",
+    "he", "she", "we", "they", "my", "your", "not", "no", "do", "does", "did", "so", "if", "then", "than",
+    "me", "him", "her", "us", "them", "our", "their", "there", "what", "which", "who", "how", "all", "any",
+    "can", "will", "would", "has", "have", "had", "also", "just", "only", "very", "some", "such", "more",
+    "most", "other", "over", "under", "about", "into", "through", "after", "before", "between", "up", "out",
+];
+
+/// Термы текста: слова → нижний регистр → без стоп-слов → основа
+pub(crate) fn analyze(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase().replace('ё', "е"))
+        .filter(|w| !STOP_WORDS.contains(&w.as_str()))
+        .map(|w| stem_word(&w))
+        .collect()
+}
+
+// ── Внутренние структуры ──
+
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    /// doc_id → (term → tf)
+    docs: HashMap<String, HashMap<String, u32>>,
+    #[serde(skip)]
+    postings: HashMap<String, HashMap<String, u32>>,
+    #[serde(skip)]
+    lens: HashMap<String, usize>,
+    #[serde(skip)]
+    total_len: usize,
+}
+
+impl Index {
+    fn insert(&mut self, doc_id: &str, terms: HashMap<String, u32>) {
+        self.remove(doc_id);
+        for (term, &tf) in &terms {
+            self.postings.entry(term.clone()).or_default().insert(doc_id.to_string(), tf);
+        }
+        let len = terms.values().map(|&tf| tf as usize).sum();
+        self.total_len += len;
+        self.lens.insert(doc_id.to_string(), len);
+        self.docs.insert(doc_id.to_string(), terms);
+    }
+
+    fn remove(&mut self, doc_id: &str) -> bool {
+        let Some(terms) = self.docs.remove(doc_id) else {
+            return false;
+        };
+        self.total_len -= self.lens.remove(doc_id).unwrap_or(0);
+        for term in terms.into_keys() {
+            if let Some(posting) = self.postings.get_mut(&term) {
+                posting.remove(doc_id);
+                if posting.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+        true
+    }
+
+    fn rebuild(&mut self) {
+        let docs = std::mem::take(&mut self.docs);
+        self.postings.clear();
+        self.lens.clear();
+        self.total_len = 0;
+        for (doc_id, terms) in docs {
+            self.insert(&doc_id, terms);
+        }
+    }
+
+    fn search(&self, query: &str, top_k: usize, k1: f64, b: f64) -> Vec<(String, f64)> {
+        if self.docs.is_empty() {
+            return Vec::new();
+        }
+        let n = self.docs.len() as f64;
+        let avg_len = (self.total_len as f64 / n).max(1.0);
+        let unique: HashSet<String> = analyze(query).into_iter().collect();
+
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        for term in &unique {
+            let Some(posting) = self.postings.get(term) else {
+                continue;
+            };
+            let df = posting.len() as f64;
+            let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+            for (doc_id, &tf) in posting {
+                let tf = tf as f64;
+                let norm = 1.0 - b + b * self.lens[doc_id] as f64 / avg_len;
+                *scores.entry(doc_id).or_insert(0.0) += idf * tf * (k1 + 1.0) / (tf + k1 * norm);
+            }
+        }
+
+        let mut results: Vec<(String, f64)> = scores.into_iter().map(|(id, s)| (id.to_string(), s)).collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results.truncate(top_k);
+        results
+    }
+}
+
+fn term_counts(text: &str) -> HashMap<String, u32> {
+    let mut counts = HashMap::new();
+    for term in analyze(text) {
+        *counts.entry(term).or_insert(0) += 1;
+    }
+    counts
+}
+
+// ── PyO3 класс ──
+
+#[pyclass(frozen)]
+pub struct Bm25Index {
+    k1: f64,
+    b: f64,
+    inner: RwLock<Index>,
+}
+
+#[pymethods]
+impl Bm25Index {
+    #[new]
+    #[pyo3(signature = (k1=1.2, b=0.75))]
+    fn new(k1: f64, b: f64) -> Self {
+        Self {
+            k1,
+            b: b.clamp(0.0, 1.0),
+            inner: RwLock::new(Index::default()),
+        }
+    }
+
+    /// Добавить документ (существующий с тем же doc_id заменяется)
+    fn add_document(&self, doc_id: &str, text: &str) {
+        let terms = term_counts(text);
+        self.inner.write().insert(doc_id, terms);
+    }
+
+    fn remove(&self, doc_id: &str) -> bool {
+        self.inner.write().remove(doc_id)
+    }
+
+    /// [(doc_id, score)] по убыванию релевантности
+    #[pyo3(signature = (query, top_k=10))]
+    fn search(&self, query: &str, top_k: usize) -> Vec<(String, f64)> {
+        self.inner.read().search(query, top_k, self.k1, self.b)
+    }
+
+    fn contains(&self, doc_id: &str) -> bool {
+        self.inner.read().docs.contains_key(doc_id)
+    }
+
+    fn clear(&self) {
+        *self.inner.write() = Index::default();
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.read().docs.len()
+    }
+
+    // ── Персистентность ──
+
+    fn save(&self, path: &str) -> PyResult<()> {
+        let data = serde_json::to_string(&*self.inner.read())
+            .map_err(|e| PersistenceError::new_err(format!("Не удалось сериализовать индекс: {}", e)))?;
+        std::fs::write(path, data)
+            .map_err(|e| PersistenceError::new_err(format!("Не удалось записать {}: {}", path, e)))
+    }
+
+    /// Загрузить индекс из файла, заменив текущее содержимое
+    fn load(&self, path: &str) -> PyResult<()> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| PersistenceError::new_err(format!("Не удалось прочитать {}: {}", path, e)))?;
+        let mut index: Index = serde_json::from_str(&data)
+            .map_err(|e| PersistenceError::new_err(format!("Повреждённый индекс {}: {}", path, e)))?;
+        index.rebuild();
+        *self.inner.write() = index;
+        Ok(())
+    }
+
+    /// save / load без блокировки цикла asyncio
+    fn save_async(slf: Py<Self>, py: Python<'_>, path: String) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().save(&path))
+    }
+
+    fn load_async(slf: Py<Self>, py: Python<'_>, path: String) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().load(&path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(docs: &[(&str, &str)]) -> Bm25Index {
+        let idx = Bm25Index::new(1.2, 0.75);
+        for (id, text) in docs {
+            idx.add_document(id, text);
+        }
+        idx
+    }
+
+    #[test]
+    fn test_analyze_stems_and_drops_stop_words() {
+        assert_eq!(analyze("Я люблю кошек и котов!"), vec!["любл", "кошек", "кот"]);
+        assert_eq!(analyze("The cats are running"), vec!["cat", "run"]);
+    }
+
+    #[test]
+    fn test_search_ranks_by_relevance() {
+        let idx = index(&[
+            ("a", "Переезд в Москву запланирован на весну"),
+            ("b", "Кошки любят спать на солнце"),
+            ("c", "Переезды утомляют, особенно переезд с кошкой"),
+        ]);
+        let results = idx.search("переезд", 10);
+        let ids: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["c", "a"]);
+        assert!(results[0].1 > results[1].1);
+        assert!(idx.search("квантовая физика", 10).is_empty());
+    }
+
+    #[test]
+    fn test_replace_and_remove() {
+        let idx = index(&[("a", "rust compiler"), ("b", "python interpreter")]);
+        idx.add_document("a", "garden flowers");
+        assert!(idx.search("compiler", 10).is_empty());
+        assert_eq!(idx.search("flowers", 10)[0].0, "a");
+        assert!(idx.remove("a"));
+        assert!(!idx.remove("a"));
+        assert_eq!(idx.__len__(), 1);
+        assert_eq!(idx.inner.read().total_len, 2);
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let path = std::env::temp_dir().join(format!("bm25_test_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let idx = index(&[("a", "Переезд в Москву"), ("b", "Отпуск на море")]);
+        idx.save(path).unwrap();
+
+        let loaded = Bm25Index::new(1.2, 0.75);
+        loaded.add_document("x", "лишний документ");
+        loaded.load(path).unwrap();
+        std::fs::remove_file(path).ok();
+
+        assert!(!loaded.contains("x"));
+        assert_eq!(loaded.search("переезды", 5), idx.search("переезды", 5));
+        assert_eq!(loaded.inner.read().total_len, idx.inner.read().total_len);
+    }
+}
@@ -0,0 +1,857 @@
+//! CoreConfig — общая конфигурация компонентов ядра
+//!
+//! - Разделы: memory, cache, threads, sessions, compressor, pipeline,
+//!   rate_limit, events, spelling, lexicons, runtime, maintenance; data_dir — корень
+//!   каталогов (memory/, embeddings/, threads/, sessions/), если раздел не задаёт свой
+//! - Загрузка: from_file (.toml / .json), from_toml, from_json, from_dict;
+//!   недостающие поля — значения по умолчанию, неизвестные — ошибка
+//! - Конструкторы принимают `config=`: явный аргумент важнее конфигурации,
+//!   конфигурация — значений по умолчанию
+//! - to_dict() → from_dict() возвращает равную конфигурацию
+//! - Синтаксис → ParseError, чтение файла → PersistenceError,
+//!   недопустимые значения → ValueError с путём поля ("memory.working_size")
+//! - apply_runtime() задаёт потоки пула ввода-вывода и вычислительного пула rayon
+//!   (до первого использования); runtime.shared — значение shared= по умолчанию
+
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::types::PyDict;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use crate::async_io;
+use crate::context_compressor::{ELLIPSIS, EPISODE_PREVIEW_CHARS, PREVIEW_CHARS, RECENT_WINDOW};
+use crate::errors::{ParseError, PersistenceError};
+use crate::event_bus::{json_to_py, py_to_json};
+use crate::thread_tracker::{MAX_ARCHIVED, MAX_THREAD_MESSAGES};
+
+// ── Разделы ──
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MemorySection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    pub working_size: usize,
+    pub max_episodic: usize,
+    /// Интервал автосохранения MemoryEngine, секунды (0 — выключено)
+    pub autosave_secs: f64,
+    /// Срок жизни эпизодов по importance, часы: {"1": 24}
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_by_importance: Option<HashMap<String, f64>>,
+}
+
+impl Default for MemorySection {
+    fn default() -> Self {
+        Self { dir: None, working_size: 10, max_episodic: 1000, autosave_secs: 0.0, ttl_by_importance: None }
+    }
+}
+
+impl MemorySection {
+    /// ttl_by_importance с числовыми ключами (validate проверил, что они разбираются)
+    pub fn ttl_tiers(&self) -> HashMap<i32, f64> {
+        self.ttl_by_importance
+            .iter()
+            .flatten()
+            .filter_map(|(importance, hours)| Some((importance.trim().parse().ok()?, *hours)))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CacheSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    pub max_size: usize,
+}
+
+impl Default for CacheSection {
+    fn default() -> Self {
+        Self { dir: None, max_size: 10000 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ThreadsSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    pub timeout_secs: i64,
+    pub max_open: usize,
+    /// Сколько сообщений нити хранить в архиве (нет — все)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_messages: Option<usize>,
+    pub max_thread_messages: usize,
+    pub max_archived: usize,
+}
+
+impl Default for ThreadsSection {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            timeout_secs: 600,
+            max_open: 5,
+            archive_messages: None,
+            max_thread_messages: MAX_THREAD_MESSAGES,
+            max_archived: MAX_ARCHIVED,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SessionsSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    pub idle_timeout_secs: i64,
+    pub max_sessions: usize,
+    pub working_size: usize,
+    pub thread_timeout_secs: i64,
+}
+
+impl Default for SessionsSection {
+    fn default() -> Self {
+        Self { dir: None, idle_timeout_secs: 1800, max_sessions: 10000, working_size: 20, thread_timeout_secs: 600 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CompressorSection {
+    pub compression_ratio: f64,
+    pub preview_chars: usize,
+    pub recent_messages: usize,
+    pub ellipsis: String,
+    pub episode_preview_chars: usize,
+    pub collapse_repeats: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_ratios: Option<HashMap<String, f64>>,
+}
+
+impl Default for CompressorSection {
+    fn default() -> Self {
+        Self {
+            compression_ratio: 0.3,
+            preview_chars: PREVIEW_CHARS,
+            recent_messages: RECENT_WINDOW,
+            ellipsis: ELLIPSIS.to_string(),
+            episode_preview_chars: EPISODE_PREVIEW_CHARS,
+            collapse_repeats: true,
+            token_ratios: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PipelineSection {
+    pub memory_items: usize,
+    pub budget_tokens: usize,
+    pub keep_last: usize,
+    pub add_to_working: bool,
+}
+
+impl Default for PipelineSection {
+    fn default() -> Self {
+        Self { memory_items: 3, budget_tokens: 1500, keep_last: 4, add_to_working: true }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RateLimitSection {
+    pub capacity: f64,
+    pub refill_per_sec: f64,
+    pub idle_ttl_secs: f64,
+}
+
+impl Default for RateLimitSection {
+    fn default() -> Self {
+        Self { capacity: 10.0, refill_per_sec: 1.0, idle_ttl_secs: 3600.0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct EventsSection {
+    pub max_queue: usize,
+}
+
+impl Default for EventsSection {
+    fn default() -> Self {
+        Self { max_queue: 1000 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SpellingSection {
+    pub max_edit_distance: usize,
+    pub prefix_length: usize,
+}
+
+impl Default for SpellingSection {
+    fn default() -> Self {
+        Self { max_edit_distance: 2, prefix_length: 7 }
+    }
+}
+
+/// Словари поверх встроенных
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LexiconsSection {
+    /// Важные слова ContextCompressor (заменяют встроенные)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub important_words: Option<HashMap<String, f64>>,
+    /// Дополнительные стоп-слова KeywordExtractor
+    pub stop_words: Vec<String>,
+    /// Дополнительные корни и исключения ProfanityFilter
+    pub profanity_words: Vec<String>,
+    pub profanity_exceptions: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RuntimeSection {
+    /// Потоки фонового ввода-вывода (*_async)
+    pub io_threads: usize,
+    /// Потоки rayon для пакетных операций (нет — по числу ядер)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_threads: Option<usize>,
+    /// Общее состояние для нескольких процессов (shared= компонентов)
+    pub shared: bool,
+}
+
+impl Default for RuntimeSection {
+    fn default() -> Self {
+        Self { io_threads: async_io::DEFAULT_IO_THREADS, compute_threads: None, shared: false }
+    }
+}
+
+/// Интервалы фонового обслуживания (Maintenance), секунды; 0 — вид задач отключён
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MaintenanceSection {
+    /// Сохранение памяти, кэша эмбеддингов и снимков сессий
+    pub save_secs: f64,
+    /// Архив просроченных нитей, выгрузка простаивающих сессий
+    pub expire_secs: f64,
+    /// Удаление простаивающих вёдер RateLimiter
+    pub prune_secs: f64,
+    /// Checkpoint WAL хранилищ
+    pub checkpoint_secs: f64,
+}
+
+impl Default for MaintenanceSection {
+    fn default() -> Self {
+        Self { save_secs: 300.0, expire_secs: 60.0, prune_secs: 600.0, checkpoint_secs: 3600.0 }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<String>,
+    pub memory: MemorySection,
+    pub cache: CacheSection,
+    pub threads: ThreadsSection,
+    pub sessions: SessionsSection,
+    pub compressor: CompressorSection,
+    pub pipeline: PipelineSection,
+    pub rate_limit: RateLimitSection,
+    pub events: EventsSection,
+    pub spelling: SpellingSection,
+    pub lexicons: LexiconsSection,
+    pub runtime: RuntimeSection,
+    pub maintenance: MaintenanceSection,
+}
+
+impl Config {
+    pub(crate) fn from_value(value: Value) -> Result<Self, String> {
+        let config: Config = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Каталог компонента: свой из раздела, иначе data_dir/<sub>
+    pub(crate) fn dir(&self, own: &Option<String>, sub: &str) -> Option<String> {
+        own.clone()
+            .or_else(|| self.data_dir.as_ref().map(|root| Path::new(root).join(sub).to_string_lossy().into_owned()))
+    }
+
+    pub(crate) fn memory_dir(&self) -> Option<String> {
+        self.dir(&self.memory.dir, "memory")
+    }
+
+    pub(crate) fn cache_dir(&self) -> Option<String> {
+        self.dir(&self.cache.dir, "embeddings")
+    }
+
+    pub(crate) fn threads_dir(&self) -> Option<String> {
+        self.dir(&self.threads.dir, "threads")
+    }
+
+    pub(crate) fn sessions_dir(&self) -> Option<String> {
+        self.dir(&self.sessions.dir, "sessions")
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("memory.working_size", self.memory.working_size),
+            ("memory.max_episodic", self.memory.max_episodic),
+            ("cache.max_size", self.cache.max_size),
+            ("threads.max_open", self.threads.max_open),
+            ("sessions.max_sessions", self.sessions.max_sessions),
+            ("sessions.working_size", self.sessions.working_size),
+            ("compressor.recent_messages", self.compressor.recent_messages),
+            ("runtime.io_threads", self.runtime.io_threads),
+            ("runtime.compute_threads", self.runtime.compute_threads.unwrap_or(1)),
+        ];
+        if let Some((name, _)) = positive.iter().find(|(_, value)| *value == 0) {
+            return Err(format!("{} должен быть > 0", name));
+        }
+        let timeouts = [
+            ("threads.timeout_secs", self.threads.timeout_secs),
+            ("sessions.idle_timeout_secs", self.sessions.idle_timeout_secs),
+            ("sessions.thread_timeout_secs", self.sessions.thread_timeout_secs),
+        ];
+        if let Some((name, value)) = timeouts.iter().find(|(_, value)| *value <= 0) {
+            return Err(format!("{} должен быть > 0: {}", name, value));
+        }
+        for (importance, hours) in self.memory.ttl_by_importance.iter().flatten() {
+            if importance.trim().parse::<i32>().is_err() {
+                return Err(format!("memory.ttl_by_importance: ключ должен быть целым importance: {:?}", importance));
+            }
+            if !(hours.is_finite() && *hours > 0.0) {
+                return Err(format!("memory.ttl_by_importance.{} должен быть > 0: {}", importance, hours));
+            }
+        }
+        let ratio = self.compressor.compression_ratio;
+        if !(ratio > 0.0 && ratio <= 1.0) {
+            return Err(format!("compressor.compression_ratio должен быть в (0, 1]: {}", ratio));
+        }
+        let ellipsis = self.compressor.ellipsis.chars().count();
+        if self.compressor.preview_chars <= ellipsis || self.compressor.episode_preview_chars <= ellipsis {
+            return Err("compressor: длина превью должна быть больше длины ellipsis".to_string());
+        }
+        let limit = self.rate_limit;
+        if !(limit.capacity.is_finite() && limit.capacity > 0.0) {
+            return Err(format!("rate_limit.capacity должен быть > 0: {}", limit.capacity));
+        }
+        for (name, value) in [("rate_limit.refill_per_sec", limit.refill_per_sec), ("rate_limit.idle_ttl_secs", limit.idle_ttl_secs)] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{} должен быть >= 0: {}", name, value));
+            }
+        }
+        let every = self.maintenance;
+        for (name, value) in [
+            ("memory.autosave_secs", self.memory.autosave_secs),
+            ("maintenance.save_secs", every.save_secs),
+            ("maintenance.expire_secs", every.expire_secs),
+            ("maintenance.prune_secs", every.prune_secs),
+            ("maintenance.checkpoint_secs", every.checkpoint_secs),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{} должен быть >= 0: {}", name, value));
+            }
+        }
+        if self.spelling.prefix_length <= self.spelling.max_edit_distance {
+            return Err("spelling.prefix_length должен быть больше spelling.max_edit_distance".to_string());
+        }
+        Ok(())
+    }
+}
+
+static DEFAULT: LazyLock<Arc<Config>> = LazyLock::new(|| Arc::new(Config::default()));
+
+/// Конфигурация из аргумента `config=` конструктора или по умолчанию
+pub(crate) fn settings(config: Option<PyRef<'_, CoreConfig>>) -> Arc<Config> {
+    config.map_or_else(|| Arc::clone(&DEFAULT), |c| Arc::clone(&c.inner))
+}
+
+/// Рекурсивно накладывает patch на base (таблицы сливаются, остальное заменяется)
+fn merge(base: &mut Value, patch: Value) {
+    match (base, patch) {
+        (Value::Object(base), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match base.get_mut(&key) {
+                    Some(slot) => merge(slot, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (slot, value) => *slot = value,
+    }
+}
+
+// ── TOML ──
+
+/// Подмножество TOML для конфигурации: [таблица] и [a.b], ключ = значение
+/// (строки "..." и '...', целые, дробные, true/false, массивы, встроенные
+/// таблицы { a = 1 }), точечные ключи, комментарии #
+struct Toml {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Toml {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> String {
+        format!("TOML, строка {}: {}", self.line, message)
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+        }
+    }
+
+    /// Пробелы, комментарии и переводы строк (между строками и внутри массивов)
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n' | '\r') => {
+                    self.bump();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_spaces();
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(format!("ожидается '{}', найдено '{}'", expected, c))),
+            None => Err(self.error(format!("ожидается '{}', найден конец файла", expected))),
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.bump();
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("лишний текст после значения: '{}'", c))),
+        }
+    }
+
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        self.bump();
+                    }
+                    if self.pos == start {
+                        return Err(self.error("ожидается ключ"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            path.push(part);
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(path);
+            }
+            self.bump();
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.bump();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err(self.error("незакрытая строка")),
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(kind @ ('u' | 'U')) => {
+                            let len = if kind == 'u' { 4 } else { 8 };
+                            let hex: String = (0..len).filter_map(|_| self.bump()).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error(format!("некорректный код символа \\{}{}", kind, hex)))?
+                        }
+                        other => return Err(self.error(format!("неизвестная escape-последовательность \\{}", other.unwrap_or(' ')))),
+                    };
+                    out.push(escaped);
+                }
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.bump();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err(self.error("незакрытая строка")),
+                Some('\'') => return Ok(out),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(_) => self.scalar(),
+            None => Err(self.error("ожидается значение")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            match self.bump() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("в массиве ожидается ',' или ']'")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.bump();
+        let mut table = Value::Object(Map::new());
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(table);
+        }
+        loop {
+            let key = self.key()?;
+            self.expect('=')?;
+            let value = self.value()?;
+            self.insert(&mut table, &key, value)?;
+            self.skip_spaces();
+            match self.bump() {
+                Some(',') => {}
+                Some('}') => return Ok(table),
+                _ => return Err(self.error("во встроенной таблице ожидается ',' или '}'")),
+            }
+        }
+    }
+
+    fn scalar(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !matches!(c, ' ' | '\t' | '\n' | '\r' | ',' | ']' | '}' | '#')) {
+            self.bump();
+        }
+        let token: String = self.chars[start..self.pos].iter().collect();
+        match token.as_str() {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            _ => {}
+        }
+        let digits = token.replace('_', "");
+        if let Ok(int) = digits.parse::<i64>() {
+            return Ok(Value::from(int));
+        }
+        digits
+            .parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .map(Value::from)
+            .ok_or_else(|| self.error(format!("некорректное значение: {}", token)))
+    }
+
+    /// Вставка по пути ключей; промежуточные таблицы создаются
+    fn insert(&self, root: &mut Value, path: &[String], value: Value) -> Result<(), String> {
+        let (last, parents) = path.split_last().ok_or_else(|| self.error("пустой ключ"))?;
+        let table = self.table(root, parents)?;
+        if table.contains_key(last) {
+            return Err(self.error(format!("повторный ключ {}", path.join("."))));
+        }
+        table.insert(last.clone(), value);
+        Ok(())
+    }
+
+    fn table<'a>(&self, root: &'a mut Value, path: &[String]) -> Result<&'a mut Map<String, Value>, String> {
+        let mut current = root;
+        for key in path {
+            let Value::Object(map) = current else { unreachable!() };
+            current = map.entry(key.clone()).or_insert_with(|| Value::Object(Map::new()));
+            if !current.is_object() {
+                return Err(self.error(format!("{} — не таблица", path.join("."))));
+            }
+        }
+        match current {
+            Value::Object(map) => Ok(map),
+            _ => Err(self.error(format!("{} — не таблица", path.join(".")))),
+        }
+    }
+}
+
+pub(crate) fn parse_toml(text: &str) -> Result<Value, String> {
+    let mut toml = Toml { chars: text.chars().collect(), pos: 0, line: 1 };
+    let mut root = Value::Object(Map::new());
+    let mut section: Vec<String> = Vec::new();
+    loop {
+        toml.skip_blank();
+        match toml.peek() {
+            None => return Ok(root),
+            Some('[') => {
+                toml.bump();
+                if toml.peek() == Some('[') {
+                    return Err(toml.error("массивы таблиц [[...]] не поддерживаются"));
+                }
+                section = toml.key()?;
+                toml.expect(']')?;
+                toml.table(&mut root, &section)?;
+            }
+            Some(_) => {
+                let mut key = section.clone();
+                key.extend(toml.key()?);
+                toml.expect('=')?;
+                let value = toml.value()?;
+                toml.insert(&mut root, &key, value)?;
+            }
+        }
+        toml.end_of_line()?;
+    }
+}
+
+// ── Python API ──
+
+/// Конфигурация компонентов: пути, размеры, таймауты, словари, потоки
+#[pyclass(frozen)]
+pub struct CoreConfig {
+    inner: Arc<Config>,
+}
+
+impl CoreConfig {
+    fn from_value(value: Value) -> PyResult<Self> {
+        let config = Config::from_value(value).map_err(PyValueError::new_err)?;
+        Ok(Self { inner: Arc::new(config) })
+    }
+
+    fn to_value(&self) -> Value {
+        serde_json::to_value(&*self.inner).unwrap_or_default()
+    }
+}
+
+#[pymethods]
+impl CoreConfig {
+    /// values — разделы словарём, как в to_dict(); недостающее — по умолчанию
+    #[new]
+    #[pyo3(signature = (values=None))]
+    fn new(values: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        match values {
+            Some(values) => Self::from_value(py_to_json(values.as_any())?),
+            None => Ok(Self { inner: Arc::clone(&DEFAULT) }),
+        }
+    }
+
+    #[staticmethod]
+    fn from_dict(values: &Bound<'_, PyDict>) -> PyResult<Self> {
+        Self::from_value(py_to_json(values.as_any())?)
+    }
+
+    #[staticmethod]
+    fn from_toml(text: &str) -> PyResult<Self> {
+        Self::from_value(parse_toml(text).map_err(ParseError::new_err)?)
+    }
+
+    #[staticmethod]
+    fn from_json(text: &str) -> PyResult<Self> {
+        let value = serde_json::from_str(text).map_err(|e| ParseError::new_err(format!("JSON: {}", e)))?;
+        Self::from_value(value)
+    }
+
+    /// Формат по расширению: .toml или .json
+    #[staticmethod]
+    fn from_file(path: &str) -> PyResult<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| PersistenceError::new_err(format!("Не удалось прочитать {}: {}", path, e)))?;
+        let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        match extension.as_str() {
+            "toml" => Self::from_toml(&text),
+            "json" => Self::from_json(&text),
+            other => Err(PyValueError::new_err(format!("Неизвестный формат конфигурации: .{} (ожидается .toml или .json)", other))),
+        }
+    }
+
+    /// Копия с изменёнными полями: config.replace({"memory": {"working_size": 20}})
+    fn replace(&self, values: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let mut value = self.to_value();
+        merge(&mut value, py_to_json(values.as_any())?);
+        Self::from_value(value)
+    }
+
+    fn to_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        json_to_py(py, &self.to_value())
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_value()).unwrap_or_default()
+    }
+
+    /// Потоки пулов из раздела runtime; только до первого *_async и пакетной операции
+    fn apply_runtime(&self) -> PyResult<()> {
+        let runtime = self.inner.runtime;
+        async_io::set_threads(runtime.io_threads).map_err(PyRuntimeError::new_err)?;
+        if let Some(threads) = runtime.compute_threads {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build_global()
+                .map_err(|e| PyRuntimeError::new_err(format!("Пул rayon уже запущен: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    #[getter]
+    fn data_dir(&self) -> Option<String> {
+        self.inner.data_dir.clone()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+
+    fn __repr__(&self) -> String {
+        format!("CoreConfig({})", serde_json::to_string(&self.to_value()).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_toml_subset() {
+        let text = r#"
+# Кристина
+data_dir = "/var/lib/kristina"
+
+[memory]
+working_size = 20  # реплик
+max_episodic = 5_000
+
+[compressor]
+compression_ratio = 0.5
+ellipsis = '…'
+token_ratios = { cjk = 0.8, code = 3 }
+
+[lexicons]
+stop_words = [
+    "ну",
+    "типа", # слова-паразиты
+]
+threads.max_open = 3
+"#;
+        let value = parse_toml(text).unwrap();
+        assert_eq!(value["memory"], json!({"working_size": 20, "max_episodic": 5000}));
+        assert_eq!(value["compressor"]["token_ratios"], json!({"cjk": 0.8, "code": 3}));
+        assert_eq!(value["lexicons"]["stop_words"], json!(["ну", "типа"]));
+        assert_eq!(value["lexicons"]["threads"]["max_open"], json!(3));
+
+        assert!(parse_toml("a = 1\na = 2").unwrap_err().contains("строка 2"));
+        assert!(parse_toml("[memory\nx = 1").is_err());
+        assert!(parse_toml("name = \"незакрытая").is_err());
+    }
+
+    #[test]
+    fn test_defaults_paths_and_validation() {
+        let config = Config::from_value(json!({
+            "data_dir": "/data",
+            "memory": {"working_size": 30},
+            "cache": {"dir": "/cache"}
+        }))
+        .unwrap();
+        assert_eq!(config.memory.working_size, 30);
+        assert_eq!(config.memory.max_episodic, 1000);
+        assert_eq!(config.memory_dir().as_deref(), Some(Path::new("/data").join("memory").to_str().unwrap()));
+        assert_eq!(config.cache_dir().as_deref(), Some("/cache"));
+        assert_eq!(Config::default().sessions_dir(), None);
+
+        let err = Config::from_value(json!({"memory": {"working_size": 0}})).unwrap_err();
+        assert!(err.contains("memory.working_size"), "{}", err);
+        assert!(Config::from_value(json!({"memory": {"working_sise": 5}})).unwrap_err().contains("working_sise"));
+        assert!(Config::from_value(json!({"rate_limit": {"capacity": -1.0}})).is_err());
+        assert!(Config::from_value(json!({"maintenance": {"save_secs": -5}})).unwrap_err().contains("maintenance.save_secs"));
+        assert!(Config::from_value(json!({"memory": {"autosave_secs": -1}})).unwrap_err().contains("memory.autosave_secs"));
+        assert!(Config::from_value(json!({"memory": {"ttl_by_importance": {"low": 24}}})).is_err());
+        let ttl = Config::from_value(json!({"memory": {"ttl_by_importance": {"1": 24}}})).unwrap();
+        assert_eq!(ttl.memory.ttl_tiers(), HashMap::from([(1, 24.0)]));
+    }
+
+    #[test]
+    fn test_round_trip_and_merge() {
+        let config = Config::from_value(parse_toml("[threads]\narchive_messages = 50\n[runtime]\nio_threads = 2").unwrap()).unwrap();
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(Config::from_value(value.clone()).unwrap(), config);
+        // None-поля не выводятся: словарь пригоден и для TOML
+        assert!(value.get("data_dir").is_none());
+
+        let mut patched = value;
+        merge(&mut patched, json!({"threads": {"max_open": 9}}));
+        let patched = Config::from_value(patched).unwrap();
+        assert_eq!(patched.threads.max_open, 9);
+        assert_eq!(patched.threads.archive_messages, Some(50));
+    }
+}
@@ -1,225 +1,2182 @@
-//! ContextCompressor — сжатие контекста разговора
-//!
-//! Оптимизации:
-//! - Aho-Corasick для детекции важных слов за O(n)
-//! - Unicode-aware оценка токенов (BPE-эвристика для RU/EN)
-//! - Безопасная обрезка по границам символов
-
-use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyTuple};
-use aho_corasick::AhoCorasick;
-
-const IMPORTANT_WORDS: &[&str] = &[
-    "важно", "главное", "нужно", "проблема", "решение",
-    "ошибка", "успешно", "не работает", "помоги", "критично",
-    "срочно", "обязательно", "ключевой", "основной", "результат",
-    "вывод", "итог", "причина", "следствие", "вопрос",
-];
-
-#[pyclass(frozen)]
-pub struct ContextCompressor {
-    #[allow(dead_code)]
-    compression_ratio: f64,
-    important_ac: AhoCorasick,
-}
-
-#[pymethods]
-impl ContextCompressor {
-    #[new]
-    #[pyo3(signature = (compression_ratio=0.3))]
-    fn new(compression_ratio: f64) -> Self {
-        // Строим паттерны в lowercase для сопоставления с lowercase текстом
-        Self {
-            compression_ratio,
-            important_ac: AhoCorasick::new(IMPORTANT_WORDS).unwrap(),
-        }
-    }
-
-    /// Сжимает историю разговора.
-    /// Принимает List[Tuple[str,str,str]] ИЛИ List[Dict] с ключами role/content/timestamp.
-    fn compress_conversation(&self, messages: Bound<'_, pyo3::types::PyList>) -> PyResult<String> {
-        let len = messages.len();
-        if len == 0 {
-            return Ok(String::new());
-        }
-
-        let start = if len > 10 { len - 10 } else { 0 };
-        let mut parts = Vec::with_capacity(len - start);
-
-        for i in start..len {
-            let item = messages.get_item(i)?;
-
-            let (role, content) = if let Ok(dict) = item.downcast::<PyDict>() {
-                // List[dict] с ключами "role", "content"
-                let r = dict
-                    .get_item("role")?
-                    .map(|v| v.extract::<String>())
-                    .transpose()?
-                    .unwrap_or_default();
-                let c = dict
-                    .get_item("content")?
-                    .map(|v| v.extract::<String>())
-                    .transpose()?
-                    .unwrap_or_default();
-                (r, c)
-            } else if let Ok(tup) = item.downcast::<PyTuple>() {
-                // List[Tuple[str, str, str]]
-                let r: String = tup.get_item(0)?.extract()?;
-                let c: String = tup.get_item(1)?.extract()?;
-                (r, c)
-            } else {
-                // Попробуем как sequence
-                let r: String = item.get_item(0)?.extract()?;
-                let c: String = item.get_item(1)?.extract()?;
-                (r, c)
-            };
-
-            let truncated = if content.chars().count() > 100 {
-                let s: String = content.chars().take(97).collect();
-                format!("{}...", s)
-            } else {
-                content
-            };
-            parts.push(format!("{}: {}", role, truncated));
-        }
-        Ok(parts.join("\n"))
-    }
-
-    /// Извлекает ключевые предложения по наличию важных слов
-    fn extract_key_points(&self, text: &str) -> Vec<String> {
-        let text_lower = text.to_lowercase();
-
-        // Разбиваем на предложения
-        let mut sentences = Vec::new();
-        let mut start = 0;
-        for (i, ch) in text.char_indices() {
-            if ch == '.' || ch == '!' || ch == '?' || ch == '\n' {
-                let sentence = text[start..i].trim();
-                if !sentence.is_empty() {
-                    sentences.push((sentence, start, i));
-                }
-                start = i + ch.len_utf8();
-            }
-        }
-        // Последнее предложение
-        let last = text[start..].trim();
-        if !last.is_empty() {
-            sentences.push((last, start, text.len()));
-        }
-
-        // Оцениваем каждое предложение
-        let mut scored: Vec<(&str, usize)> = sentences
-            .iter()
-            .filter_map(|(sentence, start_byte, end_byte)| {
-                let sentence_lower = &text_lower[*start_byte..*end_byte];
-                let count = self.important_ac.find_iter(sentence_lower).count();
-                if count > 0 {
-                    Some((*sentence, count))
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        scored.sort_by(|a, b| b.1.cmp(&a.1));
-        scored.into_iter().take(3).map(|(s, _)| s.to_string()).collect()
-    }
-
-    /// Суммаризует эпизоды. Вход: [(timestamp, user_input, importance)]
-    #[pyo3(signature = (episodes, max_length=500))]
-    fn summarize_episodes(
-        &self,
-        episodes: Vec<(String, String, i32)>,
-        max_length: usize,
-    ) -> String {
-        if episodes.is_empty() {
-            return "Нет данных в памяти".to_string();
-        }
-
-        let mut sorted = episodes;
-        sorted.sort_by(|a, b| b.2.cmp(&a.2));
-
-        let mut parts = Vec::new();
-        let mut current_length = 0;
-
-        for (timestamp, user_input, _importance) in sorted.iter().take(5) {
-            let date: String = timestamp.chars().take(10).collect();
-            let preview: String = user_input.chars().take(50).collect();
-            let snippet = format!("[{}] {}", date, preview);
-
-            if current_length + snippet.len() > max_length {
-                break;
-            }
-
-            current_length += snippet.len();
-            parts.push(snippet);
-        }
-
-        parts.join("\n")
-    }
-
-    /// BPE-эвристика: ~4 chars/token EN, ~2 chars/token RU
-    fn estimate_tokens(&self, text: &str) -> usize {
-        let mut ascii_chars = 0usize;
-        let mut non_ascii = 0usize;
-        for c in text.chars() {
-            if c.is_ascii() {
-                ascii_chars += 1;
-            } else {
-                non_ascii += 1;
-            }
-        }
-        (ascii_chars / 4) + (non_ascii / 2) + 1
-    }
-
-    /// Обрезает текст до N токенов с учётом Unicode
-    fn truncate_to_tokens(&self, text: &str, max_tokens: usize) -> String {
-        let estimated = self.estimate_tokens(text);
-        if estimated <= max_tokens {
-            return text.to_string();
-        }
-        let ratio = max_tokens as f64 / estimated as f64;
-        let char_count = text.chars().count();
-        let target_chars = (char_count as f64 * ratio) as usize;
-        text.chars().take(target_chars).collect()
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_estimate_tokens_ascii() {
-        let c = ContextCompressor::new(0.3);
-        // "hello world" = 11 ASCII chars → 11/4 + 0/2 + 1 = 3
-        assert_eq!(c.estimate_tokens("hello world"), 3);
-    }
-
-    #[test]
-    fn test_estimate_tokens_russian() {
-        let c = ContextCompressor::new(0.3);
-        // "привет" = 6 non-ASCII chars → 0/4 + 6/2 + 1 = 4
-        assert_eq!(c.estimate_tokens("привет"), 4);
-    }
-
-    // compress_conversation тест требует Python runtime (принимает PyList),
-    // поэтому тестируется через integration test с maturin
-
-    #[test]
-    fn test_extract_key_points() {
-        let c = ContextCompressor::new(0.3);
-        let text = "Всё хорошо. Есть важная проблема с сетью. Погода солнечная.";
-        let points = c.extract_key_points(text);
-        assert!(!points.is_empty());
-        assert!(points[0].contains("проблема"));
-    }
-
-    #[test]
-    fn test_truncate() {
-        let c = ContextCompressor::new(0.3);
-        let long_text = "a".repeat(1000);
-        let truncated = c.truncate_to_tokens(&long_text, 10);
-        assert!(truncated.len() < long_text.len());
-    }
-}
+//! ContextCompressor — сжатие контекста разговора
+//!
+//! Оптимизации:
+//! - Aho-Corasick для детекции важных слов за O(n)
+//! - Unicode-aware оценка токенов по письменностям (латиница, кириллица, CJK, эмодзи, код)
+//! - Безопасная обрезка по границам символов
+//! - TextRank: экстрактивная суммаризация без LLM
+//! - Настраиваемый взвешенный словарь важных слов (RwLock + пересборка автомата)
+//! - Семантическая дедупликация сообщений по эмбеддингам / EmbeddingCache
+//! - Чанкинг для RAG с перекрытием и символьными смещениями
+//! - Заготовка map-reduce суммаризации: границы чанков и слияние сводок
+//! - CompressionReport: что сохранено, обрезано и выброшено при сжатии
+//! - ConversationBuffer: инкрементальный буфер с кэшем токенов и ключевых пунктов
+//! - compress_many: пакетное сжатие через Rayon без GIL
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyDict, PyTuple};
+use aho_corasick::AhoCorasick;
+use parking_lot::RwLock;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::time::Instant;
+use crate::config::{self, CoreConfig};
+use crate::embedding_cache::EmbeddingCache;
+use crate::similarity::cosine_similarity_impl;
+use crate::segmenter::sentence_spans;
+use crate::emotion_analyzer::EmotionAnalyzer;
+use crate::memory_engine::MemoryEngine;
+use crate::metrics;
+
+const IMPORTANT_WORDS: &[&str] = &[
+    "важно", "главное", "нужно", "проблема", "решение",
+    "ошибка", "успешно", "не работает", "помоги", "критично",
+    "срочно", "обязательно", "ключевой", "основной", "результат",
+    "вывод", "итог", "причина", "следствие", "вопрос",
+    "important", "problem", "solution", "error", "urgent",
+    "critical", "result", "conclusion", "must", "required",
+];
+
+/// Взвешенный словарь важных слов с собранным автоматом
+struct Lexicon {
+    ac: AhoCorasick,
+    weights: Vec<f64>,
+    words: Vec<String>,
+}
+
+impl Lexicon {
+    fn new(words: HashMap<String, f64>) -> Result<Self, String> {
+        let mut entries: Vec<(String, f64)> = words
+            .into_iter()
+            .map(|(w, weight)| (w.trim().to_lowercase(), weight))
+            .filter(|(w, _)| !w.is_empty())
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries.dedup_by(|a, b| a.0 == b.0);
+
+        let words: Vec<String> = entries.iter().map(|(w, _)| w.clone()).collect();
+        let weights = entries.iter().map(|(_, weight)| *weight).collect();
+        // Паттерны в lowercase — сопоставляем с lowercase текстом
+        let ac = AhoCorasick::new(&words).map_err(|e| e.to_string())?;
+        Ok(Self { ac, weights, words })
+    }
+
+    fn default_words() -> HashMap<String, f64> {
+        IMPORTANT_WORDS.iter().map(|w| (w.to_string(), 1.0)).collect()
+    }
+
+    /// Сумма весов совпадений в тексте (text уже в lowercase)
+    fn score(&self, text_lower: &str) -> f64 {
+        self.ac
+            .find_iter(text_lower)
+            .map(|m| self.weights[m.pattern().as_usize()])
+            .sum()
+    }
+}
+
+/// Символов на токен по письменностям (BPE-эвристика)
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TokenProfile {
+    latin: f64,
+    cyrillic: f64,
+    cjk: f64,
+    emoji: f64,
+    /// ASCII в тексте, похожем на код
+    code: f64,
+    other: f64,
+}
+
+impl Default for TokenProfile {
+    fn default() -> Self {
+        Self { latin: 4.0, cyrillic: 2.0, cjk: 1.0, emoji: 0.5, code: 3.0, other: 2.0 }
+    }
+}
+
+/// Доля спецсимволов среди непробельного ASCII, начиная с которой текст считается кодом
+const CODE_SYMBOL_SHARE: f64 = 0.12;
+
+enum Script {
+    Ascii,
+    Cyrillic,
+    Cjk,
+    Emoji,
+    Other,
+}
+
+fn script_of(c: char) -> Script {
+    match c as u32 {
+        0x00..=0x7F => Script::Ascii,
+        0x0400..=0x052F => Script::Cyrillic,
+        0x3000..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF | 0xFF00..=0xFFEF => Script::Cjk,
+        // Пиктограммы, дингбаты, модификаторы и склейки эмодзи-последовательностей
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0xFE0F | 0x200D => Script::Emoji,
+        _ => Script::Other,
+    }
+}
+
+impl TokenProfile {
+    /// Ключи: latin, cyrillic, cjk, emoji, code, other
+    pub(crate) fn with_overrides(ratios: HashMap<String, f64>) -> Result<Self, String> {
+        let mut profile = Self::default();
+        for (key, ratio) in ratios {
+            if !(ratio.is_finite() && ratio > 0.0) {
+                return Err(format!("Некорректное число символов на токен для '{}': {}", key, ratio));
+            }
+            let slot = match key.as_str() {
+                "latin" => &mut profile.latin,
+                "cyrillic" => &mut profile.cyrillic,
+                "cjk" => &mut profile.cjk,
+                "emoji" => &mut profile.emoji,
+                "code" => &mut profile.code,
+                "other" => &mut profile.other,
+                _ => return Err(format!("Неизвестный профиль токенов: {}", key)),
+            };
+            *slot = ratio;
+        }
+        Ok(profile)
+    }
+
+    fn to_map(self) -> HashMap<String, f64> {
+        HashMap::from([
+            ("latin".to_string(), self.latin),
+            ("cyrillic".to_string(), self.cyrillic),
+            ("cjk".to_string(), self.cjk),
+            ("emoji".to_string(), self.emoji),
+            ("code".to_string(), self.code),
+            ("other".to_string(), self.other),
+        ])
+    }
+
+    pub(crate) fn estimate(&self, text: &str) -> usize {
+        let mut counts = ScriptCounts::default();
+        counts.add(text);
+        self.tokens(&counts)
+    }
+
+    /// Оценка по накопленным счётчикам: текст по частям без повторного просмотра
+    pub(crate) fn tokens(&self, counts: &ScriptCounts) -> usize {
+        let c = counts;
+        let looks_like_code = c.visible > 0 && c.symbols as f64 / c.visible as f64 >= CODE_SYMBOL_SHARE;
+        let ascii_ratio = if looks_like_code { self.code } else { self.latin };
+        let tokens = c.ascii as f64 / ascii_ratio
+            + c.cyrillic as f64 / self.cyrillic
+            + c.cjk as f64 / self.cjk
+            + c.emoji as f64 / self.emoji
+            + c.other as f64 / self.other;
+        tokens as usize + 1
+    }
+}
+
+/// Символы по письменностям; add по частям равен add всего текста
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ScriptCounts {
+    ascii: usize,
+    /// Спецсимволы среди непробельного ASCII (признак кода)
+    symbols: usize,
+    visible: usize,
+    cyrillic: usize,
+    cjk: usize,
+    emoji: usize,
+    other: usize,
+}
+
+impl ScriptCounts {
+    pub(crate) fn add(&mut self, text: &str) {
+        for c in text.chars() {
+            match script_of(c) {
+                Script::Ascii => {
+                    self.ascii += 1;
+                    if !c.is_ascii_whitespace() {
+                        self.visible += 1;
+                        if !c.is_ascii_alphanumeric() && !matches!(c, '.' | ',' | '\'' | '"' | '!' | '?' | '-') {
+                            self.symbols += 1;
+                        }
+                    }
+                }
+                Script::Cyrillic => self.cyrillic += 1,
+                Script::Cjk => self.cjk += 1,
+                Script::Emoji => self.emoji += 1,
+                Script::Other => self.other += 1,
+            }
+        }
+    }
+}
+
+// Значения по умолчанию для параметров конструктора
+pub(crate) const RECENT_WINDOW: usize = 10;
+pub(crate) const PREVIEW_CHARS: usize = 100;
+pub(crate) const EPISODE_PREVIEW_CHARS: usize = 50;
+pub(crate) const ELLIPSIS: &str = "...";
+/// Порог score_message, при котором старое сообщение без ключевых
+/// предложений всё же попадает в сводку превью
+const SURVIVE_SCORE: f64 = 1.0;
+
+/// Отчёт о сжатии: почему из промпта пропал контекст
+#[pyclass(frozen, get_all)]
+#[derive(Clone, Debug, Default)]
+pub struct CompressionReport {
+    pub original_tokens: usize,
+    pub compressed_tokens: usize,
+    /// Сообщения, вошедшие дословно
+    pub kept: usize,
+    /// Сообщения, сокращённые до превью
+    pub truncated: usize,
+    /// Сообщения, от которых остались только ключевые пункты (или ничего)
+    pub dropped: usize,
+    pub key_points: Vec<String>,
+    /// Сообщения, слитые с соседним повтором ("(×N)")
+    pub collapsed: usize,
+    pub elapsed_ms: f64,
+}
+
+#[pymethods]
+impl CompressionReport {
+    fn __repr__(&self) -> String {
+        format!(
+            "CompressionReport(tokens={}→{}, kept={}, truncated={}, dropped={}, collapsed={}, key_points={})",
+            self.original_tokens,
+            self.compressed_tokens,
+            self.kept,
+            self.truncated,
+            self.dropped,
+            self.collapsed,
+            self.key_points.len()
+        )
+    }
+}
+
+#[pyclass(frozen)]
+pub struct ContextCompressor {
+    compression_ratio: f64,
+    lexicon: RwLock<Lexicon>,
+    /// Длина превью сообщения в compress_conversation (символов, с ellipsis)
+    preview_chars: usize,
+    /// Сколько последних сообщений compress_conversation пытается сохранить
+    recent_messages: usize,
+    /// Длина превью эпизода в summarize_episodes
+    episode_preview_chars: usize,
+    ellipsis: String,
+    /// Сливать подряд идущие повторы одной роли перед сжатием
+    collapse_repeats: bool,
+    tokens: TokenProfile,
+}
+
+impl ContextCompressor {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        compression_ratio: f64,
+        important_words: Option<HashMap<String, f64>>,
+        preview_chars: usize,
+        recent_messages: usize,
+        ellipsis: &str,
+        episode_preview_chars: usize,
+        collapse_repeats: bool,
+        token_ratios: Option<HashMap<String, f64>>,
+    ) -> PyResult<Self> {
+        let ellipsis_len = ellipsis.chars().count();
+        if preview_chars <= ellipsis_len || episode_preview_chars <= ellipsis_len {
+            return Err(PyValueError::new_err("Длина превью должна быть больше длины ellipsis"));
+        }
+        if recent_messages == 0 {
+            return Err(PyValueError::new_err("recent_messages должен быть > 0"));
+        }
+        let lexicon = Lexicon::new(important_words.unwrap_or_else(Lexicon::default_words))
+            .map_err(PyValueError::new_err)?;
+        let tokens = match token_ratios {
+            Some(ratios) => TokenProfile::with_overrides(ratios).map_err(PyValueError::new_err)?,
+            None => TokenProfile::default(),
+        };
+        Ok(Self {
+            compression_ratio,
+            lexicon: RwLock::new(lexicon),
+            preview_chars,
+            recent_messages,
+            episode_preview_chars,
+            ellipsis: ellipsis.to_string(),
+            collapse_repeats,
+            tokens,
+        })
+    }
+}
+
+#[pymethods]
+impl ContextCompressor {
+    /// important_words: {слово/фраза: вес}; None — встроенный словарь с весом 1.0.
+    /// preview_chars / episode_preview_chars — длина превью (вместе с ellipsis),
+    /// recent_messages — окно последних сообщений compress_conversation.
+    /// collapse_repeats — сливать подряд идущие (почти) одинаковые сообщения в одно с "(×N)".
+    /// token_ratios — символов на токен по письменностям, например {"cjk": 0.8};
+    /// ключи: latin, cyrillic, cjk, emoji, code, other.
+    /// Незаданные параметры берутся из config (compressor, lexicons.important_words).
+    #[new]
+    #[pyo3(signature = (
+        compression_ratio=None, important_words=None, preview_chars=None, recent_messages=None, ellipsis=None,
+        episode_preview_chars=None, collapse_repeats=None, token_ratios=None, *, config=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        compression_ratio: Option<f64>,
+        important_words: Option<HashMap<String, f64>>,
+        preview_chars: Option<usize>,
+        recent_messages: Option<usize>,
+        ellipsis: Option<String>,
+        episode_preview_chars: Option<usize>,
+        collapse_repeats: Option<bool>,
+        token_ratios: Option<HashMap<String, f64>>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let config = config::settings(config);
+        let section = &config.compressor;
+        Self::new(
+            compression_ratio.unwrap_or(section.compression_ratio),
+            important_words.or_else(|| config.lexicons.important_words.clone()),
+            preview_chars.unwrap_or(section.preview_chars),
+            recent_messages.unwrap_or(section.recent_messages),
+            ellipsis.as_deref().unwrap_or(&section.ellipsis),
+            episode_preview_chars.unwrap_or(section.episode_preview_chars),
+            collapse_repeats.unwrap_or(section.collapse_repeats),
+            token_ratios.or_else(|| section.token_ratios.clone()),
+        )
+    }
+
+    /// Заменяет словарь важных слов: {"deploy": 2.0, "сервер упал": 3.0}
+    fn set_important_words(&self, words: HashMap<String, f64>) -> PyResult<()> {
+        let lexicon = Lexicon::new(words).map_err(PyValueError::new_err)?;
+        *self.lexicon.write() = lexicon;
+        Ok(())
+    }
+
+    fn get_important_words(&self) -> HashMap<String, f64> {
+        let lexicon = self.lexicon.read();
+        lexicon
+            .words
+            .iter()
+            .cloned()
+            .zip(lexicon.weights.iter().copied())
+            .collect()
+    }
+
+    /// Сжимает историю разговора до ~compression_ratio от исходного объёма токенов.
+    /// Принимает List[Tuple[str,str,str]] ИЛИ List[Dict] с ключами role/content/timestamp.
+    /// Какие старые сообщения уцелеют, решает score_message (analyzer — опционально).
+    /// protect / protect_roles / protect_markers — индексы, роли и подстроки
+    /// (без учёта регистра) сообщений, которые сохраняются дословно.
+    /// Возвращает список {"role", "content"} (сводка старой части — system-сообщение);
+    /// legacy=True — прежнюю строку "role: content" через перевод строки.
+    #[pyo3(signature = (
+        messages, analyzer=None, protect=None, protect_roles=None, protect_markers=None, legacy=false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn compress_conversation(
+        &self,
+        py: Python<'_>,
+        messages: Bound<'_, pyo3::types::PyList>,
+        analyzer: Option<PyRef<'_, EmotionAnalyzer>>,
+        protect: Option<Vec<usize>>,
+        protect_roles: Option<Vec<String>>,
+        protect_markers: Option<Vec<String>>,
+        legacy: bool,
+    ) -> PyResult<PyObject> {
+        let messages = extract_messages(&messages)?;
+        let protected = protection_mask(&messages, protect, protect_roles, protect_markers);
+        self.compress_pairs_report(&messages, analyzer.as_deref(), &protected).0.into_py(py, legacy)
+    }
+
+    /// То же, что compress_conversation, плюс CompressionReport:
+    /// сколько сообщений сохранено/обрезано/выброшено и какие ключевые пункты взяты
+    #[pyo3(signature = (
+        messages, analyzer=None, protect=None, protect_roles=None, protect_markers=None, legacy=false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn compress_conversation_detailed(
+        &self,
+        py: Python<'_>,
+        messages: Bound<'_, pyo3::types::PyList>,
+        analyzer: Option<PyRef<'_, EmotionAnalyzer>>,
+        protect: Option<Vec<usize>>,
+        protect_roles: Option<Vec<String>>,
+        protect_markers: Option<Vec<String>>,
+        legacy: bool,
+    ) -> PyResult<(PyObject, CompressionReport)> {
+        let messages = extract_messages(&messages)?;
+        let protected = protection_mask(&messages, protect, protect_roles, protect_markers);
+        let (compressed, report) = self.compress_pairs_report(&messages, analyzer.as_deref(), &protected);
+        Ok((compressed.into_py(py, legacy)?, report))
+    }
+
+    /// Важность сообщения: веса важных слов + вопросы + длина,
+    /// с analyzer — ещё и эмоциональность (уверенность не-нейтральной эмоции)
+    #[pyo3(signature = (text, analyzer=None))]
+    fn score_message(&self, text: &str, analyzer: Option<PyRef<'_, EmotionAnalyzer>>) -> f64 {
+        self.message_score(text, analyzer.as_deref())
+    }
+
+    /// Сжатие с учётом ролей → список {"role", "content"}:
+    /// system-сообщения сохраняются дословно, последние keep_last реплик
+    /// user/assistant — целиком, середина сворачивается в TextRank-сводку
+    /// (одно system-сообщение). Последнее сообщение пользователя не теряется никогда.
+    /// С embeddings (по одному на сообщение) или cache перед сжатием
+    /// отбрасываются семантические повторы (cos >= dedup_threshold).
+    /// Защищённые сообщения (protect*, см. compress_conversation) не сворачиваются
+    /// и не удаляются дедупликацией.
+    #[pyo3(signature = (
+        messages, budget_tokens, keep_last=4, embeddings=None, cache=None, dedup_threshold=0.95,
+        protect=None, protect_roles=None, protect_markers=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn compress_messages(
+        &self,
+        messages: Bound<'_, pyo3::types::PyList>,
+        budget_tokens: usize,
+        keep_last: usize,
+        embeddings: Option<Vec<Vec<f32>>>,
+        cache: Option<PyRef<'_, EmbeddingCache>>,
+        dedup_threshold: f32,
+        protect: Option<Vec<usize>>,
+        protect_roles: Option<Vec<String>>,
+        protect_markers: Option<Vec<String>>,
+    ) -> PyResult<Vec<HashMap<String, String>>> {
+        let mut messages = extract_messages(&messages)?;
+        let mut protected = protection_mask(&messages, protect, protect_roles, protect_markers);
+        if embeddings.is_some() || cache.is_some() {
+            let vectors = message_vectors(&messages, embeddings, cache.as_deref())?;
+            let mut kept = dedup_indices(&messages, &vectors, dedup_threshold);
+            kept.extend((0..messages.len()).filter(|&i| protected[i]));
+            kept.sort_unstable();
+            kept.dedup();
+            messages = kept.iter().map(|&i| messages[i].clone()).collect();
+            protected = kept.iter().map(|&i| protected[i]).collect();
+        }
+        Ok(to_dicts(self.compress_roles(&messages, budget_tokens, keep_last, &protected)))
+    }
+
+    /// Сливает подряд идущие одинаковые/почти одинаковые сообщения одной роли:
+    /// ["?", "?", "??"] → ["? (×3)"]
+    fn collapse_duplicates(
+        &self,
+        messages: Bound<'_, pyo3::types::PyList>,
+    ) -> PyResult<Vec<HashMap<String, String>>> {
+        let messages = extract_messages(&messages)?;
+        Ok(to_dicts(collapse_runs(&messages, &[]).0))
+    }
+
+    /// Сжатие рабочей памяти MemoryEngine напрямую в Rust (без копирования кортежей
+    /// через Python): role-aware сжатие как в compress_messages, результат —
+    /// строки "role: content"
+    #[pyo3(signature = (engine, budget_tokens, keep_last=4))]
+    fn compress_working_memory(&self, engine: PyRef<'_, MemoryEngine>, budget_tokens: usize, keep_last: usize) -> String {
+        self.working_context(&engine, budget_tokens, keep_last)
+    }
+
+    /// Перекрывающиеся окна разговора для длинного анализа (тренды эмоций,
+    /// сегментация тем) → [(start, end, messages)], [start, end) — индексы сообщений.
+    /// Окно не больше window_tokens (кроме одиночного длинного сообщения),
+    /// соседние окна делят хвост примерно в overlap_tokens.
+    #[pyo3(signature = (messages, window_tokens, overlap_tokens=0))]
+    fn window(
+        &self,
+        messages: Bound<'_, pyo3::types::PyList>,
+        window_tokens: usize,
+        overlap_tokens: usize,
+    ) -> PyResult<Vec<Window>> {
+        if overlap_tokens >= window_tokens {
+            return Err(PyValueError::new_err("overlap_tokens должен быть меньше window_tokens"));
+        }
+        let messages = extract_messages(&messages)?;
+        Ok(self
+            .message_windows(&messages, window_tokens, overlap_tokens)
+            .into_iter()
+            .map(|(start, end)| (start, end, to_dicts(messages[start..end].to_vec())))
+            .collect())
+    }
+
+    /// Пакетное сжатие многих разговоров (как compress_messages, без дедупликации):
+    /// разговоры обрабатываются параллельно через Rayon с отпущенным GIL
+    #[pyo3(signature = (conversations, budget_tokens, keep_last=4))]
+    fn compress_many(
+        &self,
+        py: Python<'_>,
+        conversations: Vec<Bound<'_, pyo3::types::PyList>>,
+        budget_tokens: usize,
+        keep_last: usize,
+    ) -> PyResult<Vec<Vec<HashMap<String, String>>>> {
+        let conversations = conversations
+            .iter()
+            .map(extract_messages)
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(py.allow_threads(|| self.compress_batch(&conversations, budget_tokens, keep_last)))
+    }
+
+    /// Убирает семантические повторы: из пары сообщений одной роли
+    /// с cos >= threshold остаётся более новое. system не трогается,
+    /// сообщения без эмбеддинга сохраняются.
+    #[pyo3(signature = (messages, embeddings=None, cache=None, threshold=0.95))]
+    fn dedup_messages(
+        &self,
+        messages: Bound<'_, pyo3::types::PyList>,
+        embeddings: Option<Vec<Vec<f32>>>,
+        cache: Option<PyRef<'_, EmbeddingCache>>,
+        threshold: f32,
+    ) -> PyResult<Vec<HashMap<String, String>>> {
+        let messages = extract_messages(&messages)?;
+        let vectors = message_vectors(&messages, embeddings, cache.as_deref())?;
+        let kept = dedup_indices(&messages, &vectors, threshold);
+        Ok(to_dicts(kept.into_iter().map(|i| messages[i].clone()).collect()))
+    }
+
+    /// Ключевые предложения по весам важных слов → [(sentence, score, char_offset)],
+    /// по убыванию score; не больше max_points, со score >= min_score (и > 0).
+    /// max_tokens — жадно берутся лучшие предложения, пока помещаются в бюджет.
+    #[pyo3(signature = (text, max_points=3, min_score=0.0, max_tokens=None))]
+    fn extract_key_points(
+        &self,
+        text: &str,
+        max_points: usize,
+        min_score: f64,
+        max_tokens: Option<usize>,
+    ) -> Vec<(String, f64, usize)> {
+        let lexicon = self.lexicon.read();
+
+        // Оцениваем каждое предложение суммой весов важных слов
+        let mut scored: Vec<(&str, f64, usize)> = sentence_spans(text)
+            .into_iter()
+            .filter_map(|(sentence, start, _)| {
+                let sentence = sentence.trim_end_matches(['.', '!', '?', '…']).trim_end();
+                let score = lexicon.score(&sentence.to_lowercase());
+                (score > 0.0 && score >= min_score).then_some((sentence, score, start))
+            })
+            .collect();
+
+        // Стабильная сортировка: при равном весе — порядок в тексте
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        if let Some(budget) = max_tokens {
+            let mut used = 0;
+            scored.retain(|(sentence, _, _)| {
+                let cost = self.estimate_tokens(sentence);
+                let fits = used + cost <= budget;
+                if fits {
+                    used += cost;
+                }
+                fits
+            });
+        }
+        scored.truncate(max_points);
+        scored
+            .into_iter()
+            .map(|(s, score, start)| (s.to_string(), score, text[..start].chars().count()))
+            .collect()
+    }
+
+    /// TextRank: граф сходства предложений + PageRank.
+    /// Возвращает max_sentences самых центральных предложений в исходном порядке.
+    #[pyo3(signature = (text, max_sentences=3))]
+    fn summarize(&self, text: &str, max_sentences: usize) -> Vec<String> {
+        let sentences: Vec<&str> = sentence_texts(text);
+        if sentences.len() <= max_sentences {
+            return sentences.into_iter().map(String::from).collect();
+        }
+
+        let scores = textrank(&sentences);
+        let mut order: Vec<usize> = (0..sentences.len()).collect();
+        order.sort_by(|&a, &b| scores[b].partial_cmp(&scores[a]).unwrap_or(std::cmp::Ordering::Equal));
+        let mut picked: Vec<usize> = order.into_iter().take(max_sentences).collect();
+        picked.sort_unstable();
+        picked.into_iter().map(|i| sentences[i].to_string()).collect()
+    }
+
+    /// Разбивка текста на чанки для RAG → [(text, start, end)], смещения в символах.
+    /// Чанк не превышает chunk_tokens (оценка estimate_tokens), соседние чанки
+    /// перекрываются примерно на overlap_tokens. При respect_sentences границы
+    /// проходят по предложениям (слишком длинные режутся по словам, затем по символам).
+    #[pyo3(signature = (text, chunk_tokens, overlap_tokens=0, respect_sentences=true))]
+    fn chunk_text(
+        &self,
+        text: &str,
+        chunk_tokens: usize,
+        overlap_tokens: usize,
+        respect_sentences: bool,
+    ) -> PyResult<Vec<(String, usize, usize)>> {
+        if chunk_tokens == 0 {
+            return Err(PyValueError::new_err("chunk_tokens должен быть > 0"));
+        }
+        if overlap_tokens >= chunk_tokens {
+            return Err(PyValueError::new_err("overlap_tokens должен быть меньше chunk_tokens"));
+        }
+        let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        let to_char = |b: usize| boundaries.partition_point(|&x| x < b);
+        Ok(self
+            .chunk_spans(text, chunk_tokens, overlap_tokens, respect_sentences)
+            .into_iter()
+            .map(|(s, e)| (text[s..e].to_string(), to_char(s), to_char(e)))
+            .collect())
+    }
+
+    /// Map-шаг иерархической суммаризации: история режется на чанки по
+    /// chunk_budget токенов → [(text, first, end)], где [first, end) — индексы
+    /// сообщений. Суммаризация чанков — на стороне Python (LLM), итог собирает
+    /// merge_summaries. Сообщение больше бюджета делится по предложениям.
+    fn plan_summarization(
+        &self,
+        messages: Bound<'_, pyo3::types::PyList>,
+        chunk_budget: usize,
+    ) -> PyResult<Vec<(String, usize, usize)>> {
+        if chunk_budget == 0 {
+            return Err(PyValueError::new_err("chunk_budget должен быть > 0"));
+        }
+        let messages = extract_messages(&messages)?;
+        Ok(self.plan_chunks(&messages, chunk_budget))
+    }
+
+    /// Reduce-шаг: объединяет сводки чанков, убирая повторяющиеся предложения;
+    /// если результат не помещается в budget, остаются самые центральные (TextRank)
+    fn merge_summaries(&self, summaries: Vec<String>, budget: usize) -> String {
+        let mut seen = std::collections::HashSet::new();
+        let mut sentences: Vec<&str> = Vec::new();
+        for summary in &summaries {
+            for sentence in sentence_texts(summary) {
+                if seen.insert(content_words(sentence).join(" ")) {
+                    sentences.push(sentence);
+                }
+            }
+        }
+        let merged = sentences.join(". ");
+        if self.estimate_tokens(&merged) <= budget {
+            return merged;
+        }
+        self.select_sentences(&merged, budget).join(". ")
+    }
+
+    /// Суммаризует эпизоды. Вход: [(timestamp, user_input, importance)]
+    #[pyo3(signature = (episodes, max_length=500))]
+    fn summarize_episodes(
+        &self,
+        episodes: Vec<(String, String, i32)>,
+        max_length: usize,
+    ) -> String {
+        if episodes.is_empty() {
+            return "Нет данных в памяти".to_string();
+        }
+
+        let mut sorted = episodes;
+        sorted.sort_by_key(|s| std::cmp::Reverse(s.2));
+
+        let mut parts = Vec::new();
+        let mut current_length = 0;
+
+        for (timestamp, user_input, _importance) in sorted.iter().take(5) {
+            let date: String = timestamp.chars().take(10).collect();
+            let preview = self.preview(user_input, self.episode_preview_chars);
+            let snippet = format!("[{}] {}", date, preview);
+
+            if current_length + snippet.len() > max_length {
+                break;
+            }
+
+            current_length += snippet.len();
+            parts.push(snippet);
+        }
+
+        parts.join("\n")
+    }
+
+    /// Структурный вариант summarize_episodes: [{"timestamp", "preview", "importance",
+    /// "tokens"}] по убыванию важности; эпизоды, не влезающие в budget_tokens
+    /// (по токенам превью), пропускаются по одному
+    #[pyo3(signature = (episodes, budget_tokens=200, max_items=None))]
+    fn summarize_episodes_items<'py>(
+        &self,
+        py: Python<'py>,
+        episodes: Vec<(String, String, i32)>,
+        budget_tokens: usize,
+        max_items: Option<usize>,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        self.episode_items(episodes, budget_tokens, max_items)
+            .into_iter()
+            .map(|item| {
+                let dict = PyDict::new(py);
+                dict.set_item("timestamp", item.timestamp)?;
+                dict.set_item("preview", item.preview)?;
+                dict.set_item("importance", item.importance)?;
+                dict.set_item("tokens", item.tokens)?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// BPE-эвристика по письменностям: ~4 chars/token латиница, ~3 код,
+    /// ~2 кириллица, ~1 CJK, ~0.5 эмодзи (настраивается token_ratios)
+    pub(crate) fn estimate_tokens(&self, text: &str) -> usize {
+        self.tokens.estimate(text)
+    }
+
+    /// Текущие коэффициенты символов на токен
+    fn get_token_ratios(&self) -> HashMap<String, f64> {
+        self.tokens.to_map()
+    }
+
+    /// Обрезает текст до N токенов с учётом разметки: режет предпочтительно
+    /// по абзацам, затем по строкам, предложениям, словам; строки таблиц
+    /// не разрываются, незакрытый ``` / ~~~ блок кода закрывается.
+    fn truncate_to_tokens(&self, text: &str, max_tokens: usize) -> String {
+        if self.estimate_tokens(text) <= max_tokens {
+            return text.to_string();
+        }
+        let cuts = cut_points(text);
+        let with_fence = |pos: usize, fence: Option<&str>| {
+            let mut out = text[..pos].trim_end().to_string();
+            if let Some(marker) = fence {
+                out.push('\n');
+                out.push_str(marker);
+            }
+            out
+        };
+
+        // Самая длинная допустимая обрезка по символам
+        let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        let fit = boundaries.partition_point(|&b| self.estimate_tokens(&text[..b]) <= max_tokens);
+        let limit = boundaries[fit.saturating_sub(1)];
+
+        // Лучший структурный разрез не короче 60% от предела, иначе — самый длинный
+        let mut candidates: Vec<&CutPoint> = cuts.iter().filter(|c| c.pos > 0 && c.pos <= limit).collect();
+        candidates.sort_by(|a, b| b.priority.cmp(&a.priority).then(b.pos.cmp(&a.pos)));
+        let threshold = limit * 3 / 5;
+        let preferred = candidates.iter().filter(|c| c.pos >= threshold);
+        let mut longest = candidates.clone();
+        longest.sort_by_key(|c| std::cmp::Reverse(c.pos));
+        for cut in preferred.chain(longest.iter()) {
+            let out = with_fence(cut.pos, cut.fence);
+            if self.estimate_tokens(&out) <= max_tokens {
+                return out;
+            }
+        }
+        text[..limit].to_string()
+    }
+}
+
+// ── Приватные методы ──
+
+impl ContextCompressor {
+    fn compress_batch(
+        &self,
+        conversations: &[Vec<(String, String)>],
+        budget: usize,
+        keep_last: usize,
+    ) -> Vec<Vec<HashMap<String, String>>> {
+        conversations
+            .par_iter()
+            .map(|messages| to_dicts(self.compress_roles(messages, budget, keep_last, &[])))
+            .collect()
+    }
+
+    fn episode_items(
+        &self,
+        episodes: Vec<(String, String, i32)>,
+        budget: usize,
+        max_items: Option<usize>,
+    ) -> Vec<EpisodeItem> {
+        let mut sorted = episodes;
+        sorted.sort_by_key(|e| std::cmp::Reverse(e.2));
+
+        let mut used = 0;
+        let mut items = Vec::new();
+        for (timestamp, user_input, importance) in sorted {
+            if max_items.is_some_and(|m| items.len() >= m) {
+                break;
+            }
+            let preview = self.preview(&user_input, self.episode_preview_chars);
+            let tokens = self.estimate_tokens(&preview);
+            if used + tokens > budget {
+                continue;
+            }
+            used += tokens;
+            items.push(EpisodeItem { timestamp, preview, importance, tokens });
+        }
+        items
+    }
+
+    /// Сжатая рабочая память MemoryEngine строками "role: content"
+    pub(crate) fn working_context(&self, engine: &MemoryEngine, budget_tokens: usize, keep_last: usize) -> String {
+        render_messages(&self.compress_roles(&engine.working_messages(), budget_tokens, keep_last, &[]))
+    }
+
+    /// Краткое содержание диалога [(user, assistant)]: "Тема: ..." и самые
+    /// центральные (TextRank) предложения реплик, всё в пределах max_tokens
+    pub(crate) fn summarize_dialogue(&self, topic: &str, exchanges: &[(String, String)], max_tokens: usize) -> String {
+        let header = format!("Тема: {}.", topic);
+        let header_tokens = self.estimate_tokens(&header);
+        if header_tokens >= max_tokens {
+            return self.truncate_to_tokens(&header, max_tokens);
+        }
+        let text = exchanges
+            .iter()
+            .flat_map(|(user, assistant)| [user.as_str(), assistant.as_str()])
+            .filter(|s| !s.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let body = self.condense(&text, max_tokens - header_tokens - 1);
+        if body.is_empty() {
+            return header;
+        }
+        format!("{} {}", header, body)
+    }
+
+    /// Самые центральные предложения текста одной строкой в пределах max_tokens
+    pub(crate) fn condense(&self, text: &str, max_tokens: usize) -> String {
+        let sentences = self.select_sentences(text, max_tokens);
+        if sentences.is_empty() {
+            return String::new();
+        }
+        format!("{}.", sentences.join(". "))
+    }
+
+    /// До трёх ключевых предложений — для сжатия
+    fn key_sentences(&self, text: &str) -> Vec<String> {
+        self.extract_key_points(text, 3, 0.0, None).into_iter().map(|(s, _, _)| s).collect()
+    }
+
+    /// Превью не длиннее max_chars символов (с ellipsis при обрезке)
+    fn preview(&self, content: &str, max_chars: usize) -> String {
+        if content.chars().count() > max_chars {
+            let keep = max_chars.saturating_sub(self.ellipsis.chars().count());
+            let s: String = content.chars().take(keep).collect();
+            format!("{}{}", s.trim_end(), self.ellipsis)
+        } else {
+            content.to_string()
+        }
+    }
+
+    fn message_tokens(&self, role: &str, content: &str) -> usize {
+        self.estimate_tokens(role) + self.estimate_tokens(content)
+    }
+
+    fn compress_roles(
+        &self,
+        messages: &[(String, String)],
+        budget: usize,
+        keep_last: usize,
+        protected: &[bool],
+    ) -> Vec<(String, String)> {
+        let _timer = metrics::timer("context_compression_seconds");
+        let collapsed;
+        let (messages, protected) = if self.collapse_repeats {
+            collapsed = collapse_runs(messages, protected);
+            (collapsed.0.as_slice(), collapsed.1.as_slice())
+        } else {
+            (messages, protected)
+        };
+        let system: Vec<&(String, String)> = messages.iter().filter(|(r, _)| r == "system").collect();
+        let dialog: Vec<&(String, String)> = messages.iter().filter(|(r, _)| r != "system").collect();
+        let dialog_protected: Vec<bool> = messages
+            .iter()
+            .enumerate()
+            .filter(|(_, (r, _))| r != "system")
+            .map(|(i, _)| protected.get(i).copied().unwrap_or(false))
+            .collect();
+
+        let mut used: usize = system.iter().map(|(r, c)| self.message_tokens(r, c)).sum();
+        let last_user = dialog.iter().rposition(|(r, _)| r == "user");
+
+        // Хвост: последние keep_last реплик, пока помещаются (от новых к старым);
+        // последнее сообщение пользователя — вне зависимости от бюджета
+        let mut tail: Vec<usize> = Vec::new();
+        for i in (dialog.len().saturating_sub(keep_last)..dialog.len()).rev() {
+            let cost = self.message_tokens(&dialog[i].0, &dialog[i].1);
+            if used + cost <= budget || Some(i) == last_user {
+                used += cost;
+                tail.push(i);
+            }
+        }
+        // Последнее сообщение пользователя и защищённые — вне зависимости от бюджета
+        let forced = last_user.into_iter().chain((0..dialog.len()).filter(|&i| dialog_protected[i]));
+        for i in forced {
+            if !tail.contains(&i) {
+                used += self.message_tokens(&dialog[i].0, &dialog[i].1);
+                tail.push(i);
+            }
+        }
+        tail.sort_unstable();
+
+        // Середина — всё, что не вошло в хвост
+        let middle: Vec<&str> = dialog
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !tail.contains(i))
+            .map(|(_, (_, c))| c.as_str())
+            .collect();
+
+        let mut result: Vec<(String, String)> = system.into_iter().cloned().collect();
+        if !middle.is_empty() {
+            let header = "Краткое содержание предыдущей части разговора:";
+            let overhead = self.message_tokens("system", header);
+            if used + overhead < budget {
+                let sentences =
+                    self.select_sentences(&middle.join("\n"), budget - used - overhead);
+                if !sentences.is_empty() {
+                    result.push(("system".to_string(), format!("{} {}", header, sentences.join(". "))));
+                }
+            }
+        }
+        result.extend(tail.into_iter().map(|i| dialog[i].clone()));
+        result
+    }
+
+    /// Чанки истории для plan_summarization: строки "role: content"
+    /// набираются, пока помещаются в budget
+    fn plan_chunks(&self, messages: &[(String, String)], budget: usize) -> Vec<(String, usize, usize)> {
+        let mut chunks = Vec::new();
+        let mut lines: Vec<String> = Vec::new();
+        let mut first = 0;
+        for (i, (role, content)) in messages.iter().enumerate() {
+            let line = format!("{}: {}", role, content);
+            let candidate = if lines.is_empty() {
+                line.clone()
+            } else {
+                format!("{}\n{}", lines.join("\n"), line)
+            };
+            if self.estimate_tokens(&candidate) <= budget {
+                lines.push(line);
+                continue;
+            }
+            if !lines.is_empty() {
+                chunks.push((lines.join("\n"), first, i));
+                lines.clear();
+            }
+            first = i;
+            if self.estimate_tokens(&line) <= budget {
+                lines.push(line);
+                continue;
+            }
+            // Одно сообщение длиннее бюджета — режем содержимое
+            let prefix = format!("{}: ", role);
+            let room = budget.saturating_sub(self.estimate_tokens(&prefix)).max(1);
+            for (s, e) in self.chunk_spans(content, room, 0, true) {
+                chunks.push((format!("{}{}", prefix, &content[s..e]), i, i + 1));
+            }
+            first = i + 1;
+        }
+        if !lines.is_empty() {
+            chunks.push((lines.join("\n"), first, messages.len()));
+        }
+        chunks
+    }
+
+    /// Диапазоны индексов окон (см. window)
+    fn message_windows(&self, messages: &[(String, String)], window: usize, overlap: usize) -> Vec<(usize, usize)> {
+        let costs: Vec<usize> = messages.iter().map(|(r, c)| self.message_tokens(r, c)).collect();
+        let n = costs.len();
+        let mut windows = Vec::new();
+        let mut start = 0;
+        while start < n {
+            let mut end = start + 1;
+            let mut used = costs[start];
+            while end < n && used + costs[end] <= window {
+                used += costs[end];
+                end += 1;
+            }
+            windows.push((start, end));
+            if end == n {
+                break;
+            }
+            // Следующее окно начинается с хвоста текущего в пределах overlap
+            let mut next = end;
+            let mut shared = 0;
+            while next > start + 1 && shared + costs[next - 1] <= overlap {
+                shared += costs[next - 1];
+                next -= 1;
+            }
+            start = next;
+        }
+        windows
+    }
+
+    /// Байтовые диапазоны чанков (см. chunk_text)
+    fn chunk_spans(
+        &self,
+        text: &str,
+        chunk_tokens: usize,
+        overlap_tokens: usize,
+        respect_sentences: bool,
+    ) -> Vec<(usize, usize)> {
+        let coarse: Vec<(usize, usize)> = if respect_sentences {
+            sentence_spans(text).into_iter().map(|(_, s, e)| (s, e)).collect()
+        } else {
+            word_spans(text, 0)
+        };
+
+        // Единицы, каждая из которых помещается в чанк
+        let mut units: Vec<(usize, usize)> = Vec::with_capacity(coarse.len());
+        for (s, e) in coarse {
+            if self.estimate_tokens(&text[s..e]) <= chunk_tokens {
+                units.push((s, e));
+                continue;
+            }
+            for (ws, we) in word_spans(&text[s..e], s) {
+                if self.estimate_tokens(&text[ws..we]) <= chunk_tokens {
+                    units.push((ws, we));
+                } else {
+                    units.extend(self.hard_split(text, ws, we, chunk_tokens));
+                }
+            }
+        }
+        // Единица тянется до начала следующей — так в чанк попадают
+        // финальные знаки препинания
+        let n = units.len();
+        for i in 0..n {
+            let limit = if i + 1 < n { units[i + 1].0 } else { text.len() };
+            units[i].1 = units[i].0 + text[units[i].0..limit].trim_end().len();
+        }
+
+        let tokens = |a: usize, b: usize| self.estimate_tokens(&text[units[a].0..units[b].1]);
+        let mut chunks = Vec::new();
+        let mut i = 0;
+        while i < n {
+            let mut j = i;
+            while j + 1 < n && tokens(i, j + 1) <= chunk_tokens {
+                j += 1;
+            }
+            chunks.push((units[i].0, units[j].1));
+            if j + 1 >= n {
+                break;
+            }
+            // Перекрытие: хвост текущего чанка, укладывающийся в overlap_tokens
+            let mut k = j + 1;
+            while k > i + 1 && tokens(k - 1, j) <= overlap_tokens {
+                k -= 1;
+            }
+            i = k;
+        }
+        chunks
+    }
+
+    /// Режет [start, end) по границам символов на куски не длиннее max_tokens
+    fn hard_split(&self, text: &str, start: usize, end: usize, max_tokens: usize) -> Vec<(usize, usize)> {
+        let mut pieces = Vec::new();
+        let mut s = start;
+        while s < end {
+            let cuts: Vec<usize> = text[s..end]
+                .char_indices()
+                .skip(1)
+                .map(|(i, _)| s + i)
+                .chain(std::iter::once(end))
+                .collect();
+            // Наибольший разрез, при котором кусок помещается (минимум — один символ)
+            let fit = cuts.partition_point(|&c| self.estimate_tokens(&text[s..c]) <= max_tokens);
+            let cut = cuts[fit.saturating_sub(1)];
+            pieces.push((s, cut));
+            s = cut;
+        }
+        pieces
+    }
+
+    /// Предложения с наибольшим TextRank, пока помещаются в max_tokens,
+    /// в исходном порядке
+    fn select_sentences(&self, text: &str, max_tokens: usize) -> Vec<String> {
+        let sentences: Vec<&str> = sentence_texts(text);
+        if sentences.is_empty() {
+            return Vec::new();
+        }
+        let scores = textrank(&sentences);
+        let mut order: Vec<usize> = (0..sentences.len()).collect();
+        order.sort_by(|&a, &b| scores[b].partial_cmp(&scores[a]).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut used = 0;
+        let mut picked = Vec::new();
+        for i in order {
+            let cost = self.estimate_tokens(sentences[i]);
+            if used + cost <= max_tokens {
+                used += cost;
+                picked.push(i);
+            }
+        }
+        picked.sort_unstable();
+        picked.into_iter().map(|i| sentences[i].to_string()).collect()
+    }
+
+    /// Важность сообщения: лексикон + вопросы (?) + длина + уверенность эмоции
+    fn message_score(&self, text: &str, analyzer: Option<&EmotionAnalyzer>) -> f64 {
+        let mut score = self.lexicon.read().score(&text.to_lowercase());
+        score += 0.5 * text.matches('?').count().min(2) as f64;
+        // Длина: log-шкала, 50 слов и больше — максимум 0.5
+        let words = text.split_whitespace().count() as f64;
+        score += 0.5 * ((1.0 + words).ln() / 51f64.ln()).min(1.0);
+        if let Some(analyzer) = analyzer {
+            let (emotion, confidence, _) = analyzer.detailed(text, false);
+            if emotion != "neutral" {
+                score += 0.5 * confidence;
+            }
+        }
+        score
+    }
+
+    /// Бюджет = compression_ratio × исходные токены. Последние сообщения
+    /// (до recent_messages) идут дословно, пока помещаются, затем — превью;
+    /// из более старых берутся только ключевые предложения.
+    /// Самое новое сообщение сохраняется всегда.
+    fn compress_pairs_report(
+        &self,
+        messages: &[(String, String)],
+        analyzer: Option<&EmotionAnalyzer>,
+        protected: &[bool],
+    ) -> (Compressed, CompressionReport) {
+        let started = Instant::now();
+        let mut report = CompressionReport::default();
+        if messages.is_empty() {
+            return (Compressed::default(), report);
+        }
+        let collapsed;
+        let (messages, protected) = if self.collapse_repeats {
+            collapsed = collapse_runs(messages, protected);
+            report.collapsed = messages.len() - collapsed.0.len();
+            (collapsed.0.as_slice(), collapsed.1.as_slice())
+        } else {
+            (messages, protected)
+        };
+
+        let lines: Vec<String> = messages
+            .iter()
+            .map(|(role, content)| format!("{}: {}", role, content))
+            .collect();
+        let original: usize = lines.iter().map(|l| self.estimate_tokens(l)).sum();
+        let target = ((original as f64 * self.compression_ratio).ceil() as usize).max(1);
+
+        // Защищённые сообщения идут дословно, их токены резервируются заранее
+        let is_protected = |i: usize| protected.get(i).copied().unwrap_or(false);
+        let mut used: usize = (0..messages.len())
+            .filter(|&i| is_protected(i))
+            .map(|i| self.estimate_tokens(&lines[i]))
+            .sum();
+        let mut recent: Vec<(String, String)> = Vec::new();
+        let window_start = messages.len().saturating_sub(self.recent_messages);
+        let mut older_end = messages.len();
+
+        for i in (window_start..messages.len()).rev() {
+            if is_protected(i) {
+                recent.push(messages[i].clone());
+                older_end = i;
+                report.kept += 1;
+                continue;
+            }
+            let cost = self.estimate_tokens(&lines[i]);
+            if recent.is_empty() || used + cost <= target {
+                used += cost;
+                recent.push(messages[i].clone());
+                older_end = i;
+                report.kept += 1;
+                continue;
+            }
+
+            let (role, content) = &messages[i];
+            let preview = self.preview(content, self.preview_chars);
+            let cost = self.estimate_tokens(&format!("{}: {}", role, preview));
+            if used + cost > target {
+                break;
+            }
+            used += cost;
+            recent.push((role.clone(), preview));
+            older_end = i;
+            report.truncated += 1;
+        }
+        recent.reverse();
+        let pinned: Vec<(String, String)> =
+            (0..older_end).filter(|&i| is_protected(i)).map(|i| messages[i].clone()).collect();
+        report.kept += pinned.len();
+        report.dropped = older_end - pinned.len();
+
+        // Не поместившееся — по убыванию важности: ключевые предложения,
+        // а у важных сообщений без них — превью. В выводе — исходный порядок.
+        let mut ranked: Vec<(usize, f64)> = (0..older_end)
+            .filter(|&i| !is_protected(i))
+            .map(|i| (i, self.message_score(&messages[i].1, analyzer)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let mut selected: Vec<(usize, String)> = Vec::new();
+        for (i, score) in ranked {
+            let content = &messages[i].1;
+            let mut candidates = self.key_sentences(content);
+            if candidates.is_empty() && score >= SURVIVE_SCORE {
+                candidates.push(self.preview(content, self.preview_chars));
+            }
+            for point in candidates {
+                let cost = self.estimate_tokens(&format!("- {}", point));
+                if used + cost <= target {
+                    used += cost;
+                    selected.push((i, point));
+                }
+            }
+        }
+        selected.sort_by_key(|(i, _)| *i);
+        report.key_points = selected.into_iter().map(|(_, p)| p).collect();
+
+        let mut kept = pinned;
+        kept.extend(recent);
+        let compressed = Compressed { points: report.key_points.clone(), messages: kept };
+        report.original_tokens = original;
+        report.compressed_tokens = self.estimate_tokens(&compressed.to_text());
+        report.elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+        (compressed, report)
+    }
+}
+
+/// Элемент summarize_episodes_items
+#[derive(Debug, PartialEq)]
+struct EpisodeItem {
+    timestamp: String,
+    preview: String,
+    importance: i32,
+    tokens: usize,
+}
+
+/// Результат compress_conversation: ключевые пункты старой части + сохранённые сообщения
+#[derive(Default)]
+struct Compressed {
+    points: Vec<String>,
+    messages: Vec<(String, String)>,
+}
+
+impl Compressed {
+    fn summary(&self) -> Option<String> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let mut lines = vec!["Ранее в разговоре:".to_string()];
+        lines.extend(self.points.iter().map(|p| format!("- {}", p)));
+        Some(lines.join("\n"))
+    }
+
+    /// Legacy-формат: одна строка "role: content" на сообщение
+    fn to_text(&self) -> String {
+        let body = render_messages(&self.messages);
+        match self.summary() {
+            Some(summary) if body.is_empty() => summary,
+            Some(summary) => format!("{}\n{}", summary, body),
+            None => body,
+        }
+    }
+
+    /// Сообщения для chat-completion API; сводка — отдельным system-сообщением
+    fn to_messages(&self) -> Vec<(String, String)> {
+        self.summary()
+            .map(|s| ("system".to_string(), s))
+            .into_iter()
+            .chain(self.messages.iter().cloned())
+            .collect()
+    }
+
+    fn into_py(self, py: Python<'_>, legacy: bool) -> PyResult<PyObject> {
+        if legacy {
+            Ok(self.to_text().into_pyobject(py)?.into_any().unbind())
+        } else {
+            Ok(to_dicts(self.to_messages()).into_pyobject(py)?.into_any().unbind())
+        }
+    }
+}
+
+// ── ConversationBuffer ──
+
+struct BufferedMessage {
+    line: String,
+    tokens: usize,
+    /// Ключевые предложения считаются один раз — при push
+    points: Vec<String>,
+}
+
+#[derive(Default)]
+struct BufferState {
+    messages: Vec<BufferedMessage>,
+    total_tokens: usize,
+}
+
+/// Потоковый буфер разговора: сообщения добавляются по одному, оценка
+/// токенов и ключевые предложения кэшируются, так что render(budget)
+/// не пересчитывает всю историю на каждом ходе.
+#[pyclass(frozen)]
+pub struct ConversationBuffer {
+    compressor: ContextCompressor,
+    state: RwLock<BufferState>,
+}
+
+#[pymethods]
+impl ConversationBuffer {
+    /// Словарь и параметры превью — как у ContextCompressor с тем же config
+    #[new]
+    #[pyo3(signature = (important_words=None, *, config=None))]
+    fn new(important_words: Option<HashMap<String, f64>>, config: Option<PyRef<'_, CoreConfig>>) -> PyResult<Self> {
+        let config = config::settings(config);
+        let section = &config.compressor;
+        Ok(Self {
+            compressor: ContextCompressor::new(
+                1.0,
+                important_words.or_else(|| config.lexicons.important_words.clone()),
+                section.preview_chars,
+                section.recent_messages,
+                &section.ellipsis,
+                section.episode_preview_chars,
+                section.collapse_repeats,
+                section.token_ratios.clone(),
+            )?,
+            state: RwLock::new(BufferState::default()),
+        })
+    }
+
+    fn push(&self, role: &str, content: &str) {
+        let line = format!("{}: {}", role, content);
+        let message = BufferedMessage {
+            tokens: self.compressor.estimate_tokens(&line),
+            points: self.compressor.key_sentences(content),
+            line,
+        };
+        let mut state = self.state.write();
+        state.total_tokens += message.tokens;
+        state.messages.push(message);
+    }
+
+    /// Контекст в пределах budget токенов: свежие сообщения дословно
+    /// (самое новое — всегда), перед ними — ключевые пункты более старых
+    fn render(&self, budget: usize) -> String {
+        let state = self.state.read();
+        let messages = &state.messages;
+
+        let mut used = 0;
+        let mut start = messages.len();
+        while start > 0 {
+            let cost = messages[start - 1].tokens;
+            if start < messages.len() && used + cost > budget {
+                break;
+            }
+            used += cost;
+            start -= 1;
+        }
+
+        // Пункты берутся от новых к старым, выводятся в исходном порядке
+        let mut points: Vec<String> = Vec::new();
+        'older: for message in messages[..start].iter().rev() {
+            for point in message.points.iter().rev() {
+                let line = format!("- {}", point);
+                let cost = self.compressor.estimate_tokens(&line);
+                if used + cost > budget {
+                    break 'older;
+                }
+                used += cost;
+                points.push(line);
+            }
+        }
+        points.reverse();
+
+        let mut parts = Vec::with_capacity(points.len() + messages.len() - start + 1);
+        if !points.is_empty() {
+            parts.push("Ранее в разговоре:".to_string());
+            parts.extend(points);
+        }
+        parts.extend(messages[start..].iter().map(|m| m.line.clone()));
+        parts.join("\n")
+    }
+
+    /// Оценка токенов всей истории (обновляется при push)
+    #[getter]
+    fn total_tokens(&self) -> usize {
+        self.state.read().total_tokens
+    }
+
+    fn clear(&self) {
+        *self.state.write() = BufferState::default();
+    }
+
+    fn __len__(&self) -> usize {
+        self.state.read().messages.len()
+    }
+}
+
+// ── Разрезы с учётом разметки ──
+
+/// Допустимое место обрезки: pos — байт, fence — маркер открытого блока кода
+struct CutPoint<'a> {
+    pos: usize,
+    /// 3 — граница абзаца/блока, 2 — конец строки, 1 — конец предложения
+    /// или строка кода, 0 — граница слова
+    priority: u8,
+    fence: Option<&'a str>,
+}
+
+fn cut_points(text: &str) -> Vec<CutPoint<'_>> {
+    let mut cuts = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let trimmed = content.trim_start();
+        let end = offset + content.len();
+        let marker = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m));
+        match (fence, marker) {
+            (None, Some(m)) => fence = Some(m),
+            (Some(open), Some(m)) if open == m => {
+                fence = None;
+                cuts.push(CutPoint { pos: end, priority: 3, fence: None });
+            }
+            (Some(_), _) => cuts.push(CutPoint { pos: end, priority: 1, fence }),
+            (None, None) => {
+                // Внутри строки таблицы не режем
+                if !trimmed.starts_with('|') {
+                    for (_, _, e) in sentence_spans(content) {
+                        cuts.push(CutPoint { pos: offset + e, priority: 1, fence: None });
+                    }
+                    for (i, _) in content.match_indices(' ') {
+                        cuts.push(CutPoint { pos: offset + i, priority: 0, fence: None });
+                    }
+                }
+                let priority = if content.trim().is_empty() { 3 } else { 2 };
+                cuts.push(CutPoint { pos: end, priority, fence: None });
+            }
+        }
+        offset += line.len();
+    }
+    cuts
+}
+
+/// List[Tuple] / List[Dict] / List[Sequence] → [(role, content)]
+fn extract_messages(messages: &Bound<'_, pyo3::types::PyList>) -> PyResult<Vec<(String, String)>> {
+    let mut result = Vec::with_capacity(messages.len());
+    for item in messages.iter() {
+        let pair = if let Ok(dict) = item.downcast::<PyDict>() {
+            // List[dict] с ключами "role", "content"
+            let r = dict
+                .get_item("role")?
+                .map(|v| v.extract::<String>())
+                .transpose()?
+                .unwrap_or_default();
+            let c = dict
+                .get_item("content")?
+                .map(|v| v.extract::<String>())
+                .transpose()?
+                .unwrap_or_default();
+            (r, c)
+        } else if let Ok(tup) = item.downcast::<PyTuple>() {
+            // List[Tuple[str, str, str]]
+            let r: String = tup.get_item(0)?.extract()?;
+            let c: String = tup.get_item(1)?.extract()?;
+            (r, c)
+        } else {
+            // Попробуем как sequence
+            let r: String = item.get_item(0)?.extract()?;
+            let c: String = item.get_item(1)?.extract()?;
+            (r, c)
+        };
+        result.push(pair);
+    }
+    Ok(result)
+}
+
+/// Слияние подряд идущих повторов одной роли (защищённые не сливаются).
+/// Возвращает сообщения с пометкой "(×N)" и маску защиты для них.
+fn collapse_runs(messages: &[(String, String)], protected: &[bool]) -> (Vec<(String, String)>, Vec<bool>) {
+    let is_protected = |i: usize| protected.get(i).copied().unwrap_or(false);
+    let mut result: Vec<(String, String)> = Vec::with_capacity(messages.len());
+    let mut mask = Vec::with_capacity(messages.len());
+    let mut i = 0;
+    while i < messages.len() {
+        let (role, content) = &messages[i];
+        let mut j = i + 1;
+        if !is_protected(i) {
+            while j < messages.len()
+                && !is_protected(j)
+                && messages[j].0 == *role
+                && near_identical(content, &messages[j].1)
+            {
+                j += 1;
+            }
+        }
+        let content = if j - i > 1 { format!("{} (×{})", content, j - i) } else { content.clone() };
+        result.push((role.clone(), content));
+        mask.push(is_protected(i));
+        i = j;
+    }
+    (result, mask)
+}
+
+/// Совпадение после нормализации (регистр, пунктуация, пробелы) или
+/// пересечение слов >= 85% для сообщений от 4 слов
+fn near_identical(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| -> Vec<String> {
+        s.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_lowercase())
+            .collect()
+    };
+    let (wa, wb) = (normalize(a), normalize(b));
+    if wa == wb {
+        // "?" и "??" — обе без слов; сравниваем хотя бы набор знаков
+        return !wa.is_empty() || a.trim().chars().next() == b.trim().chars().next();
+    }
+    if wa.len() < 4 || wb.len() < 4 {
+        return false;
+    }
+    let sa: std::collections::HashSet<&String> = wa.iter().collect();
+    let sb: std::collections::HashSet<&String> = wb.iter().collect();
+    let common = sa.intersection(&sb).count();
+    common as f64 / sa.union(&sb).count() as f64 >= 0.85
+}
+
+/// Маска защищённых сообщений: по индексу, роли или маркеру-подстроке
+fn protection_mask(
+    messages: &[(String, String)],
+    indices: Option<Vec<usize>>,
+    roles: Option<Vec<String>>,
+    markers: Option<Vec<String>>,
+) -> Vec<bool> {
+    let indices = indices.unwrap_or_default();
+    let roles = roles.unwrap_or_default();
+    let markers: Vec<String> = markers.unwrap_or_default().iter().map(|m| m.to_lowercase()).collect();
+    messages
+        .iter()
+        .enumerate()
+        .map(|(i, (role, content))| {
+            indices.contains(&i)
+                || roles.contains(role)
+                || (!markers.is_empty() && {
+                    let lower = content.to_lowercase();
+                    markers.iter().any(|m| lower.contains(m.as_str()))
+                })
+        })
+        .collect()
+}
+
+/// Эмбеддинги сообщений: явные (по одному на сообщение) или из EmbeddingCache по тексту
+fn message_vectors(
+    messages: &[(String, String)],
+    embeddings: Option<Vec<Vec<f32>>>,
+    cache: Option<&EmbeddingCache>,
+) -> PyResult<Vec<Option<Vec<f32>>>> {
+    if let Some(embeddings) = embeddings {
+        if embeddings.len() != messages.len() {
+            return Err(PyValueError::new_err(format!(
+                "Эмбеддингов {} — ожидается по одному на сообщение ({})",
+                embeddings.len(),
+                messages.len()
+            )));
+        }
+        return Ok(embeddings.into_iter().map(Some).collect());
+    }
+    Ok(messages
+        .iter()
+        .map(|(_, content)| cache.and_then(|c| c.peek(content)))
+        .collect())
+}
+
+/// Индексы сохраняемых сообщений (в исходном порядке). Проход от новых
+/// к старым: сообщение отбрасывается, если похоже на уже оставленное той же роли.
+fn dedup_indices(
+    messages: &[(String, String)],
+    vectors: &[Option<Vec<f32>>],
+    threshold: f32,
+) -> Vec<usize> {
+    let mut kept: Vec<usize> = Vec::with_capacity(messages.len());
+    for i in (0..messages.len()).rev() {
+        let role = &messages[i].0;
+        let duplicate = role != "system"
+            && vectors[i].as_ref().is_some_and(|v| {
+                kept.iter().any(|&k| {
+                    messages[k].0 == *role
+                        && vectors[k]
+                            .as_ref()
+                            .is_some_and(|kv| cosine_similarity_impl(v, kv) >= threshold)
+                })
+            });
+        if !duplicate {
+            kept.push(i);
+        }
+    }
+    kept.reverse();
+    kept
+}
+
+/// Окно разговора: (start, end, сообщения [start, end))
+type Window = (usize, usize, Vec<HashMap<String, String>>);
+
+fn render_messages(messages: &[(String, String)]) -> String {
+    messages
+        .iter()
+        .map(|(role, content)| format!("{}: {}", role, content))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn to_dicts(messages: Vec<(String, String)>) -> Vec<HashMap<String, String>> {
+    messages
+        .into_iter()
+        .map(|(role, content)| {
+            HashMap::from([("role".to_string(), role), ("content".to_string(), content)])
+        })
+        .collect()
+}
+
+/// Байтовые диапазоны слов (по пробелам), сдвинутые на offset
+pub(crate) fn word_spans(text: &str, offset: usize) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, ch) in text.char_indices() {
+        match (ch.is_whitespace(), start) {
+            (true, Some(s)) => {
+                spans.push((offset + s, offset + i));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((offset + s, offset + text.len()));
+    }
+    spans
+}
+
+// ── Предложения и TextRank ──
+
+/// Тексты предложений без завершающих знаков — так их удобно склеивать через ". "
+fn sentence_texts(text: &str) -> Vec<&str> {
+    sentence_spans(text)
+        .into_iter()
+        .map(|(s, _, _)| s.trim_end_matches(['.', '!', '?', '…']).trim_end())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Значимые слова предложения (lowercase, от 3 символов)
+fn content_words(sentence: &str) -> Vec<String> {
+    sentence
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3)
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// PageRank по графу сходства (Mihalcea & Tarau 2004):
+/// sim(i, j) = |общие слова| / (ln|Si| + ln|Sj|)
+fn textrank(sentences: &[&str]) -> Vec<f64> {
+    const DAMPING: f64 = 0.85;
+    const MAX_ITER: usize = 50;
+    const EPS: f64 = 1e-6;
+
+    let n = sentences.len();
+    let words: Vec<Vec<String>> = sentences
+        .iter()
+        .map(|s| {
+            let mut w = content_words(s);
+            w.sort_unstable();
+            w.dedup();
+            w
+        })
+        .collect();
+
+    let mut weights = vec![vec![0.0f64; n]; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let common = words[i].iter().filter(|w| words[j].binary_search(w).is_ok()).count();
+            let denom = (words[i].len() as f64).ln() + (words[j].len() as f64).ln();
+            if common > 0 && denom > 0.0 {
+                weights[i][j] = common as f64 / denom;
+                weights[j][i] = weights[i][j];
+            }
+        }
+    }
+    let out_sums: Vec<f64> = weights.iter().map(|row| row.iter().sum()).collect();
+
+    let mut scores = vec![1.0f64; n];
+    for _ in 0..MAX_ITER {
+        let next: Vec<f64> = (0..n)
+            .map(|i| {
+                let incoming: f64 = (0..n)
+                    .filter(|&j| out_sums[j] > 0.0)
+                    .map(|j| weights[j][i] / out_sums[j] * scores[j])
+                    .sum();
+                (1.0 - DAMPING) + DAMPING * incoming
+            })
+            .collect();
+        let delta: f64 = next.iter().zip(&scores).map(|(a, b)| (a - b).abs()).sum();
+        scores = next;
+        if delta < EPS {
+            break;
+        }
+    }
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compressor(ratio: f64, words: Option<HashMap<String, f64>>) -> ContextCompressor {
+        ContextCompressor::new(ratio, words, PREVIEW_CHARS, RECENT_WINDOW, ELLIPSIS, EPISODE_PREVIEW_CHARS, true, None)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_estimate_tokens_ascii() {
+        let c = compressor(0.3, None);
+        // "hello world" = 11 ASCII chars → 11/4 + 0/2 + 1 = 3
+        assert_eq!(c.estimate_tokens("hello world"), 3);
+    }
+
+    #[test]
+    fn test_estimate_tokens_russian() {
+        let c = compressor(0.3, None);
+        // "привет" = 6 non-ASCII chars → 0/4 + 6/2 + 1 = 4
+        assert_eq!(c.estimate_tokens("привет"), 4);
+    }
+
+    #[test]
+    fn test_estimate_tokens_profiles() {
+        let c = compressor(0.3, None);
+        // CJK: ~1 символ на токен, эмодзи — ~2 токена
+        assert_eq!(c.estimate_tokens("你好世界"), 5);
+        assert_eq!(c.estimate_tokens("🎉🎉"), 5);
+        // Код: ~3 символа на токен вместо 4
+        let code = "fn f(x: &[u8]) -> u8 { x[0] }";
+        assert_eq!(c.estimate_tokens(code), code.len() / 3 + 1);
+
+        let custom = ContextCompressor::new(
+            0.3, None, PREVIEW_CHARS, RECENT_WINDOW, ELLIPSIS, EPISODE_PREVIEW_CHARS, true,
+            Some(HashMap::from([("cjk".to_string(), 2.0)])),
+        )
+        .unwrap();
+        assert_eq!(custom.estimate_tokens("你好世界"), 3);
+        assert_eq!(custom.get_token_ratios()["latin"], 4.0);
+        for bad in [("klingon", 1.0), ("latin", 0.0)] {
+            let ratios = Some(HashMap::from([(bad.0.to_string(), bad.1)]));
+            let result = ContextCompressor::new(
+                0.3, None, PREVIEW_CHARS, RECENT_WINDOW, ELLIPSIS, EPISODE_PREVIEW_CHARS, true, ratios,
+            );
+            assert!(result.is_err());
+        }
+    }
+
+    // compress_conversation тест требует Python runtime (принимает PyList),
+    // поэтому тестируется через integration test с maturin;
+    // здесь — его ядро compress_pairs_report
+
+    /// Разные (не сливаемые collapse_repeats) ответы-заполнители
+    fn replies(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("Ответ {}: длинный текст без особого смысла, просто для объёма {}.", i, i * 7)).collect()
+    }
+
+    fn msgs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items.iter().map(|(r, c)| (r.to_string(), c.to_string())).collect()
+    }
+
+    #[test]
+    fn test_compress_honors_ratio() {
+        let c = compressor(0.3, None);
+        let filler = "Просто болтаем о погоде и всяком разном без особой цели. ".repeat(4);
+        let mut items = vec![("user", "Важно: сервер падает при деплое.")];
+        for _ in 0..8 {
+            items.push(("user", filler.as_str()));
+            items.push(("assistant", filler.as_str()));
+        }
+        items.push(("user", "Что делать дальше?"));
+        let messages = msgs(&items);
+
+        let original: usize = messages
+            .iter()
+            .map(|(r, m)| c.estimate_tokens(&format!("{}: {}", r, m)))
+            .sum();
+        let compressed = c.compress_pairs_report(&messages, None, &[]).0.to_text();
+        assert!(c.estimate_tokens(&compressed) <= (original as f64 * 0.35) as usize);
+        assert!(compressed.ends_with("user: Что делать дальше?"));
+        assert!(compressed.contains("сервер падает"));
+
+        // ratio=1.0 — последние RECENT_WINDOW дословно, из старых — ключевые пункты
+        let full = compressor(1.0, None).compress_pairs_report(&messages, None, &[]).0.to_text();
+        assert_eq!(full.lines().count(), RECENT_WINDOW + 2);
+        assert!(full.contains(filler.trim()));
+    }
+
+    #[test]
+    fn test_extract_key_points() {
+        let c = compressor(0.3, None);
+        let text = "Всё хорошо. Есть важная проблема с сетью. Погода солнечная.";
+        let points = c.extract_key_points(text, 3, 0.0, None);
+        assert!(!points.is_empty());
+        let (sentence, score, offset) = &points[0];
+        assert!(sentence.contains("проблема"));
+        assert!(*score > 0.0);
+        assert_eq!(text.chars().skip(*offset).take(4).collect::<String>(), "Есть");
+        assert!(c.extract_key_points(text, 3, 100.0, None).is_empty());
+        assert!(c.extract_key_points(text, 0, 0.0, None).is_empty());
+    }
+
+    #[test]
+    fn test_key_points_token_budget() {
+        let c = compressor(0.3, None);
+        let long = format!("Важно: {}", "очень длинное пояснение ".repeat(10));
+        let text = format!("{}. Важно и срочно. Проблема решена.", long.trim());
+        let all = c.extract_key_points(&text, 10, 0.0, None);
+        assert_eq!(all.len(), 3);
+        // Длинное предложение не влезает в бюджет, короткие — влезают
+        let budget = c.estimate_tokens("Важно и срочно") + c.estimate_tokens("Проблема решена");
+        let fitted = c.extract_key_points(&text, 10, 0.0, Some(budget));
+        let sentences: Vec<&str> = fitted.iter().map(|p| p.0.as_str()).collect();
+        assert_eq!(sentences, vec!["Важно и срочно", "Проблема решена"]);
+        assert!(c.extract_key_points(&text, 10, 0.0, Some(0)).is_empty());
+    }
+
+    #[test]
+    fn test_summarize_textrank() {
+        let c = compressor(0.3, None);
+        let text = "Кот Барсик любит молоко. Барсик пьёт молоко каждое утро. \
+                    Сегодня шёл дождь. Молоко для Барсика покупают в магазине. \
+                    Биржевые котировки выросли.";
+        let summary = c.summarize(text, 2);
+        assert_eq!(summary.len(), 2);
+        assert!(summary.iter().all(|s| s.contains("олоко")));
+        // Короткий текст возвращается целиком
+        assert_eq!(c.summarize("Одно предложение.", 3), vec!["Одно предложение"]);
+    }
+
+    #[test]
+    fn test_summarize_dialogue() {
+        let c = compressor(0.3, None);
+        let dialogue = msgs(&[
+            ("Хочу взять кота из приюта.", "Отличная идея, кот из приюта будет благодарен."),
+            ("Какой корм нужен коту?", "Коту нужен качественный корм."),
+        ]);
+        let summary = c.summarize_dialogue("кот", &dialogue, 40);
+        assert!(summary.starts_with("Тема: кот."));
+        assert!(summary.contains("кот"));
+        assert!(c.estimate_tokens(&summary) <= 40);
+        assert_eq!(c.summarize_dialogue("кот", &[], 40), "Тема: кот.");
+    }
+
+    #[test]
+    fn test_compress_roles() {
+        let c = compressor(0.3, None);
+        let long = "Мы обсуждали переезд в Казань. Переезд назначен на май. Кот поедет в переноске. ";
+        let mut items = vec![("system", "Ты — Кристина.")];
+        for _ in 0..6 {
+            items.push(("user", long));
+            items.push(("assistant", long));
+        }
+        items.push(("user", "Так когда переезд?"));
+        items.push(("assistant", "В мае."));
+        let messages = msgs(&items);
+
+        let out = c.compress_roles(&messages, 80, 2, &[]);
+        assert_eq!(out[0], ("system".to_string(), "Ты — Кристина.".to_string()));
+        assert!(out[1].1.starts_with("Краткое содержание"));
+        assert_eq!(&out[out.len() - 2..], &messages[messages.len() - 2..]);
+        let total: usize = out.iter().map(|(r, m)| c.message_tokens(r, m)).sum();
+        assert!(total <= 80);
+
+        // Даже при нулевом бюджете последний вопрос пользователя остаётся
+        let out = c.compress_roles(&messages, 0, 2, &[]);
+        assert!(out.iter().any(|(r, m)| r == "user" && m == "Так когда переезд?"));
+    }
+
+    #[test]
+    fn test_dedup_indices() {
+        let messages = msgs(&[
+            ("user", "Ок, понял"),
+            ("assistant", "Отлично"),
+            ("user", "Хорошо, понятно"),
+            ("user", "А что с котом?"),
+        ]);
+        let vectors = vec![
+            Some(vec![1.0, 0.0, 0.0]),
+            Some(vec![1.0, 0.0, 0.0]),
+            Some(vec![0.99, 0.05, 0.0]),
+            Some(vec![0.0, 1.0, 0.0]),
+        ];
+        // Ранний повтор пользователя убран, ответ ассистента (другая роль) сохранён
+        assert_eq!(dedup_indices(&messages, &vectors, 0.95), vec![1, 2, 3]);
+        assert_eq!(dedup_indices(&messages, &vec![None; 4], 0.95), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_chunk_text() {
+        let c = compressor(0.3, None);
+        let text = "Первое предложение тут. Второе чуть длиннее первого! Третье? Четвёртое и последнее.";
+        let chunks = c.chunk_text(text, 15, 5, true).unwrap();
+        assert!(chunks.len() > 1);
+        for (chunk, s, e) in &chunks {
+            assert!(c.estimate_tokens(chunk) <= 15);
+            let by_offsets: String = text.chars().skip(*s).take(e - s).collect();
+            assert_eq!(&by_offsets, chunk);
+        }
+        // Чанки начинаются с предложений и покрывают весь текст
+        assert!(chunks[0].0.starts_with("Первое"));
+        assert!(chunks.last().unwrap().0.ends_with("последнее."));
+
+        // Без разрывов по словам: слово без пробелов режется по символам
+        let long = "x".repeat(50);
+        let pieces = c.chunk_text(&long, 4, 0, false).unwrap();
+        assert_eq!(pieces.iter().map(|p| p.0.as_str()).collect::<String>(), long);
+        assert!(c.chunk_text("abc", 5, 5, true).is_err());
+    }
+
+    #[test]
+    fn test_plan_and_merge() {
+        let c = compressor(0.3, None);
+        let messages = msgs(&[
+            ("user", "Привет, как дела?"),
+            ("assistant", "Всё хорошо, спасибо"),
+            ("user", &"Очень длинное сообщение. ".repeat(20)),
+            ("assistant", "Понятно"),
+        ]);
+        let chunks = c.plan_chunks(&messages, 30);
+        assert!(chunks.iter().all(|(t, _, _)| c.estimate_tokens(t) <= 30));
+        assert_eq!((chunks[0].1, chunks[0].2), (0, 2));
+        // Длинное сообщение разбито на несколько чанков с одним индексом
+        assert!(chunks.iter().filter(|(_, s, e)| (*s, *e) == (2, 3)).count() > 1);
+        assert_eq!(chunks.last().unwrap().2, 4);
+
+        let merged = c.merge_summaries(
+            vec!["Кот любит рыбу. Погода солнечная".into(), "кот любит рыбу! Завтра дождь".into()],
+            100,
+        );
+        assert_eq!(merged, "Кот любит рыбу. Погода солнечная. Завтра дождь");
+    }
+
+    #[test]
+    fn test_compression_report() {
+        let c = compressor(0.3, None);
+        let mut items = vec![("user", "Важно: меня зовут Анна, запомни это.")];
+        let filler = replies(15);
+        items.extend(filler.iter().map(|r| ("assistant", r.as_str())));
+        let messages = msgs(&items);
+        let (compressed, report) = c.compress_pairs_report(&messages, None, &[]);
+        let text = compressed.to_text();
+        assert_eq!(report.kept + report.truncated + report.dropped, messages.len());
+        assert!(report.dropped > 0);
+        assert!(report.compressed_tokens < report.original_tokens);
+        assert_eq!(report.key_points, vec!["Важно: меня зовут Анна, запомни это".to_string()]);
+        assert_eq!(text, c.compress_pairs_report(&messages, None, &[]).0.to_text());
+    }
+
+    #[test]
+    fn test_score_message() {
+        let c = compressor(0.3, None);
+        let plain = c.message_score("ок", None);
+        assert!(c.message_score("Как тебя зовут? Сколько тебе лет?", None) > plain);
+        assert!(c.message_score("Запомни: это важно", None) > plain);
+        let analyzer = EmotionAnalyzer::new(true);
+        let text = "Спасибо, это отлично!";
+        assert!(c.message_score(text, Some(&analyzer)) > c.message_score(text, None));
+
+        // Важный старый вопрос переживает сжатие, болтовня — нет
+        let mut items = vec![("user", "Какой у тебя любимый фильм? А книга?"), ("user", "ну ладно")];
+        let filler = replies(12);
+        items.extend(filler.iter().map(|r| ("assistant", r.as_str())));
+        let (compressed, report) = c.compress_pairs_report(&msgs(&items), None, &[]);
+        let text = compressed.to_text();
+        assert!(text.contains("любимый фильм"));
+        assert!(!text.contains("ну ладно"));
+        assert_eq!(report.key_points.len(), 1);
+    }
+
+    #[test]
+    fn test_conversation_buffer() {
+        let buffer = ConversationBuffer::new(None, None).unwrap();
+        buffer.push("user", "Важно: меня зовут Анна.");
+        for i in 0..20 {
+            buffer.push("assistant", &format!("Ответ номер {} без особого смысла.", i));
+        }
+        assert_eq!(buffer.__len__(), 21);
+        let total = buffer.total_tokens();
+        assert!(total > 100);
+
+        let rendered = buffer.render(40);
+        assert!(rendered.ends_with("assistant: Ответ номер 19 без особого смысла."));
+        assert!(buffer.compressor.estimate_tokens(&rendered) <= 40 + 5);
+
+        // Свободное после дословных сообщений место уходит на пункты старых
+        let last = buffer.compressor.estimate_tokens("assistant: Ответ номер 19 без особого смысла.");
+        let point = buffer.compressor.estimate_tokens("- Важно: меня зовут Анна");
+        let rendered = buffer.render(last + point);
+        assert_eq!(
+            rendered,
+            "Ранее в разговоре:\n- Важно: меня зовут Анна\nassistant: Ответ номер 19 без особого смысла."
+        );
+        // Бюджет меньше последнего сообщения — оно всё равно остаётся
+        assert_eq!(buffer.render(1), "assistant: Ответ номер 19 без особого смысла.");
+
+        buffer.clear();
+        assert_eq!((buffer.__len__(), buffer.total_tokens()), (0, 0));
+        assert_eq!(buffer.render(100), "");
+    }
+
+    #[test]
+    fn test_protected_messages() {
+        let c = compressor(0.2, None);
+        let mut items = vec![("tool", "Результат: файл report.pdf создан"), ("user", "Всегда отвечай кратко")];
+        let filler = replies(15);
+        items.extend(filler.iter().map(|r| ("assistant", r.as_str())));
+        let messages = msgs(&items);
+        let protected = protection_mask(&messages, None, Some(vec!["tool".into()]), Some(vec!["ВСЕГДА".into()]));
+        assert_eq!(&protected[..3], &[true, true, false]);
+
+        let (compressed, report) = c.compress_pairs_report(&messages, None, &protected);
+        let text = compressed.to_text();
+        assert!(text.contains("tool: Результат: файл report.pdf создан\nuser: Всегда отвечай кратко"));
+
+        // Структурный вывод: роли сохранены, сводка — отдельное system-сообщение
+        let structured = compressed.to_messages();
+        assert_eq!(structured[0], messages[0]);
+        assert_eq!(structured.last().unwrap(), messages.last().unwrap());
+        assert_eq!(structured.len(), compressed.messages.len() + usize::from(!compressed.points.is_empty()));
+        assert_eq!(report.kept + report.truncated + report.dropped, messages.len());
+
+        let out = c.compress_roles(&messages, 30, 2, &protected);
+        assert_eq!(out[0], messages[0]);
+        assert_eq!(out[1], messages[1]);
+    }
+
+    #[test]
+    fn test_preview_options() {
+        let c = ContextCompressor::new(0.1, None, 12, 2, "…", 8, true, None).unwrap();
+        assert_eq!(c.preview("Очень длинное сообщение", 12), "Очень длинн…");
+        assert_eq!(c.preview("коротко", 12), "коротко");
+        let messages = msgs(&[("user", "раз"), ("user", "два"), ("user", "три"), ("user", "четыре")]);
+        let (compressed, _) = c.compress_pairs_report(&messages, None, &[]);
+        assert!(compressed.messages.len() <= 2);
+        let summary = c.summarize_episodes(vec![("2024-01-01T10:00".into(), "Длинный эпизод".into(), 1)], 100);
+        assert_eq!(summary, "[2024-01-01] Длинный…");
+        assert!(ContextCompressor::new(0.3, None, 2, 10, "...", 50, true, None).is_err());
+        assert!(ContextCompressor::new(0.3, None, 100, 0, "...", 50, true, None).is_err());
+    }
+
+    #[test]
+    fn test_collapse_runs() {
+        let messages = msgs(&[
+            ("user", "?"),
+            ("user", "??"),
+            ("user", "?"),
+            ("assistant", "Извините, я не понял вопрос."),
+            ("assistant", "извините я не понял вопрос"),
+            ("user", "Привет"),
+            ("user", "Пока"),
+        ]);
+        let (collapsed, mask) = collapse_runs(&messages, &[]);
+        assert_eq!(
+            collapsed,
+            msgs(&[
+                ("user", "? (×3)"),
+                ("assistant", "Извините, я не понял вопрос. (×2)"),
+                ("user", "Привет"),
+                ("user", "Пока"),
+            ])
+        );
+        assert_eq!(mask, vec![false; 4]);
+
+        // Защищённое сообщение не сливается
+        let (collapsed, mask) = collapse_runs(&messages[..3], &[false, true, false]);
+        assert_eq!(collapsed.len(), 3);
+        assert_eq!(mask, vec![false, true, false]);
+    }
+
+    #[test]
+    fn test_compress_batch() {
+        let c = compressor(0.3, None);
+        let filler = replies(30);
+        let conversations: Vec<Vec<(String, String)>> = (0..20)
+            .map(|n| {
+                let mut items = vec![("user", "Привет")];
+                items.extend(filler.iter().take(n).map(|r| ("assistant", r.as_str())));
+                msgs(&items)
+            })
+            .collect();
+        let batch = c.compress_batch(&conversations, 60, 4);
+        assert_eq!(batch.len(), conversations.len());
+        for (out, messages) in batch.iter().zip(&conversations) {
+            let expected = to_dicts(c.compress_roles(messages, 60, 4, &[]));
+            assert_eq!(out, &expected);
+        }
+    }
+
+    #[test]
+    fn test_episode_items() {
+        let c = compressor(0.3, None);
+        let episodes = vec![
+            ("2024-01-01T10:00".to_string(), "Короткий".to_string(), 1),
+            ("2024-01-02T10:00".to_string(), "Очень важный эпизод про работу над проектом".repeat(3), 9),
+            ("2024-01-03T10:00".to_string(), "Средний эпизод".to_string(), 5),
+        ];
+        let all = c.episode_items(episodes.clone(), 1000, None);
+        assert_eq!(all.iter().map(|i| i.importance).collect::<Vec<_>>(), vec![9, 5, 1]);
+        assert!(all[0].preview.ends_with(ELLIPSIS));
+        assert_eq!(all[1].tokens, c.estimate_tokens("Средний эпизод"));
+
+        // Крупный эпизод не влезает — остальные всё равно берутся
+        let small = c.episode_items(episodes.clone(), 15, None);
+        assert_eq!(small.iter().map(|i| i.importance).collect::<Vec<_>>(), vec![5, 1]);
+        assert_eq!(c.episode_items(episodes, 1000, Some(1)).len(), 1);
+    }
+
+    #[test]
+    fn test_render_messages() {
+        let messages = msgs(&[("system", "Ты помощник"), ("user", "Привет")]);
+        assert_eq!(render_messages(&messages), "system: Ты помощник\nuser: Привет");
+        assert_eq!(render_messages(&[]), "");
+    }
+
+    #[test]
+    fn test_message_windows() {
+        let c = compressor(0.3, None);
+        let filler = replies(10);
+        let messages = msgs(&filler.iter().map(|r| ("user", r.as_str())).collect::<Vec<_>>());
+        let cost = c.message_tokens("user", &filler[0]);
+        let windows = c.message_windows(&messages, cost * 3 + 2, cost + 2);
+        assert_eq!(windows.first(), Some(&(0, 3)));
+        assert_eq!(windows[1].0, 2);
+        assert_eq!(windows.last().unwrap().1, messages.len());
+        for pair in windows.windows(2) {
+            assert!(pair[1].0 > pair[0].0 && pair[1].0 < pair[0].1);
+        }
+        // Без перекрытия окна стыкуются
+        let plain = c.message_windows(&messages, cost * 3 + 2, 0);
+        assert!(plain.windows(2).all(|p| p[1].0 == p[0].1));
+        // Сообщение больше окна — окно из одного сообщения
+        assert_eq!(c.message_windows(&messages[..2], 1, 0), vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn test_custom_lexicon() {
+        let words = HashMap::from([("deploy".to_string(), 1.0), ("сервер упал".to_string(), 3.0)]);
+        let c = compressor(0.3, Some(words));
+        let text = "Погода хорошая. Вчера был deploy. Ночью сервер упал.";
+        assert_eq!(c.key_sentences(text), vec!["Ночью сервер упал", "Вчера был deploy"]);
+
+        // Встроенные слова больше не действуют
+        assert!(c.key_sentences("Есть важная проблема.").is_empty());
+
+        c.set_important_words(HashMap::from([("ИНВОЙС".to_string(), 1.0)])).unwrap();
+        assert_eq!(c.key_sentences("Пришёл инвойс."), vec!["Пришёл инвойс"]);
+        assert_eq!(c.get_important_words().get("инвойс"), Some(&1.0));
+    }
+
+    #[test]
+    fn test_truncate() {
+        let c = compressor(0.3, None);
+        let long_text = "a".repeat(1000);
+        let truncated = c.truncate_to_tokens(&long_text, 10);
+        assert!(truncated.len() < long_text.len());
+    }
+
+    #[test]
+    fn test_truncate_markdown() {
+        let c = compressor(0.3, None);
+        // Разрез внутри блока кода — блок закрывается
+        let text = format!("Пример:\n```python\n{}```\nКонец.", "print('hello world')\n".repeat(20));
+        let out = c.truncate_to_tokens(&text, 40);
+        assert!(c.estimate_tokens(&out) <= 40);
+        assert!(out.ends_with("print('hello world')\n```"));
+        assert_eq!(out.matches("```").count(), 2);
+
+        // Таблица режется только между строками
+        let table = format!("| a | b |\n|---|---|\n{}", "| long cell value | other |\n".repeat(10));
+        let out = c.truncate_to_tokens(&table, 30);
+        assert!(out.ends_with('|'));
+
+        // Обычный текст — по границе предложения
+        let prose = "Первое предложение. Второе предложение подлиннее. Третье предложение совсем длинное и не влезет.";
+        let out = c.truncate_to_tokens(prose, 25);
+        assert_eq!(out, "Первое предложение. Второе предложение подлиннее.");
+    }
+}
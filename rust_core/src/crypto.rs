@@ -0,0 +1,266 @@
+//! Шифрование памяти на диске
+//!
+//! - ChaCha20-Poly1305 (AEAD): подмена или порча данных обнаруживается при
+//!   расшифровке, а не превращается в мусор
+//! - Ключ — Argon2id из пароля и случайной соли; соль в заголовке каждой
+//!   записи, ключи для чужих солей выводятся один раз и кэшируются
+//! - Запись: MAGIC | соль (16) | nonce (12) | шифртекст с тегом; пространство
+//!   имён и ключ записи — associated data: запись, перенесённая в другую
+//!   строку хранилища, не расшифровывается. С cipher открытая запись — ошибка
+//! - Поток (JSONL-экспорт): MAGIC | соль, затем кадры длина (u32 LE) | nonce |
+//!   шифртекст — файл пишется и читается построчно; номер кадра — associated
+//!   data, последний кадр — число кадров: обрезанный, переставленный или
+//!   дописанный поток не читается
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use parking_lot::Mutex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+/// Заголовок зашифрованной записи и потока
+pub(crate) const MAGIC: &[u8; 6] = b"KRENC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+/// Кадр потока больше этого — повреждение, а не строка памяти
+const MAX_FRAME: usize = 256 << 20;
+
+type Salt = [u8; SALT_LEN];
+
+pub(crate) struct Cipher {
+    passphrase: String,
+    salt: Salt,
+    aead: ChaCha20Poly1305,
+    /// Ключи записей с другой солью (сохранены другим экземпляром)
+    foreign: Mutex<HashMap<Salt, ChaCha20Poly1305>>,
+}
+
+impl Cipher {
+    pub(crate) fn new(passphrase: &str) -> Result<Self, String> {
+        if passphrase.is_empty() {
+            return Err("Пароль шифрования памяти пустой".to_string());
+        }
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Ok(Self {
+            passphrase: passphrase.to_string(),
+            salt,
+            aead: derive(passphrase, &salt)?,
+            foreign: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Зашифрованная запись с заголовком; aad расшифровка должна получить ту же
+    pub(crate) fn seal(&self, plain: &[u8], aad: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + plain.len() + 16);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.salt);
+        self.seal_into(&mut out, plain, aad);
+        out
+    }
+
+    /// Запись после seal (с любой солью); ошибка — не тот пароль, другая aad или порча
+    pub(crate) fn open(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+        let body = data.strip_prefix(MAGIC.as_slice()).ok_or("нет заголовка шифрования")?;
+        if body.len() < SALT_LEN + NONCE_LEN {
+            return Err("зашифрованная запись обрезана".to_string());
+        }
+        let (salt, sealed) = body.split_at(SALT_LEN);
+        self.open_with(salt.try_into().expect("длина соли проверена"), sealed, aad)
+    }
+
+    /// Заголовок потока: MAGIC | соль
+    pub(crate) fn write_header(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&self.salt)
+    }
+
+    /// Кадр номер index (с нуля, по порядку)
+    pub(crate) fn write_frame(&self, out: &mut impl Write, index: u64, plain: &[u8]) -> io::Result<()> {
+        self.write_sealed(out, plain, &frame_aad(index, false))
+    }
+
+    /// Завершающий кадр после frames кадров; без него поток считается обрезанным
+    pub(crate) fn write_trailer(&self, out: &mut impl Write, frames: u64) -> io::Result<()> {
+        self.write_sealed(out, &frames.to_le_bytes(), &frame_aad(frames, true))
+    }
+
+    fn write_sealed(&self, out: &mut impl Write, plain: &[u8], aad: &[u8]) -> io::Result<()> {
+        let mut frame = Vec::with_capacity(NONCE_LEN + plain.len() + 16);
+        self.seal_into(&mut frame, plain, aad);
+        out.write_all(&(frame.len() as u32).to_le_bytes())?;
+        out.write_all(&frame)
+    }
+
+    /// Соль потока после MAGIC (MAGIC уже прочитан)
+    pub(crate) fn read_salt(input: &mut impl Read) -> io::Result<Salt> {
+        let mut salt = [0u8; SALT_LEN];
+        input.read_exact(&mut salt)?;
+        Ok(salt)
+    }
+
+    /// Кадр номер index; None — завершающий кадр с числом кадров index и концом
+    /// файла после него. Конец файла без завершающего кадра — ошибка
+    pub(crate) fn read_frame(&self, input: &mut impl Read, salt: &Salt, index: u64) -> Result<Option<Vec<u8>>, String> {
+        let mut len = [0u8; 4];
+        input.read_exact(&mut len).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => "поток обрезан: нет завершающего кадра".to_string(),
+            _ => e.to_string(),
+        })?;
+        let len = u32::from_le_bytes(len) as usize;
+        if !(NONCE_LEN..=MAX_FRAME).contains(&len) {
+            return Err(format!("кадр некорректной длины {}", len));
+        }
+        let mut frame = vec![0u8; len];
+        input.read_exact(&mut frame).map_err(|e| format!("кадр обрезан: {}", e))?;
+        if let Ok(plain) = self.open_with(salt, &frame, &frame_aad(index, false)) {
+            return Ok(Some(plain));
+        }
+        let count = self.open_with(salt, &frame, &frame_aad(index, true))?;
+        if count != index.to_le_bytes() {
+            return Err("завершающий кадр не совпадает с числом кадров".to_string());
+        }
+        match input.read(&mut [0u8; 1]).map_err(|e| e.to_string())? {
+            0 => Ok(None),
+            _ => Err("данные после завершающего кадра".to_string()),
+        }
+    }
+
+    fn seal_into(&self, out: &mut Vec<u8>, plain: &[u8], aad: &[u8]) {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .aead
+            .encrypt(&nonce, Payload { msg: plain, aad })
+            .expect("ChaCha20-Poly1305 шифрует любой объём памяти");
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+    }
+
+    fn open_with(&self, salt: &Salt, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+        if sealed.len() < NONCE_LEN {
+            return Err("зашифрованная запись обрезана".to_string());
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::from_slice(nonce);
+        let sealed = Payload { msg: sealed, aad };
+        let failed = |_| "не расшифровано: неверный пароль или данные повреждены".to_string();
+        if *salt == self.salt {
+            return self.aead.decrypt(nonce, sealed).map_err(failed);
+        }
+        let mut foreign = self.foreign.lock();
+        let aead = match foreign.get(salt) {
+            Some(aead) => aead,
+            None => foreign.entry(*salt).or_insert(derive(&self.passphrase, salt)?),
+        };
+        aead.decrypt(nonce, sealed).map_err(failed)
+    }
+}
+
+/// Запись хранилища namespace / key: с cipher — только зашифрованная для этой
+/// строки, без него — только открытая
+pub(crate) fn open_record<'a>(
+    cipher: Option<&Cipher>,
+    namespace: &str,
+    key: &str,
+    data: &'a [u8],
+) -> Result<Cow<'a, [u8]>, String> {
+    match (data.starts_with(MAGIC), cipher) {
+        (false, None) => Ok(Cow::Borrowed(data)),
+        (true, Some(cipher)) => cipher.open(data, &record_aad(namespace, key)).map(Cow::Owned),
+        (true, None) => Err("память зашифрована, нужен passphrase".to_string()),
+        (false, Some(_)) => Err("открытая запись в зашифрованной памяти".to_string()),
+    }
+}
+
+/// Запись для хранилища namespace / key: с cipher — зашифрованная
+pub(crate) fn seal_record(cipher: Option<&Cipher>, namespace: &str, key: &str, plain: Vec<u8>) -> Vec<u8> {
+    match cipher {
+        Some(cipher) => cipher.seal(&plain, &record_aad(namespace, key)),
+        None => plain,
+    }
+}
+
+fn record_aad(namespace: &str, key: &str) -> Vec<u8> {
+    [b"record\0", namespace.as_bytes(), b"\0", key.as_bytes()].concat()
+}
+
+fn frame_aad(index: u64, trailer: bool) -> Vec<u8> {
+    let kind: &[u8] = if trailer { b"trailer\0" } else { b"frame\0" };
+    [kind, &index.to_le_bytes()].concat()
+}
+
+fn derive(passphrase: &str, salt: &Salt) -> Result<ChaCha20Poly1305, String> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("ключ из пароля не выведен: {}", e))?;
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_and_stream_round_trip() {
+        let cipher = Cipher::new("секрет").unwrap();
+        let sealed = seal_record(Some(&cipher), "memory.facts", "имя", "Меня зовут Аня".as_bytes().to_vec());
+        assert!(sealed.starts_with(MAGIC) && !sealed.windows(6).any(|w| w == "Аня".as_bytes()));
+        assert_eq!(&*open_record(Some(&cipher), "memory.facts", "имя", &sealed).unwrap(), "Меня зовут Аня".as_bytes());
+        assert_eq!(&*open_record(None, "memory.facts", "имя", b"{}").unwrap(), b"{}");
+        assert!(open_record(None, "memory.facts", "имя", &sealed).unwrap_err().contains("passphrase"));
+
+        // Другой экземпляр (своя соль) с тем же паролем читает, с чужим — нет
+        let other = Cipher::new("секрет").unwrap();
+        assert_eq!(&*open_record(Some(&other), "memory.facts", "имя", &sealed).unwrap(), "Меня зовут Аня".as_bytes());
+        let wrong = Cipher::new("другой").unwrap();
+        assert!(open_record(Some(&wrong), "memory.facts", "имя", &sealed).unwrap_err().contains("неверный пароль"));
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open_record(Some(&cipher), "memory.facts", "имя", &tampered).is_err());
+    }
+
+    #[test]
+    fn test_records_bound_to_their_row() {
+        let cipher = Cipher::new("секрет").unwrap();
+        let sealed = seal_record(Some(&cipher), "memory.facts", "имя", "Аня".as_bytes().to_vec());
+        // Перенос в другую строку или пространство имён и открытая подмена не читаются
+        assert!(open_record(Some(&cipher), "memory.facts", "город", &sealed).is_err());
+        assert!(open_record(Some(&cipher), "memory.episodes", "имя", &sealed).is_err());
+        assert!(open_record(Some(&cipher), "memory.facts", "имя", "Ира".as_bytes()).unwrap_err().contains("открытая"));
+    }
+
+    #[test]
+    fn test_stream_rejects_truncated_and_reordered_frames() {
+        let cipher = Cipher::new("секрет").unwrap();
+        let mut header = Vec::new();
+        cipher.write_header(&mut header).unwrap();
+        let frame = |index: u64, plain: &[u8]| {
+            let mut out = Vec::new();
+            cipher.write_frame(&mut out, index, plain).unwrap();
+            out
+        };
+        let (first, second) = (frame(0, b"first"), frame(1, b"second"));
+        let mut trailer = Vec::new();
+        cipher.write_trailer(&mut trailer, 2).unwrap();
+        let read = |parts: &[&[u8]]| -> Result<Vec<Vec<u8>>, String> {
+            let stream = parts.concat();
+            let mut input = &stream[MAGIC.len()..];
+            let salt = Cipher::read_salt(&mut input).unwrap();
+            let mut frames = Vec::new();
+            while let Some(plain) = cipher.read_frame(&mut input, &salt, frames.len() as u64)? {
+                frames.push(plain);
+            }
+            Ok(frames)
+        };
+
+        assert_eq!(read(&[&header, &first, &second, &trailer]).unwrap(), vec![b"first".to_vec(), b"second".to_vec()]);
+        assert!(read(&[&header, &first, &second]).unwrap_err().contains("обрезан"));
+        assert!(read(&[&header, &first, &trailer]).is_err());
+        assert!(read(&[&header, &second, &first, &trailer]).is_err());
+        assert!(read(&[&header, &first, &second, &trailer, &first]).unwrap_err().contains("после завершающего"));
+    }
+}
@@ -0,0 +1,591 @@
+//! parse_datetime_ru — разбор выражений даты/времени на русском
+//!
+//! - Относительные: "через 15 минут", "через полчаса", "через пару дней",
+//!   "через две недели", "3 дня назад"
+//! - Дни: "сегодня", "завтра", "послезавтра", "вчера", "позавчера"
+//! - Дни недели: "в пятницу" (ближайшая), "в эту среду", "в следующий вторник"
+//!   (на следующей календарной неделе); "на следующей неделе", "в следующем месяце"
+//! - Даты: "15 марта [2025 года]", "15.03[.2025]", "2025-03-15"; прошедшая дата
+//!   без года — в следующем году
+//! - Время: "в 19:30", "в 7 вечера", "к 9 утра", "в 2 ночи", "в полдень",
+//!   "в полночь", "утром" / "днём" / "вечером" / "ночью"
+//! - Только время без даты: ближайший будущий момент ("в 7" в 15:00 → 19:00)
+//! - Дата без времени — 09:00; результат в часовом поясе now (RFC 3339)
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+
+/// Время для даты без явного времени ("завтра", "в пятницу")
+const DEFAULT_HOUR: u32 = 9;
+
+/// Найденное выражение; start/end — смещения в символах, text — охваченный фрагмент
+#[pyclass(frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct DateTimeMatch {
+    /// RFC 3339 в часовом поясе now
+    pub timestamp: String,
+    pub unix: i64,
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[pymethods]
+impl DateTimeMatch {
+    fn __repr__(&self) -> String {
+        format!("DateTimeMatch(timestamp={:?}, text={:?}, start={}, end={})", self.timestamp, self.text, self.start, self.end)
+    }
+}
+
+struct Token {
+    word: String,
+    start: usize,
+    end: usize,
+}
+
+/// Слова и числа; ":", "." и "-" между цифрами — часть токена ("19:30", "15.03.2025")
+fn tokens(chars: &[char]) -> Vec<Token> {
+    let digit_at = |i: usize| chars.get(i).is_some_and(|c| c.is_ascii_digit());
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_alphanumeric() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len()
+            && (chars[i].is_alphanumeric() || (matches!(chars[i], ':' | '.' | '-') && digit_at(i.wrapping_sub(1)) && digit_at(i + 1)))
+        {
+            i += 1;
+        }
+        let word = chars[start..i].iter().collect::<String>().to_lowercase().replace('ё', "е");
+        tokens.push(Token { word, start, end: i });
+    }
+    tokens
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Unit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+fn unit(word: &str) -> Option<Unit> {
+    let unit = match word {
+        "ч" => Unit::Hour,
+        "день" | "сутки" | "суток" => Unit::Day,
+        "лет" => Unit::Year,
+        w if w.starts_with("сек") => Unit::Second,
+        w if w.starts_with("мин") => Unit::Minute,
+        w if w.starts_with("час") => Unit::Hour,
+        w if w.starts_with("дн") => Unit::Day,
+        w if w.starts_with("недел") => Unit::Week,
+        w if w.starts_with("месяц") => Unit::Month,
+        "год" | "года" | "году" => Unit::Year,
+        _ => return None,
+    };
+    Some(unit)
+}
+
+fn number_word(word: &str) -> Option<u32> {
+    Some(match word {
+        "один" | "одна" | "одну" | "одного" | "одной" => 1,
+        "два" | "две" | "пару" | "пара" => 2,
+        "три" => 3,
+        "четыре" => 4,
+        "пять" => 5,
+        "шесть" => 6,
+        "семь" => 7,
+        "восемь" => 8,
+        "девять" => 9,
+        "десять" => 10,
+        "одиннадцать" => 11,
+        "двенадцать" => 12,
+        "тринадцать" => 13,
+        "четырнадцать" => 14,
+        "пятнадцать" => 15,
+        "шестнадцать" => 16,
+        "семнадцать" => 17,
+        "восемнадцать" => 18,
+        "девятнадцать" => 19,
+        "двадцать" => 20,
+        "тридцать" => 30,
+        "сорок" => 40,
+        "пятьдесят" => 50,
+        "шестьдесят" => 60,
+        _ => return None,
+    })
+}
+
+/// Количество с позиции i: "15", "пятнадцать", "двадцать пять", "полтора" → (значение, токенов)
+fn amount(tokens: &[Token], i: usize) -> Option<(f64, usize)> {
+    let word = tokens.get(i)?.word.as_str();
+    if let Ok(n) = word.parse::<u32>() {
+        return Some((n as f64, 1));
+    }
+    if matches!(word, "полтора" | "полторы") {
+        return Some((1.5, 1));
+    }
+    let n = number_word(word)?;
+    if n >= 20 && n % 10 == 0 {
+        if let Some(units) = tokens.get(i + 1).and_then(|t| number_word(&t.word)).filter(|u| *u < 10) {
+            return Some(((n + units) as f64, 2));
+        }
+    }
+    Some((n as f64, 1))
+}
+
+fn month(word: &str) -> Option<u32> {
+    const MONTHS: &[&str] = &["янв", "фев", "мар", "апр", "ма", "июн", "июл", "авг", "сен", "окт", "ноя", "дек"];
+    if word == "мая" || word == "май" {
+        return Some(5);
+    }
+    MONTHS.iter().position(|m| *m != "ма" && word.starts_with(m)).map(|p| p as u32 + 1)
+}
+
+fn weekday(word: &str) -> Option<i64> {
+    const DAYS: &[&str] = &["понедельник", "вторник", "сред", "четверг", "пятниц", "суббот", "воскресень"];
+    DAYS.iter().position(|d| word.starts_with(d)).map(|p| p as i64)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DayPart {
+    Morning,
+    Day,
+    Evening,
+    Night,
+}
+
+fn day_part(word: &str) -> Option<DayPart> {
+    match word {
+        "утра" | "утром" => Some(DayPart::Morning),
+        "дня" | "днем" => Some(DayPart::Day),
+        "вечера" | "вечером" => Some(DayPart::Evening),
+        "ночи" | "ночью" => Some(DayPart::Night),
+        _ => None,
+    }
+}
+
+impl DayPart {
+    fn default_hour(self) -> u32 {
+        match self {
+            Self::Morning => 9,
+            Self::Day => 13,
+            Self::Evening => 19,
+            Self::Night => 23,
+        }
+    }
+
+    /// "7 вечера" → 19, "2 ночи" → 2, "11 ночи" → 23, "12 дня" → 12
+    fn adjust(self, hour: u32) -> u32 {
+        match self {
+            Self::Day if (1..=6).contains(&hour) => hour + 12,
+            Self::Evening if hour < 12 => hour + 12,
+            Self::Night if hour == 12 => 0,
+            Self::Night if (7..12).contains(&hour) => hour + 12,
+            _ => hour,
+        }
+    }
+}
+
+/// Собранные компоненты выражения
+#[derive(Default)]
+struct Parsed {
+    shifted: bool,
+    seconds: i64,
+    months: i32,
+    date: Option<NaiveDate>,
+    time: Option<(u32, u32)>,
+    day_part: Option<DayPart>,
+    /// Первый и последний токен распознанных компонентов
+    span: Option<(usize, usize)>,
+}
+
+impl Parsed {
+    fn shift(&mut self, value: f64, unit: Unit) {
+        self.shifted = true;
+        let seconds = match unit {
+            Unit::Second => 1.0,
+            Unit::Minute => 60.0,
+            Unit::Hour => 3600.0,
+            Unit::Day => 86_400.0,
+            Unit::Week => 604_800.0,
+            Unit::Month => {
+                self.months += value.round() as i32;
+                return;
+            }
+            Unit::Year => {
+                self.months += (value * 12.0).round() as i32;
+                return;
+            }
+        };
+        self.seconds += (value * seconds).round() as i64;
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    today: NaiveDate,
+}
+
+impl Parser<'_> {
+    fn word(&self, i: usize) -> &str {
+        self.tokens.get(i).map_or("", |t| t.word.as_str())
+    }
+
+    /// Пробует компоненты по порядку; возвращает число поглощённых токенов
+    fn component(&self, i: usize, p: &mut Parsed) -> Option<usize> {
+        self.relative(i, p)
+            .or_else(|| self.ago(i, p))
+            .or_else(|| self.day_word(i, p))
+            .or_else(|| self.weekday(i, p))
+            .or_else(|| self.next_period(i, p))
+            .or_else(|| self.date(i, p))
+            .or_else(|| self.time(i, p))
+            .or_else(|| self.day_part_word(i, p))
+    }
+
+    /// "через 15 минут", "через час", "через полчаса"
+    fn relative(&self, i: usize, p: &mut Parsed) -> Option<usize> {
+        if self.word(i) != "через" {
+            return None;
+        }
+        if self.word(i + 1) == "полчаса" {
+            p.shift(30.0, Unit::Minute);
+            return Some(2);
+        }
+        if let Some(u) = unit(self.word(i + 1)) {
+            p.shift(1.0, u);
+            return Some(2);
+        }
+        let (value, n) = amount(self.tokens, i + 1)?;
+        let u = unit(self.word(i + 1 + n))?;
+        p.shift(value, u);
+        Some(n + 2)
+    }
+
+    /// "3 дня назад", "час назад"
+    fn ago(&self, i: usize, p: &mut Parsed) -> Option<usize> {
+        let (value, n) = amount(self.tokens, i).unwrap_or((1.0, 0));
+        let u = unit(self.word(i + n))?;
+        if self.word(i + n + 1) != "назад" {
+            return None;
+        }
+        p.shift(-value, u);
+        Some(n + 2)
+    }
+
+    fn day_word(&self, i: usize, p: &mut Parsed) -> Option<usize> {
+        let days = match self.word(i) {
+            "сегодня" => 0,
+            "завтра" => 1,
+            "послезавтра" => 2,
+            "вчера" => -1,
+            "позавчера" => -2,
+            _ => return None,
+        };
+        p.date = Some(self.today + Duration::days(days));
+        Some(1)
+    }
+
+    /// "[в|во] [следующий|этот|ближайший] пятницу"
+    fn weekday(&self, i: usize, p: &mut Parsed) -> Option<usize> {
+        let mut j = i;
+        if matches!(self.word(j), "в" | "во") {
+            j += 1;
+        }
+        let modifier = self.word(j);
+        let next = modifier.starts_with("следующ");
+        let this = matches!(modifier, "этот" | "эту" | "это" | "эти");
+        if next || this || modifier.starts_with("ближайш") {
+            j += 1;
+        }
+        let target = weekday(self.word(j))?;
+        let current = self.today.weekday().num_days_from_monday() as i64;
+        let days = if next {
+            7 - current + target
+        } else if this {
+            (target - current).rem_euclid(7)
+        } else {
+            match (target - current).rem_euclid(7) {
+                0 => 7,
+                d => d,
+            }
+        };
+        p.date = Some(self.today + Duration::days(days));
+        Some(j + 1 - i)
+    }
+
+    /// "на следующей неделе" (понедельник), "в следующем месяце" (1-е число)
+    fn next_period(&self, i: usize, p: &mut Parsed) -> Option<usize> {
+        if !matches!(self.word(i), "на" | "в") || !self.word(i + 1).starts_with("следующ") {
+            return None;
+        }
+        let word = self.word(i + 2);
+        if word.starts_with("недел") {
+            let current = self.today.weekday().num_days_from_monday() as i64;
+            p.date = Some(self.today + Duration::days(7 - current));
+        } else if word.starts_with("месяц") {
+            let first = self.today.with_day(1)?;
+            p.date = Some(first.checked_add_months(Months::new(1))?);
+        } else {
+            return None;
+        }
+        Some(3)
+    }
+
+    /// "15 марта [2025 [года]]", "15.03[.2025]", "2025-03-15"
+    fn date(&self, i: usize, p: &mut Parsed) -> Option<usize> {
+        let word = self.word(i);
+        let dashed: Vec<&str> = word.split('-').collect();
+        let (date, n) = if dashed.len() == 3 && dashed[0].len() == 4 {
+            let date = NaiveDate::from_ymd_opt(dashed[0].parse().ok()?, dashed[1].parse().ok()?, dashed[2].parse().ok()?)?;
+            (date, 1)
+        } else if word.contains('.') {
+            let parts: Vec<&str> = word.split('.').collect();
+            if !(2..=3).contains(&parts.len()) || parts[1].len() != 2 {
+                return None;
+            }
+            let (day, month) = (parts[0].parse().ok()?, parts[1].parse().ok()?);
+            match parts.get(2) {
+                Some(year) => {
+                    let year: i32 = year.parse().ok()?;
+                    let year = if year < 100 { 2000 + year } else { year };
+                    (NaiveDate::from_ymd_opt(year, month, day)?, 1)
+                }
+                None => (self.upcoming(day, month)?, 1),
+            }
+        } else {
+            let day: u32 = word.parse().ok()?;
+            let month = month(self.word(i + 1))?;
+            let year = self.word(i + 2);
+            if year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()) {
+                let date = NaiveDate::from_ymd_opt(year.parse().ok()?, month, day)?;
+                let n = if matches!(self.word(i + 3), "года" | "г" | "год") { 4 } else { 3 };
+                (date, n)
+            } else {
+                (self.upcoming(day, month)?, 2)
+            }
+        };
+        p.date = Some(date);
+        Some(n)
+    }
+
+    /// Дата без года: в этом году, а если уже прошла — в следующем
+    fn upcoming(&self, day: u32, month: u32) -> Option<NaiveDate> {
+        let date = NaiveDate::from_ymd_opt(self.today.year(), month, day)?;
+        if date < self.today {
+            NaiveDate::from_ymd_opt(self.today.year() + 1, month, day)
+        } else {
+            Some(date)
+        }
+    }
+
+    /// "в 19:30", "в 7 [часов] [вечера]", "к девяти утра" — без "в"/"к" нужно двоеточие,
+    /// "часов" или часть суток, чтобы не принимать за время любое число
+    fn time(&self, i: usize, p: &mut Parsed) -> Option<usize> {
+        let mut j = i;
+        let prefixed = matches!(self.word(j), "в" | "к" | "около");
+        if prefixed {
+            j += 1;
+        }
+        match self.word(j) {
+            "полдень" => {
+                p.time = Some((12, 0));
+                return Some(j + 1 - i);
+            }
+            "полночь" => {
+                p.time = Some((0, 0));
+                return Some(j + 1 - i);
+            }
+            _ => {}
+        }
+        let word = self.word(j);
+        let (hour, minute, colon, n) = if let Some((h, m)) = word.split_once(':') {
+            (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?, true, 1)
+        } else {
+            let (value, n) = amount(self.tokens, j)?;
+            if value.fract() != 0.0 {
+                return None;
+            }
+            (value as u32, 0, false, n)
+        };
+        j += n;
+        let hours_word = self.word(j) == "ч" || self.word(j).starts_with("час");
+        if hours_word {
+            j += 1;
+        }
+        let part = day_part(self.word(j));
+        if part.is_some() {
+            j += 1;
+        }
+        // После числа идёт другая единица ("в 5 минут") — это не время
+        if !hours_word && unit(self.word(j)).is_some_and(|u| u != Unit::Hour) && part.is_none() {
+            return None;
+        }
+        if !(colon || hours_word || part.is_some() || prefixed) || hour > 24 || minute > 59 {
+            return None;
+        }
+        let hour = part.map_or(hour, |part| part.adjust(hour)) % 24;
+        p.time = Some((hour, minute));
+        if part.is_some() {
+            p.day_part = part;
+        }
+        Some(j - i)
+    }
+
+    fn day_part_word(&self, i: usize, p: &mut Parsed) -> Option<usize> {
+        // Родительный падеж ("до вечера") без числа временем не считается
+        let part = match self.word(i) {
+            "утром" => DayPart::Morning,
+            "днем" => DayPart::Day,
+            "вечером" => DayPart::Evening,
+            "ночью" => DayPart::Night,
+            _ => return None,
+        };
+        p.day_part = Some(part);
+        Some(1)
+    }
+}
+
+fn parse_now(now: Option<&str>) -> PyResult<DateTime<FixedOffset>> {
+    let Some(now) = now else {
+        return Ok(Utc::now().fixed_offset());
+    };
+    if let Ok(dt) = DateTime::parse_from_rfc3339(now) {
+        return Ok(dt);
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(now, f).ok())
+        .map(|dt| dt.and_utc().fixed_offset())
+        .ok_or_else(|| PyValueError::new_err(format!("Некорректное значение now: {} (ожидается ISO 8601)", now)))
+}
+
+pub(crate) fn parse(text: &str, now: DateTime<FixedOffset>) -> Option<DateTimeMatch> {
+    let chars: Vec<char> = text.chars().collect();
+    let tokens = tokens(&chars);
+    let parser = Parser { tokens: &tokens, today: now.date_naive() };
+    let mut p = Parsed::default();
+    let mut i = 0;
+    while i < tokens.len() {
+        match parser.component(i, &mut p) {
+            Some(n) => {
+                let first = p.span.map_or(i, |(s, _)| s);
+                p.span = Some((first, i + n - 1));
+                i += n;
+            }
+            None => i += 1,
+        }
+    }
+    let (first, last) = p.span?;
+
+    let base = if p.months >= 0 {
+        now.checked_add_months(Months::new(p.months as u32))?
+    } else {
+        now.checked_sub_months(Months::new(p.months.unsigned_abs()))?
+    } + Duration::seconds(p.seconds);
+    let date = p.date.unwrap_or_else(|| base.date_naive());
+    let (hour, minute) = match (p.time, p.day_part) {
+        (Some(time), _) => time,
+        (None, Some(part)) => (part.default_hour(), 0),
+        (None, None) if p.shifted && p.date.is_none() => (base.hour(), base.minute()),
+        (None, None) => (DEFAULT_HOUR, 0),
+    };
+    let second = if p.shifted && p.time.is_none() && p.day_part.is_none() && p.date.is_none() { base.second() } else { 0 };
+    let naive = date.and_time(NaiveTime::from_hms_opt(hour, minute, second)?);
+    let mut result = now.offset().from_local_datetime(&naive).single()?;
+
+    // Только время: ближайший будущий момент ("в 7" днём — это 19:00)
+    if p.date.is_none() && !p.shifted && result <= now {
+        let ambiguous = p.day_part.is_none() && (1..12).contains(&hour);
+        result += if ambiguous && result + Duration::hours(12) > now { Duration::hours(12) } else { Duration::days(1) };
+    }
+
+    let (start, end) = (tokens[first].start, tokens[last].end);
+    Some(DateTimeMatch {
+        timestamp: result.to_rfc3339(),
+        unix: result.timestamp(),
+        text: chars[start..end].iter().collect(),
+        start,
+        end,
+    })
+}
+
+/// Первое выражение даты/времени в тексте → DateTimeMatch или None;
+/// now — ISO 8601 (по умолчанию текущее время UTC), его часовой пояс сохраняется
+#[pyfunction]
+#[pyo3(signature = (text, now=None))]
+pub fn parse_datetime_ru(text: &str, now: Option<&str>) -> PyResult<Option<DateTimeMatch>> {
+    Ok(parse(text, parse_now(now)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Среда, 12 марта 2025, 15:00 МСК
+    fn at(text: &str) -> Option<String> {
+        let now = DateTime::parse_from_rfc3339("2025-03-12T15:00:00+03:00").unwrap();
+        parse(text, now).map(|m| m.timestamp)
+    }
+
+    #[test]
+    fn test_relative_and_day_words() {
+        assert_eq!(at("через 15 минут").unwrap(), "2025-03-12T15:15:00+03:00");
+        assert_eq!(at("через полчаса").unwrap(), "2025-03-12T15:30:00+03:00");
+        assert_eq!(at("через два часа").unwrap(), "2025-03-12T17:00:00+03:00");
+        assert_eq!(at("через двадцать пять минут").unwrap(), "2025-03-12T15:25:00+03:00");
+        assert_eq!(at("через пару дней в 10 утра").unwrap(), "2025-03-14T10:00:00+03:00");
+        assert_eq!(at("через месяц").unwrap(), "2025-04-12T15:00:00+03:00");
+        assert_eq!(at("3 дня назад").unwrap(), "2025-03-09T15:00:00+03:00");
+        assert_eq!(at("завтра в 7 вечера").unwrap(), "2025-03-13T19:00:00+03:00");
+        assert_eq!(at("послезавтра вечером").unwrap(), "2025-03-14T19:00:00+03:00");
+        assert_eq!(at("завтра").unwrap(), "2025-03-13T09:00:00+03:00");
+    }
+
+    #[test]
+    fn test_weekdays_and_dates() {
+        assert_eq!(at("в пятницу").unwrap(), "2025-03-14T09:00:00+03:00");
+        assert_eq!(at("в среду").unwrap(), "2025-03-19T09:00:00+03:00");
+        assert_eq!(at("в следующий вторник в 19:30").unwrap(), "2025-03-18T19:30:00+03:00");
+        assert_eq!(at("на следующей неделе").unwrap(), "2025-03-17T09:00:00+03:00");
+        assert_eq!(at("5 апреля в 10:30").unwrap(), "2025-04-05T10:30:00+03:00");
+        assert_eq!(at("1 марта").unwrap(), "2026-03-01T09:00:00+03:00");
+        assert_eq!(at("15 мая 2026 года").unwrap(), "2026-05-15T09:00:00+03:00");
+        assert_eq!(at("встреча 20.03.2025 в 14:00").unwrap(), "2025-03-20T14:00:00+03:00");
+        assert_eq!(at("дедлайн 2025-04-01").unwrap(), "2025-04-01T09:00:00+03:00");
+    }
+
+    #[test]
+    fn test_time_only_picks_nearest_future() {
+        assert_eq!(at("в 7").unwrap(), "2025-03-12T19:00:00+03:00");
+        assert_eq!(at("в 9 утра").unwrap(), "2025-03-13T09:00:00+03:00");
+        assert_eq!(at("к 18:45").unwrap(), "2025-03-12T18:45:00+03:00");
+        assert_eq!(at("в 2 ночи").unwrap(), "2025-03-13T02:00:00+03:00");
+        assert_eq!(at("в полночь").unwrap(), "2025-03-13T00:00:00+03:00");
+        assert_eq!(at("в 3 дня").unwrap(), "2025-03-13T15:00:00+03:00");
+    }
+
+    #[test]
+    fn test_span_and_no_match() {
+        let now = DateTime::parse_from_rfc3339("2025-03-12T15:00:00+03:00").unwrap();
+        let m = parse("Напомни завтра в 7 вечера позвонить маме", now).unwrap();
+        assert_eq!((m.start, m.end, m.text.as_str()), (8, 25, "завтра в 7 вечера"));
+        assert_eq!(m.unix, 1741881600);
+        assert!(at("купи 7 яблок и версию 2.5").is_none());
+        assert!(at("просто текст").is_none());
+        assert!(parse_datetime_ru("завтра", Some("вчера")).is_err());
+        assert_eq!(
+            parse_datetime_ru("через час", Some("2025-03-12 15:00")).unwrap().unwrap().timestamp,
+            "2025-03-12T16:00:00+00:00"
+        );
+    }
+}
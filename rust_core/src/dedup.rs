@@ -0,0 +1,239 @@
+//! Deduplicator — поиск почти-дубликатов текстов
+//!
+//! - Шинглы: n-граммы нормализованных слов (нижний регистр, ё→е, без пунктуации)
+//! - MinHash: num_perm минимумов xxh3 с разными seed → оценка сходства Жаккара
+//! - SimHash: 64-битный отпечаток, близость — расстояние Хэмминга
+//! - LSH: сигнатура режется на bands полос, кандидаты — тексты с общей полосой;
+//!   find_duplicates сравнивает только кандидатов, а не все пары
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use xxhash_rust::xxh3::{xxh3_64, xxh3_64_with_seed};
+
+const DEFAULT_THRESHOLD: f64 = 0.8;
+
+fn normalized_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase().replace('ё', "е"))
+        .collect()
+}
+
+/// Хэши шинглов; текст короче shingle_size — один шингл из всех слов
+fn shingles(text: &str, size: usize) -> HashSet<u64> {
+    let words = normalized_words(text);
+    if words.is_empty() {
+        return HashSet::new();
+    }
+    if words.len() <= size {
+        return HashSet::from([xxh3_64(words.join(" ").as_bytes())]);
+    }
+    words.windows(size).map(|w| xxh3_64(w.join(" ").as_bytes())).collect()
+}
+
+/// SimHash по шинглам из одного и двух слов
+fn simhash(text: &str) -> u64 {
+    let words = normalized_words(text);
+    let mut weights = [0i64; 64];
+    let features = words
+        .iter()
+        .cloned()
+        .chain(words.windows(2).map(|w| w.join(" ")));
+    for feature in features {
+        let h = xxh3_64(feature.as_bytes());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if h >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, &w)| w > 0)
+        .fold(0u64, |acc, (bit, _)| acc | 1 << bit)
+}
+
+#[pyclass(frozen)]
+pub struct Deduplicator {
+    num_perm: usize,
+    bands: usize,
+    shingle_size: usize,
+}
+
+#[pymethods]
+impl Deduplicator {
+    #[new]
+    #[pyo3(signature = (num_perm=128, bands=32, shingle_size=3))]
+    fn new(num_perm: usize, bands: usize, shingle_size: usize) -> PyResult<Self> {
+        if num_perm == 0 || bands == 0 || !num_perm.is_multiple_of(bands) {
+            return Err(PyValueError::new_err("num_perm должен быть > 0 и делиться на bands"));
+        }
+        if shingle_size == 0 {
+            return Err(PyValueError::new_err("shingle_size должен быть > 0"));
+        }
+        Ok(Self { num_perm, bands, shingle_size })
+    }
+
+    /// MinHash-сигнатура (num_perm значений); пустой текст — пустая сигнатура
+    fn minhash(&self, text: &str) -> Vec<u64> {
+        self.signature(text)
+    }
+
+    #[pyo3(name = "simhash")]
+    fn py_simhash(&self, text: &str) -> u64 {
+        simhash(text)
+    }
+
+    /// Число различающихся бит двух SimHash
+    #[staticmethod]
+    fn hamming_distance(a: u64, b: u64) -> u32 {
+        (a ^ b).count_ones()
+    }
+
+    /// Оценка сходства Жаккара по MinHash (0.0–1.0)
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        estimate(&self.signature(a), &self.signature(b))
+    }
+
+    #[pyo3(signature = (a, b, threshold=DEFAULT_THRESHOLD))]
+    fn is_duplicate(&self, a: &str, b: &str, threshold: f64) -> bool {
+        self.similarity(a, b) >= threshold
+    }
+
+    /// Пары почти-дубликатов корпуса → [(i, j, similarity)], i < j
+    #[pyo3(signature = (corpus, threshold=DEFAULT_THRESHOLD))]
+    fn find_duplicates(&self, py: Python<'_>, corpus: Vec<String>, threshold: f64) -> Vec<(usize, usize, f64)> {
+        py.allow_threads(|| self.duplicates(&corpus, threshold))
+    }
+
+    /// Индексы текстов, оставшихся после удаления дубликатов (первое вхождение)
+    #[pyo3(signature = (corpus, threshold=DEFAULT_THRESHOLD))]
+    fn unique(&self, py: Python<'_>, corpus: Vec<String>, threshold: f64) -> Vec<usize> {
+        py.allow_threads(|| self.unique_indices(&corpus, threshold))
+    }
+}
+
+// ── MinHash и LSH ──
+
+fn estimate(a: &[u64], b: &[u64]) -> f64 {
+    if a.is_empty() || b.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+}
+
+impl Deduplicator {
+    fn signature(&self, text: &str) -> Vec<u64> {
+        let shingles = shingles(text, self.shingle_size);
+        if shingles.is_empty() {
+            return Vec::new();
+        }
+        (0..self.num_perm as u64)
+            .map(|seed| {
+                shingles
+                    .iter()
+                    .map(|h| xxh3_64_with_seed(&h.to_le_bytes(), seed))
+                    .min()
+                    .unwrap_or(u64::MAX)
+            })
+            .collect()
+    }
+
+    fn duplicates(&self, corpus: &[String], threshold: f64) -> Vec<(usize, usize, f64)> {
+        let signatures: Vec<Vec<u64>> = corpus.par_iter().map(|t| self.signature(t)).collect();
+        let rows = self.num_perm / self.bands;
+
+        let mut candidates: HashSet<(usize, usize)> = HashSet::new();
+        for band in 0..self.bands {
+            let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+            for (i, sig) in signatures.iter().enumerate() {
+                if sig.is_empty() {
+                    continue;
+                }
+                let bytes: Vec<u8> = sig[band * rows..(band + 1) * rows]
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect();
+                buckets.entry(xxh3_64(&bytes)).or_default().push(i);
+            }
+            for bucket in buckets.values() {
+                for (k, &i) in bucket.iter().enumerate() {
+                    for &j in &bucket[k + 1..] {
+                        candidates.insert((i, j));
+                    }
+                }
+            }
+        }
+
+        let mut pairs: Vec<(usize, usize, f64)> = candidates
+            .into_iter()
+            .map(|(i, j)| (i, j, estimate(&signatures[i], &signatures[j])))
+            .filter(|&(_, _, sim)| sim >= threshold)
+            .collect();
+        pairs.sort_by_key(|&(i, j, _)| (i, j));
+        pairs
+    }
+
+    fn unique_indices(&self, corpus: &[String], threshold: f64) -> Vec<usize> {
+        let mut dropped = HashSet::new();
+        for (i, j, _) in self.duplicates(corpus, threshold) {
+            if !dropped.contains(&i) {
+                dropped.insert(j);
+            }
+        }
+        (0..corpus.len()).filter(|i| !dropped.contains(i)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dedup() -> Deduplicator {
+        Deduplicator::new(128, 32, 2).unwrap()
+    }
+
+    #[test]
+    fn test_similarity_ignores_case_and_punctuation() {
+        let d = dedup();
+        let a = "Я планирую переезд в Москву весной, нужно найти квартиру";
+        assert!((d.similarity(a, "я планирую переезд в москву весной нужно найти квартиру!") - 1.0).abs() < 1e-9);
+        assert!(d.is_duplicate(a, "Я планирую переезд в Москву весной, нужно найти квартиру поближе", 0.6));
+        assert!(!d.is_duplicate(a, "Сегодня отличная погода для прогулки в парке", 0.3));
+        assert_eq!(d.similarity("", a), 0.0);
+    }
+
+    #[test]
+    fn test_simhash_distance() {
+        let a = simhash("the quick brown fox jumps over the lazy dog near the river bank");
+        let b = simhash("the quick brown fox jumps over the lazy dog near the river");
+        let c = simhash("совершенно другой текст про погоду и планы на выходные");
+        assert!(Deduplicator::hamming_distance(a, b) < Deduplicator::hamming_distance(a, c));
+    }
+
+    #[test]
+    fn test_find_duplicates_with_lsh() {
+        let corpus: Vec<String> = [
+            "напомни купить молоко и хлеб вечером после работы",
+            "сегодня обсуждали архитектуру нового сервиса поиска",
+            "Напомни купить молоко и хлеб вечером после работы!",
+            "обсуждали архитектуру нового сервиса поиска сегодня утром",
+            "",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let d = dedup();
+        let pairs = d.duplicates(&corpus, 0.9);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].0, pairs[0].1), (0, 2));
+        assert_eq!(d.unique_indices(&corpus, 0.9), vec![0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn test_rejects_bad_bands() {
+        assert!(Deduplicator::new(100, 32, 3).is_err());
+        assert!(Deduplicator::new(64, 16, 0).is_err());
+    }
+}
@@ -1,140 +1,446 @@
-//! EmbeddingCache — lock-free кэш эмбеддингов
-//!
-//! Оптимизации vs Python fallback:
-//! - DashMap: конкурентный доступ без GIL
-//! - AtomicU64: lock-free счётчики hits/misses
-//! - xxh3: ~10x быстрее md5 для хэширования текста
-//! - LRU eviction: удаляет 10% наименее используемых
-
-use pyo3::prelude::*;
-use dashmap::DashMap;
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
-use xxhash_rust::xxh3::xxh3_64;
-
-#[inline]
-fn text_hash(text: &str) -> String {
-    format!("{:016x}", xxh3_64(text.as_bytes()))
-}
-
-#[pyclass(frozen)]
-pub struct EmbeddingCache {
-    cache: DashMap<String, Vec<f32>>,
-    access_count: DashMap<String, u64>,
-    max_size: usize,
-    cache_path: PathBuf,
-    hits: AtomicU64,
-    misses: AtomicU64,
-}
-
-#[pymethods]
-impl EmbeddingCache {
-    #[new]
-    #[pyo3(signature = (cache_dir, max_size=10000))]
-    fn new(cache_dir: &str, max_size: usize) -> PyResult<Self> {
-        let dir = PathBuf::from(cache_dir);
-        std::fs::create_dir_all(&dir).ok();
-
-        let cache = Self {
-            cache: DashMap::new(),
-            access_count: DashMap::new(),
-            max_size,
-            cache_path: dir.join("embedding_cache.json"),
-            hits: AtomicU64::new(0),
-            misses: AtomicU64::new(0),
-        };
-
-        cache.load_from_disk();
-        Ok(cache)
-    }
-
-    fn get(&self, text: &str) -> Option<Vec<f32>> {
-        let h = text_hash(text);
-        if let Some(entry) = self.cache.get(&h) {
-            self.access_count
-                .entry(h)
-                .and_modify(|c| *c += 1)
-                .or_insert(1);
-            self.hits.fetch_add(1, Ordering::Relaxed);
-            Some(entry.value().clone())
-        } else {
-            self.misses.fetch_add(1, Ordering::Relaxed);
-            None
-        }
-    }
-
-    fn put(&self, text: &str, embedding: Vec<f32>) {
-        let h = text_hash(text);
-        if self.cache.len() >= self.max_size {
-            self.evict_lru();
-        }
-        self.cache.insert(h.clone(), embedding);
-        self.access_count.insert(h, 1);
-    }
-
-    fn contains(&self, text: &str) -> bool {
-        let h = text_hash(text);
-        self.cache.contains_key(&h)
-    }
-
-    #[pyo3(name = "len")]
-    fn py_len(&self) -> usize {
-        self.cache.len()
-    }
-
-    fn get_stats(&self) -> (usize, u64, u64) {
-        (
-            self.cache.len(),
-            self.hits.load(Ordering::Relaxed),
-            self.misses.load(Ordering::Relaxed),
-        )
-    }
-
-    fn save(&self) {
-        let map: HashMap<String, Vec<f32>> = self.cache
-            .iter()
-            .map(|r| (r.key().clone(), r.value().clone()))
-            .collect();
-        if let Ok(data) = serde_json::to_string(&map) {
-            let _ = std::fs::write(&self.cache_path, data);
-        }
-    }
-
-    fn clear(&self) {
-        self.cache.clear();
-        self.access_count.clear();
-        self.hits.store(0, Ordering::Relaxed);
-        self.misses.store(0, Ordering::Relaxed);
-    }
-}
-
-impl EmbeddingCache {
-    fn load_from_disk(&self) {
-        if !self.cache_path.exists() {
-            return;
-        }
-        if let Ok(data) = std::fs::read_to_string(&self.cache_path) {
-            if let Ok(map) = serde_json::from_str::<HashMap<String, Vec<f32>>>(&data) {
-                for (k, v) in map {
-                    self.cache.insert(k.clone(), v);
-                    self.access_count.insert(k, 0);
-                }
-            }
-        }
-    }
-
-    fn evict_lru(&self) {
-        let evict_count = std::cmp::max(1, self.max_size / 10);
-        let mut entries: Vec<(String, u64)> = self.access_count
-            .iter()
-            .map(|r| (r.key().clone(), *r.value()))
-            .collect();
-        entries.sort_by_key(|(_, count)| *count);
-
-        for (key, _) in entries.into_iter().take(evict_count) {
-            self.cache.remove(&key);
-            self.access_count.remove(&key);
-        }
-    }
-}
+//! EmbeddingCache — lock-free кэш эмбеддингов
+//!
+//! Оптимизации vs Python fallback:
+//! - DashMap: конкурентный доступ без GIL
+//! - AtomicU64: lock-free счётчики hits/misses
+//! - xxh3: ~10x быстрее md5 для хэширования текста (нормализованного: normalize)
+//! - LRU eviction: удаляет 10% наименее используемых
+//! - События (set_event_bus): cache_eviction
+//! - put с пустым вектором или другой размерностью → CacheError; сбой save →
+//!   PersistenceError; нечитаемый кэш — RuntimeWarning и пустой кэш
+//! - Хранение: Storage (SQLite, пространство embeddings, вектор — f32 LE) в cache_dir
+//!   или общий storage=; прежний embedding_cache.json импортируется при первой загрузке
+//! - shared=True (несколько процессов): put пишет сразу в хранилище, промах
+//!   в памяти ищется в хранилище, вытеснение удаляет записи и оттуда
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use xxhash_rust::xxh3::xxh3_64;
+use parking_lot::{Mutex, RwLock};
+use serde_json::json;
+
+use crate::async_io;
+use crate::storage::{self, Records, Storage, Store};
+use crate::config::{self, CoreConfig};
+use crate::errors::{self, CacheError, PersistenceError};
+use crate::event_bus::{self, EventBus, SharedBus};
+use crate::metrics;
+use crate::text_normalizer::normalized;
+
+#[inline]
+/// Ключ кэша: хеш нормализованного текста — невидимые символы, двойники
+/// и лишние пробелы не порождают новых записей
+fn text_hash(text: &str) -> String {
+    format!("{:016x}", xxh3_64(normalized(text).as_bytes()))
+}
+
+const NAMESPACE: &str = "embeddings";
+
+/// Вектор для хранилища: f32 little-endian
+fn encode(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+/// None — пустая или обрезанная запись
+fn decode(data: &[u8]) -> Option<Vec<f32>> {
+    if data.is_empty() || !data.len().is_multiple_of(4) {
+        return None;
+    }
+    Some(data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+}
+
+#[pyclass(frozen)]
+pub struct EmbeddingCache {
+    cache: DashMap<String, Vec<f32>>,
+    access_count: DashMap<String, u64>,
+    max_size: usize,
+    store: Arc<Store>,
+    /// Прежний JSON-файл кэша (импорт при пустом хранилище)
+    legacy_path: Option<PathBuf>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bus: RwLock<Option<SharedBus>>,
+    /// Вытеснение выполняет один поток за раз
+    evicting: Mutex<()>,
+    /// Записи общие для процессов с тем же хранилищем
+    shared: bool,
+}
+
+impl EmbeddingCache {
+    #[cfg(test)]
+    pub(crate) fn new(cache_dir: &str, max_size: usize) -> PyResult<Self> {
+        let dir = PathBuf::from(cache_dir);
+        let store = storage::open(&dir).map_err(PersistenceError::new_err)?;
+        Ok(Self::with_store(store, Some(dir), max_size, false))
+    }
+
+    fn with_store(store: Arc<Store>, legacy_dir: Option<PathBuf>, max_size: usize, shared: bool) -> Self {
+        let cache = Self {
+            cache: DashMap::new(),
+            access_count: DashMap::new(),
+            max_size,
+            store,
+            legacy_path: legacy_dir.map(|dir| dir.join("embedding_cache.json")),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            bus: RwLock::new(None),
+            evicting: Mutex::new(()),
+            shared,
+        };
+
+        cache.load_from_disk();
+        cache
+    }
+}
+
+#[pymethods]
+impl EmbeddingCache {
+    /// cache_dir можно опустить, если config задаёт cache.dir или data_dir;
+    /// storage — общее хранилище вместо kristina.db в cache_dir;
+    /// shared=True — записи общие для процессов (runtime.shared)
+    #[new]
+    #[pyo3(signature = (cache_dir=None, max_size=None, *, storage=None, shared=None, config=None))]
+    fn py_new(
+        cache_dir: Option<String>,
+        max_size: Option<usize>,
+        storage: Option<PyRef<'_, Storage>>,
+        shared: Option<bool>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let config = config::settings(config);
+        let dir = cache_dir.or_else(|| config.cache_dir()).map(PathBuf::from);
+        let store = match (storage, &dir) {
+            (Some(storage), _) => storage.shared(),
+            (None, Some(dir)) => storage::open(dir).map_err(PersistenceError::new_err)?,
+            (None, None) => {
+                return Err(PyValueError::new_err(
+                    "Не задан cache_dir: укажите его, storage или cache.dir / data_dir в CoreConfig",
+                ))
+            }
+        };
+        Ok(Self::with_store(
+            store,
+            dir,
+            max_size.unwrap_or(config.cache.max_size),
+            shared.unwrap_or(config.runtime.shared),
+        ))
+    }
+
+    fn get(&self, text: &str) -> Option<Vec<f32>> {
+        let h = text_hash(text);
+        if let Some(entry) = self.cache.get(&h) {
+            self.access_count
+                .entry(h)
+                .and_modify(|c| *c += 1)
+                .or_insert(1);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            metrics::inc("embedding_cache_hits_total", 1);
+            Some(entry.value().clone())
+        } else if let Some(embedding) = self.fetch_shared(&h) {
+            // Записал другой процесс
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            metrics::inc("embedding_cache_hits_total", 1);
+            self.insert(h, embedding.clone());
+            Some(embedding)
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            metrics::inc("embedding_cache_misses_total", 1);
+            None
+        }
+    }
+
+    fn put(&self, text: &str, embedding: Vec<f32>) -> PyResult<()> {
+        if embedding.is_empty() {
+            return Err(CacheError::new_err("Пустой эмбеддинг"));
+        }
+        // Размерность задаёт первая запись: смесь моделей ломает косинусное сходство
+        let dim = self.cache.iter().next().map(|e| e.value().len());
+        if let Some(dim) = dim.filter(|&dim| dim != embedding.len()) {
+            return Err(CacheError::new_err(format!(
+                "Размерность эмбеддинга {} не совпадает с размерностью кэша {}",
+                embedding.len(),
+                dim
+            )));
+        }
+        let h = text_hash(text);
+        if self.shared {
+            self.store
+                .put(NAMESPACE, &h, &encode(&embedding))
+                .map_err(|e| self.store.error("записать эмбеддинг в", e))?;
+        }
+        self.insert(h, embedding);
+        Ok(())
+    }
+
+    fn contains(&self, text: &str) -> bool {
+        let h = text_hash(text);
+        self.cache.contains_key(&h) || self.fetch_shared(&h).is_some()
+    }
+
+    #[pyo3(name = "len")]
+    fn py_len(&self) -> usize {
+        self.cache.len()
+    }
+
+    fn get_stats(&self) -> (usize, u64, u64) {
+        (
+            self.cache.len(),
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    pub(crate) fn save(&self) -> PyResult<()> {
+        let started = Instant::now();
+        let records: Records = self.cache.iter().map(|r| (r.key().clone(), encode(r.value()))).collect();
+        let count = records.len();
+        // В режиме shared замена стёрла бы записи других процессов
+        let result = if self.shared {
+            self.store.append(NAMESPACE, &records)
+        } else {
+            self.store.replace(&[(NAMESPACE, records)])
+        };
+        result.map_err(|e| self.store.error("сохранить кэш эмбеддингов в", e))?;
+        log::info!(
+            "Кэш эмбеддингов сохранён в {}: записей {} за {:.1} мс",
+            self.store.path().display(),
+            count,
+            started.elapsed().as_secs_f64() * 1000.0
+        );
+        Ok(())
+    }
+
+    /// save без блокировки цикла asyncio
+    fn save_async(slf: Py<Self>, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().save())
+    }
+
+    /// Публиковать события в EventBus (None — отключить)
+    #[pyo3(signature = (bus=None))]
+    fn set_event_bus(&self, bus: Option<PyRef<'_, EventBus>>) {
+        *self.bus.write() = bus.map(|b| b.shared());
+    }
+
+    /// В режиме shared очищает и записи в хранилище
+    fn clear(&self) -> PyResult<()> {
+        if self.shared {
+            self.store
+                .replace(&[(NAMESPACE, Vec::new())])
+                .map_err(|e| self.store.error("очистить кэш эмбеддингов в", e))?;
+        }
+        self.cache.clear();
+        self.access_count.clear();
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl EmbeddingCache {
+    pub(crate) fn store(&self) -> Arc<Store> {
+        Arc::clone(&self.store)
+    }
+
+    /// Чтение для других модулей ядра — без учёта в hits/misses и LRU
+    pub(crate) fn peek(&self, text: &str) -> Option<Vec<f32>> {
+        let h = text_hash(text);
+        self.cache.get(&h).map(|e| e.value().clone()).or_else(|| self.fetch_shared(&h))
+    }
+
+    fn insert(&self, h: String, embedding: Vec<f32>) {
+        if self.cache.len() >= self.max_size {
+            self.evict_lru();
+        }
+        self.cache.insert(h.clone(), embedding);
+        self.access_count.insert(h, 1);
+    }
+
+    /// Запись из хранилища в режиме shared; сбой чтения — предупреждение и промах
+    fn fetch_shared(&self, h: &str) -> Option<Vec<f32>> {
+        if !self.shared {
+            return None;
+        }
+        match self.store.get(NAMESPACE, h) {
+            Ok(data) => data.as_deref().and_then(decode),
+            Err(e) => {
+                errors::warn(module_path!(), &format!("Кэш эмбеддингов {} не прочитан: {}", self.store.path().display(), e));
+                None
+            }
+        }
+    }
+
+    /// Кэш восстановим пересчётом: битый файл не мешает запуску
+    fn load_from_disk(&self) {
+        let records = match self.store.scan(NAMESPACE) {
+            Ok(records) => records,
+            Err(e) => {
+                return errors::warn(module_path!(), &format!(
+                    "Кэш эмбеддингов {} не загружен, начинаю с пустого: {}",
+                    self.store.path().display(),
+                    e
+                ))
+            }
+        };
+        if records.is_empty() {
+            return self.import_json();
+        }
+        let mut skipped = 0;
+        for (key, data) in records {
+            let Some(embedding) = decode(&data) else {
+                skipped += 1;
+                continue;
+            };
+            self.cache.insert(key.clone(), embedding);
+            self.access_count.insert(key, 0);
+        }
+        if skipped > 0 {
+            errors::warn(module_path!(), &format!("Кэш эмбеддингов: пропущено повреждённых записей {}", skipped));
+        }
+        log::debug!("Кэш эмбеддингов загружен из {}: записей {}", self.store.path().display(), self.cache.len());
+    }
+
+    /// Прежний embedding_cache.json → кэш и хранилище (файл → *.migrated)
+    fn import_json(&self) {
+        let Some(path) = self.legacy_path.as_ref().filter(|p| p.exists()) else {
+            return;
+        };
+        let map = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_json::from_str::<HashMap<String, Vec<f32>>>(&data).map_err(|e| e.to_string()));
+        let map = match map {
+            Ok(map) => map,
+            Err(e) => {
+                return errors::warn(module_path!(), &format!(
+                    "Кэш эмбеддингов {} не загружен, начинаю с пустого: {}",
+                    path.display(),
+                    e
+                ))
+            }
+        };
+        for (k, v) in map {
+            self.cache.insert(k.clone(), v);
+            self.access_count.insert(k, 0);
+        }
+        match self.save() {
+            Ok(()) => {
+                let _ = std::fs::rename(path, path.with_extension("json.migrated"));
+                log::info!("Кэш эмбеддингов импортирован из {}", path.display());
+            }
+            // Файл остаётся: импорт повторится при следующей загрузке
+            Err(_) => errors::warn(module_path!(), &format!("Кэш эмбеддингов из {} не перенесён в хранилище", path.display())),
+        }
+    }
+
+    fn evict_lru(&self) {
+        // Без GIL put-ы идут параллельно: вытесняет один поток, остальные
+        // ждут его и перепроверяют размер — иначе каждый вытеснил бы свою долю
+        let guard = self.evicting.lock();
+        if self.cache.len() < self.max_size {
+            return;
+        }
+        let evict_count = std::cmp::max(1, self.max_size / 10);
+        let mut entries: Vec<(String, u64)> = self.access_count
+            .iter()
+            .map(|r| (r.key().clone(), *r.value()))
+            .collect();
+        entries.sort_by_key(|(_, count)| *count);
+
+        let mut evicted = Vec::with_capacity(evict_count);
+        for (key, _) in entries.into_iter().take(evict_count) {
+            if self.cache.remove(&key).is_some() {
+                evicted.push(key.clone());
+            }
+            self.access_count.remove(&key);
+        }
+        // Общее хранилище ограничено тем же лимитом
+        let removed = if self.shared { self.store.remove(NAMESPACE, &evicted) } else { Ok(()) };
+        let evicted = evicted.len();
+        metrics::inc("embedding_cache_evictions_total", evicted as u64);
+        event_bus::emit(&self.bus, "cache_eviction", "embedding_cache", || {
+            json!({ "evicted": evicted, "size": self.cache.len(), "max_size": self.max_size })
+        });
+        drop(guard);
+        log::debug!("Кэш эмбеддингов: вытеснено {} записей (лимит {})", evicted, self.max_size);
+        if let Err(e) = removed {
+            errors::warn(module_path!(), &format!(
+                "Вытесненные эмбеддинги не удалены из {}: {}",
+                self.store.path().display(),
+                e
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_concurrent_put_get_stays_bounded() {
+        let dir = std::env::temp_dir().join(format!("kristina_embedding_{}", std::process::id()));
+        let cache = Arc::new(EmbeddingCache::new(dir.to_str().unwrap(), 100).unwrap());
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let cache = Arc::clone(&cache);
+                std::thread::spawn(move || {
+                    for i in 0..500 {
+                        let text = format!("t{} text {}", t, i);
+                        cache.put(&text, vec![i as f32; 4]).unwrap();
+                        if let Some(embedding) = cache.get(&text) {
+                            assert_eq!(embedding[0], i as f32);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // Превышение — не больше одной вставки на поток во время вытеснения
+        assert!(cache.py_len() <= 100 + 8);
+        let (_, hits, misses) = cache.get_stats();
+        assert_eq!(hits + misses, 8 * 500);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+    #[test]
+    fn test_put_rejects_bad_embeddings() {
+        let dir = std::env::temp_dir().join(format!("kristina_embedding_dim_{}", std::process::id()));
+        let cache = EmbeddingCache::new(dir.to_str().unwrap(), 100).unwrap();
+        assert!(cache.put("пусто", Vec::new()).is_err());
+        cache.put("привет", vec![0.1, 0.2, 0.3]).unwrap();
+        assert!(cache.put("мир", vec![0.1, 0.2]).is_err());
+        assert!(!cache.contains("мир"));
+        cache.save().unwrap();
+        assert_eq!(EmbeddingCache::new(dir.to_str().unwrap(), 100).unwrap().py_len(), 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_shared_entries_between_processes() {
+        let dir = std::env::temp_dir().join(format!("kristina_embedding_shared_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let shared = || EmbeddingCache::with_store(storage::open(&dir).unwrap(), None, 10, true);
+        let (a, b) = (shared(), shared());
+
+        a.put("привет", vec![0.5, 0.25]).unwrap();
+        assert!(b.contains("привет"));
+        assert_eq!(b.get("привет"), Some(vec![0.5, 0.25]));
+        assert_eq!(b.get_stats(), (1, 1, 0));
+
+        // Вытеснение удаляет записи и из хранилища
+        for i in 0..10 {
+            a.put(&format!("текст {}", i), vec![i as f32, 0.0]).unwrap();
+        }
+        assert_eq!(a.store.keys(NAMESPACE).unwrap().len(), a.py_len());
+        b.clear().unwrap();
+        assert!(a.store.keys(NAMESPACE).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
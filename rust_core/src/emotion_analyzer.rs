@@ -1,173 +1,311 @@
-//! EmotionAnalyzer — анализ эмоций через Aho-Corasick
-//!
-//! Преимущество над Python regex:
-//! - Aho-Corasick: O(n + m) вместо O(n * p) для p паттернов
-//! - Единственный проход по тексту для всех паттернов
-//! - Поддержка RU + EN + emoji
-
-use pyo3::prelude::*;
-use aho_corasick::AhoCorasick;
-
-#[pyclass(frozen)]
-pub struct EmotionAnalyzer {
-    positive_ac: AhoCorasick,
-    negative_ac: AhoCorasick,
-    curious_ac: AhoCorasick,
-    positive_patterns: Vec<String>,
-    negative_patterns: Vec<String>,
-    curious_patterns: Vec<String>,
-}
-
-#[pymethods]
-impl EmotionAnalyzer {
-    #[new]
-    fn new() -> Self {
-        let positive: Vec<&str> = vec![
-            "спасибо", "отлично", "супер", "хорошо", "круто", "молодец",
-            "замечательно", "класс", "здорово", "прекрасно", "великолепно",
-            "восхитительно", "браво", "ура", "обожаю", "нравится", "люблю",
-            "рад", "рада", "счастлив", "доволен", "довольна", "благодарю",
-            "спс", "пасиб", "awesome", "nice", "great", "thanks", "cool",
-            "\u{1f44d}", "\u{1f60a}", "\u{1f603}", "\u{2764}\u{fe0f}",
-            "\u{1f389}", "\u{1f4aa}", "\u{1f525}",
-        ];
-        let negative: Vec<&str> = vec![
-            "не работает", "ошибка", "плохо", "не получается", "проблема",
-            "сломал", "баг", "глючит", "тормозит", "зависает", "ужасно",
-            "отстой", "бесит", "раздражает", "не понимаю", "запутал",
-            "неправильно", "некорректно", "фигня", "дерьмо", "не так",
-            "broken", "error", "bug", "wrong", "bad", "fail",
-            "\u{1f61e}", "\u{1f621}", "\u{1f624}", "\u{1f494}",
-            "\u{1f622}", "\u{1f92c}",
-        ];
-        let curious: Vec<&str> = vec![
-            "как", "что", "почему", "зачем", "когда", "где", "кто",
-            "сколько", "можно ли", "а если", "расскажи", "объясни",
-            "подскажи", "помоги", "покажи", "научи", "интересно",
-            "how", "what", "why", "when", "where", "who",
-            "\u{1f914}", "\u{2753}", "\u{1f9d0}",
-        ];
-
-        let positive_patterns: Vec<String> = positive.iter().map(|s| s.to_string()).collect();
-        let negative_patterns: Vec<String> = negative.iter().map(|s| s.to_string()).collect();
-        let curious_patterns: Vec<String> = curious.iter().map(|s| s.to_string()).collect();
-
-        // Паттерны уже в lowercase — сопоставляем с lowercase текстом
-        Self {
-            positive_ac: AhoCorasick::new(&positive).unwrap(),
-            negative_ac: AhoCorasick::new(&negative).unwrap(),
-            curious_ac: AhoCorasick::new(&curious).unwrap(),
-            positive_patterns,
-            negative_patterns,
-            curious_patterns,
-        }
-    }
-
-    fn analyze(&self, text: &str) -> String {
-        let text_lower = text.to_lowercase();
-
-        let pos_count = self.positive_ac.find_iter(&text_lower).count();
-        let neg_count = self.negative_ac.find_iter(&text_lower).count();
-        let mut cur_count = self.curious_ac.find_iter(&text_lower).count();
-
-        if text.contains('?') {
-            cur_count += 2;
-        }
-
-        if pos_count == 0 && neg_count == 0 && cur_count == 0 {
-            return "neutral".to_string();
-        }
-
-        if neg_count > pos_count && neg_count >= cur_count {
-            "negative".to_string()
-        } else if pos_count > neg_count && pos_count >= cur_count {
-            "positive".to_string()
-        } else if cur_count > 0 {
-            "curious".to_string()
-        } else {
-            "neutral".to_string()
-        }
-    }
-
-    fn analyze_detailed(&self, text: &str) -> (String, f64, Vec<String>) {
-        let text_lower = text.to_lowercase();
-
-        let pos_matches: Vec<String> = self.positive_ac
-            .find_iter(&text_lower)
-            .map(|m| self.positive_patterns[m.pattern().as_usize()].clone())
-            .collect();
-        let neg_matches: Vec<String> = self.negative_ac
-            .find_iter(&text_lower)
-            .map(|m| self.negative_patterns[m.pattern().as_usize()].clone())
-            .collect();
-        let cur_matches: Vec<String> = self.curious_ac
-            .find_iter(&text_lower)
-            .map(|m| self.curious_patterns[m.pattern().as_usize()].clone())
-            .collect();
-
-        let total = pos_matches.len() + neg_matches.len() + cur_matches.len();
-
-        if total == 0 {
-            return ("neutral".to_string(), 0.5, vec![]);
-        }
-
-        let emotion = self.analyze(text);
-        let dominant_count = match emotion.as_str() {
-            "positive" => pos_matches.len(),
-            "negative" => neg_matches.len(),
-            "curious" => cur_matches.len(),
-            _ => 0,
-        };
-
-        let confidence = if total > 0 {
-            (dominant_count as f64 / total as f64).min(1.0)
-        } else {
-            0.5
-        };
-
-        let mut all_matches = Vec::with_capacity(total);
-        all_matches.extend(pos_matches);
-        all_matches.extend(neg_matches);
-        all_matches.extend(cur_matches);
-
-        (emotion, confidence, all_matches)
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_positive_emotion() {
-        let analyzer = EmotionAnalyzer::new();
-        assert_eq!(analyzer.analyze("Спасибо, отлично!"), "positive");
-    }
-
-    #[test]
-    fn test_negative_emotion() {
-        let analyzer = EmotionAnalyzer::new();
-        assert_eq!(analyzer.analyze("Не работает, ошибка!"), "negative");
-    }
-
-    #[test]
-    fn test_curious_emotion() {
-        let analyzer = EmotionAnalyzer::new();
-        assert_eq!(analyzer.analyze("Как это сделать?"), "curious");
-    }
-
-    #[test]
-    fn test_neutral() {
-        let analyzer = EmotionAnalyzer::new();
-        assert_eq!(analyzer.analyze("абвгд"), "neutral");
-    }
-
-    #[test]
-    fn test_detailed() {
-        let analyzer = EmotionAnalyzer::new();
-        let (emotion, confidence, matches) = analyzer.analyze_detailed("Спасибо, круто!");
-        assert_eq!(emotion, "positive");
-        assert!(confidence > 0.0);
-        assert!(!matches.is_empty());
-    }
-}
+//! EmotionAnalyzer — анализ эмоций через Aho-Corasick
+//!
+//! Преимущество над Python regex:
+//! - Aho-Corasick: O(n + m) вместо O(n * p) для p паттернов
+//! - Единственный проход по тексту для всех паттернов
+//! - Поддержка RU + EN + emoji
+//! - Слова сравниваются по основам (стемминг RU/EN) с границами слов:
+//!   "сломала" ~ "сломал", но "рад" не находится в "градусник"
+//! - translit=True: русские слова находятся и в транслите ("spasibo", "ne rabotaet")
+//! - Markdown-разметка и блоки кода не анализируются
+//! - События (set_event_bus): emotion_spike — positive/negative минимум
+//!   с spike_matches совпадениями
+
+use pyo3::prelude::*;
+use aho_corasick::AhoCorasick;
+use parking_lot::RwLock;
+use serde_json::json;
+
+use crate::event_bus::{EventBus, SharedBus};
+use crate::markdown;
+use crate::text_normalizer::normalized;
+use crate::stemmer::{stem_key, stem_text};
+use crate::translit::{has_latin, to_cyrillic};
+
+/// Символов текста в событии emotion_spike
+const SPIKE_PREVIEW_CHARS: usize = 80;
+
+/// Паттерны одной эмоции: слова — по основам, эмодзи — как есть
+struct Patterns {
+    words_ac: AhoCorasick,
+    words: Vec<String>,
+    /// Паттерн на кириллице — сверяется и с транслитом
+    cyrillic: Vec<bool>,
+    symbols_ac: AhoCorasick,
+    symbols: Vec<String>,
+}
+
+impl Patterns {
+    fn new(patterns: &[&str]) -> Self {
+        let (words, symbols): (Vec<&str>, Vec<&str>) =
+            patterns.iter().partition(|p| p.chars().any(char::is_alphanumeric));
+        let keys: Vec<String> = words.iter().map(|w| stem_key(stem_text(w).iter().map(String::as_str))).collect();
+        Self {
+            words_ac: AhoCorasick::new(&keys).unwrap(),
+            words: words.iter().map(|s| s.to_string()).collect(),
+            cyrillic: words.iter().map(|w| w.chars().any(|c| ('\u{0400}'..='\u{04FF}').contains(&c))).collect(),
+            symbols_ac: AhoCorasick::new(&symbols).unwrap(),
+            symbols: symbols.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Совпавшие паттерны; line — stem_key текста, translit — stem_key текста
+    /// в кириллице: кириллические паттерны ищутся в нём (он содержит и исходную
+    /// кириллицу), остальные — в line, поэтому ничего не считается дважды
+    fn matches(&self, text: &str, line: &str, translit: Option<&str>) -> Vec<String> {
+        let mut found = Vec::new();
+        for m in self.words_ac.find_iter(line) {
+            let id = m.pattern().as_usize();
+            if translit.is_none() || !self.cyrillic[id] {
+                found.push(self.words[id].clone());
+            }
+        }
+        if let Some(translit) = translit {
+            for m in self.words_ac.find_iter(translit) {
+                let id = m.pattern().as_usize();
+                if self.cyrillic[id] {
+                    found.push(self.words[id].clone());
+                }
+            }
+        }
+        found.extend(self.symbols_ac.find_iter(text).map(|m| self.symbols[m.pattern().as_usize()].clone()));
+        found
+    }
+}
+
+#[pyclass(frozen)]
+pub struct EmotionAnalyzer {
+    positive: Patterns,
+    negative: Patterns,
+    curious: Patterns,
+    translit: bool,
+    /// EventBus и порог совпадений для emotion_spike
+    bus: RwLock<Option<(SharedBus, usize)>>,
+}
+
+#[pymethods]
+impl EmotionAnalyzer {
+    /// translit — искать русские паттерны и в тексте, набранном латиницей
+    #[new]
+    #[pyo3(signature = (translit=true))]
+    pub(crate) fn new(translit: bool) -> Self {
+        let positive: Vec<&str> = vec![
+            "спасибо", "отлично", "супер", "хорошо", "круто", "молодец",
+            "замечательно", "класс", "здорово", "прекрасно", "великолепно",
+            "восхитительно", "браво", "ура", "обожаю", "нравится", "люблю",
+            "рад", "рада", "счастлив", "доволен", "довольна", "благодарю",
+            "спс", "пасиб", "awesome", "nice", "great", "thanks", "cool",
+            "\u{1f44d}", "\u{1f60a}", "\u{1f603}", "\u{2764}\u{fe0f}",
+            "\u{1f389}", "\u{1f4aa}", "\u{1f525}",
+        ];
+        let negative: Vec<&str> = vec![
+            "не работает", "ошибка", "плохо", "не получается", "проблема",
+            "сломал", "баг", "глючит", "тормозит", "зависает", "ужасно",
+            "отстой", "бесит", "раздражает", "не понимаю", "запутал",
+            "неправильно", "некорректно", "фигня", "дерьмо", "не так",
+            "broken", "error", "bug", "wrong", "bad", "fail",
+            "\u{1f61e}", "\u{1f621}", "\u{1f624}", "\u{1f494}",
+            "\u{1f622}", "\u{1f92c}",
+        ];
+        let curious: Vec<&str> = vec![
+            "как", "что", "почему", "зачем", "когда", "где", "кто",
+            "сколько", "можно ли", "а если", "расскажи", "объясни",
+            "подскажи", "помоги", "покажи", "научи", "интересно",
+            "how", "what", "why", "when", "where", "who",
+            "\u{1f914}", "\u{2753}", "\u{1f9d0}",
+        ];
+
+        Self {
+            positive: Patterns::new(&positive),
+            negative: Patterns::new(&negative),
+            curious: Patterns::new(&curious),
+            translit,
+            bus: RwLock::new(None),
+        }
+    }
+
+    fn analyze(&self, text: &str) -> String {
+        let (pos, neg, cur) = self.matches(text);
+        let emotion = classify(text, pos.len(), neg.len(), cur.len());
+        self.report_spike(text, &emotion, &pos, &neg);
+        emotion
+    }
+
+    pub(crate) fn analyze_detailed(&self, text: &str) -> (String, f64, Vec<String>) {
+        self.detailed(text, true)
+    }
+
+    /// Публиковать emotion_spike в EventBus (None — отключить): реплики с
+    /// positive/negative и не меньше spike_matches совпадений
+    #[pyo3(signature = (bus=None, spike_matches=3))]
+    fn set_event_bus(&self, bus: Option<PyRef<'_, EventBus>>, spike_matches: usize) {
+        *self.bus.write() = bus.map(|b| (b.shared(), spike_matches.max(1)));
+    }
+}
+
+impl EmotionAnalyzer {
+    /// analyze_detailed; report=false — без событий (оценка истории, не новой реплики)
+    pub(crate) fn detailed(&self, text: &str, report: bool) -> (String, f64, Vec<String>) {
+        let (pos_matches, neg_matches, cur_matches) = self.matches(text);
+        let total = pos_matches.len() + neg_matches.len() + cur_matches.len();
+
+        if total == 0 {
+            return ("neutral".to_string(), 0.5, vec![]);
+        }
+
+        let emotion = classify(text, pos_matches.len(), neg_matches.len(), cur_matches.len());
+        if report {
+            self.report_spike(text, &emotion, &pos_matches, &neg_matches);
+        }
+        let dominant_count = match emotion.as_str() {
+            "positive" => pos_matches.len(),
+            "negative" => neg_matches.len(),
+            "curious" => cur_matches.len(),
+            _ => 0,
+        };
+
+        let confidence = if total > 0 {
+            (dominant_count as f64 / total as f64).min(1.0)
+        } else {
+            0.5
+        };
+
+        let mut all_matches = Vec::with_capacity(total);
+        all_matches.extend(pos_matches);
+        all_matches.extend(neg_matches);
+        all_matches.extend(cur_matches);
+
+        (emotion, confidence, all_matches)
+    }
+
+    fn report_spike(&self, text: &str, emotion: &str, positive: &[String], negative: &[String]) {
+        let matches = match emotion {
+            "positive" => positive,
+            "negative" => negative,
+            _ => return,
+        };
+        let bus = self.bus.read().clone();
+        if let Some((bus, spike_matches)) = bus {
+            if matches.len() >= spike_matches {
+                let preview: String = text.chars().take(SPIKE_PREVIEW_CHARS).collect();
+                bus.emit("emotion_spike", "emotion_analyzer", json!({
+                    "emotion": emotion,
+                    "matches": matches,
+                    "text": preview,
+                }));
+            }
+        }
+    }
+
+    /// Совпадения (positive, negative, curious) — основы текста считаются один раз
+    fn matches(&self, text: &str) -> (Vec<String>, Vec<String>, Vec<String>) {
+        // Разметка и код не несут эмоций; двойники и невидимые символы —
+        // частый способ обойти слова-маркеры
+        let text = &*normalized(text);
+        let text = &*markdown::plain(text, false);
+        let line = stem_key(stem_text(text).iter().map(String::as_str));
+        let translit = (self.translit && has_latin(text))
+            .then(|| stem_key(stem_text(&to_cyrillic(text)).iter().map(String::as_str)));
+        let translit = translit.as_deref();
+        (
+            self.positive.matches(text, &line, translit),
+            self.negative.matches(text, &line, translit),
+            self.curious.matches(text, &line, translit),
+        )
+    }
+}
+
+/// Эмоция по числу совпадений; "?" добавляет любопытства
+fn classify(text: &str, pos_count: usize, neg_count: usize, mut cur_count: usize) -> String {
+    if text.contains('?') {
+        cur_count += 2;
+    }
+
+    if pos_count == 0 && neg_count == 0 && cur_count == 0 {
+        return "neutral".to_string();
+    }
+
+    if neg_count > pos_count && neg_count >= cur_count {
+        "negative".to_string()
+    } else if pos_count > neg_count && pos_count >= cur_count {
+        "positive".to_string()
+    } else if cur_count > 0 {
+        "curious".to_string()
+    } else {
+        "neutral".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positive_emotion() {
+        let analyzer = EmotionAnalyzer::new(true);
+        assert_eq!(analyzer.analyze("Спасибо, отлично!"), "positive");
+    }
+
+    #[test]
+    fn test_negative_emotion() {
+        let analyzer = EmotionAnalyzer::new(true);
+        assert_eq!(analyzer.analyze("Не работает, ошибка!"), "negative");
+    }
+
+    #[test]
+    fn test_curious_emotion() {
+        let analyzer = EmotionAnalyzer::new(true);
+        assert_eq!(analyzer.analyze("Как это сделать?"), "curious");
+    }
+
+    #[test]
+    fn test_neutral() {
+        let analyzer = EmotionAnalyzer::new(true);
+        assert_eq!(analyzer.analyze("абвгд"), "neutral");
+    }
+
+    #[test]
+    fn test_detailed() {
+        let analyzer = EmotionAnalyzer::new(true);
+        let (emotion, confidence, matches) = analyzer.analyze_detailed("Спасибо, круто!");
+        assert_eq!(emotion, "positive");
+        assert!(confidence > 0.0);
+        assert!(!matches.is_empty());
+    }
+
+    #[test]
+    fn test_stem_matching() {
+        let analyzer = EmotionAnalyzer::new(true);
+        // Словоформы совпадают, подстроки чужих слов — нет
+        assert_eq!(analyzer.analyze("Всё сломалось, опять ошибки"), "negative");
+        assert_eq!(analyzer.analyze("Градусник показал ноль, багаж собран"), "neutral");
+        let (_, _, matches) = analyzer.analyze_detailed("Ничего не работало \u{1f621}");
+        assert_eq!(matches, vec!["не работает", "\u{1f621}"]);
+    }
+
+    #[test]
+    fn test_translit_matching() {
+        let analyzer = EmotionAnalyzer::new(true);
+        assert_eq!(analyzer.analyze("spasibo, vse otlichno"), "positive");
+        assert_eq!(analyzer.analyze("opyat ne rabotaet, oshibka"), "negative");
+        // Английские паттерны по-прежнему ищутся в исходном тексте, без двойного счёта
+        let (_, _, matches) = analyzer.analyze_detailed("thanks, круто");
+        assert_eq!(matches, vec!["thanks", "круто"]);
+        assert_eq!(EmotionAnalyzer::new(false).analyze("spasibo, vse otlichno"), "neutral");
+    }
+
+    #[test]
+    fn test_emotion_spike_event() {
+        let analyzer = EmotionAnalyzer::new(true);
+        let bus = EventBus::new(10);
+        *analyzer.bus.write() = Some((bus.shared(), 2));
+        analyzer.analyze("Спасибо");
+        analyzer.analyze_detailed("Опять ошибка, ничего не работает, ужасно");
+        // Оценка истории без событий
+        analyzer.detailed("Ужасно, ошибка, баг", false);
+
+        let events = bus.drain_events(None);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, "emotion_spike");
+    }
+}
@@ -0,0 +1,353 @@
+//! EntityRecognizer — типизированные сущности по справочникам и эвристикам
+//!
+//! - Справочники (gazetteers): label → имена ("person", "city", "project"...);
+//!   все имена — в одном автомате Aho-Corasick по основам слов, поэтому
+//!   "Москва" находится и в "в Москве", а "Анна" — в "с Анной"
+//! - Эвристики поверх справочников: @упоминания ("mention"), фразы в кавычках
+//!   ("quote"), цепочки слов с заглавной буквы не в начале предложения ("proper")
+//! - Совпадение справочника побеждает эвристику на том же фрагменте
+//! - Состояние за Arc: ThreadTracker может разделять живой справочник
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use aho_corasick::{AhoCorasick, MatchKind};
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::stemmer::{stem_key, stem_word};
+
+const QUOTES: &[(char, char)] = &[('«', '»'), ('"', '"'), ('“', '”')];
+/// Длиннее — скорее цитата, чем название
+const MAX_QUOTE_CHARS: usize = 60;
+
+/// Найденная сущность; start/end — смещения в символах, canonical —
+/// имя из справочника (для эвристик совпадает с text)
+#[pyclass(frozen, get_all)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entity {
+    pub text: String,
+    pub label: String,
+    pub canonical: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[pymethods]
+impl Entity {
+    fn __repr__(&self) -> String {
+        format!("Entity(text={:?}, label={:?}, start={}, end={})", self.text, self.label, self.start, self.end)
+    }
+}
+
+/// Слово текста: символьные границы и основа
+struct Token {
+    start: usize,
+    end: usize,
+    stem: String,
+}
+
+fn tokens(chars: &[char]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_alphanumeric() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        // Дефис внутри слова: "Санкт-Петербург"
+        while i < chars.len() && (chars[i].is_alphanumeric() || (chars[i] == '-' && i + 1 < chars.len() && chars[i + 1].is_alphanumeric())) {
+            i += 1;
+        }
+        let word: String = chars[start..i].iter().collect::<String>().to_lowercase().replace('ё', "е");
+        tokens.push(Token { start, end: i, stem: stem_word(&word) });
+    }
+    tokens
+}
+
+/// Распознаватель, разделяемый с другими модулями ядра (без GIL)
+pub(crate) type SharedRecognizer = Arc<RwLock<Recognizer>>;
+
+/// Справочники и скомпилированный автомат
+#[derive(Default)]
+pub(crate) struct Recognizer {
+    /// label → имена (в порядке добавления)
+    gazetteers: BTreeMap<String, Vec<String>>,
+    ac: Option<AhoCorasick>,
+    /// Для паттерна автомата: (label, каноническое имя)
+    targets: Vec<(String, String)>,
+    /// Имя из справочника с заглавной буквы совпадает только с заглавным словом
+    require_capital: bool,
+}
+
+impl Recognizer {
+    fn rebuild(&mut self) -> Result<(), String> {
+        let mut keys: Vec<String> = Vec::new();
+        let mut targets = Vec::new();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for (label, names) in &self.gazetteers {
+            for name in names {
+                let chars: Vec<char> = name.chars().collect();
+                let key = stem_key(tokens(&chars).iter().map(|t| t.stem.as_str()));
+                if key.is_empty() || seen.contains_key(&key) {
+                    continue;
+                }
+                seen.insert(key.clone(), keys.len());
+                keys.push(key);
+                targets.push((label.clone(), name.clone()));
+            }
+        }
+        self.ac = if keys.is_empty() {
+            None
+        } else {
+            Some(
+                AhoCorasick::builder()
+                    .match_kind(MatchKind::LeftmostLongest)
+                    .build(&keys)
+                    .map_err(|e| e.to_string())?,
+            )
+        };
+        self.targets = targets;
+        Ok(())
+    }
+
+    pub(crate) fn recognize(&self, text: &str) -> Vec<Entity> {
+        let chars: Vec<char> = text.chars().collect();
+        let tokens = tokens(&chars);
+        let mut found = self.gazetteer_matches(&chars, &tokens);
+        for entity in heuristics(&chars, &tokens) {
+            if !found.iter().any(|f| entity.start < f.end && f.start < entity.end) {
+                found.push(entity);
+            }
+        }
+        found.sort_by_key(|e| e.start);
+        found
+    }
+
+    /// Только совпадения со справочниками, без эвристик
+    pub(crate) fn known(&self, text: &str) -> Vec<Entity> {
+        let chars: Vec<char> = text.chars().collect();
+        self.gazetteer_matches(&chars, &tokens(&chars))
+    }
+
+    fn gazetteer_matches(&self, chars: &[char], tokens: &[Token]) -> Vec<Entity> {
+        let Some(ac) = &self.ac else {
+            return Vec::new();
+        };
+        let line = stem_key(tokens.iter().map(|t| t.stem.as_str()));
+        // Байтовое начало каждого слова в line → индекс слова
+        let mut token_at: HashMap<usize, usize> = HashMap::new();
+        let mut pos = 0;
+        for (i, t) in tokens.iter().enumerate() {
+            token_at.insert(pos, i);
+            pos += t.stem.len() + 2;
+        }
+        let mut found = Vec::new();
+        for m in ac.find_iter(&line) {
+            let Some(&first) = token_at.get(&m.start()) else { continue };
+            let count = line[m.start()..m.end()].split_whitespace().count();
+            let last = first + count - 1;
+            let (label, canonical) = &self.targets[m.pattern().as_usize()];
+            let capital_ok = !self.require_capital
+                || !canonical.chars().next().is_some_and(char::is_uppercase)
+                || chars[tokens[first].start].is_uppercase();
+            if capital_ok {
+                let (start, end) = (tokens[first].start, tokens[last].end);
+                found.push(Entity {
+                    text: chars[start..end].iter().collect(),
+                    label: label.clone(),
+                    canonical: canonical.clone(),
+                    start,
+                    end,
+                });
+            }
+        }
+        found
+    }
+}
+
+fn heuristic(chars: &[char], label: &str, start: usize, end: usize) -> Entity {
+    let text: String = chars[start..end].iter().collect();
+    Entity { canonical: text.clone(), text, label: label.to_string(), start, end }
+}
+
+/// @упоминания, фразы в кавычках, цепочки заглавных слов
+fn heuristics(chars: &[char], tokens: &[Token]) -> Vec<Entity> {
+    let mut found = Vec::new();
+    for t in tokens {
+        if t.start > 0 && chars[t.start - 1] == '@' {
+            found.push(heuristic(chars, "mention", t.start - 1, t.end));
+        }
+    }
+
+    for &(open, close) in QUOTES {
+        let mut i = 0;
+        while let Some(s) = chars[i..].iter().position(|&c| c == open).map(|p| i + p) {
+            let Some(e) = chars[s + 1..].iter().position(|&c| c == close).map(|p| s + 1 + p) else { break };
+            let inner: String = chars[s + 1..e].iter().collect();
+            let lead = inner.len() - inner.trim_start().len();
+            let trimmed = inner.trim();
+            if !trimmed.is_empty() && trimmed.chars().count() <= MAX_QUOTE_CHARS {
+                let start = s + 1 + inner[..lead].chars().count();
+                found.push(heuristic(chars, "quote", start, start + trimmed.chars().count()));
+            }
+            i = e + 1;
+        }
+    }
+
+    // Заглавное слово в начале предложения — не имя; пунктуация между словами рвёт цепочку
+    let mut run: Option<(usize, usize)> = None;
+    let mut sentence_start = true;
+    let mut prev_end = 0;
+    for t in tokens {
+        let gap: String = chars[prev_end..t.start].iter().collect();
+        if gap.contains(['.', '!', '?', '…', ':', '\n']) {
+            sentence_start = true;
+        }
+        let broken = !gap.trim().is_empty();
+        let capitalized = chars[t.start].is_uppercase() && t.end - t.start > 1;
+        let mention = t.start > 0 && chars[t.start - 1] == '@';
+        if let Some((s, e)) = run {
+            if broken || !capitalized || mention {
+                found.push(heuristic(chars, "proper", s, e));
+                run = None;
+            }
+        }
+        if capitalized && !sentence_start && !mention {
+            run = Some((run.map_or(t.start, |(s, _)| s), t.end));
+        }
+        sentence_start = false;
+        prev_end = t.end;
+    }
+    if let Some((s, e)) = run {
+        found.push(heuristic(chars, "proper", s, e));
+    }
+
+    // Кавычки важнее имён внутри них
+    let quotes: Vec<(usize, usize)> = found.iter().filter(|e| e.label == "quote").map(|e| (e.start, e.end)).collect();
+    found.retain(|e| e.label != "proper" || !quotes.iter().any(|&(s, end)| e.start >= s && e.end <= end));
+    found
+}
+
+#[pyclass(frozen)]
+pub struct EntityRecognizer {
+    inner: SharedRecognizer,
+}
+
+#[pymethods]
+impl EntityRecognizer {
+    /// gazetteers — {label: [имя, ...]}; require_capital — имена из справочника
+    /// с заглавной буквы не совпадают со строчными словами ("Роза" ≠ "роза")
+    #[new]
+    #[pyo3(signature = (gazetteers=None, require_capital=false))]
+    pub(crate) fn new(gazetteers: Option<HashMap<String, Vec<String>>>, require_capital: bool) -> PyResult<Self> {
+        let mut recognizer = Recognizer { require_capital, ..Recognizer::default() };
+        recognizer.gazetteers = gazetteers.unwrap_or_default().into_iter().collect();
+        recognizer.rebuild().map_err(PyValueError::new_err)?;
+        Ok(Self { inner: Arc::new(RwLock::new(recognizer)) })
+    }
+
+    /// Добавить имена в справочник label (создаётся, если его нет)
+    pub(crate) fn add_gazetteer(&self, label: &str, names: Vec<String>) -> PyResult<()> {
+        let mut recognizer = self.inner.write();
+        let list = recognizer.gazetteers.entry(label.to_string()).or_default();
+        for name in names.into_iter().map(|n| n.trim().to_string()).filter(|n| !n.is_empty()) {
+            if !list.contains(&name) {
+                list.push(name);
+            }
+        }
+        recognizer.rebuild().map_err(PyValueError::new_err)
+    }
+
+    fn remove_gazetteer(&self, label: &str) -> PyResult<bool> {
+        let mut recognizer = self.inner.write();
+        if recognizer.gazetteers.remove(label).is_none() {
+            return Ok(false);
+        }
+        recognizer.rebuild().map_err(PyValueError::new_err)?;
+        Ok(true)
+    }
+
+    fn get_gazetteers(&self) -> HashMap<String, Vec<String>> {
+        self.inner.read().gazetteers.clone().into_iter().collect()
+    }
+
+    /// Сущности по возрастанию start
+    fn recognize(&self, text: &str) -> Vec<Entity> {
+        self.inner.read().recognize(text)
+    }
+}
+
+impl EntityRecognizer {
+    /// Общее состояние — для модулей ядра, которым нужен живой справочник
+    pub(crate) fn shared(&self) -> SharedRecognizer {
+        Arc::clone(&self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recognizer() -> EntityRecognizer {
+        let gazetteers = HashMap::from([
+            ("city".to_string(), vec!["Москва".to_string(), "Санкт-Петербург".to_string(), "Нижний Новгород".to_string()]),
+            ("person".to_string(), vec!["Анна".to_string()]),
+            ("project".to_string(), vec!["Кристина".to_string()]),
+        ]);
+        EntityRecognizer::new(Some(gazetteers), false).unwrap()
+    }
+
+    fn labeled(r: &EntityRecognizer, text: &str) -> Vec<(String, String)> {
+        r.recognize(text).into_iter().map(|e| (e.label, e.canonical)).collect()
+    }
+
+    #[test]
+    fn test_gazetteer_matches_inflected_forms() {
+        let r = recognizer();
+        let found = r.recognize("Вчера с Анной ездили из Москвы в Нижний Новгород");
+        let summary: Vec<(&str, &str, &str)> =
+            found.iter().map(|e| (e.label.as_str(), e.text.as_str(), e.canonical.as_str())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("person", "Анной", "Анна"),
+                ("city", "Москвы", "Москва"),
+                ("city", "Нижний Новгород", "Нижний Новгород"),
+            ]
+        );
+        let chars: Vec<char> = "Вчера с Анной".chars().collect();
+        assert_eq!(chars[found[0].start..found[0].end].iter().collect::<String>(), "Анной");
+        // Соседние совпадения не теряются
+        assert_eq!(labeled(&r, "москва санкт-петербург").len(), 2);
+    }
+
+    #[test]
+    fn test_heuristics_and_priority() {
+        let r = recognizer();
+        let found = labeled(&r, "Привет, @bob! Посмотри «Война и мир» и напиши Ивану Петрову. Кристина ждёт.");
+        assert_eq!(
+            found,
+            vec![
+                ("mention".to_string(), "@bob".to_string()),
+                ("quote".to_string(), "Война и мир".to_string()),
+                ("proper".to_string(), "Ивану Петрову".to_string()),
+                ("project".to_string(), "Кристина".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_require_capital_and_updates() {
+        let strict = EntityRecognizer::new(Some(HashMap::from([("person".to_string(), vec!["Роза".to_string()])])), true).unwrap();
+        assert!(strict.recognize("купил розы").is_empty());
+        assert_eq!(labeled(&strict, "позвони Розе"), vec![("person".to_string(), "Роза".to_string())]);
+
+        let r = recognizer();
+        r.add_gazetteer("product", vec!["iPhone".into()]).unwrap();
+        assert_eq!(labeled(&r, "куплю iphone"), vec![("product".to_string(), "iPhone".to_string())]);
+        assert!(r.remove_gazetteer("product").unwrap());
+        assert!(r.recognize("куплю iphone").is_empty());
+    }
+}
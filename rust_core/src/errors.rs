@@ -0,0 +1,56 @@
+//! Исключения ядра
+//!
+//! - KristinaError — базовое: `except kristina_core.KristinaError` ловит всё ниже
+//! - ParseError — разбор вызовов инструментов, планов, JSON, шаблонов, словарей
+//! - MemoryError — обращение к отсутствующим данным памяти (нить, пользователь);
+//!   не путать со встроенным MemoryError Python
+//! - CacheError — некорректные данные EmbeddingCache (пустой вектор, другая размерность)
+//! - PersistenceError — чтение и запись файлов: save/load, снимки, архивы
+//! - Некорректные аргументы по-прежнему ValueError
+//! - Сбои фоновых путей, где исключению некуда всплыть (выгрузка сессии,
+//!   дозапись архива нитей, битый кэш), — RuntimeWarning и запись WARNING
+//!   в логгер модуля
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyRuntimeWarning};
+use pyo3::prelude::*;
+use std::ffi::CString;
+use std::fmt::Display;
+use std::path::Path;
+
+use crate::metrics;
+
+create_exception!(kristina_core, KristinaError, PyException, "Базовое исключение ядра Кристины");
+create_exception!(kristina_core, ParseError, KristinaError, "Не удалось разобрать входные данные");
+create_exception!(kristina_core, MemoryError, KristinaError, "Нет запрошенных данных памяти");
+create_exception!(kristina_core, CacheError, KristinaError, "Некорректные данные кэша эмбеддингов");
+create_exception!(kristina_core, PersistenceError, KristinaError, "Ошибка чтения или записи на диск");
+
+/// PersistenceError "Не удалось <action> <path>: <err>"
+pub(crate) fn persistence(action: &str, path: &Path, err: impl Display) -> PyErr {
+    PersistenceError::new_err(format!("Не удалось {} {}: {}", action, path.display(), err))
+}
+
+/// RuntimeWarning для сбоев, которые не прерывают операцию; target — module_path!()
+pub(crate) fn warn(target: &str, message: &str) {
+    metrics::inc("warnings_total", 1);
+    log::warn!(target: target, "{}", message);
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    Python::with_gil(|py| {
+        let category = py.get_type::<PyRuntimeWarning>();
+        // warnings.simplefilter("error") превращает предупреждение в исключение
+        if let Err(err) = PyErr::warn(py, &category, &message, 1) {
+            err.write_unraisable(py, None);
+        }
+    });
+}
+
+pub(crate) fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("KristinaError", py.get_type::<KristinaError>())?;
+    m.add("ParseError", py.get_type::<ParseError>())?;
+    m.add("MemoryError", py.get_type::<MemoryError>())?;
+    m.add("CacheError", py.get_type::<CacheError>())?;
+    m.add("PersistenceError", py.get_type::<PersistenceError>())?;
+    Ok(())
+}
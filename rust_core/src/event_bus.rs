@@ -0,0 +1,346 @@
+//! EventBus — события компонентов ядра для плагинов и аналитики
+//!
+//! - Компоненты подключаются через set_event_bus(bus): MemoryEngine
+//!   (episode_added, episode_deleted, episodes_evicted, episodes_expired,
+//!   episodes_consolidated), EmbeddingCache (cache_eviction), ThreadTracker / MultiThreadTracker (thread_opened, thread_closed),
+//!   EmotionAnalyzer (emotion_spike)
+//! - Python получает события колбэками subscribe(callback, kinds) или
+//!   опросом drain_events() из ограниченной очереди (старые вытесняются)
+//! - emit вызывается компонентами без удерживаемых блокировок: колбэк может
+//!   обращаться к любому объекту ядра
+//! - Исключения колбэков не прерывают компонент (sys.unraisablehook)
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use parking_lot::{Mutex, RwLock};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::{self, CoreConfig};
+
+// ── Событие ──
+
+#[pyclass(frozen)]
+#[derive(Clone)]
+pub struct Event {
+    #[pyo3(get)]
+    seq: u64,
+    #[pyo3(get)]
+    pub(crate) kind: String,
+    #[pyo3(get)]
+    source: String,
+    /// RFC 3339
+    #[pyo3(get)]
+    timestamp: String,
+    pub(crate) payload: Map<String, Value>,
+}
+
+#[pymethods]
+impl Event {
+    #[getter]
+    fn payload<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (key, value) in &self.payload {
+            dict.set_item(key, json_to_py(py, value)?)?;
+        }
+        Ok(dict)
+    }
+
+    /// {"seq", "kind", "source", "timestamp", "payload"}
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("seq", self.seq)?;
+        dict.set_item("kind", &self.kind)?;
+        dict.set_item("source", &self.source)?;
+        dict.set_item("timestamp", &self.timestamp)?;
+        dict.set_item("payload", self.payload(py)?)?;
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Event(seq={}, kind={:?}, source={:?})", self.seq, self.kind, self.source)
+    }
+}
+
+pub(crate) fn json_to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_pyobject(py)?.to_owned().into_any().unbind(),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into_pyobject(py)?.into_any().unbind(),
+            None => n.as_f64().unwrap_or(f64::NAN).into_pyobject(py)?.into_any().unbind(),
+        },
+        Value::String(s) => s.into_pyobject(py)?.into_any().unbind(),
+        Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(json_to_py(py, item)?)?;
+            }
+            list.into_any().unbind()
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, item) in map {
+                dict.set_item(key, json_to_py(py, item)?)?;
+            }
+            dict.into_any().unbind()
+        }
+    })
+}
+
+/// Значение Python → JSON: None, bool, int, float, str, list/tuple, dict;
+/// прочее — через str()
+pub(crate) fn py_to_json(value: &Bound<'_, PyAny>) -> PyResult<Value> {
+    Ok(if value.is_none() {
+        Value::Null
+    } else if value.is_instance_of::<PyBool>() {
+        Value::Bool(value.extract()?)
+    } else if value.is_instance_of::<PyInt>() {
+        match value.extract::<i64>() {
+            Ok(i) => Value::from(i),
+            Err(_) => Value::String(value.str()?.to_string()),
+        }
+    } else if value.is_instance_of::<PyFloat>() {
+        Value::from(value.extract::<f64>()?)
+    } else if let Ok(s) = value.downcast::<PyString>() {
+        Value::String(s.to_string())
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        Value::Array(value.try_iter()?.map(|item| py_to_json(&item?)).collect::<PyResult<_>>()?)
+    } else if let Ok(dict) = value.downcast::<PyDict>() {
+        let mut map = Map::new();
+        for (key, item) in dict.iter() {
+            map.insert(key.str()?.to_string(), py_to_json(&item)?);
+        }
+        Value::Object(map)
+    } else {
+        Value::String(value.str()?.to_string())
+    })
+}
+
+// ── Шина ──
+
+struct Subscriber {
+    id: u64,
+    /// None — все события
+    kinds: Option<HashSet<String>>,
+    callback: PyObject,
+}
+
+pub(crate) struct Bus {
+    max_queue: usize,
+    queue: Mutex<VecDeque<Event>>,
+    subscribers: RwLock<Vec<Subscriber>>,
+    next_subscriber: AtomicU64,
+    seq: AtomicU64,
+    dropped: AtomicU64,
+}
+
+pub(crate) type SharedBus = Arc<Bus>;
+
+impl Bus {
+    fn new(max_queue: usize) -> Self {
+        Self {
+            max_queue,
+            queue: Mutex::new(VecDeque::new()),
+            subscribers: RwLock::new(Vec::new()),
+            next_subscriber: AtomicU64::new(1),
+            seq: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Публикует событие: в очередь для drain_events и подписчикам kind.
+    /// Вызывать без удерживаемых блокировок компонента.
+    pub(crate) fn emit(&self, kind: &str, source: &str, payload: Value) {
+        let event = Event {
+            seq: self.seq.fetch_add(1, Ordering::Relaxed) + 1,
+            kind: kind.to_string(),
+            source: source.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            payload: match payload {
+                Value::Object(map) => map,
+                Value::Null => Map::new(),
+                other => Map::from_iter([("value".to_string(), other)]),
+            },
+        };
+        if self.max_queue > 0 {
+            let mut queue = self.queue.lock();
+            queue.push_back(event.clone());
+            if queue.len() > self.max_queue {
+                queue.pop_front();
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if self.subscribers.read().iter().any(|s| s.wants(kind)) {
+            self.notify(event);
+        }
+    }
+
+    /// Колбэки вызываются вне блокировки подписчиков: колбэк может
+    /// подписываться и отписываться
+    fn notify(&self, event: Event) {
+        Python::with_gil(|py| {
+            let callbacks: Vec<PyObject> = self
+                .subscribers
+                .read()
+                .iter()
+                .filter(|s| s.wants(&event.kind))
+                .map(|s| s.callback.clone_ref(py))
+                .collect();
+            let event = match Py::new(py, event) {
+                Ok(event) => event,
+                Err(err) => return err.write_unraisable(py, None),
+            };
+            for callback in callbacks {
+                if let Err(err) = callback.call1(py, (event.clone_ref(py),)) {
+                    err.write_unraisable(py, None);
+                }
+            }
+        });
+    }
+
+    fn drain(&self, limit: Option<usize>) -> Vec<Event> {
+        let mut queue = self.queue.lock();
+        let n = limit.unwrap_or(queue.len()).min(queue.len());
+        queue.drain(..n).collect()
+    }
+}
+
+impl Subscriber {
+    fn wants(&self, kind: &str) -> bool {
+        self.kinds.as_ref().is_none_or(|kinds| kinds.contains(kind))
+    }
+}
+
+/// Публикует событие, если шина подключена; payload строится только тогда
+pub(crate) fn emit(bus: &RwLock<Option<SharedBus>>, kind: &str, source: &str, payload: impl FnOnce() -> Value) {
+    let bus = bus.read().clone();
+    if let Some(bus) = bus {
+        bus.emit(kind, source, payload());
+    }
+}
+
+// ── PyO3 класс ──
+
+#[pyclass(frozen)]
+pub struct EventBus {
+    bus: SharedBus,
+}
+
+impl EventBus {
+    pub(crate) fn new(max_queue: usize) -> Self {
+        Self { bus: Arc::new(Bus::new(max_queue)) }
+    }
+}
+
+#[pymethods]
+impl EventBus {
+    #[new]
+    #[pyo3(signature = (max_queue=None, *, config=None))]
+    fn py_new(max_queue: Option<usize>, config: Option<PyRef<'_, CoreConfig>>) -> Self {
+        Self::new(max_queue.unwrap_or(config::settings(config).events.max_queue))
+    }
+
+    /// callable(Event) на события kinds (None — все); возвращает id подписки
+    #[pyo3(signature = (callback, kinds=None))]
+    fn subscribe(&self, callback: Bound<'_, PyAny>, kinds: Option<Vec<String>>) -> PyResult<u64> {
+        if !callback.is_callable() {
+            return Err(PyValueError::new_err("callback должен быть вызываемым"));
+        }
+        let id = self.bus.next_subscriber.fetch_add(1, Ordering::Relaxed);
+        self.bus.subscribers.write().push(Subscriber {
+            id,
+            kinds: kinds.map(|k| k.into_iter().collect()),
+            callback: callback.unbind(),
+        });
+        Ok(id)
+    }
+
+    fn unsubscribe(&self, subscription_id: u64) -> bool {
+        let mut subscribers = self.bus.subscribers.write();
+        let before = subscribers.len();
+        subscribers.retain(|s| s.id != subscription_id);
+        subscribers.len() < before
+    }
+
+    /// Событие из Python (плагины): payload — dict с JSON-совместимыми значениями
+    #[pyo3(signature = (kind, payload=None, source="python"))]
+    fn emit(&self, py: Python<'_>, kind: &str, payload: Option<Bound<'_, PyDict>>, source: &str) -> PyResult<()> {
+        let payload = payload.map(|p| py_to_json(p.as_any())).transpose()?.unwrap_or(Value::Null);
+        py.allow_threads(|| self.bus.emit(kind, source, payload));
+        Ok(())
+    }
+
+    /// Забирает накопленные события (старые первыми); limit=None — все
+    #[pyo3(signature = (limit=None))]
+    pub(crate) fn drain_events(&self, limit: Option<usize>) -> Vec<Event> {
+        self.bus.drain(limit)
+    }
+
+    /// Событий в очереди
+    fn pending(&self) -> usize {
+        self.bus.queue.lock().len()
+    }
+
+    /// (опубликовано, вытеснено из очереди, подписчиков)
+    fn get_stats(&self) -> (u64, u64, usize) {
+        (
+            self.bus.seq.load(Ordering::Relaxed),
+            self.bus.dropped.load(Ordering::Relaxed),
+            self.bus.subscribers.read().len(),
+        )
+    }
+}
+
+impl EventBus {
+    pub(crate) fn shared(&self) -> SharedBus {
+        Arc::clone(&self.bus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_queue_and_drain() {
+        let bus = EventBus::new(3);
+        for i in 0..5 {
+            bus.bus.emit("tick", "test", json!({ "i": i }));
+        }
+        assert_eq!(bus.pending(), 3);
+        assert_eq!(bus.get_stats(), (5, 2, 0));
+        let first = bus.drain_events(Some(2));
+        assert_eq!(first.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(first[0].payload["i"], json!(2));
+        assert_eq!(bus.drain_events(None).len(), 1);
+        assert_eq!(bus.pending(), 0);
+    }
+
+    #[test]
+    fn test_emit_helper_and_disabled_queue() {
+        let slot: RwLock<Option<SharedBus>> = RwLock::new(None);
+        let mut built = false;
+        emit(&slot, "tick", "test", || {
+            built = true;
+            Value::Null
+        });
+        assert!(!built);
+
+        let bus = EventBus::new(10);
+        *slot.write() = Some(bus.shared());
+        emit(&slot, "tick", "test", || json!(42));
+        let events = bus.drain_events(None);
+        assert_eq!(events[0].payload["value"], json!(42));
+        assert_eq!(events[0].kind, "tick");
+
+        let silent = EventBus::new(0);
+        silent.bus.emit("tick", "test", Value::Null);
+        assert_eq!(silent.pending(), 0);
+    }
+}
@@ -0,0 +1,253 @@
+//! IntentClassifier — классификация намерений по шаблонам фраз
+//!
+//! - Шаблон: фраза, сопоставляемая по основам слов ("напомни" ≈ "напомнить");
+//!   "*" — любой промежуток: "напомни * через" совпадёт с "напомни мне позвонить через час"
+//! - Все куски всех шаблонов — в одном автомате Aho-Corasick, один проход по тексту
+//! - Вес совпадения: weight × число слов шаблона (длинные фразы специфичнее)
+//! - Уверенность: доля веса намерения среди всех совпавших
+//! - translit=True: шаблон совпадает и с текстом в транслите ("napomni cherez chas")
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use aho_corasick::AhoCorasick;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+use crate::stemmer::stem_word;
+use crate::translit::{has_latin, to_cyrillic};
+
+/// Основы слов через пробел, с пробелами по краям — границы слов для автомата
+fn stem_line(text: &str) -> String {
+    let mut line = String::from(" ");
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        line.push_str(&stem_word(&word.to_lowercase().replace('ё', "е")));
+        line.push(' ');
+    }
+    line
+}
+
+struct Pattern {
+    intent: usize,
+    /// Индексы кусков в автомате, по порядку
+    segments: Vec<usize>,
+    weight: f64,
+    words: usize,
+}
+
+/// Скомпилированные шаблоны всех намерений
+struct Compiled {
+    ac: Option<AhoCorasick>,
+    names: Vec<String>,
+    patterns: Vec<Pattern>,
+}
+
+impl Compiled {
+    fn build(intents: &HashMap<String, Vec<(String, f64)>>) -> Result<Self, String> {
+        let mut names: Vec<String> = intents.keys().cloned().collect();
+        names.sort();
+        let mut pieces: Vec<String> = Vec::new();
+        let mut piece_ids: HashMap<String, usize> = HashMap::new();
+        let mut patterns = Vec::new();
+        for (intent, name) in names.iter().enumerate() {
+            for (pattern, weight) in &intents[name] {
+                let mut segments = Vec::new();
+                let mut words = 0;
+                for part in pattern.split('*') {
+                    let line = stem_line(part);
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    words += line.split_whitespace().count();
+                    let id = *piece_ids.entry(line.clone()).or_insert_with(|| {
+                        pieces.push(line);
+                        pieces.len() - 1
+                    });
+                    segments.push(id);
+                }
+                if !segments.is_empty() {
+                    patterns.push(Pattern { intent, segments, weight: *weight, words });
+                }
+            }
+        }
+        let ac = if pieces.is_empty() { None } else { Some(AhoCorasick::new(&pieces).map_err(|e| e.to_string())?) };
+        Ok(Self { ac, names, patterns })
+    }
+
+    /// Вхождения кусков в текст: piece → [(start, end)] в stem_line
+    fn occurrences(ac: &AhoCorasick, text: &str) -> HashMap<usize, Vec<(usize, usize)>> {
+        let line = stem_line(text);
+        let mut occurrences: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+        for m in ac.find_overlapping_iter(&line) {
+            occurrences.entry(m.pattern().as_usize()).or_default().push((m.start(), m.end()));
+        }
+        occurrences
+    }
+
+    /// Куски шаблона встречаются по порядку; соседние делят пробел на границе
+    fn matched(pattern: &Pattern, occurrences: &HashMap<usize, Vec<(usize, usize)>>) -> bool {
+        let mut pos = 0;
+        pattern.segments.iter().all(|seg| {
+            let next = occurrences.get(seg).and_then(|occ| occ.iter().find(|&&(s, _)| s >= pos));
+            match next {
+                Some(&(_, e)) => {
+                    pos = e - 1;
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+
+    /// [(intent, score)] совпавших намерений; translit — сверять ещё и с to_cyrillic(text)
+    fn scores(&self, text: &str, translit: bool) -> Vec<(usize, f64)> {
+        let Some(ac) = &self.ac else {
+            return Vec::new();
+        };
+        let occurrences = Self::occurrences(ac, text);
+        let converted = (translit && has_latin(text)).then(|| Self::occurrences(ac, &to_cyrillic(text)));
+
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for pattern in &self.patterns {
+            let matched = Self::matched(pattern, &occurrences)
+                || converted.as_ref().is_some_and(|occ| Self::matched(pattern, occ));
+            if matched {
+                *scores.entry(pattern.intent).or_insert(0.0) += pattern.weight * pattern.words as f64;
+            }
+        }
+        scores.into_iter().collect()
+    }
+}
+
+#[pyclass(frozen)]
+pub struct IntentClassifier {
+    /// Исходные шаблоны: intent → [(pattern, weight)]
+    intents: RwLock<HashMap<String, Vec<(String, f64)>>>,
+    compiled: RwLock<Compiled>,
+    threshold: f64,
+    translit: bool,
+}
+
+#[pymethods]
+impl IntentClassifier {
+    /// intents — {intent: [pattern, ...]}; threshold — минимальная уверенность для predict;
+    /// translit — распознавать русские шаблоны в тексте, набранном латиницей
+    #[new]
+    #[pyo3(signature = (intents=None, threshold=0.0, translit=true))]
+    fn new(intents: Option<HashMap<String, Vec<String>>>, threshold: f64, translit: bool) -> PyResult<Self> {
+        let source: HashMap<String, Vec<(String, f64)>> = intents
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, patterns)| (name, patterns.into_iter().map(|p| (p, 1.0)).collect()))
+            .collect();
+        let compiled = Compiled::build(&source).map_err(PyValueError::new_err)?;
+        Ok(Self { intents: RwLock::new(source), compiled: RwLock::new(compiled), threshold, translit })
+    }
+
+    /// Добавить шаблоны намерению (создаётся, если его нет)
+    #[pyo3(signature = (intent, patterns, weight=1.0))]
+    fn add_intent(&self, intent: &str, patterns: Vec<String>, weight: f64) -> PyResult<()> {
+        if !(weight.is_finite() && weight > 0.0) {
+            return Err(PyValueError::new_err(format!("Некорректный вес шаблона: {}", weight)));
+        }
+        let mut intents = self.intents.write();
+        intents.entry(intent.to_string()).or_default().extend(patterns.into_iter().map(|p| (p, weight)));
+        *self.compiled.write() = Compiled::build(&intents).map_err(PyValueError::new_err)?;
+        Ok(())
+    }
+
+    fn remove_intent(&self, intent: &str) -> PyResult<bool> {
+        let mut intents = self.intents.write();
+        if intents.remove(intent).is_none() {
+            return Ok(false);
+        }
+        *self.compiled.write() = Compiled::build(&intents).map_err(PyValueError::new_err)?;
+        Ok(true)
+    }
+
+    fn list_intents(&self) -> Vec<String> {
+        self.compiled.read().names.clone()
+    }
+
+    /// [(intent, confidence)] по убыванию уверенности
+    #[pyo3(signature = (text, top_k=3))]
+    fn classify(&self, text: &str, top_k: usize) -> Vec<(String, f64)> {
+        self.ranked(text, top_k)
+    }
+
+    /// Самое вероятное намерение, если его уверенность >= threshold
+    fn predict(&self, text: &str) -> Option<String> {
+        self.ranked(text, 1)
+            .into_iter()
+            .next()
+            .filter(|(_, confidence)| *confidence >= self.threshold)
+            .map(|(intent, _)| intent)
+    }
+}
+
+impl IntentClassifier {
+    fn ranked(&self, text: &str, top_k: usize) -> Vec<(String, f64)> {
+        let compiled = self.compiled.read();
+        let scores = compiled.scores(text, self.translit);
+        let total: f64 = scores.iter().map(|(_, s)| s).sum();
+        let mut ranked: Vec<(String, f64)> = scores
+            .into_iter()
+            .map(|(intent, score)| (compiled.names[intent].clone(), score / total))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(top_k);
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classifier() -> IntentClassifier {
+        let intents = HashMap::from([
+            ("reminder".to_string(), vec!["напомни * через".to_string(), "поставь напоминание".to_string()]),
+            ("weather".to_string(), vec!["погода".to_string(), "какая погода".to_string(), "weather".to_string()]),
+            ("greeting".to_string(), vec!["привет".to_string(), "добрый день".to_string()]),
+        ]);
+        IntentClassifier::new(Some(intents), 0.6, true).unwrap()
+    }
+
+    #[test]
+    fn test_morphology_and_wildcards() {
+        let c = classifier();
+        assert_eq!(c.predict("Напомни мне позвонить маме через час"), Some("reminder".to_string()));
+        assert_eq!(c.predict("Поставь напоминания на утро"), Some("reminder".to_string()));
+        // Порядок кусков важен
+        assert_eq!(c.predict("через час напомни"), None);
+        assert_eq!(c.predict("Какую погоду обещают?"), Some("weather".to_string()));
+        assert_eq!(c.predict("Расскажи анекдот"), None);
+        // Транслит
+        assert_eq!(c.predict("napomni pozvonit cherez chas"), Some("reminder".to_string()));
+        assert_eq!(c.predict("kakaya pogoda?"), Some("weather".to_string()));
+    }
+
+    #[test]
+    fn test_ranking_and_threshold() {
+        let c = classifier();
+        let ranked = c.classify("Привет! Какая погода сегодня?", 3);
+        assert_eq!(ranked[0].0, "weather");
+        assert_eq!(ranked[1].0, "greeting");
+        assert!((ranked.iter().map(|(_, s)| s).sum::<f64>() - 1.0).abs() < 1e-9);
+        // weather 3 (1+2 слова) против greeting 1 → 0.75 ≥ 0.6
+        assert_eq!(c.predict("Привет! Какая погода сегодня?"), Some("weather".to_string()));
+        assert_eq!(c.predict("привет, погода"), None);
+    }
+
+    #[test]
+    fn test_add_and_remove_intents() {
+        let c = IntentClassifier::new(None, 0.0, true).unwrap();
+        assert!(c.classify("что угодно", 3).is_empty());
+        c.add_intent("music", vec!["включи музыку".into(), "play * song".into()], 2.0).unwrap();
+        assert_eq!(c.classify("Включи музыку погромче", 1), vec![("music".to_string(), 1.0)]);
+        assert_eq!(c.predict("play that song again"), Some("music".to_string()));
+        assert!(c.add_intent("bad", vec!["x".into()], 0.0).is_err());
+        assert!(c.remove_intent("music").unwrap());
+        assert!(!c.remove_intent("music").unwrap());
+        assert!(c.list_intents().is_empty());
+    }
+}
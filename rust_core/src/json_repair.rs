@@ -0,0 +1,515 @@
+//! repair_json — починка JSON из ответов LLM
+//!
+//! - Markdown-ограждения ```json ... ``` и текст вокруг JSON отбрасываются
+//! - Висячие запятые, пропущенные запятые и двоеточия
+//! - Одинарные кавычки, ключи без кавычек, True/False/None/undefined/NaN
+//! - Неэкранированные переводы строк, табуляции и кавычки внутри строк
+//! - Комментарии // и /* */
+//! - Обрезанный вывод: незакрытые строки, объекты и массивы закрываются,
+//!   ключ без значения получает null
+//! - Результат — JsonRepair: валидный компактный JSON и список исправлений
+//!   либо ошибка с позицией (строка:столбец в исправленном тексте)
+
+use pyo3::prelude::*;
+use serde_json::Value;
+
+use crate::errors::ParseError;
+use crate::event_bus::json_to_py;
+
+#[pyclass(frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct JsonRepair {
+    /// Валидный компактный JSON; None — починить не удалось
+    pub json: Option<String>,
+    /// Что было исправлено: "висячая запятая", "одинарные кавычки", ...
+    pub fixes: Vec<String>,
+    /// Ошибка разбора после починки
+    pub error: Option<String>,
+    /// (строка, столбец) ошибки в исправленном тексте, с 1
+    pub position: Option<(usize, usize)>,
+}
+
+#[pymethods]
+impl JsonRepair {
+    #[getter]
+    fn ok(&self) -> bool {
+        self.json.is_some()
+    }
+
+    /// Исправлений не понадобилось
+    #[getter]
+    fn unchanged(&self) -> bool {
+        self.json.is_some() && self.fixes.is_empty()
+    }
+
+    /// Разобранное значение (dict / list / ...); ParseError, если починить не удалось
+    fn value(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let Some(json) = &self.json else {
+            return Err(ParseError::new_err(format!(
+                "JSON не починен: {}",
+                self.error.as_deref().unwrap_or("")
+            )));
+        };
+        let value: Value = serde_json::from_str(json).map_err(|e| ParseError::new_err(e.to_string()))?;
+        json_to_py(py, &value)
+    }
+
+    fn __repr__(&self) -> String {
+        match &self.json {
+            Some(_) => format!("JsonRepair(ok=True, fixes={:?})", self.fixes),
+            None => format!("JsonRepair(ok=False, error={:?})", self.error),
+        }
+    }
+}
+
+// ── Разбор ──
+
+/// Что ожидается в текущем контейнере
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// Ключ объекта (после '{' или ',')
+    Key,
+    /// Двоеточие после ключа
+    AfterKey,
+    /// Значение (после ':' в объекте, после '[' или ',' в массиве)
+    Value,
+    /// Запятая или закрывающая скобка
+    Done,
+}
+
+struct Frame {
+    /// '}' или ']'
+    close: char,
+    state: State,
+}
+
+struct Repairer<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    out: String,
+    stack: Vec<Frame>,
+    /// Значение верхнего уровня разобрано
+    done: bool,
+    fixes: &'a mut Vec<String>,
+}
+
+impl Repairer<'_> {
+    fn fix(&mut self, what: &str) {
+        if !self.fixes.iter().any(|f| f == what) {
+            self.fixes.push(what.to_string());
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    /// Следующий значимый символ после позиции from (пропуская пробелы)
+    fn next_significant(&self, from: usize) -> Option<char> {
+        self.chars[from.min(self.chars.len())..].iter().copied().find(|c| !c.is_whitespace())
+    }
+
+    /// Перед новым значением: вставляет пропущенные запятую или двоеточие;
+    /// true — значение будет ключом объекта
+    fn before_value(&mut self) -> bool {
+        let Some(frame) = self.stack.last_mut() else { return false };
+        match frame.state {
+            State::Key => true,
+            State::Value => false,
+            State::AfterKey => {
+                frame.state = State::Value;
+                self.out.push(':');
+                self.fix("пропущено двоеточие");
+                false
+            }
+            State::Done => {
+                let is_object = frame.close == '}';
+                frame.state = if is_object { State::Key } else { State::Value };
+                self.out.push(',');
+                self.fix("пропущена запятая");
+                is_object
+            }
+        }
+    }
+
+    /// Значение выведено: ключ ждёт двоеточия, прочее — запятой
+    fn after_value(&mut self, is_key: bool) {
+        match self.stack.last_mut() {
+            Some(frame) => frame.state = if is_key { State::AfterKey } else { State::Done },
+            None => self.done = true,
+        }
+    }
+
+    /// Закрывает текущий контейнер, достраивая недостающее
+    fn close_frame(&mut self) {
+        let Some(frame) = self.stack.pop() else { return };
+        match frame.state {
+            State::AfterKey => {
+                self.fix("ключ без значения");
+                self.out.push_str(":null");
+            }
+            State::Value if frame.close == '}' => {
+                self.fix("ключ без значения");
+                self.out.push_str("null");
+            }
+            State::Key | State::Value if self.out.ends_with(',') => {
+                self.fix("висячая запятая");
+                self.out.pop();
+            }
+            _ => {}
+        }
+        self.out.push(frame.close);
+        self.after_value(false);
+    }
+
+    fn comma(&mut self) {
+        let Some(frame) = self.stack.last_mut() else {
+            return self.fix("лишняя запятая");
+        };
+        let is_object = frame.close == '}';
+        match frame.state {
+            State::Done => {
+                frame.state = if is_object { State::Key } else { State::Value };
+                self.out.push(',');
+            }
+            State::AfterKey | State::Value if is_object => {
+                let missing = if frame.state == State::AfterKey { ":null," } else { "null," };
+                frame.state = State::Key;
+                self.out.push_str(missing);
+                self.fix("ключ без значения");
+            }
+            _ => self.fix("лишняя запятая"),
+        }
+    }
+
+    fn colon(&mut self) {
+        match self.stack.last_mut() {
+            Some(frame) if frame.state == State::AfterKey => {
+                frame.state = State::Value;
+                self.out.push(':');
+            }
+            _ => self.fix("лишнее двоеточие"),
+        }
+    }
+
+    fn string(&mut self, quote: char) {
+        if quote == '\'' {
+            self.fix("одинарные кавычки");
+        }
+        self.pos += 1;
+        self.out.push('"');
+        loop {
+            let Some(c) = self.peek(0) else {
+                self.fix("незакрытая строка");
+                break;
+            };
+            self.pos += 1;
+            match c {
+                '\\' => match self.peek(0) {
+                    Some('\'') => {
+                        self.out.push('\'');
+                        self.pos += 1;
+                    }
+                    Some(e @ ('"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' | 'u')) => {
+                        self.out.push('\\');
+                        self.out.push(e);
+                        self.pos += 1;
+                    }
+                    _ => {
+                        self.fix("некорректное экранирование");
+                        self.out.push_str("\\\\");
+                    }
+                },
+                c if c == quote => {
+                    // Кавычка внутри строки без экранирования: за концом строки
+                    // должен идти разделитель
+                    let closes = match self.next_significant(self.pos) {
+                        None => true,
+                        Some(next) => matches!(next, ',' | ':' | '}' | ']' | '"' | '\''),
+                    };
+                    if closes {
+                        break;
+                    }
+                    self.fix("неэкранированная кавычка в строке");
+                    self.out.push_str(if quote == '"' { "\\\"" } else { "'" });
+                }
+                '"' => self.out.push_str("\\\""),
+                '\n' => {
+                    self.fix("перевод строки внутри строки");
+                    self.out.push_str("\\n");
+                }
+                '\r' => {
+                    self.fix("перевод строки внутри строки");
+                    self.out.push_str("\\r");
+                }
+                '\t' => {
+                    self.fix("табуляция внутри строки");
+                    self.out.push_str("\\t");
+                }
+                c if (c as u32) < 0x20 => {
+                    self.fix("управляющий символ внутри строки");
+                    self.out.push_str(&format!("\\u{:04x}", c as u32));
+                }
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    /// Число, литерал или слово без кавычек
+    fn bare(&mut self, is_key: bool) {
+        let start = self.pos;
+        while let Some(c) = self.peek(0) {
+            if c.is_whitespace() || matches!(c, ',' | ':' | '{' | '}' | '[' | ']' | '"' | '\'') {
+                break;
+            }
+            self.pos += 1;
+        }
+        let word: String = self.chars[start..self.pos].iter().collect();
+        if is_key {
+            self.fix("ключ без кавычек");
+            self.out.push_str(&serde_json::to_string(&word).unwrap_or_default());
+            return;
+        }
+        let literal = match word.as_str() {
+            "true" | "false" | "null" => Some(word.as_str()),
+            "True" => Some("true"),
+            "False" => Some("false"),
+            "None" | "undefined" | "NaN" | "Infinity" | "-Infinity" => Some("null"),
+            _ => None,
+        };
+        if let Some(literal) = literal {
+            if literal != word {
+                self.fix("литерал не из JSON");
+            }
+            self.out.push_str(literal);
+        } else if let Some(number) = number(&word) {
+            if number != word {
+                self.fix("некорректное число");
+            }
+            self.out.push_str(&number);
+        } else if self.stack.is_empty() {
+            // Не JSON вовсе — пусть serde сообщит об ошибке
+            self.out.push_str(&word);
+        } else {
+            self.fix("строка без кавычек");
+            self.out.push_str(&serde_json::to_string(&word).unwrap_or_default());
+        }
+    }
+
+    /// Пропускает комментарий; false — это не комментарий
+    fn comment(&mut self) -> bool {
+        match self.peek(1) {
+            Some('/') => {
+                while self.peek(0).is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            }
+            Some('*') => {
+                self.pos += 2;
+                while self.pos < self.chars.len() && !(self.peek(0) == Some('*') && self.peek(1) == Some('/')) {
+                    self.pos += 1;
+                }
+                self.pos = (self.pos + 2).min(self.chars.len());
+            }
+            _ => return false,
+        }
+        self.fix("комментарии");
+        true
+    }
+
+    fn run(&mut self) {
+        while let Some(c) = self.peek(0) {
+            // Значение верхнего уровня разобрано — дальше текст модели
+            if self.done {
+                if self.chars[self.pos..].iter().any(|c| !c.is_whitespace()) {
+                    self.fix("текст после JSON");
+                }
+                return;
+            }
+            match c {
+                c if c.is_whitespace() => self.pos += 1,
+                '/' if self.comment() => {}
+                '{' | '[' => {
+                    self.before_value();
+                    self.pos += 1;
+                    self.out.push(c);
+                    let (close, state) = if c == '{' { ('}', State::Key) } else { (']', State::Value) };
+                    self.stack.push(Frame { close, state });
+                }
+                '}' | ']' => {
+                    self.pos += 1;
+                    if !self.stack.iter().any(|f| f.close == c) {
+                        self.fix("лишняя закрывающая скобка");
+                        continue;
+                    }
+                    // ']' при незакрытом вложенном объекте: закрываем и его
+                    if self.stack.last().is_some_and(|f| f.close != c) {
+                        self.fix("несовпадающая скобка");
+                        while self.stack.last().is_some_and(|f| f.close != c) {
+                            self.close_frame();
+                        }
+                    }
+                    self.close_frame();
+                }
+                ',' => {
+                    self.pos += 1;
+                    self.comma();
+                }
+                ':' => {
+                    self.pos += 1;
+                    self.colon();
+                }
+                '"' | '\'' => {
+                    let is_key = self.before_value();
+                    self.string(c);
+                    self.after_value(is_key);
+                }
+                _ => {
+                    let is_key = self.before_value();
+                    self.bare(is_key);
+                    self.after_value(is_key);
+                }
+            }
+        }
+        if !self.stack.is_empty() {
+            self.fix("обрезанный JSON");
+            while !self.stack.is_empty() {
+                self.close_frame();
+            }
+        }
+    }
+}
+
+/// Число в записи JSON: "+5" → "5", ".5" → "0.5", "5." → "5", "0x1F" → None
+fn number(word: &str) -> Option<String> {
+    let (sign, digits) = match word.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", word.strip_prefix('+').unwrap_or(word)),
+    };
+    let value: f64 = digits.parse().ok()?;
+    if !value.is_finite() || !digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    let mut fixed = digits.to_string();
+    if fixed.starts_with('.') {
+        fixed.insert(0, '0');
+    }
+    if let Some(stripped) = fixed.strip_suffix('.') {
+        fixed = stripped.to_string();
+    }
+    fixed = fixed.replace(".e", "e").replace(".E", "E");
+    // Ведущие нули ("007") JSON не допускает
+    let int_end = fixed.find(['.', 'e', 'E']).unwrap_or(fixed.len());
+    let trimmed = fixed[..int_end].trim_start_matches('0');
+    let int_part = if trimmed.is_empty() { "0" } else { trimmed };
+    Some(format!("{}{}{}", sign, int_part, &fixed[int_end..]))
+}
+
+/// Содержимое первого markdown-ограждения ```...```
+fn strip_fence(text: &str) -> Option<&str> {
+    let start = text.find("```")?;
+    let body = &text[start + 3..];
+    // Язык после ограждения: ```json
+    let body = &body[body.find('\n').map_or(0, |i| i + 1)..];
+    Some(body.find("```").map_or(body, |end| &body[..end]))
+}
+
+/// Починка без Python: (исправленный текст, исправления)
+pub(crate) fn repair(text: &str) -> (String, Vec<String>) {
+    let mut fixes = Vec::new();
+    let mut text = text.trim();
+    if let Some(inner) = strip_fence(text) {
+        fixes.push("markdown-ограждение".to_string());
+        text = inner.trim();
+    }
+    if let Some(start) = text.find(['{', '[']) {
+        if start > 0 {
+            fixes.push("текст перед JSON".to_string());
+            text = &text[start..];
+        }
+    }
+    let mut repairer = Repairer { chars: text.chars().collect(), pos: 0, out: String::new(), stack: Vec::new(), done: false, fixes: &mut fixes };
+    repairer.run();
+    let out = repairer.out;
+    (out, fixes)
+}
+
+/// Разбор с починкой: значение и исправления либо ошибка
+pub(crate) fn parse_lenient(text: &str) -> Result<(Value, Vec<String>), serde_json::Error> {
+    if let Ok(value) = serde_json::from_str(text) {
+        return Ok((value, Vec::new()));
+    }
+    let (fixed, fixes) = repair(text);
+    serde_json::from_str(&fixed).map(|value| (value, fixes))
+}
+
+pub(crate) fn repair_json_impl(text: &str) -> JsonRepair {
+    match serde_json::from_str::<Value>(text) {
+        Ok(value) => JsonRepair { json: Some(value.to_string()), fixes: Vec::new(), error: None, position: None },
+        Err(_) => {
+            let (fixed, fixes) = repair(text);
+            match serde_json::from_str::<Value>(&fixed) {
+                Ok(value) => JsonRepair { json: Some(value.to_string()), fixes, error: None, position: None },
+                Err(e) => JsonRepair {
+                    json: None,
+                    fixes,
+                    error: Some(e.to_string()),
+                    position: Some((e.line(), e.column())),
+                },
+            }
+        }
+    }
+}
+
+/// Чинит типичные повреждения JSON в выводе LLM → JsonRepair
+#[pyfunction]
+pub fn repair_json(py: Python<'_>, text: &str) -> JsonRepair {
+    py.allow_threads(|| repair_json_impl(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fixed(text: &str) -> Value {
+        let result = repair_json_impl(text);
+        serde_json::from_str(result.json.as_deref().unwrap_or_else(|| panic!("{:?}: {:?}", text, result.error))).unwrap()
+    }
+
+    #[test]
+    fn test_common_damage() {
+        assert_eq!(fixed(r#"{"a": 1, "b": [1, 2,],}"#), json!({"a": 1, "b": [1, 2]}));
+        assert_eq!(fixed("{'name': 'web_search', 'ok': True, 'x': None}"), json!({"name": "web_search", "ok": true, "x": null}));
+        assert_eq!(fixed("{name: \"a\nb\", count: +5, ratio: .5}"), json!({"name": "a\nb", "count": 5, "ratio": 0.5}));
+        assert_eq!(fixed("Вот ответ:\n```json\n{\"a\": 1}\n```\nГотово."), json!({"a": 1}));
+        assert_eq!(fixed(r#"{"a": 1 "b": 2} и ещё текст"#), json!({"a": 1, "b": 2}));
+        assert_eq!(fixed("{\"a\": 1, // комментарий\n \"b\": /* x */ 2}"), json!({"a": 1, "b": 2}));
+        assert_eq!(fixed(r#"{"say": "он сказал "привет" и ушёл"}"#), json!({"say": "он сказал \"привет\" и ушёл"}));
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert_eq!(fixed(r#"{"items": [{"id": 1}, {"id": 2, "name": "Каз"#), json!({"items": [{"id": 1}, {"id": 2, "name": "Каз"}]}));
+        assert_eq!(fixed(r#"{"a": 1, "b":"#), json!({"a": 1, "b": null}));
+        assert_eq!(fixed(r#"{"a": 1, "b"#), json!({"a": 1, "b": null}));
+        assert_eq!(fixed(r#"[1, 2, "#), json!([1, 2]));
+        let result = repair_json_impl(r#"{"a": [1, 2"#);
+        assert!(result.fixes.contains(&"обрезанный JSON".to_string()));
+    }
+
+    #[test]
+    fn test_valid_and_hopeless() {
+        let valid = repair_json_impl(r#"{ "a" : [1, 2.5e3] }"#);
+        assert_eq!(valid.json.as_deref(), Some(r#"{"a":[1,2500.0]}"#));
+        assert!(valid.fixes.is_empty());
+
+        let hopeless = repair_json_impl("просто текст без JSON");
+        assert!(hopeless.json.is_none());
+        assert!(hopeless.error.is_some());
+        assert!(hopeless.position.is_some());
+
+        assert_eq!(parse_lenient("{'a': 1,}").unwrap().0, json!({"a": 1}));
+    }
+}
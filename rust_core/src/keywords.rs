@@ -0,0 +1,192 @@
+//! KeywordExtractor — ключевые фразы методом RAKE
+//!
+//! - Кандидаты: серии слов между стоп-словами и знаками препинания
+//! - Вес слова: degree / frequency (насколько слово "тянет" длинные фразы)
+//! - Вес фразы: сумма весов слов; статистика считается по основам (стемминг RU/EN),
+//!   так что "переезд" и "переезда" — одно слово
+//! - Стоп-слова: общий RU/EN список + пользовательские
+
+use pyo3::prelude::*;
+use parking_lot::RwLock;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::bm25::STOP_WORDS;
+use crate::config::{self, CoreConfig};
+use crate::stemmer::stem_word;
+
+/// Знаки, которые не разрывают слово
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '\''
+}
+
+struct Candidate {
+    /// Фраза в нижнем регистре, как в первом вхождении
+    text: String,
+    stems: Vec<String>,
+}
+
+#[pyclass(frozen)]
+pub struct KeywordExtractor {
+    max_words: usize,
+    min_chars: usize,
+    stop_words: RwLock<HashSet<String>>,
+}
+
+impl KeywordExtractor {
+    pub(crate) fn new(max_words: usize, min_chars: usize, stop_words: Option<Vec<String>>) -> Self {
+        let extractor = Self {
+            max_words: max_words.max(1),
+            min_chars,
+            stop_words: RwLock::new(STOP_WORDS.iter().map(|w| w.to_string()).collect()),
+        };
+        if let Some(words) = stop_words {
+            extractor.add_stop_words(words);
+        }
+        extractor
+    }
+}
+
+#[pymethods]
+impl KeywordExtractor {
+    /// max_words — фразы длиннее отбрасываются; min_chars — более короткие
+    /// слова работают как разделители; стоп-слова из lexicons.stop_words config
+    /// добавляются к stop_words
+    #[new]
+    #[pyo3(signature = (max_words=3, min_chars=3, stop_words=None, *, config=None))]
+    fn py_new(
+        max_words: usize,
+        min_chars: usize,
+        stop_words: Option<Vec<String>>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> Self {
+        let extractor = Self::new(max_words, min_chars, stop_words);
+        extractor.add_stop_words(config::settings(config).lexicons.stop_words.clone());
+        extractor
+    }
+
+    fn add_stop_words(&self, words: Vec<String>) {
+        let mut stop = self.stop_words.write();
+        stop.extend(words.iter().map(|w| w.trim().to_lowercase().replace('ё', "е")).filter(|w| !w.is_empty()));
+    }
+
+    /// [(фраза, вес)] по убыванию веса
+    #[pyo3(signature = (text, top_n=10))]
+    fn extract(&self, text: &str, top_n: usize) -> Vec<(String, f64)> {
+        self.keyphrases(text, top_n)
+    }
+
+    /// Пакетная версия extract через Rayon без GIL
+    #[pyo3(signature = (texts, top_n=10))]
+    fn extract_many(&self, py: Python<'_>, texts: Vec<String>, top_n: usize) -> Vec<Vec<(String, f64)>> {
+        py.allow_threads(|| texts.par_iter().map(|t| self.keyphrases(t, top_n)).collect())
+    }
+}
+
+// ── RAKE ──
+
+impl KeywordExtractor {
+    pub(crate) fn keyphrases(&self, text: &str, top_n: usize) -> Vec<(String, f64)> {
+        let candidates = self.candidates(text);
+
+        let mut frequency: HashMap<&str, f64> = HashMap::new();
+        let mut degree: HashMap<&str, f64> = HashMap::new();
+        for candidate in &candidates {
+            for stem in &candidate.stems {
+                *frequency.entry(stem).or_insert(0.0) += 1.0;
+                *degree.entry(stem).or_insert(0.0) += candidate.stems.len() as f64;
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut phrases: Vec<(String, f64)> = Vec::new();
+        for candidate in &candidates {
+            if !seen.insert(candidate.stems.join(" ")) {
+                continue;
+            }
+            let score = candidate.stems.iter().map(|s| degree[s.as_str()] / frequency[s.as_str()]).sum();
+            phrases.push((candidate.text.clone(), score));
+        }
+        phrases.sort_by(|a, b| b.1.total_cmp(&a.1));
+        phrases.truncate(top_n);
+        phrases
+    }
+
+    fn candidates(&self, text: &str) -> Vec<Candidate> {
+        let stop = self.stop_words.read();
+        let mut candidates = Vec::new();
+        let mut current: Vec<String> = Vec::new();
+        let mut flush = |current: &mut Vec<String>| {
+            if !current.is_empty() && current.len() <= self.max_words {
+                candidates.push(Candidate {
+                    text: current.join(" "),
+                    stems: current.iter().map(|w| stem_word(w)).collect(),
+                });
+            }
+            current.clear();
+        };
+
+        let mut word = String::new();
+        for c in text.chars().chain(std::iter::once('.')) {
+            if is_word_char(c) {
+                word.push(c);
+                continue;
+            }
+            if !word.is_empty() {
+                let lower = word.trim_matches(|c| c == '-' || c == '\'').to_lowercase().replace('ё', "е");
+                let delimiter = lower.chars().count() < self.min_chars
+                    || stop.contains(&lower)
+                    || lower.chars().all(|c| c.is_numeric());
+                if delimiter {
+                    flush(&mut current);
+                } else {
+                    current.push(lower);
+                }
+                word.clear();
+            }
+            if !c.is_whitespace() {
+                flush(&mut current);
+            }
+        }
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rake_prefers_multiword_phrases() {
+        let extractor = KeywordExtractor::new(3, 3, None);
+        let text = "Compatibility of systems of linear constraints over the set of natural numbers. \
+                    Criteria of compatibility of a system of linear Diophantine equations are considered.";
+        let phrases = extractor.extract(text, 3);
+        assert_eq!(phrases[0].0, "linear diophantine equations");
+        assert!(phrases.iter().all(|(p, _)| !p.contains("of")));
+    }
+
+    #[test]
+    fn test_russian_morphology_merges_stats() {
+        let extractor = KeywordExtractor::new(3, 3, None);
+        let text = "Я планирую переезд в Москву. Для переезда нужна квартира, а квартиру найти сложно.";
+        let phrases = extractor.extract(text, 10);
+        let texts: Vec<&str> = phrases.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(texts.len(), 4);
+        assert!(texts.contains(&"переезда нужна квартира"));
+        // "переезд" встречается дважды в разных формах: degree 5 / freq 2
+        let score = |p: &str| phrases.iter().find(|(t, _)| t == p).unwrap().1;
+        assert!((score("планирую переезд") - 4.5).abs() < 1e-9);
+        assert!(!texts.iter().any(|p| p.split(' ').any(|w| w == "для" || w == "в")));
+    }
+
+    #[test]
+    fn test_custom_stop_words_and_numbers() {
+        let extractor = KeywordExtractor::new(2, 3, Some(vec!["Нужна".to_string()]));
+        let phrases = extractor.extract("Встреча 2024 года: нужна подготовка отчёта для руководства", 10);
+        let texts: Vec<&str> = phrases.iter().map(|(p, _)| p.as_str()).collect();
+        assert!(texts.contains(&"подготовка отчета"));
+        assert!(texts.contains(&"встреча"));
+        assert!(!texts.iter().any(|p| p.contains("2024") || p.contains("нужна")));
+    }
+}
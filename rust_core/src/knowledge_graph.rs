@@ -0,0 +1,419 @@
+//! KnowledgeGraph — граф знаний из троек (subject, predicate, object)
+//!
+//! - "кот_Барсик — принадлежит — пользователь": отношения, которые не
+//!   укладываются в плоское key→value семантической памяти
+//! - Индекс на каждой позиции: query с любым набором известных позиций
+//!   перебирает только самый короткий список кандидатов
+//! - Тройки уникальны; порядок выдачи — порядок добавления
+//! - neighbors / traverse / path — обход в обе стороны рёбер
+//! - Персистентность: JSON [[s, p, o], ...]; ошибки чтения и записи → PersistenceError
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use parking_lot::RwLock;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+
+use crate::async_io;
+use crate::errors::PersistenceError;
+
+type Triple = (String, String, String);
+
+// ── Хранилище ──
+
+#[derive(Default)]
+struct Graph {
+    triples: HashMap<u64, Triple>,
+    ids: HashMap<Triple, u64>,
+    by_subject: HashMap<String, BTreeSet<u64>>,
+    by_predicate: HashMap<String, BTreeSet<u64>>,
+    by_object: HashMap<String, BTreeSet<u64>>,
+    next_id: u64,
+}
+
+fn index_add(index: &mut HashMap<String, BTreeSet<u64>>, key: &str, id: u64) {
+    index.entry(key.to_string()).or_default().insert(id);
+}
+
+fn index_remove(index: &mut HashMap<String, BTreeSet<u64>>, key: &str, id: u64) {
+    if let Some(ids) = index.get_mut(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+impl Graph {
+    fn add(&mut self, triple: Triple) -> bool {
+        if self.ids.contains_key(&triple) {
+            return false;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        index_add(&mut self.by_subject, &triple.0, id);
+        index_add(&mut self.by_predicate, &triple.1, id);
+        index_add(&mut self.by_object, &triple.2, id);
+        self.ids.insert(triple.clone(), id);
+        self.triples.insert(id, triple);
+        true
+    }
+
+    fn remove_id(&mut self, id: u64) -> bool {
+        let Some(triple) = self.triples.remove(&id) else {
+            return false;
+        };
+        index_remove(&mut self.by_subject, &triple.0, id);
+        index_remove(&mut self.by_predicate, &triple.1, id);
+        index_remove(&mut self.by_object, &triple.2, id);
+        self.ids.remove(&triple);
+        true
+    }
+
+    /// id троек по шаблону (None — любое значение), в порядке добавления
+    fn matching(&self, subject: Option<&str>, predicate: Option<&str>, object: Option<&str>) -> Vec<u64> {
+        let empty = BTreeSet::new();
+        let lists = [
+            subject.map(|s| self.by_subject.get(s).unwrap_or(&empty)),
+            predicate.map(|p| self.by_predicate.get(p).unwrap_or(&empty)),
+            object.map(|o| self.by_object.get(o).unwrap_or(&empty)),
+        ];
+        let Some(shortest) = lists.iter().flatten().min_by_key(|ids| ids.len()) else {
+            let mut all: Vec<u64> = self.triples.keys().copied().collect();
+            all.sort_unstable();
+            return all;
+        };
+        shortest
+            .iter()
+            .copied()
+            .filter(|id| {
+                let (s, p, o) = &self.triples[id];
+                subject.is_none_or(|x| x == s) && predicate.is_none_or(|x| x == p) && object.is_none_or(|x| x == o)
+            })
+            .collect()
+    }
+
+    /// Рёбра узла: (predicate, сосед, исходящее ли ребро)
+    fn edges(&self, node: &str, predicate: Option<&str>, outgoing: bool, incoming: bool) -> Vec<(String, String, bool)> {
+        let mut ids: Vec<(u64, bool)> = Vec::new();
+        if outgoing {
+            ids.extend(self.matching(Some(node), predicate, None).into_iter().map(|id| (id, true)));
+        }
+        if incoming {
+            ids.extend(self.matching(None, predicate, Some(node)).into_iter().map(|id| (id, false)));
+        }
+        ids.sort_unstable();
+        ids.into_iter()
+            .map(|(id, out)| {
+                let (s, p, o) = &self.triples[&id];
+                (p.clone(), if out { o.clone() } else { s.clone() }, out)
+            })
+            .collect()
+    }
+
+    fn sorted_triples(&self) -> Vec<Triple> {
+        self.matching(None, None, None).into_iter().map(|id| self.triples[&id].clone()).collect()
+    }
+}
+
+/// (исходящие, входящие) по direction: "out", "in" или "both"
+fn parse_direction(direction: &str) -> PyResult<(bool, bool)> {
+    match direction {
+        "out" => Ok((true, false)),
+        "in" => Ok((false, true)),
+        "both" => Ok((true, true)),
+        other => Err(PyValueError::new_err(format!(
+            "Неизвестное направление: {} (ожидается out, in или both)",
+            other
+        ))),
+    }
+}
+
+// ── PyO3 класс ──
+
+#[pyclass(frozen)]
+pub struct KnowledgeGraph {
+    path: Option<PathBuf>,
+    graph: RwLock<Graph>,
+}
+
+#[pymethods]
+impl KnowledgeGraph {
+    /// path — JSON-файл графа; загружается, если существует
+    #[new]
+    #[pyo3(signature = (path=None))]
+    pub(crate) fn new(path: Option<&str>) -> PyResult<Self> {
+        let kg = Self { path: path.map(PathBuf::from), graph: RwLock::new(Graph::default()) };
+        if kg.path.as_ref().is_some_and(|p| p.exists()) {
+            kg.load(None)?;
+        }
+        Ok(kg)
+    }
+
+    /// Добавляет тройку; False, если такая уже есть
+    fn add(&self, subject: &str, predicate: &str, object: &str) -> bool {
+        self.graph.write().add((subject.to_string(), predicate.to_string(), object.to_string()))
+    }
+
+    /// Добавляет тройки; возвращает число новых
+    fn add_many(&self, triples: Vec<Triple>) -> usize {
+        let mut graph = self.graph.write();
+        triples.into_iter().filter(|t| graph.add(t.clone())).count()
+    }
+
+    fn remove(&self, subject: &str, predicate: &str, object: &str) -> bool {
+        let mut graph = self.graph.write();
+        let key = (subject.to_string(), predicate.to_string(), object.to_string());
+        match graph.ids.get(&key).copied() {
+            Some(id) => graph.remove_id(id),
+            None => false,
+        }
+    }
+
+    /// Удаляет тройки по шаблону (None — любое значение); возвращает их число
+    #[pyo3(signature = (subject=None, predicate=None, object=None))]
+    fn remove_matching(&self, subject: Option<&str>, predicate: Option<&str>, object: Option<&str>) -> usize {
+        let mut graph = self.graph.write();
+        let ids = graph.matching(subject, predicate, object);
+        ids.into_iter().filter(|&id| graph.remove_id(id)).count()
+    }
+
+    /// Удаляет узел со всеми его рёбрами; возвращает число удалённых троек
+    fn remove_node(&self, node: &str) -> usize {
+        let mut graph = self.graph.write();
+        let mut ids = graph.matching(Some(node), None, None);
+        ids.extend(graph.matching(None, None, Some(node)));
+        ids.into_iter().filter(|&id| graph.remove_id(id)).count()
+    }
+
+    fn contains(&self, subject: &str, predicate: &str, object: &str) -> bool {
+        self.graph
+            .read()
+            .ids
+            .contains_key(&(subject.to_string(), predicate.to_string(), object.to_string()))
+    }
+
+    /// Тройки по шаблону: query(subject="кот_Барсик"), query(predicate="принадлежит",
+    /// object="пользователь"); без аргументов — все
+    #[pyo3(signature = (subject=None, predicate=None, object=None, limit=None))]
+    fn query(&self, subject: Option<&str>, predicate: Option<&str>, object: Option<&str>, limit: Option<usize>) -> Vec<Triple> {
+        let graph = self.graph.read();
+        graph
+            .matching(subject, predicate, object)
+            .into_iter()
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|id| graph.triples[&id].clone())
+            .collect()
+    }
+
+    /// Соседи узла: [(predicate, node, direction)], direction — "out" или "in"
+    #[pyo3(signature = (node, predicate=None, direction="both"))]
+    fn neighbors(&self, node: &str, predicate: Option<&str>, direction: &str) -> PyResult<Vec<(String, String, String)>> {
+        let (outgoing, incoming) = parse_direction(direction)?;
+        Ok(self
+            .graph
+            .read()
+            .edges(node, predicate, outgoing, incoming)
+            .into_iter()
+            .map(|(p, n, out)| (p, n, if out { "out" } else { "in" }.to_string()))
+            .collect())
+    }
+
+    /// Обход в ширину от start: [(node, depth)] без самого start
+    #[pyo3(signature = (start, max_depth=2, predicate=None, direction="both"))]
+    fn traverse(&self, start: &str, max_depth: usize, predicate: Option<&str>, direction: &str) -> PyResult<Vec<(String, usize)>> {
+        let (outgoing, incoming) = parse_direction(direction)?;
+        let graph = self.graph.read();
+        let mut seen: HashSet<String> = HashSet::from([start.to_string()]);
+        let mut queue = VecDeque::from([(start.to_string(), 0)]);
+        let mut found = Vec::new();
+        while let Some((node, depth)) = queue.pop_front() {
+            if depth == max_depth {
+                continue;
+            }
+            for (_, next, _) in graph.edges(&node, predicate, outgoing, incoming) {
+                if seen.insert(next.clone()) {
+                    found.push((next.clone(), depth + 1));
+                    queue.push_back((next, depth + 1));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Кратчайший путь между узлами (рёбра в любую сторону) как список троек;
+    /// None — пути нет в пределах max_depth
+    #[pyo3(signature = (source, target, max_depth=4))]
+    fn path(&self, source: &str, target: &str, max_depth: usize) -> Option<Vec<Triple>> {
+        if source == target {
+            return Some(Vec::new());
+        }
+        let graph = self.graph.read();
+        // узел → (предыдущий узел, тройка ребра)
+        let mut parent: HashMap<String, (String, Triple)> = HashMap::new();
+        let mut queue = VecDeque::from([(source.to_string(), 0)]);
+        while let Some((node, depth)) = queue.pop_front() {
+            if depth == max_depth {
+                continue;
+            }
+            for (p, next, out) in graph.edges(&node, None, true, true) {
+                if next == source || parent.contains_key(&next) {
+                    continue;
+                }
+                let triple = if out {
+                    (node.clone(), p, next.clone())
+                } else {
+                    (next.clone(), p, node.clone())
+                };
+                parent.insert(next.clone(), (node.clone(), triple));
+                if next == target {
+                    let mut path = Vec::new();
+                    let mut current = target.to_string();
+                    while let Some((prev, triple)) = parent.get(&current) {
+                        path.push(triple.clone());
+                        current = prev.clone();
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                queue.push_back((next, depth + 1));
+            }
+        }
+        None
+    }
+
+    /// Все узлы (субъекты и объекты), отсортированные
+    fn nodes(&self) -> Vec<String> {
+        let graph = self.graph.read();
+        let nodes: BTreeSet<&String> = graph.by_subject.keys().chain(graph.by_object.keys()).collect();
+        nodes.into_iter().cloned().collect()
+    }
+
+    /// Предикаты с числом троек, по убыванию
+    fn predicates(&self) -> Vec<(String, usize)> {
+        let graph = self.graph.read();
+        let mut predicates: Vec<(String, usize)> = graph.by_predicate.iter().map(|(p, ids)| (p.clone(), ids.len())).collect();
+        predicates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        predicates
+    }
+
+    fn clear(&self) {
+        *self.graph.write() = Graph::default();
+    }
+
+    /// Сохраняет граф в JSON (path — иначе путь из конструктора)
+    #[pyo3(signature = (path=None))]
+    fn save(&self, path: Option<&str>) -> PyResult<()> {
+        let path = self.resolve_path(path)?;
+        let triples = self.graph.read().sorted_triples();
+        let data = serde_json::to_string(&triples).map_err(|e| PersistenceError::new_err(e.to_string()))?;
+        std::fs::write(&path, data).map_err(|e| PersistenceError::new_err(format!("Не удалось записать {}: {}", path.display(), e)))
+    }
+
+    /// Заменяет граф содержимым JSON-файла
+    #[pyo3(signature = (path=None))]
+    fn load(&self, path: Option<&str>) -> PyResult<()> {
+        let path = self.resolve_path(path)?;
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| PersistenceError::new_err(format!("Не удалось прочитать {}: {}", path.display(), e)))?;
+        let triples: Vec<Triple> = serde_json::from_str(&data)
+            .map_err(|e| PersistenceError::new_err(format!("Некорректный файл графа {}: {}", path.display(), e)))?;
+        let mut graph = Graph::default();
+        for triple in triples {
+            graph.add(triple);
+        }
+        *self.graph.write() = graph;
+        Ok(())
+    }
+
+    /// save / load без блокировки цикла asyncio
+    #[pyo3(signature = (path=None))]
+    fn save_async(slf: Py<Self>, py: Python<'_>, path: Option<String>) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().save(path.as_deref()))
+    }
+
+    #[pyo3(signature = (path=None))]
+    fn load_async(slf: Py<Self>, py: Python<'_>, path: Option<String>) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().load(path.as_deref()))
+    }
+
+    fn __len__(&self) -> usize {
+        self.graph.read().triples.len()
+    }
+}
+
+impl KnowledgeGraph {
+    fn resolve_path(&self, path: Option<&str>) -> PyResult<PathBuf> {
+        path.map(PathBuf::from)
+            .or_else(|| self.path.clone())
+            .ok_or_else(|| PyValueError::new_err("Не задан путь к файлу графа"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> KnowledgeGraph {
+        let kg = KnowledgeGraph::new(None).unwrap();
+        kg.add("кот_Барсик", "принадлежит", "пользователь");
+        kg.add("кот_Барсик", "любит", "рыба");
+        kg.add("пользователь", "живёт_в", "Казань");
+        kg.add("Казань", "находится_в", "Россия");
+        kg
+    }
+
+    #[test]
+    fn test_add_and_query() {
+        let kg = sample();
+        assert!(!kg.add("кот_Барсик", "любит", "рыба"));
+        assert_eq!(kg.__len__(), 4);
+        assert_eq!(kg.query(Some("кот_Барсик"), None, None, None).len(), 2);
+        assert_eq!(
+            kg.query(None, Some("принадлежит"), Some("пользователь"), None),
+            vec![("кот_Барсик".to_string(), "принадлежит".to_string(), "пользователь".to_string())]
+        );
+        assert!(kg.query(Some("собака"), None, None, None).is_empty());
+        assert_eq!(kg.query(None, None, None, Some(3)).len(), 3);
+    }
+
+    #[test]
+    fn test_neighbors_and_traverse() {
+        let kg = sample();
+        let neighbors = kg.neighbors("пользователь", None, "both").unwrap();
+        assert_eq!(
+            neighbors,
+            vec![
+                ("принадлежит".to_string(), "кот_Барсик".to_string(), "in".to_string()),
+                ("живёт_в".to_string(), "Казань".to_string(), "out".to_string()),
+            ]
+        );
+        assert!(kg.neighbors("пользователь", None, "sideways").is_err());
+
+        let reached = kg.traverse("кот_Барсик", 2, None, "out").unwrap();
+        assert_eq!(
+            reached,
+            vec![("пользователь".to_string(), 1), ("рыба".to_string(), 1), ("Казань".to_string(), 2)]
+        );
+        let path = kg.path("рыба", "Россия", 4).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(path[0].2, "рыба");
+        assert!(kg.path("рыба", "Россия", 2).is_none());
+    }
+
+    #[test]
+    fn test_remove_and_persistence() {
+        let file = std::env::temp_dir().join(format!("kristina_kg_{}.json", std::process::id()));
+        let kg = sample();
+        assert_eq!(kg.remove_node("Казань"), 2);
+        assert!(kg.remove("кот_Барсик", "любит", "рыба"));
+        assert_eq!(kg.__len__(), 1);
+        assert_eq!(kg.nodes(), vec!["кот_Барсик", "пользователь"]);
+
+        kg.save(file.to_str()).unwrap();
+        let loaded = KnowledgeGraph::new(file.to_str()).unwrap();
+        assert!(loaded.contains("кот_Барсик", "принадлежит", "пользователь"));
+        assert_eq!(loaded.__len__(), 1);
+        std::fs::remove_file(&file).ok();
+    }
+}
@@ -0,0 +1,135 @@
+//! Определение языка текста по профилям символьных триграмм (ru / uk / en)
+//!
+//! - Письменность: доля кириллицы и латиницы среди букв
+//! - Кириллица: ru vs uk по частым триграммам + характерным буквам
+//!   (ы э ъ ё — только ru; і ї є ґ — только uk)
+//! - Латиница: en, уверенность зависит от доли частых английских триграмм
+//! - Нет букв — ("unknown", 0.0)
+
+use pyo3::prelude::*;
+use std::collections::HashMap;
+
+const EN_TRIGRAMS: &[&str] = &[
+    " th", "the", "he ", "and", "nd ", " an", "ing", "ng ", " to", "to ", " of", "of ", "ion", "ed ", " in",
+    "in ", "er ", "is ", " is", "es ", "at ", "re ", "on ", "ent", "for", " fo", "hat", "tha", "his", "ter",
+    " be", "you", " yo", "ou ", "it ", " it", " wh", "ly ", "ere", "tio",
+];
+const RU_TRIGRAMS: &[&str] = &[
+    " пр", "ого", "ени", " на", "ть ", " по", "то ", " не", "не ", "ет ", "ов ", "ния", "ско", "ал ", "ия ",
+    "что", " чт", "про", "ани", "ост", " ко", "ые ", "ать", "ова", "ем ", "ся ", "ий ", "ль ", "его", "как",
+    " ка", " во", "ой ", "ах ", " я ", "ешь", "ает", "ую ", "ый ", "ее ",
+];
+const UK_TRIGRAMS: &[&str] = &[
+    "ння", " і ", " на", " пр", "ти ", "ні ", "их ", " не", "не ", "ськ", "ій ", " ві", "від", " що", "що ",
+    "ть ", "ся ", "ої ", "ува", "ати", "ює ", "ає ", " як", "як ", "ств", "ого", "ці ", "ли ", " бу", "був",
+    "це ", " це", "ими", "ом ", "ки ", "ми ", " та", "та ", "ий ", "ала",
+];
+
+/// Буквы, встречающиеся только в одном из языков, и их вес
+const RU_ONLY: &[char] = &['ы', 'э', 'ъ', 'ё'];
+const UK_ONLY: &[char] = &['і', 'ї', 'є', 'ґ'];
+const UNIQUE_LETTER_WEIGHT: f64 = 3.0;
+
+/// Доля частых триграмм, при которой английский считается уверенным
+const EN_FULL_MATCH: f64 = 0.25;
+
+fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{0400}'..='\u{04FF}')
+}
+
+/// Триграммы нормализованного текста: буквы в нижнем регистре, прочее — пробел
+fn trigrams(text: &str) -> HashMap<String, usize> {
+    let mut normalized = String::from(" ");
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphabetic() {
+            normalized.push(c);
+        } else if !normalized.ends_with(' ') {
+            normalized.push(' ');
+        }
+    }
+    if !normalized.ends_with(' ') {
+        normalized.push(' ');
+    }
+    let chars: Vec<char> = normalized.chars().collect();
+    let mut counts = HashMap::new();
+    for w in chars.windows(3) {
+        *counts.entry(w.iter().collect::<String>()).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn profile_hits(grams: &HashMap<String, usize>, profile: &[&str]) -> f64 {
+    profile.iter().filter_map(|g| grams.get(*g)).sum::<usize>() as f64
+}
+
+/// (код языка, уверенность 0.0–1.0)
+pub(crate) fn detect(text: &str) -> (&'static str, f64) {
+    let (mut cyrillic, mut latin) = (0usize, 0usize);
+    let (mut ru_letters, mut uk_letters) = (0usize, 0usize);
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if is_cyrillic(c) {
+            cyrillic += 1;
+            ru_letters += RU_ONLY.contains(&c) as usize;
+            uk_letters += UK_ONLY.contains(&c) as usize;
+        } else if c.is_ascii_alphabetic() {
+            latin += 1;
+        }
+    }
+    let letters = cyrillic + latin;
+    if letters == 0 {
+        return ("unknown", 0.0);
+    }
+    let grams = trigrams(text);
+
+    if latin > cyrillic {
+        let share = latin as f64 / letters as f64;
+        let total = grams.values().sum::<usize>().max(1) as f64;
+        let matched = (profile_hits(&grams, EN_TRIGRAMS) / total / EN_FULL_MATCH).min(1.0);
+        return ("en", share * (0.5 + 0.5 * matched));
+    }
+
+    let share = cyrillic as f64 / letters as f64;
+    let ru = profile_hits(&grams, RU_TRIGRAMS) + ru_letters as f64 * UNIQUE_LETTER_WEIGHT;
+    let uk = profile_hits(&grams, UK_TRIGRAMS) + uk_letters as f64 * UNIQUE_LETTER_WEIGHT;
+    // Без совпадений — скорее русский (основной язык), но неуверенно
+    let (lang, margin) = match (ru, uk) {
+        (r, u) if r + u == 0.0 => ("ru", 0.5),
+        (r, u) if u > r => ("uk", u / (r + u)),
+        (r, u) => ("ru", r / (r + u)),
+    };
+    (lang, share * margin)
+}
+
+/// Язык текста по символьным триграммам → (lang_code, confidence);
+/// lang_code: "ru", "uk", "en" или "unknown"
+#[pyfunction]
+pub fn detect_language(text: &str) -> (String, f64) {
+    let (lang, confidence) = detect(text);
+    (lang.to_string(), confidence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_languages() {
+        assert_eq!(detect("Привет! Как дела? Что ты сегодня делаешь вечером?").0, "ru");
+        assert_eq!(detect("Привіт! Як справи? Що ти робиш сьогодні ввечері?").0, "uk");
+        assert_eq!(detect("Hello! How are you doing this evening?").0, "en");
+        assert_eq!(detect("12345 !!! 🙂"), ("unknown", 0.0));
+    }
+
+    #[test]
+    fn test_confidence_reflects_mixing() {
+        let (_, pure) = detect("The quick brown fox jumps over the lazy dog and the cat");
+        let (lang, mixed) = detect("The quick brown fox jumps over the lazy dog и кошка");
+        assert_eq!(lang, "en");
+        assert!(pure > mixed);
+        assert!(pure <= 1.0 && mixed > 0.0);
+
+        let (_, ru) = detect("Это был очень интересный разговор о жизни");
+        let (_, uk) = detect("Це була дуже цікава розмова про життя");
+        assert!(ru > 0.5 && uk > 0.5);
+    }
+}
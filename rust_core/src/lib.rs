@@ -1,33 +1,176 @@
-//! Кристина 6.0 — Высокопроизводительное Rust-ядро
-//!
-//! PyO3 модуль, предоставляющий:
-//! - MemoryEngine: управление памятью (working/episodic/semantic)
-//! - EmbeddingCache: lock-free кэш эмбеддингов
-//! - EmotionAnalyzer: Aho-Corasick анализ эмоций
-//! - ToolCallParser: парсер вызовов инструментов
-//! - ContextCompressor: сжатие контекста
-//! - ThreadTracker: отслеживание нитей разговора
-//! - cosine_similarity / batch_cosine_similarity: векторные операции
-
-use pyo3::prelude::*;
-
-mod similarity;
-mod memory_engine;
-mod embedding_cache;
-mod emotion_analyzer;
-mod tool_parser;
-mod context_compressor;
-mod thread_tracker;
-
-#[pymodule]
-fn kristina_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_class::<memory_engine::MemoryEngine>()?;
-    m.add_class::<embedding_cache::EmbeddingCache>()?;
-    m.add_class::<emotion_analyzer::EmotionAnalyzer>()?;
-    m.add_class::<tool_parser::ToolCallParser>()?;
-    m.add_class::<context_compressor::ContextCompressor>()?;
-    m.add_class::<thread_tracker::ThreadTracker>()?;
-    m.add_function(wrap_pyfunction!(similarity::cosine_similarity, m)?)?;
-    m.add_function(wrap_pyfunction!(similarity::batch_cosine_similarity, m)?)?;
-    Ok(())
-}
+//! Кристина 6.0 — Высокопроизводительное Rust-ядро
+//!
+//! PyO3 модуль, предоставляющий:
+//! - CoreConfig: общая конфигурация из TOML/JSON, передаётся конструкторам через config=
+//! - Storage: общее хранилище SQLite (kristina.db, WAL, пул соединений, vacuum/backup)
+//! - MemoryEngine: управление памятью (working/episodic/semantic), шифрование на диске по паролю
+//! - EmbeddingCache: lock-free кэш эмбеддингов
+//! - EmotionAnalyzer: Aho-Corasick анализ эмоций
+//! - IntentClassifier: намерения по шаблонам фраз (Aho-Corasick + стемминг)
+//! - EntityRecognizer: сущности по справочникам и эвристикам заглавных букв
+//! - ToolCallParser: парсер вызовов инструментов
+//! - ContextCompressor: сжатие контекста
+//! - ThreadTracker: отслеживание нитей разговора
+//! - MultiThreadTracker: нити по пользователям с общими настройками
+//! - Stemmer / stem: стемминг RU/EN (Snowball) — общий для всех модулей
+//! - Bm25Index: полнотекстовый BM25-индекс со стеммингом RU/EN
+//! - TextSplitter: рекурсивная нарезка документов на чанки с перекрытием
+//! - StreamAccumulator: потоковый вывод LLM по готовым предложениям, блокам кода и строкам ACTION
+//! - Deduplicator: почти-дубликаты через MinHash/SimHash и LSH
+//! - KeywordExtractor: ключевые фразы (RAKE) со стеммингом RU/EN
+//! - TfIdfVectorizer: разреженные TF-IDF векторы и косинусный поиск
+//! - PiiScrubber: поиск и маскирование персональных данных
+//! - ProfanityFilter: обнаружение и цензура мата (RU/EN, маски, транслит)
+//! - PromptTemplate: шаблоны промптов с условиями, циклами и бюджетами слотов
+//! - RateLimiter: token bucket на пользователя, общий для всех потоков Python
+//! - UserProfile: профиль пользователя (факты, эмоции, темы, часы активности) для промпта
+//! - SessionManager: сессии пользователей (рабочая память, нити, настроение) с выгрузкой по простою
+//! - KnowledgeGraph: граф знаний из троек (subject, predicate, object) с обходом
+//! - Pipeline: эмоции, нити, память и сжатие контекста за один вызов без GIL
+//! - EventBus: события компонентов (эпизоды, кэш, нити, всплески эмоций) для Python
+//! - Maintenance: фоновые сохранение, истечение нитей и сессий, очистка, checkpoint WAL по расписанию
+//! - Metrics: счётчики и гистограммы всех модулей, snapshot и экспорт в Prometheus
+//! - repair_json: починка JSON из вывода LLM (запятые, кавычки, обрезанный вывод)
+//! - strip_markdown / extract_structure: текст без markdown и структура документа со спанами
+//! - normalize: NFC, двойники латиница/кириллица, невидимые символы, пробелы
+//! - SpellChecker: исправление опечаток по частотным словарям RU/EN (SymSpell)
+//! - split_sentences: сегментация на предложения (сокращения, числа, URL)
+//! - transliterate: транслитерация RU ↔ латиница ("privet" ↔ "привет")
+//! - parse_datetime_ru: даты и время на русском ("завтра в 7 вечера", "через 15 минут")
+//! - detect_language: язык текста (ru/uk/en) по символьным триграммам
+//! - cosine_similarity / batch_cosine_similarity: векторные операции
+//!
+//! Методы ввода-вывода (save/load) имеют варианты *_async → asyncio.Future
+//!
+//! Несколько процессов-воркеров: shared=True (или runtime.shared в CoreConfig)
+//! у MemoryEngine, EmbeddingCache, ThreadTracker и MultiThreadTracker — рабочая
+//! память, нити и кэш общие через Storage в одном каталоге данных
+//!
+//! Логирование: записи ядра идут в Python logging, логгеры kristina_core.<модуль>
+//! (уровни кэшируются; после смены — refresh_log_levels())
+//!
+//! Исключения: KristinaError и его подклассы ParseError, MemoryError,
+//! CacheError, PersistenceError; некорректные аргументы — ValueError
+//!
+//! Free-threaded CPython (3.13t): модуль объявлен как не использующий GIL.
+//! Все классы frozen и Sync — состояние под DashMap / RwLock / атомиками,
+//! вызовы Python-колбэков идут без удерживаемых блокировок ядра
+
+use pyo3::prelude::*;
+
+mod similarity;
+mod memory_engine;
+mod embedding_cache;
+mod emotion_analyzer;
+mod tool_parser;
+mod context_compressor;
+mod thread_tracker;
+mod segmenter;
+mod stemmer;
+mod bm25;
+mod text_splitter;
+mod dedup;
+mod keywords;
+mod tfidf;
+mod language;
+mod pii;
+mod profanity;
+mod intent;
+mod entities;
+mod translit;
+mod datetime_ru;
+mod prompt_template;
+mod rate_limiter;
+mod session;
+mod knowledge_graph;
+mod pipeline;
+mod event_bus;
+mod metrics;
+mod json_repair;
+mod markdown;
+mod text_normalizer;
+mod spell_checker;
+mod async_io;
+mod errors;
+mod config;
+mod logging;
+mod storage;
+mod stream;
+mod user_profile;
+mod maintenance;
+mod crypto;
+
+#[pymodule(gil_used = false)]
+fn kristina_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    logging::init();
+    errors::register(m)?;
+    m.add_class::<config::CoreConfig>()?;
+    m.add_class::<storage::Storage>()?;
+    m.add_class::<memory_engine::MemoryEngine>()?;
+    m.add_class::<embedding_cache::EmbeddingCache>()?;
+    m.add_class::<emotion_analyzer::EmotionAnalyzer>()?;
+    m.add_class::<intent::IntentClassifier>()?;
+    m.add_class::<entities::EntityRecognizer>()?;
+    m.add_class::<entities::Entity>()?;
+    m.add_class::<tool_parser::ToolCallParser>()?;
+    m.add_class::<tool_parser::ToolCall>()?;
+    m.add_class::<tool_parser::PlanStep>()?;
+    m.add_class::<context_compressor::ContextCompressor>()?;
+    m.add_class::<context_compressor::CompressionReport>()?;
+    m.add_class::<context_compressor::ConversationBuffer>()?;
+    m.add_class::<thread_tracker::ThreadTracker>()?;
+    m.add_class::<thread_tracker::MultiThreadTracker>()?;
+    m.add_class::<stemmer::Stemmer>()?;
+    m.add_class::<bm25::Bm25Index>()?;
+    m.add_class::<text_splitter::TextSplitter>()?;
+    m.add_class::<text_splitter::TextChunk>()?;
+    m.add_class::<stream::StreamAccumulator>()?;
+    m.add_class::<stream::StreamSegment>()?;
+    m.add_class::<dedup::Deduplicator>()?;
+    m.add_class::<keywords::KeywordExtractor>()?;
+    m.add_class::<tfidf::TfIdfVectorizer>()?;
+    m.add_class::<pii::PiiScrubber>()?;
+    m.add_class::<pii::PiiMatch>()?;
+    m.add_class::<profanity::ProfanityFilter>()?;
+    m.add_class::<datetime_ru::DateTimeMatch>()?;
+    m.add_class::<prompt_template::PromptTemplate>()?;
+    m.add_class::<rate_limiter::RateLimiter>()?;
+    m.add_class::<session::SessionManager>()?;
+    m.add_class::<session::Session>()?;
+    m.add_class::<user_profile::UserProfile>()?;
+    m.add_class::<knowledge_graph::KnowledgeGraph>()?;
+    m.add_class::<pipeline::Pipeline>()?;
+    m.add_class::<pipeline::PipelineResult>()?;
+    m.add_class::<event_bus::EventBus>()?;
+    m.add_class::<event_bus::Event>()?;
+    m.add_class::<metrics::Metrics>()?;
+    m.add_class::<maintenance::Maintenance>()?;
+    m.add_class::<json_repair::JsonRepair>()?;
+    m.add_class::<markdown::MarkdownElement>()?;
+    m.add_class::<spell_checker::SpellChecker>()?;
+    m.add_function(wrap_pyfunction!(similarity::cosine_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(similarity::batch_cosine_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(segmenter::split_sentences, m)?)?;
+    m.add_function(wrap_pyfunction!(language::detect_language, m)?)?;
+    m.add_function(wrap_pyfunction!(stemmer::stem, m)?)?;
+    m.add_function(wrap_pyfunction!(translit::transliterate, m)?)?;
+    m.add_function(wrap_pyfunction!(datetime_ru::parse_datetime_ru, m)?)?;
+    m.add_function(wrap_pyfunction!(json_repair::repair_json, m)?)?;
+    m.add_function(wrap_pyfunction!(markdown::strip_markdown, m)?)?;
+    m.add_function(wrap_pyfunction!(markdown::extract_structure, m)?)?;
+    m.add_function(wrap_pyfunction!(text_normalizer::normalize, m)?)?;
+    m.add_function(wrap_pyfunction!(logging::refresh_log_levels, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    /// kristina_core.pyi в каталоге крейта совпадает со стабом из исходников;
+    /// обновить: `KRISTINA_CORE_STUBS=1 cargo build`
+    #[test]
+    fn stub_is_current() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/kristina_core.pyi"));
+        let committed = include_str!("../kristina_core.pyi");
+        assert!(generated == committed, "kristina_core.pyi устарел: KRISTINA_CORE_STUBS=1 cargo build");
+    }
+}
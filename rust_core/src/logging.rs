@@ -0,0 +1,135 @@
+//! Мост log → Python logging
+//!
+//! - Модули ядра пишут макросами log (error!/warn!/info!/debug!/trace!);
+//!   запись уходит в logging.getLogger("kristina_core.<модуль>") — уровни
+//!   и обработчики задаёт конфигурация logging приложения
+//! - Действующий уровень логгера кэшируется на модуль: запись ниже уровня
+//!   не берёт GIL; после смены уровней — refresh_log_levels()
+//! - TRACE → 5 (ниже DEBUG), остальные уровни — одноимённые
+//! - Записи не делаются под блокировками ядра: обработчик Python может
+//!   обратиться к ядру из того же потока
+//! - Ошибка обработчика уходит в sys.unraisablehook и не прерывает операцию
+
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use dashmap::DashMap;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::sync::LazyLock;
+
+/// Уровень Python для уровня log
+fn py_level(level: Level) -> u32 {
+    match level {
+        Level::Error => 40,
+        Level::Warn => 30,
+        Level::Info => 20,
+        Level::Debug => 10,
+        Level::Trace => 5,
+    }
+}
+
+/// Имя логгера Python: "kristina_core::memory_engine" → "kristina_core.memory_engine"
+fn logger_name(target: &str) -> String {
+    target.replace("::", ".")
+}
+
+struct CachedLogger {
+    logger: PyObject,
+    /// logger.getEffectiveLevel() на момент кэширования
+    level: u32,
+}
+
+struct PythonLogger {
+    loggers: DashMap<String, CachedLogger>,
+}
+
+impl PythonLogger {
+    /// Логгер цели, если уровень пропускает запись
+    fn logger_for(&self, py: Python<'_>, target: &str, level: Level) -> PyResult<Option<PyObject>> {
+        if let Some(cached) = self.loggers.get(target) {
+            return Ok((py_level(level) >= cached.level).then(|| cached.logger.clone_ref(py)));
+        }
+        let logger = py.import("logging")?.call_method1("getLogger", (logger_name(target),))?;
+        let effective: u32 = logger.call_method0("getEffectiveLevel")?.extract()?;
+        let allowed = py_level(level) >= effective;
+        let logger = logger.unbind();
+        let result = allowed.then(|| logger.clone_ref(py));
+        self.loggers.insert(target.to_string(), CachedLogger { logger, level: effective });
+        Ok(result)
+    }
+
+    fn emit(&self, py: Python<'_>, record: &Record<'_>, message: String) -> PyResult<()> {
+        let Some(logger) = self.logger_for(py, record.target(), record.level())? else {
+            return Ok(());
+        };
+        let logger = logger.bind(py);
+        let args = (
+            logger.getattr("name")?,
+            py_level(record.level()),
+            record.file().unwrap_or("<rust>"),
+            record.line().unwrap_or(0),
+            message,
+            PyTuple::empty(py),
+            py.None(),
+        );
+        let py_record = logger.call_method1("makeRecord", args)?;
+        logger.call_method1("handle", (py_record,))?;
+        Ok(())
+    }
+}
+
+impl Log for PythonLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // Без кэша ответ знает только Python — решит log()
+        self.loggers
+            .get(metadata.target())
+            .is_none_or(|cached| py_level(metadata.level()) >= cached.level)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // Форматирование — до захвата GIL
+        let message = record.args().to_string();
+        Python::with_gil(|py| {
+            if let Err(err) = self.emit(py, record, message) {
+                err.write_unraisable(py, None);
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: LazyLock<PythonLogger> = LazyLock::new(|| PythonLogger { loggers: DashMap::new() });
+
+/// Подключает мост при импорте модуля; повторный импорт (подинтерпретатор) не меняет логгер
+pub(crate) fn init() {
+    if log::set_logger(&*LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+}
+
+/// Перечитать уровни логгеров kristina_core.* после изменения конфигурации logging
+#[pyfunction]
+pub fn refresh_log_levels() {
+    LOGGER.loggers.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_and_names() {
+        assert_eq!(logger_name("kristina_core::memory_engine"), "kristina_core.memory_engine");
+        assert_eq!(logger_name("kristina_core"), "kristina_core");
+        let levels: Vec<u32> = [Level::Trace, Level::Debug, Level::Info, Level::Warn, Level::Error]
+            .into_iter()
+            .map(py_level)
+            .collect();
+        assert_eq!(levels, vec![5, 10, 20, 30, 40]);
+        // Пустой кэш: решение откладывается до Python
+        assert!(LOGGER.enabled(&Metadata::builder().level(Level::Trace).target("kristina_core::x").build()));
+    }
+}
@@ -0,0 +1,505 @@
+//! Maintenance — фоновое обслуживание компонентов по расписанию
+//!
+//! - add(component) регистрирует задачи компонента:
+//!   MemoryEngine — удаление просроченных эпизодов, сохранение и checkpoint WAL;
+//!   EmbeddingCache — сохранение и checkpoint WAL его хранилища;
+//!   ThreadTracker / MultiThreadTracker — архив просроченных нитей;
+//!   SessionManager — выгрузка простаивающих сессий и снимки;
+//!   RateLimiter — удаление простаивающих вёдер; Storage — checkpoint WAL
+//! - Интервалы по видам задач (save, expire, prune, checkpoint) — раздел
+//!   maintenance CoreConfig или аргументы конструктора; 0 — вид отключён
+//! - start() запускает отдельный поток, stop() будит его и дожидается
+//!   (без GIL); run_now() выполняет задачи сразу в текущем потоке
+//! - status(): по задаче — запуски, сбои, время и длительность последнего,
+//!   результат или ошибка, секунд до следующего
+//! - Сбой задачи не останавливает поток: предупреждение в журнал и
+//!   счётчик maintenance_failures_total
+
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::types::PyDict;
+use parking_lot::{Condvar, Mutex, RwLock};
+use chrono::{DateTime, Utc};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::config::{self, CoreConfig};
+use crate::embedding_cache::EmbeddingCache;
+use crate::memory_engine::MemoryEngine;
+use crate::metrics;
+use crate::rate_limiter::RateLimiter;
+use crate::session::SessionManager;
+use crate::storage::{Storage, Store};
+use crate::thread_tracker::{MultiThreadTracker, ThreadTracker};
+
+// ── Задачи ──
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Save,
+    Expire,
+    Prune,
+    Checkpoint,
+}
+
+impl Kind {
+    const ALL: [Kind; 4] = [Kind::Save, Kind::Expire, Kind::Prune, Kind::Checkpoint];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Save => "save",
+            Kind::Expire => "expire",
+            Kind::Prune => "prune",
+            Kind::Checkpoint => "checkpoint",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Kind::ALL.into_iter().find(|k| k.as_str() == name)
+    }
+}
+
+/// Ok — краткий итог для status(), Err — текст ошибки
+type Job = Box<dyn Fn() -> Result<String, String> + Send + Sync>;
+
+#[derive(Default)]
+struct TaskStatus {
+    runs: u64,
+    failures: u64,
+    last_run: Option<DateTime<Utc>>,
+    duration: f64,
+    result: Option<String>,
+    error: Option<String>,
+    /// None — ещё не запланирована (срок отсчитывается от первого прохода)
+    due: Option<Instant>,
+}
+
+struct Task {
+    name: String,
+    kind: Kind,
+    job: Job,
+    status: Mutex<TaskStatus>,
+}
+
+/// Сигналы потоку: остановка и изменение списка задач
+#[derive(Default)]
+struct Control {
+    stopping: bool,
+    changed: bool,
+}
+
+struct Scheduler {
+    /// Интервал по Kind::ALL; None — вид отключён
+    intervals: [Option<Duration>; 4],
+    tasks: RwLock<Vec<Arc<Task>>>,
+    /// Хранилища с checkpoint: одно на файл, даже если его делят компоненты
+    stores: Mutex<Vec<PathBuf>>,
+    control: Mutex<Control>,
+    wake: Condvar,
+}
+
+impl Scheduler {
+    fn new(intervals: [f64; 4]) -> Self {
+        Self {
+            intervals: intervals.map(|secs| (secs > 0.0).then(|| Duration::from_secs_f64(secs))),
+            tasks: RwLock::new(Vec::new()),
+            stores: Mutex::new(Vec::new()),
+            control: Mutex::new(Control::default()),
+            wake: Condvar::new(),
+        }
+    }
+
+    fn interval(&self, kind: Kind) -> Option<Duration> {
+        self.intervals[kind as usize]
+    }
+
+    /// Имя уникально: второй компонент того же вида — "memory.save#2"
+    fn add(&self, name: &str, kind: Kind, job: impl Fn() -> Result<String, String> + Send + Sync + 'static) -> String {
+        let mut tasks = self.tasks.write();
+        let taken = tasks.iter().filter(|t| t.name == name || t.name.starts_with(&format!("{}#", name))).count();
+        let name = if taken == 0 { name.to_string() } else { format!("{}#{}", name, taken + 1) };
+        tasks.push(Arc::new(Task { name: name.clone(), kind, job: Box::new(job), status: Mutex::default() }));
+        drop(tasks);
+        self.control.lock().changed = true;
+        self.wake.notify_all();
+        name
+    }
+
+    fn add_checkpoint(&self, store: Arc<Store>) -> Option<String> {
+        let path = store.path().to_path_buf();
+        {
+            let mut stores = self.stores.lock();
+            if stores.contains(&path) {
+                return None;
+            }
+            stores.push(path);
+        }
+        Some(self.add("storage.checkpoint", Kind::Checkpoint, move || {
+            let (busy, log, moved) = store.checkpoint().map_err(|e| e.to_string())?;
+            Ok(if busy != 0 {
+                format!("база занята, перенесено страниц {} из {}", moved, log)
+            } else {
+                format!("перенесено страниц {}", moved)
+            })
+        }))
+    }
+
+    fn run(&self, task: &Task) {
+        let started = Instant::now();
+        let outcome = (task.job)();
+        let elapsed = started.elapsed();
+        let mut status = task.status.lock();
+        status.runs += 1;
+        status.last_run = Some(Utc::now());
+        status.duration = elapsed.as_secs_f64();
+        status.due = self.interval(task.kind).map(|every| Instant::now() + every);
+        let error = match outcome {
+            Ok(result) => {
+                status.result = Some(result);
+                status.error = None;
+                None
+            }
+            Err(error) => {
+                status.failures += 1;
+                status.result = None;
+                status.error = Some(error.clone());
+                Some(error)
+            }
+        };
+        drop(status);
+        metrics::inc("maintenance_runs_total", 1);
+        metrics::observe("maintenance_task_seconds", elapsed.as_secs_f64());
+        if let Some(error) = error {
+            metrics::inc("maintenance_failures_total", 1);
+            log::warn!("Задача обслуживания {} не выполнена: {}", task.name, error);
+        }
+    }
+
+    /// Выполнить задачи, срок которых наступил; возвращает ближайший следующий срок
+    fn run_due(&self, now: Instant) -> Option<Instant> {
+        let tasks: Vec<Arc<Task>> = self.tasks.read().clone();
+        let mut next: Option<Instant> = None;
+        for task in tasks {
+            let Some(every) = self.interval(task.kind) else {
+                continue;
+            };
+            let due = *task.status.lock().due.get_or_insert(now + every);
+            let due = if due <= now {
+                self.run(&task);
+                task.status.lock().due.unwrap_or(now + every)
+            } else {
+                due
+            };
+            next = Some(next.map_or(due, |n| n.min(due)));
+        }
+        next
+    }
+
+    fn run_loop(&self) {
+        loop {
+            let next = self.run_due(Instant::now());
+            let mut control = self.control.lock();
+            if !control.stopping && !control.changed {
+                match next {
+                    Some(at) => {
+                        self.wake.wait_until(&mut control, at);
+                    }
+                    None => self.wake.wait(&mut control),
+                }
+            }
+            if control.stopping {
+                return;
+            }
+            control.changed = false;
+        }
+    }
+
+    /// Все задачи (или одного вида) сразу; возвращает их имена
+    fn run_now(&self, kind: Option<Kind>) -> Vec<String> {
+        let tasks: Vec<Arc<Task>> = self.tasks.read().clone();
+        tasks
+            .iter()
+            .filter(|t| kind.is_none_or(|k| t.kind == k))
+            .map(|task| {
+                self.run(task);
+                task.name.clone()
+            })
+            .collect()
+    }
+}
+
+fn error_text(err: PyErr) -> String {
+    err.to_string()
+}
+
+// ── PyO3 класс ──
+
+/// Планировщик фонового обслуживания: сохранение, истечение, очистка, checkpoint
+#[pyclass(frozen)]
+pub struct Maintenance {
+    scheduler: Arc<Scheduler>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Maintenance {
+    fn start_thread(&self) -> std::io::Result<bool> {
+        let mut thread = self.thread.lock();
+        if thread.is_some() {
+            return Ok(false);
+        }
+        *self.scheduler.control.lock() = Control::default();
+        let scheduler = Arc::clone(&self.scheduler);
+        let handle = std::thread::Builder::new()
+            .name("kristina-maintenance".to_string())
+            .spawn(move || scheduler.run_loop())?;
+        *thread = Some(handle);
+        log::info!("Обслуживание запущено: задач {}", self.scheduler.tasks.read().len());
+        Ok(true)
+    }
+
+    fn stop_thread(&self) -> bool {
+        let Some(handle) = self.thread.lock().take() else {
+            return false;
+        };
+        self.scheduler.control.lock().stopping = true;
+        self.scheduler.wake.notify_all();
+        // Паника задачи уже в журнале потока; поток в любом случае завершён
+        let _ = handle.join();
+        log::info!("Обслуживание остановлено");
+        true
+    }
+}
+
+impl Drop for Maintenance {
+    /// Поток без владельца не живёт дольше объекта: сигнал остановки без ожидания
+    fn drop(&mut self) {
+        if self.thread.get_mut().is_some() {
+            self.scheduler.control.lock().stopping = true;
+            self.scheduler.wake.notify_all();
+        }
+    }
+}
+
+#[pymethods]
+impl Maintenance {
+    /// Интервалы в секундах (0 — вид отключён); незаданные — из раздела
+    /// maintenance config, затем по умолчанию
+    #[new]
+    #[pyo3(signature = (*, save_secs=None, expire_secs=None, prune_secs=None, checkpoint_secs=None, config=None))]
+    fn py_new(
+        save_secs: Option<f64>,
+        expire_secs: Option<f64>,
+        prune_secs: Option<f64>,
+        checkpoint_secs: Option<f64>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let every = config::settings(config).maintenance;
+        let intervals = [
+            save_secs.unwrap_or(every.save_secs),
+            expire_secs.unwrap_or(every.expire_secs),
+            prune_secs.unwrap_or(every.prune_secs),
+            checkpoint_secs.unwrap_or(every.checkpoint_secs),
+        ];
+        if let Some(bad) = intervals.iter().find(|s| !(s.is_finite() && **s >= 0.0)) {
+            return Err(PyValueError::new_err(format!("Интервал должен быть >= 0: {}", bad)));
+        }
+        Ok(Self { scheduler: Arc::new(Scheduler::new(intervals)), thread: Mutex::new(None) })
+    }
+
+    /// Зарегистрировать задачи компонента; возвращает их имена.
+    /// Неподдерживаемый тип → TypeError
+    fn add(&self, component: &Bound<'_, PyAny>) -> PyResult<Vec<String>> {
+        let s = &self.scheduler;
+        let mut names = Vec::new();
+        if let Ok(memory) = component.downcast::<MemoryEngine>() {
+            let memory = memory.clone().unbind();
+            let store = memory.get().store();
+            let expiring = memory.clone_ref(component.py());
+            names.push(s.add("memory.expire", Kind::Expire, move || {
+                Ok(format!("удалено просроченных эпизодов: {}", expiring.get().purge_expired()))
+            }));
+            names.push(s.add("memory.save", Kind::Save, move || {
+                memory.get().save().map(|()| "сохранено".to_string()).map_err(error_text)
+            }));
+            names.extend(s.add_checkpoint(store));
+        } else if let Ok(cache) = component.downcast::<EmbeddingCache>() {
+            let cache = cache.clone().unbind();
+            let store = cache.get().store();
+            names.push(s.add("cache.save", Kind::Save, move || {
+                cache.get().save().map(|()| "сохранено".to_string()).map_err(error_text)
+            }));
+            names.extend(s.add_checkpoint(store));
+        } else if let Ok(tracker) = component.downcast::<ThreadTracker>() {
+            let tracker = tracker.clone().unbind();
+            names.push(s.add("threads.expire", Kind::Expire, move || {
+                Ok(if tracker.get().expire_if_idle() { "нити архивированы" } else { "просроченных нет" }.to_string())
+            }));
+        } else if let Ok(tracker) = component.downcast::<MultiThreadTracker>() {
+            let tracker = tracker.clone().unbind();
+            names.push(s.add("threads.expire", Kind::Expire, move || {
+                Ok(format!("пользователей с архивированными нитями: {}", tracker.get().expire_idle().len()))
+            }));
+        } else if let Ok(sessions) = component.downcast::<SessionManager>() {
+            let sessions = sessions.clone().unbind();
+            let saver = sessions.clone_ref(component.py());
+            names.push(s.add("sessions.expire", Kind::Expire, move || {
+                Ok(format!("выгружено сессий: {}", sessions.get().expire_idle().len()))
+            }));
+            names.push(s.add("sessions.save", Kind::Save, move || {
+                saver.get().save().map(|n| format!("снимков: {}", n)).map_err(error_text)
+            }));
+        } else if let Ok(limiter) = component.downcast::<RateLimiter>() {
+            let limiter = limiter.clone().unbind();
+            names.push(s.add("rate_limit.prune", Kind::Prune, move || {
+                Ok(format!("удалено вёдер: {}", limiter.get().cleanup()))
+            }));
+        } else if let Ok(storage) = component.downcast::<Storage>() {
+            names.extend(s.add_checkpoint(storage.get().shared()));
+        } else {
+            return Err(PyTypeError::new_err(format!(
+                "Maintenance не обслуживает {}",
+                component.get_type().name()?
+            )));
+        }
+        Ok(names)
+    }
+
+    /// Запустить фоновый поток; False — уже запущен
+    fn start(&self) -> PyResult<bool> {
+        self.start_thread()
+            .map_err(|e| PyRuntimeError::new_err(format!("Не удалось запустить поток обслуживания: {}", e)))
+    }
+
+    /// Остановить поток и дождаться текущей задачи; False — не был запущен
+    fn stop(&self, py: Python<'_>) -> bool {
+        py.allow_threads(|| self.stop_thread())
+    }
+
+    #[getter]
+    fn running(&self) -> bool {
+        self.thread.lock().is_some()
+    }
+
+    /// Выполнить задачи сейчас (все или вида kind: save, expire, prune, checkpoint);
+    /// возвращает имена выполненных, итоги — в status()
+    #[pyo3(signature = (kind=None))]
+    fn run_now(&self, py: Python<'_>, kind: Option<&str>) -> PyResult<Vec<String>> {
+        let kind = kind
+            .map(|name| {
+                Kind::parse(name).ok_or_else(|| {
+                    PyValueError::new_err(format!("kind: ожидается save, expire, prune или checkpoint, получено {:?}", name))
+                })
+            })
+            .transpose()?;
+        Ok(py.allow_threads(|| self.scheduler.run_now(kind)))
+    }
+
+    /// {имя: {kind, interval_secs, runs, failures, last_run, duration_ms, result, error, next_in_secs}}
+    fn status<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let now = Instant::now();
+        let out = PyDict::new(py);
+        for task in self.scheduler.tasks.read().iter() {
+            let status = task.status.lock();
+            let item = PyDict::new(py);
+            item.set_item("kind", task.kind.as_str())?;
+            item.set_item("interval_secs", self.scheduler.interval(task.kind).map(|d| d.as_secs_f64()))?;
+            item.set_item("runs", status.runs)?;
+            item.set_item("failures", status.failures)?;
+            item.set_item("last_run", status.last_run.map(|t| t.to_rfc3339()))?;
+            item.set_item("duration_ms", status.duration * 1000.0)?;
+            item.set_item("result", status.result.clone())?;
+            item.set_item("error", status.error.clone())?;
+            item.set_item("next_in_secs", status.due.map(|due| due.saturating_duration_since(now).as_secs_f64()))?;
+            out.set_item(&task.name, item)?;
+        }
+        Ok(out)
+    }
+
+    fn __len__(&self) -> usize {
+        self.scheduler.tasks.read().len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Maintenance(tasks={}, running={})", self.__len__(), self.running())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_intervals_status_and_failures() {
+        let scheduler = Scheduler::new([10.0, 0.0, 10.0, 10.0]);
+        let saves = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&saves);
+        let first = scheduler.add("memory.save", Kind::Save, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Ok("сохранено".to_string())
+        });
+        let second = scheduler.add("memory.save", Kind::Save, || Err("диск полон".to_string()));
+        scheduler.add("threads.expire", Kind::Expire, || Ok(String::new()));
+        assert_eq!((first.as_str(), second.as_str()), ("memory.save", "memory.save#2"));
+
+        // Первый проход только планирует; срок наступает через интервал
+        let t0 = Instant::now();
+        let next = scheduler.run_due(t0).unwrap();
+        assert_eq!(saves.load(Ordering::Relaxed), 0);
+        assert!(next >= t0 + Duration::from_secs(10));
+        scheduler.run_due(t0 + Duration::from_secs(11));
+        assert_eq!(saves.load(Ordering::Relaxed), 1);
+
+        let tasks = scheduler.tasks.read();
+        let failed = tasks[1].status.lock();
+        assert_eq!((failed.runs, failed.failures), (1, 1));
+        assert_eq!(failed.error.as_deref(), Some("диск полон"));
+        // Отключённый вид не запускается и не планируется
+        assert_eq!(tasks[2].status.lock().runs, 0);
+        assert!(tasks[2].status.lock().due.is_none());
+    }
+
+    #[test]
+    fn test_thread_start_stop_and_run_now() {
+        let maintenance = Maintenance { scheduler: Arc::new(Scheduler::new([0.01, 0.0, 0.0, 0.0])), thread: Mutex::new(None) };
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&runs);
+        maintenance.scheduler.add("cache.save", Kind::Save, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Ok(String::new())
+        });
+        assert!(maintenance.start_thread().unwrap());
+        assert!(!maintenance.start_thread().unwrap());
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(maintenance.stop_thread());
+        let stopped_at = runs.load(Ordering::Relaxed);
+        assert!(stopped_at >= 2, "запусков: {}", stopped_at);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(runs.load(Ordering::Relaxed), stopped_at);
+        assert!(!maintenance.stop_thread());
+
+        assert_eq!(maintenance.scheduler.run_now(Some(Kind::Save)), vec!["cache.save".to_string()]);
+        assert!(maintenance.scheduler.run_now(Some(Kind::Prune)).is_empty());
+        assert_eq!(runs.load(Ordering::Relaxed), stopped_at + 1);
+    }
+
+    #[test]
+    fn test_checkpoint_once_per_store() {
+        let dir = std::env::temp_dir().join(format!("kristina_maintenance_{}", std::process::id()));
+        let store = crate::storage::open(&dir).unwrap();
+        let scheduler = Scheduler::new([0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(scheduler.add_checkpoint(Arc::clone(&store)).as_deref(), Some("storage.checkpoint"));
+        assert!(scheduler.add_checkpoint(Arc::clone(&store)).is_none());
+        store.put("test", "k", b"v").unwrap();
+        scheduler.run_now(None);
+        let tasks = scheduler.tasks.read();
+        let status = tasks[0].status.lock();
+        assert!(status.error.is_none(), "{:?}", status.error);
+        assert!(status.result.as_deref().unwrap().starts_with("перенесено"));
+        drop(status);
+        drop(tasks);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
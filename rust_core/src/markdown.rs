@@ -0,0 +1,594 @@
+//! Markdown — очистка разметки и извлечение структуры
+//!
+//! - strip_markdown: текст без разметки для индексации памяти, анализа эмоций
+//!   и оценки токенов (UI при этом хранит исходник)
+//!   - заголовки (#, setext), списки и чекбоксы, цитаты, таблицы, разделители
+//!   - **жирный**, *курсив*, ~~зачёркнутый~~, `код` — остаётся текст;
+//!     snake_case и "2 * 3" не трогаются
+//!   - ссылки и картинки → текст / alt; <автоссылки> → URL; HTML-теги убираются
+//!   - блоки кода ``` / ~~~ — содержимое (keep_code) или ничего
+//! - extract_structure: заголовки, пункты списков, блоки кода, ссылки
+//!   с диапазонами [start, end) в символах исходного текста
+//! - MemoryEngine индексирует, а EmotionAnalyzer анализирует очищенный текст
+
+use pyo3::prelude::*;
+use std::borrow::Cow;
+
+#[pyclass(frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct MarkdownElement {
+    /// heading / list_item / code_block / link / image
+    pub kind: String,
+    /// Текст без разметки; для code_block — код
+    pub text: String,
+    /// Уровень заголовка (1–6) или вложенность пункта списка (с 1); иначе 0
+    pub level: usize,
+    /// Нумерованный пункт списка
+    pub ordered: bool,
+    /// URL ссылки / картинки (None для ссылок по метке [text][ref])
+    pub url: Option<String>,
+    /// Язык блока кода (```python)
+    pub language: Option<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[pymethods]
+impl MarkdownElement {
+    fn __repr__(&self) -> String {
+        format!("MarkdownElement(kind={:?}, text={:?}, start={}, end={})", self.kind, self.text, self.start, self.end)
+    }
+}
+
+impl MarkdownElement {
+    fn new(kind: &str, text: String, start: usize, end: usize) -> Self {
+        Self { kind: kind.to_string(), text, level: 0, ordered: false, url: None, language: None, start, end }
+    }
+}
+
+// ── Разбор ──
+
+/// Символы, без которых разметки в тексте нет
+const MARKUP_CHARS: &[char] = &['*', '_', '~', '`', '#', '>', '[', '<', '|', '\\', '=', '-', '+'];
+
+struct Parser {
+    chars: Vec<char>,
+    keep_code: bool,
+    elements: Vec<MarkdownElement>,
+}
+
+/// Строка: [start, end) в символах без '\n'
+type Line = (usize, usize);
+
+impl Parser {
+    fn new(text: &str, keep_code: bool) -> Self {
+        Self { chars: text.chars().collect(), keep_code, elements: Vec::new() }
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (i, &c) in self.chars.iter().enumerate() {
+            if c == '\n' {
+                lines.push((start, i));
+                start = i + 1;
+            }
+        }
+        lines.push((start, self.chars.len()));
+        lines
+    }
+
+    fn text(&self, from: usize, to: usize) -> String {
+        self.chars[from..to].iter().collect()
+    }
+
+    fn skip_spaces(&self, mut i: usize, end: usize) -> usize {
+        while i < end && matches!(self.chars[i], ' ' | '\t') {
+            i += 1;
+        }
+        i
+    }
+
+    /// Длина серии символа c с позиции i (не дальше end)
+    fn run(&self, i: usize, end: usize, c: char) -> usize {
+        self.chars[i..end].iter().take_while(|&&x| x == c).count()
+    }
+
+    /// Весь документ → текст без разметки
+    fn parse(&mut self) -> String {
+        let lines = self.lines();
+        let mut out = String::new();
+        // Последняя строка абзаца (кандидат в setext-заголовок): (строка, начало в out)
+        let mut paragraph: Option<(Line, usize)> = None;
+        let mut i = 0;
+        while i < lines.len() {
+            let (start, end) = lines[i];
+            i += 1;
+            let content = self.skip_spaces(start, end);
+            let indent = content - start;
+            let first = self.chars.get(content).copied().filter(|_| content < end);
+
+            // Блок кода ``` / ~~~
+            if let Some(fence @ ('`' | '~')) = first {
+                let len = self.run(content, end, fence);
+                if len >= 3 && indent < 4 && !(fence == '`' && self.chars[content + len..end].contains(&'`')) {
+                    i = self.code_block(&lines, i, (start, end), fence, len, &mut out);
+                    paragraph = None;
+                    continue;
+                }
+            }
+            if first.is_none() {
+                push_break(&mut out);
+                paragraph = None;
+                continue;
+            }
+            // Setext: строка "===" / "---" под абзацем
+            if let (Some(((p_start, _), out_at)), Some(c @ ('=' | '-'))) = (paragraph, first) {
+                let rest = &self.chars[content..end];
+                if rest.iter().all(|&x| x == c || x == ' ') && rest.iter().filter(|&&x| x == c).count() >= 2 {
+                    let text = out[out_at..].trim().to_string();
+                    let mut heading = MarkdownElement::new("heading", text, p_start, end);
+                    heading.level = if c == '=' { 1 } else { 2 };
+                    self.elements.push(heading);
+                    paragraph = None;
+                    continue;
+                }
+            }
+            let out_at = out.len();
+            let emitted = self.block_line(content, end, indent, &mut out);
+            if emitted.is_some() {
+                out.push('\n');
+            }
+            paragraph = (emitted == Some(true)).then_some(((start, end), out_at));
+        }
+        collapse_breaks(&out)
+    }
+
+    /// Блок кода с открывающей строки fence_line; возвращает индекс следующей строки
+    fn code_block(&mut self, lines: &[Line], next: usize, fence_line: Line, fence: char, len: usize, out: &mut String) -> usize {
+        let (start, end) = fence_line;
+        let info_start = self.skip_spaces(start, end) + len;
+        let language = self.text(info_start, end).split_whitespace().next().map(str::to_string);
+        let close = lines[next..].iter().position(|&(s, e)| {
+            let c = self.skip_spaces(s, e);
+            let n = self.run(c, e, fence);
+            n >= len && self.skip_spaces(c + n, e) == e
+        });
+        let body = match close {
+            Some(k) => &lines[next..next + k],
+            None => &lines[next..],
+        };
+        let code = body.iter().map(|&(s, e)| self.text(s, e)).collect::<Vec<_>>().join("\n");
+        let block_end = match close {
+            Some(k) => lines[next + k].1,
+            None => self.chars.len(),
+        };
+        push_break(out);
+        if self.keep_code && !code.trim().is_empty() {
+            out.push_str(&code);
+            push_break(out);
+        }
+        let mut element = MarkdownElement::new("code_block", code, start, block_end);
+        element.language = language;
+        self.elements.push(element);
+        close.map_or(lines.len(), |k| next + k + 1)
+    }
+
+    /// Непустая строка вне блока кода: Some(true) — строка абзаца,
+    /// Some(false) — прочий текст, None — строка без текста (разделитель, определение)
+    fn block_line(&mut self, content: usize, end: usize, indent: usize, out: &mut String) -> Option<bool> {
+        let c = self.chars[content];
+        // Цитата: снимаем '>' и разбираем остаток как строку
+        if c == '>' {
+            let rest = self.skip_spaces(content + 1, end);
+            if rest == end {
+                push_break(out);
+                return None;
+            }
+            return self.block_line(rest, end, indent, out);
+        }
+        // ATX-заголовок
+        if c == '#' {
+            let level = self.run(content, end, '#');
+            let after = content + level;
+            if level <= 6 && (after == end || matches!(self.chars[after], ' ' | '\t')) {
+                let mut text_end = end;
+                while text_end > after && matches!(self.chars[text_end - 1], ' ' | '\t' | '#') {
+                    text_end -= 1;
+                }
+                let text = self.inline_text(after, text_end).trim().to_string();
+                out.push_str(&text);
+                let mut heading = MarkdownElement::new("heading", text, content, end);
+                heading.level = level;
+                self.elements.push(heading);
+                return Some(false);
+            }
+        }
+        // Разделитель *** / --- / ___
+        if matches!(c, '*' | '-' | '_') {
+            let rest = &self.chars[content..end];
+            if rest.iter().all(|&x| x == c || x == ' ') && rest.iter().filter(|&&x| x == c).count() >= 3 {
+                push_break(out);
+                return None;
+            }
+        }
+        // Определение ссылки [ref]: url
+        if c == '[' {
+            if let Some(close) = self.closing_bracket(content, end) {
+                if close + 1 < end && self.chars[close + 1] == ':' {
+                    return None;
+                }
+            }
+        }
+        // Пункт списка
+        if let Some((marker_end, ordered)) = self.list_marker(content, end) {
+            let mut text_start = self.skip_spaces(marker_end, end);
+            // Чекбокс [ ] / [x]
+            if text_start + 2 < end
+                && self.chars[text_start] == '['
+                && matches!(self.chars[text_start + 1], ' ' | 'x' | 'X')
+                && self.chars[text_start + 2] == ']'
+            {
+                text_start = self.skip_spaces(text_start + 3, end);
+            }
+            let text = self.inline_text(text_start, end).trim().to_string();
+            out.push_str(&text);
+            let mut item = MarkdownElement::new("list_item", text, content, end);
+            item.level = indent / 2 + 1;
+            item.ordered = ordered;
+            self.elements.push(item);
+            return Some(false);
+        }
+        // Строка таблицы
+        if c == '|' {
+            let row = &self.chars[content..end];
+            if row.iter().all(|&x| matches!(x, '|' | '-' | ':' | ' ' | '\t')) {
+                return None;
+            }
+            let mut cells = Vec::new();
+            let mut cell_start = content + 1;
+            let mut i = content + 1;
+            while i < end {
+                match self.chars[i] {
+                    '\\' => i += 1,
+                    '|' => {
+                        cells.push((cell_start, i));
+                        cell_start = i + 1;
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            if cell_start < end {
+                cells.push((cell_start, end));
+            }
+            let texts: Vec<String> = cells
+                .into_iter()
+                .map(|(s, e)| self.inline_text(s, e).trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+            out.push_str(&texts.join(" "));
+            return Some(false);
+        }
+        let text = self.inline_text(content, end);
+        out.push_str(text.trim_end());
+        Some(true)
+    }
+
+    /// "- ", "* ", "+ ", "1. ", "1) " → (конец маркера, нумерованный)
+    fn list_marker(&self, content: usize, end: usize) -> Option<(usize, bool)> {
+        let c = self.chars[content];
+        let followed_by_space = |i: usize| i == end || matches!(self.chars[i], ' ' | '\t');
+        if matches!(c, '-' | '*' | '+') && content + 1 < end && followed_by_space(content + 1) {
+            return Some((content + 1, false));
+        }
+        let digits = self.chars[content..end].iter().take_while(|x| x.is_ascii_digit()).count();
+        let marker = content + digits;
+        if (1..=9).contains(&digits) && marker < end && matches!(self.chars[marker], '.' | ')') && followed_by_space(marker + 1) {
+            return Some((marker + 1, true));
+        }
+        None
+    }
+
+    fn inline_text(&mut self, from: usize, to: usize) -> String {
+        let mut out = String::new();
+        self.inline(from, to, &mut out);
+        out
+    }
+
+    /// Строчная разметка в [from, to) → out
+    fn inline(&mut self, from: usize, to: usize, out: &mut String) {
+        let mut i = from;
+        while i < to {
+            let c = self.chars[i];
+            match c {
+                '\\' if i + 1 < to && self.chars[i + 1].is_ascii_punctuation() => {
+                    out.push(self.chars[i + 1]);
+                    i += 2;
+                }
+                '`' => {
+                    let n = self.run(i, to, '`');
+                    match self.find_run(i + n, to, '`', n) {
+                        Some(close) => {
+                            out.push_str(self.text(i + n, close).trim());
+                            i = close + n;
+                        }
+                        None => {
+                            out.push_str(&self.text(i, i + n));
+                            i += n;
+                        }
+                    }
+                }
+                '!' if i + 1 < to && self.chars[i + 1] == '[' => match self.link(i + 1, to, true, out) {
+                    Some(next) => i = next,
+                    None => {
+                        out.push(c);
+                        i += 1;
+                    }
+                },
+                '[' => match self.link(i, to, false, out) {
+                    Some(next) => i = next,
+                    None => {
+                        out.push(c);
+                        i += 1;
+                    }
+                },
+                '<' => match self.angle(i, to, out) {
+                    Some(next) => i = next,
+                    None => {
+                        out.push(c);
+                        i += 1;
+                    }
+                },
+                '*' | '_' | '~' => {
+                    let n = self.run(i, to, c);
+                    match self.emphasis(i, to, c, n) {
+                        Some(close) => {
+                            self.inline(i + n, close, out);
+                            i = close + n;
+                        }
+                        None => {
+                            out.push_str(&self.text(i, i + n));
+                            i += n;
+                        }
+                    }
+                }
+                _ => {
+                    out.push(c);
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Закрывающая серия ровно из n символов c в [from, to)
+    fn find_run(&self, from: usize, to: usize, c: char, n: usize) -> Option<usize> {
+        let mut i = from;
+        while i < to {
+            if self.chars[i] == c {
+                let len = self.run(i, to, c);
+                if len == n {
+                    return Some(i);
+                }
+                i += len;
+            } else {
+                i += 1;
+            }
+        }
+        None
+    }
+
+    /// Парная серия выделения: открывающая прилегает к тексту справа,
+    /// закрывающая — слева; '_' только на границе слова (snake_case не трогаем)
+    fn emphasis(&self, open: usize, to: usize, c: char, n: usize) -> Option<usize> {
+        if n > 3 || (c == '~' && n != 2) {
+            return None;
+        }
+        let is_word = |i: usize| self.chars.get(i).is_some_and(|x| x.is_alphanumeric());
+        let after = open + n;
+        if after >= to || self.chars[after].is_whitespace() || (c == '_' && open > 0 && is_word(open - 1)) {
+            return None;
+        }
+        let mut i = after + 1;
+        while i < to {
+            if self.chars[i] == c {
+                let len = self.run(i, to, c);
+                let closes = len == n
+                    && !self.chars[i - 1].is_whitespace()
+                    && !(c == '_' && i + len < to && is_word(i + len));
+                if closes {
+                    return Some(i);
+                }
+                i += len;
+            } else {
+                i += 1;
+            }
+        }
+        None
+    }
+
+    /// ']' для '[' в позиции open (с учётом вложенности и экранирования)
+    fn closing_bracket(&self, open: usize, to: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut i = open;
+        while i < to {
+            match self.chars[i] {
+                '\\' => i += 1,
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        None
+    }
+
+    /// [text](url "title"), [text][ref], ![alt](url) → текст в out; позиция после ссылки
+    fn link(&mut self, open: usize, to: usize, image: bool, out: &mut String) -> Option<usize> {
+        let close = self.closing_bracket(open, to)?;
+        let (url, next) = match self.chars.get(close + 1) {
+            Some('(') if close + 1 < to => {
+                let mut depth = 0;
+                let mut j = close + 1;
+                let end = loop {
+                    if j >= to {
+                        return None;
+                    }
+                    match self.chars[j] {
+                        '(' => depth += 1,
+                        ')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break j;
+                            }
+                        }
+                        _ => {}
+                    }
+                    j += 1;
+                };
+                let target = self.text(close + 2, end);
+                let url = target.split_whitespace().next().unwrap_or("").trim_matches(['<', '>']).to_string();
+                (Some(url), end + 1)
+            }
+            Some('[') if close + 1 < to => (None, self.closing_bracket(close + 1, to)? + 1),
+            _ => return None,
+        };
+        let text = self.inline_text(open + 1, close);
+        out.push_str(&text);
+        let start = if image { open - 1 } else { open };
+        let mut element = MarkdownElement::new(if image { "image" } else { "link" }, text, start, next);
+        element.url = url;
+        self.elements.push(element);
+        Some(next)
+    }
+
+    /// <https://...> → URL; <tag ...> / </tag> → ничего
+    fn angle(&mut self, open: usize, to: usize, out: &mut String) -> Option<usize> {
+        let close = (open + 1..to).find(|&j| self.chars[j] == '>')?;
+        let inner = self.text(open + 1, close);
+        if inner.contains(' ') && !inner.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        if inner.starts_with("http://") || inner.starts_with("https://") || inner.starts_with("mailto:") {
+            out.push_str(&inner);
+            let mut element = MarkdownElement::new("link", inner.clone(), open, close + 1);
+            element.url = Some(inner);
+            self.elements.push(element);
+        } else if !inner.trim_start_matches('/').starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        Some(close + 1)
+    }
+}
+
+/// Пустая строка между блоками (не более одной подряд)
+fn push_break(out: &mut String) {
+    if !out.is_empty() && !out.ends_with("\n\n") {
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+}
+
+fn collapse_breaks(out: &str) -> String {
+    let mut result = String::with_capacity(out.len());
+    let mut blank = 0;
+    for line in out.lines() {
+        if line.trim().is_empty() {
+            blank += 1;
+            continue;
+        }
+        if !result.is_empty() {
+            result.push_str(if blank > 0 { "\n\n" } else { "\n" });
+        }
+        result.push_str(line);
+        blank = 0;
+    }
+    result
+}
+
+/// Текст без разметки; без символов разметки — без копирования
+pub(crate) fn plain(text: &str, keep_code: bool) -> Cow<'_, str> {
+    if !text.contains(MARKUP_CHARS) {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(Parser::new(text, keep_code).parse())
+}
+
+pub(crate) fn structure(text: &str) -> Vec<MarkdownElement> {
+    let mut parser = Parser::new(text, false);
+    parser.parse();
+    let mut elements = parser.elements;
+    elements.sort_by_key(|e| (e.start, e.end));
+    elements
+}
+
+// ── Python API ──
+
+/// Текст без markdown-разметки; keep_code=False — без содержимого блоков кода
+#[pyfunction]
+#[pyo3(signature = (text, keep_code=true))]
+pub fn strip_markdown(py: Python<'_>, text: &str, keep_code: bool) -> String {
+    py.allow_threads(|| plain(text, keep_code).into_owned())
+}
+
+/// Заголовки, пункты списков, блоки кода и ссылки в порядке появления
+#[pyfunction]
+pub fn extract_structure(py: Python<'_>, text: &str) -> Vec<MarkdownElement> {
+    py.allow_threads(|| structure(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_inline_and_blocks() {
+        let text = "# Заголовок #\n\nЭто **жирный**, *курсив*, ~~старое~~ и `код`.\n\
+                    > Цитата с [ссылкой](https://example.com \"title\")\n\n\
+                    - [x] пункт один\n  2. вложенный\n\n---\n| a | b |\n|---|:-:|\n| 1 | 2 |";
+        assert_eq!(
+            plain(text, true),
+            "Заголовок\n\nЭто жирный, курсив, старое и код.\nЦитата с ссылкой\n\nпункт один\nвложенный\n\na b\n1 2"
+        );
+        // Не разметка
+        assert_eq!(plain("snake_case_name и 2 * 3 * 4", true), "snake_case_name и 2 * 3 * 4");
+        assert!(matches!(plain("просто текст.", true), Cow::Borrowed(_)));
+        assert_eq!(plain("Смотри ![схема](a.png) и <b>тут</b> <https://x.ru>", true), "Смотри схема и тут https://x.ru");
+    }
+
+    #[test]
+    fn test_code_blocks() {
+        let text = "До\n```python\nx = a * b * c\n```\nПосле";
+        assert_eq!(plain(text, true), "До\n\nx = a * b * c\n\nПосле");
+        assert_eq!(plain(text, false), "До\n\nПосле");
+        // Незакрытый блок — до конца текста
+        assert_eq!(plain("```\n**не разметка**", true), "**не разметка**");
+    }
+
+    #[test]
+    fn test_structure_spans() {
+        let text = "Заголовок\n=========\n\n## Раздел\n\n1. Первый [док](http://d.ru)\n    - вложенный\n\n```rust\nfn main() {}\n```";
+        let elements = structure(text);
+        let kinds: Vec<&str> = elements.iter().map(|e| e.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["heading", "heading", "list_item", "link", "list_item", "code_block"]);
+
+        let chars: Vec<char> = text.chars().collect();
+        let span = |e: &MarkdownElement| chars[e.start..e.end].iter().collect::<String>();
+        assert_eq!((elements[0].level, elements[0].text.as_str()), (1, "Заголовок"));
+        assert_eq!((elements[1].level, span(&elements[1]).as_str()), (2, "## Раздел"));
+        assert!(elements[2].ordered);
+        assert_eq!(elements[2].text, "Первый док");
+        assert_eq!(span(&elements[3]), "[док](http://d.ru)");
+        assert_eq!(elements[3].url.as_deref(), Some("http://d.ru"));
+        assert_eq!(elements[4].level, 3);
+        assert_eq!(elements[5].language.as_deref(), Some("rust"));
+        assert_eq!(elements[5].text, "fn main() {}");
+    }
+}
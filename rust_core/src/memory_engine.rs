@@ -1,325 +1,2159 @@
-//! MemoryEngine — управление памятью Кристины
-//!
-//! Три уровня:
-//! - Working memory: текущий контекст (FIFO, ограничен по размеру)
-//! - Episodic memory: история взаимодействий с keyword-индексом (xxh3)
-//! - Semantic memory: факты key→value (DashMap, lock-free)
-//!
-//! Персистентность: JSON на диск (episodic.json, semantic.json)
-//! Индексирование: xxh3 hash слов → inverted index для быстрого поиска
-
-use pyo3::prelude::*;
-use dashmap::DashMap;
-use parking_lot::RwLock;
-use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use chrono::{Utc, DateTime};
-use xxhash_rust::xxh3::xxh3_64;
-
-// ── Внутренние структуры ──
-
-#[derive(Clone, Serialize, Deserialize)]
-struct Episode {
-    timestamp: String,
-    user_input: String,
-    response: String,
-    emotion: String,
-    importance: i32,
-    keywords: Vec<String>,
-}
-
-#[derive(Clone)]
-struct WorkingEntry {
-    role: String,
-    content: String,
-    timestamp: String,
-}
-
-// ── Стоп-слова для извлечения ключевых слов (RU + EN) ──
-
-const STOP_WORDS: &[&str] = &[
-    "я", "ты", "он", "она", "мы", "вы", "они", "в", "на", "и",
-    "с", "по", "для", "от", "к", "не", "что", "это", "как", "но",
-    "the", "is", "are", "a", "an", "in", "on", "for", "to", "of",
-];
-
-#[inline]
-fn word_hash(word: &str) -> u64 {
-    xxh3_64(word.as_bytes())
-}
-
-fn extract_keywords(text: &str) -> Vec<String> {
-    text.split_whitespace()
-        .filter_map(|w| {
-            let lower = w.to_lowercase();
-            if lower.chars().count() > 3 && !STOP_WORDS.contains(&lower.as_str()) {
-                Some(lower)
-            } else {
-                None
-            }
-        })
-        .take(10)
-        .collect()
-}
-
-// ── PyO3 класс ──
-
-#[pyclass(frozen)]
-pub struct MemoryEngine {
-    dir: PathBuf,
-    working_size: usize,
-    max_episodic: usize,
-    working: RwLock<Vec<WorkingEntry>>,
-    episodic: RwLock<Vec<Episode>>,
-    semantic: DashMap<String, String>,
-    keyword_index: RwLock<HashMap<u64, Vec<usize>>>,
-}
-
-#[pymethods]
-impl MemoryEngine {
-    #[new]
-    #[pyo3(signature = (memory_dir, working_size=10, max_episodic=1000))]
-    fn new(memory_dir: &str, working_size: usize, max_episodic: usize) -> PyResult<Self> {
-        let dir = PathBuf::from(memory_dir);
-        std::fs::create_dir_all(&dir).ok();
-
-        let engine = Self {
-            dir,
-            working_size,
-            max_episodic,
-            working: RwLock::new(Vec::new()),
-            episodic: RwLock::new(Vec::new()),
-            semantic: DashMap::new(),
-            keyword_index: RwLock::new(HashMap::new()),
-        };
-
-        engine.load_from_disk();
-        Ok(engine)
-    }
-
-    // ── Working Memory ──
-
-    fn add_to_working(&self, role: &str, content: &str) {
-        let mut working = self.working.write();
-        working.push(WorkingEntry {
-            role: role.to_string(),
-            content: content.to_string(),
-            timestamp: Utc::now().to_rfc3339(),
-        });
-        while working.len() > self.working_size {
-            working.remove(0);
-        }
-    }
-
-    fn get_working_memory(&self) -> Vec<(String, String, String)> {
-        let working = self.working.read();
-        working
-            .iter()
-            .map(|e| (e.role.clone(), e.content.clone(), e.timestamp.clone()))
-            .collect()
-    }
-
-    fn clear_working(&self) {
-        self.working.write().clear();
-    }
-
-    // ── Episodic Memory ──
-
-    #[pyo3(signature = (user_input, response, emotion, importance=1))]
-    fn add_episode(&self, user_input: &str, response: &str, emotion: &str, importance: i32) {
-        let keywords = extract_keywords(user_input);
-        let episode = Episode {
-            timestamp: Utc::now().to_rfc3339(),
-            user_input: user_input.to_string(),
-            response: response.to_string(),
-            emotion: emotion.to_string(),
-            importance,
-            keywords,
-        };
-
-        let mut episodic = self.episodic.write();
-        let idx = episodic.len();
-        episodic.push(episode);
-
-        // Обновляем keyword index
-        let combined = format!("{} {}", user_input, response);
-        let mut ki = self.keyword_index.write();
-        index_text(&mut ki, idx, &combined);
-
-        // Проверяем необходимость ротации
-        let needs_eviction = episodic.len() > self.max_episodic;
-        drop(ki);
-        drop(episodic);
-
-        if needs_eviction {
-            self.evict_episodes();
-        }
-    }
-
-    #[pyo3(signature = (query, max_items=3))]
-    fn get_relevant_context(&self, query: &str, max_items: usize) -> Vec<(String, String, i32)> {
-        let episodic = self.episodic.read();
-        let ki = self.keyword_index.read();
-
-        let mut scores: HashMap<usize, i32> = HashMap::new();
-
-        for word in query.split_whitespace() {
-            let lower = word.to_lowercase();
-            if lower.chars().count() <= 2 {
-                continue;
-            }
-            let h = word_hash(&lower);
-            if let Some(indices) = ki.get(&h) {
-                for &idx in indices {
-                    *scores.entry(idx).or_insert(0) += 1;
-                }
-            }
-        }
-
-        let mut results: Vec<(String, String, i32)> = scores
-            .iter()
-            .filter_map(|(&idx, &keyword_score)| {
-                episodic.get(idx).map(|ep| {
-                    let final_score = keyword_score * ep.importance;
-                    let preview: String = ep.user_input.chars().take(80).collect();
-                    (ep.timestamp.clone(), preview, final_score)
-                })
-            })
-            .collect();
-
-        results.sort_by(|a, b| b.2.cmp(&a.2));
-        results.truncate(max_items);
-        results
-    }
-
-    // ── Semantic Memory ──
-
-    fn add_semantic(&self, key: &str, value: &str) {
-        self.semantic.insert(key.to_string(), value.to_string());
-    }
-
-    fn get_semantic(&self, key: &str) -> Option<String> {
-        self.semantic.get(key).map(|v| v.value().clone())
-    }
-
-    // ── Персистентность ──
-
-    fn save(&self) {
-        let episodic_path = self.dir.join("episodic.json");
-        let semantic_path = self.dir.join("semantic.json");
-
-        if let Ok(data) = serde_json::to_string_pretty(&*self.episodic.read()) {
-            let _ = std::fs::write(&episodic_path, data);
-        }
-
-        let semantic_map: HashMap<String, String> = self.semantic
-            .iter()
-            .map(|r| (r.key().clone(), r.value().clone()))
-            .collect();
-        if let Ok(data) = serde_json::to_string_pretty(&semantic_map) {
-            let _ = std::fs::write(&semantic_path, data);
-        }
-    }
-
-    fn load(&self) {
-        self.load_from_disk();
-    }
-
-    fn get_stats(&self) -> (usize, usize, usize) {
-        (
-            self.working.read().len(),
-            self.episodic.read().len(),
-            self.semantic.len(),
-        )
-    }
-}
-
-// ── Приватные методы ──
-
-impl MemoryEngine {
-    fn load_from_disk(&self) {
-        let episodic_path = self.dir.join("episodic.json");
-        if episodic_path.exists() {
-            if let Ok(data) = std::fs::read_to_string(&episodic_path) {
-                if let Ok(episodes) = serde_json::from_str::<Vec<Episode>>(&data) {
-                    let mut ep = self.episodic.write();
-                    *ep = episodes;
-                    let mut ki = self.keyword_index.write();
-                    rebuild_index(&mut ki, &ep);
-                }
-            }
-        }
-
-        let semantic_path = self.dir.join("semantic.json");
-        if semantic_path.exists() {
-            if let Ok(data) = std::fs::read_to_string(&semantic_path) {
-                if let Ok(map) = serde_json::from_str::<HashMap<String, String>>(&data) {
-                    self.semantic.clear();
-                    for (k, v) in map {
-                        self.semantic.insert(k, v);
-                    }
-                }
-            }
-        }
-    }
-
-    fn evict_episodes(&self) {
-        let mut episodic = self.episodic.write();
-        let remove_count = std::cmp::max(1, self.max_episodic / 10);
-        let now = Utc::now();
-
-        let mut scored: Vec<(usize, f64)> = episodic
-            .iter()
-            .enumerate()
-            .map(|(i, ep)| {
-                let age_hours = ep
-                    .timestamp
-                    .parse::<DateTime<Utc>>()
-                    .map(|ts| (now - ts).num_seconds() as f64 / 3600.0)
-                    .unwrap_or(1.0)
-                    .max(1.0);
-                (i, ep.importance as f64 / age_hours)
-            })
-            .collect();
-
-        // Сортируем по eviction score (наименее ценные первыми)
-        scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
-
-        let mut to_remove: Vec<usize> = scored
-            .iter()
-            .take(remove_count)
-            .map(|&(i, _)| i)
-            .collect();
-        to_remove.sort_unstable_by(|a, b| b.cmp(a)); // Обратный порядок для безопасного удаления
-
-        for idx in to_remove {
-            if idx < episodic.len() {
-                episodic.remove(idx);
-            }
-        }
-
-        let mut ki = self.keyword_index.write();
-        rebuild_index(&mut ki, &episodic);
-    }
-}
-
-// ── Standalone helpers ──
-
-fn index_text(ki: &mut HashMap<u64, Vec<usize>>, idx: usize, text: &str) {
-    for word in text.split_whitespace() {
-        let lower = word.to_lowercase();
-        if lower.chars().count() > 2 {
-            let h = word_hash(&lower);
-            ki.entry(h).or_default().push(idx);
-        }
-    }
-}
-
-fn rebuild_index(ki: &mut HashMap<u64, Vec<usize>>, episodes: &[Episode]) {
-    ki.clear();
-    for (i, ep) in episodes.iter().enumerate() {
-        let combined = format!("{} {}", ep.user_input, ep.response);
-        index_text(ki, i, &combined);
-    }
-}
+//! MemoryEngine — управление памятью Кристины
+//!
+//! Три уровня:
+//! - Working memory: текущий контекст (FIFO, ограничен по размеру)
+//! - Episodic memory: история взаимодействий с keyword-индексом (xxh3);
+//!   у эпизода постоянный id (add_episode, find_episodes) для delete_episode /
+//!   update_episode — индексы при этом правятся точечно, без перестройки
+//! - Semantic memory: факты key→value (DashMap, lock-free)
+//!
+//! Персистентность: Storage (SQLite, memory.episodes / memory.facts) в memory_dir
+//! или общий storage=; save() — одна транзакция, падение посреди записи не
+//! портит сохранённое; после save база копируется в kristina.db.bak, и
+//! повреждённый файл при открытии или load() восстанавливается из этой копии;
+//! прежние episodic.json / semantic.json импортируются при первой загрузке;
+//! сбои чтения, записи и битые данные → PersistenceError (память процесса
+//! при этом не меняется)
+//! Индексирование: xxh3 hash основ слов (стемминг RU/EN) → inverted index;
+//! запрос "миграцию" находит эпизод с "миграции"; markdown-разметка
+//! (URL ссылок, **, #) в индекс не попадает, текст нормализуется (normalize)
+//! Эмбеддинги: add_episode(..., embedding=) — нормированный вектор в индексе
+//! (одна размерность на движок); search_by_embedding находит перефразированные
+//! запросы: косинус смешивается с keyword-баллом текста запроса
+//!
+//! Резервная копия и перенос: export_jsonl / import_jsonl — по строке на
+//! эпизод или факт, запись и чтение потоковые (без строки со всей памятью)
+//!
+//! Шифрование (passphrase=): записи хранилища, общая рабочая память и экспорт
+//! зашифрованы (crypto), запись привязана к своей строке хранилища; открытая
+//! запись в зашифрованной памяти — PersistenceError, память до включения
+//! шифрования читается только с migrate_plaintext=True и сразу сохраняется
+//! зашифрованной; импортированные episodic.json / semantic.json удаляются, а оставшиеся от
+//! импорта без passphrase *.json.migrated — RuntimeWarning при открытии
+//!
+//! Несколько процессов (shared=True): рабочая память общая (memory.working,
+//! изменения под блокировкой записи хранилища), факты пишутся и читаются
+//! сразу в хранилище; эпизоды у каждого процесса свои до save() / load()
+//!
+//! Автосохранение (autosave_secs > 0): фоновый поток раз в интервал вызывает
+//! save(), если эпизоды или факты менялись (dirty); close() / выход из with
+//! останавливает поток и дописывает несохранённое
+//!
+//! Срок жизни: ttl_hours эпизода или ttl_by_importance движка ({1: 24} —
+//! мелочи живут сутки); purge_expired() удаляет просроченные, вызывается при
+//! загрузке и перед вытеснением
+//!
+//! Консолидация (consolidate): эпизоды с высокой importance или часто
+//! находимые поиском (счётчик retrievals, сохраняется с эпизодом) переносят
+//! факты в семантическую память — встроенными шаблонами или extractor из Python
+//!
+//! События (set_event_bus): episode_added, episode_deleted, episodes_evicted,
+//! episodes_expired, episodes_consolidated
+
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use dashmap::DashMap;
+use parking_lot::{Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard};
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use chrono::{Utc, DateTime};
+use std::time::{Duration, Instant};
+use xxhash_rust::xxh3::xxh3_64;
+use serde_json::json;
+
+use crate::async_io;
+use crate::storage::{self, Records, SharedCell, Storage, Store};
+use crate::config::{self, CoreConfig};
+use crate::crypto::{self, Cipher};
+use crate::errors::{self, PersistenceError};
+use crate::event_bus::{self, EventBus, SharedBus};
+use crate::metrics;
+use crate::markdown;
+use crate::stemmer::stem_word;
+use crate::text_normalizer::normalized;
+
+// ── Внутренние структуры ──
+
+#[derive(Serialize, Deserialize)]
+struct Episode {
+    /// Постоянный номер (растёт с каждым эпизодом, не переиспользуется);
+    /// 0 — эпизод из старой версии, номер назначается при загрузке
+    #[serde(default)]
+    id: u64,
+    timestamp: String,
+    user_input: String,
+    response: String,
+    emotion: String,
+    importance: i32,
+    keywords: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    embedding: Option<Vec<f32>>,
+    /// Сколько раз эпизод попадал в выдачу поиска (атомик — счёт под блокировкой чтения)
+    #[serde(default)]
+    retrievals: AtomicU32,
+    /// Факты эпизода уже перенесены в семантическую память (consolidate)
+    #[serde(default)]
+    consolidated: bool,
+    /// Срок жизни, часы (None — по ttl_by_importance движка или бессрочно)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl_hours: Option<f64>,
+}
+
+/// Строка JSONL-экспорта: {"type": "episode", ...} или {"type": "fact", "key", "value"}
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ExportLine<'a> {
+    Episode(&'a Episode),
+    Fact { key: &'a str, value: &'a str },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ImportLine {
+    Episode(Episode),
+    Fact { key: String, value: String },
+}
+
+impl Episode {
+    /// Срок жизни истёк к now; время без разбора — не истекает
+    fn expired(&self, tiers: &HashMap<i32, f64>, now: DateTime<Utc>) -> bool {
+        let Some(hours) = self.ttl_hours.or_else(|| tiers.get(&self.importance).copied()) else {
+            return false;
+        };
+        self.timestamp
+            .parse::<DateTime<Utc>>()
+            .is_ok_and(|ts| (now - ts).num_milliseconds() as f64 > hours * 3_600_000.0)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct WorkingEntry {
+    role: String,
+    content: String,
+    timestamp: String,
+}
+
+// ── Стоп-слова для извлечения ключевых слов (RU + EN) ──
+
+const STOP_WORDS: &[&str] = &[
+    "я", "ты", "он", "она", "мы", "вы", "они", "в", "на", "и",
+    "с", "по", "для", "от", "к", "не", "что", "это", "как", "но",
+    "the", "is", "are", "a", "an", "in", "on", "for", "to", "of",
+];
+
+#[inline]
+fn word_hash(word: &str) -> u64 {
+    xxh3_64(word.as_bytes())
+}
+
+/// Текст для индекса и поиска: нормализованный, без markdown-разметки
+fn index_form(text: &str) -> String {
+    markdown::plain(&normalized(text), true).into_owned()
+}
+
+/// Хэши основ слов длиннее 2 символов — и для индекса, и для запроса
+fn stem_hashes(text: &str) -> impl Iterator<Item = u64> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 2)
+        .map(|w| word_hash(&stem_word(w)))
+}
+
+/// Копия общей рабочей памяти из хранилища; битые данные — копия не меняется
+fn replace_working(working: &mut Vec<WorkingEntry>, data: &[u8], cipher: Option<&Cipher>) -> Result<(), String> {
+    let data = crypto::open_record(cipher, WORKING, "", data)?;
+    *working = serde_json::from_slice(&data).map_err(|e| format!("данные повреждены: {}", e))?;
+    Ok(())
+}
+
+/// Единичный вектор; None — пустой, нулевой или с NaN / inf
+fn unit_vector(v: &[f32]) -> Option<Vec<f32>> {
+    if v.is_empty() || v.iter().any(|x| !x.is_finite()) {
+        return None;
+    }
+    let norm = v.iter().map(|&x| x as f64 * x as f64).sum::<f64>().sqrt();
+    (norm > 1e-8).then(|| v.iter().map(|&x| (x as f64 / norm) as f32).collect())
+}
+
+/// Нормированные эмбеддинги эпизодов: косинус — скалярное произведение
+#[derive(Default)]
+struct VectorIndex {
+    dim: Option<usize>,
+    /// (id эпизода, единичный вектор)
+    rows: Vec<(u64, Vec<f32>)>,
+}
+
+impl VectorIndex {
+    fn check(&self, dim: usize) -> Result<(), String> {
+        match self.dim {
+            Some(expected) if expected != dim => {
+                Err(format!("Размерность эмбеддинга {} не совпадает с индексом памяти ({})", dim, expected))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Вектор другой размерности (гонка с первым эпизодом) не индексируется
+    fn insert(&mut self, id: u64, embedding: &[f32]) {
+        let Some(unit) = unit_vector(embedding) else {
+            return;
+        };
+        if *self.dim.get_or_insert(unit.len()) == unit.len() {
+            self.rows.push((id, unit));
+        }
+    }
+
+    /// Без векторов размерность снова не задана
+    fn remove(&mut self, ids: &[u64]) {
+        self.rows.retain(|(id, _)| !ids.contains(id));
+        if self.rows.is_empty() {
+            self.dim = None;
+        }
+    }
+
+    fn rebuild(episodes: &[Episode]) -> Self {
+        let mut index = Self::default();
+        for ep in episodes {
+            if let Some(embedding) = &ep.embedding {
+                index.insert(ep.id, embedding);
+            }
+        }
+        index
+    }
+
+    /// (id эпизода, косинус) для единичного query
+    fn similarities(&self, query: &[f32]) -> Vec<(u64, f32)> {
+        let dot = |(id, row): &(u64, Vec<f32>)| {
+            let sim: f64 = row.iter().zip(query).map(|(&a, &b)| a as f64 * b as f64).sum();
+            (*id, sim as f32)
+        };
+        if self.rows.len() > PARALLEL_ROWS {
+            self.rows.par_iter().map(dot).collect()
+        } else {
+            self.rows.iter().map(dot).collect()
+        }
+    }
+}
+
+/// Итоговый балл поиска: доля keyword_weight — keyword-балл (с учётом importance,
+/// нормирован на лучший эпизод запроса), остальное — косинус (отрицательный = 0)
+fn merged_score(cosine: f32, keyword: f64, best_keyword: f64, keyword_weight: f64) -> f64 {
+    let keyword = if best_keyword > 0.0 { keyword / best_keyword } else { 0.0 };
+    (1.0 - keyword_weight) * (cosine.max(0.0) as f64) + keyword_weight * keyword
+}
+
+fn extract_keywords(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .filter_map(|w| {
+            let lower = w.to_lowercase();
+            if lower.chars().count() > 3 && !STOP_WORDS.contains(&lower.as_str()) {
+                Some(lower)
+            } else {
+                None
+            }
+        })
+        .take(10)
+        .collect()
+}
+
+/// Факты из реплики пользователя по шаблонам: "меня зовут Аня" → (имя, Аня),
+/// "я живу в Казани" → (город, Казани), "мне 30 лет" → (возраст, 30),
+/// "я работаю врачом" → (работа, врачом), "моя любимая книга — Мастер и
+/// Маргарита" → (любимая книга, Мастер и Маргарита); то же для my name is /
+/// i live in / i work as. Значение — до конца фразы, не длиннее FACT_WORDS слов
+fn extract_facts(text: &str) -> Vec<(String, String)> {
+    let mut facts = Vec::new();
+    for sentence in text.split(['.', '!', '?', '\n', ';']) {
+        let words: Vec<&str> = sentence.split_whitespace().collect();
+        let lower: Vec<String> = words
+            .iter()
+            .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+            .collect();
+        let at = |i: usize| lower.get(i).map_or("", String::as_str);
+        for i in 0..words.len() {
+            let fact = match (at(i), at(i + 1), at(i + 2)) {
+                ("меня", "зовут", _) => proper_name(&words[i + 2..]).map(|v| ("имя".to_string(), v)),
+                ("my", "name", "is") => proper_name(&words[i + 3..]).map(|v| ("имя".to_string(), v)),
+                ("я", "живу", "в" | "во") | ("i", "live", "in") => {
+                    proper_name(&words[i + 3..]).map(|v| ("город".to_string(), v))
+                }
+                ("я", "работаю", _) => fact_value(&words[i + 2..], true).map(|v| ("работа".to_string(), v)),
+                ("i", "work", "as") => fact_value(&words[i + 3..], true).map(|v| ("работа".to_string(), v)),
+                ("мне", age, "год" | "года" | "лет") if age.chars().all(|c| c.is_ascii_digit()) => {
+                    Some(("возраст".to_string(), age.to_string()))
+                }
+                ("мой" | "моя" | "моё" | "мое", adjective @ ("любимый" | "любимая" | "любимое"), subject)
+                    if !subject.is_empty() =>
+                {
+                    let rest = &words[i + 3..];
+                    let rest = match rest.first() {
+                        Some(&("—" | "-" | "–" | "это")) => &rest[1..],
+                        _ => rest,
+                    };
+                    fact_value(rest, false).map(|v| (format!("{} {}", adjective, subject), v))
+                }
+                _ => None,
+            };
+            facts.extend(fact);
+        }
+    }
+    facts
+}
+
+/// Слов в значении факта из шаблона
+const FACT_WORDS: usize = 4;
+
+/// Начало words до запятой / двоеточия / тире (conjunctions — и до союза),
+/// без обрамляющей пунктуации
+fn fact_value(words: &[&str], conjunctions: bool) -> Option<String> {
+    let mut taken = Vec::new();
+    for word in words.iter().take(FACT_WORDS) {
+        if matches!(*word, "—" | "-" | "–")
+            || (conjunctions && matches!(word.to_lowercase().as_str(), "и" | "а" | "но" | "and" | "but"))
+        {
+            break;
+        }
+        let trimmed = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if !trimmed.is_empty() {
+            taken.push(trimmed);
+        }
+        if word.ends_with([',', ':', ')']) {
+            break;
+        }
+    }
+    (!taken.is_empty()).then(|| taken.join(" "))
+}
+
+/// Имя собственное: первое слово и следующие за ним слова с заглавной буквы
+/// ("Нижнем Новгороде"); "Аня и я" → "Аня"
+fn proper_name(words: &[&str]) -> Option<String> {
+    let capitalized = |w: &str| w.chars().find(|c| c.is_alphanumeric()).is_some_and(char::is_uppercase);
+    let end = words
+        .iter()
+        .skip(1)
+        .position(|w| !capitalized(w))
+        .map_or(words.len(), |n| n + 1);
+    fact_value(&words[..end], false)
+}
+
+// ── Сохраняемое состояние и автосохранение ──
+
+/// Эпизоды и факты — часть памяти, которую пишет save(); общая с потоком автосохранения
+struct Durable {
+    store: Arc<Store>,
+    episodic: RwLock<Vec<Episode>>,
+    semantic: DashMap<String, String>,
+    /// Режим shared: факты уже в хранилище, save их не заменяет
+    facts_shared: bool,
+    /// Шифрование записей хранилища и экспорта (None — открытый JSON)
+    cipher: Option<Cipher>,
+    /// Изменений всего и на момент последнего успешного save
+    changes: AtomicU64,
+    saved: AtomicU64,
+}
+
+impl Durable {
+    fn touch(&self) {
+        self.changes.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.changes.load(Ordering::Acquire) > self.saved.load(Ordering::Acquire)
+    }
+
+    fn save(&self) -> PyResult<()> {
+        let started = Instant::now();
+        let mark = self.changes.load(Ordering::Acquire);
+        let episodes = self.episode_records(self.episodic.read().iter())?;
+        let facts: Records = self.semantic.iter().map(|r| self.fact_record(r.key(), r.value())).collect();
+        let counts = (episodes.len(), facts.len());
+        // В режиме shared факты уже в хранилище, замена стёрла бы факты других процессов
+        let batches = if self.facts_shared {
+            vec![(EPISODES, episodes)]
+        } else {
+            vec![(EPISODES, episodes), (FACTS, facts)]
+        };
+        self.commit(&batches, &[], mark)?;
+        log::info!(
+            "Память сохранена в {}: эпизодов {}, фактов {} за {:.1} мс",
+            self.store.path().display(),
+            counts.0,
+            counts.1,
+            started.elapsed().as_secs_f64() * 1000.0
+        );
+        Ok(())
+    }
+
+    /// Ключ — номер эпизода: порядок записи совпадает с порядком в памяти
+    fn episode_records<'a>(&self, episodes: impl Iterator<Item = &'a Episode>) -> PyResult<Records> {
+        let records: Result<Records, _> = episodes
+            .enumerate()
+            .map(|(i, ep)| {
+                let key = format!("{:08}", i);
+                serde_json::to_vec(ep).map(|data| {
+                    let data = crypto::seal_record(self.cipher.as_ref(), EPISODES, &key, data);
+                    (key, data)
+                })
+            })
+            .collect();
+        records.map_err(|e| errors::persistence("сериализовать эпизоды для", self.store.path(), e))
+    }
+
+    fn fact_record(&self, key: &str, value: &str) -> (String, Vec<u8>) {
+        (key.to_string(), crypto::seal_record(self.cipher.as_ref(), FACTS, key, value.as_bytes().to_vec()))
+    }
+
+    /// Запись одной транзакцией; память на момент mark считается сохранённой
+    fn commit(&self, replace: &[(&str, Records)], append: &[(&str, Records)], mark: u64) -> PyResult<()> {
+        self.store.write(replace, append).map_err(|e| self.store.error("сохранить память в", e))?;
+        self.saved.fetch_max(mark, Ordering::AcqRel);
+        // Сохранённое уже в базе; без свежей копии восстановление вернёт предыдущий save
+        if let Err(e) = self.store.snapshot() {
+            errors::warn(module_path!(), &e);
+        }
+        Ok(())
+    }
+}
+
+/// Поток автосохранения: раз в интервал сохраняет, если были изменения
+struct Autosave {
+    /// (остановка, пробуждение)
+    control: Arc<(Mutex<bool>, Condvar)>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Autosave {
+    fn start(durable: Arc<Durable>, every: Duration) -> std::io::Result<Self> {
+        let control = Arc::new((Mutex::new(false), Condvar::new()));
+        let worker = Arc::clone(&control);
+        let handle = std::thread::Builder::new().name("kristina-autosave".to_string()).spawn(move || {
+            let (stopping, wake) = &*worker;
+            let mut stopped = stopping.lock();
+            loop {
+                wake.wait_for(&mut stopped, every);
+                if *stopped {
+                    return;
+                }
+                if durable.is_dirty() {
+                    let result = MutexGuard::unlocked(&mut stopped, || durable.save());
+                    if let Err(e) = result {
+                        errors::warn(module_path!(), &format!("Автосохранение памяти не удалось: {}", e));
+                    }
+                }
+            }
+        })?;
+        Ok(Self { control, handle: Mutex::new(Some(handle)) })
+    }
+
+    /// Остановить и дождаться потока (текущее сохранение завершается)
+    fn stop(&self) {
+        *self.control.0.lock() = true;
+        self.control.1.notify_all();
+        if let Some(handle) = self.handle.lock().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Autosave {
+    /// Без close() поток только получает сигнал остановки, несохранённое не пишется
+    fn drop(&mut self) {
+        *self.control.0.lock() = true;
+        self.control.1.notify_all();
+    }
+}
+
+// ── PyO3 класс ──
+
+const EPISODES: &str = "memory.episodes";
+const FACTS: &str = "memory.facts";
+/// Рабочая память в режиме shared
+const WORKING: &str = "memory.working";
+/// Векторов в индексе, начиная с которых косинусы считаются через Rayon
+const PARALLEL_ROWS: usize = 256;
+/// Символов user_input в превью результатов поиска
+const PREVIEW_CHARS: usize = 80;
+
+#[pyclass(frozen)]
+pub struct MemoryEngine {
+    store: Arc<Store>,
+    /// Каталог прежних JSON-файлов (импорт при пустом хранилище)
+    legacy_dir: Option<PathBuf>,
+    working_size: usize,
+    max_episodic: usize,
+    working: RwLock<Vec<WorkingEntry>>,
+    durable: Arc<Durable>,
+    /// Хэш основы слова → id эпизодов
+    keyword_index: RwLock<HashMap<u64, Vec<u64>>>,
+    /// Следующий id эпизода (выдаётся под блокировкой записи эпизодов)
+    next_id: AtomicU64,
+    /// Срок жизни эпизодов по importance, часы (без ttl_hours у эпизода)
+    ttl_tiers: HashMap<i32, f64>,
+    vectors: RwLock<VectorIndex>,
+    bus: RwLock<Option<SharedBus>>,
+    /// Рабочая память, общая для процессов (None — только в процессе)
+    shared: Option<SharedCell>,
+    /// Поток автосохранения (None — только явный save)
+    autosave: Option<Autosave>,
+}
+
+/// Необязательные возможности движка (keyword-аргументы конструктора)
+#[derive(Default)]
+struct Options {
+    autosave: Option<Duration>,
+    ttl_tiers: HashMap<i32, f64>,
+    cipher: Option<Cipher>,
+    /// Однократно принять открытые записи при открытии с cipher
+    migrate_plaintext: bool,
+}
+
+impl MemoryEngine {
+    #[cfg(test)]
+    pub(crate) fn new(memory_dir: &str, working_size: usize, max_episodic: usize) -> PyResult<Self> {
+        let dir = PathBuf::from(memory_dir);
+        let store = storage::open(&dir).map_err(PersistenceError::new_err)?;
+        Self::with_store(store, Some(dir), working_size, max_episodic, false, Options::default())
+    }
+
+    fn shutdown(&self) -> PyResult<()> {
+        if let Some(autosave) = &self.autosave {
+            autosave.stop();
+        }
+        if self.durable.is_dirty() {
+            self.durable.save()?;
+        }
+        Ok(())
+    }
+
+    fn with_store(
+        store: Arc<Store>,
+        legacy_dir: Option<PathBuf>,
+        working_size: usize,
+        max_episodic: usize,
+        shared: bool,
+        options: Options,
+    ) -> PyResult<Self> {
+        let Options { autosave, ttl_tiers, cipher, migrate_plaintext } = options;
+        let durable = Arc::new(Durable {
+            store: Arc::clone(&store),
+            episodic: RwLock::new(Vec::new()),
+            semantic: DashMap::new(),
+            facts_shared: shared,
+            cipher,
+            changes: AtomicU64::new(0),
+            saved: AtomicU64::new(0),
+        });
+        let mut engine = Self {
+            shared: shared.then(|| SharedCell::new(Arc::clone(&store), WORKING.to_string())),
+            store,
+            legacy_dir,
+            working_size,
+            max_episodic,
+            working: RwLock::new(Vec::new()),
+            durable,
+            keyword_index: RwLock::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            ttl_tiers,
+            vectors: RwLock::new(VectorIndex::default()),
+            bus: RwLock::new(None),
+            autosave: None,
+        };
+
+        engine.load_from_disk(migrate_plaintext)?;
+        if engine.cipher().is_some() {
+            engine.warn_plaintext_legacy();
+        }
+        if let Some(every) = autosave {
+            let worker = Autosave::start(Arc::clone(&engine.durable), every).map_err(|e| {
+                PyRuntimeError::new_err(format!("Не удалось запустить поток автосохранения: {}", e))
+            })?;
+            engine.autosave = Some(worker);
+        }
+        Ok(engine)
+    }
+}
+
+#[pymethods]
+impl MemoryEngine {
+    /// Незаданные параметры берутся из config (раздел memory), затем по умолчанию;
+    /// memory_dir можно опустить, если config задаёт memory.dir или data_dir.
+    /// storage — общее хранилище вместо kristina.db в memory_dir;
+    /// shared=True — рабочая память и факты общие для процессов (runtime.shared);
+    /// autosave_secs > 0 — фоновое сохранение при изменениях (memory.autosave_secs),
+    /// несохранённое дописывает close() или выход из with;
+    /// ttl_by_importance — срок жизни эпизодов по importance, часы: {1: 24}
+    /// (memory.ttl_by_importance); passphrase — шифрование эпизодов, фактов и
+    /// экспорта на диске (ChaCha20-Poly1305), зашифрованное без него не читается;
+    /// migrate_plaintext=True — один раз зашифровать память, сохранённую без
+    /// passphrase (без флага открытые записи → PersistenceError)
+    #[new]
+    #[pyo3(signature = (
+        memory_dir=None, working_size=None, max_episodic=None, *, storage=None, shared=None, autosave_secs=None,
+        ttl_by_importance=None, passphrase=None, migrate_plaintext=false, config=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        memory_dir: Option<String>,
+        working_size: Option<usize>,
+        max_episodic: Option<usize>,
+        storage: Option<PyRef<'_, Storage>>,
+        shared: Option<bool>,
+        autosave_secs: Option<f64>,
+        ttl_by_importance: Option<HashMap<i32, f64>>,
+        passphrase: Option<&str>,
+        migrate_plaintext: bool,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let config = config::settings(config);
+        let dir = memory_dir.or_else(|| config.memory_dir()).map(PathBuf::from);
+        let store = match (storage, &dir) {
+            (Some(storage), _) => storage.shared(),
+            (None, Some(dir)) => storage::open(dir).map_err(PersistenceError::new_err)?,
+            (None, None) => {
+                return Err(PyValueError::new_err(
+                    "Не задан memory_dir: укажите его, storage или memory.dir / data_dir в CoreConfig",
+                ))
+            }
+        };
+        let autosave_secs = autosave_secs.unwrap_or(config.memory.autosave_secs);
+        if !(autosave_secs.is_finite() && autosave_secs >= 0.0) {
+            return Err(PyValueError::new_err(format!("autosave_secs должен быть >= 0: {}", autosave_secs)));
+        }
+        let ttl_tiers = ttl_by_importance.unwrap_or_else(|| config.memory.ttl_tiers());
+        if let Some((importance, hours)) = ttl_tiers.iter().find(|(_, hours)| !valid_ttl(**hours)) {
+            return Err(PyValueError::new_err(format!("ttl_by_importance[{}] должен быть > 0: {}", importance, hours)));
+        }
+        let cipher = passphrase.map(Cipher::new).transpose().map_err(PyValueError::new_err)?;
+        let options = Options {
+            autosave: (autosave_secs > 0.0).then(|| Duration::from_secs_f64(autosave_secs)),
+            ttl_tiers,
+            cipher,
+            migrate_plaintext,
+        };
+        Self::with_store(
+            store,
+            dir,
+            working_size.unwrap_or(config.memory.working_size),
+            max_episodic.unwrap_or(config.memory.max_episodic),
+            shared.unwrap_or(config.runtime.shared),
+            options,
+        )
+    }
+
+    // ── Working Memory ──
+
+    pub(crate) fn add_to_working(&self, role: &str, content: &str) {
+        let entry = WorkingEntry {
+            role: role.to_string(),
+            content: content.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+        };
+        self.update_working(|working| {
+            working.push(entry);
+            while working.len() > self.working_size {
+                working.remove(0);
+            }
+        });
+    }
+
+    fn get_working_memory(&self) -> Vec<(String, String, String)> {
+        let working = self.working();
+        working
+            .iter()
+            .map(|e| (e.role.clone(), e.content.clone(), e.timestamp.clone()))
+            .collect()
+    }
+
+    fn clear_working(&self) {
+        self.update_working(Vec::clear);
+    }
+
+    // ── Episodic Memory ──
+
+    /// embedding — вектор для search_by_embedding; размерность у всех эпизодов
+    /// одна, иначе (и для нулевого вектора) — ValueError. ttl_hours — срок жизни
+    /// эпизода (иначе по ttl_by_importance). Возвращает id эпизода
+    #[pyo3(
+        name = "add_episode",
+        signature = (user_input, response, emotion, importance=1, embedding=None, ttl_hours=None)
+    )]
+    fn py_add_episode(
+        &self,
+        user_input: &str,
+        response: &str,
+        emotion: &str,
+        importance: i32,
+        embedding: Option<Vec<f32>>,
+        ttl_hours: Option<f64>,
+    ) -> PyResult<u64> {
+        if let Some(hours) = ttl_hours.filter(|&hours| !valid_ttl(hours)) {
+            return Err(PyValueError::new_err(format!("ttl_hours должен быть > 0: {}", hours)));
+        }
+        if let Some(embedding) = &embedding {
+            if unit_vector(embedding).is_none() {
+                return Err(PyValueError::new_err("Эмбеддинг пустой, нулевой или содержит NaN / inf"));
+            }
+            self.vectors.read().check(embedding.len()).map_err(PyValueError::new_err)?;
+        }
+        Ok(self.insert_episode(user_input, response, emotion, importance, embedding, ttl_hours))
+    }
+
+    #[pyo3(signature = (query, max_items=3))]
+    pub(crate) fn get_relevant_context(&self, query: &str, max_items: usize) -> Vec<(String, String, i32)> {
+        self.rank_by_keywords(query, max_items)
+            .into_iter()
+            .map(|(_, timestamp, preview, score)| (timestamp, preview, score))
+            .collect()
+    }
+
+    /// get_relevant_context с id: [(id, timestamp, preview, score)] — для
+    /// delete_episode / update_episode
+    #[pyo3(signature = (query, max_items=3))]
+    fn find_episodes(&self, query: &str, max_items: usize) -> Vec<(u64, String, String, i32)> {
+        self.rank_by_keywords(query, max_items)
+    }
+
+    /// Удалить эпизод; keyword- и векторный индексы правятся точечно.
+    /// False — эпизода нет (удалён или вытеснен)
+    fn delete_episode(&self, id: u64) -> bool {
+        let mut episodic = self.durable.episodic.write();
+        let Some(pos) = position(&episodic, id) else {
+            return false;
+        };
+        let episode = episodic.remove(pos);
+        unindex_episode(&mut self.keyword_index.write(), &episode);
+        self.vectors.write().remove(&[id]);
+        self.durable.touch();
+        let count = episodic.len();
+        drop(episodic);
+
+        event_bus::emit(&self.bus, "episode_deleted", "memory_engine", || json!({ "id": id, "episodes": count }));
+        true
+    }
+
+    /// Удалить эпизоды с истёкшим сроком жизни (ttl_hours эпизода или
+    /// ttl_by_importance); вызывается и при загрузке, и перед вытеснением.
+    /// Возвращает число удалённых
+    pub(crate) fn purge_expired(&self) -> usize {
+        let now = Utc::now();
+        let mut episodic = self.durable.episodic.write();
+        if !episodic.iter().any(|ep| ep.expired(&self.ttl_tiers, now)) {
+            return 0;
+        }
+        let (expired, kept): (Vec<Episode>, Vec<Episode>) =
+            std::mem::take(&mut *episodic).into_iter().partition(|ep| ep.expired(&self.ttl_tiers, now));
+        *episodic = kept;
+        let mut ki = self.keyword_index.write();
+        for episode in &expired {
+            unindex_episode(&mut ki, episode);
+        }
+        let ids: Vec<u64> = expired.iter().map(|ep| ep.id).collect();
+        self.vectors.write().remove(&ids);
+        self.durable.touch();
+        let count = episodic.len();
+        drop(ki);
+        drop(episodic);
+
+        metrics::inc("memory_episodes_expired_total", ids.len() as u64);
+        event_bus::emit(&self.bus, "episodes_expired", "memory_engine", || {
+            json!({ "count": ids.len(), "episodes": count })
+        });
+        ids.len()
+    }
+
+    /// Исправить importance и / или emotion; текст и индексы не меняются.
+    /// False — эпизода нет
+    #[pyo3(signature = (id, importance=None, emotion=None))]
+    fn update_episode(&self, id: u64, importance: Option<i32>, emotion: Option<String>) -> bool {
+        let mut episodic = self.durable.episodic.write();
+        let Some(pos) = position(&episodic, id) else {
+            return false;
+        };
+        let episode = &mut episodic[pos];
+        if let Some(importance) = importance {
+            episode.importance = importance;
+        }
+        if let Some(emotion) = emotion {
+            episode.emotion = emotion;
+        }
+        self.durable.touch();
+        true
+    }
+
+    /// [(timestamp, preview, score)] по эмбеддингу запроса: эпизоды с эмбеддингом —
+    /// по косинусу, query (текст) добавляет keyword-балл с долей keyword_weight;
+    /// score в [0, 1], по убыванию
+    #[pyo3(signature = (query_vec, top_k=3, query=None, keyword_weight=0.3))]
+    fn search_by_embedding(
+        &self,
+        py: Python<'_>,
+        query_vec: Vec<f32>,
+        top_k: usize,
+        query: Option<&str>,
+        keyword_weight: f64,
+    ) -> PyResult<Vec<(String, String, f64)>> {
+        if !(0.0..=1.0).contains(&keyword_weight) {
+            return Err(PyValueError::new_err(format!("keyword_weight должен быть в [0, 1]: {}", keyword_weight)));
+        }
+        py.allow_threads(|| self.search_embedding(&query_vec, top_k, query, keyword_weight))
+            .map_err(PyValueError::new_err)
+    }
+
+    // ── Semantic Memory ──
+
+    /// В режиме shared факт сразу пишется в хранилище (сбой → PersistenceError)
+    fn add_semantic(&self, key: &str, value: &str) -> PyResult<()> {
+        if self.shared.is_some() {
+            let data = crypto::seal_record(self.cipher(), FACTS, key, value.as_bytes().to_vec());
+            self.store.put(FACTS, key, &data).map_err(|e| self.store.error("записать факт в", e))?;
+        }
+        self.durable.semantic.insert(key.to_string(), value.to_string());
+        self.durable.touch();
+        Ok(())
+    }
+
+    /// В режиме shared — из хранилища (факт мог записать другой процесс)
+    fn get_semantic(&self, key: &str) -> PyResult<Option<String>> {
+        if self.shared.is_some() {
+            let value = self.store.get(FACTS, key).map_err(|e| self.store.error("прочитать факт из", e))?;
+            let value = value
+                .map(|v| self.open_fact(key, &v))
+                .transpose()
+                .map_err(|e| errors::persistence("расшифровать факт из", self.store.path(), e))?;
+            if let Some(value) = &value {
+                self.durable.semantic.insert(key.to_string(), value.clone());
+            }
+            return Ok(value);
+        }
+        Ok(self.durable.semantic.get(key).map(|v| v.value().clone()))
+    }
+
+    /// Перенос устойчивого из эпизодов в факты. Кандидаты — не консолидированные
+    /// эпизоды с importance >= min_importance или найденные поиском не менее
+    /// min_retrievals раз. extractor(user_input, response) → dict / [(key, value)] /
+    /// None; без него — встроенные шаблоны ("меня зовут …", "я живу в …",
+    /// "мне N лет", "мой любимый X — …"). Эпизод консолидируется один раз.
+    /// Возвращает добавленные факты [(key, value)]
+    #[pyo3(signature = (extractor=None, *, min_importance=3, min_retrievals=3))]
+    fn consolidate(
+        &self,
+        py: Python<'_>,
+        extractor: Option<Bound<'_, PyAny>>,
+        min_importance: i32,
+        min_retrievals: u32,
+    ) -> PyResult<Vec<(String, String)>> {
+        let Some(extractor) = extractor else {
+            return py.allow_threads(|| {
+                self.consolidate_with(min_importance, min_retrievals, |user_input, _| Ok(extract_facts(user_input)))
+            });
+        };
+        if !extractor.is_callable() {
+            return Err(PyValueError::new_err("extractor должен быть вызываемым"));
+        }
+        self.consolidate_with(min_importance, min_retrievals, |user_input, response| {
+            let facts = extractor.call1((user_input, response))?;
+            if facts.is_none() {
+                Ok(Vec::new())
+            } else if let Ok(map) = facts.extract::<HashMap<String, String>>() {
+                Ok(map.into_iter().collect())
+            } else {
+                facts.extract::<Vec<(String, String)>>()
+            }
+        })
+    }
+
+    // ── Персистентность ──
+
+    /// Эпизоды и факты заменяются одной транзакцией SQLite (WAL): сбой или
+    /// падение процесса посреди записи оставляют прежнее сохранённое состояние;
+    /// затем обновляется копия kristina.db.bak (сбой копии — RuntimeWarning)
+    pub(crate) fn save(&self) -> PyResult<()> {
+        self.durable.save()
+    }
+
+    fn load(&self) -> PyResult<()> {
+        self.load_from_disk(false)
+    }
+
+    /// Эпизоды и факты построчно в JSONL (запись в path.tmp, затем переименование):
+    /// память не сериализуется целиком в строку; с passphrase файл зашифрован
+    /// (заголовок и кадр на строку). Возвращает (эпизодов, фактов)
+    fn export_jsonl(&self, py: Python<'_>, path: PathBuf) -> PyResult<(usize, usize)> {
+        py.allow_threads(|| self.export_to(&path))
+    }
+
+    /// Эпизоды и факты из JSONL export_jsonl, файл читается построчно
+    /// (зашифрованный — по заголовку, нужен тот же passphrase).
+    /// merge=True — добавить к памяти (эпизоды получают новые id, уже
+    /// имеющиеся — с тем же timestamp и user_input — пропускаются, факты
+    /// перезаписываются); merge=False — заменить память. Битая строка или
+    /// эмбеддинг другой размерности → PersistenceError, память не меняется.
+    /// Итог сразу пишется в хранилище одной транзакцией (в режиме shared
+    /// merge=False заменяет и общие факты); ошибка записи → PersistenceError,
+    /// память и хранилище прежние. Возвращает (добавлено эпизодов, фактов)
+    #[pyo3(signature = (path, merge=true))]
+    fn import_jsonl(&self, py: Python<'_>, path: PathBuf, merge: bool) -> PyResult<(usize, usize)> {
+        py.allow_threads(|| self.import_from(&path, merge))
+    }
+
+    /// Есть изменения эпизодов или фактов после последнего save
+    #[getter]
+    fn dirty(&self) -> bool {
+        self.durable.is_dirty()
+    }
+
+    /// Остановить автосохранение и сохранить несохранённое; повторный вызов безопасен
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| self.shutdown())
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Выход из with — close(); исключение блока не подавляется
+    #[pyo3(signature = (_exc_type=None, _exc=None, _traceback=None))]
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: Option<PyObject>,
+        _exc: Option<PyObject>,
+        _traceback: Option<PyObject>,
+    ) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+
+    /// save без блокировки цикла asyncio: `await memory.save_async()`
+    fn save_async(slf: Py<Self>, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().save())
+    }
+
+    fn load_async(slf: Py<Self>, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().load_from_disk(false))
+    }
+
+    /// Публиковать события в EventBus (None — отключить)
+    #[pyo3(signature = (bus=None))]
+    fn set_event_bus(&self, bus: Option<PyRef<'_, EventBus>>) {
+        *self.bus.write() = bus.map(|b| b.shared());
+    }
+
+    fn get_stats(&self) -> (usize, usize, usize) {
+        (
+            self.working.read().len(),
+            self.durable.episodic.read().len(),
+            self.durable.semantic.len(),
+        )
+    }
+}
+
+// ── Приватные методы ──
+
+impl MemoryEngine {
+    #[cfg(test)]
+    pub(crate) fn add_episode(&self, user_input: &str, response: &str, emotion: &str, importance: i32) -> u64 {
+        self.insert_episode(user_input, response, emotion, importance, None, None)
+    }
+
+    fn insert_episode(
+        &self,
+        user_input: &str,
+        response: &str,
+        emotion: &str,
+        importance: i32,
+        embedding: Option<Vec<f32>>,
+        ttl_hours: Option<f64>,
+    ) -> u64 {
+        let keywords = extract_keywords(&index_form(user_input));
+        let mut episode = Episode {
+            id: 0,
+            timestamp: Utc::now().to_rfc3339(),
+            user_input: user_input.to_string(),
+            response: response.to_string(),
+            emotion: emotion.to_string(),
+            importance,
+            keywords,
+            embedding,
+            retrievals: AtomicU32::new(0),
+            consolidated: false,
+            ttl_hours,
+        };
+
+        // id под блокировкой записи — эпизоды упорядочены по id
+        let mut episodic = self.durable.episodic.write();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        episode.id = id;
+
+        // Обновляем keyword index и векторный индекс
+        let mut ki = self.keyword_index.write();
+        index_episode(&mut ki, &episode);
+        if let Some(embedding) = &episode.embedding {
+            self.vectors.write().insert(id, embedding);
+        }
+        episodic.push(episode);
+        self.durable.touch();
+
+        // Проверяем необходимость ротации
+        let count = episodic.len();
+        let needs_eviction = count > self.max_episodic;
+        drop(ki);
+        drop(episodic);
+
+        event_bus::emit(&self.bus, "episode_added", "memory_engine", || {
+            json!({ "emotion": emotion, "importance": importance, "episodes": count })
+        });
+        if needs_eviction {
+            // Сначала просроченные: вытеснение может уже не понадобиться
+            self.purge_expired();
+            let removed = self.evict_episodes();
+            if removed > 0 {
+                // Число перечитывается: параллельные add_episode меняют его после снятия блокировки
+                let episodes = self.durable.episodic.read().len();
+                metrics::inc("memory_episodes_evicted_total", removed as u64);
+                event_bus::emit(&self.bus, "episodes_evicted", "memory_engine", || {
+                    json!({ "count": removed, "episodes": episodes })
+                });
+            }
+        }
+        id
+    }
+
+    /// [(id, timestamp, preview, score)] по ключевым словам, score = совпадения × importance;
+    /// найденным эпизодам засчитывается retrieval
+    fn rank_by_keywords(&self, query: &str, max_items: usize) -> Vec<(u64, String, String, i32)> {
+        let _timer = metrics::timer("memory_retrieval_seconds");
+        let episodic = self.durable.episodic.read();
+        let ki = self.keyword_index.read();
+
+        let scores = keyword_scores(&ki, query);
+
+        let mut ranked: Vec<(&Episode, i32)> = scores
+            .iter()
+            .filter_map(|(&id, &keyword_score)| {
+                let ep = &episodic[position(&episodic, id)?];
+                Some((ep, keyword_score * ep.importance))
+            })
+            .collect();
+
+        // При равном балле — порядок эпизодов (HashMap порядка не держит)
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.id.cmp(&b.0.id)));
+        ranked.truncate(max_items);
+        ranked
+            .into_iter()
+            .map(|(ep, score)| {
+                ep.retrievals.fetch_add(1, Ordering::Relaxed);
+                let preview: String = ep.user_input.chars().take(PREVIEW_CHARS).collect();
+                (ep.id, ep.timestamp.clone(), preview, score)
+            })
+            .collect()
+    }
+
+    fn export_to(&self, path: &Path) -> PyResult<(usize, usize)> {
+        let tmp = path.with_extension("jsonl.tmp");
+        let written = self.write_jsonl(&tmp).and_then(|counts| std::fs::rename(&tmp, path).map(|()| counts));
+        written.map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            errors::persistence("экспортировать память в", path, e)
+        })
+    }
+
+    /// Эпизоды под блокировкой чтения (add_episode ждёт конца записи)
+    /// С passphrase — заголовок, по зашифрованному кадру на строку и завершающий кадр
+    fn write_jsonl(&self, path: &Path) -> std::io::Result<(usize, usize)> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let cipher = self.cipher();
+        if let Some(cipher) = cipher {
+            cipher.write_header(&mut out)?;
+        }
+        let mut line = Vec::new();
+        let mut frames = 0;
+        let mut write_line = |out: &mut BufWriter<File>, value: &ExportLine| -> std::io::Result<()> {
+            line.clear();
+            serde_json::to_writer(&mut line, value)?;
+            match cipher {
+                Some(cipher) => {
+                    cipher.write_frame(out, frames, &line)?;
+                    frames += 1;
+                    Ok(())
+                }
+                None => {
+                    line.push(b'\n');
+                    out.write_all(&line)
+                }
+            }
+        };
+        let episodic = self.durable.episodic.read();
+        for episode in episodic.iter() {
+            write_line(&mut out, &ExportLine::Episode(episode))?;
+        }
+        let mut facts = 0;
+        for fact in self.durable.semantic.iter() {
+            write_line(&mut out, &ExportLine::Fact { key: fact.key(), value: fact.value() })?;
+            facts += 1;
+        }
+        if let Some(cipher) = cipher {
+            cipher.write_trailer(&mut out, frames)?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok((episodic.len(), facts))
+    }
+
+    fn import_from(&self, path: &Path, merge: bool) -> PyResult<(usize, usize)> {
+        let file = File::open(path).map_err(|e| errors::persistence("открыть", path, e))?;
+        let mut episodes = Vec::new();
+        let mut facts = Vec::new();
+        let mut dim = if merge { self.vectors.read().dim } else { None };
+        let mut take = |n: usize, line: &[u8]| -> PyResult<()> {
+            if line.trim_ascii().is_empty() {
+                return Ok(());
+            }
+            let bad = |e: &dyn std::fmt::Display| errors::persistence("разобрать", path, format!("строка {}: {}", n + 1, e));
+            match serde_json::from_slice(line).map_err(|e| bad(&e))? {
+                ImportLine::Episode(episode) => {
+                    if let Some(embedding) = &episode.embedding {
+                        if unit_vector(embedding).is_none() || *dim.get_or_insert(embedding.len()) != embedding.len() {
+                            return Err(bad(&"эмбеддинг нулевой или другой размерности"));
+                        }
+                    }
+                    episodes.push(episode);
+                }
+                ImportLine::Fact { key, value } => facts.push((key, value)),
+            }
+            Ok(())
+        };
+
+        // Зашифрованный экспорт узнаётся по заголовку
+        let mut reader = BufReader::new(file);
+        let read_error = |e: &dyn std::fmt::Display| errors::persistence("прочитать", path, e);
+        if reader.fill_buf().map_err(|e| read_error(&e))?.starts_with(crypto::MAGIC) {
+            let cipher = self.cipher().ok_or_else(|| read_error(&"экспорт зашифрован, нужен passphrase"))?;
+            reader.consume(crypto::MAGIC.len());
+            let salt = Cipher::read_salt(&mut reader).map_err(|e| read_error(&e))?;
+            let mut n = 0;
+            while let Some(line) = cipher.read_frame(&mut reader, &salt, n as u64).map_err(|e| read_error(&e))? {
+                take(n, &line)?;
+                n += 1;
+            }
+        } else {
+            for (n, line) in reader.split(b'\n').enumerate() {
+                take(n, &line.map_err(|e| read_error(&e))?)?;
+            }
+        }
+
+        let fact_count = facts.len();
+        let added = self.apply_import(episodes, facts, merge)?;
+        self.purge_expired();
+        while self.durable.episodic.read().len() > self.max_episodic && self.evict_episodes() > 0 {}
+        log::info!("Память импортирована из {}: эпизодов {}, фактов {}", path.display(), added, fact_count);
+        Ok((added, fact_count))
+    }
+
+    /// Итог импорта сначала пишется в хранилище одной транзакцией и лишь затем
+    /// подменяет память: при ошибке записи не меняется ни то, ни другое.
+    /// merge=True — эпизоды, которых ещё нет, в конец с новыми id; иначе
+    /// замена эпизодов (id из файла сохраняются) и фактов, в режиме shared —
+    /// и общих. Блокировка эпизодов держится до конца: add_episode ждёт
+    fn apply_import(&self, episodes: Vec<Episode>, facts: Vec<(String, String)>, merge: bool) -> PyResult<usize> {
+        let durable = &*self.durable;
+        let mut episodic = durable.episodic.write();
+        let mark = durable.changes.load(Ordering::Acquire);
+        let fresh = if merge {
+            let mut known: HashSet<(String, String)> =
+                episodic.iter().map(|ep| (ep.timestamp.clone(), ep.user_input.clone())).collect();
+            episodes
+                .into_iter()
+                .filter(|ep| known.insert((ep.timestamp.clone(), ep.user_input.clone())))
+                .map(|mut ep| {
+                    ep.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    ep
+                })
+                .collect()
+        } else {
+            let mut episodes = episodes;
+            renumber(&mut episodes, &self.next_id);
+            episodes
+        };
+
+        let kept: &[Episode] = if merge { &episodic } else { &[] };
+        let episode_records = durable.episode_records(kept.iter().chain(&fresh))?;
+        let imported: Records = facts.iter().map(|(key, value)| durable.fact_record(key, value)).collect();
+        let mut replace = vec![(EPISODES, episode_records)];
+        let mut append = Vec::new();
+        match (durable.facts_shared, merge) {
+            // Общие факты других процессов при merge остаются
+            (true, true) => append.push((FACTS, imported)),
+            (false, true) => {
+                let mut merged: HashMap<String, String> =
+                    durable.semantic.iter().map(|r| (r.key().clone(), r.value().clone())).collect();
+                merged.extend(facts.iter().cloned());
+                replace.push((FACTS, merged.iter().map(|(key, value)| durable.fact_record(key, value)).collect()));
+            }
+            (_, false) => replace.push((FACTS, imported)),
+        }
+        durable.commit(&replace, &append, mark)?;
+
+        let added = fresh.len();
+        {
+            let mut ki = self.keyword_index.write();
+            let mut vectors = self.vectors.write();
+            if merge {
+                for episode in &fresh {
+                    index_episode(&mut ki, episode);
+                    if let Some(embedding) = &episode.embedding {
+                        vectors.insert(episode.id, embedding);
+                    }
+                }
+                episodic.extend(fresh);
+            } else {
+                rebuild_index(&mut ki, &fresh);
+                *vectors = VectorIndex::rebuild(&fresh);
+                *episodic = fresh;
+            }
+        }
+        drop(episodic);
+        if !merge {
+            durable.semantic.clear();
+        }
+        for (key, value) in facts {
+            durable.semantic.insert(key, value);
+        }
+        Ok(added)
+    }
+
+    /// Кандидаты читаются под блокировкой, extract вызывается без неё; отметка
+    /// consolidated — по id (эпизод могло вытеснить или удалить) сразу после
+    /// записи его фактов: ошибка extract на следующем эпизоде не приводит к
+    /// повторному переносу уже обработанных
+    fn consolidate_with(
+        &self,
+        min_importance: i32,
+        min_retrievals: u32,
+        mut extract: impl FnMut(&str, &str) -> PyResult<Vec<(String, String)>>,
+    ) -> PyResult<Vec<(String, String)>> {
+        let candidates: Vec<(u64, String, String)> = self
+            .durable
+            .episodic
+            .read()
+            .iter()
+            .filter(|ep| {
+                !ep.consolidated
+                    && (ep.importance >= min_importance || ep.retrievals.load(Ordering::Relaxed) >= min_retrievals)
+            })
+            .map(|ep| (ep.id, ep.user_input.clone(), ep.response.clone()))
+            .collect();
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut added = Vec::new();
+        let mut done = 0;
+        let outcome = candidates.into_iter().try_for_each(|(id, user_input, response)| -> PyResult<()> {
+            for (key, value) in extract(&user_input, &response)? {
+                let (key, value) = (key.trim().to_string(), value.trim().to_string());
+                if key.is_empty() || value.is_empty() {
+                    continue;
+                }
+                self.add_semantic(&key, &value)?;
+                added.push((key, value));
+            }
+            let mut episodic = self.durable.episodic.write();
+            if let Some(pos) = position(&episodic, id) {
+                episodic[pos].consolidated = true;
+            }
+            drop(episodic);
+            self.durable.touch();
+            done += 1;
+            Ok(())
+        });
+
+        if done > 0 {
+            metrics::inc("memory_facts_consolidated_total", added.len() as u64);
+            event_bus::emit(&self.bus, "episodes_consolidated", "memory_engine", || {
+                json!({ "episodes": done, "facts": added.len() })
+            });
+        }
+        outcome.map(|()| added)
+    }
+
+    fn search_embedding(
+        &self,
+        query_vec: &[f32],
+        top_k: usize,
+        query: Option<&str>,
+        keyword_weight: f64,
+    ) -> Result<Vec<(String, String, f64)>, String> {
+        let _timer = metrics::timer("memory_retrieval_seconds");
+        let unit = unit_vector(query_vec).ok_or("Эмбеддинг запроса пустой, нулевой или содержит NaN / inf")?;
+        let episodic = self.durable.episodic.read();
+        let ki = self.keyword_index.read();
+        let vectors = self.vectors.read();
+        vectors.check(unit.len())?;
+
+        // Кандидаты — эпизоды с эмбеддингом и совпадения по ключевым словам
+        let mut candidates: HashMap<u64, (f32, f64)> =
+            vectors.similarities(&unit).into_iter().map(|(id, cos)| (id, (cos, 0.0))).collect();
+        if let Some(query) = query.filter(|_| keyword_weight > 0.0) {
+            for (id, score) in keyword_scores(&ki, query) {
+                let importance = position(&episodic, id).map_or(1, |pos| episodic[pos].importance);
+                candidates.entry(id).or_insert((0.0, 0.0)).1 = (score * importance) as f64;
+            }
+        }
+        let best_keyword = candidates.values().map(|&(_, kw)| kw).fold(0.0, f64::max);
+
+        let mut ranked: Vec<(u64, f64)> = candidates
+            .into_iter()
+            .map(|(id, (cos, kw))| (id, merged_score(cos, kw, best_keyword, keyword_weight)))
+            .collect();
+        // При равном балле — порядок эпизодов (HashMap порядка не держит)
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        Ok(ranked
+            .into_iter()
+            .filter_map(|(id, score)| Some((&episodic[position(&episodic, id)?], score)))
+            .take(top_k)
+            .map(|(ep, score)| {
+                ep.retrievals.fetch_add(1, Ordering::Relaxed);
+                let preview: String = ep.user_input.chars().take(PREVIEW_CHARS).collect();
+                (ep.timestamp.clone(), preview, score)
+            })
+            .collect())
+    }
+
+    /// Рабочая память как [(role, content)] — для других модулей ядра без копирования через Python
+    pub(crate) fn working_messages(&self) -> Vec<(String, String)> {
+        self.working()
+            .iter()
+            .map(|e| (e.role.clone(), e.content.clone()))
+            .collect()
+    }
+
+    /// Хранилище памяти — рядом с ним пишут свои данные другие компоненты (UserProfile)
+    pub(crate) fn store(&self) -> Arc<Store> {
+        Arc::clone(&self.store)
+    }
+
+    /// Факты semantic memory, по ключу
+    pub(crate) fn facts(&self) -> Vec<(String, String)> {
+        let mut facts: Vec<(String, String)> =
+            self.durable.semantic.iter().map(|r| (r.key().clone(), r.value().clone())).collect();
+        facts.sort();
+        facts
+    }
+
+    /// Рабочая память для чтения; в режиме shared — с изменениями других процессов
+    fn working(&self) -> RwLockReadGuard<'_, Vec<WorkingEntry>> {
+        if let Some(shared) = &self.shared {
+            let result = shared.refresh().map_err(|e| e.to_string()).and_then(|fresh| {
+                let Some((version, data)) = fresh else {
+                    return Ok(());
+                };
+                let mut working = self.working.write();
+                if shared.accept(version) {
+                    replace_working(&mut working, &data, self.cipher())
+                } else {
+                    Ok(())
+                }
+            });
+            if let Err(e) = result {
+                errors::warn(module_path!(), &format!(
+                    "Общая рабочая память {} не прочитана: {}",
+                    shared.path().display(),
+                    e
+                ));
+            }
+        }
+        self.working.read()
+    }
+
+    /// Изменение рабочей памяти; в режиме shared — под блокировкой записи
+    /// хранилища, чтобы одновременные изменения процессов не терялись
+    fn update_working(&self, f: impl FnOnce(&mut Vec<WorkingEntry>)) {
+        let mut working = self.working.write();
+        let Some(shared) = &self.shared else {
+            return f(&mut working);
+        };
+        let (write, fresh) = match shared.lock() {
+            Ok(locked) => locked,
+            Err(e) => {
+                f(&mut working);
+                drop(working);
+                return errors::warn(module_path!(), &format!(
+                    "Общая рабочая память {} недоступна, изменение осталось в процессе: {}",
+                    shared.path().display(),
+                    e
+                ));
+            }
+        };
+        let replaced = fresh.map_or(Ok(()), |data| replace_working(&mut working, &data, self.cipher()));
+        f(&mut working);
+        let result = replaced.and_then(|()| {
+            let data = serde_json::to_vec(&*working).map_err(|e| e.to_string())?;
+            write.commit(&crypto::seal_record(self.cipher(), WORKING, "", data)).map_err(|e| e.to_string())
+        });
+        drop(working);
+        if let Err(e) = result {
+            errors::warn(module_path!(), &format!(
+                "Общая рабочая память {} не обновлена, изменение осталось в процессе: {}",
+                shared.path().display(),
+                e
+            ));
+        }
+    }
+
+    /// Отсутствующий файл — пустая память. Повреждённая база (не читается и не
+    /// проходит integrity_check) восстанавливается из kristina.db.bak — копии
+    /// последнего удачного save; без копии или с битыми записями — PersistenceError
+    /// (иначе следующий save затёр бы файл пустыми данными). С cipher открытая
+    /// запись — PersistenceError; migrate_plaintext — однократный перенос
+    /// памяти до шифрования: открытые записи читаются и сразу сохраняются
+    /// зашифрованными
+    fn load_from_disk(&self, migrate_plaintext: bool) -> PyResult<()> {
+        let scan = || -> rusqlite::Result<(Records, Records)> {
+            Ok((self.store.scan(EPISODES)?, self.store.scan(FACTS)?))
+        };
+        let (episodes, facts) = match scan() {
+            Ok(records) => records,
+            Err(e) => match self.store.repair() {
+                Ok(true) => scan().map_err(|e| self.store.error("прочитать восстановленную", e))?,
+                Ok(false) => return Err(self.store.error("прочитать", e)),
+                Err(repair) => return Err(errors::persistence("прочитать", self.store.path(), repair)),
+            },
+        };
+        if episodes.is_empty() && facts.is_empty() {
+            return self.import_json();
+        }
+        let migrating = migrate_plaintext
+            && self.cipher().is_some()
+            && episodes.iter().chain(&facts).any(|(_, data)| !data.starts_with(crypto::MAGIC));
+        // Только при переносе открытая запись читается без cipher
+        let cipher = |data: &[u8]| if migrating && !data.starts_with(crypto::MAGIC) { None } else { self.cipher() };
+        let episodes: Vec<Episode> = episodes
+            .iter()
+            .map(|(key, data)| {
+                let data = crypto::open_record(cipher(data), EPISODES, key, data)?;
+                serde_json::from_slice(&data).map_err(|e| e.to_string())
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|e| errors::persistence("разобрать эпизод из", self.store.path(), e))?;
+        let facts: HashMap<String, String> = facts
+            .into_iter()
+            .map(|(key, value)| {
+                let value = crypto::open_record(cipher(&value), FACTS, &key, &value)?;
+                Ok((key, String::from_utf8_lossy(&value).into_owned()))
+            })
+            .collect::<Result<_, String>>()
+            .map_err(|e| errors::persistence("разобрать факт из", self.store.path(), e))?;
+        log::debug!(
+            "Память загружена из {}: эпизодов {}, фактов {}",
+            self.store.path().display(),
+            episodes.len(),
+            facts.len()
+        );
+        self.restore(Some(episodes), Some(facts));
+        if migrating {
+            self.save()?;
+            log::info!("Открытая память в {} зашифрована", self.store.path().display());
+        }
+        Ok(())
+    }
+
+    fn cipher(&self) -> Option<&Cipher> {
+        self.durable.cipher.as_ref()
+    }
+
+    fn open_fact(&self, key: &str, data: &[u8]) -> Result<String, String> {
+        crypto::open_record(self.cipher(), FACTS, key, data).map(|value| String::from_utf8_lossy(&value).into_owned())
+    }
+
+    /// Прежние episodic.json / semantic.json → память и хранилище; файлы
+    /// переименовываются в *.migrated, чтобы не импортироваться повторно.
+    /// С passphrase открытые файлы после зашифрованного save удаляются
+    fn import_json(&self) -> PyResult<()> {
+        let Some(dir) = &self.legacy_dir else {
+            return Ok(());
+        };
+        let episodic_path = dir.join("episodic.json");
+        let semantic_path = dir.join("semantic.json");
+        let episodes = read_json::<Vec<Episode>>(&episodic_path)?;
+        let facts = read_json::<HashMap<String, String>>(&semantic_path)?;
+        if episodes.is_none() && facts.is_none() {
+            return Ok(());
+        }
+        self.restore(episodes, facts);
+        self.save()?;
+        for path in [episodic_path, semantic_path] {
+            if !path.exists() {
+                continue;
+            }
+            if self.cipher().is_some() {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    errors::warn(
+                        module_path!(),
+                        &format!("Не удалось удалить открытую копию памяти {}: {}", path.display(), e),
+                    );
+                }
+                continue;
+            }
+            let migrated = path.with_extension("json.migrated");
+            if let Err(e) = std::fs::rename(&path, &migrated) {
+                // Память уже в хранилище; файл останется и импортируется снова при пустом хранилище
+                errors::warn(
+                    module_path!(),
+                    &format!("Не удалось переименовать {} в {}: {}", path.display(), migrated.display(), e),
+                );
+            }
+        }
+        log::info!("Память импортирована из JSON в {}", self.store.path().display());
+        Ok(())
+    }
+
+    /// *.json.migrated от импорта без passphrase — открытая копия памяти рядом
+    /// с зашифрованной; не удаляется молча, только предупреждение
+    fn warn_plaintext_legacy(&self) {
+        let Some(dir) = &self.legacy_dir else {
+            return;
+        };
+        for name in ["episodic.json.migrated", "semantic.json.migrated"] {
+            let path = dir.join(name);
+            if path.exists() {
+                errors::warn(
+                    module_path!(),
+                    &format!("Память зашифрована, но {} хранит её открытую копию — удалите файл", path.display()),
+                );
+            }
+        }
+    }
+
+    fn restore(&self, episodes: Option<Vec<Episode>>, facts: Option<HashMap<String, String>>) {
+        if let Some(mut episodes) = episodes {
+            let mut ep = self.durable.episodic.write();
+            renumber(&mut episodes, &self.next_id);
+            *ep = episodes;
+            let mut ki = self.keyword_index.write();
+            rebuild_index(&mut ki, &ep);
+            *self.vectors.write() = VectorIndex::rebuild(&ep);
+        }
+        if let Some(map) = facts {
+            self.durable.semantic.clear();
+            for (k, v) in map {
+                self.durable.semantic.insert(k, v);
+            }
+        }
+        // Память совпадает с хранилищем — сохранять нечего
+        let changes = self.durable.changes.load(Ordering::Acquire);
+        self.durable.saved.fetch_max(changes, Ordering::AcqRel);
+        // Просроченное за время простоя удаляется (и снова dirty)
+        self.purge_expired();
+    }
+
+    /// Удаляет наименее ценные эпизоды; возвращает их число
+    fn evict_episodes(&self) -> usize {
+        let mut episodic = self.durable.episodic.write();
+        // Параллельные add_episode (без GIL) могли уже вытеснить лишнее
+        if episodic.len() <= self.max_episodic {
+            return 0;
+        }
+        let remove_count = std::cmp::max(1, self.max_episodic / 10);
+        let now = Utc::now();
+
+        let mut scored: Vec<(usize, f64)> = episodic
+            .iter()
+            .enumerate()
+            .map(|(i, ep)| {
+                let age_hours = ep
+                    .timestamp
+                    .parse::<DateTime<Utc>>()
+                    .map(|ts| (now - ts).num_seconds() as f64 / 3600.0)
+                    .unwrap_or(1.0)
+                    .max(1.0);
+                (i, ep.importance as f64 / age_hours)
+            })
+            .collect();
+
+        // Сортируем по eviction score (наименее ценные первыми)
+        scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut to_remove: Vec<usize> = scored
+            .iter()
+            .take(remove_count)
+            .map(|&(i, _)| i)
+            .collect();
+        to_remove.sort_unstable_by(|a, b| b.cmp(a)); // Обратный порядок для безопасного удаления
+
+        let mut ki = self.keyword_index.write();
+        let mut removed = Vec::with_capacity(to_remove.len());
+        for idx in to_remove {
+            if idx < episodic.len() {
+                let episode = episodic.remove(idx);
+                unindex_episode(&mut ki, &episode);
+                removed.push(episode.id);
+            }
+        }
+        self.vectors.write().remove(&removed);
+        removed.len()
+    }
+}
+
+// ── Standalone helpers ──
+
+fn episode_text(ep: &Episode) -> String {
+    index_form(&format!("{} {}", ep.user_input, ep.response))
+}
+
+fn index_episode(ki: &mut HashMap<u64, Vec<u64>>, ep: &Episode) {
+    for h in stem_hashes(&episode_text(ep)) {
+        ki.entry(h).or_default().push(ep.id);
+    }
+}
+
+/// Точечное удаление из индекса: только списки основ слов самого эпизода
+fn unindex_episode(ki: &mut HashMap<u64, Vec<u64>>, ep: &Episode) {
+    for h in stem_hashes(&episode_text(ep)) {
+        if let Some(ids) = ki.get_mut(&h) {
+            ids.retain(|&id| id != ep.id);
+            if ids.is_empty() {
+                ki.remove(&h);
+            }
+        }
+    }
+}
+
+/// Старые эпизоды без id (и нарушенный порядок) получают новые номера;
+/// next_id — дальше последнего
+fn renumber(episodes: &mut [Episode], next_id: &AtomicU64) {
+    let mut last = 0;
+    for episode in episodes.iter_mut() {
+        if episode.id <= last {
+            episode.id = last + 1;
+        }
+        last = episode.id;
+    }
+    next_id.fetch_max(last + 1, Ordering::Relaxed);
+}
+
+fn valid_ttl(hours: f64) -> bool {
+    hours.is_finite() && hours > 0.0
+}
+
+/// Позиция эпизода по id (эпизоды упорядочены по id)
+fn position(episodes: &[Episode], id: u64) -> Option<usize> {
+    episodes.binary_search_by_key(&id, |ep| ep.id).ok()
+}
+
+/// JSON-файл памяти; None — файла нет
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> PyResult<Option<T>> {
+    let data = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(errors::persistence("прочитать", path, e)),
+    };
+    serde_json::from_str(&data)
+        .map(Some)
+        .map_err(|e| errors::persistence("разобрать повреждённый файл", path, e))
+}
+
+/// Совпавших основ слов запроса на эпизод
+fn keyword_scores(ki: &HashMap<u64, Vec<u64>>, query: &str) -> HashMap<u64, i32> {
+    let mut scores: HashMap<u64, i32> = HashMap::new();
+    for h in stem_hashes(&index_form(query)) {
+        if let Some(ids) = ki.get(&h) {
+            for &id in ids {
+                *scores.entry(id).or_insert(0) += 1;
+            }
+        }
+    }
+    scores
+}
+
+fn rebuild_index(ki: &mut HashMap<u64, Vec<u64>>, episodes: &[Episode]) {
+    ki.clear();
+    for ep in episodes {
+        index_episode(ki, ep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relevant_context_matches_word_forms() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_{}", std::process::id()));
+        let engine = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        engine.add_episode("Обсуждали миграции базы данных", "Да, переносим таблицы", "neutral", 2);
+        engine.add_episode("Кот опять спит на клавиатуре", "Милота!", "positive", 1);
+
+        let found = engine.get_relevant_context("как там миграция базы?", 3);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1, "Обсуждали миграции базы данных");
+        assert_eq!(found[0].2, 4);
+        assert_eq!(engine.get_relevant_context("котов", 3)[0].1, "Кот опять спит на клавиатуре");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_keyword_ties_keep_episode_order() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_ties_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let engine = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        let ids: Vec<u64> = (0..20).map(|i| engine.add_episode(&format!("погода {}", i), "ок", "neutral", 2)).collect();
+        engine.add_episode("погода важная", "ок", "neutral", 5);
+
+        for _ in 0..5 {
+            let found: Vec<u64> = engine.find_episodes("погода", 21).into_iter().map(|r| r.0).collect();
+            assert_eq!(found[1..], ids[..]);
+            let top: Vec<u64> = engine.find_episodes("погода", 4).into_iter().map(|r| r.0).collect();
+            assert_eq!(top[1..], ids[..3]);
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_concurrent_episodes_and_search() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_mt_{}", std::process::id()));
+        let engine = std::sync::Arc::new(MemoryEngine::new(dir.to_str().unwrap(), 10, 50).unwrap());
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let engine = std::sync::Arc::clone(&engine);
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        engine.add_episode(&format!("Поток {} обсуждал переезд {}", t, i), "Ок", "neutral", 1);
+                        engine.add_to_working("user", &format!("сообщение {}", i));
+                        assert!(engine.get_relevant_context("переезд", 3).len() <= 3);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // Индекс согласован с эпизодами, лимиты соблюдены
+        assert!(engine.durable.episodic.read().len() <= 50);
+        assert_eq!(engine.working_messages().len(), 10);
+        assert_eq!(engine.get_relevant_context("переезд", 3).len(), 3);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+    #[test]
+    fn test_save_load_and_corrupt_file() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_io_{}", std::process::id()));
+        let engine = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        engine.add_episode("Запомни пароль от wi-fi", "Запомнила", "neutral", 3);
+        engine.add_semantic("город", "Казань").unwrap();
+        engine.save().unwrap();
+
+        let restored = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        assert_eq!(restored.get_stats(), (0, 1, 1));
+        assert!(dir.join("kristina.db").exists());
+
+        // Прежний JSON импортируется в пустое хранилище; битый не подменяется молча пустой памятью
+        let legacy = dir.join("legacy");
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::write(legacy.join("semantic.json"), "{\"город\": \"Казань\"}").unwrap();
+        let imported = MemoryEngine::new(legacy.to_str().unwrap(), 10, 100).unwrap();
+        assert_eq!(imported.get_stats(), (0, 0, 1));
+        assert!(legacy.join("semantic.json.migrated").exists());
+        let broken = dir.join("broken");
+        std::fs::create_dir_all(&broken).unwrap();
+        std::fs::write(broken.join("semantic.json"), "{\"город\": ").unwrap();
+        assert!(MemoryEngine::new(broken.to_str().unwrap(), 10, 100).is_err());
+        restored.store.put(EPISODES, "99999999", b"{").unwrap();
+        assert!(restored.load().is_err());
+        // Неудачная загрузка не применяется частично
+        assert_eq!(restored.get_stats(), (0, 1, 1));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_corrupt_db_recovers_from_snapshot() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_recover_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let engine = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        engine.add_episode("Переезжаю в Казань", "Удачи с переездом", "joy", 4);
+        engine.add_episode("Кота зовут Барсик", "Запомнила", "neutral", 3);
+        engine.add_semantic("город", "Казань").unwrap();
+        engine.save().unwrap();
+        assert!(dir.join("kristina.db.bak").exists());
+        drop(engine);
+
+        // Файл затёрт мусором: открытие восстанавливает последний save из копии
+        std::fs::write(dir.join("kristina.db"), vec![0xAB; 8192]).unwrap();
+        let restored = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        assert_eq!(restored.get_stats(), (0, 2, 1));
+        assert_eq!(restored.get_relevant_context("Барсик", 3).len(), 1);
+        assert!(dir.join("kristina.db.corrupt").exists());
+
+        // Заголовок цел, испорчена страница таблицы — ловит integrity_check
+        assert!(!restored.store.repair().unwrap());
+        restored.add_episode("Купила велосипед", "Здорово", "joy", 3);
+        restored.save().unwrap();
+        drop(restored);
+        let mut data = std::fs::read(dir.join("kristina.db")).unwrap();
+        let page = data.len() - 4096;
+        data[page..].fill(0xAB);
+        std::fs::write(dir.join("kristina.db"), data).unwrap();
+        let restored = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        assert_eq!(restored.get_stats(), (0, 3, 1));
+        assert_ne!(std::fs::read(dir.join("kristina.db")).unwrap()[page..], vec![0xAB; 4096][..]);
+        drop(restored);
+
+        // Без копии битый файл — ошибка, а не пустая память
+        std::fs::remove_file(dir.join("kristina.db.bak")).unwrap();
+        std::fs::write(dir.join("kristina.db"), vec![0xAB; 8192]).unwrap();
+        assert!(MemoryEngine::new(dir.to_str().unwrap(), 10, 100).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_shared_working_memory_and_facts() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_shared_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let shared = || MemoryEngine::with_store(storage::open(&dir).unwrap(), None, 3, 100, true, Options::default()).unwrap();
+        let (a, b) = (shared(), shared());
+
+        a.add_to_working("user", "привет");
+        b.add_to_working("assistant", "здравствуй");
+        let working = a.get_working_memory();
+        assert_eq!(working.iter().map(|e| e.1.as_str()).collect::<Vec<_>>(), vec!["привет", "здравствуй"]);
+        for i in 0..5 {
+            a.add_to_working("user", &i.to_string());
+        }
+        assert_eq!(b.working_messages().len(), 3);
+        b.clear_working();
+        assert!(a.get_working_memory().is_empty());
+
+        a.add_semantic("город", "Казань").unwrap();
+        assert_eq!(b.get_semantic("город").unwrap().as_deref(), Some("Казань"));
+        // save эпизодов одного процесса не стирает факты другого
+        b.add_semantic("имя", "Аня").unwrap();
+        a.save().unwrap();
+        assert_eq!(a.get_semantic("имя").unwrap().as_deref(), Some("Аня"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_search_by_embedding_merges_keywords() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_vec_{}", std::process::id()));
+        let engine = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        engine.insert_episode("Обсуждали переезд в Казань", "Да, в августе", "neutral", 1, Some(vec![1.0, 0.1, 0.0]), None);
+        engine.insert_episode("Кот спит на клавиатуре", "Милота!", "positive", 1, Some(vec![0.0, 1.0, 0.0]), None);
+        engine.insert_episode("Рецепт борща", "Свёкла обязательна", "neutral", 1, None, None);
+
+        // Перефразированный запрос без общих слов — находится по вектору
+        let found = engine.search_embedding(&[0.9, 0.0, 0.1], 3, Some("смена города"), 0.3).unwrap();
+        assert_eq!(found[0].1, "Обсуждали переезд в Казань");
+        assert!(found[0].2 > found[1].2);
+        // Эпизод без эмбеддинга попадает в выдачу по ключевым словам
+        let found = engine.search_embedding(&[0.0, 1.0, 0.0], 3, Some("борщ"), 0.5).unwrap();
+        assert_eq!(found.len(), 3);
+        assert_eq!((found[0].1.as_str(), found[0].2), ("Кот спит на клавиатуре", 0.5));
+        assert!(found.iter().any(|r| r.1 == "Рецепт борща" && (r.2 - 0.5).abs() < 1e-9));
+
+        assert!(engine.search_embedding(&[1.0, 0.0], 3, None, 0.3).unwrap_err().contains("Размерность"));
+        assert!(engine.search_embedding(&[0.0, 0.0, 0.0], 3, None, 0.3).is_err());
+
+        // Эмбеддинги переживают save / load, индекс перестраивается
+        engine.save().unwrap();
+        let reloaded = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        let found = reloaded.search_embedding(&[0.0, 1.0, 0.0], 1, None, 0.0).unwrap();
+        assert_eq!(found[0].1, "Кот спит на клавиатуре");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_autosave_writes_only_changes_and_close_flushes() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_autosave_{}", std::process::id()));
+        let open = |autosave| {
+            let options = Options { autosave, ..Options::default() };
+            MemoryEngine::with_store(storage::open(&dir).unwrap(), None, 10, 100, false, options).unwrap()
+        };
+        let engine = open(Some(Duration::from_millis(20)));
+        assert!(!engine.durable.is_dirty());
+
+        engine.add_semantic("город", "Казань").unwrap();
+        assert!(engine.durable.is_dirty());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while engine.durable.is_dirty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!engine.durable.is_dirty());
+        assert_eq!(open(None).get_semantic("город").unwrap().as_deref(), Some("Казань"));
+
+        // close останавливает поток и дописывает то, что он не успел
+        engine.shutdown().unwrap();
+        engine.add_episode("Обсуждали переезд", "Да", "neutral", 1);
+        assert!(engine.durable.is_dirty());
+        engine.shutdown().unwrap();
+        assert!(!engine.durable.is_dirty());
+        assert_eq!(open(None).durable.episodic.read().len(), 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_consolidate_promotes_facts_once() {
+        let facts = extract_facts("Мне 30 лет и я работаю врачом в больнице. My name is John Smith and I live in Boston");
+        assert_eq!(
+            facts,
+            [("возраст", "30"), ("работа", "врачом в больнице"), ("имя", "John Smith"), ("город", "Boston")]
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+
+        let dir = std::env::temp_dir().join(format!("kristina_memory_consolidate_{}", std::process::id()));
+        let engine = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        engine.add_episode("Меня зовут Аня, я живу в Нижнем Новгороде и люблю кофе", "Приятно!", "positive", 3);
+        engine.add_episode("Мой любимый цвет — синий", "Красиво", "neutral", 1);
+        engine.add_episode("Меня зовут не так важно", "Ок", "neutral", 1);
+        // Часто находимый эпизод становится кандидатом и без высокой importance
+        for _ in 0..3 {
+            engine.get_relevant_context("какой любимый цвет", 1);
+        }
+
+        let added = engine.consolidate_with(3, 3, |user_input, _| Ok(extract_facts(user_input))).unwrap();
+        let added: Vec<(&str, &str)> = added.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(added, [("имя", "Аня"), ("город", "Нижнем Новгороде"), ("любимый цвет", "синий")]);
+        assert_eq!(engine.get_semantic("любимый цвет").unwrap().as_deref(), Some("синий"));
+        assert!(engine.consolidate_with(3, 3, |_, _| panic!("эпизоды уже консолидированы")).unwrap().is_empty());
+
+        // Счётчик и отметка переживают save / load
+        engine.save().unwrap();
+        let reloaded = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        assert_eq!(reloaded.durable.episodic.read()[1].retrievals.load(Ordering::Relaxed), 3);
+        assert!(reloaded.consolidate_with(3, 3, |_, _| panic!("эпизоды уже консолидированы")).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_consolidate_failing_extractor_keeps_finished_episodes() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_consolidate_err_{}", std::process::id()));
+        let engine = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        engine.add_episode("Меня зовут Аня", "Приятно!", "positive", 3);
+        engine.add_episode("Я живу в Казани", "Красивый город", "neutral", 3);
+        engine.add_episode("Мне 30 лет", "Ок", "neutral", 3);
+
+        // extractor падает на втором эпизоде: факты первого уже записаны и он отмечен
+        let mut calls = Vec::new();
+        let result = engine.consolidate_with(3, 3, |user_input, _| {
+            calls.push(user_input.to_string());
+            if calls.len() == 2 {
+                return Err(PyValueError::new_err("сбой extractor"));
+            }
+            Ok(extract_facts(user_input))
+        });
+        assert!(result.is_err());
+        assert_eq!(engine.get_semantic("имя").unwrap().as_deref(), Some("Аня"));
+        assert!(engine.dirty());
+
+        // Повтор обрабатывает только необработанные эпизоды
+        let mut retried = Vec::new();
+        let added = engine
+            .consolidate_with(3, 3, |user_input, _| {
+                retried.push(user_input.to_string());
+                Ok(extract_facts(user_input))
+            })
+            .unwrap();
+        assert_eq!(retried, ["Я живу в Казани", "Мне 30 лет"]);
+        assert!(added.iter().all(|(key, _)| key != "имя"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_delete_and_update_episodes_by_id() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_ids_{}", std::process::id()));
+        let engine = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        let wrong = engine.add_episode("Переезжаем в Самару", "Понятно", "neutral", 1);
+        let right = engine.add_episode("Переезжаем в Казань", "Отлично", "neutral", 1);
+        let other = engine.add_episode("Кот спит", "Милота", "positive", 1);
+        assert!(wrong < right && right < other);
+
+        assert!(engine.delete_episode(wrong));
+        assert!(!engine.delete_episode(wrong));
+        // Индекс без перестройки: "Самара" больше не находится, id остальных не сдвинулись
+        assert!(engine.find_episodes("самара", 3).is_empty());
+        assert!(!engine.keyword_index.read().values().any(|ids| ids.contains(&wrong)));
+        assert_eq!(engine.find_episodes("переезжаем", 3)[0].0, right);
+        assert_eq!(engine.find_episodes("кот", 3)[0].0, other);
+
+        assert!(engine.update_episode(right, Some(5), Some("positive".to_string())));
+        assert!(!engine.update_episode(wrong, Some(5), None));
+        assert_eq!(engine.find_episodes("казань", 3)[0].3, 5);
+
+        // id сохраняются и не переиспользуются после load
+        engine.save().unwrap();
+        let reloaded = MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap();
+        assert_eq!(reloaded.find_episodes("кот", 3)[0].0, other);
+        assert!(reloaded.add_episode("Новый эпизод", "Ок", "neutral", 1) > other);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_purge_expired_by_episode_and_importance_ttl() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_ttl_{}", std::process::id()));
+        let open = || {
+            let options = Options { ttl_tiers: HashMap::from([(1, 24.0)]), ..Options::default() };
+            MemoryEngine::with_store(storage::open(&dir).unwrap(), None, 10, 100, false, options).unwrap()
+        };
+        let backdate = |engine: &MemoryEngine, id: u64, hours: i64| {
+            let mut episodic = engine.durable.episodic.write();
+            let pos = position(&episodic, id).unwrap();
+            episodic[pos].timestamp = (Utc::now() - chrono::Duration::hours(hours)).to_rfc3339();
+        };
+        let engine = open();
+        let small_talk = engine.add_episode("Привет, как дела", "Хорошо", "neutral", 1);
+        let important = engine.add_episode("Переезжаем в Казань", "Отлично", "positive", 5);
+        let reminder = engine.insert_episode("Напомни про встречу", "Ок", "neutral", 5, None, Some(1.0));
+        backdate(&engine, small_talk, 2);
+        backdate(&engine, reminder, 2);
+        assert_eq!(engine.purge_expired(), 1);
+        backdate(&engine, small_talk, 25);
+        assert_eq!(engine.purge_expired(), 1);
+        assert_eq!(engine.purge_expired(), 0);
+        assert!(engine.find_episodes("привет", 3).is_empty());
+        assert_eq!(engine.find_episodes("казань", 3)[0].0, important);
+
+        // Просроченное за время простоя удаляется при загрузке
+        let stale = engine.add_episode("Погода сегодня", "Солнечно", "neutral", 1);
+        backdate(&engine, stale, 30);
+        engine.save().unwrap();
+        let reloaded = open();
+        assert_eq!(reloaded.get_stats().1, 1);
+        assert!(reloaded.durable.is_dirty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_jsonl_export_import_merge_and_replace() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_jsonl_{}", std::process::id()));
+        let source = MemoryEngine::new(dir.join("a").to_str().unwrap(), 10, 100).unwrap();
+        source.insert_episode("Переезжаем в Казань", "Отлично", "positive", 3, Some(vec![1.0, 0.0]), None);
+        source.add_episode("Кот спит", "Милота", "positive", 1);
+        source.add_semantic("город", "Казань").unwrap();
+        let backup = dir.join("memory.jsonl");
+        assert_eq!(source.export_to(&backup).unwrap(), (2, 1));
+        assert_eq!(std::fs::read_to_string(&backup).unwrap().lines().count(), 3);
+
+        let target = MemoryEngine::new(dir.join("b").to_str().unwrap(), 10, 100).unwrap();
+        target.add_episode("Свой эпизод", "Ок", "neutral", 1);
+        assert_eq!(target.import_from(&backup, true).unwrap(), (2, 1));
+        // Повторный merge не дублирует эпизоды
+        assert_eq!(target.import_from(&backup, true).unwrap(), (0, 1));
+        assert_eq!(target.get_stats().1, 3);
+        assert_eq!(target.find_episodes("казань", 3).len(), 1);
+        assert_eq!(target.search_embedding(&[1.0, 0.0], 1, None, 0.0).unwrap()[0].1, "Переезжаем в Казань");
+
+        assert_eq!(target.import_from(&backup, false).unwrap(), (2, 1));
+        assert!(target.find_episodes("свой", 3).is_empty());
+        assert_eq!(target.get_semantic("город").unwrap().as_deref(), Some("Казань"));
+
+        // Битая строка — память не меняется
+        std::fs::write(&backup, "{\"type\": \"fact\", \"key\": \"имя\", \"value\": \"Аня\"}\nне json\n").unwrap();
+        assert!(target.import_from(&backup, true).is_err());
+        assert_eq!(target.get_semantic("имя").unwrap(), None);
+        assert_eq!(target.get_stats().1, 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_import_writes_store_before_memory() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_import_tx_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let shared = || MemoryEngine::with_store(storage::open(&dir).unwrap(), None, 10, 100, true, Options::default()).unwrap();
+        let (a, b) = (shared(), shared());
+        a.add_episode("Свой эпизод", "Ок", "neutral", 1);
+        a.save().unwrap();
+        b.add_semantic("город", "Казань").unwrap();
+        let backup = dir.join("memory.jsonl");
+        std::fs::write(
+            &backup,
+            "{\"type\": \"fact\", \"key\": \"имя\", \"value\": \"Аня\"}\n{\"type\": \"fact\", \"key\": \"сбой\", \"value\": \"-\"}\n",
+        )
+        .unwrap();
+
+        // Запись падает на последнем факте — ни память, ни база не меняются
+        let conn = rusqlite::Connection::open(dir.join("kristina.db")).unwrap();
+        conn.execute_batch("CREATE TRIGGER fail BEFORE INSERT ON records WHEN NEW.key = 'сбой' BEGIN SELECT RAISE(ABORT, 'сбой'); END")
+            .unwrap();
+        assert!(a.import_from(&backup, false).is_err());
+        assert_eq!((a.get_stats().1, shared().get_stats().1), (1, 1));
+        assert_eq!(b.get_semantic("имя").unwrap(), None);
+        assert_eq!(b.get_semantic("город").unwrap().as_deref(), Some("Казань"));
+
+        // merge=False заменяет и общие факты; импорт уже на диске
+        conn.execute_batch("DROP TRIGGER fail").unwrap();
+        assert_eq!(a.import_from(&backup, false).unwrap(), (0, 2));
+        assert_eq!(b.get_semantic("город").unwrap(), None);
+        assert_eq!(b.get_semantic("имя").unwrap().as_deref(), Some("Аня"));
+        assert!(!a.durable.is_dirty());
+        assert_eq!(shared().get_stats().1, 0);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_encrypted_store_and_export() {
+        let dir = std::env::temp_dir().join(format!("kristina_memory_crypto_{}", std::process::id()));
+        let open_with = |passphrase: Option<&str>, migrate_plaintext: bool| {
+            let cipher = passphrase.map(|p| Cipher::new(p).unwrap());
+            let options = Options { cipher, migrate_plaintext, ..Options::default() };
+            MemoryEngine::with_store(storage::open(&dir).unwrap(), None, 10, 100, false, options)
+        };
+        let open = |passphrase: Option<&str>| open_with(passphrase, false);
+        // Открытая память до включения шифрования читается только явным переносом
+        let plain = open(None).unwrap();
+        plain.add_episode("Меня зовут Аня", "Приятно", "positive", 3);
+        plain.save().unwrap();
+        assert!(open(Some("секрет")).is_err());
+        let engine = open_with(Some("секрет"), true).unwrap();
+        assert!(!engine.durable.is_dirty());
+        engine.add_semantic("имя", "Аня").unwrap();
+        engine.save().unwrap();
+
+        let records = engine.store.scan(EPISODES).unwrap().into_iter().chain(engine.store.scan(FACTS).unwrap());
+        assert!(records.into_iter().all(|(_, data)| data.starts_with(crypto::MAGIC)));
+        assert!(open(None).is_err());
+        assert!(open(Some("другой")).is_err());
+        let reopened = open(Some("секрет")).unwrap();
+        assert_eq!(reopened.find_episodes("аня", 3).len(), 1);
+        assert_eq!(reopened.get_semantic("имя").unwrap().as_deref(), Some("Аня"));
+
+        // Запись, перенесённая в другую строку, или открытая подмена не читаются
+        let fact = engine.store.get(FACTS, "имя").unwrap().unwrap();
+        engine.store.put(FACTS, "город", &fact).unwrap();
+        assert!(open(Some("секрет")).is_err());
+        engine.store.put(FACTS, "город", "Казань".as_bytes()).unwrap();
+        assert!(open(Some("секрет")).is_err());
+        engine.store.remove(FACTS, &["город".to_string()]).unwrap();
+
+        // Экспорт зашифрован тем же паролем, заголовок узнаётся при импорте
+        let backup = dir.join("memory.jsonl");
+        engine.export_to(&backup).unwrap();
+        let data = std::fs::read(&backup).unwrap();
+        assert!(data.starts_with(crypto::MAGIC) && !data.windows(6).any(|w| w == "Аня".as_bytes()));
+        assert_eq!(reopened.import_from(&backup, false).unwrap(), (1, 1));
+        assert!(plain.import_from(&backup, true).is_err());
+        // Без завершающего кадра (длина | nonce | число кадров | тег) поток обрезан
+        std::fs::write(&backup, &data[..data.len() - (4 + 12 + 8 + 16)]).unwrap();
+        assert!(reopened.import_from(&backup, false).is_err());
+
+        // Прежние JSON с passphrase импортируются и удаляются: открытой копии не остаётся
+        let legacy = dir.join("legacy");
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::write(legacy.join("semantic.json"), "{\"город\": \"Казань\"}").unwrap();
+        std::fs::write(legacy.join("episodic.json"), "[]").unwrap();
+        let options = Options { cipher: Some(Cipher::new("секрет").unwrap()), ..Options::default() };
+        let imported =
+            MemoryEngine::with_store(storage::open(&legacy).unwrap(), Some(legacy.clone()), 10, 100, false, options)
+                .unwrap();
+        assert_eq!(imported.get_semantic("город").unwrap().as_deref(), Some("Казань"));
+        let leftovers: Vec<_> = std::fs::read_dir(&legacy)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.contains(".json"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,330 @@
+//! Metrics — счётчики и гистограммы всех модулей ядра
+//!
+//! - Один реестр на процесс: каждый Metrics() в Python — ручка к нему
+//! - Модули пишут без блокировок (DashMap + атомики):
+//!   - memory_retrieval_seconds, memory_episodes_evicted_total,
+//!     memory_episodes_expired_total, memory_facts_consolidated_total — MemoryEngine
+//!   - embedding_cache_hits_total / _misses_total / _evictions_total — EmbeddingCache
+//!     (и производная embedding_cache_hit_rate)
+//!   - tool_calls_parsed_total, tool_parse_failures_total — ToolCallParser
+//!   - threads_opened_total, threads_closed_total — ThreadTracker
+//!   - context_compression_seconds — ContextCompressor (role-aware сжатие)
+//!   - sessions_expired_total, sessions_evicted_total — SessionManager
+//!   - rate_limit_rejections_total — RateLimiter
+//!   - pipeline_seconds — Pipeline.process
+//! - snapshot() → dict; to_prometheus() — текстовый формат Prometheus
+//!   (имена с префиксом kristina_)
+//! - Python может добавлять свои: inc(name), observe(name, seconds)
+//! - set_enabled(False) выключает сбор: одна атомарная проверка на вызов
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyDict;
+use dashmap::DashMap;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+
+const PREFIX: &str = "kristina_";
+
+/// Верхние границы корзин гистограмм (секунды); последняя корзина — +Inf
+const BUCKETS: &[f64] = &[0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// Производные доли: (имя, счётчик «успехов», счётчик «неудач»)
+const RATIOS: &[(&str, &str, &str)] =
+    &[("embedding_cache_hit_rate", "embedding_cache_hits_total", "embedding_cache_misses_total")];
+
+// ── Реестр ──
+
+struct Histogram {
+    /// Не накопительные счётчики корзин; последний — сверх всех границ
+    buckets: Vec<AtomicU64>,
+    /// f64 в битах
+    sum: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: (0..=BUCKETS.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0f64.to_bits()),
+            count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&self, value: f64) {
+        let slot = BUCKETS.iter().position(|&le| value <= le).unwrap_or(BUCKETS.len());
+        self.buckets[slot].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self.sum.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + value).to_bits())
+        });
+    }
+
+    fn summary(&self) -> HistogramSummary {
+        let mut cumulative = 0;
+        let buckets = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                cumulative += b.load(Ordering::Relaxed);
+                (BUCKETS.get(i).copied().unwrap_or(f64::INFINITY), cumulative)
+            })
+            .collect();
+        HistogramSummary {
+            count: self.count.load(Ordering::Relaxed),
+            sum: f64::from_bits(self.sum.load(Ordering::Relaxed)),
+            buckets,
+        }
+    }
+}
+
+struct HistogramSummary {
+    count: u64,
+    sum: f64,
+    /// (верхняя граница, накопленное число наблюдений)
+    buckets: Vec<(f64, u64)>,
+}
+
+struct Registry {
+    enabled: AtomicBool,
+    counters: DashMap<String, AtomicU64>,
+    histograms: DashMap<String, Histogram>,
+}
+
+impl Registry {
+    fn new() -> Self {
+        Self { enabled: AtomicBool::new(true), counters: DashMap::new(), histograms: DashMap::new() }
+    }
+
+    fn inc(&self, name: &str, n: u64) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        match self.counters.get(name) {
+            Some(counter) => counter.fetch_add(n, Ordering::Relaxed),
+            None => self.counters.entry(name.to_string()).or_default().fetch_add(n, Ordering::Relaxed),
+        };
+    }
+
+    fn observe(&self, name: &str, value: f64) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        match self.histograms.get(name) {
+            Some(histogram) => histogram.observe(value),
+            None => self.histograms.entry(name.to_string()).or_default().observe(value),
+        }
+    }
+
+    fn counters(&self) -> BTreeMap<String, u64> {
+        self.counters.iter().map(|c| (c.key().clone(), c.load(Ordering::Relaxed))).collect()
+    }
+
+    fn histograms(&self) -> BTreeMap<String, HistogramSummary> {
+        self.histograms.iter().map(|h| (h.key().clone(), h.summary())).collect()
+    }
+
+    /// Доли, для которых есть хотя бы одно наблюдение
+    fn ratios(counters: &BTreeMap<String, u64>) -> BTreeMap<&'static str, f64> {
+        RATIOS
+            .iter()
+            .filter_map(|&(name, good, bad)| {
+                let good = counters.get(good).copied().unwrap_or(0);
+                let total = good + counters.get(bad).copied().unwrap_or(0);
+                (total > 0).then(|| (name, good as f64 / total as f64))
+            })
+            .collect()
+    }
+
+    fn prometheus(&self) -> String {
+        let mut out = String::new();
+        let counters = self.counters();
+        for (name, value) in &counters {
+            let _ = writeln!(out, "# TYPE {PREFIX}{name} counter\n{PREFIX}{name} {value}");
+        }
+        for (name, value) in Self::ratios(&counters) {
+            let _ = writeln!(out, "# TYPE {PREFIX}{name} gauge\n{PREFIX}{name} {value}");
+        }
+        for (name, h) in self.histograms() {
+            let _ = writeln!(out, "# TYPE {PREFIX}{name} histogram");
+            for (le, count) in &h.buckets {
+                let le = if le.is_infinite() { "+Inf".to_string() } else { le.to_string() };
+                let _ = writeln!(out, "{PREFIX}{name}_bucket{{le=\"{le}\"}} {count}");
+            }
+            let _ = writeln!(out, "{PREFIX}{name}_sum {}\n{PREFIX}{name}_count {}", h.sum, h.count);
+        }
+        out
+    }
+
+    fn reset(&self) {
+        self.counters.clear();
+        self.histograms.clear();
+    }
+}
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+// ── API для модулей ядра ──
+
+pub(crate) fn inc(name: &str, n: u64) {
+    REGISTRY.inc(name, n);
+}
+
+pub(crate) fn observe(name: &str, seconds: f64) {
+    REGISTRY.observe(name, seconds);
+}
+
+/// Замер длительности: наблюдение в гистограмму name при выходе из области
+pub(crate) struct Timer {
+    name: &'static str,
+    started: Instant,
+}
+
+pub(crate) fn timer(name: &'static str) -> Timer {
+    Timer { name, started: Instant::now() }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        observe(self.name, elapsed);
+        log::trace!("{}: {:.3} мс", self.name, elapsed * 1000.0);
+    }
+}
+
+/// Имя метрики Prometheus: [a-zA-Z_:][a-zA-Z0-9_:]*
+fn check_name(name: &str) -> PyResult<()> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    if valid {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err(format!("Некорректное имя метрики: {:?}", name)))
+    }
+}
+
+// ── PyO3 класс ──
+
+#[pyclass(frozen)]
+pub struct Metrics {}
+
+#[pymethods]
+impl Metrics {
+    #[new]
+    fn new() -> Self {
+        Self {}
+    }
+
+    /// {"counters": {name: int}, "ratios": {name: float},
+    ///  "histograms": {name: {"count", "sum", "mean", "buckets": [(le, cumulative)]}}}
+    fn snapshot<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let counters = REGISTRY.counters();
+        let histograms = PyDict::new(py);
+        for (name, h) in REGISTRY.histograms() {
+            let item = PyDict::new(py);
+            item.set_item("count", h.count)?;
+            item.set_item("sum", h.sum)?;
+            item.set_item("mean", if h.count > 0 { h.sum / h.count as f64 } else { 0.0 })?;
+            item.set_item("buckets", h.buckets)?;
+            histograms.set_item(name, item)?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("ratios", Registry::ratios(&counters))?;
+        dict.set_item("counters", counters)?;
+        dict.set_item("histograms", histograms)?;
+        Ok(dict)
+    }
+
+    /// Текстовый формат Prometheus (exposition format 0.0.4)
+    fn to_prometheus(&self) -> String {
+        REGISTRY.prometheus()
+    }
+
+    /// Счётчик из Python: inc("llm_requests_total")
+    #[pyo3(signature = (name, value=1))]
+    fn inc(&self, name: &str, value: u64) -> PyResult<()> {
+        check_name(name)?;
+        REGISTRY.inc(name, value);
+        Ok(())
+    }
+
+    /// Наблюдение в гистограмму из Python (секунды): observe("llm_latency_seconds", 0.8)
+    fn observe(&self, name: &str, value: f64) -> PyResult<()> {
+        check_name(name)?;
+        if !value.is_finite() || value < 0.0 {
+            return Err(PyValueError::new_err(format!("Значение должно быть >= 0: {}", value)));
+        }
+        REGISTRY.observe(name, value);
+        Ok(())
+    }
+
+    /// Обнуляет все метрики
+    fn reset(&self) {
+        REGISTRY.reset();
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        REGISTRY.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    fn is_enabled(&self) -> bool {
+        REGISTRY.enabled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_and_ratios() {
+        let registry = Registry::new();
+        registry.inc("embedding_cache_hits_total", 3);
+        registry.inc("embedding_cache_misses_total", 1);
+        registry.inc("embedding_cache_hits_total", 1);
+        let counters = registry.counters();
+        assert_eq!(counters["embedding_cache_hits_total"], 4);
+        assert_eq!(Registry::ratios(&counters)["embedding_cache_hit_rate"], 0.8);
+
+        registry.enabled.store(false, Ordering::Relaxed);
+        registry.inc("embedding_cache_hits_total", 10);
+        assert_eq!(registry.counters()["embedding_cache_hits_total"], 4);
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let registry = Registry::new();
+        for value in [0.00005, 0.003, 0.003, 10.0] {
+            registry.observe("memory_retrieval_seconds", value);
+        }
+        let h = &registry.histograms()["memory_retrieval_seconds"];
+        assert_eq!(h.count, 4);
+        assert!((h.sum - 10.00605).abs() < 1e-9);
+        assert_eq!(h.buckets[0], (0.0001, 1));
+        assert_eq!(h.buckets.iter().find(|(le, _)| *le == 0.005).unwrap().1, 3);
+        assert_eq!(*h.buckets.last().unwrap(), (f64::INFINITY, 4));
+    }
+
+    #[test]
+    fn test_prometheus_format() {
+        let registry = Registry::new();
+        registry.inc("tool_parse_failures_total", 2);
+        registry.observe("pipeline_seconds", 0.02);
+        let text = registry.prometheus();
+        assert!(text.contains("# TYPE kristina_tool_parse_failures_total counter\nkristina_tool_parse_failures_total 2\n"));
+        assert!(text.contains("kristina_pipeline_seconds_bucket{le=\"0.01\"} 0\n"));
+        assert!(text.contains("kristina_pipeline_seconds_bucket{le=\"0.025\"} 1\n"));
+        assert!(text.contains("kristina_pipeline_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("kristina_pipeline_seconds_count 1\n"));
+        assert!(check_name("9bad").is_err());
+        assert!(check_name("llm_requests_total").is_ok());
+    }
+}
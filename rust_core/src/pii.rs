@@ -0,0 +1,428 @@
+//! PiiScrubber — поиск и маскирование персональных данных
+//!
+//! Без regex: ручные сканеры по символам + Aho-Corasick для словарных маркеров.
+//! - email: локальная часть @ домен с точкой и буквенной зоной
+//! - phone: +7/7/8 и 11 цифр, либо "+" и 10–15 цифр, либо 10 цифр с разделителями
+//! - card: 13–19 цифр с проверкой Луна
+//! - inn: 10/12 цифр подряд с контрольными суммами ФНС
+//! - passport: серия и номер ("45 08 123456", "4508 123456") или 10 цифр после слова "паспорт"
+//! - address: маркер улицы ("ул.", "проспект", "street"...) + название + номер дома
+//!
+//! Смещения — в символах; пересекающиеся находки не возвращаются (побеждает более ранняя).
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use aho_corasick::{AhoCorasick, MatchKind};
+use std::collections::HashSet;
+
+const KINDS: &[&str] = &["email", "phone", "card", "inn", "passport", "address"];
+
+const STREET_MARKERS: &[&str] = &[
+    "ул.", "улица", "улице", "улицу", "пр-т", "проспект", "просп.", "пер.", "переулок", "бульвар", "б-р",
+    "шоссе", "наб.", "набережная", "пл.", "площадь", "street", "st.", "avenue", "ave.", "road", "rd.",
+];
+/// Части адреса после названия улицы
+const ADDRESS_PARTS: &[&str] = &[
+    "д", "д.", "дом", "к", "к.", "корп", "корп.", "корпус", "стр", "стр.", "строение", "кв", "кв.", "квартира",
+    "оф", "оф.", "офис", "apt", "apt.",
+];
+const PASSPORT_MARKERS: &[&str] = &["паспорт", "passport"];
+/// На сколько символов назад искать слово "паспорт" перед 10 цифрами
+const PASSPORT_CONTEXT_CHARS: usize = 30;
+/// Сколько слов названия улицы допускается до номера дома
+const MAX_STREET_WORDS: usize = 4;
+
+/// Найденный фрагмент: kind — тип данных, start/end — смещения в символах
+#[pyclass(frozen, get_all)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PiiMatch {
+    pub kind: String,
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[pymethods]
+impl PiiMatch {
+    fn __repr__(&self) -> String {
+        format!("PiiMatch(kind={:?}, start={}, end={})", self.kind, self.start, self.end)
+    }
+}
+
+#[pyclass(frozen)]
+pub struct PiiScrubber {
+    kinds: HashSet<String>,
+    street_ac: AhoCorasick,
+    passport_ac: AhoCorasick,
+}
+
+#[pymethods]
+impl PiiScrubber {
+    /// kinds — какие типы искать (по умолчанию все)
+    #[new]
+    #[pyo3(signature = (kinds=None))]
+    fn new(kinds: Option<Vec<String>>) -> PyResult<Self> {
+        let kinds: HashSet<String> = match kinds {
+            Some(list) => {
+                if let Some(bad) = list.iter().find(|k| !KINDS.contains(&k.as_str())) {
+                    return Err(PyValueError::new_err(format!(
+                        "Неизвестный тип персональных данных: {} (доступны: {})",
+                        bad,
+                        KINDS.join(", ")
+                    )));
+                }
+                list.into_iter().collect()
+            }
+            None => KINDS.iter().map(|k| k.to_string()).collect(),
+        };
+        let build = |patterns: &[&str]| {
+            AhoCorasick::builder()
+                .ascii_case_insensitive(true)
+                .match_kind(MatchKind::LeftmostLongest)
+                .build(patterns)
+                .map_err(|e| PyValueError::new_err(e.to_string()))
+        };
+        Ok(Self { kinds, street_ac: build(STREET_MARKERS)?, passport_ac: build(PASSPORT_MARKERS)? })
+    }
+
+    /// Все находки по возрастанию start
+    fn scan(&self, text: &str) -> Vec<PiiMatch> {
+        self.find(text)
+    }
+
+    fn contains_pii(&self, text: &str) -> bool {
+        !self.find(text).is_empty()
+    }
+
+    /// Текст для логов: находки заменены на "[EMAIL]", "[PHONE]" и т.п.
+    /// или на placeholder, если он задан
+    #[pyo3(signature = (text, placeholder=None))]
+    fn mask(&self, text: &str, placeholder: Option<&str>) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::with_capacity(text.len());
+        let mut pos = 0;
+        for m in self.find(text) {
+            out.extend(&chars[pos..m.start]);
+            match placeholder {
+                Some(p) => out.push_str(p),
+                None => {
+                    out.push('[');
+                    out.push_str(&m.kind.to_uppercase());
+                    out.push(']');
+                }
+            }
+            pos = m.end;
+        }
+        out.extend(&chars[pos..]);
+        out
+    }
+}
+
+// ── Сканеры ──
+
+type Span = (&'static str, usize, usize);
+
+impl PiiScrubber {
+    fn find(&self, text: &str) -> Vec<PiiMatch> {
+        let chars: Vec<char> = text.chars().collect();
+        let lower: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+
+        let mut spans: Vec<Span> = Vec::new();
+        spans.extend(emails(&chars));
+        spans.extend(self.numbers(&chars, &lower));
+        spans.extend(self.addresses(&chars, &lower));
+        spans.retain(|(kind, _, _)| self.kinds.contains(*kind));
+        spans.sort_by_key(|&(_, s, e)| (s, std::cmp::Reverse(e)));
+
+        let mut result: Vec<PiiMatch> = Vec::new();
+        for (kind, start, end) in spans {
+            if result.last().is_some_and(|last| start < last.end) {
+                continue;
+            }
+            result.push(PiiMatch { kind: kind.to_string(), text: chars[start..end].iter().collect(), start, end });
+        }
+        result
+    }
+
+    /// Серии цифр с разделителями " -()" → phone / card / inn / passport
+    fn numbers(&self, chars: &[char], lower: &[char]) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let n = chars.len();
+        let mut i = 0;
+        while i < n {
+            let starts = chars[i].is_ascii_digit()
+                || (matches!(chars[i], '+' | '(') && i + 1 < n && chars[i + 1].is_ascii_digit());
+            if !starts || (i > 0 && chars[i - 1].is_alphanumeric()) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            let mut digits = String::new();
+            let mut groups: Vec<usize> = vec![0];
+            let mut end = i;
+            let mut j = i;
+            while j < n {
+                let c = chars[j];
+                if c.is_ascii_digit() {
+                    digits.push(c);
+                    *groups.last_mut().unwrap() += 1;
+                    end = j + 1;
+                } else if matches!(c, ' ' | '-' | '(' | ')') || (c == '+' && j == start) {
+                    // Разделитель — только если дальше (через 1–2 знака) снова цифра
+                    let next_digit = chars[j + 1..n.min(j + 3)].iter().position(|c| c.is_ascii_digit());
+                    if c != '+' && next_digit.is_none() {
+                        break;
+                    }
+                    if *groups.last().unwrap() > 0 {
+                        groups.push(0);
+                    }
+                } else {
+                    break;
+                }
+                j += 1;
+            }
+            // Число не должно продолжаться буквой ("10кг")
+            if end < n && chars[end].is_alphanumeric() {
+                i = j.max(i + 1);
+                continue;
+            }
+            let plus = chars[start] == '+';
+            let separated = groups.len() > 1;
+            let kind = classify(&digits, plus, separated, &groups, || self.passport_context(lower, start));
+            if let Some(kind) = kind {
+                spans.push((kind, start, end));
+            }
+            i = j.max(i + 1);
+        }
+        spans
+    }
+
+    fn passport_context(&self, lower: &[char], start: usize) -> bool {
+        let from = start.saturating_sub(PASSPORT_CONTEXT_CHARS);
+        let context: String = lower[from..start].iter().collect();
+        self.passport_ac.is_match(&context)
+    }
+
+    /// Маркер улицы + до MAX_STREET_WORDS слов названия + номер дома и части адреса
+    fn addresses(&self, chars: &[char], lower: &[char]) -> Vec<Span> {
+        let text: String = lower.iter().collect();
+        let byte_to_char: Vec<usize> = {
+            let mut map = vec![0; text.len() + 1];
+            for (ci, (bi, c)) in text.char_indices().enumerate() {
+                map[bi..bi + c.len_utf8()].fill(ci);
+            }
+            map[text.len()] = lower.len();
+            map
+        };
+        let mut spans = Vec::new();
+        for m in self.street_ac.find_iter(&text) {
+            let start = byte_to_char[m.start()];
+            let marker_end = byte_to_char[m.end()];
+            if start > 0 && chars[start - 1].is_alphanumeric() {
+                continue;
+            }
+            if let Some(end) = address_end(chars, marker_end) {
+                spans.push(("address", start, end));
+            }
+        }
+        spans
+    }
+}
+
+fn classify(
+    digits: &str,
+    plus: bool,
+    separated: bool,
+    groups: &[usize],
+    passport_context: impl Fn() -> bool,
+) -> Option<&'static str> {
+    let len = digits.len();
+    let groups: Vec<usize> = groups.iter().copied().filter(|&g| g > 0).collect();
+    if plus {
+        return (10..=15).contains(&len).then_some("phone");
+    }
+    if len == 11 && (digits.starts_with('7') || digits.starts_with('8')) {
+        return Some("phone");
+    }
+    if (13..=19).contains(&len) && luhn(digits) {
+        return Some("card");
+    }
+    if len == 10 {
+        if groups == [4, 6] || groups == [2, 2, 6] {
+            return Some("passport");
+        }
+        if !separated {
+            if passport_context() {
+                return Some("passport");
+            }
+            return inn_valid(digits).then_some("inn");
+        }
+        return Some("phone");
+    }
+    if len == 12 && !separated && inn_valid(digits) {
+        return Some("inn");
+    }
+    None
+}
+
+fn luhn(digits: &str) -> bool {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn inn_valid(digits: &str) -> bool {
+    let d: Vec<u32> = digits.chars().filter_map(|c| c.to_digit(10)).collect();
+    let check = |coefs: &[u32]| coefs.iter().zip(&d).map(|(k, v)| k * v).sum::<u32>() % 11 % 10;
+    match d.len() {
+        10 => check(&[2, 4, 10, 3, 5, 9, 4, 6, 8]) == d[9],
+        12 => check(&[7, 2, 4, 10, 3, 5, 9, 4, 6, 8]) == d[10] && check(&[3, 7, 2, 4, 10, 3, 5, 9, 4, 6, 8]) == d[11],
+        _ => false,
+    }
+}
+
+fn emails(chars: &[char]) -> Vec<Span> {
+    let local = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-');
+    let domain = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-');
+    let mut spans = Vec::new();
+    for (at, _) in chars.iter().enumerate().filter(|(_, &c)| c == '@') {
+        let mut start = at;
+        while start > 0 && local(chars[start - 1]) {
+            start -= 1;
+        }
+        let mut end = at + 1;
+        while end < chars.len() && domain(chars[end]) {
+            end += 1;
+        }
+        while end > at + 1 && matches!(chars[end - 1], '.' | '-') {
+            end -= 1;
+        }
+        let host: String = chars[at + 1..end].iter().collect();
+        let tld_ok = host
+            .rsplit_once('.')
+            .is_some_and(|(name, tld)| !name.is_empty() && tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic()));
+        if start < at && tld_ok {
+            spans.push(("email", start, end));
+        }
+    }
+    spans
+}
+
+/// Конец адреса после маркера, если за названием улицы есть номер дома
+fn address_end(chars: &[char], from: usize) -> Option<usize> {
+    let n = chars.len();
+    let mut pos = from;
+    let mut name_words = 0;
+    let mut end = None;
+    // Номер после первого (дома) допустим только за частью адреса: "кв. 12"
+    let mut after_part = false;
+    loop {
+        // Следующий токен: пропускаем пробелы и запятые
+        let mut s = pos;
+        while s < n && (chars[s].is_whitespace() || chars[s] == ',') {
+            if chars[s] == '\n' {
+                return end;
+            }
+            s += 1;
+        }
+        let mut e = s;
+        while e < n && !chars[e].is_whitespace() && chars[e] != ',' {
+            e += 1;
+        }
+        if s == e {
+            return end;
+        }
+        let token: String = chars[s..e].iter().collect::<String>().to_lowercase();
+        let bare = token.trim_end_matches(['.', ';', ':', '!', '?']);
+        let is_number = bare.chars().next().is_some_and(|c| c.is_ascii_digit())
+            && bare.chars().all(|c| c.is_alphanumeric() || c == '/' || c == '-');
+        // "д.5" / "кв.12" — часть и номер слитно
+        let part_with_number = ADDRESS_PARTS.iter().any(|p| {
+            p.ends_with('.') && bare.starts_with(p) && bare[p.len()..].chars().next().is_some_and(|c| c.is_ascii_digit())
+        });
+
+        if part_with_number || (is_number && (end.is_none() || after_part)) {
+            end = Some(s + bare.chars().count());
+            after_part = false;
+            // Пунктуация сразу за номером — конец предложения
+            if is_number && token.len() != bare.len() {
+                return end;
+            }
+        } else if ADDRESS_PARTS.contains(&token.as_str()) || ADDRESS_PARTS.contains(&bare) {
+            if end.is_none() && name_words == 0 {
+                return None;
+            }
+            after_part = true;
+        } else if end.is_none() && !is_number && name_words < MAX_STREET_WORDS {
+            name_words += 1;
+        } else {
+            return end;
+        }
+        pos = e;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrubber() -> PiiScrubber {
+        PiiScrubber::new(None).unwrap()
+    }
+
+    fn kinds(text: &str) -> Vec<(String, String)> {
+        scrubber().scan(text).into_iter().map(|m| (m.kind, m.text)).collect()
+    }
+
+    #[test]
+    fn test_email_and_phone() {
+        let found = kinds("Пиши на ivan.petrov+ai@mail.example.ru или звони +7 (916) 123-45-67, либо 8 800 555 35 35.");
+        assert_eq!(
+            found,
+            vec![
+                ("email".to_string(), "ivan.petrov+ai@mail.example.ru".to_string()),
+                ("phone".to_string(), "+7 (916) 123-45-67".to_string()),
+                ("phone".to_string(), "8 800 555 35 35".to_string()),
+            ]
+        );
+        assert!(kinds("встреча в 2024 году, 15 марта, комната 404").is_empty());
+        assert!(kinds("user@localhost").is_empty());
+    }
+
+    #[test]
+    fn test_card_inn_passport() {
+        let found = kinds("Карта 4111 1111 1111 1111, ИНН 7707083893, паспорт 4508123456, серия 45 08 654321");
+        let found: Vec<&str> = found.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(found, vec!["card", "inn", "passport", "passport"]);
+        // Неверная контрольная сумма — не карта и не ИНН
+        assert!(kinds("номер заказа 4111 1111 1111 1112 и 7707083890").is_empty());
+        assert!(inn_valid("500100732259"));
+    }
+
+    #[test]
+    fn test_address() {
+        let found = kinds("Живу на ул. Ленина, д. 5, кв. 12. Приходи в гости");
+        assert_eq!(found, vec![("address".to_string(), "ул. Ленина, д. 5, кв. 12".to_string())]);
+        let found = kinds("Офис: Невский проспект 28, 3 этаж");
+        assert_eq!(found[0].1, "проспект 28");
+        assert!(kinds("Улица была пустой и тихой").is_empty());
+    }
+
+    #[test]
+    fn test_mask_and_kind_filter() {
+        let s = scrubber();
+        assert_eq!(
+            s.mask("Почта: a@b.io, тел. +79161234567", None),
+            "Почта: [EMAIL], тел. [PHONE]"
+        );
+        assert_eq!(s.mask("a@b.io", Some("***")), "***");
+        let emails_only = PiiScrubber::new(Some(vec!["email".into()])).unwrap();
+        assert_eq!(emails_only.mask("a@b.io +79161234567", None), "[EMAIL] +79161234567");
+        assert!(PiiScrubber::new(Some(vec!["ssn".into()])).is_err());
+    }
+}
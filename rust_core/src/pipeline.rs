@@ -0,0 +1,231 @@
+//! Pipeline — обработка сообщения пользователя за один вызов
+//!
+//! - EmotionAnalyzer → ThreadTracker (связанность и маршрутизация по нитям) →
+//!   MemoryEngine (рабочая память и поиск эпизодов) → ContextCompressor
+//!   (сжатая рабочая память в бюджет токенов)
+//! - Весь проход — с отпущенным GIL: один переход Python → Rust вместо шести
+//! - Компоненты — те же объекты, что у Python-кода: состояние общее
+//! - Результат — PipelineResult со всеми промежуточными данными
+
+use pyo3::prelude::*;
+use std::time::Instant;
+
+use crate::config::{self, CoreConfig};
+use crate::context_compressor::ContextCompressor;
+use crate::emotion_analyzer::EmotionAnalyzer;
+use crate::memory_engine::MemoryEngine;
+use crate::metrics;
+use crate::thread_tracker::{ThreadTracker, ASSISTANT_PREVIEW_CHARS, CONTEXT_EXCHANGES, RELATED_THRESHOLD, USER_PREVIEW_CHARS};
+
+#[pyclass(frozen, get_all)]
+pub struct PipelineResult {
+    emotion: String,
+    emotion_confidence: f64,
+    emotion_matches: Vec<String>,
+    /// Связанность с активной нитью до маршрутизации сообщения
+    relatedness: f64,
+    is_related: bool,
+    thread_id: Option<u64>,
+    topic: Option<String>,
+    entities: Vec<String>,
+    /// Контекст активной нити (ThreadTracker.get_context)
+    thread_context: Option<String>,
+    /// [(timestamp, preview, score)] — как MemoryEngine.get_relevant_context
+    memories: Vec<(String, String, i32)>,
+    /// Сжатая рабочая память строками "role: content"
+    context: String,
+    context_tokens: usize,
+    elapsed_ms: f64,
+}
+
+#[pymethods]
+impl PipelineResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "PipelineResult(emotion={:?}, topic={:?}, memories={}, context_tokens={}, elapsed_ms={:.2})",
+            self.emotion,
+            self.topic,
+            self.memories.len(),
+            self.context_tokens,
+            self.elapsed_ms
+        )
+    }
+}
+
+/// Параметры прохода
+#[derive(Clone, Copy)]
+struct Settings {
+    memory_items: usize,
+    budget_tokens: usize,
+    keep_last: usize,
+    add_to_working: bool,
+}
+
+#[pyclass(frozen)]
+pub struct Pipeline {
+    analyzer: Py<EmotionAnalyzer>,
+    tracker: Py<ThreadTracker>,
+    memory: Py<MemoryEngine>,
+    compressor: Py<ContextCompressor>,
+    settings: Settings,
+}
+
+#[pymethods]
+impl Pipeline {
+    /// memory_items — сколько эпизодов искать; budget_tokens / keep_last —
+    /// сжатие рабочей памяти (см. ContextCompressor.compress_working_memory);
+    /// add_to_working — класть реплики в рабочую память MemoryEngine;
+    /// незаданные параметры берутся из раздела pipeline config
+    #[new]
+    #[pyo3(signature = (
+        analyzer, tracker, memory, compressor, memory_items=None, budget_tokens=None, keep_last=None,
+        add_to_working=None, *, config=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        analyzer: Py<EmotionAnalyzer>,
+        tracker: Py<ThreadTracker>,
+        memory: Py<MemoryEngine>,
+        compressor: Py<ContextCompressor>,
+        memory_items: Option<usize>,
+        budget_tokens: Option<usize>,
+        keep_last: Option<usize>,
+        add_to_working: Option<bool>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> Self {
+        let defaults = config::settings(config).pipeline;
+        Self {
+            analyzer,
+            tracker,
+            memory,
+            compressor,
+            settings: Settings {
+                memory_items: memory_items.unwrap_or(defaults.memory_items),
+                budget_tokens: budget_tokens.unwrap_or(defaults.budget_tokens),
+                keep_last: keep_last.unwrap_or(defaults.keep_last),
+                add_to_working: add_to_working.unwrap_or(defaults.add_to_working),
+            },
+        }
+    }
+
+    /// Обрабатывает сообщение пользователя. Без response — анализ до ответа:
+    /// нить только оценивается (relatedness), в рабочую память идёт реплика
+    /// пользователя. С response — обмен целиком: маршрутизация по нитям
+    /// (ThreadTracker.update) и обе реплики в рабочую память.
+    #[pyo3(signature = (user_input, response=None))]
+    fn process(&self, py: Python<'_>, user_input: &str, response: Option<&str>) -> PipelineResult {
+        let analyzer = self.analyzer.get();
+        let tracker = self.tracker.get();
+        let memory = self.memory.get();
+        let compressor = self.compressor.get();
+        let settings = self.settings;
+        py.allow_threads(|| run(analyzer, tracker, memory, compressor, settings, user_input, response))
+    }
+}
+
+fn run(
+    analyzer: &EmotionAnalyzer,
+    tracker: &ThreadTracker,
+    memory: &MemoryEngine,
+    compressor: &ContextCompressor,
+    settings: Settings,
+    user_input: &str,
+    response: Option<&str>,
+) -> PipelineResult {
+    let started = Instant::now();
+    let (emotion, emotion_confidence, emotion_matches) = analyzer.analyze_detailed(user_input);
+
+    let relatedness = tracker.relatedness(user_input);
+    if let Some(response) = response {
+        tracker.update(user_input, response);
+    }
+
+    if settings.add_to_working {
+        memory.add_to_working("user", user_input);
+        if let Some(response) = response {
+            memory.add_to_working("assistant", response);
+        }
+    }
+    let memories = memory.get_relevant_context(user_input, settings.memory_items);
+    let context = compressor.working_context(memory, settings.budget_tokens, settings.keep_last);
+
+    PipelineResult {
+        emotion,
+        emotion_confidence,
+        emotion_matches,
+        relatedness,
+        is_related: relatedness >= RELATED_THRESHOLD,
+        thread_id: tracker.get_current_thread_id(),
+        topic: tracker.get_current_topic(),
+        entities: tracker.get_entities(),
+        thread_context: tracker.get_context(CONTEXT_EXCHANGES, USER_PREVIEW_CHARS, ASSISTANT_PREVIEW_CHARS),
+        memories,
+        context_tokens: compressor.estimate_tokens(&context),
+        context,
+        elapsed_ms: {
+            let elapsed = started.elapsed().as_secs_f64();
+            metrics::observe("pipeline_seconds", elapsed);
+            log::debug!("Сообщение обработано за {:.1} мс", elapsed * 1000.0);
+            elapsed * 1000.0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thread_tracker::{MAX_ARCHIVED, MAX_THREAD_MESSAGES};
+
+    struct Parts {
+        analyzer: EmotionAnalyzer,
+        tracker: ThreadTracker,
+        memory: MemoryEngine,
+        compressor: ContextCompressor,
+        dir: std::path::PathBuf,
+    }
+
+    fn parts(name: &str) -> Parts {
+        let dir = std::env::temp_dir().join(format!("kristina_pipeline_{}_{}", name, std::process::id()));
+        Parts {
+            analyzer: EmotionAnalyzer::new(true),
+            tracker: ThreadTracker::new(600, 5, None, MAX_THREAD_MESSAGES, MAX_ARCHIVED, None),
+            memory: MemoryEngine::new(dir.to_str().unwrap(), 10, 100).unwrap(),
+            compressor: ContextCompressor::new(0.3, None, 200, 6, "…", 120, true, None).unwrap(),
+            dir,
+        }
+    }
+
+    const SETTINGS: Settings = Settings { memory_items: 3, budget_tokens: 500, keep_last: 4, add_to_working: true };
+
+    #[test]
+    fn test_exchange_updates_all_components() {
+        let p = parts("exchange");
+        p.memory.add_episode("Обсуждали переезд в Казань", "Да, в августе", "neutral", 2);
+
+        let result = run(&p.analyzer, &p.tracker, &p.memory, &p.compressor, SETTINGS, "Спасибо, отлично, переезд удался", Some("Всё по плану"));
+        assert_eq!(result.emotion, "positive");
+        assert_eq!(result.relatedness, 0.0);
+        assert!(result.thread_id.is_some());
+        assert_eq!(result.memories.len(), 1);
+        assert!(result.context.contains("user: Спасибо, отлично, переезд удался"));
+        assert!(result.context.contains("assistant: Всё по плану"));
+        assert!(result.context_tokens > 0);
+        assert!(result.thread_context.is_some());
+        std::fs::remove_dir_all(&p.dir).ok();
+    }
+
+    #[test]
+    fn test_without_response_only_reads_threads() {
+        let p = parts("read");
+        run(&p.analyzer, &p.tracker, &p.memory, &p.compressor, SETTINGS, "Давай про переезд в Казань", Some("Давай"));
+        let thread = p.tracker.get_current_thread_id();
+
+        let result = run(&p.analyzer, &p.tracker, &p.memory, &p.compressor, SETTINGS, "Помнишь про переезд?", None);
+        assert!(result.is_related);
+        assert_eq!(result.thread_id, thread);
+        // Нить не получила новое сообщение, рабочая память — получила
+        assert_eq!(p.tracker.list_threads()[0].2, 1);
+        assert_eq!(p.memory.working_messages().len(), 3);
+        std::fs::remove_dir_all(&p.dir).ok();
+    }
+}
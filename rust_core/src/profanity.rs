@@ -0,0 +1,311 @@
+//! ProfanityFilter — обнаружение и цензура нецензурной лексики (RU + EN)
+//!
+//! Нормализация слова перед проверкой:
+//! - нижний регистр, ё→е, точки/дефисы/подчёркивания внутри слова убираются ("б.л.я")
+//! - в словах с кириллицей латинские двойники и цифры → кириллица ("xуй", "6лять", "п1зда")
+//! - в латинских словах leet → буквы ("sh1t", "f@ck"); повторы букв схлопываются ("бляяяя")
+//!
+//! Совпадение: точное слово, корень в начале слова (в т.ч. после приставки:
+//! "заебал", "распиздяй"), либо маска со звёздочками ("б**ть") по списку форм.
+//! Транслит ("blyat", "pizdec") — отдельным списком латинских корней.
+
+use pyo3::prelude::*;
+use parking_lot::RwLock;
+use std::collections::HashSet;
+
+use crate::config::{self, CoreConfig};
+
+/// Корни: совпадение в начале слова или после приставки
+const ROOTS: &[&str] = &[
+    "хуй", "хуе", "хуя", "хуи", "хер", "пизд", "бля", "ебал", "ебат", "ебан", "ебну", "ебло", "ебуч", "ебл",
+    "уеб", "мудак", "мудил", "пидор", "пидар", "гандон", "залуп", "долбоеб", "шлюх", "говн", "дерьм",
+    "fuck", "shit", "bitch", "cunt", "asshole", "motherfuck",
+    "blya", "blyat", "blyad", "pizd", "huy", "xuy", "yebat", "ebat", "eban", "mudak", "pidor", "pidar",
+];
+/// Только целые слова (как корни дают ложные срабатывания: "сукно", "dickens")
+const WORDS: &[&str] = &[
+    "сука", "суки", "суку", "сукой", "сучка", "сучара", "мразь", "мрази", "манда", "ебу", "ебет",
+    "dick", "dicks", "cock", "bastard", "whore", "slut", "wanker", "twat", "suka", "hui", "nahui",
+];
+/// Формы для масок со звёздочками
+const MASK_FORMS: &[&str] = &[
+    "бля", "блять", "блядь", "сука", "хуй", "хуйня", "нахуй", "похуй", "пизда", "пиздец", "ебать", "ебал",
+    "заебал", "ебаный", "мудак", "fuck", "fucking", "shit", "bitch", "cunt", "asshole",
+];
+/// Приставки, после которых ищется корень
+const PREFIXES: &[&str] = &[
+    "", "за", "на", "по", "от", "вы", "раз", "рас", "при", "до", "у", "об", "съ", "въ", "под", "пере", "недо",
+    "про", "о", "mother", "na", "za", "po",
+];
+/// Слова, которые похожи на мат, но им не являются
+const EXCEPTIONS: &[&str] = &["херсон", "херес", "херувим", "херувимы"];
+
+const MASK_WILDCARDS: &[char] = &['*', '#', '%'];
+
+fn cyrillic_lookalike(c: char) -> char {
+    match c {
+        'a' | '@' => 'а',
+        'b' | '6' => 'б',
+        'c' => 'с',
+        'e' | '3' => 'е',
+        'h' => 'н',
+        'k' => 'к',
+        'm' => 'м',
+        'o' | '0' => 'о',
+        'p' => 'р',
+        't' => 'т',
+        'x' => 'х',
+        'y' | 'u' => 'у',
+        '1' | 'i' => 'и',
+        '4' => 'ч',
+        'z' => 'з',
+        'ё' => 'е',
+        _ => c,
+    }
+}
+
+fn latin_leet(c: char) -> char {
+    match c {
+        '@' | '4' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' => 't',
+        _ => c,
+    }
+}
+
+fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{0400}'..='\u{04FF}')
+}
+
+/// Нормализованная форма слова (с масками "*", если они были)
+fn normalize(word: &str) -> String {
+    let lower: Vec<char> = word
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !matches!(c, '.' | '-' | '_' | '\''))
+        .collect();
+    let cyrillic = lower.iter().any(|&c| is_cyrillic(c));
+    lower
+        .into_iter()
+        .map(|c| if cyrillic { cyrillic_lookalike(c) } else { latin_leet(c) })
+        .collect()
+}
+
+fn collapse_repeats(word: &str) -> String {
+    let mut out = String::with_capacity(word.len());
+    let mut prev = None;
+    for c in word.chars() {
+        if prev != Some(c) {
+            out.push(c);
+        }
+        prev = Some(c);
+    }
+    out
+}
+
+fn mask_matches(masked: &str, form: &str) -> bool {
+    let masked: Vec<char> = masked.chars().collect();
+    let form: Vec<char> = form.chars().collect();
+    masked.len() == form.len()
+        && !MASK_WILDCARDS.contains(&masked[0])
+        && masked.iter().zip(&form).all(|(m, f)| MASK_WILDCARDS.contains(m) || m == f)
+}
+
+struct Lexicon {
+    roots: Vec<String>,
+    words: HashSet<String>,
+    exceptions: HashSet<String>,
+}
+
+impl Lexicon {
+    fn default_lexicon() -> Self {
+        Self {
+            roots: ROOTS.iter().map(|r| r.to_string()).collect(),
+            words: WORDS.iter().map(|w| w.to_string()).collect(),
+            exceptions: EXCEPTIONS.iter().map(|w| w.to_string()).collect(),
+        }
+    }
+
+    fn is_profane(&self, token: &str) -> bool {
+        let normalized = normalize(token);
+        if normalized.is_empty() || self.exceptions.contains(&normalized) {
+            return false;
+        }
+        if normalized.contains(MASK_WILDCARDS) {
+            return MASK_FORMS.iter().any(|f| mask_matches(&normalized, f));
+        }
+        let collapsed = collapse_repeats(&normalized);
+        self.matches(&normalized) || self.matches(&collapsed)
+    }
+
+    fn matches(&self, word: &str) -> bool {
+        self.words.contains(word)
+            || PREFIXES.iter().any(|p| {
+                word.strip_prefix(p)
+                    .is_some_and(|rest| self.roots.iter().any(|r| rest.starts_with(r.as_str())))
+            })
+    }
+}
+
+/// Слова текста: (начало, конец) в символах без краевой пунктуации
+fn word_spans(chars: &[char]) -> Vec<(usize, usize)> {
+    let keep = |c: char| c.is_alphanumeric() || MASK_WILDCARDS.contains(&c) || matches!(c, '@' | '$' | '!');
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let mut end = i;
+        while end < chars.len() && !chars[end].is_whitespace() {
+            end += 1;
+        }
+        let (mut s, mut e) = (i, end);
+        while s < e && !keep(chars[s]) {
+            s += 1;
+        }
+        // "!" в конце — пунктуация, а не leet
+        while e > s && (!keep(chars[e - 1]) || chars[e - 1] == '!') {
+            e -= 1;
+        }
+        if chars[s..e].iter().any(|c| c.is_alphabetic()) {
+            spans.push((s, e));
+        }
+        i = end;
+    }
+    spans
+}
+
+#[pyclass(frozen)]
+pub struct ProfanityFilter {
+    lexicon: RwLock<Lexicon>,
+}
+
+impl ProfanityFilter {
+    pub(crate) fn new(extra_words: Option<Vec<String>>, exceptions: Option<Vec<String>>) -> Self {
+        let filter = Self { lexicon: RwLock::new(Lexicon::default_lexicon()) };
+        if let Some(words) = extra_words {
+            filter.add_words(words, false);
+        }
+        if let Some(words) = exceptions {
+            filter.add_exceptions(words);
+        }
+        filter
+    }
+}
+
+#[pymethods]
+impl ProfanityFilter {
+    /// extra_words — дополнительные корни; exceptions — слова, которые не считать матом;
+    /// lexicons.profanity_words / profanity_exceptions config добавляются к ним
+    #[new]
+    #[pyo3(signature = (extra_words=None, exceptions=None, *, config=None))]
+    fn py_new(
+        extra_words: Option<Vec<String>>,
+        exceptions: Option<Vec<String>>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> Self {
+        let config = config::settings(config);
+        let lexicons = &config.lexicons;
+        let extra: Vec<String> = extra_words.into_iter().flatten().chain(lexicons.profanity_words.iter().cloned()).collect();
+        let exceptions: Vec<String> =
+            exceptions.into_iter().flatten().chain(lexicons.profanity_exceptions.iter().cloned()).collect();
+        Self::new(Some(extra), Some(exceptions))
+    }
+
+    /// Добавить слова: exact=True — только целые слова, иначе корни
+    #[pyo3(signature = (words, exact=false))]
+    fn add_words(&self, words: Vec<String>, exact: bool) {
+        let mut lexicon = self.lexicon.write();
+        for word in words.iter().map(|w| normalize(w.trim())).filter(|w| !w.is_empty()) {
+            if exact {
+                lexicon.words.insert(word);
+            } else if !lexicon.roots.contains(&word) {
+                lexicon.roots.push(word);
+            }
+        }
+    }
+
+    fn add_exceptions(&self, words: Vec<String>) {
+        let mut lexicon = self.lexicon.write();
+        lexicon.exceptions.extend(words.iter().map(|w| normalize(w.trim())).filter(|w| !w.is_empty()));
+    }
+
+    fn contains_profanity(&self, text: &str) -> bool {
+        !self.find(text).is_empty()
+    }
+
+    /// Найденные слова → [(word, start, end)], смещения в символах
+    fn find(&self, text: &str) -> Vec<(String, usize, usize)> {
+        let chars: Vec<char> = text.chars().collect();
+        let lexicon = self.lexicon.read();
+        word_spans(&chars)
+            .into_iter()
+            .filter_map(|(s, e)| {
+                let word: String = chars[s..e].iter().collect();
+                lexicon.is_profane(&word).then_some((word, s, e))
+            })
+            .collect()
+    }
+
+    /// Заменяет найденные слова символом mask_char; keep_first — оставить первую букву
+    #[pyo3(signature = (text, mask_char='*', keep_first=false))]
+    fn censor(&self, text: &str, mask_char: char, keep_first: bool) -> String {
+        let mut chars: Vec<char> = text.chars().collect();
+        for (_, s, e) in self.find(text) {
+            let from = if keep_first { s + 1 } else { s };
+            chars[from..e].iter_mut().for_each(|c| *c = mask_char);
+        }
+        chars.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter() -> ProfanityFilter {
+        ProfanityFilter::new(None, None)
+    }
+
+    fn found(text: &str) -> Vec<String> {
+        filter().find(text).into_iter().map(|(w, _, _)| w).collect()
+    }
+
+    #[test]
+    fn test_detects_forms_and_prefixes() {
+        assert_eq!(found("Да ну, блять, опять всё сломалось"), vec!["блять"]);
+        assert_eq!(found("Он меня заебал своим распиздяйством"), vec!["заебал", "распиздяйством"]);
+        assert_eq!(found("What the fuck, this is bullshit!"), vec!["fuck"]);
+        assert!(found("Оскорблять нельзя, себя уважай, купи хлеба на сто рублей").is_empty());
+        assert!(found("Суккуб из сукна, Dickens, Херсон").is_empty());
+    }
+
+    #[test]
+    fn test_obfuscations_and_translit() {
+        assert_eq!(found("б**ть, опять"), vec!["б**ть"]);
+        assert_eq!(found("xуй знает, 6лять"), vec!["xуй", "6лять"]);
+        assert_eq!(found("б.л.я.т.ь и бляяяяя"), vec!["б.л.я.т.ь", "бляяяяя"]);
+        assert_eq!(found("ну blyat, sh1t happens"), vec!["blyat", "sh1t"]);
+        // Маска без первой буквы слишком неоднозначна
+        assert!(found("***** и ок").is_empty());
+    }
+
+    #[test]
+    fn test_censor_and_custom_lists() {
+        let f = filter();
+        assert_eq!(f.censor("Ну ты и сука!", '*', false), "Ну ты и ****!");
+        assert_eq!(f.censor("Fucking bug", '#', true), "F###### bug");
+
+        let custom = ProfanityFilter::new(Some(vec!["дурак".into()]), Some(vec!["блямба".into()]));
+        assert!(custom.contains_profanity("сам дураками"));
+        assert!(!custom.contains_profanity("на лбу блямба"));
+        custom.add_words(vec!["редиска".into()], true);
+        assert!(custom.contains_profanity("ты редиска"));
+        assert!(!custom.contains_profanity("редиски"));
+    }
+}
@@ -0,0 +1,511 @@
+//! PromptTemplate — сборка промпта из шаблона
+//!
+//! - Подстановка: {{name}}, вложенные поля и индексы: {{user.name}}, {{this.0}}
+//! - Условия: {{#if name}}...{{else}}...{{/if}} (пустые строки/списки, False, None — ложь)
+//! - Циклы: {{#each history}}{{role}}: {{content}}{{/each}}; внутри — {{this}},
+//!   {{@index}} и поля элемента (словари и кортежи из MemoryEngine)
+//! - Бюджет слота в токенах: {{memory:200}} обрезается по границе слова с "…";
+//!   {{#each facts:300}} оставляет первые элементы, {{#each history:500 last}} —
+//!   последние, которые помещаются целиком
+//! - Токены оцениваются тем же профилем письменностей, что в ContextCompressor
+//! - Строка, где стоит только блочный тег, из вывода убирается целиком
+//! - Синтаксическая ошибка шаблона → ParseError
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyBool, PyDict, PyList, PyString, PyTuple};
+use std::collections::HashMap;
+
+use crate::context_compressor::{word_spans, TokenProfile};
+use crate::errors::ParseError;
+
+const ELLIPSIS: &str = "…";
+
+/// Значение слота (из Python: str, bool, None, list/tuple, dict; прочее — str())
+#[derive(Clone, Debug)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Str(String),
+    List(Vec<Value>),
+    Map(HashMap<String, Value>),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Self::Null => false,
+            Self::Bool(b) => *b,
+            Self::Str(s) => !s.is_empty(),
+            Self::List(items) => !items.is_empty(),
+            Self::Map(map) => !map.is_empty(),
+        }
+    }
+
+    fn render(&self) -> String {
+        match self {
+            Self::Null | Self::Map(_) => String::new(),
+            Self::Bool(b) => if *b { "true" } else { "false" }.to_string(),
+            Self::Str(s) => s.clone(),
+            Self::List(items) => items.iter().map(Value::render).collect::<Vec<_>>().join("\n"),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Map(map) => map.get(key),
+            Self::List(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        }
+    }
+}
+
+fn to_value(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if obj.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        Ok(Value::Bool(b.is_true()))
+    } else if let Ok(s) = obj.downcast::<PyString>() {
+        Ok(Value::Str(s.to_string()))
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        let mut map = HashMap::with_capacity(dict.len());
+        for (key, value) in dict.iter() {
+            map.insert(key.str()?.to_string(), to_value(&value)?);
+        }
+        Ok(Value::Map(map))
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        list.iter().map(|item| to_value(&item)).collect::<PyResult<_>>().map(Value::List)
+    } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        tuple.iter().map(|item| to_value(&item)).collect::<PyResult<_>>().map(Value::List)
+    } else {
+        Ok(Value::Str(obj.str()?.to_string()))
+    }
+}
+
+// ── Разбор шаблона ──
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var { path: Vec<String>, budget: Option<usize> },
+    If { path: Vec<String>, then: Vec<Node>, otherwise: Vec<Node> },
+    Each { path: Vec<String>, budget: Option<usize>, keep_last: bool, body: Vec<Node> },
+}
+
+enum Tag {
+    Var { path: Vec<String>, budget: Option<usize> },
+    If(Vec<String>),
+    Else,
+    EndIf,
+    Each { path: Vec<String>, budget: Option<usize>, keep_last: bool },
+    EndEach,
+}
+
+impl Tag {
+    fn is_block(&self) -> bool {
+        !matches!(self, Self::Var { .. })
+    }
+}
+
+fn parse_path(name: &str) -> Result<Vec<String>, String> {
+    let valid = |part: &str| {
+        !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '@')
+    };
+    let path: Vec<String> = name.split('.').map(str::to_string).collect();
+    if path.iter().all(|p| valid(p)) {
+        Ok(path)
+    } else {
+        Err(format!("Некорректное имя переменной: '{}'", name))
+    }
+}
+
+/// "name" или "name:200" → (path, budget)
+fn parse_slot(spec: &str) -> Result<(Vec<String>, Option<usize>), String> {
+    match spec.split_once(':') {
+        Some((name, budget)) => {
+            let budget = budget
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Некорректный бюджет токенов: '{}'", spec))?;
+            Ok((parse_path(name.trim())?, Some(budget)))
+        }
+        None => Ok((parse_path(spec.trim())?, None)),
+    }
+}
+
+fn parse_tag(inner: &str) -> Result<Tag, String> {
+    let inner = inner.trim();
+    if let Some(rest) = inner.strip_prefix("#if ") {
+        return Ok(Tag::If(parse_path(rest.trim())?));
+    }
+    if let Some(rest) = inner.strip_prefix("#each ") {
+        let rest = rest.trim();
+        let (spec, keep_last) = match rest.strip_suffix(" last") {
+            Some(spec) => (spec, true),
+            None => (rest, false),
+        };
+        let (path, budget) = parse_slot(spec)?;
+        return Ok(Tag::Each { path, budget, keep_last });
+    }
+    match inner {
+        "else" => Ok(Tag::Else),
+        "/if" => Ok(Tag::EndIf),
+        "/each" => Ok(Tag::EndEach),
+        _ if inner.starts_with(['#', '/']) => Err(format!("Неизвестный тег: '{{{{{}}}}}'", inner)),
+        _ => {
+            let (path, budget) = parse_slot(inner)?;
+            Ok(Tag::Var { path, budget })
+        }
+    }
+}
+
+/// Лексемы: текст и теги; строка только с блочным тегом вырезается целиком
+fn lex(template: &str) -> Result<Vec<Result<String, Tag>>, String> {
+    let blank = |s: &str| s.chars().all(|c| c == ' ' || c == '\t');
+    let mut items: Vec<Result<String, Tag>> = Vec::new();
+    let mut rest = template;
+    // Текущая строка вывода пока пустая (до тега только пробелы)
+    let mut line_clean = true;
+    while let Some(open) = rest.find("{{") {
+        let close = rest[open..]
+            .find("}}")
+            .ok_or_else(|| format!("Незакрытый тег на позиции {}", template.len() - rest.len() + open))?;
+        let mut text = rest[..open].to_string();
+        let tag = parse_tag(&rest[open + 2..open + close])?;
+        let mut after = &rest[open + close + 2..];
+
+        let line_start = text.rfind('\n').map_or(0, |p| p + 1);
+        let at_line_start = blank(&text[line_start..]) && (line_start > 0 || line_clean);
+        line_clean = match tag {
+            Tag::Var { .. } => false,
+            _ => at_line_start,
+        };
+        if tag.is_block() && at_line_start {
+            let trailing = after.len() - after.trim_start_matches([' ', '\t']).len();
+            let tail = &after[trailing..];
+            if tail.is_empty() || tail.starts_with('\n') || tail.starts_with("\r\n") {
+                text.truncate(line_start);
+                after = tail.strip_prefix("\r\n").or_else(|| tail.strip_prefix('\n')).unwrap_or(tail);
+            }
+        }
+        if !text.is_empty() {
+            items.push(Ok(text));
+        }
+        items.push(Err(tag));
+        rest = after;
+    }
+    if !rest.is_empty() {
+        items.push(Ok(rest.to_string()));
+    }
+    Ok(items)
+}
+
+/// Узлы до закрывающего тега; возвращает и сам тег, которым закончился уровень
+fn build(items: &mut std::vec::IntoIter<Result<String, Tag>>) -> Result<(Vec<Node>, Option<Tag>), String> {
+    let mut nodes = Vec::new();
+    while let Some(item) = items.next() {
+        let tag = match item {
+            Ok(text) => {
+                nodes.push(Node::Text(text));
+                continue;
+            }
+            Err(tag) => tag,
+        };
+        match tag {
+            Tag::Var { path, budget } => nodes.push(Node::Var { path, budget }),
+            Tag::If(path) => {
+                let (then, end) = build(items)?;
+                let otherwise = match end {
+                    Some(Tag::EndIf) => Vec::new(),
+                    Some(Tag::Else) => match build(items)? {
+                        (otherwise, Some(Tag::EndIf)) => otherwise,
+                        _ => return Err(format!("Блок {{{{#if {}}}}} не закрыт {{{{/if}}}}", path.join("."))),
+                    },
+                    _ => return Err(format!("Блок {{{{#if {}}}}} не закрыт {{{{/if}}}}", path.join("."))),
+                };
+                nodes.push(Node::If { path, then, otherwise });
+            }
+            Tag::Each { path, budget, keep_last } => match build(items)? {
+                (body, Some(Tag::EndEach)) => nodes.push(Node::Each { path, budget, keep_last, body }),
+                _ => return Err(format!("Блок {{{{#each {}}}}} не закрыт {{{{/each}}}}", path.join("."))),
+            },
+            end @ (Tag::Else | Tag::EndIf | Tag::EndEach) => return Ok((nodes, Some(end))),
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn parse(template: &str) -> Result<Vec<Node>, String> {
+    let mut items = lex(template)?.into_iter();
+    match build(&mut items)? {
+        (nodes, None) => Ok(nodes),
+        (_, Some(Tag::Else)) => Err("{{else}} вне блока {{#if}}".to_string()),
+        (_, Some(_)) => Err("Закрывающий тег без открывающего".to_string()),
+    }
+}
+
+fn collect_variables(nodes: &[Node], out: &mut Vec<String>) {
+    for node in nodes {
+        let path = match node {
+            Node::Text(_) => continue,
+            Node::Var { path, .. } | Node::If { path, .. } | Node::Each { path, .. } => path,
+        };
+        let name = path[0].as_str();
+        if name != "this" && !name.starts_with('@') && !out.iter().any(|v| v == name) {
+            out.push(name.to_string());
+        }
+        // Внутри циклов имена — поля элементов, а не переменные шаблона
+        if let Node::If { then, otherwise, .. } = node {
+            collect_variables(then, out);
+            collect_variables(otherwise, out);
+        }
+    }
+}
+
+// ── Рендеринг ──
+
+/// Область видимости: значения шаблона и элементы вложенных циклов
+struct Scope<'a> {
+    values: &'a HashMap<String, Value>,
+    /// (элемент, индекс) — от внешнего цикла к внутреннему
+    items: Vec<(&'a Value, usize)>,
+}
+
+impl<'a> Scope<'a> {
+    fn lookup(&self, path: &[String]) -> Option<&'a Value> {
+        let head = path[0].as_str();
+        let mut value = match head {
+            "this" => self.items.last()?.0,
+            _ => self
+                .items
+                .iter()
+                .rev()
+                .find_map(|(item, _)| item.get(head))
+                .or_else(|| self.values.get(head))?,
+        };
+        for key in &path[1..] {
+            value = value.get(key)?;
+        }
+        Some(value)
+    }
+}
+
+#[pyclass(frozen)]
+pub struct PromptTemplate {
+    nodes: Vec<Node>,
+    tokens: TokenProfile,
+    /// Ошибка на отсутствующую переменную (иначе — пустая строка)
+    strict: bool,
+}
+
+#[pymethods]
+impl PromptTemplate {
+    /// token_ratios — символов на токен по письменностям, как в ContextCompressor;
+    /// strict — ошибка при отсутствующей переменной
+    #[new]
+    #[pyo3(signature = (template, token_ratios=None, strict=false))]
+    pub(crate) fn new(template: &str, token_ratios: Option<HashMap<String, f64>>, strict: bool) -> PyResult<Self> {
+        let nodes = parse(template).map_err(ParseError::new_err)?;
+        let tokens = match token_ratios {
+            Some(ratios) => TokenProfile::with_overrides(ratios).map_err(PyValueError::new_err)?,
+            None => TokenProfile::default(),
+        };
+        Ok(Self { nodes, tokens, strict })
+    }
+
+    /// Значения — словарём и/или именованными аргументами (они важнее)
+    #[pyo3(signature = (values=None, **kwargs))]
+    fn render(&self, values: Option<&Bound<'_, PyDict>>, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+        let mut map = HashMap::new();
+        for dict in values.into_iter().chain(kwargs) {
+            for (key, value) in dict.iter() {
+                map.insert(key.str()?.to_string(), to_value(&value)?);
+            }
+        }
+        self.render_values(&map).map_err(PyValueError::new_err)
+    }
+
+    /// Имена переменных верхнего уровня в порядке появления
+    fn variables(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        collect_variables(&self.nodes, &mut out);
+        out
+    }
+
+    fn estimate_tokens(&self, text: &str) -> usize {
+        self.tokens.estimate(text)
+    }
+}
+
+impl PromptTemplate {
+    pub(crate) fn render_values(&self, values: &HashMap<String, Value>) -> Result<String, String> {
+        let mut scope = Scope { values, items: Vec::new() };
+        let mut out = String::new();
+        self.render_nodes(&self.nodes, &mut scope, &mut out)?;
+        Ok(out)
+    }
+
+    fn lookup<'a>(&self, scope: &Scope<'a>, path: &[String]) -> Result<Option<&'a Value>, String> {
+        if path[0] == "@index" {
+            return Ok(None);
+        }
+        match scope.lookup(path) {
+            None if self.strict => Err(format!("Переменная не задана: {}", path.join("."))),
+            found => Ok(found),
+        }
+    }
+
+    fn render_nodes<'a>(&self, nodes: &'a [Node], scope: &mut Scope<'a>, out: &mut String) -> Result<(), String> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Var { path, budget } => {
+                    let text = if path[0] == "@index" {
+                        scope.items.last().map(|(_, i)| i.to_string()).unwrap_or_default()
+                    } else {
+                        self.lookup(scope, path)?.map(Value::render).unwrap_or_default()
+                    };
+                    match budget {
+                        Some(budget) => out.push_str(&self.truncate(&text, *budget)),
+                        None => out.push_str(&text),
+                    }
+                }
+                Node::If { path, then, otherwise } => {
+                    let truthy = self.lookup(scope, path)?.is_some_and(Value::truthy);
+                    self.render_nodes(if truthy { then } else { otherwise }, scope, out)?;
+                }
+                Node::Each { path, budget, keep_last, body } => {
+                    let items: &[Value] = match self.lookup(scope, path)? {
+                        Some(Value::List(items)) => items,
+                        Some(Value::Null) | None => &[],
+                        Some(_) => return Err(format!("{{{{#each {}}}}}: ожидается список", path.join("."))),
+                    };
+                    let mut rendered = Vec::with_capacity(items.len());
+                    for (i, item) in items.iter().enumerate() {
+                        scope.items.push((item, i));
+                        let mut part = String::new();
+                        let result = self.render_nodes(body, scope, &mut part);
+                        scope.items.pop();
+                        result?;
+                        rendered.push(part);
+                    }
+                    match budget {
+                        Some(budget) => out.push_str(&self.fit_items(rendered, *budget, *keep_last)),
+                        None => rendered.iter().for_each(|part| out.push_str(part)),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Самое длинное начало текста по границе слова с "…", укладывающееся в бюджет
+    fn truncate(&self, text: &str, budget: usize) -> String {
+        if self.tokens.estimate(text) <= budget {
+            return text.to_string();
+        }
+        let ends: Vec<usize> = word_spans(text, 0).into_iter().map(|(_, e)| e).collect();
+        let fit = ends.partition_point(|&e| self.tokens.estimate(&format!("{}{}", &text[..e], ELLIPSIS)) <= budget);
+        match fit {
+            0 => String::new(),
+            n => format!("{}{}", text[..ends[n - 1]].trim_end_matches(|c: char| c.is_ascii_punctuation()), ELLIPSIS),
+        }
+    }
+
+    /// Целые элементы цикла в пределах бюджета: первые или последние (keep_last)
+    fn fit_items(&self, rendered: Vec<String>, budget: usize, keep_last: bool) -> String {
+        let mut used = 0;
+        let mut kept: Vec<&String> = Vec::new();
+        let ordered: Box<dyn Iterator<Item = &String>> =
+            if keep_last { Box::new(rendered.iter().rev()) } else { Box::new(rendered.iter()) };
+        for part in ordered {
+            let tokens = self.tokens.estimate(part);
+            if used + tokens > budget {
+                break;
+            }
+            used += tokens;
+            kept.push(part);
+        }
+        if keep_last {
+            kept.reverse();
+        }
+        kept.into_iter().map(String::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(text: &str) -> Value {
+        Value::Str(text.to_string())
+    }
+
+    fn render(template: &str, values: Vec<(&str, Value)>) -> String {
+        let values: HashMap<String, Value> = values.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+        PromptTemplate::new(template, None, false).unwrap().render_values(&values).unwrap()
+    }
+
+    #[test]
+    fn test_variables_and_conditions() {
+        let template = "Ты — {{name}}.\n{{#if mood}}\nНастроение: {{mood}}.\n{{else}}\nНастроение неизвестно.\n{{/if}}\nПользователь: {{user.name}}";
+        let user = Value::Map(HashMap::from([("name".to_string(), s("Аня"))]));
+        assert_eq!(
+            render(template, vec![("name", s("Кристина")), ("mood", s("радость")), ("user", user.clone())]),
+            "Ты — Кристина.\nНастроение: радость.\nПользователь: Аня"
+        );
+        assert_eq!(
+            render(template, vec![("name", s("Кристина")), ("mood", s("")), ("user", user)]),
+            "Ты — Кристина.\nНастроение неизвестно.\nПользователь: Аня"
+        );
+        let t = PromptTemplate::new(template, None, false).unwrap();
+        assert_eq!(t.variables(), vec!["name", "mood", "user"]);
+    }
+
+    #[test]
+    fn test_each_over_memory_items() {
+        let history = Value::List(vec![
+            Value::List(vec![s("user"), s("Привет")]),
+            Value::List(vec![s("assistant"), s("Здравствуй!")]),
+        ]);
+        let facts = Value::List(vec![
+            Value::Map(HashMap::from([("key".to_string(), s("город")), ("value".to_string(), s("Казань"))])),
+        ]);
+        let template = "{{#each facts}}\n- {{key}}: {{value}}\n{{/each}}\n{{#each history}}\n{{@index}}. {{this.0}}: {{this.1}}\n{{/each}}\n";
+        assert_eq!(
+            render(template, vec![("history", history), ("facts", facts)]),
+            "- город: Казань\n0. user: Привет\n1. assistant: Здравствуй!\n"
+        );
+    }
+
+    #[test]
+    fn test_token_budgets() {
+        let t = PromptTemplate::new("{{bio:8}}", None, false).unwrap();
+        let bio = "Любит горы, джаз и долгие прогулки по вечерней Казани";
+        let values = HashMap::from([("bio".to_string(), s(bio))]);
+        let out = t.render_values(&values).unwrap();
+        assert!(out.ends_with('…') && bio.starts_with(out.trim_end_matches('…')));
+        assert!(t.estimate_tokens(&out) <= 8);
+
+        let items = Value::List((1..=5).map(|i| s(&format!("сообщение {} ", i))).collect());
+        let last = PromptTemplate::new("{{#each log:14 last}}{{this}}{{/each}}", None, false).unwrap();
+        let first = PromptTemplate::new("{{#each log:14}}{{this}}{{/each}}", None, false).unwrap();
+        let values = HashMap::from([("log".to_string(), items)]);
+        assert_eq!(last.render_values(&values).unwrap(), "сообщение 4 сообщение 5 ");
+        assert_eq!(first.render_values(&values).unwrap(), "сообщение 1 сообщение 2 ");
+    }
+
+    #[test]
+    fn test_syntax_errors_and_strict() {
+        assert!(PromptTemplate::new("{{#if a}}x", None, false).is_err());
+        assert!(PromptTemplate::new("{{#each a}}x{{/if}}", None, false).is_err());
+        assert!(PromptTemplate::new("x{{/each}}", None, false).is_err());
+        assert!(PromptTemplate::new("{{name", None, false).is_err());
+        assert!(PromptTemplate::new("{{name:abc}}", None, false).is_err());
+        assert!(PromptTemplate::new("{{#for x}}{{/for}}", None, false).is_err());
+
+        let strict = PromptTemplate::new("Привет, {{name}}", None, true).unwrap();
+        assert!(strict.render_values(&HashMap::new()).is_err());
+        assert_eq!(render("Привет, {{name}}!", vec![]), "Привет, !");
+    }
+}
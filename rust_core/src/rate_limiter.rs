@@ -0,0 +1,312 @@
+//! RateLimiter — token bucket на пользователя
+//!
+//! - У каждого user_id своё ведро: capacity токенов, пополнение refill_per_sec
+//! - try_acquire(user_id, cost) — неблокирующая попытка; acquire(...) ждёт
+//!   с отпущенным GIL (до timeout), безопасно из любых потоков Python
+//! - Лимиты по умолчанию + индивидуальные через set_limit
+//! - DashMap: ведра разных пользователей не блокируют друг друга
+//! - Время монотонное (Instant): перевод часов не сбрасывает лимиты
+//! - cleanup() удаляет вёдра, простаивавшие дольше idle_ttl_secs
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::config::{self, CoreConfig};
+use crate::metrics;
+
+/// Шаг ожидания в acquire, пока не хватает токенов
+const MAX_WAIT_STEP: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Limit {
+    capacity: f64,
+    refill_per_sec: f64,
+}
+
+impl Limit {
+    fn new(capacity: f64, refill_per_sec: f64) -> PyResult<Self> {
+        if !(capacity.is_finite() && capacity > 0.0) {
+            return Err(PyValueError::new_err(format!("capacity должен быть > 0: {}", capacity)));
+        }
+        if !(refill_per_sec.is_finite() && refill_per_sec >= 0.0) {
+            return Err(PyValueError::new_err(format!("refill_per_sec должен быть >= 0: {}", refill_per_sec)));
+        }
+        Ok(Self { capacity, refill_per_sec })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    limit: Limit,
+    /// Индивидуальный лимит (не меняется вместе с лимитом по умолчанию)
+    custom: bool,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: Limit, custom: bool, now: Instant) -> Self {
+        Self { tokens: limit.capacity, limit, custom, updated: now }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.refill_per_sec).min(self.limit.capacity);
+        self.updated = now;
+    }
+
+    /// Секунд до того, как станет доступно cost токенов (inf — никогда)
+    fn wait_for(&self, cost: f64) -> f64 {
+        if self.tokens >= cost {
+            0.0
+        } else if cost > self.limit.capacity || self.limit.refill_per_sec == 0.0 {
+            f64::INFINITY
+        } else {
+            (cost - self.tokens) / self.limit.refill_per_sec
+        }
+    }
+}
+
+fn check_cost(cost: f64) -> PyResult<()> {
+    if cost.is_finite() && cost >= 0.0 {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err(format!("cost должен быть >= 0: {}", cost)))
+    }
+}
+
+#[pyclass(frozen)]
+pub struct RateLimiter {
+    default: RwLock<Limit>,
+    buckets: DashMap<String, Bucket>,
+    idle_ttl: Duration,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(capacity: f64, refill_per_sec: f64, idle_ttl_secs: f64) -> PyResult<Self> {
+        if !(idle_ttl_secs.is_finite() && idle_ttl_secs >= 0.0) {
+            return Err(PyValueError::new_err(format!("idle_ttl_secs должен быть >= 0: {}", idle_ttl_secs)));
+        }
+        Ok(Self {
+            default: RwLock::new(Limit::new(capacity, refill_per_sec)?),
+            buckets: DashMap::new(),
+            idle_ttl: Duration::from_secs_f64(idle_ttl_secs),
+        })
+    }
+}
+
+#[pymethods]
+impl RateLimiter {
+    /// capacity — размер ведра (всплеск); refill_per_sec — скорость пополнения;
+    /// незаданные параметры берутся из раздела rate_limit config
+    #[new]
+    #[pyo3(signature = (capacity=None, refill_per_sec=None, idle_ttl_secs=None, *, config=None))]
+    fn py_new(
+        capacity: Option<f64>,
+        refill_per_sec: Option<f64>,
+        idle_ttl_secs: Option<f64>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let limits = config::settings(config).rate_limit;
+        Self::new(
+            capacity.unwrap_or(limits.capacity),
+            refill_per_sec.unwrap_or(limits.refill_per_sec),
+            idle_ttl_secs.unwrap_or(limits.idle_ttl_secs),
+        )
+    }
+
+    /// Списывает cost токенов, если они есть; иначе False и ничего не списывается
+    #[pyo3(signature = (user_id, cost=1.0))]
+    fn try_acquire(&self, user_id: &str, cost: f64) -> PyResult<bool> {
+        check_cost(cost)?;
+        let acquired = self.acquire_at(user_id, cost, Instant::now()).is_ok();
+        if !acquired {
+            metrics::inc("rate_limit_rejections_total", 1);
+        }
+        Ok(acquired)
+    }
+
+    /// Ждёт токены с отпущенным GIL; timeout=None — без ограничения (но если
+    /// cost больше ёмкости ведра — сразу False)
+    #[pyo3(signature = (user_id, cost=1.0, timeout=None))]
+    fn acquire(&self, py: Python<'_>, user_id: &str, cost: f64, timeout: Option<f64>) -> PyResult<bool> {
+        check_cost(cost)?;
+        let deadline = timeout.map(|t| Instant::now() + Duration::from_secs_f64(t.max(0.0)));
+        let acquired = py.allow_threads(|| loop {
+            let now = Instant::now();
+            let wait = match self.acquire_at(user_id, cost, now) {
+                Ok(()) => return true,
+                Err(wait) if wait.is_infinite() => return false,
+                Err(wait) => Duration::from_secs_f64(wait),
+            };
+            if deadline.is_some_and(|d| now + wait > d) {
+                return false;
+            }
+            std::thread::sleep(wait.min(MAX_WAIT_STEP));
+        });
+        if !acquired {
+            metrics::inc("rate_limit_rejections_total", 1);
+        }
+        Ok(acquired)
+    }
+
+    /// Через сколько секунд будет доступно cost токенов (0 — сейчас, inf — никогда)
+    #[pyo3(signature = (user_id, cost=1.0))]
+    fn retry_after(&self, user_id: &str, cost: f64) -> PyResult<f64> {
+        check_cost(cost)?;
+        Ok(self.with_bucket(user_id, Instant::now(), |b| b.wait_for(cost)))
+    }
+
+    /// Доступные токены пользователя сейчас
+    fn available(&self, user_id: &str) -> f64 {
+        self.with_bucket(user_id, Instant::now(), |b| b.tokens)
+    }
+
+    /// Индивидуальный лимит; текущие токены обрезаются до новой ёмкости
+    fn set_limit(&self, user_id: &str, capacity: f64, refill_per_sec: f64) -> PyResult<()> {
+        let limit = Limit::new(capacity, refill_per_sec)?;
+        let now = Instant::now();
+        let mut bucket = self.buckets.entry(user_id.to_string()).or_insert_with(|| Bucket::new(limit, true, now));
+        bucket.refill(now);
+        bucket.limit = limit;
+        bucket.custom = true;
+        bucket.tokens = bucket.tokens.min(capacity);
+        Ok(())
+    }
+
+    /// Лимит по умолчанию — для новых и всех вёдер без индивидуального лимита
+    fn set_default_limit(&self, capacity: f64, refill_per_sec: f64) -> PyResult<()> {
+        let limit = Limit::new(capacity, refill_per_sec)?;
+        let mut default = self.default.write();
+        let now = Instant::now();
+        for mut bucket in self.buckets.iter_mut() {
+            if !bucket.custom {
+                bucket.refill(now);
+                bucket.limit = limit;
+                bucket.tokens = bucket.tokens.min(capacity);
+            }
+        }
+        *default = limit;
+        Ok(())
+    }
+
+    /// Полное ведро и лимит по умолчанию; True, если пользователь был
+    fn reset(&self, user_id: &str) -> bool {
+        self.buckets.remove(user_id).is_some()
+    }
+
+    /// Удаляет вёдра, не использовавшиеся дольше idle_ttl_secs и уже полные
+    /// (индивидуальные лимиты сохраняются); возвращает число удалённых
+    pub(crate) fn cleanup(&self) -> usize {
+        self.cleanup_at(Instant::now())
+    }
+
+    fn __len__(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+impl RateLimiter {
+    /// Ok — списано; Err(секунд до доступности)
+    fn acquire_at(&self, user_id: &str, cost: f64, now: Instant) -> Result<(), f64> {
+        let default = *self.default.read();
+        let mut bucket = self.buckets.entry(user_id.to_string()).or_insert_with(|| Bucket::new(default, false, now));
+        bucket.refill(now);
+        let wait = bucket.wait_for(cost);
+        if wait == 0.0 {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(wait)
+        }
+    }
+
+    /// Чтение состояния без создания ведра: неизвестный пользователь — полное ведро
+    fn with_bucket<T>(&self, user_id: &str, now: Instant, f: impl FnOnce(&Bucket) -> T) -> T {
+        match self.buckets.get_mut(user_id) {
+            Some(mut bucket) => {
+                bucket.refill(now);
+                f(&bucket)
+            }
+            None => f(&Bucket::new(*self.default.read(), false, now)),
+        }
+    }
+
+    fn cleanup_at(&self, now: Instant) -> usize {
+        let before = self.buckets.len();
+        self.buckets.retain(|_, bucket| {
+            let idle = now.saturating_duration_since(bucket.updated) >= self.idle_ttl;
+            bucket.refill(now);
+            bucket.custom || !(idle && bucket.tokens >= bucket.limit.capacity)
+        });
+        before - self.buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_and_refill() {
+        let limiter = RateLimiter::new(3.0, 2.0, 3600.0).unwrap();
+        let t0 = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.acquire_at("u1", 1.0, t0).is_ok());
+        }
+        let wait = limiter.acquire_at("u1", 1.0, t0).unwrap_err();
+        assert!((wait - 0.5).abs() < 1e-9);
+        // Другой пользователь — своё ведро
+        assert!(limiter.acquire_at("u2", 3.0, t0).is_ok());
+        // Через полсекунды пополнился один токен
+        assert!(limiter.acquire_at("u1", 1.0, t0 + Duration::from_millis(500)).is_ok());
+        assert!(limiter.acquire_at("u1", 1.0, t0 + Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn test_cost_and_limits() {
+        let limiter = RateLimiter::new(5.0, 1.0, 3600.0).unwrap();
+        assert!(limiter.try_acquire("u", -1.0).is_err());
+        assert!(!limiter.try_acquire("u", 6.0).unwrap());
+        assert_eq!(limiter.retry_after("u", 6.0).unwrap(), f64::INFINITY);
+        assert!(RateLimiter::new(0.0, 1.0, 10.0).is_err());
+
+        limiter.set_limit("vip", 100.0, 10.0).unwrap();
+        assert!(limiter.try_acquire("vip", 50.0).unwrap());
+        limiter.set_default_limit(2.0, 1.0).unwrap();
+        assert!(limiter.available("u") <= 2.0);
+        assert!(limiter.available("vip") >= 49.0);
+        assert!(limiter.reset("vip"));
+        assert_eq!(limiter.available("vip"), 2.0);
+    }
+
+    #[test]
+    fn test_cleanup_idle_buckets() {
+        let limiter = RateLimiter::new(2.0, 1.0, 60.0).unwrap();
+        let t0 = Instant::now();
+        limiter.acquire_at("idle", 1.0, t0).unwrap();
+        limiter.acquire_at("busy", 2.0, t0).unwrap();
+        limiter.set_limit("custom", 5.0, 1.0).unwrap();
+        assert_eq!(limiter.cleanup_at(t0 + Duration::from_secs(1)), 0);
+        // busy обновлялся недавно, idle простаивал больше ttl
+        limiter.acquire_at("busy", 0.0, t0 + Duration::from_secs(100)).unwrap();
+        assert_eq!(limiter.cleanup_at(t0 + Duration::from_secs(120)), 1);
+        assert_eq!(limiter.__len__(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_acquire_is_exact() {
+        // Без пополнения: из 8 × 100 попыток проходит ровно ёмкость ведра
+        let limiter = std::sync::Arc::new(RateLimiter::new(250.0, 1e-9, 3600.0).unwrap());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let limiter = std::sync::Arc::clone(&limiter);
+                std::thread::spawn(move || (0..100).filter(|_| limiter.try_acquire("shared", 1.0).unwrap()).count())
+            })
+            .collect();
+        let granted: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(granted, 250);
+    }
+}
@@ -0,0 +1,179 @@
+//! Сегментация текста на предложения (RU/EN)
+//!
+//! Правила:
+//! - Терминаторы: . ! ? … и перевод строки; серии ("?!", "...") — один разрыв
+//! - Точка — разрыв только перед пробелом/концом текста: "3.14", URL, e-mail,
+//!   "т.д" внутри слова не режутся
+//! - Сокращения ("т.е.", "см.", "e.g.", "Dr.") и инициалы ("А. С. Пушкин") — без разрыва;
+//!   "т.д.", "etc." и т.п. закрывают предложение, если дальше заглавная буква
+//! - Многоточие — разрыв, только если дальше заглавная буква
+//! - Внутри скобок и «кавычек» разрывов нет; закрывающие кавычки/скобки
+//!   после терминатора остаются в предложении
+
+use pyo3::prelude::*;
+
+/// Сокращения (в нижнем регистре, без финальной точки)
+const ABBREVIATIONS: &[&str] = &[
+    "т.д", "т.п", "т.е", "т.к", "т.н", "т.ч", "и.о", "др", "пр", "см", "ср", "им", "г", "гг",
+    "вв", "ул", "пер", "кв", "стр", "рис", "табл", "тыс", "млн", "млрд", "руб", "коп", "проф",
+    "акад", "доц", "e.g", "i.e", "etc", "vs", "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr",
+    "fig", "no", "approx", "cf", "inc", "ltd", "a.m", "p.m",
+];
+
+/// Сокращения, которыми может заканчиваться предложение
+const FINAL_ABBREVIATIONS: &[&str] = &["т.д", "т.п", "др", "etc", "inc", "ltd", "a.m", "p.m"];
+
+const OPENERS: &[char] = &['(', '[', '«', '“'];
+const CLOSERS: &[char] = &[')', ']', '»', '”'];
+/// Может стоять после терминатора и относится к предложению
+const TRAILERS: &[char] = &['"', '\'', ')', ']', '»', '”'];
+
+fn is_terminator(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…')
+}
+
+/// Разбивает текст на предложения (с завершающей пунктуацией)
+#[pyfunction]
+pub fn split_sentences(text: &str) -> Vec<String> {
+    sentence_spans(text).into_iter().map(|(s, _, _)| s.to_string()).collect()
+}
+
+/// Предложения с байтовыми смещениями: (sentence, start, end), sentence == text[start..end]
+pub(crate) fn sentence_spans(text: &str) -> Vec<(&str, usize, usize)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let n = chars.len();
+    let byte_at = |i: usize| chars.get(i).map_or(text.len(), |&(b, _)| b);
+    let next_visible = |i: usize| chars[i.min(n)..].iter().map(|&(_, c)| c).find(|c| !c.is_whitespace());
+
+    let mut spans = Vec::new();
+    let mut push = |from: usize, to: usize| {
+        let raw = &text[from..to];
+        let sentence = raw.trim();
+        if !sentence.is_empty() {
+            let s = from + (raw.len() - raw.trim_start().len());
+            spans.push((sentence, s, s + sentence.len()));
+        }
+    };
+
+    let mut start = 0;
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < n {
+        let (pos, ch) = chars[i];
+        if ch == '\n' {
+            push(start, pos);
+            start = pos + 1;
+            depth = 0;
+        } else if OPENERS.contains(&ch) {
+            depth += 1;
+        } else if CLOSERS.contains(&ch) {
+            depth = depth.saturating_sub(1);
+            // "(Так и есть.) Дальше" — разрыв после закрывающей скобки
+            let closes_sentence = depth == 0 && i > 0 && is_terminator(chars[i - 1].1);
+            let end = byte_at(i + 1);
+            if closes_sentence
+                && (i + 1 == n || chars[i + 1].1.is_whitespace())
+                && next_visible(i + 1).is_none_or(|c| c.is_uppercase())
+            {
+                push(start, end);
+                start = end;
+            }
+        } else if is_terminator(ch) && depth == 0 {
+            let mut j = i;
+            while j < n && is_terminator(chars[j].1) {
+                j += 1;
+            }
+            let run: String = chars[i..j].iter().map(|&(_, c)| c).collect();
+            while j < n && TRAILERS.contains(&chars[j].1) {
+                j += 1;
+            }
+            if (j == n || chars[j].1.is_whitespace()) && is_boundary(text, start, pos, &run, next_visible(j)) {
+                push(start, byte_at(j));
+                start = byte_at(j);
+            }
+            i = j;
+            continue;
+        }
+        i += 1;
+    }
+    push(start, text.len());
+    spans
+}
+
+/// Решение о разрыве после серии терминаторов run, начинающейся в байте pos
+fn is_boundary(text: &str, start: usize, pos: usize, run: &str, next: Option<char>) -> bool {
+    let next_upper = next.is_none_or(|c| c.is_uppercase() || !c.is_alphabetic());
+    if run.contains(['!', '?']) {
+        return true;
+    }
+    if run.contains('…') || run.len() > 1 {
+        // Многоточие: "Ну... я не знаю" — одно предложение
+        return next_upper;
+    }
+    let word = text[start..pos]
+        .rsplit(|c: char| c.is_whitespace() || OPENERS.contains(&c) || c == '"')
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    if ABBREVIATIONS.contains(&word.as_str()) {
+        return FINAL_ABBREVIATIONS.contains(&word.as_str()) && next_upper && next.is_some();
+    }
+    // Инициалы: одиночная буква перед заглавной
+    let mut letters = word.chars();
+    if let (Some(c), None) = (letters.next(), letters.next()) {
+        if c.is_alphabetic() && next.is_some_and(|c| c.is_uppercase()) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_split() {
+        assert_eq!(
+            split_sentences("Привет! Как дела? Всё хорошо."),
+            vec!["Привет!", "Как дела?", "Всё хорошо."]
+        );
+        assert_eq!(split_sentences("Первая строка\nвторая"), vec!["Первая строка", "вторая"]);
+        assert!(split_sentences("  ").is_empty());
+    }
+
+    #[test]
+    fn test_abbreviations_and_numbers() {
+        assert_eq!(
+            split_sentences("Купи хлеб, молоко и т.д. Потом домой."),
+            vec!["Купи хлеб, молоко и т.д.", "Потом домой."]
+        );
+        assert_eq!(split_sentences("Пи равно 3.14 примерно. Да."), vec!["Пи равно 3.14 примерно.", "Да."]);
+        assert_eq!(split_sentences("См. рис. 3 и т.е. всё."), vec!["См. рис. 3 и т.е. всё."]);
+        assert_eq!(split_sentences("Use e.g. Python. Ok"), vec!["Use e.g. Python.", "Ok"]);
+        assert_eq!(split_sentences("Автор — А. С. Пушкин. Конец"), vec!["Автор — А. С. Пушкин.", "Конец"]);
+    }
+
+    #[test]
+    fn test_urls_ellipsis_quotes() {
+        assert_eq!(
+            split_sentences("Зайди на example.com/a.html сегодня. Спасибо"),
+            vec!["Зайди на example.com/a.html сегодня.", "Спасибо"]
+        );
+        assert_eq!(split_sentences("Ну... я не знаю. Может… Да!"), vec!["Ну... я не знаю.", "Может…", "Да!"]);
+        assert_eq!(
+            split_sentences("Он сказал: «Привет! Как ты?» и ушёл. Всё."),
+            vec!["Он сказал: «Привет! Как ты?» и ушёл.", "Всё."]
+        );
+        assert_eq!(split_sentences("(Так и есть.) Дальше."), vec!["(Так и есть.)", "Дальше."]);
+        assert_eq!(split_sentences("\"Стоп!\" Он замер."), vec!["\"Стоп!\"", "Он замер."]);
+    }
+
+    #[test]
+    fn test_spans_match_text() {
+        let text = "  Один.  Два?\nТри…";
+        for (s, a, b) in sentence_spans(text) {
+            assert_eq!(&text[a..b], s);
+        }
+    }
+}
@@ -0,0 +1,652 @@
+//! SessionManager — сессии пользователей
+//!
+//! - Сессия на user_id: рабочая память (последние working_size сообщений),
+//!   свой ThreadTracker и настроение
+//! - get_or_create(user_id) атомарен (DashMap entry): два потока получают одну сессию
+//! - Настроение — экспоненциальное сглаживание валентности реплик пользователя
+//!   (EmotionAnalyzer); можно задать явно через set_mood
+//! - expire_idle() выгружает сессии, простаивавшие дольше idle_timeout_secs;
+//!   сверх max_sessions выгружаются самые давние
+//! - С data_dir выгруженная сессия пишется в снимок user_<hash>/session.json
+//!   (рабочая память, настроение, активная тема) и восстанавливается при
+//!   следующем get_or_create; save() снимает все живые сессии
+//! - Сбой save() → PersistenceError; сбой записи при выгрузке и битый снимок
+//!   при восстановлении — RuntimeWarning (сессия выгружается / создаётся заново)
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyDict;
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use parking_lot::Mutex;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::async_io;
+use crate::config::{self, CoreConfig};
+use crate::emotion_analyzer::EmotionAnalyzer;
+use crate::errors::{self, PersistenceError};
+use crate::metrics;
+use crate::thread_tracker::{
+    Spill, ThreadTracker, ARCHIVE_FILE, ARCHIVE_NAMESPACE, ASSISTANT_PREVIEW_CHARS, CONTEXT_EXCHANGES, MAX_ARCHIVED,
+    MAX_THREAD_MESSAGES, USER_PREVIEW_CHARS,
+};
+
+const SNAPSHOT_FILE: &str = "session.json";
+/// Вес новой реплики в сглаженной валентности
+const MOOD_ALPHA: f64 = 0.3;
+/// |валентность| выше порога — настроение positive / negative
+const MOOD_THRESHOLD: f64 = 0.2;
+/// Сколько открытых нитей держит трекер сессии
+const SESSION_MAX_OPEN: usize = 5;
+
+// ── Внутренние структуры ──
+
+#[derive(Clone, Serialize, Deserialize)]
+struct WorkingEntry {
+    role: String,
+    content: String,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Mood {
+    /// positive / negative / neutral (или заданное через set_mood)
+    label: String,
+    /// Сглаженная валентность в [-1, 1]
+    valence: f64,
+    /// Эмоция последней реплики пользователя
+    last_emotion: String,
+}
+
+impl Default for Mood {
+    fn default() -> Self {
+        Self { label: "neutral".to_string(), valence: 0.0, last_emotion: "neutral".to_string() }
+    }
+}
+
+impl Mood {
+    fn observe(&mut self, emotion: String, confidence: f64) {
+        let target = match emotion.as_str() {
+            "positive" => confidence,
+            "negative" => -confidence,
+            _ => 0.0,
+        };
+        self.valence = (self.valence * (1.0 - MOOD_ALPHA) + target * MOOD_ALPHA).clamp(-1.0, 1.0);
+        self.label = mood_label(self.valence).to_string();
+        self.last_emotion = emotion;
+    }
+}
+
+fn mood_label(valence: f64) -> &'static str {
+    if valence > MOOD_THRESHOLD {
+        "positive"
+    } else if valence < -MOOD_THRESHOLD {
+        "negative"
+    } else {
+        "neutral"
+    }
+}
+
+/// Снимок сессии на диске
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    user_id: String,
+    created: DateTime<Utc>,
+    last_active: DateTime<Utc>,
+    working: Vec<WorkingEntry>,
+    mood: Mood,
+    topic: Option<String>,
+    entities: Vec<String>,
+    messages: u64,
+}
+
+struct SessionState {
+    user_id: String,
+    created: DateTime<Utc>,
+    last_active: Mutex<DateTime<Utc>>,
+    working_size: usize,
+    working: Mutex<VecDeque<WorkingEntry>>,
+    mood: Mutex<Mood>,
+    messages: AtomicU64,
+    tracker: ThreadTracker,
+    analyzer: Arc<EmotionAnalyzer>,
+}
+
+impl SessionState {
+    fn touch(&self, now: DateTime<Utc>) {
+        let mut last = self.last_active.lock();
+        *last = (*last).max(now);
+    }
+
+    fn is_idle(&self, now: DateTime<Utc>, timeout_secs: i64) -> bool {
+        (now - *self.last_active.lock()).num_seconds() > timeout_secs
+    }
+
+    fn push(&self, role: &str, content: &str, now: DateTime<Utc>) {
+        if role == "user" {
+            let (emotion, confidence, _) = self.analyzer.analyze_detailed(content);
+            self.mood.lock().observe(emotion, confidence);
+        }
+        let mut working = self.working.lock();
+        working.push_back(WorkingEntry { role: role.to_string(), content: content.to_string(), timestamp: now });
+        while working.len() > self.working_size {
+            working.pop_front();
+        }
+        drop(working);
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.touch(now);
+    }
+
+    fn exchange(&self, user_input: &str, response: &str, now: DateTime<Utc>) {
+        self.push("user", user_input, now);
+        self.push("assistant", response, now);
+        self.tracker.update(user_input, response);
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            user_id: self.user_id.clone(),
+            created: self.created,
+            last_active: *self.last_active.lock(),
+            working: self.working.lock().iter().cloned().collect(),
+            mood: self.mood.lock().clone(),
+            topic: self.tracker.get_current_topic(),
+            entities: self.tracker.get_entities(),
+            messages: self.messages.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// ── Сессия (Python-объект) ──
+
+/// Сессия пользователя; живёт, пока её держит SessionManager или Python
+#[pyclass(frozen)]
+pub struct Session {
+    state: Arc<SessionState>,
+}
+
+#[pymethods]
+impl Session {
+    #[getter]
+    fn user_id(&self) -> String {
+        self.state.user_id.clone()
+    }
+
+    /// Время создания (RFC 3339); у восстановленной сессии — исходное
+    #[getter]
+    fn created_at(&self) -> String {
+        self.state.created.to_rfc3339()
+    }
+
+    #[getter]
+    fn last_active(&self) -> String {
+        self.state.last_active.lock().to_rfc3339()
+    }
+
+    /// Секунд с последней активности
+    fn idle_secs(&self) -> f64 {
+        (Utc::now() - *self.state.last_active.lock()).num_milliseconds() as f64 / 1000.0
+    }
+
+    /// Сообщение в рабочую память; реплики role="user" обновляют настроение
+    fn add_message(&self, role: &str, content: &str) {
+        self.state.push(role, content, Utc::now());
+    }
+
+    /// Обмен репликами: обе — в рабочую память, пара — в трекер нитей
+    fn record_exchange(&self, user_input: &str, response: &str) {
+        self.state.exchange(user_input, response, Utc::now());
+    }
+
+    /// [(role, content, timestamp)] — от старых к новым
+    fn get_working_memory(&self) -> Vec<(String, String, String)> {
+        self.state
+            .working
+            .lock()
+            .iter()
+            .map(|e| (e.role.clone(), e.content.clone(), e.timestamp.to_rfc3339()))
+            .collect()
+    }
+
+    fn clear_working(&self) {
+        self.state.working.lock().clear();
+    }
+
+    /// (label, valence, last_emotion)
+    pub(crate) fn get_mood(&self) -> (String, f64, String) {
+        let mood = self.state.mood.lock();
+        (mood.label.clone(), mood.valence, mood.last_emotion.clone())
+    }
+
+    /// Явное настроение (например, из внешней модели); valence в [-1, 1],
+    /// None — оставить текущую
+    #[pyo3(signature = (label, valence=None))]
+    fn set_mood(&self, label: &str, valence: Option<f64>) -> PyResult<()> {
+        if let Some(v) = valence {
+            if !(-1.0..=1.0).contains(&v) {
+                return Err(PyValueError::new_err(format!("valence должна быть в [-1, 1]: {}", v)));
+            }
+        }
+        let mut mood = self.state.mood.lock();
+        mood.label = label.to_string();
+        if let Some(v) = valence {
+            mood.valence = v;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_current_topic(&self) -> Option<String> {
+        self.state.tracker.get_current_topic()
+    }
+
+    pub(crate) fn get_entities(&self) -> Vec<String> {
+        self.state.tracker.get_entities()
+    }
+
+    /// Контекст активной нити для промпта (см. ThreadTracker.get_context)
+    #[pyo3(signature = (max_exchanges=CONTEXT_EXCHANGES))]
+    fn get_thread_context(&self, max_exchanges: usize) -> Option<String> {
+        self.state.tracker.get_context(max_exchanges, USER_PREVIEW_CHARS, ASSISTANT_PREVIEW_CHARS)
+    }
+
+    fn end_thread(&self) {
+        self.state.tracker.end_thread();
+    }
+
+    fn __repr__(&self) -> String {
+        let mood = self.state.mood.lock().label.clone();
+        format!(
+            "Session(user_id={:?}, messages={}, mood={:?})",
+            self.state.user_id,
+            self.state.messages.load(Ordering::Relaxed),
+            mood
+        )
+    }
+}
+
+impl Session {
+    pub(crate) fn last_active_at(&self) -> DateTime<Utc> {
+        *self.state.last_active.lock()
+    }
+}
+
+// ── Менеджер ──
+
+#[pyclass(frozen)]
+pub struct SessionManager {
+    idle_timeout_secs: i64,
+    max_sessions: usize,
+    working_size: usize,
+    thread_timeout_secs: i64,
+    /// Каталог снимков; у каждого пользователя свой подкаталог
+    data_dir: Option<PathBuf>,
+    analyzer: Arc<EmotionAnalyzer>,
+    sessions: DashMap<String, Arc<SessionState>>,
+    created: AtomicU64,
+    restored: AtomicU64,
+    expired: AtomicU64,
+    evicted: AtomicU64,
+}
+
+impl SessionManager {
+    pub(crate) fn new(
+        idle_timeout_secs: i64,
+        max_sessions: usize,
+        working_size: usize,
+        thread_timeout_secs: i64,
+        data_dir: Option<&str>,
+    ) -> PyResult<Self> {
+        if idle_timeout_secs <= 0 || thread_timeout_secs <= 0 {
+            return Err(PyValueError::new_err("Таймауты должны быть > 0"));
+        }
+        if max_sessions == 0 || working_size == 0 {
+            return Err(PyValueError::new_err("max_sessions и working_size должны быть > 0"));
+        }
+        Ok(Self {
+            idle_timeout_secs,
+            max_sessions,
+            working_size,
+            thread_timeout_secs,
+            data_dir: data_dir.map(PathBuf::from),
+            analyzer: Arc::new(EmotionAnalyzer::new(true)),
+            sessions: DashMap::new(),
+            created: AtomicU64::new(0),
+            restored: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+            evicted: AtomicU64::new(0),
+        })
+    }
+}
+
+#[pymethods]
+impl SessionManager {
+    /// Незаданные параметры берутся из раздела sessions config;
+    /// data_dir — sessions.dir или data_dir/sessions
+    #[new]
+    #[pyo3(signature = (
+        idle_timeout_secs=None, max_sessions=None, working_size=None, thread_timeout_secs=None, data_dir=None,
+        *, config=None
+    ))]
+    fn py_new(
+        idle_timeout_secs: Option<i64>,
+        max_sessions: Option<usize>,
+        working_size: Option<usize>,
+        thread_timeout_secs: Option<i64>,
+        data_dir: Option<String>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let config = config::settings(config);
+        let sessions = &config.sessions;
+        Self::new(
+            idle_timeout_secs.unwrap_or(sessions.idle_timeout_secs),
+            max_sessions.unwrap_or(sessions.max_sessions),
+            working_size.unwrap_or(sessions.working_size),
+            thread_timeout_secs.unwrap_or(sessions.thread_timeout_secs),
+            data_dir.or_else(|| config.sessions_dir()).as_deref(),
+        )
+    }
+
+    /// Сессия пользователя: живая, восстановленная из снимка или новая
+    fn get_or_create(&self, user_id: &str) -> Session {
+        Session { state: self.session(user_id, Utc::now()) }
+    }
+
+    /// Живая сессия без создания и без продления активности
+    fn get(&self, user_id: &str) -> Option<Session> {
+        self.sessions.get(user_id).map(|s| Session { state: Arc::clone(&s) })
+    }
+
+    /// Забывает пользователя вместе со снимком; True, если сессия была
+    fn remove(&self, user_id: &str) -> bool {
+        let removed = self.sessions.remove(user_id).is_some();
+        let snapshot = self.snapshot_path(user_id).filter(|p| p.exists());
+        let had_snapshot = snapshot.is_some_and(|p| std::fs::remove_file(p).is_ok());
+        removed || had_snapshot
+    }
+
+    /// Выгружает простаивающие сессии (со снимком при data_dir);
+    /// возвращает их user_id
+    pub(crate) fn expire_idle(&self) -> Vec<String> {
+        self.expire_at(Utc::now())
+    }
+
+    /// Снимки всех живых сессий; возвращает их число
+    pub(crate) fn save(&self) -> PyResult<usize> {
+        let states: Vec<Arc<SessionState>> = self.sessions.iter().map(|s| Arc::clone(&s)).collect();
+        for state in &states {
+            self.write_snapshot(state).map_err(|e| {
+                PersistenceError::new_err(format!("Не удалось сохранить сессию {}: {}", state.user_id, e))
+            })?;
+        }
+        Ok(states.len())
+    }
+
+    /// save без блокировки цикла asyncio
+    fn save_async(slf: Py<Self>, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().save())
+    }
+
+    fn users(&self) -> Vec<String> {
+        self.sessions.iter().map(|r| r.key().clone()).collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.sessions.len()
+    }
+
+    fn __contains__(&self, user_id: &str) -> bool {
+        self.sessions.contains_key(user_id)
+    }
+
+    /// active, created, restored, expired (по простою), evicted (сверх max_sessions),
+    /// messages (в живых сессиях)
+    fn get_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("active", self.sessions.len())?;
+        dict.set_item("created", self.created.load(Ordering::Relaxed))?;
+        dict.set_item("restored", self.restored.load(Ordering::Relaxed))?;
+        dict.set_item("expired", self.expired.load(Ordering::Relaxed))?;
+        dict.set_item("evicted", self.evicted.load(Ordering::Relaxed))?;
+        let messages: u64 = self.sessions.iter().map(|s| s.messages.load(Ordering::Relaxed)).sum();
+        dict.set_item("messages", messages)?;
+        Ok(dict)
+    }
+}
+
+impl SessionManager {
+    /// Каталог пользователя по хэшу user_id: безопасное имя для любого идентификатора
+    fn user_dir(&self, user_id: &str) -> Option<PathBuf> {
+        self.data_dir.as_ref().map(|d| d.join(format!("user_{:016x}", xxh3_64(user_id.as_bytes()))))
+    }
+
+    fn snapshot_path(&self, user_id: &str) -> Option<PathBuf> {
+        self.user_dir(user_id).map(|d| d.join(SNAPSHOT_FILE))
+    }
+
+    fn session(&self, user_id: &str, now: DateTime<Utc>) -> Arc<SessionState> {
+        if let Some(state) = self.sessions.get(user_id) {
+            state.touch(now);
+            return Arc::clone(&state);
+        }
+        // Снимок читается без блокировки шарда; при гонке побеждает первая вставка
+        let snapshot = self.snapshot_path(user_id).and_then(|p| read_snapshot(&p));
+        let restored = snapshot.is_some();
+        let state = Arc::new(self.build(user_id, snapshot, now));
+        let state = match self.sessions.entry(user_id.to_string()) {
+            Entry::Occupied(e) => Arc::clone(e.get()),
+            Entry::Vacant(e) => {
+                let counter = if restored { &self.restored } else { &self.created };
+                counter.fetch_add(1, Ordering::Relaxed);
+                Arc::clone(e.insert(state).value())
+            }
+        };
+        if restored {
+            log::debug!("Сессия {} восстановлена из снимка", user_id);
+        }
+        state.touch(now);
+        self.enforce_limit(user_id);
+        state
+    }
+
+    fn build(&self, user_id: &str, snapshot: Option<Snapshot>, now: DateTime<Utc>) -> SessionState {
+        // Архив нитей — в общем хранилище data_dir, пространство имён пользователя
+        let legacy = self.user_dir(user_id).map(|d| d.join(ARCHIVE_FILE));
+        let mut warnings = Vec::new();
+        let spill = Spill::locate(
+            None,
+            self.data_dir.as_deref(),
+            format!("{}.user_{:016x}", ARCHIVE_NAMESPACE, xxh3_64(user_id.as_bytes())),
+            legacy,
+            &mut warnings,
+        );
+        let tracker = ThreadTracker::with_spill(
+            self.thread_timeout_secs,
+            SESSION_MAX_OPEN,
+            None,
+            MAX_THREAD_MESSAGES,
+            MAX_ARCHIVED,
+            spill,
+            warnings,
+        );
+        let mut state = SessionState {
+            user_id: user_id.to_string(),
+            created: now,
+            last_active: Mutex::new(now),
+            working_size: self.working_size,
+            working: Mutex::new(VecDeque::new()),
+            mood: Mutex::new(Mood::default()),
+            messages: AtomicU64::new(0),
+            tracker,
+            analyzer: Arc::clone(&self.analyzer),
+        };
+        if let Some(snapshot) = snapshot {
+            state.created = snapshot.created;
+            let skip = snapshot.working.len().saturating_sub(self.working_size);
+            state.working = Mutex::new(snapshot.working.into_iter().skip(skip).collect());
+            state.mood = Mutex::new(snapshot.mood);
+            state.messages = AtomicU64::new(snapshot.messages);
+            if let Some(topic) = snapshot.topic {
+                state.tracker.start_thread(&topic, Some(snapshot.entities), None);
+            }
+        }
+        state
+    }
+
+    fn expire_at(&self, now: DateTime<Utc>) -> Vec<String> {
+        let idle: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|s| s.is_idle(now, self.idle_timeout_secs))
+            .map(|s| s.key().clone())
+            .collect();
+        // remove_if перепроверяет простой: сессию могли взять между проходами
+        let expired: Vec<String> = idle
+            .into_iter()
+            .filter_map(|user_id| self.sessions.remove_if(&user_id, |_, s| s.is_idle(now, self.idle_timeout_secs)))
+            .map(|(user_id, state)| {
+                self.offload(&state);
+                user_id
+            })
+            .collect();
+        self.expired.fetch_add(expired.len() as u64, Ordering::Relaxed);
+        metrics::inc("sessions_expired_total", expired.len() as u64);
+        if !expired.is_empty() {
+            log::info!("Выгружено простаивающих сессий: {}", expired.len());
+        }
+        expired
+    }
+
+    /// Сверх max_sessions выгружает самые давно активные сессии (кроме только что взятой)
+    fn enforce_limit(&self, keep: &str) {
+        while self.sessions.len() > self.max_sessions {
+            let oldest = self
+                .sessions
+                .iter()
+                .filter(|s| s.key() != keep)
+                .min_by_key(|s| *s.last_active.lock())
+                .map(|s| s.key().clone());
+            let Some((_, state)) = oldest.and_then(|user_id| self.sessions.remove(&user_id)) else {
+                break;
+            };
+            self.offload(&state);
+            self.evicted.fetch_add(1, Ordering::Relaxed);
+            metrics::inc("sessions_evicted_total", 1);
+        }
+    }
+
+    /// Снимок выгружаемой сессии; ошибки записи не прерывают выгрузку
+    fn offload(&self, state: &SessionState) {
+        log::debug!("Сессия {} выгружена", state.user_id);
+        if let Err(e) = self.write_snapshot(state) {
+            errors::warn(module_path!(), &format!("Снимок сессии {} не записан, она выгружена без него: {}", state.user_id, e));
+        }
+    }
+
+    fn write_snapshot(&self, state: &SessionState) -> std::io::Result<()> {
+        let (Some(dir), Some(path)) = (self.user_dir(&state.user_id), self.snapshot_path(&state.user_id)) else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(dir)?;
+        let data = serde_json::to_string(&state.snapshot()).map_err(std::io::Error::other)?;
+        std::fs::write(path, data)
+    }
+}
+
+/// Снимок сессии; None — его нет или он нечитаем (с предупреждением)
+fn read_snapshot(path: &Path) -> Option<Snapshot> {
+    let data = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            errors::warn(module_path!(), &format!("Не удалось прочитать снимок {}: {}", path.display(), e));
+            return None;
+        }
+    };
+    serde_json::from_str(&data)
+        .map_err(|e| errors::warn(module_path!(), &format!("Повреждённый снимок {}, сессия создана заново: {}", path.display(), e)))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_get_or_create_and_working_memory() {
+        let manager = SessionManager::new(1800, 100, 3, 600, None).unwrap();
+        let now = Utc::now();
+        let a = manager.session("u1", now);
+        let b = manager.session("u1", now);
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(manager.created.load(Ordering::Relaxed), 1);
+
+        for i in 0..5 {
+            a.push("user", &format!("сообщение {}", i), now);
+        }
+        let working: Vec<String> = a.working.lock().iter().map(|e| e.content.clone()).collect();
+        assert_eq!(working, vec!["сообщение 2", "сообщение 3", "сообщение 4"]);
+        assert_eq!(a.messages.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn test_mood_follows_user_messages() {
+        let manager = SessionManager::new(1800, 100, 10, 600, None).unwrap();
+        let state = manager.session("u1", Utc::now());
+        for _ in 0..3 {
+            state.push("user", "Спасибо, отлично, супер!", Utc::now());
+        }
+        assert_eq!(state.mood.lock().label, "positive");
+        // Реплики ассистента настроение не меняют
+        let valence = state.mood.lock().valence;
+        state.push("assistant", "Ужасно, всё сломал, ошибка", Utc::now());
+        assert_eq!(state.mood.lock().valence, valence);
+        for _ in 0..6 {
+            state.push("user", "Опять ошибка, не работает", Utc::now());
+        }
+        assert_eq!(state.mood.lock().label, "negative");
+        assert_eq!(state.mood.lock().last_emotion, "negative");
+    }
+
+    #[test]
+    fn test_expire_and_restore_from_snapshot() {
+        let dir = std::env::temp_dir().join(format!("kristina_sessions_{}", std::process::id()));
+        let manager = SessionManager::new(60, 100, 10, 600, dir.to_str()).unwrap();
+        let t0 = Utc::now();
+        let state = manager.session("idle", t0);
+        state.exchange("Давай обсудим переезд в Казань", "Конечно, что именно?", t0);
+        manager.session("busy", t0 + Duration::seconds(100));
+
+        assert_eq!(manager.expire_at(t0 + Duration::seconds(120)), vec!["idle".to_string()]);
+        assert_eq!(manager.sessions.len(), 1);
+
+        let restored = manager.session("idle", t0 + Duration::seconds(130));
+        assert!(!Arc::ptr_eq(&state, &restored));
+        assert_eq!(manager.restored.load(Ordering::Relaxed), 1);
+        assert_eq!(restored.working.lock().len(), 2);
+        assert_eq!(restored.created, state.created);
+        assert_eq!(restored.tracker.get_current_topic(), state.tracker.get_current_topic());
+        assert!(restored.tracker.get_current_topic().is_some());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_max_sessions_evicts_oldest() {
+        let manager = SessionManager::new(1800, 2, 10, 600, None).unwrap();
+        let t0 = Utc::now();
+        manager.session("a", t0);
+        manager.session("b", t0 + Duration::seconds(1));
+        manager.session("c", t0 + Duration::seconds(2));
+        let mut users = manager.users();
+        users.sort();
+        assert_eq!(users, vec!["b", "c"]);
+        assert_eq!(manager.evicted.load(Ordering::Relaxed), 1);
+        assert!(SessionManager::new(0, 2, 10, 600, None).is_err());
+    }
+}
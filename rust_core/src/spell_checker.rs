@@ -0,0 +1,366 @@
+//! SpellChecker — исправление опечаток по частотному словарю (SymSpell)
+//!
+//! - Словарь: "слово частота" построчно (RU, EN или оба сразу); add_word / add_text
+//!   для своих слов и корпусов; нечитаемый файл → PersistenceError,
+//!   некорректная частота → ParseError
+//! - Предвычисленные удаления: у каждого слова — все варианты с удалёнными
+//!   до max_edit_distance символами (по первым prefix_length символам);
+//!   поиск — удаления из запроса и проверка кандидатов расстоянием
+//!   Дамерау–Левенштейна (перестановка соседних букв — одна правка)
+//! - Лучший кандидат: меньшее расстояние, затем большая частота
+//! - Сравнение без регистра и ё → е; регистр исправленного слова сохраняется
+//! - Короткие слова (до 4 букв) исправляются не дальше чем на 1 правку,
+//!   слова короче 3 букв и с цифрами — не исправляются
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::async_io;
+use crate::config::{self, CoreConfig};
+use crate::errors::{ParseError, PersistenceError};
+
+/// Слова короче не исправляются: "в", "на" и т.п. слишком многозначны
+const MIN_WORD_CHARS: usize = 3;
+/// До этой длины — не больше одной правки
+const SHORT_WORD_CHARS: usize = 4;
+
+/// Ключ словаря: нижний регистр, ё → е
+fn fold(word: &str) -> String {
+    word.to_lowercase().replace('ё', "е")
+}
+
+/// Расстояние Дамерау–Левенштейна (OSA); None — больше max
+fn distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut prev2: Vec<usize> = Vec::new();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (prev[j] + 1).min(row[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(prev2[j - 2] + 1);
+            }
+        }
+        if row.iter().min().is_some_and(|&m| m > max) {
+            return None;
+        }
+        prev2 = std::mem::replace(&mut prev, row);
+    }
+    Some(prev[b.len()]).filter(|&d| d <= max)
+}
+
+/// Все варианты word с удалёнными 1..=depth символами (и сам word)
+fn deletes(word: &[char], depth: usize, out: &mut HashSet<Vec<char>>) {
+    if !out.insert(word.to_vec()) || depth == 0 || word.len() <= 1 {
+        return;
+    }
+    for i in 0..word.len() {
+        let mut shorter = word.to_vec();
+        shorter.remove(i);
+        deletes(&shorter, depth - 1, out);
+    }
+}
+
+fn delete_hash(chars: &[char]) -> u64 {
+    xxh3_64(chars.iter().collect::<String>().as_bytes())
+}
+
+/// Регистр исходного слова → исправленное: "ПРЕВЕТ" → "ПРИВЕТ", "Превет" → "Привет"
+fn match_case(original: &str, corrected: &str) -> String {
+    let letters: Vec<char> = original.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+        return corrected.to_uppercase();
+    }
+    if original.chars().next().is_some_and(char::is_uppercase) {
+        let mut chars = corrected.chars();
+        return chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect());
+    }
+    corrected.to_string()
+}
+
+// ── Словарь ──
+
+#[derive(Default)]
+struct Dictionary {
+    /// (слово, частота)
+    words: Vec<(String, u64)>,
+    ids: HashMap<String, u32>,
+    /// хеш удаления → слова
+    deletes: HashMap<u64, Vec<u32>>,
+}
+
+impl Dictionary {
+    fn add(&mut self, word: &str, frequency: u64, max_distance: usize, prefix_length: usize) {
+        let key = fold(word.trim());
+        if key.is_empty() {
+            return;
+        }
+        if let Some(&id) = self.ids.get(&key) {
+            self.words[id as usize].1 += frequency;
+            return;
+        }
+        let id = self.words.len() as u32;
+        let chars: Vec<char> = key.chars().take(prefix_length).collect();
+        let mut variants = HashSet::new();
+        deletes(&chars, max_distance, &mut variants);
+        for variant in variants {
+            self.deletes.entry(delete_hash(&variant)).or_default().push(id);
+        }
+        self.ids.insert(key.clone(), id);
+        self.words.push((key, frequency));
+    }
+
+    /// Кандидаты (слово, расстояние, частота), лучшие первыми
+    fn lookup(&self, word: &str, max_distance: usize, prefix_length: usize) -> Vec<(String, usize, u64)> {
+        let key = fold(word);
+        let input: Vec<char> = key.chars().collect();
+        let max_distance = if input.len() <= SHORT_WORD_CHARS { max_distance.min(1) } else { max_distance };
+
+        let prefix: Vec<char> = input.iter().copied().take(prefix_length).collect();
+        let mut variants = HashSet::new();
+        deletes(&prefix, max_distance, &mut variants);
+
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        for variant in variants {
+            let Some(ids) = self.deletes.get(&delete_hash(&variant)) else { continue };
+            for &id in ids {
+                if !seen.insert(id) {
+                    continue;
+                }
+                let (candidate, frequency) = &self.words[id as usize];
+                let chars: Vec<char> = candidate.chars().collect();
+                if let Some(d) = distance(&input, &chars, max_distance) {
+                    found.push((candidate.clone(), d, *frequency));
+                }
+            }
+        }
+        found.sort_by(|a, b| a.1.cmp(&b.1).then(b.2.cmp(&a.2)).then_with(|| a.0.cmp(&b.0)));
+        found
+    }
+}
+
+// ── PyO3 класс ──
+
+#[pyclass(frozen)]
+pub struct SpellChecker {
+    max_edit_distance: usize,
+    prefix_length: usize,
+    dict: RwLock<Dictionary>,
+}
+
+impl SpellChecker {
+    pub(crate) fn new(max_edit_distance: usize, prefix_length: usize) -> PyResult<Self> {
+        if prefix_length <= max_edit_distance {
+            return Err(PyValueError::new_err("prefix_length должен быть больше max_edit_distance"));
+        }
+        Ok(Self { max_edit_distance, prefix_length, dict: RwLock::new(Dictionary::default()) })
+    }
+}
+
+#[pymethods]
+impl SpellChecker {
+    #[new]
+    #[pyo3(signature = (max_edit_distance=None, prefix_length=None, *, config=None))]
+    fn py_new(
+        max_edit_distance: Option<usize>,
+        prefix_length: Option<usize>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let spelling = config::settings(config).spelling;
+        Self::new(
+            max_edit_distance.unwrap_or(spelling.max_edit_distance),
+            prefix_length.unwrap_or(spelling.prefix_length),
+        )
+    }
+
+    /// Частотный словарь: строки "слово частота" (или просто "слово" — частота 1);
+    /// возвращает число прочитанных строк
+    fn load_dictionary(&self, py: Python<'_>, path: &str) -> PyResult<usize> {
+        py.allow_threads(|| self.load_dictionary_impl(path))
+    }
+
+    /// load_dictionary без блокировки цикла asyncio
+    fn load_dictionary_async(slf: Py<Self>, py: Python<'_>, path: String) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().load_dictionary_impl(&path))
+    }
+
+    /// Добавляет слово (частота суммируется с имеющейся)
+    #[pyo3(signature = (word, frequency=1))]
+    pub(crate) fn add_word(&self, word: &str, frequency: u64) {
+        self.dict.write().add(word, frequency, self.max_edit_distance, self.prefix_length);
+    }
+
+    /// Пополняет словарь словами текста (частота — число вхождений)
+    fn add_text(&self, py: Python<'_>, text: &str) {
+        py.allow_threads(|| {
+            let mut dict = self.dict.write();
+            for word in words(text).filter(|w| w.chars().count() >= MIN_WORD_CHARS) {
+                dict.add(word, 1, self.max_edit_distance, self.prefix_length);
+            }
+        });
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.dict.read().ids.contains_key(&fold(word))
+    }
+
+    /// Частота слова в словаре (0 — нет)
+    fn frequency(&self, word: &str) -> u64 {
+        let dict = self.dict.read();
+        dict.ids.get(&fold(word)).map_or(0, |&id| dict.words[id as usize].1)
+    }
+
+    /// Кандидаты [(слово, расстояние, частота)] — лучшие первыми
+    #[pyo3(signature = (word, max_distance=None, limit=5))]
+    fn suggest(&self, word: &str, max_distance: Option<usize>, limit: usize) -> PyResult<Vec<(String, usize, u64)>> {
+        let max_distance = self.check_distance(max_distance)?;
+        let mut found = self.dict.read().lookup(word, max_distance, self.prefix_length);
+        found.truncate(limit);
+        Ok(found)
+    }
+
+    /// Лучшее исправление слова; без кандидатов — слово как есть
+    #[pyo3(signature = (word, max_distance=None))]
+    pub(crate) fn correct(&self, word: &str, max_distance: Option<usize>) -> PyResult<String> {
+        let max_distance = self.check_distance(max_distance)?;
+        Ok(self.correct_word(&self.dict.read(), word, max_distance).unwrap_or_else(|| word.to_string()))
+    }
+
+    /// Исправляет все слова текста; пунктуация, пробелы и числа сохраняются
+    #[pyo3(signature = (text, max_distance=None))]
+    fn correct_text(&self, py: Python<'_>, text: &str, max_distance: Option<usize>) -> PyResult<String> {
+        let max_distance = self.check_distance(max_distance)?;
+        Ok(py.allow_threads(|| self.correct_text_impl(text, max_distance)))
+    }
+
+    fn __len__(&self) -> usize {
+        self.dict.read().words.len()
+    }
+}
+
+impl SpellChecker {
+    fn load_dictionary_impl(&self, path: &str) -> PyResult<usize> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| PersistenceError::new_err(format!("Не удалось прочитать {}: {}", path, e)))?;
+        let mut entries = Vec::new();
+        for (line_no, line) in data.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            let Some(word) = parts.next() else { continue };
+            let frequency = match parts.next() {
+                Some(f) => f.parse::<u64>().map_err(|_| {
+                    ParseError::new_err(format!("{}:{}: некорректная частота '{}'", path, line_no + 1, f))
+                })?,
+                None => 1,
+            };
+            entries.push((word, frequency));
+        }
+        let mut dict = self.dict.write();
+        for &(word, frequency) in &entries {
+            dict.add(word, frequency, self.max_edit_distance, self.prefix_length);
+        }
+        Ok(entries.len())
+    }
+
+    fn check_distance(&self, max_distance: Option<usize>) -> PyResult<usize> {
+        let max_distance = max_distance.unwrap_or(self.max_edit_distance);
+        if max_distance > self.max_edit_distance {
+            return Err(PyValueError::new_err(format!(
+                "max_distance не может превышать max_edit_distance ({})",
+                self.max_edit_distance
+            )));
+        }
+        Ok(max_distance)
+    }
+
+    /// Исправление слова или None (слово известно, короткое, с цифрами, без кандидатов)
+    fn correct_word(&self, dict: &Dictionary, word: &str, max_distance: usize) -> Option<String> {
+        if word.chars().count() < MIN_WORD_CHARS
+            || word.chars().any(|c| c.is_numeric())
+            || dict.ids.contains_key(&fold(word))
+        {
+            return None;
+        }
+        let (best, _, _) = dict.lookup(word, max_distance, self.prefix_length).into_iter().next()?;
+        Some(match_case(word, &best))
+    }
+
+    fn correct_text_impl(&self, text: &str, max_distance: usize) -> String {
+        let dict = self.dict.read();
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for word in words(text) {
+            let start = word.as_ptr() as usize - text.as_ptr() as usize;
+            out.push_str(&text[last..start]);
+            match self.correct_word(&dict, word, max_distance) {
+                Some(fixed) => out.push_str(&fixed),
+                None => out.push_str(word),
+            }
+            last = start + word.len();
+        }
+        out.push_str(&text[last..]);
+        out
+    }
+}
+
+/// Слова текста: серии букв (дефис внутри слова — часть слова: "кто-то")
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '-'))
+        .map(|w| w.trim_matches('-'))
+        .filter(|w| !w.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> SpellChecker {
+        let checker = SpellChecker::new(2, 7).unwrap();
+        for (word, frequency) in [
+            ("привет", 500), ("приведу", 20), ("переезд", 40), ("казань", 30), ("погода", 60),
+            ("погоду", 50), ("память", 45), ("hello", 300), ("help", 200), ("weather", 80), ("кот", 90), ("код", 70),
+        ] {
+            checker.add_word(word, frequency);
+        }
+        checker
+    }
+
+    #[test]
+    fn test_distance() {
+        let d = |a: &str, b: &str| distance(&a.chars().collect::<Vec<_>>(), &b.chars().collect::<Vec<_>>(), 3);
+        assert_eq!(d("привет", "привет"), Some(0));
+        assert_eq!(d("првиет", "привет"), Some(1));
+        assert_eq!(d("превед", "привет"), Some(2));
+        assert_eq!(d("abc", "xyzabc"), Some(3));
+        assert_eq!(d("a", "abcde"), None);
+    }
+
+    #[test]
+    fn test_correct_words() {
+        let checker = checker();
+        assert_eq!(checker.correct("превет", None).unwrap(), "привет");
+        assert_eq!(checker.correct("Пирвет", None).unwrap(), "Привет");
+        assert_eq!(checker.correct("WHEATHER", None).unwrap(), "WEATHER");
+        assert_eq!(checker.correct("helo", None).unwrap(), "hello");
+        // Короткие слова — одна правка, неизвестное без кандидатов — как есть
+        assert_eq!(checker.correct("кто", None).unwrap(), "кот");
+        assert_eq!(checker.correct("кита", None).unwrap(), "кита");
+        assert_eq!(checker.correct("zzzzzz", None).unwrap(), "zzzzzz");
+        assert!(checker.correct("превет", Some(5)).is_err());
+    }
+
+    #[test]
+    fn test_correct_text() {
+        let checker = checker();
+        let fixed = checker.correct_text_impl("Превет! Какая пгода в Казнаь на 2 дня, help?", 2);
+        assert_eq!(fixed, "Привет! Какая погода в Казань на 2 дня, help?");
+        assert_eq!(checker.dict.read().lookup("пгоду", 2, 7)[0].0, "погоду");
+    }
+}
@@ -0,0 +1,492 @@
+//! Стемминг RU/EN — алгоритмы Snowball
+//!
+//! - Русский: Snowball Russian (RV/R2, окончания причастий, глаголов, существительных)
+//! - Английский: Porter2 (Snowball English) с исключениями
+//! - stem_word: язык по письменности слова (кириллица → RU, латиница → EN)
+//! - Общий примитив ядра: BM25, MemoryEngine, EmotionAnalyzer, ThreadTracker,
+//!   IntentClassifier и EntityRecognizer нормализуют морфологию одинаково
+//! - Python: stem(word, lang) и класс Stemmer
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+
+// ── Русский ──
+
+const RU_VOWELS: &[char] = &['а', 'е', 'и', 'о', 'у', 'ы', 'э', 'ю', 'я'];
+
+const RU_PERFECTIVE_GERUND_1: &[&str] = &["вшись", "вши", "в"];
+const RU_PERFECTIVE_GERUND_2: &[&str] = &["ившись", "ывшись", "ивши", "ывши", "ив", "ыв"];
+const RU_ADJECTIVE: &[&str] = &[
+    "ими", "ыми", "его", "ого", "ему", "ому", "ее", "ие", "ые", "ое", "ей", "ий", "ый", "ой", "ем", "им",
+    "ым", "ом", "их", "ых", "ую", "юю", "ая", "яя", "ою", "ею",
+];
+const RU_PARTICIPLE_1: &[&str] = &["ем", "нн", "вш", "ющ", "щ"];
+const RU_PARTICIPLE_2: &[&str] = &["ивш", "ывш", "ующ"];
+const RU_REFLEXIVE: &[&str] = &["ся", "сь"];
+const RU_VERB_1: &[&str] = &[
+    "ете", "йте", "ешь", "нно", "ла", "на", "ли", "ем", "ло", "но", "ет", "ют", "ны", "ть", "й", "л", "н",
+];
+const RU_VERB_2: &[&str] = &[
+    "ейте", "уйте", "ила", "ыла", "ена", "ите", "или", "ыли", "ило", "ыло", "ено", "ует", "уют", "ены",
+    "ить", "ыть", "ишь", "ей", "уй", "ил", "ыл", "им", "ым", "ен", "ят", "ит", "ыт", "ую", "ю",
+];
+const RU_NOUN: &[&str] = &[
+    "иями", "ями", "ами", "ией", "иям", "ием", "иях", "ев", "ов", "ие", "ье", "еи", "ии", "ей", "ой", "ий",
+    "ям", "ем", "ам", "ом", "ах", "ях", "ию", "ью", "ия", "ья", "а", "е", "и", "й", "о", "у", "ы", "ь", "ю",
+    "я",
+];
+const RU_DERIVATIONAL: &[&str] = &["ость", "ост"];
+const RU_SUPERLATIVE: &[&str] = &["ейше", "ейш"];
+
+/// Длиннейшее окончание из списка, целиком лежащее в word[start..]
+fn ru_ending(word: &[char], start: usize, endings: &[&str]) -> Option<usize> {
+    endings
+        .iter()
+        .map(|e| e.chars().collect::<Vec<char>>())
+        .filter(|e| e.len() <= word.len() - start && word.ends_with(e))
+        .map(|e| e.len())
+        .max()
+}
+
+/// Окончание группы 1 — только после "а" или "я" (сама буква остаётся)
+fn ru_ending_after_a(word: &[char], start: usize, endings: &[&str]) -> Option<usize> {
+    endings
+        .iter()
+        .map(|e| e.chars().collect::<Vec<char>>())
+        .filter(|e| {
+            e.len() < word.len() - start + 1
+                && e.len() < word.len()
+                && word.ends_with(e)
+                && word.len() - e.len() > start
+                && matches!(word[word.len() - e.len() - 1], 'а' | 'я')
+        })
+        .map(|e| e.len())
+        .max()
+}
+
+/// Удаляет самое длинное окончание из двух групп (1 — после "а"/"я")
+fn ru_remove(word: &mut Vec<char>, rv: usize, group1: &[&str], group2: &[&str]) -> bool {
+    let len = ru_ending_after_a(word, rv, group1)
+        .into_iter()
+        .chain(ru_ending(word, rv, group2))
+        .max();
+    if let Some(len) = len {
+        word.truncate(word.len() - len);
+        true
+    } else {
+        false
+    }
+}
+
+/// Позиция после первой гласной (RV) и R1/R2 по Snowball
+fn ru_regions(word: &[char]) -> (usize, usize) {
+    let is_vowel = |c: char| RU_VOWELS.contains(&c);
+    let rv = word.iter().position(|&c| is_vowel(c)).map_or(word.len(), |i| i + 1);
+    let after_vc = |from: usize| {
+        (from.max(1)..word.len())
+            .find(|&i| !is_vowel(word[i]) && is_vowel(word[i - 1]))
+            .map_or(word.len(), |i| i + 1)
+    };
+    let r1 = after_vc(0);
+    let r2 = after_vc(r1 + 1).max(r1);
+    (rv, r2)
+}
+
+pub(crate) fn stem_ru(word: &str) -> String {
+    let mut w: Vec<char> = word.to_lowercase().replace('ё', "е").chars().collect();
+    let (rv, r2) = ru_regions(&w);
+    if rv >= w.len() {
+        return w.into_iter().collect();
+    }
+
+    // Шаг 1
+    if !ru_remove(&mut w, rv, RU_PERFECTIVE_GERUND_1, RU_PERFECTIVE_GERUND_2) {
+        if let Some(len) = ru_ending(&w, rv, RU_REFLEXIVE) {
+            w.truncate(w.len() - len);
+        }
+        let adjectival = if let Some(len) = ru_ending(&w, rv, RU_ADJECTIVE) {
+            w.truncate(w.len() - len);
+            // Причастие перед прилагательным окончанием: "читающий" → "чита"
+            ru_remove(&mut w, rv, RU_PARTICIPLE_1, RU_PARTICIPLE_2);
+            true
+        } else {
+            false
+        };
+        if !adjectival && !ru_remove(&mut w, rv, RU_VERB_1, RU_VERB_2) {
+            if let Some(len) = ru_ending(&w, rv, RU_NOUN) {
+                w.truncate(w.len() - len);
+            }
+        }
+    }
+
+    // Шаг 2
+    if w.len() > rv && w.last() == Some(&'и') {
+        w.pop();
+    }
+
+    // Шаг 3
+    if let Some(len) = ru_ending(&w, r2.min(w.len()), RU_DERIVATIONAL) {
+        w.truncate(w.len() - len);
+    }
+
+    // Шаг 4
+    if w.len() >= rv + 2 && w.ends_with(&['н', 'н']) {
+        w.pop();
+    } else if let Some(len) = ru_ending(&w, rv.min(w.len()), RU_SUPERLATIVE) {
+        w.truncate(w.len() - len);
+        if w.len() >= rv + 2 && w.ends_with(&['н', 'н']) {
+            w.pop();
+        }
+    } else if w.len() > rv && w.last() == Some(&'ь') {
+        w.pop();
+    }
+    w.into_iter().collect()
+}
+
+// ── Английский (Porter2) ──
+
+const EN_EXCEPTIONS: &[(&str, &str)] = &[
+    ("skis", "ski"), ("skies", "sky"), ("dying", "die"), ("lying", "lie"), ("tying", "tie"),
+    ("idly", "idl"), ("gently", "gentl"), ("ugly", "ugli"), ("early", "earli"), ("only", "onli"),
+    ("singly", "singl"), ("sky", "sky"), ("news", "news"), ("howe", "howe"), ("atlas", "atlas"),
+    ("cosmos", "cosmos"), ("bias", "bias"), ("andes", "andes"),
+];
+const EN_EXCEPTIONS_1A: &[&str] = &["inning", "outing", "canning", "herring", "earring", "proceed", "exceed", "succeed"];
+const EN_DOUBLES: &[&str] = &["bb", "dd", "ff", "gg", "mm", "nn", "pp", "rr", "tt"];
+const EN_LI_ENDING: &[char] = &['c', 'd', 'e', 'g', 'h', 'k', 'm', 'n', 'r', 't'];
+
+const EN_STEP2: &[(&str, &str)] = &[
+    ("ization", "ize"), ("ational", "ate"), ("fulness", "ful"), ("ousness", "ous"), ("iveness", "ive"),
+    ("tional", "tion"), ("biliti", "ble"), ("lessli", "less"), ("entli", "ent"), ("ation", "ate"),
+    ("alism", "al"), ("aliti", "al"), ("ousli", "ous"), ("iviti", "ive"), ("fulli", "ful"),
+    ("enci", "ence"), ("anci", "ance"), ("abli", "able"), ("izer", "ize"), ("ator", "ate"),
+    ("alli", "al"), ("bli", "ble"), ("ogi", "og"), ("li", ""),
+];
+const EN_STEP3: &[(&str, &str)] = &[
+    ("ational", "ate"), ("tional", "tion"), ("alize", "al"), ("icate", "ic"), ("iciti", "ic"),
+    ("ative", ""), ("ical", "ic"), ("ness", ""), ("ful", ""),
+];
+const EN_STEP4: &[&str] = &[
+    "ement", "ance", "ence", "able", "ible", "ment", "ant", "ent", "ism", "ate", "iti", "ous", "ive",
+    "ize", "ion", "al", "er", "ic",
+];
+
+fn en_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y')
+}
+
+/// R1 и R2 (с исключениями gener/commun/arsen)
+fn en_regions(w: &[char]) -> (usize, usize) {
+    let after_vc = |from: usize| {
+        (from.max(1)..w.len())
+            .find(|&i| !en_vowel(w[i]) && en_vowel(w[i - 1]))
+            .map_or(w.len(), |i| i + 1)
+    };
+    let s: String = w.iter().collect();
+    let r1 = ["gener", "commun", "arsen"]
+        .iter()
+        .find(|p| s.starts_with(*p))
+        .map_or_else(|| after_vc(0), |p| p.len());
+    (r1, after_vc(r1 + 1).max(r1))
+}
+
+/// Короткий слог в конце w[..end]
+fn en_short_syllable(w: &[char], end: usize) -> bool {
+    match end {
+        2 => en_vowel(w[0]) && !en_vowel(w[1]),
+        n if n >= 3 => {
+            !en_vowel(w[n - 3]) && en_vowel(w[n - 2]) && !en_vowel(w[n - 1]) && !matches!(w[n - 1], 'w' | 'x' | 'Y')
+        }
+        _ => false,
+    }
+}
+
+fn ends(w: &[char], suffix: &str) -> bool {
+    let s: Vec<char> = suffix.chars().collect();
+    w.ends_with(&s)
+}
+
+fn set_suffix(w: &mut Vec<char>, old_len: usize, new: &str) {
+    w.truncate(w.len() - old_len);
+    w.extend(new.chars());
+}
+
+pub(crate) fn stem_en(word: &str) -> String {
+    let lower = word.to_lowercase();
+    if lower.chars().count() <= 2 {
+        return lower;
+    }
+    if let Some((_, stem)) = EN_EXCEPTIONS.iter().find(|(w, _)| *w == lower) {
+        return stem.to_string();
+    }
+    let mut w: Vec<char> = lower.trim_start_matches('\'').chars().collect();
+    if w.is_empty() {
+        return lower;
+    }
+    // y в начале или после гласной — согласная Y
+    for i in 0..w.len() {
+        if w[i] == 'y' && (i == 0 || en_vowel(w[i - 1])) {
+            w[i] = 'Y';
+        }
+    }
+    let (r1, r2) = en_regions(&w);
+
+    // Шаг 0
+    for suffix in ["'s'", "'s", "'"] {
+        if ends(&w, suffix) {
+            w.truncate(w.len() - suffix.chars().count());
+            break;
+        }
+    }
+
+    // Шаг 1a
+    if ends(&w, "sses") {
+        set_suffix(&mut w, 4, "ss");
+    } else if ends(&w, "ied") || ends(&w, "ies") {
+        let replacement = if w.len() > 4 { "i" } else { "ie" };
+        set_suffix(&mut w, 3, replacement);
+    } else if ends(&w, "us") || ends(&w, "ss") {
+    } else if ends(&w, "s") && w.len() >= 2 && w[..w.len() - 2].iter().any(|&c| en_vowel(c)) {
+        w.pop();
+    }
+    let current: String = w.iter().collect();
+    if EN_EXCEPTIONS_1A.contains(&current.as_str()) {
+        return current;
+    }
+
+    // Шаг 1b
+    if let Some(suffix) = ["eedly", "eed"].iter().find(|s| ends(&w, s)) {
+        let len = suffix.len();
+        if w.len() - len >= r1 {
+            set_suffix(&mut w, len, "ee");
+        }
+    } else if let Some(suffix) = ["ingly", "edly", "ing", "ed"].iter().find(|s| ends(&w, s)) {
+        let len = suffix.len();
+        if w[..w.len() - len].iter().any(|&c| en_vowel(c)) {
+            w.truncate(w.len() - len);
+            if ends(&w, "at") || ends(&w, "bl") || ends(&w, "iz") {
+                w.push('e');
+            } else if EN_DOUBLES.iter().any(|d| ends(&w, d)) {
+                w.pop();
+            } else if r1 >= w.len() && en_short_syllable(&w, w.len()) {
+                w.push('e');
+            }
+        }
+    }
+
+    // Шаг 1c
+    if w.len() > 2 && matches!(w[w.len() - 1], 'y' | 'Y') && !en_vowel(w[w.len() - 2]) {
+        let last = w.len() - 1;
+        w[last] = 'i';
+    }
+
+    // Шаг 2
+    if let Some((suffix, replacement)) = EN_STEP2.iter().find(|(s, _)| ends(&w, s)) {
+        let len = suffix.len();
+        if w.len() - len >= r1 {
+            let stem_end = w.len() - len;
+            let allowed = match *suffix {
+                "ogi" => stem_end > 0 && w[stem_end - 1] == 'l',
+                "li" => stem_end > 0 && EN_LI_ENDING.contains(&w[stem_end - 1]),
+                _ => true,
+            };
+            if allowed {
+                set_suffix(&mut w, len, replacement);
+            }
+        }
+    }
+
+    // Шаг 3
+    if let Some((suffix, replacement)) = EN_STEP3.iter().find(|(s, _)| ends(&w, s)) {
+        let len = suffix.len();
+        if w.len() - len >= r1 && (*suffix != "ative" || w.len() - len >= r2) {
+            set_suffix(&mut w, len, replacement);
+        }
+    }
+
+    // Шаг 4
+    if let Some(suffix) = EN_STEP4.iter().find(|s| ends(&w, s)) {
+        let len = suffix.len();
+        let stem_end = w.len() - len;
+        if stem_end >= r2 && (*suffix != "ion" || (stem_end > 0 && matches!(w[stem_end - 1], 's' | 't'))) {
+            w.truncate(stem_end);
+        }
+    }
+
+    // Шаг 5
+    if w.last() == Some(&'e') {
+        let stem_end = w.len() - 1;
+        if stem_end >= r2 || (stem_end >= r1 && !en_short_syllable(&w, stem_end)) {
+            w.pop();
+        }
+    } else if w.last() == Some(&'l') && w.len() > r2 && w.len() >= 2 && w[w.len() - 2] == 'l' {
+        w.pop();
+    }
+
+    w.into_iter().map(|c| if c == 'Y' { 'y' } else { c }).collect()
+}
+
+/// Основа слова: язык по первой букве (кириллица → RU, латиница → EN),
+/// остальное — в нижнем регистре без изменений
+pub(crate) fn stem_word(word: &str) -> String {
+    match word.chars().find(|c| c.is_alphabetic()) {
+        Some(c) if ('\u{0400}'..='\u{04FF}').contains(&c) => stem_ru(word),
+        Some(c) if c.is_ascii_alphabetic() => stem_en(word),
+        _ => word.to_lowercase(),
+    }
+}
+
+/// Основы слов текста по порядку (разбиение по не-буквенно-цифровым символам)
+pub(crate) fn stem_text(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(stem_word)
+        .collect()
+}
+
+/// " основа  основа " — каждое слово в своих пробелах: границы слов для
+/// Aho-Corasick, соседние совпадения не делят пробел
+pub(crate) fn stem_key<'a>(stems: impl IntoIterator<Item = &'a str>) -> String {
+    stems.into_iter().map(|s| format!(" {} ", s)).collect()
+}
+
+// ── Python API ──
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Lang {
+    Auto,
+    Ru,
+    En,
+}
+
+impl Lang {
+    fn parse(lang: &str) -> PyResult<Self> {
+        match lang.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "ru" => Ok(Self::Ru),
+            "en" => Ok(Self::En),
+            other => Err(PyValueError::new_err(format!("Неизвестный язык стемминга: {} (ожидается ru, en или auto)", other))),
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Ru => "ru",
+            Self::En => "en",
+        }
+    }
+
+    fn stem(self, word: &str) -> String {
+        match self {
+            Self::Auto => stem_word(word),
+            Self::Ru => stem_ru(word),
+            Self::En => stem_en(word),
+        }
+    }
+}
+
+/// Основа слова; lang — "ru", "en" или "auto" (по письменности)
+#[pyfunction]
+#[pyo3(signature = (word, lang="auto"))]
+pub fn stem(word: &str, lang: &str) -> PyResult<String> {
+    Ok(Lang::parse(lang)?.stem(word))
+}
+
+#[pyclass(frozen)]
+pub struct Stemmer {
+    lang: Lang,
+}
+
+#[pymethods]
+impl Stemmer {
+    #[new]
+    #[pyo3(signature = (lang="auto"))]
+    fn new(lang: &str) -> PyResult<Self> {
+        Ok(Self { lang: Lang::parse(lang)? })
+    }
+
+    #[getter]
+    fn lang(&self) -> &'static str {
+        self.lang.code()
+    }
+
+    fn stem(&self, word: &str) -> String {
+        self.lang.stem(word)
+    }
+
+    fn stem_many(&self, words: Vec<String>) -> Vec<String> {
+        words.iter().map(|w| self.lang.stem(w)).collect()
+    }
+
+    /// Основы всех слов текста по порядку
+    fn stem_text(&self, text: &str) -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| self.lang.stem(w))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stem_ru() {
+        let cases = [
+            ("переезд", "переезд"),
+            ("переезды", "переезд"),
+            ("кошки", "кошк"),
+            ("кошкой", "кошк"),
+            ("читающий", "чита"),
+            ("красивейший", "красив"),
+            ("вернувшись", "вернувш"),
+            ("радость", "радост"),
+            ("программирование", "программирован"),
+            ("ёлки", "елк"),
+        ];
+        for (word, stem) in cases {
+            assert_eq!(stem_ru(word), stem, "{}", word);
+        }
+    }
+
+    #[test]
+    fn test_stem_en() {
+        let cases = [
+            ("running", "run"),
+            ("cats", "cat"),
+            ("generously", "generous"),
+            ("happiness", "happi"),
+            ("relational", "relat"),
+            ("hopping", "hop"),
+            ("skies", "sky"),
+            ("caresses", "caress"),
+            ("agreed", "agre"),
+            ("database", "databas"),
+        ];
+        for (word, stem) in cases {
+            assert_eq!(stem_en(word), stem, "{}", word);
+        }
+    }
+
+    #[test]
+    fn test_stem_word_by_script() {
+        assert_eq!(stem_word("Migrations"), "migrat");
+        assert_eq!(stem_word("Миграции"), "миграц");
+        assert_eq!(stem_word("2024"), "2024");
+    }
+
+    #[test]
+    fn test_stemmer_api() {
+        assert_eq!(stem("кошками", "auto").unwrap(), "кошк");
+        assert_eq!(stem("running", "EN").unwrap(), "run");
+        assert!(stem("кошка", "de").is_err());
+
+        let ru = Stemmer::new("ru").unwrap();
+        assert_eq!(ru.lang(), "ru");
+        assert_eq!(ru.stem_many(vec!["Ёлки".into(), "переезды".into()]), vec!["елк", "переезд"]);
+        assert_eq!(stem_text("Мигрируем базы, migrating databases!"), vec!["мигриру", "баз", "migrat", "databas"]);
+        assert_eq!(stem_key(["кот", "спит"]), " кот  спит ");
+    }
+}
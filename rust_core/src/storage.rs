@@ -0,0 +1,648 @@
+//! Storage — общее хранилище SQLite для компонентов ядра
+//!
+//! - Один файл kristina.db на каталог данных; компоненты с одним каталогом
+//!   (или одним Storage, переданным через storage=) делят файл и пул соединений
+//! - Схема: записи (namespace, key) → value (байты); порядок — порядок записи
+//! - Пространства имён ядра: memory.episodes, memory.facts (MemoryEngine),
+//!   embeddings (EmbeddingCache), threads.archive[.user_<hash>] (архив нитей);
+//!   остальные свободны для Python (кэш ответов и т.п.)
+//! - WAL: чтения не ждут записи, несколько процессов могут открыть один файл
+//! - Прежние JSON-файлы (episodic.json, semantic.json, embedding_cache.json,
+//!   archived_threads.jsonl) импортируются при первом открытии пустого хранилища
+//! - vacuum() — сжатие файла; backup(path) — согласованная копия без остановки записи
+//! - Последняя удачная копия kristina.db.bak (snapshot после save у MemoryEngine):
+//!   файл, который не открывается или не проходит PRAGMA integrity_check,
+//!   откладывается в kristina.db.corrupt и заменяется копией
+//! - Общее состояние процессов (shared=True у компонентов): значение с версией
+//!   в таблице versions; изменение — под блокировкой записи SQLite (BEGIN IMMEDIATE),
+//!   процесс перечитывает значение, только если версия выросла
+//! - Ошибки SQLite → PersistenceError
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyBytes;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Weak};
+use std::time::Duration;
+
+use crate::async_io;
+use crate::config::{self, CoreConfig};
+use crate::errors::{self, PersistenceError};
+
+const DB_FILE: &str = "kristina.db";
+/// Расширения последней удачной копии и отложенного битого файла
+const SNAPSHOT_EXT: &str = "db.bak";
+const CORRUPT_EXT: &str = "db.corrupt";
+/// Простаивающих соединений в пуле; при нехватке открываются временные
+const POOL_SIZE: usize = 4;
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS records (
+    namespace TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value BLOB NOT NULL,
+    PRIMARY KEY (namespace, key)
+);
+CREATE TABLE IF NOT EXISTS versions (
+    namespace TEXT PRIMARY KEY,
+    version INTEGER NOT NULL
+);";
+
+pub(crate) type Records = Vec<(String, Vec<u8>)>;
+
+pub(crate) struct Store {
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+    /// Снимки и восстановление по одному: у них общие временные файлы
+    snapshot_lock: Mutex<()>,
+}
+
+impl Store {
+    fn connect(&self) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        Ok(conn)
+    }
+
+    fn checkout(&self) -> rusqlite::Result<Connection> {
+        let idle = self.idle.lock().pop();
+        match idle {
+            Some(conn) => Ok(conn),
+            None => self.connect(),
+        }
+    }
+
+    fn checkin(&self, conn: Connection) {
+        let mut idle = self.idle.lock();
+        if idle.len() < POOL_SIZE {
+            idle.push(conn);
+        }
+    }
+
+    /// Соединение из пула на время f; блокировка пула — только на взятие и возврат
+    fn with<T>(&self, f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+        let mut conn = self.checkout()?;
+        let result = f(&mut conn);
+        self.checkin(conn);
+        result
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// PersistenceError "Не удалось <action> <файл>: <err>"
+    pub(crate) fn error(&self, action: &str, err: rusqlite::Error) -> PyErr {
+        errors::persistence(action, &self.path, err)
+    }
+
+    pub(crate) fn get(&self, namespace: &str, key: &str) -> rusqlite::Result<Option<Vec<u8>>> {
+        self.with(|conn| {
+            conn.query_row(
+                "SELECT value FROM records WHERE namespace = ?1 AND key = ?2",
+                params![namespace, key],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+    }
+
+    pub(crate) fn put(&self, namespace: &str, key: &str, value: &[u8]) -> rusqlite::Result<()> {
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO records (namespace, key, value) VALUES (?1, ?2, ?3)",
+                params![namespace, key, value],
+            )
+            .map(|_| ())
+        })
+    }
+
+    pub(crate) fn delete(&self, namespace: &str, key: &str) -> rusqlite::Result<bool> {
+        self.with(|conn| {
+            conn.execute("DELETE FROM records WHERE namespace = ?1 AND key = ?2", params![namespace, key])
+                .map(|n| n > 0)
+        })
+    }
+
+    /// Удаляет ключи одной транзакцией
+    pub(crate) fn remove(&self, namespace: &str, keys: &[String]) -> rusqlite::Result<()> {
+        self.with(|conn| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            {
+                let mut stmt = tx.prepare_cached("DELETE FROM records WHERE namespace = ?1 AND key = ?2")?;
+                for key in keys {
+                    stmt.execute(params![namespace, key])?;
+                }
+            }
+            tx.commit()
+        })
+    }
+
+    /// Записи пространства имён в порядке записи
+    pub(crate) fn scan(&self, namespace: &str) -> rusqlite::Result<Records> {
+        self.with(|conn| {
+            let mut stmt = conn.prepare_cached("SELECT key, value FROM records WHERE namespace = ?1 ORDER BY rowid")?;
+            let rows = stmt.query_map(params![namespace], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+    }
+
+    pub(crate) fn keys(&self, namespace: &str) -> rusqlite::Result<Vec<String>> {
+        self.with(|conn| {
+            let mut stmt = conn.prepare_cached("SELECT key FROM records WHERE namespace = ?1 ORDER BY rowid")?;
+            let rows = stmt.query_map(params![namespace], |row| row.get(0))?;
+            rows.collect()
+        })
+    }
+
+    pub(crate) fn namespaces(&self) -> rusqlite::Result<Vec<(String, u64)>> {
+        self.with(|conn| {
+            let mut stmt = conn.prepare_cached("SELECT namespace, COUNT(*) FROM records GROUP BY namespace")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+    }
+
+    /// Заменяет содержимое пространств имён одной транзакцией
+    pub(crate) fn replace(&self, batches: &[(&str, Records)]) -> rusqlite::Result<()> {
+        self.write(batches, &[])
+    }
+
+    /// replace для одних пространств и дописывание в другие — одной транзакцией
+    pub(crate) fn write(&self, replace: &[(&str, Records)], append: &[(&str, Records)]) -> rusqlite::Result<()> {
+        self.with(|conn| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            for (namespace, records) in replace {
+                tx.execute("DELETE FROM records WHERE namespace = ?1", params![namespace])?;
+                insert(&tx, namespace, records)?;
+            }
+            for (namespace, records) in append {
+                insert(&tx, namespace, records)?;
+            }
+            tx.commit()
+        })
+    }
+
+    /// Дописывает записи одной транзакцией (совпавшие ключи заменяются)
+    pub(crate) fn append(&self, namespace: &str, records: &[(String, Vec<u8>)]) -> rusqlite::Result<()> {
+        self.with(|conn| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            insert(&tx, namespace, records)?;
+            tx.commit()
+        })
+    }
+
+    pub(crate) fn vacuum(&self) -> rusqlite::Result<()> {
+        self.with(|conn| {
+            conn.execute_batch("VACUUM")?;
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+        })
+    }
+
+    /// Перенос WAL в базу с усечением журнала: (busy, страниц в WAL, перенесено);
+    /// busy = 1 — мешали читатели, журнал усечён не полностью
+    pub(crate) fn checkpoint(&self) -> rusqlite::Result<(i64, i64, i64)> {
+        self.with(|conn| conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?))))
+    }
+
+    /// Копия базы в новый файл (VACUUM INTO); существующий файл не перезаписывается
+    pub(crate) fn backup(&self, dest: &Path) -> rusqlite::Result<()> {
+        let dest = dest.to_string_lossy();
+        self.with(|conn| conn.execute("VACUUM INTO ?1", params![dest]).map(|_| ()))
+    }
+
+    /// Схема и проверка целостности при открытии; соединение остаётся в пуле
+    fn init(&self) -> Result<(), String> {
+        let conn = self.connect().map_err(|e| e.to_string())?;
+        conn.execute_batch(SCHEMA).map_err(|e| e.to_string())?;
+        integrity(&conn)?;
+        self.checkin(conn);
+        Ok(())
+    }
+
+    /// Последняя удачная копия базы (kristina.db.bak)
+    pub(crate) fn snapshot_path(&self) -> PathBuf {
+        self.path.with_extension(SNAPSHOT_EXT)
+    }
+
+    /// Обновляет kristina.db.bak: VACUUM INTO во временный файл, fsync,
+    /// переименование — прежняя копия заменяется только целой новой
+    pub(crate) fn snapshot(&self) -> Result<(), String> {
+        let _guard = self.snapshot_lock.lock();
+        let dest = self.snapshot_path();
+        let tmp = dest.with_extension("bak.tmp");
+        let _ = std::fs::remove_file(&tmp);
+        let written = self
+            .backup(&tmp)
+            .map_err(|e| e.to_string())
+            .and_then(|()| replace_file(&tmp, &dest).map_err(|e| e.to_string()));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        written.map_err(|e| format!("Не удалось обновить копию {}: {}", dest.display(), e))
+    }
+
+    /// Проверяет базу (PRAGMA integrity_check) и при повреждении восстанавливает
+    /// её из kristina.db.bak. true — база восстановлена; Err — база битая, а
+    /// копии нет или она тоже непригодна.
+    /// Соединения пула закрываются; соединения, взятые другими потоками на
+    /// время восстановления, увидят уже новый файл
+    pub(crate) fn repair(&self) -> Result<bool, String> {
+        let problem = match self.connect().map_err(|e| e.to_string()).and_then(|conn| integrity(&conn)) {
+            Ok(()) => return Ok(false),
+            Err(problem) => problem,
+        };
+        self.restore_snapshot(&problem)?;
+        Ok(true)
+    }
+
+    /// Битый файл → kristina.db.corrupt, копия → kristina.db (через временный файл);
+    /// журналы WAL битого файла удаляются, иначе SQLite применил бы их к копии
+    fn restore_snapshot(&self, problem: &str) -> Result<(), String> {
+        let _guard = self.snapshot_lock.lock();
+        let snapshot = self.snapshot_path();
+        if !snapshot.exists() {
+            return Err(format!("База {} повреждена ({}), резервной копии нет", self.path.display(), problem));
+        }
+        self.idle.lock().clear();
+        let restore = || -> std::io::Result<()> {
+            if self.path.exists() {
+                std::fs::rename(&self.path, self.path.with_extension(CORRUPT_EXT))?;
+            }
+            for journal in ["db-wal", "db-shm"] {
+                let path = self.path.with_extension(journal);
+                if path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+            }
+            let tmp = self.path.with_extension("db.tmp");
+            std::fs::copy(&snapshot, &tmp)?;
+            replace_file(&tmp, &self.path)
+        };
+        restore().map_err(|e| format!("Не удалось восстановить {} из {}: {}", self.path.display(), snapshot.display(), e))?;
+        let conn = self.connect().map_err(|e| e.to_string())?;
+        integrity(&conn).map_err(|e| format!("Копия {} тоже повреждена: {}", snapshot.display(), e))?;
+        log::warn!(
+            "База {} повреждена ({}), восстановлена из {}; битый файл сохранён как {}",
+            self.path.display(),
+            problem,
+            snapshot.display(),
+            self.path.with_extension(CORRUPT_EXT).display()
+        );
+        Ok(())
+    }
+}
+
+/// Ошибка — текст первой найденной проблемы
+fn integrity(conn: &Connection) -> Result<(), String> {
+    let result: String = conn
+        .query_row("PRAGMA integrity_check(1)", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if result == "ok" {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
+/// fsync tmp, переименование в dest и fsync каталога: после сбоя на диске
+/// либо прежний dest, либо целый новый
+fn replace_file(tmp: &Path, dest: &Path) -> std::io::Result<()> {
+    std::fs::File::open(tmp)?.sync_all()?;
+    std::fs::rename(tmp, dest)?;
+    #[cfg(unix)]
+    if let Some(dir) = dest.parent() {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn insert(tx: &rusqlite::Transaction<'_>, namespace: &str, records: &[(String, Vec<u8>)]) -> rusqlite::Result<()> {
+    let mut stmt = tx.prepare_cached("INSERT OR REPLACE INTO records (namespace, key, value) VALUES (?1, ?2, ?3)")?;
+    for (key, value) in records {
+        stmt.execute(params![namespace, key, value])?;
+    }
+    Ok(())
+}
+
+// ── Общее состояние процессов ──
+
+/// Ключ значения SharedCell в его пространстве имён
+const STATE_KEY: &str = "state";
+
+fn version(conn: &Connection, namespace: &str) -> rusqlite::Result<u64> {
+    conn.query_row("SELECT version FROM versions WHERE namespace = ?1", params![namespace], |row| row.get(0))
+        .optional()
+        .map(|version| version.unwrap_or(0))
+}
+
+/// Значение, общее для процессов с одним хранилищем. Процесс держит свою
+/// копию и перечитывает её, когда версия в хранилище обгоняет известную
+pub(crate) struct SharedCell {
+    store: Arc<Store>,
+    namespace: String,
+    /// Последняя версия, известная процессу
+    seen: AtomicU64,
+}
+
+impl SharedCell {
+    pub(crate) fn new(store: Arc<Store>, namespace: String) -> Self {
+        Self { store, namespace, seen: AtomicU64::new(0) }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.store.path()
+    }
+
+    /// Значение, изменённое другим процессом: (версия, данные); None — изменений нет.
+    /// Копию заменяют после accept(версия) под своей блокировкой
+    pub(crate) fn refresh(&self) -> rusqlite::Result<Option<(u64, Vec<u8>)>> {
+        let seen = self.seen.load(Ordering::Acquire);
+        self.store.with(|conn| {
+            if version(conn, &self.namespace)? <= seen {
+                return Ok(None);
+            }
+            // Версия и данные одним запросом — согласованный снимок
+            conn.query_row(
+                "SELECT v.version, r.value FROM versions v \
+                 JOIN records r ON r.namespace = v.namespace AND r.key = ?2 WHERE v.namespace = ?1",
+                params![self.namespace, STATE_KEY],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+        })
+    }
+
+    /// false — копия уже новее версии из refresh (её успел обновить другой поток)
+    pub(crate) fn accept(&self, version: u64) -> bool {
+        self.seen.fetch_max(version, Ordering::AcqRel) < version
+    }
+
+    /// Начинает изменение: запись другим процессам блокируется до commit или drop.
+    /// Данные — если значение изменил другой процесс (копию нужно заменить)
+    pub(crate) fn lock(&self) -> rusqlite::Result<(SharedWrite<'_>, Option<Vec<u8>>)> {
+        let conn = self.store.checkout()?;
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+        let mut write = SharedWrite { cell: self, conn: Some(conn), version: 0 };
+        let conn = write.conn.as_ref().expect("соединение до commit");
+        write.version = version(conn, &self.namespace)?;
+        let mut fresh = None;
+        if write.version > self.seen.load(Ordering::Acquire) {
+            fresh = conn
+                .query_row(
+                    "SELECT value FROM records WHERE namespace = ?1 AND key = ?2",
+                    params![self.namespace, STATE_KEY],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            self.seen.fetch_max(write.version, Ordering::AcqRel);
+        }
+        Ok((write, fresh))
+    }
+}
+
+/// Изменение SharedCell: держит транзакцию записи; без commit — откат
+pub(crate) struct SharedWrite<'a> {
+    cell: &'a SharedCell,
+    conn: Option<Connection>,
+    version: u64,
+}
+
+impl SharedWrite<'_> {
+    /// Записывает значение со следующей версией и снимает блокировку
+    pub(crate) fn commit(self, data: &[u8]) -> rusqlite::Result<()> {
+        let conn = self.conn.as_ref().expect("соединение до commit");
+        let namespace = &self.cell.namespace;
+        conn.execute(
+            "INSERT OR REPLACE INTO records (namespace, key, value) VALUES (?1, ?2, ?3)",
+            params![namespace, STATE_KEY, data],
+        )?;
+        conn.execute(
+            "INSERT INTO versions (namespace, version) VALUES (?1, ?2) \
+             ON CONFLICT (namespace) DO UPDATE SET version = excluded.version",
+            params![namespace, self.version + 1],
+        )?;
+        conn.execute_batch("COMMIT")?;
+        self.cell.seen.fetch_max(self.version + 1, Ordering::AcqRel);
+        Ok(())
+    }
+}
+
+impl Drop for SharedWrite<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            if !conn.is_autocommit() && conn.execute_batch("ROLLBACK").is_err() {
+                // Соединение с незавершённой транзакцией в пул не возвращается
+                return;
+            }
+            self.cell.store.checkin(conn);
+        }
+    }
+}
+
+/// Открытые хранилища по пути файла: один Store (и пул) на файл в процессе
+static OPEN: LazyLock<Mutex<HashMap<PathBuf, Weak<Store>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Хранилище каталога dir (создаётся вместе с каталогом)
+pub(crate) fn open(dir: &Path) -> Result<Arc<Store>, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Не удалось создать каталог {}: {}", dir.display(), e))?;
+    let dir = dir.canonicalize().map_err(|e| format!("Не удалось открыть каталог {}: {}", dir.display(), e))?;
+    let path = dir.join(DB_FILE);
+    let mut open = OPEN.lock();
+    if let Some(store) = open.get(&path).and_then(Weak::upgrade) {
+        return Ok(store);
+    }
+    open.retain(|_, store| store.strong_count() > 0);
+    let store = Arc::new(Store { path: path.clone(), idle: Mutex::new(Vec::new()), snapshot_lock: Mutex::new(()) });
+    // Файл, который не открывается или не проходит проверку, заменяется последней копией
+    if let Err(problem) = store.init() {
+        store
+            .restore_snapshot(&problem)
+            .and_then(|()| store.init())
+            .map_err(|e| format!("Не удалось открыть хранилище {}: {}", path.display(), e))?;
+    }
+    open.insert(path, Arc::downgrade(&store));
+    Ok(store)
+}
+
+// ── PyO3 класс ──
+
+/// Хранилище SQLite: пространства имён с записями ключ → байты
+#[pyclass(frozen)]
+pub struct Storage {
+    store: Arc<Store>,
+}
+
+impl Storage {
+    pub(crate) fn shared(&self) -> Arc<Store> {
+        Arc::clone(&self.store)
+    }
+}
+
+#[pymethods]
+impl Storage {
+    /// data_dir — каталог файла kristina.db (по умолчанию data_dir из config)
+    #[new]
+    #[pyo3(signature = (data_dir=None, *, config=None))]
+    fn new(data_dir: Option<String>, config: Option<PyRef<'_, CoreConfig>>) -> PyResult<Self> {
+        let config = config::settings(config);
+        let dir = data_dir
+            .or_else(|| config.data_dir.clone())
+            .ok_or_else(|| PyValueError::new_err("Не задан data_dir: укажите его или data_dir в CoreConfig"))?;
+        let store = open(Path::new(&dir)).map_err(PersistenceError::new_err)?;
+        Ok(Self { store })
+    }
+
+    #[getter]
+    fn path(&self) -> String {
+        self.store.path.to_string_lossy().into_owned()
+    }
+
+    fn get(&self, py: Python<'_>, namespace: &str, key: &str) -> PyResult<Option<Py<PyBytes>>> {
+        let value = self.store.get(namespace, key).map_err(|e| self.store.error("прочитать", e))?;
+        Ok(value.map(|v| PyBytes::new(py, &v).unbind()))
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> PyResult<()> {
+        self.store.put(namespace, key, value).map_err(|e| self.store.error("записать в", e))
+    }
+
+    /// True — запись была
+    fn delete(&self, namespace: &str, key: &str) -> PyResult<bool> {
+        self.store.delete(namespace, key).map_err(|e| self.store.error("удалить из", e))
+    }
+
+    /// Ключи в порядке записи
+    fn keys(&self, namespace: &str) -> PyResult<Vec<String>> {
+        self.store.keys(namespace).map_err(|e| self.store.error("прочитать", e))
+    }
+
+    /// {пространство имён: число записей}
+    fn namespaces(&self) -> PyResult<HashMap<String, u64>> {
+        let counts = self.store.namespaces().map_err(|e| self.store.error("прочитать", e))?;
+        Ok(counts.into_iter().collect())
+    }
+
+    /// Сжимает файл после удалений и сбрасывает журнал WAL
+    fn vacuum(&self) -> PyResult<()> {
+        self.store.vacuum().map_err(|e| self.store.error("сжать", e))
+    }
+
+    fn vacuum_async(slf: Py<Self>, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().vacuum())
+    }
+
+    /// Согласованная копия в path (файла быть не должно); запись во время копии не блокируется
+    fn backup(&self, path: &str) -> PyResult<()> {
+        self.store.backup(Path::new(path)).map_err(|e| self.store.error("скопировать", e))
+    }
+
+    fn backup_async(slf: Py<Self>, py: Python<'_>, path: String) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().backup(&path))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Storage({:?})", self.store.path.display().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kristina_storage_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_records_and_shared_store() {
+        let dir = temp_dir("records");
+        let store = open(&dir).unwrap();
+        // Тот же каталог — тот же Store и пул
+        assert!(Arc::ptr_eq(&store, &open(&dir.join(".")).unwrap()));
+
+        store.put("cache", "b", b"2").unwrap();
+        store.put("cache", "a", b"1").unwrap();
+        assert_eq!(store.get("cache", "a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(store.keys("cache").unwrap(), vec!["b", "a"]);
+        assert!(store.delete("cache", "b").unwrap());
+        assert!(!store.delete("cache", "b").unwrap());
+
+        store.replace(&[("memory.facts", vec![("город".into(), "Казань".as_bytes().to_vec())])]).unwrap();
+        store.replace(&[("memory.facts", vec![("имя".into(), "Аня".as_bytes().to_vec())])]).unwrap();
+        assert_eq!(store.scan("memory.facts").unwrap(), vec![("имя".to_string(), "Аня".as_bytes().to_vec())]);
+        let mut namespaces = store.namespaces().unwrap();
+        namespaces.sort();
+        assert_eq!(namespaces, vec![("cache".to_string(), 1), ("memory.facts".to_string(), 1)]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_concurrent_writes_vacuum_and_backup() {
+        let dir = temp_dir("wal");
+        let store = open(&dir).unwrap();
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let store = Arc::clone(&store);
+                std::thread::spawn(move || {
+                    for i in 0..50 {
+                        store.append("log", &[(format!("{}-{}", t, i), vec![t as u8; 16])]).unwrap();
+                        assert!(store.get("log", &format!("{}-{}", t, i)).unwrap().is_some());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(store.keys("log").unwrap().len(), 400);
+        assert!(store.idle.lock().len() <= POOL_SIZE);
+
+        store.vacuum().unwrap();
+        let copy_dir = temp_dir("wal_copy");
+        std::fs::create_dir_all(&copy_dir).unwrap();
+        store.backup(&copy_dir.join(DB_FILE)).unwrap();
+        assert_eq!(open(&copy_dir).unwrap().keys("log").unwrap().len(), 400);
+        // Существующий файл не перезаписывается
+        assert!(store.backup(&copy_dir.join(DB_FILE)).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::remove_dir_all(&copy_dir).ok();
+    }
+
+    #[test]
+    fn test_shared_cell_versions() {
+        let dir = temp_dir("shared");
+        let store = open(&dir).unwrap();
+        // Два процесса — две ячейки над одним пространством имён
+        let a = SharedCell::new(Arc::clone(&store), "threads.state".into());
+        let b = SharedCell::new(Arc::clone(&store), "threads.state".into());
+        assert!(a.refresh().unwrap().is_none());
+        let (write, fresh) = a.lock().unwrap();
+        assert!(fresh.is_none());
+        write.commit(b"1").unwrap();
+        assert!(a.refresh().unwrap().is_none());
+
+        let (version, data) = b.refresh().unwrap().unwrap();
+        assert_eq!(data, b"1");
+        assert!(b.accept(version));
+        assert!(!b.accept(version));
+
+        // Без commit — откат, версия прежняя
+        drop(b.lock().unwrap());
+        assert!(a.refresh().unwrap().is_none());
+        let (write, _) = b.lock().unwrap();
+        write.commit(b"2").unwrap();
+        let (_write, fresh) = a.lock().unwrap();
+        assert_eq!(fresh.as_deref(), Some(&b"2"[..]));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
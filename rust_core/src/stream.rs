@@ -0,0 +1,521 @@
+//! StreamAccumulator — потоковый вывод LLM по готовым фрагментам
+//!
+//! - push(chunk) → завершённые сегменты: предложения (mode="sentence") или
+//!   абзацы (mode="paragraph"); последнее предложение держится, пока следующий
+//!   текст не подтвердит границу ("т.д." + "ещё" — не граница)
+//! - Границы предложений — те же правила, что у split_sentences; перевод строки — граница
+//! - Блок кода ``` / ~~~ отдаётся целиком одним сегментом kind="code" после
+//!   закрывающей ограды; внутри блока текст не режется
+//! - Строка "ACTION: ..." — отдельный сегмент kind="action", как только строка
+//!   завершена: вызов инструмента виден до конца ответа
+//! - Просматривается только неотданный хвост; токены считаются нарастающим
+//!   итогом по письменностям (как в ContextCompressor), без повторного прохода
+//! - finish() отдаёт остаток; reset() — к следующему ответу
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+use crate::context_compressor::{ScriptCounts, TokenProfile};
+use crate::segmenter::sentence_spans;
+
+const ACTION_PREFIX: &str = "ACTION:";
+
+/// Готовый фрагмент потока; start/end — смещения в символах от начала ответа
+#[pyclass(frozen, get_all)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamSegment {
+    pub text: String,
+    /// Вид: sentence, paragraph, code или action
+    pub kind: String,
+    pub index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub tokens: usize,
+}
+
+#[pymethods]
+impl StreamSegment {
+    fn __repr__(&self) -> String {
+        let preview: String = self.text.chars().take(30).collect();
+        format!(
+            "StreamSegment(kind={:?}, index={}, start={}, end={}, tokens={}, text={:?})",
+            self.kind, self.index, self.start, self.end, self.tokens, preview
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Sentence,
+    Paragraph,
+}
+
+impl Mode {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "sentence" => Some(Mode::Sentence),
+            "paragraph" => Some(Mode::Paragraph),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Mode::Sentence => "sentence",
+            Mode::Paragraph => "paragraph",
+        }
+    }
+}
+
+/// Ограда блока кода: символ и длина серии (закрывает серия не короче)
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Fence {
+    marker: char,
+    len: usize,
+}
+
+impl Fence {
+    fn run(line: &str) -> Option<(Fence, &str)> {
+        let trimmed = line.trim_start();
+        let marker = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+        let len = trimmed.chars().take_while(|&c| c == marker).count();
+        (len >= 3).then(|| (Fence { marker, len }, &trimmed[len..]))
+    }
+
+    fn open(line: &str) -> Option<Fence> {
+        Fence::run(line).map(|(fence, _)| fence)
+    }
+
+    fn closes(self, line: &str) -> bool {
+        Fence::run(line)
+            .is_some_and(|(f, rest)| f.marker == self.marker && f.len >= self.len && rest.trim().is_empty())
+    }
+}
+
+/// Особая строка в неотданном хвосте
+enum Special {
+    Fence(Fence),
+    Action,
+    /// Незавершённая строка, которая ещё может стать оградой или ACTION
+    Partial,
+}
+
+fn is_action(line: &str) -> bool {
+    line.trim_start().starts_with(ACTION_PREFIX)
+}
+
+fn may_be_special(line: &str) -> bool {
+    let t = line.trim_start();
+    !t.is_empty()
+        && (ACTION_PREFIX.starts_with(t)
+            || t.starts_with(ACTION_PREFIX)
+            || t.starts_with("```")
+            || t.starts_with("~~~")
+            || t.chars().all(|c| c == '`')
+            || t.chars().all(|c| c == '~'))
+}
+
+struct State {
+    mode: Mode,
+    profile: TokenProfile,
+    /// Неотданный хвост
+    buffer: String,
+    /// Символов до начала buffer
+    offset: usize,
+    /// buffer начинается с начала строки
+    at_line_start: bool,
+    /// Открытый блок кода; закрывающая ограда ищется с байта scan
+    fence: Option<Fence>,
+    scan: usize,
+    counts: ScriptCounts,
+    chars: usize,
+    emitted: usize,
+}
+
+impl State {
+    fn new(mode: Mode, profile: TokenProfile) -> Self {
+        Self {
+            mode,
+            profile,
+            buffer: String::new(),
+            offset: 0,
+            at_line_start: true,
+            fence: None,
+            scan: 0,
+            counts: ScriptCounts::default(),
+            chars: 0,
+            emitted: 0,
+        }
+    }
+
+    fn push(&mut self, chunk: &str) -> Vec<StreamSegment> {
+        self.counts.add(chunk);
+        self.chars += chunk.chars().count();
+        self.buffer.push_str(chunk);
+        self.drain(false)
+    }
+
+    fn finish(&mut self) -> Vec<StreamSegment> {
+        let out = self.drain(true);
+        self.buffer.clear();
+        self.at_line_start = true;
+        out
+    }
+
+    fn tokens(&self) -> usize {
+        if self.chars == 0 {
+            0
+        } else {
+            self.profile.tokens(&self.counts)
+        }
+    }
+
+    /// Сегменты, которые уже не изменятся; last — конец потока
+    fn drain(&mut self, last: bool) -> Vec<StreamSegment> {
+        let mut out = Vec::new();
+        loop {
+            if let Some(fence) = self.fence {
+                let mut pos = self.scan;
+                let mut closed = None;
+                while let Some(i) = self.buffer[pos..].find('\n') {
+                    let end = pos + i;
+                    if fence.closes(&self.buffer[pos..end]) {
+                        closed = Some(end);
+                        break;
+                    }
+                    pos = end + 1;
+                }
+                self.scan = pos;
+                let end = match closed {
+                    Some(end) => end,
+                    None if last => self.buffer.len(),
+                    None => break,
+                };
+                self.emit(&mut out, "code", 0, end);
+                self.fence = None;
+                self.consume((end + 1).min(self.buffer.len()));
+                continue;
+            }
+
+            let special = self.next_special(last);
+            let region_end = special.as_ref().map_or(self.buffer.len(), |(pos, _)| *pos);
+            // Незавершённая строка может продолжать абзац; предложение уже закрыто переводом строки
+            let complete = match special {
+                Some((_, Special::Partial)) => self.mode == Mode::Sentence,
+                Some(_) => true,
+                None => last,
+            };
+            let consumed = match self.mode {
+                Mode::Sentence => self.sentences(&mut out, region_end, complete),
+                Mode::Paragraph => self.paragraphs(&mut out, region_end, complete),
+            };
+            self.consume(consumed);
+            if consumed < region_end {
+                break;
+            }
+            let line_end = self.buffer.find('\n');
+            match special {
+                None | Some((_, Special::Partial)) => break,
+                Some((_, Special::Action)) => {
+                    let end = line_end.unwrap_or(self.buffer.len());
+                    self.emit(&mut out, "action", 0, end);
+                    self.consume((end + 1).min(self.buffer.len()));
+                }
+                Some((_, Special::Fence(fence))) => {
+                    self.fence = Some(fence);
+                    self.scan = line_end.map_or(self.buffer.len(), |end| end + 1);
+                }
+            }
+        }
+        out
+    }
+
+    /// Первая ограда / ACTION в начале строки хвоста
+    fn next_special(&self, last: bool) -> Option<(usize, Special)> {
+        let text = &self.buffer;
+        let mut pos = 0;
+        let mut line_start = self.at_line_start;
+        loop {
+            let newline = text[pos..].find('\n').map(|i| pos + i);
+            let line = &text[pos..newline.unwrap_or(text.len())];
+            if line_start {
+                if newline.is_some() || last {
+                    if let Some(fence) = Fence::open(line) {
+                        return Some((pos, Special::Fence(fence)));
+                    }
+                    if is_action(line) {
+                        return Some((pos, Special::Action));
+                    }
+                } else if may_be_special(line) {
+                    return Some((pos, Special::Partial));
+                }
+            }
+            pos = newline? + 1;
+            line_start = true;
+        }
+    }
+
+    /// Предложения buffer[..region_end]; без complete последнее ждёт продолжения.
+    /// Возвращает число байт, которые можно отбросить
+    fn sentences(&mut self, out: &mut Vec<StreamSegment>, region_end: usize, complete: bool) -> usize {
+        let region = &self.buffer[..region_end];
+        let complete = complete || region.ends_with('\n');
+        let spans: Vec<(usize, usize)> = sentence_spans(region).into_iter().map(|(_, s, e)| (s, e)).collect();
+        let ready = if complete { spans.len() } else { spans.len().saturating_sub(1) };
+        for &(start, end) in &spans[..ready] {
+            self.emit(out, "sentence", start, end);
+        }
+        if complete {
+            region_end
+        } else {
+            spans.get(ready).map_or(0, |&(start, _)| start)
+        }
+    }
+
+    /// Абзацы — по пустым строкам; абзац готов, когда за ним завершена пустая строка
+    fn paragraphs(&mut self, out: &mut Vec<StreamSegment>, region_end: usize, complete: bool) -> usize {
+        let mut bounds = Vec::new();
+        let mut start = None;
+        let mut consumed = 0;
+        let mut pos = 0;
+        while pos < region_end {
+            let newline = self.buffer[pos..region_end].find('\n').map(|i| pos + i);
+            let end = newline.unwrap_or(region_end);
+            if self.buffer[pos..end].trim().is_empty() {
+                if newline.is_some() {
+                    if let Some(from) = start.take() {
+                        bounds.push((from, pos));
+                    }
+                    consumed = end + 1;
+                }
+            } else if start.is_none() {
+                start = Some(pos);
+            }
+            pos = end + 1;
+        }
+        if complete {
+            if let Some(from) = start {
+                bounds.push((from, region_end));
+            }
+            consumed = region_end;
+        }
+        for (from, to) in bounds {
+            self.emit(out, "paragraph", from, to);
+        }
+        consumed
+    }
+
+    fn emit(&mut self, out: &mut Vec<StreamSegment>, kind: &str, from: usize, to: usize) {
+        let raw = &self.buffer[from..to];
+        let text = raw.trim();
+        if text.is_empty() {
+            return;
+        }
+        let lead = raw.len() - raw.trim_start().len();
+        let start = self.offset + self.buffer[..from + lead].chars().count();
+        out.push(StreamSegment {
+            text: text.to_string(),
+            kind: kind.to_string(),
+            index: self.emitted,
+            start,
+            end: start + text.chars().count(),
+            tokens: self.profile.estimate(text),
+        });
+        self.emitted += 1;
+    }
+
+    /// Отбросить отданное начало хвоста
+    fn consume(&mut self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        let taken: String = self.buffer.drain(..bytes).collect();
+        self.offset += taken.chars().count();
+        let tail = taken.rsplit('\n').next().unwrap_or("");
+        self.at_line_start = tail.trim().is_empty() && (self.at_line_start || taken.contains('\n'));
+        self.scan = self.scan.saturating_sub(bytes);
+    }
+}
+
+/// Накопитель потокового ответа LLM: отдаёт готовые предложения/абзацы,
+/// блоки кода и строки ACTION по мере поступления чанков
+#[pyclass(frozen)]
+pub struct StreamAccumulator {
+    state: Mutex<State>,
+}
+
+impl StreamAccumulator {
+    fn with_mode(mode: Mode, profile: TokenProfile) -> Self {
+        Self { state: Mutex::new(State::new(mode, profile)) }
+    }
+}
+
+#[pymethods]
+impl StreamAccumulator {
+    /// mode — "sentence" (для TTS) или "paragraph";
+    /// token_ratios — символов на токен по письменностям, как в ContextCompressor
+    #[new]
+    #[pyo3(signature = (mode="sentence", token_ratios=None))]
+    fn py_new(mode: &str, token_ratios: Option<HashMap<String, f64>>) -> PyResult<Self> {
+        let mode = Mode::parse(mode).ok_or_else(|| {
+            PyValueError::new_err(format!("mode: ожидается \"sentence\" или \"paragraph\", получено {mode:?}"))
+        })?;
+        let profile = match token_ratios {
+            Some(ratios) => TokenProfile::with_overrides(ratios).map_err(PyValueError::new_err)?,
+            None => TokenProfile::default(),
+        };
+        Ok(Self::with_mode(mode, profile))
+    }
+
+    /// Добавить чанк; возвращает сегменты, завершённые этим чанком
+    fn push(&self, chunk: &str) -> Vec<StreamSegment> {
+        self.state.lock().push(chunk)
+    }
+
+    /// Конец ответа: остаток хвоста (незакрытый блок кода — целиком)
+    fn finish(&self) -> Vec<StreamSegment> {
+        self.state.lock().finish()
+    }
+
+    /// Сбросить всё к началу нового ответа
+    fn reset(&self) {
+        let mut state = self.state.lock();
+        *state = State::new(state.mode, state.profile);
+    }
+
+    /// Ещё не отданный текст
+    #[getter]
+    fn pending(&self) -> String {
+        self.state.lock().buffer.clone()
+    }
+
+    /// Оценка токенов всего полученного текста
+    #[getter]
+    fn tokens(&self) -> usize {
+        self.state.lock().tokens()
+    }
+
+    /// Символов получено
+    #[getter]
+    fn chars(&self) -> usize {
+        self.state.lock().chars
+    }
+
+    /// Открыт блок кода — хвост не озвучивать
+    #[getter]
+    fn in_code(&self) -> bool {
+        self.state.lock().fence.is_some()
+    }
+
+    #[getter]
+    fn mode(&self) -> &'static str {
+        self.state.lock().mode.as_str()
+    }
+
+    fn __repr__(&self) -> String {
+        let state = self.state.lock();
+        format!(
+            "StreamAccumulator(mode={:?}, chars={}, tokens={}, segments={}, pending={})",
+            state.mode.as_str(),
+            state.chars,
+            state.tokens(),
+            state.emitted,
+            state.buffer.chars().count()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(acc: &StreamAccumulator, chunks: &[&str]) -> Vec<(String, String)> {
+        let mut state = acc.state.lock();
+        let mut out: Vec<StreamSegment> = chunks.iter().flat_map(|c| state.push(c)).collect();
+        out.extend(state.finish());
+        out.into_iter().map(|s| (s.kind, s.text)).collect()
+    }
+
+    fn sentence() -> StreamAccumulator {
+        StreamAccumulator::with_mode(Mode::Sentence, TokenProfile::default())
+    }
+
+    #[test]
+    fn test_sentences_across_chunks() {
+        let acc = sentence();
+        let mut state = acc.state.lock();
+        let first = state.push("Привет! Как");
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].text, "Привет!");
+        assert!(state.push(" дела").is_empty());
+        let second = state.push("? Всё хорошо.");
+        assert_eq!(second[0].text, "Как дела?");
+        // Последнее предложение ждёт продолжения или finish
+        assert_eq!(state.buffer.trim(), "Всё хорошо.");
+        let rest = state.finish();
+        assert_eq!(rest[0].text, "Всё хорошо.");
+        assert_eq!(rest[0].index, 2);
+
+        let text: Vec<char> = "Привет! Как дела? Всё хорошо.".chars().collect();
+        let seg = &second[0];
+        assert_eq!(text[seg.start..seg.end].iter().collect::<String>(), seg.text);
+        assert_eq!(state.tokens(), TokenProfile::default().estimate("Привет! Как дела? Всё хорошо."));
+    }
+
+    #[test]
+    fn test_code_fence_is_one_segment() {
+        let acc = sentence();
+        let got = feed(&acc, &["Вот код:\n``", "`py\nx = 1. Y = 2\n", "```\nГотово."]);
+        assert_eq!(
+            got,
+            vec![
+                ("sentence".into(), "Вот код:".into()),
+                ("code".into(), "```py\nx = 1. Y = 2\n```".into()),
+                ("sentence".into(), "Готово.".into()),
+            ]
+        );
+        // Незакрытый блок отдаётся целиком в finish
+        let got = feed(&acc, &["~~~\nЕщё. Код"]);
+        assert_eq!(got, vec![("code".into(), "~~~\nЕщё. Код".into())]);
+    }
+
+    #[test]
+    fn test_action_line_char_by_char() {
+        let acc = sentence();
+        let text = "Думаю.\nACTION: search(\"a. B\")\nДальше.";
+        let chunks: Vec<String> = text.chars().map(String::from).collect();
+        let refs: Vec<&str> = chunks.iter().map(String::as_str).collect();
+        let mut state = acc.state.lock();
+        let mut action_at = None;
+        for (i, chunk) in refs.iter().enumerate() {
+            if state.push(chunk).iter().any(|s| s.kind == "action") {
+                action_at = Some(i);
+            }
+        }
+        // Вызов виден сразу по завершении строки, до конца ответа
+        assert_eq!(action_at, text.find(")\n").map(|b| text[..b].chars().count() + 1));
+        drop(state);
+        assert_eq!(
+            feed(&sentence(), &["Да.\nACTION: x(\"a. B\")\nДальше."]),
+            vec![
+                ("sentence".into(), "Да.".into()),
+                ("action".into(), "ACTION: x(\"a. B\")".into()),
+                ("sentence".into(), "Дальше.".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paragraph_mode() {
+        let acc = StreamAccumulator::with_mode(Mode::Paragraph, TokenProfile::default());
+        let mut state = acc.state.lock();
+        assert!(state.push("Абзац один. Ещё.\nСтрока").is_empty());
+        let first = state.push("\n\nАбзац два.");
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].text, "Абзац один. Ещё.\nСтрока");
+        assert_eq!(first[0].kind, "paragraph");
+        assert_eq!(state.finish()[0].text, "Абзац два.");
+    }
+}
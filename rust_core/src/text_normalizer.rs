@@ -0,0 +1,219 @@
+//! normalize — единая нормализация текста перед хешированием и индексацией
+//!
+//! - NFC: "й" из "и" + U+0306 и готовое "й" дают одну строку
+//! - Невидимые символы: zero-width (U+200B–U+200D, U+2060), BOM, мягкий перенос
+//! - Двойники в словах со смешанной письменностью: "пpивет" (латинская p) →
+//!   "привет", "pаypal" (кириллические а, у) → "paypal"; слова целиком на одной
+//!   письменности не трогаются
+//! - Пробелы: серии пробельных символов (включая NBSP) → один пробел,
+//!   серии с переводом строки → "\n"; края обрезаются
+//! - Опционально: ё → е, нижний регистр
+//! - Применяется в ключах EmbeddingCache, индексе MemoryEngine и EmotionAnalyzer
+
+use pyo3::prelude::*;
+use std::borrow::Cow;
+use unicode_normalization::UnicodeNormalization;
+
+/// Невидимые символы, которые удаляются
+const ZERO_WIDTH: &[char] = &['\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}', '\u{00AD}', '\u{180E}'];
+
+/// Пары (кириллица, латиница), визуально неотличимые
+const HOMOGLYPHS: &[(char, char)] = &[
+    ('а', 'a'), ('е', 'e'), ('о', 'o'), ('р', 'p'), ('с', 'c'), ('у', 'y'), ('х', 'x'),
+    ('і', 'i'), ('ј', 'j'), ('ѕ', 's'), ('ԁ', 'd'), ('ԛ', 'q'), ('ԝ', 'w'),
+    ('А', 'A'), ('В', 'B'), ('Е', 'E'), ('К', 'K'), ('М', 'M'), ('Н', 'H'), ('О', 'O'),
+    ('Р', 'P'), ('С', 'C'), ('Т', 'T'), ('Х', 'X'), ('У', 'Y'), ('І', 'I'), ('Ј', 'J'), ('Ѕ', 'S'),
+];
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Options {
+    pub homoglyphs: bool,
+    pub zero_width: bool,
+    pub whitespace: bool,
+    pub yo: bool,
+    pub lowercase: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { homoglyphs: true, zero_width: true, whitespace: true, yo: false, lowercase: false }
+    }
+}
+
+fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{0400}'..='\u{04FF}' | '\u{0500}'..='\u{052F}')
+}
+
+fn to_latin(c: char) -> Option<char> {
+    HOMOGLYPHS.iter().find(|(cyr, _)| *cyr == c).map(|&(_, lat)| lat)
+}
+
+fn to_cyrillic(c: char) -> Option<char> {
+    HOMOGLYPHS.iter().find(|(_, lat)| *lat == c).map(|&(cyr, _)| cyr)
+}
+
+/// Слово со смешанной письменностью → одна письменность. Побеждает та,
+/// у которой есть буквы без двойника ("д", "z"); иначе — большинство, при
+/// равенстве — кириллица
+fn fold_word(word: &mut [char]) {
+    let (mut cyr, mut lat, mut cyr_only, mut lat_only) = (0, 0, false, false);
+    for &c in word.iter() {
+        if is_cyrillic(c) {
+            cyr += 1;
+            cyr_only |= to_latin(c).is_none();
+        } else if c.is_ascii_alphabetic() {
+            lat += 1;
+            lat_only |= to_cyrillic(c).is_none();
+        }
+    }
+    if cyr == 0 || lat == 0 {
+        return;
+    }
+    let cyrillic = match (cyr_only, lat_only) {
+        (true, false) => true,
+        (false, true) => false,
+        // Обе письменности с «настоящими» буквами — не подмена, а сплав (iPhoneы)
+        (true, true) => return,
+        (false, false) => cyr >= lat,
+    };
+    for c in word.iter_mut() {
+        let folded = if cyrillic { to_cyrillic(*c) } else { to_latin(*c) };
+        if let Some(folded) = folded {
+            *c = folded;
+        }
+    }
+}
+
+/// Текст уже нормализован (только ASCII без лишних пробелов)
+fn is_clean_ascii(text: &str, options: Options) -> bool {
+    if !text.is_ascii() || (options.lowercase && text.bytes().any(|b| b.is_ascii_uppercase())) {
+        return false;
+    }
+    if !options.whitespace {
+        return true;
+    }
+    let bytes = text.as_bytes();
+    let edge = |b: Option<&u8>| b.is_some_and(|b| b.is_ascii_whitespace());
+    !edge(bytes.first())
+        && !edge(bytes.last())
+        && bytes.windows(2).all(|w| !(w[0].is_ascii_whitespace() && w[1].is_ascii_whitespace()))
+        && !bytes.iter().any(|&b| b.is_ascii_whitespace() && b != b' ' && b != b'\n')
+}
+
+pub(crate) fn normalize_text(text: &str, options: Options) -> Cow<'_, str> {
+    if is_clean_ascii(text, options) {
+        return Cow::Borrowed(text);
+    }
+    let mut chars: Vec<char> = text.nfc().collect();
+    if options.zero_width {
+        chars.retain(|c| !ZERO_WIDTH.contains(c));
+    }
+    if options.yo {
+        for c in chars.iter_mut() {
+            *c = match *c {
+                'ё' => 'е',
+                'Ё' => 'Е',
+                other => other,
+            };
+        }
+    }
+    if options.homoglyphs {
+        let mut start = 0;
+        while start < chars.len() {
+            let len = chars[start..].iter().take_while(|c| c.is_alphanumeric()).count();
+            if len > 0 {
+                fold_word(&mut chars[start..start + len]);
+            }
+            start += len.max(1);
+        }
+    }
+
+    let mut out = String::with_capacity(text.len());
+    if options.whitespace {
+        let mut i = 0;
+        while i < chars.len() {
+            let len = chars[i..].iter().take_while(|c| c.is_whitespace()).count();
+            if len == 0 {
+                out.push(chars[i]);
+                i += 1;
+                continue;
+            }
+            if i > 0 && i + len < chars.len() {
+                out.push(if chars[i..i + len].contains(&'\n') { '\n' } else { ' ' });
+            }
+            i += len;
+        }
+    } else {
+        out.extend(chars);
+    }
+    if options.lowercase {
+        out = out.to_lowercase();
+    }
+    Cow::Owned(out)
+}
+
+/// Нормализация с настройками по умолчанию (для ключей и индексов)
+pub(crate) fn normalized(text: &str) -> Cow<'_, str> {
+    normalize_text(text, Options::default())
+}
+
+/// Нормализует текст: NFC, двойники, невидимые символы, пробелы;
+/// yo_to_e — ё → е, lowercase — нижний регистр
+#[pyfunction]
+#[pyo3(signature = (
+    text, fold_homoglyphs=true, remove_zero_width=true, collapse_whitespace=true, yo_to_e=false, lowercase=false
+))]
+pub fn normalize(
+    text: &str,
+    fold_homoglyphs: bool,
+    remove_zero_width: bool,
+    collapse_whitespace: bool,
+    yo_to_e: bool,
+    lowercase: bool,
+) -> String {
+    let options = Options {
+        homoglyphs: fold_homoglyphs,
+        zero_width: remove_zero_width,
+        whitespace: collapse_whitespace,
+        yo: yo_to_e,
+        lowercase,
+    };
+    normalize_text(text, options).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nfc_zero_width_whitespace() {
+        // "й" как и + комбинируемая кратка
+        assert_eq!(normalized("мои\u{0306}"), "мой");
+        assert_eq!(normalized("при\u{200B}вет\u{FEFF}"), "привет");
+        assert_eq!(normalized("  a \t b\u{00A0}c \n\n d  "), "a b c\nd");
+        assert!(matches!(normalized("plain ascii text"), Cow::Borrowed(_)));
+        assert!(matches!(normalized("two  spaces"), Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_homoglyphs() {
+        // Латинские p и o в русском слове
+        assert_eq!(normalized("пpивет, мир"), "привет, мир");
+        // Кириллические а и у в латинском слове
+        assert_eq!(normalized("pаypаl login"), "paypal login");
+        // Регистр сохраняется
+        assert_eq!(normalized("ПPИВЕТ"), "ПРИВЕТ");
+        // Одна письменность и сплавы не трогаются
+        assert_eq!(normalized("cpp и срр"), "cpp и срр");
+        assert_eq!(normalized("iPhoneы"), "iPhoneы");
+    }
+
+    #[test]
+    fn test_options() {
+        let options = Options { yo: true, lowercase: true, ..Options::default() };
+        assert_eq!(normalize_text("Ёлка  ЕЩЁ", options), "елка еще");
+        let raw = Options { homoglyphs: false, whitespace: false, ..Options::default() };
+        assert_eq!(normalize_text(" пpивет ", raw), " пpивет ");
+        assert_eq!(normalized("пpивет"), normalized("привет"));
+    }
+}
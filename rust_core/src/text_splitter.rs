@@ -0,0 +1,282 @@
+//! TextSplitter — рекурсивная нарезка документов на чанки
+//!
+//! - Разделители по убыванию: абзац (пустая строка) → предложение → слово → символ;
+//!   мельче режется только фрагмент, не помещающийся в chunk_tokens
+//! - Токены — та же оценка по письменностям, что в ContextCompressor
+//! - Соседние чанки перекрываются примерно на overlap_tokens
+//! - TextChunk: текст, порядковый номер, символьные смещения, оценка токенов
+//! - split_many: пакетная нарезка через Rayon без GIL
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+use crate::context_compressor::{word_spans, TokenProfile};
+use crate::segmenter::sentence_spans;
+
+/// Чанк документа; start/end — смещения в символах, text == документ[start:end]
+#[pyclass(frozen, get_all)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextChunk {
+    pub text: String,
+    pub index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub tokens: usize,
+}
+
+#[pymethods]
+impl TextChunk {
+    fn __repr__(&self) -> String {
+        let preview: String = self.text.chars().take(30).collect();
+        format!(
+            "TextChunk(index={}, start={}, end={}, tokens={}, text={:?})",
+            self.index, self.start, self.end, self.tokens, preview
+        )
+    }
+}
+
+/// Уровни разделителей; слово, не влезающее в чанк, режется по символам
+#[derive(Clone, Copy)]
+enum Level {
+    Paragraph,
+    Sentence,
+    Word,
+}
+
+#[pyclass(frozen)]
+pub struct TextSplitter {
+    chunk_tokens: usize,
+    overlap_tokens: usize,
+    tokens: TokenProfile,
+}
+
+#[pymethods]
+impl TextSplitter {
+    /// token_ratios — символов на токен по письменностям, как в ContextCompressor
+    #[new]
+    #[pyo3(signature = (chunk_tokens=512, overlap_tokens=0, token_ratios=None))]
+    fn new(chunk_tokens: usize, overlap_tokens: usize, token_ratios: Option<HashMap<String, f64>>) -> PyResult<Self> {
+        if chunk_tokens == 0 {
+            return Err(PyValueError::new_err("chunk_tokens должен быть > 0"));
+        }
+        if overlap_tokens >= chunk_tokens {
+            return Err(PyValueError::new_err("overlap_tokens должен быть меньше chunk_tokens"));
+        }
+        let tokens = match token_ratios {
+            Some(ratios) => TokenProfile::with_overrides(ratios).map_err(PyValueError::new_err)?,
+            None => TokenProfile::default(),
+        };
+        Ok(Self { chunk_tokens, overlap_tokens, tokens })
+    }
+
+    fn split(&self, text: &str) -> Vec<TextChunk> {
+        self.chunks(text)
+    }
+
+    /// Нарезка многих документов параллельно, GIL отпущен
+    fn split_many(&self, py: Python<'_>, texts: Vec<String>) -> Vec<Vec<TextChunk>> {
+        py.allow_threads(|| texts.par_iter().map(|t| self.chunks(t)).collect())
+    }
+
+    fn estimate_tokens(&self, text: &str) -> usize {
+        self.tokens.estimate(text)
+    }
+
+    #[getter]
+    fn chunk_tokens(&self) -> usize {
+        self.chunk_tokens
+    }
+
+    #[getter]
+    fn overlap_tokens(&self) -> usize {
+        self.overlap_tokens
+    }
+}
+
+// ── Нарезка ──
+
+impl TextSplitter {
+    fn chunks(&self, text: &str) -> Vec<TextChunk> {
+        let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        let to_char = |b: usize| boundaries.partition_point(|&x| x < b);
+        self.spans(text)
+            .into_iter()
+            .enumerate()
+            .map(|(index, (s, e))| TextChunk {
+                text: text[s..e].to_string(),
+                index,
+                start: to_char(s),
+                end: to_char(e),
+                tokens: self.tokens.estimate(&text[s..e]),
+            })
+            .collect()
+    }
+
+    /// Байтовые границы чанков
+    fn spans(&self, text: &str) -> Vec<(usize, usize)> {
+        let mut units = Vec::new();
+        for (s, e) in paragraph_spans(text) {
+            self.collect_units(text, s, e, Level::Paragraph, &mut units);
+        }
+        // Единица тянется до начала следующей (пунктуация, закрывающие кавычки)
+        let n = units.len();
+        for i in 0..n {
+            let limit = if i + 1 < n { units[i + 1].0 } else { text.len() };
+            units[i].1 = units[i].0 + text[units[i].0..limit].trim_end().len();
+        }
+
+        let tokens = |a: usize, b: usize| self.tokens.estimate(&text[units[a].0..units[b].1]);
+        let mut chunks = Vec::new();
+        let mut i = 0;
+        while i < n {
+            let mut j = i;
+            while j + 1 < n && tokens(i, j + 1) <= self.chunk_tokens {
+                j += 1;
+            }
+            chunks.push((units[i].0, units[j].1));
+            if j + 1 >= n {
+                break;
+            }
+            let mut k = j + 1;
+            while k > i + 1 && tokens(k - 1, j) <= self.overlap_tokens {
+                k -= 1;
+            }
+            i = k;
+        }
+        chunks
+    }
+
+    /// Фрагмент [start, end) целиком, если помещается, иначе — рекурсивно
+    /// по следующему уровню разделителей
+    fn collect_units(&self, text: &str, start: usize, end: usize, level: Level, out: &mut Vec<(usize, usize)>) {
+        if self.tokens.estimate(&text[start..end]) <= self.chunk_tokens {
+            out.push((start, end));
+            return;
+        }
+        let (pieces, next): (Vec<(usize, usize)>, Level) = match level {
+            Level::Paragraph => (
+                sentence_spans(&text[start..end]).into_iter().map(|(_, s, e)| (start + s, start + e)).collect(),
+                Level::Sentence,
+            ),
+            Level::Sentence => (word_spans(&text[start..end], start), Level::Word),
+            Level::Word => {
+                out.extend(self.hard_split(text, start, end));
+                return;
+            }
+        };
+        // Разделитель не нашёлся — сразу к следующему уровню
+        if pieces.len() <= 1 {
+            self.collect_units(text, start, end, next, out);
+            return;
+        }
+        for (s, e) in pieces {
+            self.collect_units(text, s, e, next, out);
+        }
+    }
+
+    /// Режет [start, end) по символам на куски не длиннее chunk_tokens
+    fn hard_split(&self, text: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+        let mut pieces = Vec::new();
+        let mut s = start;
+        while s < end {
+            let cuts: Vec<usize> = text[s..end]
+                .char_indices()
+                .skip(1)
+                .map(|(i, _)| s + i)
+                .chain(std::iter::once(end))
+                .collect();
+            let fit = cuts.partition_point(|&c| self.tokens.estimate(&text[s..c]) <= self.chunk_tokens);
+            let cut = cuts[fit.saturating_sub(1)];
+            pieces.push((s, cut));
+            s = cut;
+        }
+        pieces
+    }
+}
+
+/// Абзацы — серии непустых строк, разделённые пустыми; без краевых пробелов
+fn paragraph_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+    let mut pos = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            spans.extend(current.take());
+        } else {
+            let s = pos + (line.len() - line.trim_start().len());
+            let e = pos + line.trim_end().len();
+            current = Some(match current {
+                Some((start, _)) => (start, e),
+                None => (s, e),
+            });
+        }
+        pos += line.len();
+    }
+    spans.extend(current);
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn splitter(chunk_tokens: usize, overlap_tokens: usize) -> TextSplitter {
+        TextSplitter::new(chunk_tokens, overlap_tokens, None).unwrap()
+    }
+
+    #[test]
+    fn test_paragraph_spans() {
+        let text = "  Первый абзац.\nЕщё строка.\n\n \nВторой абзац.  \n";
+        let spans: Vec<&str> = paragraph_spans(text).into_iter().map(|(s, e)| &text[s..e]).collect();
+        assert_eq!(spans, vec!["Первый абзац.\nЕщё строка.", "Второй абзац."]);
+    }
+
+    #[test]
+    fn test_splits_by_paragraphs_first() {
+        let text = "Short intro paragraph.\n\nAnother short paragraph here.";
+        let chunks = splitter(10, 0).split(text);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].text, "Short intro paragraph.");
+        assert_eq!(chunks[1].text, "Another short paragraph here.");
+        assert_eq!(chunks[1].index, 1);
+
+        // Оба абзаца помещаются — один чанк вместе с разделителем
+        let whole = splitter(100, 0).split(text);
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].text, text);
+    }
+
+    #[test]
+    fn test_chunks_fit_and_offsets_are_chars() {
+        let text = "Кошка спит на окне. Собака гуляет во дворе. Птица поёт на ветке.\n\n\
+                    Оченьдлинноесловобезпробелов".repeat(3);
+        let s = splitter(8, 0);
+        let chars: Vec<char> = text.chars().collect();
+        for chunk in s.split(&text) {
+            assert!(chunk.tokens <= 8, "{:?}", chunk);
+            let slice: String = chars[chunk.start..chunk.end].iter().collect();
+            assert_eq!(slice, chunk.text);
+        }
+    }
+
+    #[test]
+    fn test_overlap_repeats_tail() {
+        let text = "one two three four five six seven eight nine ten eleven twelve";
+        let chunks = splitter(5, 2).split(text);
+        assert!(chunks.len() > 1);
+        for pair in chunks.windows(2) {
+            assert!(pair[1].start < pair[0].end, "{:?}", pair);
+        }
+        assert!(chunks.last().unwrap().text.ends_with("twelve"));
+    }
+
+    #[test]
+    fn test_rejects_bad_config() {
+        assert!(TextSplitter::new(0, 0, None).is_err());
+        assert!(TextSplitter::new(10, 10, None).is_err());
+        assert!(splitter(10, 0).split("   ").is_empty());
+    }
+}
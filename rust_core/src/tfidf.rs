@@ -0,0 +1,201 @@
+//! TfIdfVectorizer — разреженные TF-IDF векторы и косинусный поиск
+//!
+//! - Термы: та же токенизация и стемминг RU/EN, что у Bm25Index
+//! - IDF со сглаживанием: ln((1 + N) / (1 + df)) + 1
+//! - Векторы L2-нормированы → косинус = скалярное произведение
+//! - Дешёвый запасной путь поиска, когда модель эмбеддингов не настроена
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+
+use crate::bm25::analyze;
+
+/// Разреженный вектор: (индекс терма, вес), по возрастанию индекса
+type SparseVec = Vec<(usize, f64)>;
+
+#[derive(Default)]
+struct Model {
+    vocab: HashMap<String, usize>,
+    idf: Vec<f64>,
+    /// Векторы корпуса, на котором сделан fit
+    docs: Vec<SparseVec>,
+}
+
+fn dot(a: &[(usize, f64)], b: &[(usize, f64)]) -> f64 {
+    let (mut i, mut j, mut sum) = (0, 0, 0.0);
+    while i < a.len() && j < b.len() {
+        match a[i].0.cmp(&b[j].0) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                sum += a[i].1 * b[j].1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    sum
+}
+
+#[pyclass(frozen)]
+pub struct TfIdfVectorizer {
+    /// 1 + ln(tf) вместо tf
+    sublinear_tf: bool,
+    /// Термы, встретившиеся меньше чем в min_df документах, не входят в словарь
+    min_df: usize,
+    model: RwLock<Model>,
+}
+
+#[pymethods]
+impl TfIdfVectorizer {
+    #[new]
+    #[pyo3(signature = (sublinear_tf=false, min_df=1))]
+    fn new(sublinear_tf: bool, min_df: usize) -> Self {
+        Self { sublinear_tf, min_df: min_df.max(1), model: RwLock::new(Model::default()) }
+    }
+
+    /// Строит словарь и IDF по корпусу, запоминает векторы документов для search
+    fn fit(&self, corpus: Vec<String>) {
+        let analyzed: Vec<Vec<String>> = corpus.iter().map(|t| analyze(t)).collect();
+        let mut df: HashMap<&str, usize> = HashMap::new();
+        for terms in &analyzed {
+            for term in terms.iter().map(String::as_str).collect::<HashSet<_>>() {
+                *df.entry(term).or_insert(0) += 1;
+            }
+        }
+        let mut terms: Vec<(&str, usize)> = df.into_iter().filter(|&(_, n)| n >= self.min_df).collect();
+        terms.sort_unstable();
+
+        let n = corpus.len() as f64;
+        let mut model = Model {
+            vocab: terms.iter().enumerate().map(|(i, (t, _))| (t.to_string(), i)).collect(),
+            idf: terms.iter().map(|&(_, d)| ((1.0 + n) / (1.0 + d as f64)).ln() + 1.0).collect(),
+            docs: Vec::new(),
+        };
+        model.docs = analyzed.iter().map(|terms| self.vectorize(&model, terms)).collect();
+        *self.model.write() = model;
+    }
+
+    /// Векторы текстов → [[(term_index, weight)]]
+    fn transform(&self, texts: Vec<String>) -> PyResult<Vec<SparseVec>> {
+        let model = self.fitted()?;
+        Ok(texts.iter().map(|t| self.vectorize(&model, &analyze(t))).collect())
+    }
+
+    fn fit_transform(&self, corpus: Vec<String>) -> Vec<SparseVec> {
+        self.fit(corpus);
+        self.model.read().docs.clone()
+    }
+
+    /// Ближайшие документы корпуса fit → [(doc_index, cosine)], нулевые не возвращаются
+    #[pyo3(signature = (query, top_k=5))]
+    fn search(&self, query: &str, top_k: usize) -> PyResult<Vec<(usize, f64)>> {
+        let model = self.fitted()?;
+        let q = self.vectorize(&model, &analyze(query));
+        let mut results: Vec<(usize, f64)> = model
+            .docs
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (i, dot(&q, d)))
+            .filter(|&(_, s)| s > 0.0)
+            .collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        results.truncate(top_k);
+        Ok(results)
+    }
+
+    /// Косинусное сходство двух текстов в пространстве словаря
+    fn similarity(&self, a: &str, b: &str) -> PyResult<f64> {
+        let model = self.fitted()?;
+        Ok(dot(&self.vectorize(&model, &analyze(a)), &self.vectorize(&model, &analyze(b))))
+    }
+
+    /// Словарь: терм (основа) → индекс
+    fn get_vocabulary(&self) -> HashMap<String, usize> {
+        self.model.read().vocab.clone()
+    }
+
+    fn __len__(&self) -> usize {
+        self.model.read().vocab.len()
+    }
+}
+
+impl TfIdfVectorizer {
+    fn fitted(&self) -> PyResult<parking_lot::RwLockReadGuard<'_, Model>> {
+        let model = self.model.read();
+        if model.vocab.is_empty() {
+            return Err(PyValueError::new_err("Векторизатор не обучен: сначала вызовите fit"));
+        }
+        Ok(model)
+    }
+
+    fn vectorize(&self, model: &Model, terms: &[String]) -> SparseVec {
+        let mut tf: HashMap<usize, f64> = HashMap::new();
+        for term in terms {
+            if let Some(&idx) = model.vocab.get(term) {
+                *tf.entry(idx).or_insert(0.0) += 1.0;
+            }
+        }
+        let mut vec: SparseVec = tf
+            .into_iter()
+            .map(|(idx, count)| {
+                let tf = if self.sublinear_tf { 1.0 + count.ln() } else { count };
+                (idx, tf * model.idf[idx])
+            })
+            .collect();
+        vec.sort_unstable_by_key(|&(idx, _)| idx);
+        let norm = vec.iter().map(|(_, w)| w * w).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vec.iter_mut().for_each(|(_, w)| *w /= norm);
+        }
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fitted(corpus: &[&str]) -> TfIdfVectorizer {
+        let v = TfIdfVectorizer::new(false, 1);
+        v.fit(corpus.iter().map(|s| s.to_string()).collect());
+        v
+    }
+
+    #[test]
+    fn test_vectors_are_normalized_and_sorted() {
+        let v = TfIdfVectorizer::new(false, 1);
+        let corpus = vec!["кошка спит на окне".into(), "собака спит во дворе".into(), "кошка кошка собака".into()];
+        for vec in v.fit_transform(corpus) {
+            let norm: f64 = vec.iter().map(|(_, w)| w * w).sum();
+            assert!((norm - 1.0).abs() < 1e-9);
+            assert!(vec.windows(2).all(|p| p[0].0 < p[1].0));
+        }
+    }
+
+    #[test]
+    fn test_search_and_similarity() {
+        let v = fitted(&[
+            "Переезд в Москву запланирован на весну",
+            "Рецепт борща со сметаной",
+            "Нужно найти квартиру перед переездом",
+        ]);
+        let results = v.search("переезды и квартиры", 5).unwrap();
+        assert_eq!(results[0].0, 2);
+        assert_eq!(results.len(), 2);
+        assert!(v.search("квантовая физика", 5).unwrap().is_empty());
+        assert!(v.similarity("переезд весной", "весной переезд").unwrap() > 0.999);
+        assert_eq!(v.similarity("борщ", "квартира").unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_min_df_and_unfitted() {
+        let v = TfIdfVectorizer::new(true, 2);
+        assert!(v.search("что-нибудь", 3).is_err());
+        v.fit(vec!["alpha beta".into(), "beta gamma".into(), "beta delta".into()]);
+        assert_eq!(v.__len__(), 1);
+        assert!(v.get_vocabulary().contains_key("beta"));
+    }
+}
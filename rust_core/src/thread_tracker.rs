@@ -1,289 +1,2742 @@
-//! ThreadTracker — отслеживание нитей разговора
-//!
-//! Отслеживает текущую тему разговора, определяет связанность
-//! новых сообщений через:
-//! - Совпадение темы/сущностей (substring match)
-//! - Контекстные маркеры (Aho-Corasick: "помнишь", "продолжим", ...)
-//! - Timeout: нить закрывается после timeout_secs бездействия
-
-use pyo3::prelude::*;
-use parking_lot::RwLock;
-use chrono::{Utc, DateTime};
-use aho_corasick::AhoCorasick;
-
-// ── Внутренние структуры ──
-
-struct CurrentThread {
-    topic: String,
-    entities: Vec<String>,
-    started: DateTime<Utc>,
-    messages: Vec<ThreadMessage>,
-}
-
-struct ThreadMessage {
-    user: String,
-    #[allow(dead_code)]
-    assistant: String,
-    timestamp: DateTime<Utc>,
-}
-
-#[derive(Clone)]
-struct ArchivedThread {
-    topic: String,
-    duration_secs: f64,
-    message_count: usize,
-}
-
-// ── Контекстные индикаторы (RU) ──
-
-const CONTEXT_INDICATORS: &[&str] = &[
-    "помнишь", "как мы говорили", "в той же теме",
-    "продолжим", "вернёмся к", "насчёт того",
-    "по поводу", "как я говорил", "об этом же",
-];
-
-// ── PyO3 класс ──
-
-#[pyclass(frozen)]
-pub struct ThreadTracker {
-    timeout_secs: i64,
-    current: RwLock<Option<CurrentThread>>,
-    history: RwLock<Vec<ArchivedThread>>,
-    context_ac: AhoCorasick,
-}
-
-fn archive_thread(thread: CurrentThread, history: &mut Vec<ArchivedThread>) {
-    let duration = (Utc::now() - thread.started).num_seconds() as f64;
-    history.push(ArchivedThread {
-        topic: thread.topic,
-        duration_secs: duration,
-        message_count: thread.messages.len(),
-    });
-    if history.len() > 20 {
-        let excess = history.len() - 20;
-        history.drain(..excess);
-    }
-}
-
-#[pymethods]
-impl ThreadTracker {
-    #[new]
-    #[pyo3(signature = (timeout_secs=600))]
-    fn new(timeout_secs: i64) -> Self {
-        Self {
-            timeout_secs,
-            current: RwLock::new(None),
-            history: RwLock::new(Vec::new()),
-            context_ac: AhoCorasick::new(CONTEXT_INDICATORS).unwrap(),
-        }
-    }
-
-    #[pyo3(signature = (topic, entities=None))]
-    fn start_thread(&self, topic: &str, entities: Option<Vec<String>>) {
-        let mut current = self.current.write();
-        if let Some(thread) = current.take() {
-            let mut history = self.history.write();
-            archive_thread(thread, &mut history);
-        }
-        *current = Some(CurrentThread {
-            topic: topic.to_string(),
-            entities: entities.unwrap_or_default(),
-            started: Utc::now(),
-            messages: Vec::new(),
-        });
-    }
-
-    fn add_message(&self, user_input: &str, response: &str) {
-        let mut current = self.current.write();
-        if let Some(ref mut thread) = *current {
-            thread.messages.push(ThreadMessage {
-                user: user_input.to_string(),
-                assistant: response.to_string(),
-                timestamp: Utc::now(),
-            });
-        }
-    }
-
-    fn update(&self, user_input: &str, response: &str) {
-        let now = Utc::now();
-        let mut current = self.current.write();
-
-        // Проверяем timeout
-        if let Some(ref thread) = *current {
-            if let Some(last_msg) = thread.messages.last() {
-                let elapsed = (now - last_msg.timestamp).num_seconds();
-                if elapsed > self.timeout_secs {
-                    let thread = current.take().unwrap();
-                    let mut history = self.history.write();
-                    archive_thread(thread, &mut history);
-                }
-            }
-        }
-
-        // Создаём нить если нет
-        if current.is_none() {
-            *current = Some(CurrentThread {
-                topic: user_input.chars().take(50).collect(),
-                entities: Vec::new(),
-                started: now,
-                messages: Vec::new(),
-            });
-        }
-
-        if let Some(ref mut thread) = *current {
-            thread.messages.push(ThreadMessage {
-                user: user_input.to_string(),
-                assistant: response.to_string(),
-                timestamp: now,
-            });
-        }
-    }
-
-    fn is_related(&self, text: &str) -> bool {
-        let current = self.current.read();
-        let thread = match current.as_ref() {
-            Some(t) => t,
-            None => return false,
-        };
-
-        let elapsed = (Utc::now() - thread.started).num_seconds();
-        if elapsed > self.timeout_secs {
-            return false;
-        }
-
-        let text_lower = text.to_lowercase();
-
-        // Проверяем совпадение темы
-        if text_lower.contains(&thread.topic.to_lowercase()) {
-            return true;
-        }
-
-        // Проверяем сущности
-        for entity in &thread.entities {
-            if text_lower.contains(&entity.to_lowercase()) {
-                return true;
-            }
-        }
-
-        // Проверяем контекстные маркеры
-        self.context_ac.is_match(&text_lower)
-    }
-
-    fn get_context(&self) -> Option<String> {
-        let current = self.current.read();
-        let thread = current.as_ref()?;
-
-        let elapsed = (Utc::now() - thread.started).num_seconds();
-        if elapsed > self.timeout_secs {
-            return None;
-        }
-
-        let mut parts = vec![format!("Текущая тема: {}", thread.topic)];
-
-        if !thread.entities.is_empty() {
-            let entities_str: Vec<&str> = thread.entities.iter().take(5).map(|s| s.as_str()).collect();
-            parts.push(format!("Упоминается: {}", entities_str.join(", ")));
-        }
-
-        let recent_count = thread.messages.len().min(3);
-        if recent_count > 0 {
-            parts.push("\nПоследние сообщения:".to_string());
-            let start = thread.messages.len() - recent_count;
-            for msg in &thread.messages[start..] {
-                let preview: String = msg.user.chars().take(60).collect();
-                parts.push(format!("  Пользователь: {}", preview));
-            }
-        }
-
-        Some(parts.join("\n"))
-    }
-
-    fn has_active_thread(&self) -> bool {
-        let current = self.current.read();
-        match current.as_ref() {
-            Some(thread) => {
-                let elapsed = (Utc::now() - thread.started).num_seconds();
-                elapsed <= self.timeout_secs
-            }
-            None => false,
-        }
-    }
-
-    fn get_current_topic(&self) -> Option<String> {
-        let current = self.current.read();
-        current.as_ref().map(|t| t.topic.clone())
-    }
-
-    #[pyo3(signature = (limit=5))]
-    fn get_past_threads(&self, limit: usize) -> Vec<(String, f64, usize)> {
-        let history = self.history.read();
-        let start = if history.len() > limit {
-            history.len() - limit
-        } else {
-            0
-        };
-        history[start..]
-            .iter()
-            .map(|t| (t.topic.clone(), t.duration_secs, t.message_count))
-            .collect()
-    }
-
-    fn end_thread(&self) {
-        let mut current = self.current.write();
-        if let Some(thread) = current.take() {
-            let mut history = self.history.write();
-            archive_thread(thread, &mut history);
-        }
-    }
-
-    fn get_stats(&self) -> HashMap<String, bool> {
-        let current = self.current.read();
-        let mut map = HashMap::new();
-        map.insert("current_thread".to_string(), current.is_some());
-        map
-    }
-}
-
-use std::collections::HashMap;
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_start_and_get_topic() {
-        let tracker = ThreadTracker::new(600);
-        tracker.start_thread("тестовая тема", None);
-        assert_eq!(tracker.get_current_topic(), Some("тестовая тема".to_string()));
-        assert!(tracker.has_active_thread());
-    }
-
-    #[test]
-    fn test_is_related() {
-        let tracker = ThreadTracker::new(600);
-        tracker.start_thread("Rust программирование", Some(vec!["cargo".to_string()]));
-        assert!(tracker.is_related("Расскажи про Rust программирование"));
-        assert!(tracker.is_related("что там с cargo?"));
-        assert!(tracker.is_related("помнишь, мы обсуждали?"));
-    }
-
-    #[test]
-    fn test_end_thread_archives() {
-        let tracker = ThreadTracker::new(600);
-        tracker.start_thread("тема 1", None);
-        tracker.add_message("привет", "здравствуй");
-        tracker.end_thread();
-
-        assert!(tracker.get_current_topic().is_none());
-        let past = tracker.get_past_threads(5);
-        assert_eq!(past.len(), 1);
-        assert_eq!(past[0].0, "тема 1");
-    }
-
-    #[test]
-    fn test_update_creates_thread() {
-        let tracker = ThreadTracker::new(600);
-        tracker.update("новое сообщение", "ответ");
-        assert!(tracker.has_active_thread());
-    }
-}
+//! ThreadTracker — отслеживание нитей разговора
+//!
+//! Держит несколько открытых нитей (пользователь перескакивает между темами:
+//! "кстати, про отпуск..."), одна из них активная. Связанность сообщения
+//! с нитью определяется через:
+//! - Совпадение темы/сущностей (substring match) и общих слов темы
+//! - Контекстные маркеры (Aho-Corasick: "помнишь", "продолжим", ...)
+//! - Timeout: нить закрывается после timeout_secs бездействия (свой у каждой нити)
+//!
+//! Внутри нити — стек под-тем ("переезд → перевозка кота"): явный
+//! start_subtopic или автоопределение по дрейфу темы (доля новых слов).
+//!
+//! Архив: последние max_archived нитей в памяти; более старые при заданном
+//! data_dir (или storage=) дописываются в Storage (SQLite, threads.archive)
+//! и подгружаются поиском, find_resumable, resume и экспортом. Прежний
+//! archived_threads.jsonl импортируется при первом открытии.
+//!
+//! MultiThreadTracker — те же нити отдельно для каждого user_id с общими настройками.
+//!
+//! shared=True (несколько процессов-воркеров): открытые нити и архив в памяти
+//! хранятся в том же Storage (threads.state[.user_<hash>]). Процесс перечитывает
+//! их, если другой процесс что-то изменил; изменения идут под блокировкой
+//! записи хранилища и не теряются. Хронология и колбэки — свои у каждого процесса.
+//!
+//! Сущности накапливаются из каждого сообщения: имена с заглавной буквы,
+//! @упоминания, фразы в кавычках и совпадения со справочником (gazetteer).
+//! С set_entity_recognizer добавляются имена из справочников EntityRecognizer
+//! с учётом словоформ ("в Москве" → "Москва").
+//!
+//! Транслит: связанность, маркеры и find_resumable учитывают и кириллическую
+//! версию текста ("pomnish pro pereezd?"); выключается set_translit_matching(False).
+//!
+//! События (set_event_bus): thread_opened, thread_closed — с теми же полями,
+//! что у колбэков on_open / on_close (у MultiThreadTracker ещё и user_id).
+//!
+//! Нет запрошенной нити или пользователя → MemoryError. Сбои записи архива
+//! на диск не прерывают работу — RuntimeWarning после снятия блокировок.
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyDict;
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use chrono::{Utc, DateTime};
+use aho_corasick::AhoCorasick;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::BufRead;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Serialize, Deserialize};
+use xxhash_rust::xxh3::xxh3_64;
+use dashmap::DashMap;
+use crate::config::{self, CoreConfig};
+use crate::context_compressor::ContextCompressor;
+use crate::entities::{EntityRecognizer, SharedRecognizer};
+use crate::errors::{self, MemoryError};
+use crate::event_bus::{EventBus, SharedBus};
+use crate::metrics;
+use crate::stemmer::stem_word;
+use crate::storage::{self, Records, SharedCell, SharedWrite, Storage, Store};
+use crate::translit::{has_latin, to_cyrillic};
+
+// ── Внутренние структуры ──
+
+#[derive(Serialize, Deserialize)]
+struct Thread {
+    id: u64,
+    topic: String,
+    entities: Vec<String>,
+    started: DateTime<Utc>,
+    last_active: DateTime<Utc>,
+    timeout_secs: i64,
+    messages: Vec<ThreadMessage>,
+    /// Стек под-тем: последняя — текущая
+    subtopics: Vec<String>,
+    /// Сжатое содержание сообщений, вытесненных из messages
+    summary: Option<String>,
+    /// Сколько сообщений ушло в summary
+    compacted: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ThreadMessage {
+    user: String,
+    assistant: String,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ArchivedThread {
+    id: u64,
+    topic: String,
+    entities: Vec<String>,
+    started: DateTime<Utc>,
+    duration_secs: f64,
+    /// Сколько сообщений было в нити (messages может быть усечён)
+    message_count: usize,
+    /// Транскрипт: последние archive_messages сообщений
+    messages: Vec<ThreadMessage>,
+    /// Краткое содержание (если включено авто-суммирование)
+    summary: Option<String>,
+}
+
+/// Событие жизненного цикла нити — для колбэков on_open / on_close
+struct ThreadEvent {
+    opened: bool,
+    /// started / auto / resumed — для открытия; ended / timeout / evicted — для закрытия
+    reason: &'static str,
+    id: u64,
+    topic: String,
+    entities: Vec<String>,
+    message_count: usize,
+    duration_secs: f64,
+}
+
+impl ThreadEvent {
+    fn new(thread: &Thread, opened: bool, reason: &'static str) -> Self {
+        Self {
+            opened,
+            reason,
+            id: thread.id,
+            topic: thread.topic.clone(),
+            entities: thread.entities.clone(),
+            message_count: thread.message_count(),
+            duration_secs: (Utc::now() - thread.started).num_seconds() as f64,
+        }
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("event", if self.opened { "open" } else { "close" })?;
+        dict.set_item("reason", self.reason)?;
+        dict.set_item("id", self.id)?;
+        dict.set_item("topic", &self.topic)?;
+        dict.set_item("entities", &self.entities)?;
+        dict.set_item("message_count", self.message_count)?;
+        dict.set_item("duration_secs", self.duration_secs)?;
+        Ok(dict)
+    }
+
+    fn to_json(&self, user_id: Option<&str>) -> serde_json::Value {
+        let mut value = serde_json::json!({
+            "reason": self.reason,
+            "id": self.id,
+            "topic": self.topic,
+            "entities": self.entities,
+            "message_count": self.message_count,
+            "duration_secs": self.duration_secs,
+        });
+        if let Some(user_id) = user_id {
+            value["user_id"] = user_id.into();
+        }
+        value
+    }
+}
+
+/// Снимок нити для экспорта (открытой или архивной)
+struct ThreadExport {
+    id: u64,
+    topic: String,
+    entities: Vec<String>,
+    started: DateTime<Utc>,
+    archived: bool,
+    summary: Option<String>,
+    messages: Vec<ThreadMessage>,
+}
+
+impl ThreadExport {
+    fn to_json(&self) -> String {
+        let messages: Vec<serde_json::Value> = self
+            .messages
+            .iter()
+            .map(|m| {
+                serde_json::json!({
+                    "user": m.user,
+                    "assistant": m.assistant,
+                    "timestamp": m.timestamp.to_rfc3339(),
+                })
+            })
+            .collect();
+        let value = serde_json::json!({
+            "id": self.id,
+            "topic": self.topic,
+            "entities": self.entities,
+            "started": self.started.to_rfc3339(),
+            "archived": self.archived,
+            "summary": self.summary,
+            "messages": messages,
+        });
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n\n", self.topic);
+        out.push_str(&format!("*Начало: {}*\n", self.started.format("%Y-%m-%d %H:%M UTC")));
+        if !self.entities.is_empty() {
+            out.push_str(&format!("*Упоминается: {}*\n", self.entities.join(", ")));
+        }
+        if let Some(summary) = &self.summary {
+            out.push_str(&format!("\n> {}\n", summary));
+        }
+        for m in &self.messages {
+            out.push_str(&format!("\n### {}\n\n", m.timestamp.format("%H:%M:%S")));
+            out.push_str(&format!("**Пользователь:** {}\n\n", m.user));
+            if !m.assistant.is_empty() {
+                out.push_str(&format!("**Ассистент:** {}\n", m.assistant));
+            }
+        }
+        out
+    }
+}
+
+/// Запись хронологии сессии
+struct TimelineEvent {
+    timestamp: DateTime<Utc>,
+    /// started / auto / resumed / message / subtopic / ended / timeout / evicted
+    kind: &'static str,
+    thread_id: u64,
+    /// Тема нити или превью сообщения
+    detail: String,
+}
+
+/// Открытые нити, активная среди них и архив в памяти — состояние,
+/// общее для процессов в режиме shared
+#[derive(Default, Serialize, Deserialize)]
+struct Threads {
+    open: Vec<Thread>,
+    active: Option<u64>,
+    next_id: u64,
+    /// Последние max_archived закрытых нитей
+    history: Vec<ArchivedThread>,
+}
+
+impl Thread {
+    fn new(id: u64, topic: String, entities: Vec<String>, timeout_secs: i64, now: DateTime<Utc>) -> Self {
+        Self {
+            id,
+            topic,
+            entities,
+            started: now,
+            last_active: now,
+            timeout_secs,
+            messages: Vec::new(),
+            subtopics: Vec::new(),
+            summary: None,
+            compacted: 0,
+        }
+    }
+
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        (now - self.last_active).num_seconds() > self.timeout_secs
+    }
+
+    /// Секунд до таймаута (0 — уже просрочена)
+    fn seconds_left(&self, now: DateTime<Utc>) -> f64 {
+        let idle = (now - self.last_active).num_milliseconds() as f64 / 1000.0;
+        (self.timeout_secs as f64 - idle).max(0.0)
+    }
+
+    /// Всего сообщений, включая сжатые
+    fn message_count(&self) -> usize {
+        self.messages.len() + self.compacted
+    }
+
+    fn push(&mut self, user_input: &str, response: &str, now: DateTime<Utc>) {
+        self.messages.push(ThreadMessage {
+            user: user_input.to_string(),
+            assistant: response.to_string(),
+            timestamp: now,
+        });
+        self.last_active = now;
+    }
+
+    fn relevance(&self, text_lower: &str) -> usize {
+        relevance(&self.topic, &self.entities, text_lower)
+    }
+
+    /// "тема → под-тема: ... → под-тема: ..."
+    fn topic_path(&self) -> String {
+        std::iter::once(self.topic.clone())
+            .chain(self.subtopics.iter().map(|s| format!("под-тема: {}", s)))
+            .collect::<Vec<_>>()
+            .join(" → ")
+    }
+
+    /// Доля основ текста, которых нет в теме, под-темах и последних
+    /// DRIFT_WINDOW репликах пользователя (0 — всё знакомо, 1 — всё новое)
+    fn drift(&self, text: &str) -> f64 {
+        let words = stems(text);
+        if words.is_empty() {
+            return 0.0;
+        }
+        let start = self.messages.len().saturating_sub(DRIFT_WINDOW);
+        let mut context = stems(&self.topic);
+        for part in self.subtopics.iter().chain(self.messages[start..].iter().map(|m| &m.user)) {
+            context.extend(stems(part));
+        }
+        words.iter().filter(|w| !context.contains(*w)).count() as f64 / words.len() as f64
+    }
+
+    /// 1 − косинус между распределениями основ слов (сущности — с весом 2)
+    /// первых window сообщений вместе с темой и последних window сообщений.
+    /// 0, пока сообщений не больше window (сравнивать не с чем).
+    fn segment_drift(&self, window: usize) -> f64 {
+        let n = self.messages.len();
+        if window == 0 || n <= window {
+            return 0.0;
+        }
+        let mut opening = HashMap::new();
+        add_terms(&mut opening, &self.topic, &self.entities);
+        for m in &self.messages[..window] {
+            add_terms(&mut opening, &m.user, &self.entities);
+        }
+        let mut recent = HashMap::new();
+        for m in &self.messages[n - window..] {
+            add_terms(&mut recent, &m.user, &self.entities);
+        }
+        let dot: f64 = opening.iter().filter_map(|(k, a)| recent.get(k).map(|b| a * b)).sum();
+        let norm = |v: &HashMap<String, f64>| v.values().map(|x| x * x).sum::<f64>().sqrt();
+        let denom = norm(&opening) * norm(&recent);
+        if denom == 0.0 {
+            return 0.0;
+        }
+        (1.0 - dot / denom).clamp(0.0, 1.0)
+    }
+
+    fn summarize(&self, compressor: &ContextCompressor, max_tokens: usize) -> String {
+        let exchanges: Vec<(String, String)> = self
+            .messages
+            .iter()
+            .map(|m| (m.user.clone(), m.assistant.clone()))
+            .collect();
+        compressor.summarize_dialogue(&self.topic, &exchanges, max_tokens)
+    }
+
+    /// Добавляет новые сущности (без учёта регистра), не больше MAX_ENTITIES
+    fn add_entities(&mut self, found: Vec<String>) {
+        for entity in found {
+            if self.entities.len() >= MAX_ENTITIES {
+                break;
+            }
+            let lower = entity.to_lowercase();
+            if !self.entities.iter().any(|e| e.to_lowercase() == lower) {
+                self.entities.push(entity);
+            }
+        }
+    }
+}
+
+/// Насколько текст (lowercase) относится к нити: тема целиком — 3,
+/// каждая сущность — 2, каждое общее слово темы — 1
+/// (слова и сущности сравниваются по основам: "переезд" ~ "переезжаем")
+fn relevance(topic: &str, entities: &[String], text_lower: &str) -> usize {
+    let text_stems = match_stems(text_lower);
+    let topic_stems = match_stems(topic);
+    let (shared, _) = overlap(&topic_stems, &text_stems);
+    let mut score = shared;
+    if shared > 0 && shared == topic_stems.len() {
+        score += 3;
+    }
+    score += 2 * entities
+        .iter()
+        .filter(|e| entity_mentioned(e, text_lower, &text_stems))
+        .count();
+    score
+}
+
+/// Основы для нечёткого сравнения: слова от 3 символов (стемминг RU/EN)
+fn match_stems(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3)
+        .map(stem_word)
+        .collect()
+}
+
+/// Основы совпадают, одна — продолжение другой ("кот" ~ "котик") или у них
+/// общее начало от STEM_CHARS символов (чередования: "переезд" ~ "переезжа")
+fn stem_matches(a: &str, b: &str) -> bool {
+    a == b
+        || a.starts_with(b)
+        || b.starts_with(a)
+        || a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count() >= STEM_CHARS
+}
+
+/// (сколько основ topic нашлось в text, Jaccard по основам)
+fn overlap(topic: &HashSet<String>, text: &HashSet<String>) -> (usize, f64) {
+    let shared = topic.iter().filter(|t| text.iter().any(|w| stem_matches(t, w))).count();
+    let union = topic.len() + text.len() - shared;
+    (shared, if union == 0 { 0.0 } else { shared as f64 / union as f64 })
+}
+
+/// Сущность упомянута дословно или все её основы есть в тексте ("Казань" ~ "в Казани")
+fn entity_mentioned(entity: &str, text_lower: &str, text_stems: &HashSet<String>) -> bool {
+    let lower = entity.to_lowercase();
+    if lower.is_empty() {
+        return false;
+    }
+    if text_lower.contains(&lower) {
+        return true;
+    }
+    let stems = match_stems(&lower);
+    !stems.is_empty() && overlap(&stems, text_stems).0 == stems.len()
+}
+
+impl Threads {
+    fn get(&self, id: u64) -> Option<&Thread> {
+        self.open.iter().find(|t| t.id == id)
+    }
+
+    fn get_mut(&mut self, id: u64) -> Option<&mut Thread> {
+        self.open.iter_mut().find(|t| t.id == id)
+    }
+
+    fn active(&self) -> Option<&Thread> {
+        self.active.and_then(|id| self.get(id))
+    }
+
+    fn take(&mut self, id: u64) -> Option<Thread> {
+        let pos = self.open.iter().position(|t| t.id == id)?;
+        if self.active == Some(id) {
+            self.active = None;
+        }
+        Some(self.open.remove(pos))
+    }
+
+    fn open_thread(&mut self, topic: String, entities: Vec<String>, timeout_secs: i64, now: DateTime<Utc>) -> u64 {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.open.push(Thread::new(id, topic, entities, timeout_secs, now));
+        self.active = Some(id);
+        id
+    }
+}
+
+/// Слова длиннее 3 символов (lowercase) — для сравнения темы с текстом
+fn significant_words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 3)
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Общее начало, при котором разные основы считаются одним корнем
+const STEM_CHARS: usize = 5;
+
+/// Основы значимых слов (миграция/миграцию/миграции → "миграц")
+fn stems(text: &str) -> HashSet<String> {
+    significant_words(text).iter().map(|w| stem_word(w)).collect()
+}
+
+/// Очки нити для поиска: каждая основа запроса в теме — 3,
+/// в сущностях — 2, плюс 1 за каждое содержащее её сообщение
+fn search_score(query: &HashSet<String>, topic: &str, entities: &[String], messages: &[ThreadMessage]) -> usize {
+    let topic = stems(topic);
+    let entities = stems(&entities.join(" "));
+    let messages: Vec<HashSet<String>> = messages
+        .iter()
+        .map(|m| stems(&format!("{} {}", m.user, m.assistant)))
+        .collect();
+    query
+        .iter()
+        .map(|stem| {
+            3 * usize::from(topic.contains(stem))
+                + 2 * usize::from(entities.contains(stem))
+                + messages.iter().filter(|m| m.contains(stem)).count()
+        })
+        .sum()
+}
+
+/// Частоты основ текста; упомянутые сущности нити добавляются с весом 2
+fn add_terms(terms: &mut HashMap<String, f64>, text: &str, entities: &[String]) {
+    let lower = text.to_lowercase();
+    for word in lower.split(|c: char| !c.is_alphanumeric()).filter(|w| w.chars().count() > 3) {
+        *terms.entry(stem_word(word)).or_insert(0.0) += 1.0;
+    }
+    for entity in entities {
+        if !entity.is_empty() && lower.contains(&entity.to_lowercase()) {
+            *terms.entry(format!("@entity:{}", entity.to_lowercase())).or_insert(0.0) += 2.0;
+        }
+    }
+}
+
+// ── Извлечение сущностей ──
+
+const QUOTES: &[(char, char)] = &[('«', '»'), ('"', '"'), ('“', '”')];
+
+/// Сущности сообщения в порядке появления (без повторов):
+/// - @упоминания
+/// - фразы в кавычках («...», "...", “...”) до 60 символов
+/// - подряд идущие слова с заглавной буквы не в начале предложения ("Новый Орлеан")
+/// - имена из справочника (поиск без учёта регистра, форма из справочника)
+fn extract_entities(text: &str, gazetteer: &[String]) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+
+    for word in text.split_whitespace() {
+        if let Some(name) = word.strip_prefix('@') {
+            let name: String = name.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+            if !name.is_empty() {
+                push_unique(&mut found, format!("@{}", name));
+            }
+        }
+    }
+
+    for &(open, close) in QUOTES {
+        let mut rest = text;
+        while let Some(start) = rest.find(open) {
+            let after = &rest[start + open.len_utf8()..];
+            let Some(end) = after.find(close) else { break };
+            let phrase = after[..end].trim();
+            if phrase.chars().count() <= 60 {
+                push_unique(&mut found, phrase.to_string());
+            }
+            rest = &after[end + close.len_utf8()..];
+        }
+    }
+
+    // Имена ищутся отдельно, чтобы не дублировать слова из фраз в кавычках
+    let mut names: Vec<String> = Vec::new();
+    let mut run: Vec<&str> = Vec::new();
+    let mut sentence_start = true;
+    for raw in text.split_whitespace() {
+        let word = raw.trim_matches(|c: char| !c.is_alphanumeric());
+        let capitalized = word.chars().next().is_some_and(|c| c.is_uppercase()) && word.chars().count() > 1;
+        if capitalized && !sentence_start && !raw.starts_with('@') {
+            run.push(word);
+        } else if !run.is_empty() {
+            names.push(run.join(" "));
+            run.clear();
+        }
+        // Запятая и прочая пунктуация внутри предложения разрывают цепочку имён
+        if !run.is_empty() && raw.ends_with(|c: char| !c.is_alphanumeric()) {
+            names.push(run.join(" "));
+            run.clear();
+        }
+        sentence_start = raw.ends_with(['.', '!', '?', '…', ':']);
+    }
+    if !run.is_empty() {
+        names.push(run.join(" "));
+    }
+    let quoted: Vec<String> = found.iter().map(|e| e.to_lowercase()).collect();
+    for name in names {
+        let lower = name.to_lowercase();
+        if !quoted.iter().any(|q| q.contains(&lower)) {
+            push_unique(&mut found, name);
+        }
+    }
+
+    let text_lower = text.to_lowercase();
+    for name in gazetteer {
+        if !name.is_empty() && text_lower.contains(&name.to_lowercase()) {
+            push_unique(&mut found, name.clone());
+        }
+    }
+    found
+}
+
+fn push_unique(found: &mut Vec<String>, entity: String) {
+    let lower = entity.to_lowercase();
+    if !entity.is_empty() && !found.iter().any(|e| e.to_lowercase() == lower) {
+        found.push(entity);
+    }
+}
+
+// ── Оценка связанности ──
+
+/// Веса компонентов relatedness; итог нормируется на сумму весов → [0, 1]
+#[derive(Clone, Copy)]
+struct RelatednessWeights {
+    topic: f64,
+    entities: f64,
+    indicators: f64,
+    recency: f64,
+}
+
+impl Default for RelatednessWeights {
+    fn default() -> Self {
+        Self { topic: 0.4, entities: 0.3, indicators: 0.2, recency: 0.1 }
+    }
+}
+
+impl RelatednessWeights {
+    /// Ключи: topic, entities, indicators, recency
+    fn with_overrides(self, weights: HashMap<String, f64>) -> Result<Self, String> {
+        let mut result = self;
+        for (key, weight) in weights {
+            if !(weight.is_finite() && weight >= 0.0) {
+                return Err(format!("Некорректный вес '{}': {}", key, weight));
+            }
+            let slot = match key.as_str() {
+                "topic" => &mut result.topic,
+                "entities" => &mut result.entities,
+                "indicators" => &mut result.indicators,
+                "recency" => &mut result.recency,
+                _ => return Err(format!("Неизвестный компонент связанности: {}", key)),
+            };
+            *slot = weight;
+        }
+        if result.topic + result.entities + result.indicators + result.recency <= 0.0 {
+            return Err("Сумма весов должна быть > 0".to_string());
+        }
+        Ok(result)
+    }
+
+    fn to_map(self) -> HashMap<String, f64> {
+        HashMap::from([
+            ("topic".to_string(), self.topic),
+            ("entities".to_string(), self.entities),
+            ("indicators".to_string(), self.indicators),
+            ("recency".to_string(), self.recency),
+        ])
+    }
+}
+
+/// Порог is_related по умолчанию: с весами по умолчанию его проходит любое
+/// одно совпадение (слово темы, сущность или маркер), но не одна свежесть
+pub(crate) const RELATED_THRESHOLD: f64 = 0.12;
+
+/// Компоненты в [0, 1] (слова сравниваются по основам):
+/// - topic: тема целиком — 1, иначе 0.5 + 0.25 × доля совпавших основ темы
+///   + 0.25 × Jaccard основ темы и текста (0 без совпадений)
+/// - entities: 0.5 за каждую упомянутую сущность, не больше 1
+/// - indicators: 1 при контекстном маркере
+/// - recency: 1 − простой / таймаут
+fn relatedness(thread: &Thread, text_lower: &str, marker: bool, weights: &RelatednessWeights, now: DateTime<Utc>) -> f64 {
+    if thread.is_expired(now) {
+        return 0.0;
+    }
+    let text_stems = match_stems(text_lower);
+    let topic_lower = thread.topic.to_lowercase();
+    let topic = if !topic_lower.is_empty() && text_lower.contains(&topic_lower) {
+        1.0
+    } else {
+        let topic_stems = match_stems(&topic_lower);
+        match overlap(&topic_stems, &text_stems) {
+            (0, _) => 0.0,
+            (shared, jaccard) => 0.5 + 0.25 * shared as f64 / topic_stems.len() as f64 + 0.25 * jaccard,
+        }
+    };
+    let matched = thread
+        .entities
+        .iter()
+        .filter(|e| entity_mentioned(e, text_lower, &text_stems))
+        .count();
+    let entities = (matched as f64 * 0.5).min(1.0);
+    let indicators = if marker { 1.0 } else { 0.0 };
+    let idle = (now - thread.last_active).num_seconds() as f64;
+    let recency = if thread.timeout_secs > 0 {
+        (1.0 - idle / thread.timeout_secs as f64).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let total = weights.topic + weights.entities + weights.indicators + weights.recency;
+    (weights.topic * topic + weights.entities * entities + weights.indicators * indicators + weights.recency * recency)
+        / total
+}
+
+// ── Контекстные индикаторы (RU) ──
+
+const CONTEXT_INDICATORS: &[&str] = &[
+    "помнишь", "как мы говорили", "в той же теме",
+    "продолжим", "вернёмся к", "насчёт того",
+    "по поводу", "как я говорил", "об этом же",
+];
+
+/// Архивных нитей в памяти по умолчанию
+pub(crate) const MAX_ARCHIVED: usize = 20;
+/// Прежний JSONL-архив (импортируется в хранилище)
+pub(crate) const ARCHIVE_FILE: &str = "archived_threads.jsonl";
+pub(crate) const ARCHIVE_NAMESPACE: &str = "threads.archive";
+/// Общее состояние нитей (shared=True)
+pub(crate) const STATE_NAMESPACE: &str = "threads.state";
+const SHARED_NEEDS_STORE: &str = "shared=True требует data_dir или storage";
+/// get_context по умолчанию: обменов и длина реплик (символов)
+pub(crate) const CONTEXT_EXCHANGES: usize = 3;
+pub(crate) const USER_PREVIEW_CHARS: usize = 60;
+pub(crate) const ASSISTANT_PREVIEW_CHARS: usize = 80;
+/// Лимит сообщений нити по умолчанию
+pub(crate) const MAX_THREAD_MESSAGES: usize = 100;
+/// Длина сжатого содержания без компрессора (символов, хвост)
+const SUMMARY_MAX_CHARS: usize = 600;
+/// Бюджет сжатого содержания при компрессоре (токенов)
+const SUMMARY_MAX_TOKENS: usize = 150;
+/// Сколько последних реплик учитывает дрейф темы
+const DRIFT_WINDOW: usize = 3;
+/// Минимум значимых слов в сообщении для автоопределения под-темы
+const DRIFT_MIN_WORDS: usize = 3;
+/// Сколько последних событий хранит хронология
+const MAX_TIMELINE: usize = 500;
+const TIMELINE_PREVIEW_CHARS: usize = 60;
+/// Сколько сущностей накапливает одна нить
+const MAX_ENTITIES: usize = 50;
+
+// ── PyO3 класс ──
+
+#[pyclass(frozen)]
+pub struct ThreadTracker {
+    timeout_secs: i64,
+    /// Сколько нитей может быть открыто одновременно
+    max_open: usize,
+    /// Сколько последних сообщений хранить в архиве (None — все)
+    archive_messages: Option<usize>,
+    /// Сколько сообщений держит нить; сверх — старшие сжимаются в summary
+    max_thread_messages: usize,
+    /// Сколько архивных нитей держать в памяти
+    max_archived: usize,
+    /// Хранилище вытесненных архивных нитей (None — вытесненные теряются)
+    spill: Option<Spill>,
+    /// Общее состояние для процессов с тем же хранилищем (None — только в процессе)
+    shared: Option<SharedCell>,
+    threads: RwLock<Threads>,
+    context_ac: AhoCorasick,
+    /// Справочник известных имён (люди, места, проекты)
+    gazetteer: RwLock<Vec<String>>,
+    /// Справочники EntityRecognizer (общие с Python-объектом)
+    recognizer: RwLock<Option<SharedRecognizer>>,
+    /// Сопоставлять текст и в транслите
+    translit: AtomicBool,
+    weights: RwLock<RelatednessWeights>,
+    /// Порог дрейфа для автоматической под-темы (None — выключено)
+    subtopic_drift: RwLock<Option<f64>>,
+    /// Компрессор и бюджет для авто-суммирования нитей при архивации
+    summarizer: RwLock<Option<(Py<ContextCompressor>, usize)>>,
+    on_open: RwLock<Option<PyObject>>,
+    on_close: RwLock<Option<PyObject>>,
+    /// EventBus и user_id для событий (user_id задаёт MultiThreadTracker)
+    bus: RwLock<Option<(SharedBus, Option<String>)>>,
+    /// События, ожидающие вызова колбэков (вызываются после снятия блокировок)
+    pending: Mutex<Vec<ThreadEvent>>,
+    /// Предупреждения о сбоях архива — выдаются вместе с событиями
+    warnings: Mutex<Vec<String>>,
+    timeline: Mutex<VecDeque<TimelineEvent>>,
+}
+
+fn archive_thread(mut thread: Thread, keep: Option<usize>, summary: Option<String>, history: &mut Vec<ArchivedThread>) {
+    let duration = (Utc::now() - thread.started).num_seconds() as f64;
+    let message_count = thread.message_count();
+    if let Some(keep) = keep {
+        thread.messages.drain(..thread.messages.len().saturating_sub(keep));
+    }
+    // Без авто-суммирования в архив идёт накопленное сжатое содержание
+    let summary = summary.or(thread.summary);
+    history.push(ArchivedThread {
+        id: thread.id,
+        topic: thread.topic,
+        entities: thread.entities,
+        started: thread.started,
+        duration_secs: duration,
+        message_count,
+        messages: thread.messages,
+        summary,
+    });
+}
+
+/// Вытесненные архивные нити в Storage: запись на нить, ключ — id
+pub(crate) struct Spill {
+    store: Arc<Store>,
+    namespace: String,
+}
+
+impl Spill {
+    /// Архив в storage, иначе в хранилище каталога data_dir; без обоих — None
+    pub(crate) fn locate(
+        storage: Option<Arc<Store>>,
+        data_dir: Option<&Path>,
+        namespace: String,
+        legacy: Option<PathBuf>,
+        warnings: &mut Vec<String>,
+    ) -> Option<Self> {
+        let store = match (storage, data_dir) {
+            (Some(store), _) => Ok(store),
+            (None, Some(dir)) => storage::open(dir),
+            (None, None) => return None,
+        };
+        Self::open(store, namespace, legacy, warnings)
+    }
+
+    /// Архив в пространстве namespace; legacy — прежний JSONL-файл для импорта.
+    /// Недоступное хранилище — предупреждение и архив только в памяти
+    fn open(
+        store: Result<Arc<Store>, String>,
+        namespace: String,
+        legacy: Option<PathBuf>,
+        warnings: &mut Vec<String>,
+    ) -> Option<Self> {
+        let store = match store {
+            Ok(store) => store,
+            Err(e) => {
+                warnings.push(format!("Архив нитей недоступен, вытесненные нити не сохранятся: {}", e));
+                return None;
+            }
+        };
+        let spill = Self { store, namespace };
+        if let Some(legacy) = legacy.filter(|path| path.exists()) {
+            spill.import(&legacy, warnings);
+        }
+        Some(spill)
+    }
+
+    fn import(&self, legacy: &Path, warnings: &mut Vec<String>) {
+        match self.store.keys(&self.namespace) {
+            Ok(keys) if keys.is_empty() => {}
+            // Уже перенесено (или хранилище нечитаемо — тогда не трогаем файл)
+            _ => return,
+        }
+        match self.append(&read_jsonl(legacy)) {
+            Ok(()) => {
+                let _ = std::fs::rename(legacy, legacy.with_extension("jsonl.migrated"));
+            }
+            Err(e) => warnings.push(format!("Архив нитей {} не перенесён в хранилище: {}", legacy.display(), e)),
+        }
+    }
+
+    fn key(thread_id: u64) -> String {
+        format!("{:020}", thread_id)
+    }
+
+    fn append(&self, threads: &[ArchivedThread]) -> rusqlite::Result<()> {
+        let records: Records = threads
+            .iter()
+            .filter_map(|t| serde_json::to_vec(t).ok().map(|data| (Self::key(t.id), data)))
+            .collect();
+        self.store.append(&self.namespace, &records)
+    }
+
+    /// Нити в порядке вытеснения (битые записи пропускаются)
+    fn read(&self) -> rusqlite::Result<Vec<ArchivedThread>> {
+        let records = self.store.scan(&self.namespace)?;
+        Ok(records.iter().filter_map(|(_, data)| serde_json::from_slice(data).ok()).collect())
+    }
+
+    fn max_id(&self) -> u64 {
+        let keys = self.store.keys(&self.namespace).unwrap_or_default();
+        keys.iter().filter_map(|k| k.parse().ok()).max().unwrap_or(0)
+    }
+
+    /// Забирает нить из архива
+    fn take(&self, thread_id: u64) -> rusqlite::Result<Option<ArchivedThread>> {
+        let key = Self::key(thread_id);
+        let Some(data) = self.store.get(&self.namespace, &key)? else {
+            return Ok(None);
+        };
+        self.store.delete(&self.namespace, &key)?;
+        Ok(serde_json::from_slice(&data).ok())
+    }
+}
+
+/// Нити из прежнего JSONL-файла архива в порядке записи (битые строки пропускаются)
+fn read_jsonl(path: &Path) -> Vec<ArchivedThread> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+    std::io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+/// Изменяемое состояние трекера. В режиме shared держит блокировку записи
+/// хранилища и записывает в него состояние при освобождении
+struct StateGuard<'a> {
+    tracker: &'a ThreadTracker,
+    threads: RwLockWriteGuard<'a, Threads>,
+    write: Option<SharedWrite<'a>>,
+}
+
+impl Deref for StateGuard<'_> {
+    type Target = Threads;
+
+    fn deref(&self) -> &Threads {
+        &self.threads
+    }
+}
+
+impl DerefMut for StateGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Threads {
+        &mut self.threads
+    }
+}
+
+impl Drop for StateGuard<'_> {
+    fn drop(&mut self) {
+        let Some(write) = self.write.take() else {
+            return;
+        };
+        let result = serde_json::to_vec(&*self.threads)
+            .map_err(|e| e.to_string())
+            .and_then(|data| write.commit(&data).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            self.tracker.warn(format!("Общее состояние нитей не записано, изменение осталось в процессе: {}", e));
+        }
+    }
+}
+
+impl ThreadTracker {
+    pub(crate) fn new(
+        timeout_secs: i64,
+        max_open: usize,
+        archive_messages: Option<usize>,
+        max_thread_messages: usize,
+        max_archived: usize,
+        data_dir: Option<&str>,
+    ) -> Self {
+        let mut warnings = Vec::new();
+        let data_dir = data_dir.map(Path::new);
+        let legacy = data_dir.map(|dir| dir.join(ARCHIVE_FILE));
+        let spill = Spill::locate(None, data_dir, ARCHIVE_NAMESPACE.to_string(), legacy, &mut warnings);
+        Self::with_spill(timeout_secs, max_open, archive_messages, max_thread_messages, max_archived, spill, warnings)
+    }
+
+    pub(crate) fn with_spill(
+        timeout_secs: i64,
+        max_open: usize,
+        archive_messages: Option<usize>,
+        max_thread_messages: usize,
+        max_archived: usize,
+        spill: Option<Spill>,
+        warnings: Vec<String>,
+    ) -> Self {
+        // id продолжают нумерацию сохранённых нитей, чтобы не пересекаться с ними
+        let next_id = spill.as_ref().map_or(0, Spill::max_id);
+        Self {
+            timeout_secs,
+            max_open: max_open.max(1),
+            archive_messages,
+            max_thread_messages: max_thread_messages.max(2),
+            max_archived,
+            spill,
+            shared: None,
+            threads: RwLock::new(Threads { next_id, ..Threads::default() }),
+            context_ac: AhoCorasick::new(CONTEXT_INDICATORS).unwrap(),
+            gazetteer: RwLock::new(Vec::new()),
+            recognizer: RwLock::new(None),
+            translit: AtomicBool::new(true),
+            weights: RwLock::new(RelatednessWeights::default()),
+            subtopic_drift: RwLock::new(None),
+            summarizer: RwLock::new(None),
+            on_open: RwLock::new(None),
+            on_close: RwLock::new(None),
+            bus: RwLock::new(None),
+            pending: Mutex::new(Vec::new()),
+            warnings: Mutex::new(warnings),
+            timeline: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Состояние в пространстве namespace хранилища архива — общее для процессов
+    /// с тем же хранилищем; без хранилища трекер остаётся локальным
+    pub(crate) fn with_shared(mut self, namespace: String) -> Self {
+        self.shared = self.spill.as_ref().map(|spill| SharedCell::new(Arc::clone(&spill.store), namespace));
+        self
+    }
+}
+
+#[pymethods]
+impl ThreadTracker {
+    /// data_dir — каталог хранилища для архивных нитей сверх max_archived
+    /// (создаётся при необходимости), storage — общее хранилище вместо него;
+    /// shared=True — нити общие для процессов с тем же хранилищем (runtime.shared);
+    /// незаданные параметры берутся из раздела threads config
+    #[new]
+    #[pyo3(signature = (
+        timeout_secs=None, max_open=None, archive_messages=None, max_thread_messages=None,
+        max_archived=None, data_dir=None, *, storage=None, shared=None, config=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        timeout_secs: Option<i64>,
+        max_open: Option<usize>,
+        archive_messages: Option<usize>,
+        max_thread_messages: Option<usize>,
+        max_archived: Option<usize>,
+        data_dir: Option<String>,
+        storage: Option<PyRef<'_, Storage>>,
+        shared: Option<bool>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let config = config::settings(config);
+        let threads = &config.threads;
+        let data_dir = data_dir.or_else(|| config.threads_dir()).map(PathBuf::from);
+        let shared = shared.unwrap_or(config.runtime.shared);
+        if shared && data_dir.is_none() && storage.is_none() {
+            return Err(PyValueError::new_err(SHARED_NEEDS_STORE));
+        }
+        let legacy = data_dir.as_ref().map(|dir| dir.join(ARCHIVE_FILE));
+        let mut warnings = Vec::new();
+        let spill = Spill::locate(
+            storage.map(|s| s.shared()),
+            data_dir.as_deref(),
+            ARCHIVE_NAMESPACE.to_string(),
+            legacy,
+            &mut warnings,
+        );
+        let tracker = Self::with_spill(
+            timeout_secs.unwrap_or(threads.timeout_secs),
+            max_open.unwrap_or(threads.max_open),
+            archive_messages.or(threads.archive_messages),
+            max_thread_messages.unwrap_or(threads.max_thread_messages),
+            max_archived.unwrap_or(threads.max_archived),
+            spill,
+            warnings,
+        );
+        Ok(if shared { tracker.with_shared(STATE_NAMESPACE.to_string()) } else { tracker })
+    }
+
+    /// Открывает новую нить и делает её активной; прежние остаются открытыми.
+    /// timeout_secs — собственный таймаут нити (по умолчанию — трекера). Возвращает id.
+    #[pyo3(signature = (topic, entities=None, timeout_secs=None))]
+    pub(crate) fn start_thread(&self, topic: &str, entities: Option<Vec<String>>, timeout_secs: Option<i64>) -> u64 {
+        let mut threads = self.state_mut();
+        let id = threads.open_thread(
+            topic.to_string(),
+            entities.unwrap_or_default(),
+            timeout_secs.unwrap_or(self.timeout_secs),
+            Utc::now(),
+        );
+        self.record_open(&threads, id, "started");
+        self.enforce_open_limit(&mut threads);
+        drop(threads);
+        self.emit_events();
+        id
+    }
+
+    /// Добавляет обмен репликами в активную нить (с извлечением сущностей)
+    fn add_message(&self, user_input: &str, response: &str) {
+        let found = self.entities_in(user_input);
+        let mut threads = self.state_mut();
+        if let Some(id) = threads.active {
+            if let Some(thread) = threads.get_mut(id) {
+                thread.push(user_input, response, Utc::now());
+                thread.add_entities(found);
+                self.record_message(id, user_input);
+                self.compact(thread);
+            }
+        }
+    }
+
+    /// Закрывает просроченные нити и направляет сообщение в самую подходящую
+    /// открытую нить (при равенстве — в активную); если подходящих нет —
+    /// в активную, а без неё — в новую
+    pub(crate) fn update(&self, user_input: &str, response: &str) {
+        let now = Utc::now();
+        let found = self.entities_in(user_input);
+        let mut threads = self.state_mut();
+        self.expire(&mut threads, now);
+
+        let text_lower = self.match_text(user_input);
+        let marker = self.context_ac.is_match(&text_lower);
+        let active = threads.active;
+        let best = threads
+            .open
+            .iter()
+            .map(|t| {
+                let bonus = usize::from(marker && Some(t.id) == active);
+                (t.id, t.relevance(&text_lower) + bonus, Some(t.id) == active)
+            })
+            .filter(|(_, score, _)| *score > 0)
+            .max_by_key(|(_, score, is_active)| (*score, *is_active))
+            .map(|(id, _, _)| id);
+
+        // Сообщение без совпадений остаётся в активной нити — проверяем дрейф темы
+        if let (None, Some(id), Some(threshold)) = (best, active, *self.subtopic_drift.read()) {
+            if stems(user_input).len() >= DRIFT_MIN_WORDS {
+                if let Some(thread) = threads.get_mut(id) {
+                    if !marker && thread.drift(user_input) >= threshold {
+                        let subtopic: String = user_input.chars().take(50).collect();
+                        // Автоматическая под-тема сменяет предыдущую на том же уровне
+                        thread.subtopics.pop();
+                        thread.subtopics.push(subtopic.clone());
+                        self.record("subtopic", id, subtopic);
+                    }
+                }
+            }
+        }
+
+        let id = match best.or(active) {
+            Some(id) => id,
+            None => {
+                let id = threads.open_thread(user_input.chars().take(50).collect(), Vec::new(), self.timeout_secs, now);
+                self.record_open(&threads, id, "auto");
+                id
+            }
+        };
+        threads.active = Some(id);
+        if let Some(thread) = threads.get_mut(id) {
+            thread.push(user_input, response, now);
+            thread.add_entities(found);
+            self.record_message(id, user_input);
+            self.compact(thread);
+        }
+        self.enforce_open_limit(&mut threads);
+        drop(threads);
+        self.emit_events();
+    }
+
+    /// Открывает вложенную под-тему активной нити
+    fn start_subtopic(&self, topic: &str) -> PyResult<()> {
+        let mut threads = self.state_mut();
+        let id = threads.active.ok_or_else(|| MemoryError::new_err("Нет активной нити"))?;
+        if let Some(thread) = threads.get_mut(id) {
+            thread.subtopics.push(topic.to_string());
+            self.record("subtopic", id, topic.to_string());
+        }
+        Ok(())
+    }
+
+    /// Закрывает текущую под-тему (возврат на уровень выше); возвращает её
+    fn end_subtopic(&self) -> Option<String> {
+        let mut threads = self.state_mut();
+        let id = threads.active?;
+        threads.get_mut(id)?.subtopics.pop()
+    }
+
+    /// [тема, под-тема, ...] активной нити
+    fn get_topic_path(&self) -> Vec<String> {
+        let threads = self.state();
+        threads
+            .active()
+            .map(|t| std::iter::once(t.topic.clone()).chain(t.subtopics.iter().cloned()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Насколько разговор ушёл от начала активной нити, [0, 1]: сравнение
+    /// слов и сущностей последних window сообщений с первыми window и темой
+    #[pyo3(signature = (window=5))]
+    fn detect_drift(&self, window: usize) -> f64 {
+        self.state().active().map_or(0.0, |t| t.segment_drift(window))
+    }
+
+    /// detect_drift(window) >= threshold — пора закрыть нить и открыть новую
+    #[pyo3(signature = (window=5, threshold=0.8))]
+    fn has_drifted(&self, window: usize, threshold: f64) -> bool {
+        self.detect_drift(window) >= threshold
+    }
+
+    /// Дрейф темы для текста относительно активной нити, [0, 1]
+    fn drift_score(&self, text: &str) -> f64 {
+        self.state().active().map_or(0.0, |t| t.drift(text))
+    }
+
+    /// Автоопределение под-тем в update(): сообщение без совпадений с нитями
+    /// и с дрейфом >= threshold открывает под-тему. None — выключить.
+    #[pyo3(signature = (threshold=None))]
+    fn set_subtopic_detection(&self, threshold: Option<f64>) -> PyResult<()> {
+        if threshold.is_some_and(|t| !(0.0..=1.0).contains(&t)) {
+            return Err(PyValueError::new_err("Порог дрейфа должен быть в [0, 1]"));
+        }
+        *self.subtopic_drift.write() = threshold;
+        Ok(())
+    }
+
+    /// Сущности, которые были бы извлечены из текста
+    fn extract_entities(&self, text: &str) -> Vec<String> {
+        self.entities_in(text)
+    }
+
+    /// Заменяет справочник известных имён
+    fn set_gazetteer(&self, names: Vec<String>) {
+        *self.gazetteer.write() = names;
+    }
+
+    /// Подключает EntityRecognizer (None — отключить); его справочники
+    /// остаются живыми: add_gazetteer сразу виден трекеру
+    #[pyo3(signature = (recognizer=None))]
+    fn set_entity_recognizer(&self, recognizer: Option<PyRef<EntityRecognizer>>) {
+        self.set_recognizer(recognizer.map(|r| r.shared()));
+    }
+
+    /// Сопоставлять темы и маркеры и с транслитом ("pro pereezd"); по умолчанию включено
+    fn set_translit_matching(&self, enabled: bool) {
+        self.translit.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Сущности активной нити (заданные и накопленные)
+    pub(crate) fn get_entities(&self) -> Vec<String> {
+        self.state().active().map(|t| t.entities.clone()).unwrap_or_default()
+    }
+
+    /// Транскрипт нити (открытой или архивной) для отправки пользователю:
+    /// format="json" — объект с id, topic, entities, started, archived, summary, messages;
+    /// format="markdown" — читаемый текст с временем реплик
+    #[pyo3(signature = (thread_id, format="json"))]
+    fn export_thread(&self, thread_id: u64, format: &str) -> PyResult<String> {
+        let export = self
+            .export(thread_id)
+            .ok_or_else(|| MemoryError::new_err(format!("Нет нити с id {}", thread_id)))?;
+        match format {
+            "json" => Ok(export.to_json()),
+            "markdown" | "md" => Ok(export.to_markdown()),
+            _ => Err(PyValueError::new_err(format!("Неизвестный формат экспорта: {}", format))),
+        }
+    }
+
+    /// Архивирует просроченные нити сразу, не дожидаясь следующего сообщения
+    /// (для планировщика); True, если что-то было архивировано
+    pub(crate) fn expire_if_idle(&self) -> bool {
+        let mut threads = self.state_mut();
+        let before = threads.open.len();
+        self.expire(&mut threads, Utc::now());
+        let expired = threads.open.len() < before;
+        drop(threads);
+        self.emit_events();
+        expired
+    }
+
+    /// Секунд до ближайшего таймаута среди открытых нитей (None — нитей нет)
+    fn seconds_until_timeout(&self) -> Option<f64> {
+        let now = Utc::now();
+        self.state()
+            .open
+            .iter()
+            .map(|t| t.seconds_left(now))
+            .min_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+    }
+
+    /// Делает открытую нить активной
+    fn switch_to(&self, thread_id: u64) -> PyResult<()> {
+        let mut threads = self.state_mut();
+        let thread = threads
+            .get_mut(thread_id)
+            .ok_or_else(|| MemoryError::new_err(format!("Нет открытой нити с id {}", thread_id)))?;
+        thread.last_active = Utc::now();
+        threads.active = Some(thread_id);
+        Ok(())
+    }
+
+    /// Таймаут бездействия для конкретной нити
+    fn set_thread_timeout(&self, thread_id: u64, timeout_secs: i64) -> PyResult<()> {
+        let mut threads = self.state_mut();
+        let thread = threads
+            .get_mut(thread_id)
+            .ok_or_else(|| MemoryError::new_err(format!("Нет открытой нити с id {}", thread_id)))?;
+        thread.timeout_secs = timeout_secs;
+        Ok(())
+    }
+
+    /// Открытые нити: [(id, topic, message_count, is_active)]
+    pub(crate) fn list_threads(&self) -> Vec<(u64, String, usize, bool)> {
+        let threads = self.state();
+        threads
+            .open
+            .iter()
+            .map(|t| (t.id, t.topic.clone(), t.message_count(), threads.active == Some(t.id)))
+            .collect()
+    }
+
+    pub(crate) fn get_current_thread_id(&self) -> Option<u64> {
+        self.state().active
+    }
+
+    /// Насколько текст связан с активной нитью, [0, 1]: взвешенная сумма
+    /// совпадения темы, сущностей, контекстных маркеров и свежести нити.
+    /// 0 — нет активной нити или она просрочена.
+    pub(crate) fn relatedness(&self, text: &str) -> f64 {
+        let threads = self.state();
+        let Some(thread) = threads.active() else {
+            return 0.0;
+        };
+        let text_lower = self.match_text(text);
+        let marker = self.context_ac.is_match(&text_lower);
+        relatedness(thread, &text_lower, marker, &self.weights.read(), Utc::now())
+    }
+
+    /// relatedness(text) >= threshold
+    #[pyo3(signature = (text, threshold=RELATED_THRESHOLD))]
+    fn is_related(&self, text: &str, threshold: f64) -> bool {
+        let score = self.relatedness(text);
+        score > 0.0 && score >= threshold
+    }
+
+    /// Меняет веса relatedness (частично): {"topic": 0.5, "recency": 0.0}
+    fn set_relatedness_weights(&self, weights: HashMap<String, f64>) -> PyResult<()> {
+        let mut current = self.weights.write();
+        *current = current.with_overrides(weights).map_err(PyValueError::new_err)?;
+        Ok(())
+    }
+
+    fn get_relatedness_weights(&self) -> HashMap<String, f64> {
+        self.weights.read().to_map()
+    }
+
+    /// Контекст активной нити для промпта: тема, сущности, сжатое содержание
+    /// и последние max_exchanges обменов. Реплики обрезаются до user_chars /
+    /// assistant_chars символов; assistant_chars=0 — без ответов ассистента.
+    #[pyo3(signature = (max_exchanges=CONTEXT_EXCHANGES, user_chars=USER_PREVIEW_CHARS, assistant_chars=ASSISTANT_PREVIEW_CHARS))]
+    pub(crate) fn get_context(&self, max_exchanges: usize, user_chars: usize, assistant_chars: usize) -> Option<String> {
+        let threads = self.state();
+        let thread = threads.active()?;
+        if thread.is_expired(Utc::now()) {
+            return None;
+        }
+
+        let mut parts = vec![format!("Текущая тема: {}", thread.topic_path())];
+
+        if !thread.entities.is_empty() {
+            let entities_str: Vec<&str> = thread.entities.iter().take(5).map(|s| s.as_str()).collect();
+            parts.push(format!("Упоминается: {}", entities_str.join(", ")));
+        }
+
+        if let Some(summary) = &thread.summary {
+            parts.push(format!("Ранее: {}", summary));
+        }
+
+        let recent_count = thread.messages.len().min(max_exchanges);
+        if recent_count > 0 {
+            parts.push("\nПоследние сообщения:".to_string());
+            let start = thread.messages.len() - recent_count;
+            for msg in &thread.messages[start..] {
+                let preview: String = msg.user.chars().take(user_chars).collect();
+                parts.push(format!("  Пользователь: {}", preview));
+                if assistant_chars > 0 && !msg.assistant.is_empty() {
+                    let reply: String = msg.assistant.chars().take(assistant_chars).collect();
+                    parts.push(format!("  Ассистент: {}", reply));
+                }
+            }
+        }
+
+        Some(parts.join("\n"))
+    }
+
+    fn has_active_thread(&self) -> bool {
+        let threads = self.state();
+        threads.active().is_some_and(|t| !t.is_expired(Utc::now()))
+    }
+
+    pub(crate) fn get_current_topic(&self) -> Option<String> {
+        let threads = self.state();
+        threads.active().map(|t| t.topic.clone())
+    }
+
+    #[pyo3(signature = (limit=5))]
+    fn get_past_threads(&self, limit: usize) -> Vec<(String, f64, usize)> {
+        let threads = self.state();
+        let history = &threads.history;
+        let start = if history.len() > limit {
+            history.len() - limit
+        } else {
+            0
+        };
+        history[start..]
+            .iter()
+            .map(|t| (t.topic.clone(), t.duration_secs, t.message_count))
+            .collect()
+    }
+
+    /// Транскрипт архивной нити: [(user, assistant, timestamp RFC 3339)]
+    fn get_archived_thread(&self, thread_id: u64) -> Option<Vec<(String, String, String)>> {
+        let thread = self.find_archived(thread_id)?;
+        Some(
+            thread
+                .messages
+                .iter()
+                .map(|m| (m.user.clone(), m.assistant.clone(), m.timestamp.to_rfc3339()))
+                .collect(),
+        )
+    }
+
+    /// Поиск по открытым и архивным нитям (темы, сущности, сообщения):
+    /// [(id, topic, score, archived)], лучшие первыми
+    #[pyo3(signature = (query, limit=5))]
+    fn search_threads(&self, query: &str, limit: usize) -> Vec<(u64, String, usize, bool)> {
+        let query = stems(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let threads = self.state();
+        let open = threads
+            .open
+            .iter()
+            .map(|t| (t.id, t.topic.clone(), search_score(&query, &t.topic, &t.entities, &t.messages), false));
+        let spilled = self.spilled();
+        let archived = threads
+            .history
+            .iter()
+            .rev()
+            .chain(spilled.iter())
+            .map(|t| (t.id, t.topic.clone(), search_score(&query, &t.topic, &t.entities, &t.messages), true));
+        let mut found: Vec<(u64, String, usize, bool)> = open.chain(archived).filter(|r| r.2 > 0).collect();
+        found.sort_by_key(|r| std::cmp::Reverse(r.2));
+        found.truncate(limit);
+        found
+    }
+
+    /// Архивные нити, к которым может относиться текст: [(id, topic, score)],
+    /// лучшие первыми, при равенстве — более свежие. Контекстный маркер
+    /// ("вернёмся к", "помнишь") добавляет 1 всем нитям, так что без
+    /// совпадений по теме предлагаются последние закрытые.
+    #[pyo3(signature = (text, limit=3))]
+    fn find_resumable(&self, text: &str, limit: usize) -> Vec<(u64, String, usize)> {
+        let text_lower = self.match_text(text);
+        let bonus = usize::from(self.context_ac.is_match(&text_lower));
+        let threads = self.state();
+        let spilled = self.spilled();
+        let mut found: Vec<(u64, String, usize)> = threads
+            .history
+            .iter()
+            .rev()
+            .chain(spilled.iter())
+            .map(|t| (t.id, t.topic.clone(), relevance(&t.topic, &t.entities, &text_lower) + bonus))
+            .filter(|(_, _, score)| *score > 0)
+            .collect();
+        // Стабильная сортировка сохраняет порядок "свежие первыми" при равных очках
+        found.sort_by_key(|(_, _, score)| std::cmp::Reverse(*score));
+        found.truncate(limit);
+        found
+    }
+
+    /// Возвращает архивную нить в открытые (с сообщениями и сущностями)
+    /// и делает её активной
+    fn resume(&self, thread_id: u64) -> PyResult<()> {
+        let mut threads = self.state_mut();
+        let in_memory = threads.history.iter().position(|t| t.id == thread_id).map(|pos| threads.history.remove(pos));
+        let archived = in_memory
+            .or_else(|| self.take_spilled(thread_id))
+            .ok_or_else(|| MemoryError::new_err(format!("Нет архивной нити с id {}", thread_id)))?;
+        let mut thread = Thread::new(archived.id, archived.topic, archived.entities, self.timeout_secs, Utc::now());
+        thread.started = archived.started;
+        thread.compacted = archived.message_count - archived.messages.len();
+        thread.messages = archived.messages;
+        thread.summary = archived.summary;
+        threads.open.push(thread);
+        threads.active = Some(thread_id);
+        self.record_open(&threads, thread_id, "resumed");
+        self.enforce_open_limit(&mut threads);
+        drop(threads);
+        self.emit_events();
+        Ok(())
+    }
+
+    /// Краткое содержание активной нити в пределах max_tokens
+    #[pyo3(signature = (compressor, max_tokens=120))]
+    fn summarize_current(&self, compressor: PyRef<'_, ContextCompressor>, max_tokens: usize) -> Option<String> {
+        let threads = self.state();
+        threads.active().map(|t| t.summarize(&compressor, max_tokens))
+    }
+
+    /// Включает (compressor) или выключает (None) суммирование нитей при архивации
+    #[pyo3(signature = (compressor, max_tokens=120))]
+    fn set_auto_summary(&self, compressor: Option<Py<ContextCompressor>>, max_tokens: usize) {
+        *self.summarizer.write() = compressor.map(|c| (c, max_tokens));
+    }
+
+    /// Краткое содержание архивной нити (если было авто-суммирование)
+    fn get_thread_summary(&self, thread_id: u64) -> Option<String> {
+        let threads = self.state();
+        threads.history.iter().find(|t| t.id == thread_id)?.summary.clone()
+    }
+
+    /// Закрывает активную нить (в архив); остальные открытые не трогаются
+    pub(crate) fn end_thread(&self) {
+        let mut threads = self.state_mut();
+        if let Some(thread) = threads.active.and_then(|id| threads.take(id)) {
+            self.archive(thread, "ended", &mut threads.history);
+        }
+        drop(threads);
+        self.emit_events();
+    }
+
+    /// callable(dict) при открытии нити: {"event": "open", "reason", "id", "topic",
+    /// "entities", "message_count", "duration_secs"}; reason — started / auto / resumed.
+    /// None — отключить. Исключения колбэка не прерывают трекер (sys.unraisablehook).
+    #[pyo3(signature = (callback=None))]
+    fn set_on_open(&self, callback: Option<PyObject>) {
+        *self.on_open.write() = callback;
+    }
+
+    /// callable(dict) при закрытии нити (тот же формат, "event": "close");
+    /// reason — ended / timeout / evicted
+    #[pyo3(signature = (callback=None))]
+    fn set_on_close(&self, callback: Option<PyObject>) {
+        *self.on_close.write() = callback;
+    }
+
+    /// Публиковать thread_opened / thread_closed в EventBus (None — отключить)
+    #[pyo3(signature = (bus=None))]
+    fn set_event_bus(&self, bus: Option<PyRef<'_, EventBus>>) {
+        self.set_bus(bus.map(|b| b.shared()), None);
+    }
+
+    /// Последние limit событий сессии в хронологическом порядке:
+    /// [(timestamp RFC 3339, kind, thread_id, detail)]. kind — started / auto /
+    /// resumed (открытие, detail — тема), message (detail — превью реплики), subtopic,
+    /// ended / timeout / evicted (архивация, detail — тема)
+    #[pyo3(signature = (limit=50))]
+    fn get_timeline(&self, limit: usize) -> Vec<(String, String, u64, String)> {
+        let timeline = self.timeline.lock();
+        timeline
+            .iter()
+            .skip(timeline.len().saturating_sub(limit))
+            .map(|e| (e.timestamp.to_rfc3339(), e.kind.to_string(), e.thread_id, e.detail.clone()))
+            .collect()
+    }
+
+    /// Статистика: current_thread, open_threads, active_topic, active_age_secs,
+    /// active_messages, archived_threads, avg_duration_secs (по архиву),
+    /// total_messages, messages_per_thread, top_entities [(entity, threads)]
+    fn get_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.stats().to_dict(py)
+    }
+}
+
+/// Снимок статистики трекера
+struct ThreadStats {
+    current_thread: bool,
+    open_threads: usize,
+    active_topic: Option<String>,
+    active_age_secs: Option<f64>,
+    active_messages: usize,
+    archived_threads: usize,
+    avg_duration_secs: f64,
+    total_messages: usize,
+    messages_per_thread: f64,
+    top_entities: Vec<(String, usize)>,
+}
+
+impl ThreadStats {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("current_thread", self.current_thread)?;
+        dict.set_item("open_threads", self.open_threads)?;
+        dict.set_item("active_topic", &self.active_topic)?;
+        dict.set_item("active_age_secs", self.active_age_secs)?;
+        dict.set_item("active_messages", self.active_messages)?;
+        dict.set_item("archived_threads", self.archived_threads)?;
+        dict.set_item("avg_duration_secs", self.avg_duration_secs)?;
+        dict.set_item("total_messages", self.total_messages)?;
+        dict.set_item("messages_per_thread", self.messages_per_thread)?;
+        dict.set_item("top_entities", &self.top_entities)?;
+        Ok(dict)
+    }
+}
+
+const TOP_ENTITIES: usize = 5;
+
+// ── Приватные методы ──
+
+impl ThreadTracker {
+    fn set_recognizer(&self, recognizer: Option<SharedRecognizer>) {
+        *self.recognizer.write() = recognizer;
+    }
+
+    fn set_bus(&self, bus: Option<SharedBus>, user_id: Option<String>) {
+        *self.bus.write() = bus.map(|b| (b, user_id));
+    }
+
+    /// Состояние для чтения; в режиме shared — с изменениями других процессов
+    fn state(&self) -> RwLockReadGuard<'_, Threads> {
+        if let Some(shared) = &self.shared {
+            match shared.refresh() {
+                Ok(Some((version, data))) => {
+                    let mut threads = self.threads.write();
+                    // Копию мог обновить поток этого процесса, пока шло чтение
+                    if shared.accept(version) {
+                        self.replace_state(&mut threads, &data);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => self.warn(format!("Общее состояние нитей не прочитано из {}: {}", shared.path().display(), e)),
+            }
+        }
+        self.threads.read()
+    }
+
+    /// Состояние для изменения; в режиме shared другие процессы ждут
+    /// освобождения (изменения не теряются при одновременной записи)
+    fn state_mut(&self) -> StateGuard<'_> {
+        let mut threads = self.threads.write();
+        let write = self.shared.as_ref().and_then(|shared| match shared.lock() {
+            Ok((write, fresh)) => {
+                if let Some(data) = fresh {
+                    self.replace_state(&mut threads, &data);
+                }
+                Some(write)
+            }
+            Err(e) => {
+                self.warn(format!(
+                    "Общее состояние нитей {} недоступно, изменение останется в процессе: {}",
+                    shared.path().display(),
+                    e
+                ));
+                None
+            }
+        });
+        StateGuard { tracker: self, threads, write }
+    }
+
+    fn replace_state(&self, threads: &mut Threads, data: &[u8]) {
+        match serde_json::from_slice(data) {
+            Ok(state) => *threads = state,
+            Err(e) => self.warn(format!("Общее состояние нитей повреждено, оставлено локальное: {}", e)),
+        }
+    }
+
+    /// Текст для сопоставления: lowercase, с кириллической версией при транслите
+    fn match_text(&self, text: &str) -> String {
+        let lower = text.to_lowercase();
+        if self.translit.load(Ordering::Relaxed) && has_latin(&lower) {
+            let converted = to_cyrillic(&lower);
+            format!("{} {}", lower, converted)
+        } else {
+            lower
+        }
+    }
+
+    /// Сущности сообщения: встроенные эвристики + имена из EntityRecognizer;
+    /// словоформа, найденная справочником, заменяется каноническим именем
+    fn entities_in(&self, text: &str) -> Vec<String> {
+        let mut found = extract_entities(text, &self.gazetteer.read());
+        if let Some(recognizer) = self.recognizer.read().as_ref() {
+            let known = recognizer.read().known(text);
+            found.retain(|f| !known.iter().any(|k| k.text.to_lowercase() == f.to_lowercase()));
+            for entity in known {
+                push_unique(&mut found, entity.canonical);
+            }
+        }
+        found
+    }
+
+    /// Переносит нить в архив (с кратким содержанием, если включено)
+    fn archive(&self, thread: Thread, reason: &'static str, history: &mut Vec<ArchivedThread>) {
+        self.record(reason, thread.id, thread.topic.clone());
+        metrics::inc("threads_closed_total", 1);
+        if self.has_callbacks() {
+            self.pending.lock().push(ThreadEvent::new(&thread, false, reason));
+        }
+        let summary = self
+            .summarizer
+            .read()
+            .as_ref()
+            .map(|(compressor, max_tokens)| thread.summarize(compressor.get(), *max_tokens));
+        archive_thread(thread, self.archive_messages, summary, history);
+        if history.len() > self.max_archived {
+            let excess: Vec<ArchivedThread> = history.drain(..history.len() - self.max_archived).collect();
+            if let Some(spill) = &self.spill {
+                if let Err(e) = spill.append(&excess) {
+                    self.warn(format!("Нити не записаны в архив {}, они потеряны: {}", spill.store.path().display(), e));
+                }
+            }
+        }
+    }
+
+    /// Вытесненные на диск нити, свежие первыми
+    fn spilled(&self) -> Vec<ArchivedThread> {
+        let Some(spill) = &self.spill else {
+            return Vec::new();
+        };
+        match spill.read() {
+            Ok(mut spilled) => {
+                spilled.reverse();
+                spilled
+            }
+            Err(e) => {
+                self.warn(format!("Архив нитей {} не прочитан: {}", spill.store.path().display(), e));
+                Vec::new()
+            }
+        }
+    }
+
+    /// Забирает нить из архива; сбой хранилища — предупреждение, нить не найдена
+    fn take_spilled(&self, thread_id: u64) -> Option<ArchivedThread> {
+        let spill = self.spill.as_ref()?;
+        spill.take(thread_id).unwrap_or_else(|e| {
+            self.warn(format!("Нить {} не извлечена из архива {}: {}", thread_id, spill.store.path().display(), e));
+            None
+        })
+    }
+
+    /// Архивная нить из памяти или с диска
+    fn find_archived(&self, thread_id: u64) -> Option<ArchivedThread> {
+        let in_memory = self.state().history.iter().find(|t| t.id == thread_id).cloned();
+        in_memory.or_else(|| self.spilled().into_iter().find(|t| t.id == thread_id))
+    }
+
+    fn stats(&self) -> ThreadStats {
+        let now = Utc::now();
+        let threads = self.state();
+        let history = &threads.history;
+        let active = threads.active();
+
+        let counts = threads.open.iter().map(|t| t.message_count()).chain(history.iter().map(|t| t.message_count));
+        let total_threads = threads.open.len() + history.len();
+        let total_messages: usize = counts.sum();
+
+        // Сущность считается один раз на нить (без учёта регистра)
+        let mut entity_counts: HashMap<String, (String, usize)> = HashMap::new();
+        let entity_lists = threads.open.iter().map(|t| &t.entities).chain(history.iter().map(|t| &t.entities));
+        for entities in entity_lists {
+            let unique: HashSet<String> = entities.iter().map(|e| e.to_lowercase()).collect();
+            for lower in unique {
+                let original = entities.iter().find(|e| e.to_lowercase() == lower).cloned().unwrap_or_default();
+                entity_counts.entry(lower).or_insert((original, 0)).1 += 1;
+            }
+        }
+        let mut top_entities: Vec<(String, usize)> = entity_counts.into_values().collect();
+        top_entities.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_entities.truncate(TOP_ENTITIES);
+
+        ThreadStats {
+            current_thread: active.is_some(),
+            open_threads: threads.open.len(),
+            active_topic: active.map(|t| t.topic.clone()),
+            active_age_secs: active.map(|t| (now - t.started).num_seconds() as f64),
+            active_messages: active.map_or(0, |t| t.message_count()),
+            archived_threads: history.len(),
+            avg_duration_secs: if history.is_empty() {
+                0.0
+            } else {
+                history.iter().map(|t| t.duration_secs).sum::<f64>() / history.len() as f64
+            },
+            total_messages,
+            messages_per_thread: if total_threads == 0 {
+                0.0
+            } else {
+                total_messages as f64 / total_threads as f64
+            },
+            top_entities,
+        }
+    }
+
+    /// Сверх max_thread_messages старшая половина сообщений сжимается
+    /// в thread.summary (компрессором авто-суммирования, если задан, иначе —
+    /// превью реплик). Сущности нити не трогаются.
+    fn compact(&self, thread: &mut Thread) {
+        if thread.messages.len() <= self.max_thread_messages {
+            return;
+        }
+        let drained: Vec<ThreadMessage> = thread.messages.drain(..self.max_thread_messages / 2).collect();
+        thread.compacted += drained.len();
+        let previous = thread.summary.take().unwrap_or_default();
+        let summary = match self.summarizer.read().as_ref() {
+            Some((compressor, _)) => {
+                let mut text = previous;
+                for m in &drained {
+                    text.push('\n');
+                    text.push_str(&m.user);
+                    if !m.assistant.is_empty() {
+                        text.push('\n');
+                        text.push_str(&m.assistant);
+                    }
+                }
+                compressor.get().condense(&text, SUMMARY_MAX_TOKENS)
+            }
+            None => {
+                let previews = drained.iter().map(|m| m.user.chars().take(TIMELINE_PREVIEW_CHARS).collect::<String>());
+                let joined = std::iter::once(previous)
+                    .filter(|p| !p.is_empty())
+                    .chain(previews)
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                let skip = joined.chars().count().saturating_sub(SUMMARY_MAX_CHARS);
+                joined.chars().skip(skip).collect()
+            }
+        };
+        thread.summary = (!summary.is_empty()).then_some(summary);
+    }
+
+    fn export(&self, thread_id: u64) -> Option<ThreadExport> {
+        if let Some(t) = self.state().get(thread_id) {
+            return Some(ThreadExport {
+                id: t.id,
+                topic: t.topic.clone(),
+                entities: t.entities.clone(),
+                started: t.started,
+                archived: false,
+                summary: t.summary.clone(),
+                messages: t.messages.clone(),
+            });
+        }
+        let t = self.find_archived(thread_id)?;
+        Some(ThreadExport {
+            id: t.id,
+            topic: t.topic,
+            entities: t.entities,
+            started: t.started,
+            archived: true,
+            summary: t.summary,
+            messages: t.messages,
+        })
+    }
+
+    /// Событиям открытия/закрытия есть куда идти (колбэки или шина)
+    fn has_callbacks(&self) -> bool {
+        self.on_open.read().is_some() || self.on_close.read().is_some() || self.bus.read().is_some()
+    }
+
+    fn record_open(&self, threads: &Threads, id: u64, reason: &'static str) {
+        let Some(thread) = threads.get(id) else { return };
+        self.record(reason, id, thread.topic.clone());
+        metrics::inc("threads_opened_total", 1);
+        if self.has_callbacks() {
+            self.pending.lock().push(ThreadEvent::new(thread, true, reason));
+        }
+    }
+
+    fn record_message(&self, thread_id: u64, user_input: &str) {
+        self.record("message", thread_id, user_input.chars().take(TIMELINE_PREVIEW_CHARS).collect());
+    }
+
+    fn record(&self, kind: &'static str, thread_id: u64, detail: String) {
+        let mut timeline = self.timeline.lock();
+        timeline.push_back(TimelineEvent { timestamp: Utc::now(), kind, thread_id, detail });
+        if timeline.len() > MAX_TIMELINE {
+            timeline.pop_front();
+        }
+    }
+
+    fn warn(&self, message: String) {
+        self.warnings.lock().push(message);
+    }
+
+    /// Публикует накопленные события в шину и вызывает колбэки; только без
+    /// удерживаемых блокировок, чтобы колбэк мог обращаться к трекеру
+    fn emit_events(&self) {
+        let warnings = std::mem::take(&mut *self.warnings.lock());
+        for message in &warnings {
+            errors::warn(module_path!(), message);
+        }
+        let events = std::mem::take(&mut *self.pending.lock());
+        if events.is_empty() {
+            return;
+        }
+        let bus = self.bus.read().clone();
+        if let Some((bus, user_id)) = bus {
+            for event in &events {
+                let kind = if event.opened { "thread_opened" } else { "thread_closed" };
+                bus.emit(kind, "thread_tracker", event.to_json(user_id.as_deref()));
+            }
+        }
+        if self.on_open.read().is_none() && self.on_close.read().is_none() {
+            return;
+        }
+        Python::with_gil(|py| {
+            for event in events {
+                let slot = if event.opened { &self.on_open } else { &self.on_close };
+                let Some(callback) = slot.read().as_ref().map(|c| c.clone_ref(py)) else {
+                    continue;
+                };
+                if let Err(err) = event.to_dict(py).and_then(|dict| callback.call1(py, (dict,))) {
+                    err.write_unraisable(py, None);
+                }
+            }
+        });
+    }
+
+    /// Архивирует нити, просроченные к моменту now
+    fn expire(&self, threads: &mut Threads, now: DateTime<Utc>) {
+        let expired: Vec<u64> = threads.open.iter().filter(|t| t.is_expired(now)).map(|t| t.id).collect();
+        if expired.is_empty() {
+            return;
+        }
+        for id in expired {
+            if let Some(thread) = threads.take(id) {
+                self.archive(thread, "timeout", &mut threads.history);
+            }
+        }
+    }
+
+    /// Сверх max_open — архивируются давно неактивные нити (кроме активной)
+    fn enforce_open_limit(&self, threads: &mut Threads) {
+        while threads.open.len() > self.max_open {
+            let oldest = threads
+                .open
+                .iter()
+                .filter(|t| Some(t.id) != threads.active)
+                .min_by_key(|t| t.last_active)
+                .map(|t| t.id);
+            let Some(thread) = oldest.and_then(|id| threads.take(id)) else {
+                break;
+            };
+            self.archive(thread, "evicted", &mut threads.history);
+        }
+    }
+}
+
+// ── Реестр по пользователям ──
+
+/// Трекеры нитей по user_id с общей конфигурацией. Методы те же,
+/// что у ThreadTracker, с user_id первым аргументом; трекер пользователя
+/// создаётся при первом изменяющем вызове.
+#[pyclass(frozen)]
+pub struct MultiThreadTracker {
+    timeout_secs: i64,
+    max_open: usize,
+    archive_messages: Option<usize>,
+    max_thread_messages: usize,
+    max_archived: usize,
+    /// Каталог хранилища архива; у каждого пользователя своё пространство имён
+    data_dir: Option<PathBuf>,
+    /// Общее хранилище вместо data_dir
+    storage: Option<Arc<Store>>,
+    /// Нити пользователей общие для процессов с тем же хранилищем
+    shared: bool,
+    gazetteer: RwLock<Vec<String>>,
+    recognizer: RwLock<Option<SharedRecognizer>>,
+    translit: AtomicBool,
+    bus: RwLock<Option<SharedBus>>,
+    users: DashMap<String, Arc<ThreadTracker>>,
+}
+
+impl MultiThreadTracker {
+    pub(crate) fn new(
+        timeout_secs: i64,
+        max_open: usize,
+        archive_messages: Option<usize>,
+        max_thread_messages: usize,
+        max_archived: usize,
+        data_dir: Option<&str>,
+    ) -> Self {
+        Self {
+            timeout_secs,
+            max_open,
+            archive_messages,
+            max_thread_messages,
+            max_archived,
+            data_dir: data_dir.map(PathBuf::from),
+            storage: None,
+            shared: false,
+            gazetteer: RwLock::new(Vec::new()),
+            recognizer: RwLock::new(None),
+            translit: AtomicBool::new(true),
+            bus: RwLock::new(None),
+            users: DashMap::new(),
+        }
+    }
+}
+
+#[pymethods]
+impl MultiThreadTracker {
+    /// Незаданные параметры берутся из раздела threads config;
+    /// storage — общее хранилище архива вместо data_dir; shared=True — нити
+    /// общие для процессов (пользователь подключается при первом обращении)
+    #[new]
+    #[pyo3(signature = (
+        timeout_secs=None, max_open=None, archive_messages=None, max_thread_messages=None,
+        max_archived=None, data_dir=None, *, storage=None, shared=None, config=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        timeout_secs: Option<i64>,
+        max_open: Option<usize>,
+        archive_messages: Option<usize>,
+        max_thread_messages: Option<usize>,
+        max_archived: Option<usize>,
+        data_dir: Option<String>,
+        storage: Option<PyRef<'_, Storage>>,
+        shared: Option<bool>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let config = config::settings(config);
+        let threads = &config.threads;
+        let mut multi = Self::new(
+            timeout_secs.unwrap_or(threads.timeout_secs),
+            max_open.unwrap_or(threads.max_open),
+            archive_messages.or(threads.archive_messages),
+            max_thread_messages.unwrap_or(threads.max_thread_messages),
+            max_archived.unwrap_or(threads.max_archived),
+            data_dir.or_else(|| config.threads_dir()).as_deref(),
+        );
+        multi.storage = storage.map(|s| s.shared());
+        multi.shared = shared.unwrap_or(config.runtime.shared);
+        if multi.shared && multi.data_dir.is_none() && multi.storage.is_none() {
+            return Err(PyValueError::new_err(SHARED_NEEDS_STORE));
+        }
+        Ok(multi)
+    }
+
+    #[pyo3(signature = (user_id, topic, entities=None, timeout_secs=None))]
+    fn start_thread(&self, user_id: &str, topic: &str, entities: Option<Vec<String>>, timeout_secs: Option<i64>) -> u64 {
+        self.tracker(user_id).start_thread(topic, entities, timeout_secs)
+    }
+
+    fn add_message(&self, user_id: &str, user_input: &str, response: &str) {
+        self.tracker(user_id).add_message(user_input, response);
+    }
+
+    fn update(&self, user_id: &str, user_input: &str, response: &str) {
+        self.tracker(user_id).update(user_input, response);
+    }
+
+    fn switch_to(&self, user_id: &str, thread_id: u64) -> PyResult<()> {
+        self.existing(user_id)?.switch_to(thread_id)
+    }
+
+    fn resume(&self, user_id: &str, thread_id: u64) -> PyResult<()> {
+        self.existing(user_id)?.resume(thread_id)
+    }
+
+    fn end_thread(&self, user_id: &str) {
+        if let Ok(tracker) = self.existing(user_id) {
+            tracker.end_thread();
+        }
+    }
+
+    fn list_threads(&self, user_id: &str) -> Vec<(u64, String, usize, bool)> {
+        self.existing(user_id).map(|t| t.list_threads()).unwrap_or_default()
+    }
+
+    fn get_current_thread_id(&self, user_id: &str) -> Option<u64> {
+        self.existing(user_id).ok()?.get_current_thread_id()
+    }
+
+    fn relatedness(&self, user_id: &str, text: &str) -> f64 {
+        self.existing(user_id).map_or(0.0, |t| t.relatedness(text))
+    }
+
+    #[pyo3(signature = (user_id, text, threshold=RELATED_THRESHOLD))]
+    fn is_related(&self, user_id: &str, text: &str, threshold: f64) -> bool {
+        self.existing(user_id).is_ok_and(|t| t.is_related(text, threshold))
+    }
+
+    /// expire_if_idle для всех пользователей; возвращает user_id, у которых
+    /// были архивированы нити
+    pub(crate) fn expire_idle(&self) -> Vec<String> {
+        let trackers: Vec<(String, Arc<ThreadTracker>)> =
+            self.users.iter().map(|r| (r.key().clone(), Arc::clone(r.value()))).collect();
+        trackers
+            .into_iter()
+            .filter(|(_, tracker)| tracker.expire_if_idle())
+            .map(|(user_id, _)| user_id)
+            .collect()
+    }
+
+    #[pyo3(signature = (user_id, thread_id, format="json"))]
+    fn export_thread(&self, user_id: &str, thread_id: u64, format: &str) -> PyResult<String> {
+        self.existing(user_id)?.export_thread(thread_id, format)
+    }
+
+    fn seconds_until_timeout(&self, user_id: &str) -> Option<f64> {
+        self.existing(user_id).ok()?.seconds_until_timeout()
+    }
+
+    fn start_subtopic(&self, user_id: &str, topic: &str) -> PyResult<()> {
+        self.existing(user_id)?.start_subtopic(topic)
+    }
+
+    fn end_subtopic(&self, user_id: &str) -> Option<String> {
+        self.existing(user_id).ok()?.end_subtopic()
+    }
+
+    #[pyo3(signature = (user_id, window=5))]
+    fn detect_drift(&self, user_id: &str, window: usize) -> f64 {
+        self.existing(user_id).map_or(0.0, |t| t.detect_drift(window))
+    }
+
+    fn get_topic_path(&self, user_id: &str) -> Vec<String> {
+        self.existing(user_id).map(|t| t.get_topic_path()).unwrap_or_default()
+    }
+
+    #[pyo3(signature = (
+        user_id, max_exchanges=CONTEXT_EXCHANGES, user_chars=USER_PREVIEW_CHARS,
+        assistant_chars=ASSISTANT_PREVIEW_CHARS
+    ))]
+    fn get_context(&self, user_id: &str, max_exchanges: usize, user_chars: usize, assistant_chars: usize) -> Option<String> {
+        self.existing(user_id).ok()?.get_context(max_exchanges, user_chars, assistant_chars)
+    }
+
+    fn has_active_thread(&self, user_id: &str) -> bool {
+        self.existing(user_id).is_ok_and(|t| t.has_active_thread())
+    }
+
+    fn get_current_topic(&self, user_id: &str) -> Option<String> {
+        self.existing(user_id).ok()?.get_current_topic()
+    }
+
+    #[pyo3(signature = (user_id, limit=5))]
+    fn get_past_threads(&self, user_id: &str, limit: usize) -> Vec<(String, f64, usize)> {
+        self.existing(user_id).map(|t| t.get_past_threads(limit)).unwrap_or_default()
+    }
+
+    fn get_archived_thread(&self, user_id: &str, thread_id: u64) -> Option<Vec<(String, String, String)>> {
+        self.existing(user_id).ok()?.get_archived_thread(thread_id)
+    }
+
+    #[pyo3(signature = (user_id, text, limit=3))]
+    fn find_resumable(&self, user_id: &str, text: &str, limit: usize) -> Vec<(u64, String, usize)> {
+        self.existing(user_id).map(|t| t.find_resumable(text, limit)).unwrap_or_default()
+    }
+
+    #[pyo3(signature = (user_id, query, limit=5))]
+    fn search_threads(&self, user_id: &str, query: &str, limit: usize) -> Vec<(u64, String, usize, bool)> {
+        self.existing(user_id).map(|t| t.search_threads(query, limit)).unwrap_or_default()
+    }
+
+    fn get_entities(&self, user_id: &str) -> Vec<String> {
+        self.existing(user_id).map(|t| t.get_entities()).unwrap_or_default()
+    }
+
+    /// Общий справочник имён — для всех пользователей, включая будущих
+    fn set_gazetteer(&self, names: Vec<String>) {
+        let mut gazetteer = self.gazetteer.write();
+        for tracker in self.users.iter() {
+            tracker.set_gazetteer(names.clone());
+        }
+        *gazetteer = names;
+    }
+
+    /// Общий EntityRecognizer — для всех пользователей, включая будущих
+    #[pyo3(signature = (recognizer=None))]
+    fn set_entity_recognizer(&self, recognizer: Option<PyRef<EntityRecognizer>>) {
+        let shared = recognizer.map(|r| r.shared());
+        let mut current = self.recognizer.write();
+        for tracker in self.users.iter() {
+            tracker.set_recognizer(shared.clone());
+        }
+        *current = shared;
+    }
+
+    /// Транслит при сопоставлении — для всех пользователей, включая будущих
+    fn set_translit_matching(&self, enabled: bool) {
+        self.translit.store(enabled, Ordering::Relaxed);
+        for tracker in self.users.iter() {
+            tracker.set_translit_matching(enabled);
+        }
+    }
+
+    /// Общий EventBus — для всех пользователей, включая будущих;
+    /// в событиях есть user_id
+    #[pyo3(signature = (bus=None))]
+    fn set_event_bus(&self, bus: Option<PyRef<'_, EventBus>>) {
+        let shared = bus.map(|b| b.shared());
+        let mut current = self.bus.write();
+        for tracker in self.users.iter() {
+            tracker.set_bus(shared.clone(), Some(tracker.key().clone()));
+        }
+        *current = shared;
+    }
+
+    #[pyo3(signature = (user_id, limit=50))]
+    fn get_timeline(&self, user_id: &str, limit: usize) -> Vec<(String, String, u64, String)> {
+        self.existing(user_id).map(|t| t.get_timeline(limit)).unwrap_or_default()
+    }
+
+    fn get_stats<'py>(&self, py: Python<'py>, user_id: &str) -> PyResult<Bound<'py, PyDict>> {
+        match self.existing(user_id) {
+            Ok(tracker) => tracker.get_stats(py),
+            Err(_) => ThreadTracker::new(self.timeout_secs, self.max_open, self.archive_messages, self.max_thread_messages, self.max_archived, None)
+                .get_stats(py),
+        }
+    }
+
+    /// user_id всех пользователей с трекером
+    fn users(&self) -> Vec<String> {
+        self.users.iter().map(|r| r.key().clone()).collect()
+    }
+
+    /// Забывает пользователя вместе с его нитями; True, если он был
+    fn remove_user(&self, user_id: &str) -> bool {
+        self.users.remove(user_id).is_some()
+    }
+
+    fn __len__(&self) -> usize {
+        self.users.len()
+    }
+}
+
+impl MultiThreadTracker {
+    /// Трекер пользователя (создаётся при первом обращении). Возвращается Arc,
+    /// чтобы не держать шард DashMap во время вызова (колбэки, долгие операции).
+    fn tracker(&self, user_id: &str) -> Arc<ThreadTracker> {
+        if let Some(tracker) = self.users.get(user_id) {
+            return Arc::clone(&tracker);
+        }
+        // Справочники читаются до вставки: set_gazetteer / set_entity_recognizer
+        // держат их на запись, пока обходят пользователей
+        let gazetteer = self.gazetteer.read();
+        let recognizer = self.recognizer.read();
+        let bus = self.bus.read();
+        let tracker = self.users.entry(user_id.to_string()).or_insert_with(|| {
+            // Пространство имён по хэшу user_id: безопасное имя для любого идентификатора
+            let user_key = format!("user_{:016x}", xxh3_64(user_id.as_bytes()));
+            let legacy = self.data_dir.as_ref().map(|d| d.join(&user_key).join(ARCHIVE_FILE));
+            let mut warnings = Vec::new();
+            let spill = Spill::locate(
+                self.storage.clone(),
+                self.data_dir.as_deref(),
+                format!("{}.{}", ARCHIVE_NAMESPACE, user_key),
+                legacy,
+                &mut warnings,
+            );
+            let tracker = ThreadTracker::with_spill(
+                self.timeout_secs,
+                self.max_open,
+                self.archive_messages,
+                self.max_thread_messages,
+                self.max_archived,
+                spill,
+                warnings,
+            );
+            let tracker = if self.shared {
+                tracker.with_shared(format!("{}.{}", STATE_NAMESPACE, user_key))
+            } else {
+                tracker
+            };
+            tracker.set_gazetteer(gazetteer.clone());
+            tracker.set_recognizer(recognizer.clone());
+            tracker.set_translit_matching(self.translit.load(Ordering::Relaxed));
+            tracker.set_bus(bus.clone(), Some(user_id.to_string()));
+            Arc::new(tracker)
+        });
+        Arc::clone(&tracker)
+    }
+
+    fn existing(&self, user_id: &str) -> PyResult<Arc<ThreadTracker>> {
+        // Нити пользователя мог создать другой процесс
+        if self.shared {
+            return Ok(self.tracker(user_id));
+        }
+        self.users
+            .get(user_id)
+            .map(|t| Arc::clone(&t))
+            .ok_or_else(|| MemoryError::new_err(format!("Нет нитей пользователя {}", user_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_and_get_topic() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.start_thread("тестовая тема", None, None);
+        assert_eq!(tracker.get_current_topic(), Some("тестовая тема".to_string()));
+        assert!(tracker.has_active_thread());
+    }
+
+    #[test]
+    fn test_is_related() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.start_thread("Rust программирование", Some(vec!["cargo".to_string()]), None);
+        assert!(tracker.is_related("Расскажи про Rust программирование", RELATED_THRESHOLD));
+        assert!(tracker.is_related("что там с cargo?", RELATED_THRESHOLD));
+        assert!(tracker.is_related("помнишь, мы обсуждали?", RELATED_THRESHOLD));
+    }
+
+    #[test]
+    fn test_end_thread_archives() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.start_thread("тема 1", None, None);
+        tracker.add_message("привет", "здравствуй");
+        tracker.end_thread();
+
+        assert!(tracker.get_current_topic().is_none());
+        let past = tracker.get_past_threads(5);
+        assert_eq!(past.len(), 1);
+        assert_eq!(past[0].0, "тема 1");
+    }
+
+    #[test]
+    fn test_update_creates_thread() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.update("новое сообщение", "ответ");
+        assert!(tracker.has_active_thread());
+    }
+
+    #[test]
+    fn test_multiple_threads_routing() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        let vacation = tracker.start_thread("отпуск", Some(vec!["Турция".to_string()]), None);
+        let work = tracker.start_thread("релиз проекта", None, None);
+        assert_eq!(tracker.get_current_thread_id(), Some(work));
+
+        // "кстати, про отпуск" уходит в нить отпуска и делает её активной
+        tracker.update("Кстати, про отпуск: билеты в Турцию купил", "Отлично!");
+        assert_eq!(tracker.get_current_thread_id(), Some(vacation));
+        // Без совпадений — в активную
+        tracker.update("А сколько стоит?", "Недорого");
+        assert_eq!(tracker.get_current_thread_id(), Some(vacation));
+        tracker.update("Когда релиз проекта?", "В пятницу");
+        assert_eq!(tracker.get_current_thread_id(), Some(work));
+
+        let threads = tracker.list_threads();
+        assert_eq!(threads, vec![
+            (vacation, "отпуск".to_string(), 2, false),
+            (work, "релиз проекта".to_string(), 1, true),
+        ]);
+
+        tracker.switch_to(vacation).unwrap();
+        assert_eq!(tracker.get_current_topic(), Some("отпуск".to_string()));
+        assert!(tracker.switch_to(999).is_err());
+    }
+
+    #[test]
+    fn test_per_thread_timeout_and_limit() {
+        let tracker = ThreadTracker::new(600, 2, None, 100, MAX_ARCHIVED, None);
+        let short = tracker.start_thread("короткая", None, Some(-1));
+        let long = tracker.start_thread("длинная", None, None);
+        tracker.update("что-то новое", "ок");
+        // Просроченная нить ушла в архив, длинная осталась
+        assert_eq!(tracker.list_threads().iter().map(|t| t.0).collect::<Vec<_>>(), vec![long]);
+        assert_eq!(tracker.get_past_threads(5)[0].0, "короткая");
+        assert!(tracker.set_thread_timeout(short, 10).is_err());
+
+        // Лимит открытых нитей: старейшая неактивная архивируется
+        tracker.start_thread("вторая", None, None);
+        tracker.start_thread("третья", None, None);
+        let topics: Vec<String> = tracker.list_threads().into_iter().map(|t| t.1).collect();
+        assert_eq!(topics, vec!["вторая", "третья"]);
+    }
+
+    #[test]
+    fn test_resume_from_archive() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        let db = tracker.start_thread("миграция базы", Some(vec!["PostgreSQL".to_string()]), None);
+        tracker.add_message("Переносим таблицы", "Хорошо");
+        tracker.end_thread();
+        let trip = tracker.start_thread("поездка", None, None);
+        tracker.end_thread();
+
+        let found = tracker.find_resumable("Что там с PostgreSQL?", 3);
+        assert_eq!(found, vec![(db, "миграция базы".to_string(), 2)]);
+        // Маркер без совпадений — сначала последняя закрытая
+        let found = tracker.find_resumable("Вернёмся к тому, что обсуждали вчера", 3);
+        assert_eq!(found.iter().map(|f| f.0).collect::<Vec<_>>(), vec![trip, db]);
+        assert!(tracker.find_resumable("Погода", 3).is_empty());
+
+        tracker.resume(db).unwrap();
+        assert_eq!(tracker.get_current_thread_id(), Some(db));
+        assert_eq!(tracker.list_threads(), vec![(db, "миграция базы".to_string(), 1, true)]);
+        assert_eq!(tracker.get_past_threads(5).len(), 1);
+        assert!(tracker.resume(db).is_err());
+    }
+
+    #[test]
+    fn test_extract_entities() {
+        let gazetteer = vec!["Кристина".to_string()];
+        let found = extract_entities(
+            "Вчера мы с @anna_k ездили в Новый Орлеан, потом смотрели «Игру престолов». кристина знает.",
+            &gazetteer,
+        );
+        assert_eq!(found, vec!["@anna_k", "Игру престолов", "Новый Орлеан", "Кристина"]);
+        // Первое слово предложения — не сущность
+        assert!(extract_entities("Привет. Как дела?", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_entity_recognizer_feeds_entities() {
+        let tracker = ThreadTracker::new(600, 5, None, MAX_THREAD_MESSAGES, MAX_ARCHIVED, None);
+        let recognizer = EntityRecognizer::new(None, false).unwrap();
+        tracker.set_recognizer(Some(recognizer.shared()));
+        assert!(tracker.entities_in("летим в москву").is_empty());
+        // Справочник живой: изменения видны уже подключённому трекеру
+        recognizer.add_gazetteer("city", vec!["Москва".into()]).unwrap();
+        assert_eq!(tracker.entities_in("летим в москву"), vec!["Москва"]);
+        assert_eq!(tracker.entities_in("В Москве видели @anna"), vec!["@anna", "Москва"]);
+        tracker.set_recognizer(None);
+        assert!(tracker.entities_in("летим в москву").is_empty());
+    }
+
+    #[test]
+    fn test_entities_accumulate_on_thread() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.start_thread("переезд", None, None);
+        tracker.update("Думаю переехать в Казань весной", "Интересно");
+        assert_eq!(tracker.get_entities(), vec!["Казань"]);
+        // Теперь сообщение про Казань связано с нитью без упоминания темы
+        assert!(tracker.is_related("А в Казань дорого лететь?", RELATED_THRESHOLD));
+    }
+
+    #[test]
+    fn test_archived_transcript() {
+        let tracker = ThreadTracker::new(600, 5, Some(2), 100, MAX_ARCHIVED, None);
+        let id = tracker.start_thread("рецепты", None, None);
+        for i in 0..3 {
+            tracker.add_message(&format!("вопрос {}", i), &format!("ответ {}", i));
+        }
+        tracker.end_thread();
+
+        let transcript = tracker.get_archived_thread(id).unwrap();
+        let pairs: Vec<(&str, &str)> = transcript.iter().map(|m| (m.0.as_str(), m.1.as_str())).collect();
+        assert_eq!(pairs, vec![("вопрос 1", "ответ 1"), ("вопрос 2", "ответ 2")]);
+        // Счётчик — по исходной нити
+        assert_eq!(tracker.get_past_threads(1)[0].2, 3);
+        assert!(tracker.get_archived_thread(id + 1).is_none());
+    }
+
+    #[test]
+    fn test_search_threads() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        let db = tracker.start_thread("миграция базы", None, None);
+        tracker.add_message("Решили делать миграцию ночью", "Договорились");
+        tracker.end_thread();
+        let cat = tracker.start_thread("кот", None, None);
+        tracker.add_message("Кот опять не ест", "Сходите к ветеринару");
+
+        let found = tracker.search_threads("Что мы решили насчёт миграции базы данных?", 5);
+        assert_eq!(found[0].0, db);
+        assert!(found[0].3);
+        assert!(found.iter().all(|r| r.0 != cat));
+        assert_eq!(tracker.search_threads("ветеринар", 5), vec![(cat, "кот".to_string(), 1, false)]);
+        assert!(tracker.search_threads("да", 5).is_empty());
+    }
+
+    #[test]
+    fn test_thread_summary() {
+        let compressor = ContextCompressor::new(0.3, None, 100, 10, "...", 50, true, None).unwrap();
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.start_thread("ремонт", None, None);
+        tracker.add_message("Начинаем ремонт кухни в мае.", "Ремонт кухни лучше планировать заранее.");
+        let thread = tracker.threads.read();
+        let summary = thread.active().unwrap().summarize(&compressor, 60);
+        assert!(summary.starts_with("Тема: ремонт."));
+        assert!(summary.contains("кухни"));
+    }
+
+    #[test]
+    fn test_lifecycle_events() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        // Без колбэков события не копятся
+        tracker.start_thread("тема", None, None);
+        tracker.end_thread();
+        assert!(tracker.pending.lock().is_empty());
+
+        let mut threads = Threads::default();
+        let id = threads.open_thread("отпуск".to_string(), vec!["Сочи".to_string()], 600, Utc::now());
+        let thread = threads.take(id).unwrap();
+        let event = ThreadEvent::new(&thread, false, "timeout");
+        assert_eq!((event.id, event.topic.as_str(), event.reason), (id, "отпуск", "timeout"));
+        assert_eq!(event.entities, vec!["Сочи"]);
+        assert!(!event.opened);
+    }
+
+    #[test]
+    fn test_event_bus_events() {
+        let bus = EventBus::new(10);
+        let multi = MultiThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        *multi.bus.write() = Some(bus.shared());
+        multi.start_thread("u1", "отпуск", None, None);
+        multi.end_thread("u1");
+
+        let events = bus.drain_events(None);
+        let kinds: Vec<&str> = events.iter().map(|e| e.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["thread_opened", "thread_closed"]);
+        assert_eq!(events[1].payload["topic"], "отпуск");
+        assert_eq!(events[1].payload["reason"], "ended");
+        assert_eq!(events[1].payload["user_id"], "u1");
+        // Колбэков нет — очередь колбэков пуста
+        assert!(multi.existing("u1").unwrap().pending.lock().is_empty());
+    }
+
+    #[test]
+    fn test_multi_user_tracker() {
+        let multi = MultiThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        multi.set_gazetteer(vec!["Кристина".to_string()]);
+        multi.start_thread("alice", "отпуск", None, None);
+        multi.update("bob", "Кристина, привет", "Привет!");
+
+        assert_eq!(multi.get_current_topic("alice"), Some("отпуск".to_string()));
+        assert_eq!(multi.get_current_topic("bob"), Some("Кристина, привет".to_string()));
+        assert_eq!(multi.get_entities("bob"), vec!["Кристина"]);
+        assert!(multi.is_related("alice", "про отпуск", RELATED_THRESHOLD));
+        assert!(!multi.is_related("bob", "про отпуск", RELATED_THRESHOLD));
+
+        assert!(multi.get_current_topic("carol").is_none());
+        assert!(multi.switch_to("carol", 1).is_err());
+        assert_eq!(multi.__len__(), 2);
+
+        multi.end_thread("alice");
+        assert_eq!(multi.get_past_threads("alice", 5).len(), 1);
+        assert!(multi.remove_user("alice"));
+        assert!(multi.get_past_threads("alice", 5).is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        let empty = tracker.stats();
+        assert!(!empty.current_thread);
+        assert_eq!((empty.total_messages, empty.messages_per_thread), (0, 0.0));
+
+        tracker.start_thread("кино", Some(vec!["Нолан".to_string()]), None);
+        tracker.add_message("Смотрел фильм", "Какой?");
+        tracker.add_message("Новый фильм", "Понравился?");
+        tracker.end_thread();
+        tracker.start_thread("кино снова", Some(vec!["нолан".to_string(), "Вильнёв".to_string()]), None);
+        tracker.add_message("Ещё фильм", "Ок");
+
+        let stats = tracker.stats();
+        assert!(stats.current_thread);
+        assert_eq!(stats.active_topic.as_deref(), Some("кино снова"));
+        assert_eq!((stats.open_threads, stats.archived_threads), (1, 1));
+        assert_eq!((stats.active_messages, stats.total_messages), (1, 3));
+        assert_eq!(stats.messages_per_thread, 1.5);
+        assert_eq!(stats.top_entities[0].1, 2);
+        assert_eq!(stats.top_entities.len(), 2);
+    }
+
+    #[test]
+    fn test_timeline() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        let first = tracker.start_thread("погода", None, None);
+        tracker.add_message("Будет дождь?", "Да");
+        tracker.end_thread();
+        let second = tracker.start_thread("кино", None, Some(-1));
+        tracker.update("Что посмотреть вечером?", "Комедию");
+
+        let kinds: Vec<(String, u64)> = tracker.get_timeline(50).into_iter().map(|e| (e.1, e.2)).collect();
+        assert_eq!(kinds, vec![
+            ("started".to_string(), first),
+            ("message".to_string(), first),
+            ("ended".to_string(), first),
+            ("started".to_string(), second),
+            ("timeout".to_string(), second),
+            ("auto".to_string(), second + 1),
+            ("message".to_string(), second + 1),
+        ]);
+        let last = tracker.get_timeline(2);
+        assert_eq!(last.len(), 2);
+        assert_eq!(last[1].3, "Что посмотреть вечером?");
+    }
+
+    #[test]
+    fn test_relatedness_score() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        assert_eq!(tracker.relatedness("что угодно"), 0.0);
+        tracker.start_thread("переезд в Казань", Some(vec!["Казань".to_string()]), None);
+
+        let full = tracker.relatedness("Переезд в Казань — это серьёзно");
+        let entity = tracker.relatedness("В Казань летом жарко?");
+        let fresh_only = tracker.relatedness("Как дела?");
+        assert!(full > entity && entity > fresh_only);
+        assert!((fresh_only - 0.1).abs() < 1e-9);
+        assert!(!tracker.is_related("Как дела?", RELATED_THRESHOLD));
+        assert!(tracker.is_related("Как дела?", 0.05));
+
+        // Только маркеры: нормировка на сумму весов
+        tracker
+            .set_relatedness_weights(HashMap::from([
+                ("topic".to_string(), 0.0),
+                ("entities".to_string(), 0.0),
+                ("recency".to_string(), 0.0),
+            ]))
+            .unwrap();
+        assert_eq!(tracker.relatedness("Помнишь, что я говорил?"), 1.0);
+        assert!(tracker.set_relatedness_weights(HashMap::from([("mood".to_string(), 1.0)])).is_err());
+        assert!(tracker.set_relatedness_weights(HashMap::from([("indicators".to_string(), 0.0)])).is_err());
+    }
+
+    #[test]
+    fn test_translit_matching() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.start_thread("переезд в Казань", Some(vec!["Казань".to_string()]), None);
+        let fresh_only = tracker.relatedness("Kak dela?");
+        assert!(tracker.relatedness("pereezd v Kazan skoro") > fresh_only);
+
+        tracker.set_translit_matching(false);
+        assert_eq!(tracker.relatedness("pereezd v Kazan skoro"), fresh_only);
+    }
+
+    #[test]
+    fn test_subtopics() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        assert!(tracker.start_subtopic("нет нити").is_err());
+        tracker.start_thread("переезд", None, None);
+        tracker.start_subtopic("перевозка кота").unwrap();
+        let context = tracker.get_context(CONTEXT_EXCHANGES, USER_PREVIEW_CHARS, ASSISTANT_PREVIEW_CHARS).unwrap();
+        assert!(context.starts_with("Текущая тема: переезд → под-тема: перевозка кота"));
+        assert_eq!(tracker.end_subtopic(), Some("перевозка кота".to_string()));
+        assert_eq!(tracker.get_topic_path(), vec!["переезд"]);
+
+        // Автоопределение: новые слова без совпадений с темой
+        tracker.set_subtopic_detection(Some(0.8)).unwrap();
+        tracker.update("Переезд назначен на субботу", "Хорошо");
+        assert_eq!(tracker.get_topic_path().len(), 1);
+        tracker.update("Нужна переноска для кошки Муси", "Купим");
+        assert_eq!(tracker.get_topic_path(), vec!["переезд", "Нужна переноска для кошки Муси"]);
+        assert!(tracker.drift_score("переноска кошки") < 0.5);
+        assert!(tracker.set_subtopic_detection(Some(1.5)).is_err());
+    }
+
+    #[test]
+    fn test_expire_if_idle() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        assert!(!tracker.expire_if_idle());
+        assert!(tracker.seconds_until_timeout().is_none());
+
+        tracker.start_thread("долгая", None, None);
+        let left = tracker.seconds_until_timeout().unwrap();
+        assert!(left > 590.0 && left <= 600.0);
+        assert!(!tracker.expire_if_idle());
+
+        tracker.start_thread("просроченная", None, Some(-1));
+        assert_eq!(tracker.seconds_until_timeout(), Some(0.0));
+        assert!(tracker.expire_if_idle());
+        assert_eq!(tracker.get_current_thread_id(), None);
+        assert_eq!(tracker.list_threads().len(), 1);
+        assert_eq!(tracker.get_timeline(1)[0].1, "timeout");
+    }
+
+    #[test]
+    fn test_export_thread() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        let id = tracker.start_thread("отпуск", Some(vec!["Сочи".to_string()]), None);
+        tracker.add_message("Едем в Сочи в июле", "Отличный выбор");
+
+        let json: serde_json::Value = serde_json::from_str(&tracker.export_thread(id, "json").unwrap()).unwrap();
+        assert_eq!(json["topic"], "отпуск");
+        assert_eq!(json["archived"], false);
+        assert_eq!(json["messages"][0]["assistant"], "Отличный выбор");
+
+        tracker.end_thread();
+        let md = tracker.export_thread(id, "markdown").unwrap();
+        assert!(md.starts_with("# отпуск\n"));
+        assert!(md.contains("*Упоминается: Сочи*"));
+        assert!(md.contains("**Пользователь:** Едем в Сочи в июле"));
+        assert!(tracker.export_thread(id, "pdf").is_err());
+        assert!(tracker.export_thread(id + 1, "json").is_err());
+    }
+
+    #[test]
+    fn test_detect_drift() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.start_thread("ремонт кухни", Some(vec!["IKEA".to_string()]), None);
+        for text in ["Выбираем кухню в IKEA", "Кухня будет белая", "Ремонт кухни начнём в мае"] {
+            tracker.add_message(text, "");
+        }
+        assert_eq!(tracker.detect_drift(3), 0.0);
+        for text in ["Ремонт кухни затянулся", "Кухню собрали", "IKEA привезла кухню"] {
+            tracker.add_message(text, "");
+        }
+        let same = tracker.detect_drift(3);
+        for text in ["Какой фильм посмотреть", "Фильмы Нолана нравятся", "Посмотрим фильм вечером"] {
+            tracker.add_message(text, "");
+        }
+        let moved = tracker.detect_drift(3);
+        assert!(same < 0.6, "{}", same);
+        assert!(moved > 0.9, "{}", moved);
+        assert!(tracker.has_drifted(3, 0.8));
+    }
+
+    #[test]
+    fn test_rolling_compaction() {
+        let tracker = ThreadTracker::new(600, 5, None, 4, MAX_ARCHIVED, None);
+        let id = tracker.start_thread("ремонт", None, None);
+        tracker.add_message("Купили плитку в Леруа", "Отлично");
+        for i in 0..4 {
+            tracker.add_message(&format!("сообщение {}", i), "ок");
+        }
+        // 5 сообщений > 4: два старших сжаты
+        let threads = tracker.threads.read();
+        let thread = threads.get(id).unwrap();
+        assert_eq!((thread.messages.len(), thread.compacted), (3, 2));
+        assert_eq!(thread.summary.as_deref(), Some("Купили плитку в Леруа; сообщение 0"));
+        assert_eq!(thread.entities, vec!["Леруа"]);
+        drop(threads);
+
+        assert_eq!(tracker.list_threads()[0].2, 5);
+        assert!(tracker.get_context(CONTEXT_EXCHANGES, USER_PREVIEW_CHARS, ASSISTANT_PREVIEW_CHARS).unwrap().contains("Ранее: Купили плитку"));
+        tracker.end_thread();
+        assert_eq!(tracker.get_past_threads(1)[0].2, 5);
+    }
+
+    #[test]
+    fn test_context_with_replies() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.start_thread("погода", None, None);
+        tracker.add_message("Какая погода завтра?", "Завтра солнечно, около двадцати градусов");
+        tracker.add_message("А послезавтра?", "");
+
+        let context = tracker.get_context(5, 60, 7).unwrap();
+        assert!(context.contains("  Пользователь: Какая погода завтра?\n  Ассистент: Завтра \n"));
+        assert!(context.ends_with("  Пользователь: А послезавтра?"));
+
+        let last_only = tracker.get_context(1, 60, 0).unwrap();
+        assert!(!last_only.contains("Ассистент"));
+        assert!(!last_only.contains("Какая погода"));
+    }
+
+    #[test]
+    fn test_fuzzy_topic_matching() {
+        let tracker = ThreadTracker::new(600, 5, None, 100, MAX_ARCHIVED, None);
+        tracker.start_thread("переезд", Some(vec!["Казань".to_string()]), None);
+        tracker.set_relatedness_weights(HashMap::from([("recency".to_string(), 0.0)])).unwrap();
+
+        assert!(tracker.is_related("Когда переезжаем?", RELATED_THRESHOLD));
+        assert!(tracker.is_related("Квартиры в Казани дорогие", RELATED_THRESHOLD));
+        assert!(!tracker.is_related("Что на ужин?", RELATED_THRESHOLD));
+        // Короткие слова: "кот" ~ "кота"
+        assert_eq!(relevance("кот", &[], "покормил кота"), 4);
+    }
+
+    #[test]
+    fn test_archive_spillover() {
+        let dir = std::env::temp_dir().join(format!("kristina_threads_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let tracker = ThreadTracker::new(600, 5, None, 100, 1, dir.to_str());
+        let old = tracker.start_thread("миграция базы", None, None);
+        tracker.add_message("Переносим таблицы", "Ок");
+        tracker.end_thread();
+        let new = tracker.start_thread("отпуск", None, None);
+        tracker.end_thread();
+
+        // В памяти только последняя, старая — на диске, но находится
+        assert_eq!(tracker.get_past_threads(5).len(), 1);
+        assert_eq!(tracker.search_threads("миграция", 5)[0].0, old);
+        assert_eq!(tracker.get_archived_thread(old).unwrap()[0].0, "Переносим таблицы");
+        assert_eq!(tracker.find_resumable("помнишь миграцию базы?", 1)[0].0, old);
+
+        // Новый трекер продолжает нумерацию
+        let reopened = ThreadTracker::new(600, 5, None, 100, 1, dir.to_str());
+        assert!(reopened.start_thread("новая", None, None) > old);
+
+        tracker.resume(old).unwrap();
+        assert_eq!(tracker.get_current_thread_id(), Some(old));
+        assert!(tracker.spill.as_ref().unwrap().read().unwrap().is_empty());
+        assert!(tracker.get_archived_thread(new).is_some());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_shared_state_between_processes() {
+        let dir = std::env::temp_dir().join(format!("kristina_threads_shared_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // Два воркера с одним хранилищем
+        let shared = || ThreadTracker::new(600, 5, None, 100, 1, dir.to_str()).with_shared(STATE_NAMESPACE.to_string());
+        let (a, b) = (shared(), shared());
+
+        let id = a.start_thread("переезд", None, None);
+        assert_eq!(b.get_current_topic().as_deref(), Some("переезд"));
+        b.update("Когда переезд?", "В субботу");
+        assert_eq!(a.list_threads(), vec![(id, "переезд".to_string(), 1, true)]);
+
+        // Одновременные сообщения из двух процессов не теряются
+        std::thread::scope(|scope| {
+            for tracker in [&a, &b] {
+                scope.spawn(move || {
+                    for i in 0..20 {
+                        tracker.add_message(&format!("про переезд {}", i), "");
+                    }
+                });
+            }
+        });
+        assert_eq!(b.list_threads()[0].2, 41);
+
+        a.end_thread();
+        assert_eq!(b.get_past_threads(5)[0].0, "переезд");
+        assert!(b.get_current_thread_id().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
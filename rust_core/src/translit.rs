@@ -0,0 +1,178 @@
+//! Транслитерация RU ↔ латиница
+//!
+//! - to_latin: кириллица → латиница по распространённой схеме
+//!   (ж→zh, х→kh, ц→ts, щ→shch, ю→yu, я→ya; ъ и ь опускаются)
+//! - to_cyrillic: латиница → кириллица так, как пишут пользователи:
+//!   "privet, kak dela" → "привет, как дела". Диграфы — длиннейшим совпадением
+//!   (shch/sch, zh, kh, ts, ch, sh, ya/ja, yu/ju, yo/jo); "y" после гласной → й,
+//!   иначе ы; "ye" в начале слова и после гласной → е; апостроф → ь
+//! - transliterate(text, direction): "to_latin", "to_cyrillic" или "auto"
+//!   (по преобладающей письменности)
+//! - Толерантный режим матчеров (EmotionAnalyzer, IntentClassifier, ThreadTracker):
+//!   кириллические шаблоны сверяются ещё и с to_cyrillic(text)
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+
+fn latin_of(c: char) -> Option<&'static str> {
+    Some(match c {
+        'а' => "a", 'б' => "b", 'в' => "v", 'г' => "g", 'д' => "d", 'е' => "e", 'ё' => "yo",
+        'ж' => "zh", 'з' => "z", 'и' => "i", 'й' => "y", 'к' => "k", 'л' => "l", 'м' => "m",
+        'н' => "n", 'о' => "o", 'п' => "p", 'р' => "r", 'с' => "s", 'т' => "t", 'у' => "u",
+        'ф' => "f", 'х' => "kh", 'ц' => "ts", 'ч' => "ch", 'ш' => "sh", 'щ' => "shch", 'ъ' => "",
+        'ы' => "y", 'ь' => "", 'э' => "e", 'ю' => "yu", 'я' => "ya",
+        'і' => "i", 'ї' => "yi", 'є' => "ye", 'ґ' => "g",
+        _ => return None,
+    })
+}
+
+/// Диграфы латиницы, длинные — первыми
+const DIGRAPHS: &[(&str, &str)] = &[
+    ("shch", "щ"), ("sch", "щ"), ("zh", "ж"), ("kh", "х"), ("ts", "ц"), ("ch", "ч"), ("sh", "ш"),
+    ("ya", "я"), ("ja", "я"), ("yu", "ю"), ("ju", "ю"), ("yo", "ё"), ("jo", "ё"),
+];
+
+fn cyrillic_of(c: char) -> Option<&'static str> {
+    Some(match c {
+        'a' => "а", 'b' => "б", 'v' | 'w' => "в", 'g' => "г", 'd' => "д", 'e' => "е", 'z' => "з",
+        'i' => "и", 'k' | 'q' => "к", 'l' => "л", 'm' => "м", 'n' => "н", 'o' => "о", 'p' => "п",
+        'r' => "р", 's' => "с", 't' => "т", 'u' => "у", 'f' => "ф", 'h' => "х", 'c' => "ц",
+        'x' => "кс", 'j' => "й",
+        _ => return None,
+    })
+}
+
+fn is_latin_vowel(c: char) -> bool {
+    matches!(c.to_ascii_lowercase(), 'a' | 'e' | 'i' | 'o' | 'u' | 'y')
+}
+
+/// Регистр как у исходной буквы: "Ж" → "Zh" (или "ZH" внутри заглавного слова)
+fn push_cased(out: &mut String, piece: &str, upper: bool, whole_upper: bool) {
+    if !upper {
+        out.push_str(piece);
+    } else if whole_upper {
+        out.push_str(&piece.to_uppercase());
+    } else {
+        let mut chars = piece.chars();
+        if let Some(first) = chars.next() {
+            out.extend(first.to_uppercase());
+            out.push_str(chars.as_str());
+        }
+    }
+}
+
+pub(crate) fn to_latin(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        match latin_of(lower) {
+            Some(piece) => {
+                let upper = c.is_uppercase();
+                // Соседняя буква тоже заглавная — слово написано капсом
+                let whole_upper = upper
+                    && (chars.get(i + 1).is_some_and(|n| n.is_uppercase())
+                        || (i > 0 && chars[i - 1].is_uppercase() && !chars.get(i + 1).is_some_and(|n| n.is_lowercase())));
+                push_cased(&mut out, piece, upper, whole_upper);
+            }
+            None => out.push(c),
+        }
+    }
+    out
+}
+
+pub(crate) fn to_cyrillic(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if !c.is_ascii_alphabetic() {
+            // Апостроф внутри латинского слова — мягкий знак: "dvizhen'e"
+            if c == '\'' && i > 0 && chars[i - 1].is_ascii_alphabetic() {
+                out.push('ь');
+            } else {
+                out.push(c);
+            }
+            i += 1;
+            continue;
+        }
+        let upper = c.is_ascii_uppercase();
+        let whole_upper = upper && chars.get(i + 1).is_some_and(|n| n.is_ascii_uppercase());
+        let word_start = i == 0 || !chars[i - 1].is_alphabetic();
+        let after_vowel = i > 0 && is_latin_vowel(chars[i - 1]);
+
+        let digraph = DIGRAPHS.iter().find(|(lat, _)| {
+            let n = lat.len();
+            i + n <= lower.len() && lower[i..i + n].iter().copied().eq(lat.chars())
+        });
+        let (piece, len) = match (digraph, lower[i]) {
+            (Some(&(lat, cyr)), _) => (cyr, lat.len()),
+            (None, 'y') if lower.get(i + 1) == Some(&'e') && (word_start || after_vowel) => ("е", 2),
+            (None, 'y') if after_vowel => ("й", 1),
+            (None, 'y') => ("ы", 1),
+            (None, l) => (cyrillic_of(l).unwrap_or(""), 1),
+        };
+        push_cased(&mut out, piece, upper, whole_upper);
+        i += len;
+    }
+    out
+}
+
+/// Латинские буквы есть — для толерантного режима матчеров
+pub(crate) fn has_latin(text: &str) -> bool {
+    text.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// Транслитерация: direction — "to_latin", "to_cyrillic" или "auto"
+/// (кириллицы больше — в латиницу, иначе в кириллицу)
+#[pyfunction]
+#[pyo3(signature = (text, direction="auto"))]
+pub fn transliterate(text: &str, direction: &str) -> PyResult<String> {
+    match direction {
+        "to_latin" => Ok(to_latin(text)),
+        "to_cyrillic" => Ok(to_cyrillic(text)),
+        "auto" => {
+            let cyrillic = text.chars().filter(|c| latin_of(c.to_lowercase().next().unwrap_or(*c)).is_some()).count();
+            let latin = text.chars().filter(char::is_ascii_alphabetic).count();
+            Ok(if cyrillic > latin { to_latin(text) } else { to_cyrillic(text) })
+        }
+        other => Err(PyValueError::new_err(format!(
+            "Неизвестное направление транслитерации: {} (ожидается to_latin, to_cyrillic или auto)",
+            other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_latin() {
+        assert_eq!(to_latin("Привет, как дела?"), "Privet, kak dela?");
+        assert_eq!(to_latin("Щука и ёжик съели борщ"), "Shchuka i yozhik seli borshch");
+        assert_eq!(to_latin("ЖУРНАЛ Жанны"), "ZHURNAL Zhanny");
+    }
+
+    #[test]
+    fn test_to_cyrillic() {
+        assert_eq!(to_cyrillic("privet, kak dela?"), "привет, как дела?");
+        assert_eq!(to_cyrillic("Spasibo, vse khorosho, novyy shchit"), "Спасибо, все хорошо, новый щит");
+        assert_eq!(to_cyrillic("yesli moye, lyublyu, chto"), "если мое, люблю, что");
+        assert_eq!(to_cyrillic("horosho, SHKOLA, 123"), "хорошо, ШКОЛА, 123");
+        // Кириллица и прочее не трогаются
+        assert_eq!(to_cyrillic("уже ok 🙂"), "уже ок 🙂");
+    }
+
+    #[test]
+    fn test_round_trip_and_direction() {
+        for text in ["Мой новый журнал", "Цирк уехал, щенки спят", "Юля читает"] {
+            assert_eq!(to_cyrillic(&to_latin(text)), text);
+        }
+        assert_eq!(transliterate("kak dela", "auto").unwrap(), "как дела");
+        assert_eq!(transliterate("как дела", "auto").unwrap(), "kak dela");
+        assert!(transliterate("x", "backwards").is_err());
+    }
+}
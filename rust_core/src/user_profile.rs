@@ -0,0 +1,435 @@
+//! UserProfile — профиль пользователя из накопленных сигналов
+//!
+//! - Сигналы: эмоции реплик (EmotionAnalyzer / настроение Session), темы и
+//!   сущности нитей, часы активности; observe_session() снимает всё из сессии
+//! - Факты берутся из semantic memory подключённого MemoryEngine при render()
+//! - render(max_tokens) — компактный блок для промпта: разделы по убыванию
+//!   важности (факты, темы, сущности, эмоции, часы), пункты добавляются,
+//!   пока оценка токенов (как в ContextCompressor) укладывается в бюджет
+//! - Темы и сущности — не больше MAX_TERMS, реже всего встречавшиеся вытесняются
+//! - Хранение: рядом с данными MemoryEngine (profiles, ключ — user_id) или в
+//!   storage= / data_dir; профиль читается при создании
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use parking_lot::Mutex;
+use chrono::{DateTime, Local, Timelike};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::async_io;
+use crate::config::{self, CoreConfig};
+use crate::context_compressor::{ScriptCounts, TokenProfile};
+use crate::errors::{self, PersistenceError};
+use crate::memory_engine::MemoryEngine;
+use crate::session::Session;
+use crate::storage::{self, Storage, Store};
+
+const PROFILES: &str = "profiles";
+/// Сколько тем и сущностей помнит профиль
+const MAX_TERMS: usize = 100;
+/// Тем и сущностей в блоке промпта
+const TOP_TERMS: usize = 5;
+/// Часов активности в блоке промпта
+const TOP_HOURS: usize = 3;
+const HEADER: &str = "Профиль пользователя:";
+
+// ── Данные профиля ──
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct ProfileData {
+    /// Эмоция → сумма уверенностей
+    emotions: HashMap<String, f64>,
+    topics: HashMap<String, u32>,
+    entities: HashMap<String, u32>,
+    /// Сообщений по часам суток (местное время отметки)
+    hours: [u32; 24],
+    messages: u64,
+}
+
+/// +1 к счётчику; сверх MAX_TERMS вытесняется самый редкий
+fn bump(terms: &mut HashMap<String, u32>, term: &str) {
+    let term = term.trim();
+    if term.is_empty() {
+        return;
+    }
+    *terms.entry(term.to_string()).or_insert(0) += 1;
+    if terms.len() > MAX_TERMS {
+        let rarest = terms
+            .iter()
+            .filter(|(t, _)| t.as_str() != term)
+            .min_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(t, _)| t.clone());
+        if let Some(rarest) = rarest {
+            terms.remove(&rarest);
+        }
+    }
+}
+
+/// Самые частые: по убыванию счёта, при равенстве — по алфавиту
+fn top(terms: &HashMap<String, u32>, n: usize) -> Vec<(String, u32)> {
+    let mut items: Vec<(String, u32)> = terms.iter().map(|(t, &c)| (t.clone(), c)).collect();
+    items.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    items.truncate(n);
+    items
+}
+
+impl ProfileData {
+    fn observe_emotion(&mut self, emotion: &str, confidence: f64) {
+        *self.emotions.entry(emotion.to_string()).or_insert(0.0) += confidence;
+    }
+
+    fn observe_topic(&mut self, topic: Option<&str>, entities: &[String]) {
+        if let Some(topic) = topic {
+            bump(&mut self.topics, topic);
+        }
+        for entity in entities {
+            bump(&mut self.entities, entity);
+        }
+    }
+
+    fn observe_hour(&mut self, hour: u32) {
+        self.hours[hour as usize % 24] += 1;
+        self.messages += 1;
+    }
+
+    /// Доли эмоций, по убыванию
+    fn emotion_shares(&self) -> Vec<(String, f64)> {
+        let total: f64 = self.emotions.values().sum();
+        if total <= 0.0 {
+            return Vec::new();
+        }
+        let mut shares: Vec<(String, f64)> = self.emotions.iter().map(|(e, &w)| (e.clone(), w / total)).collect();
+        shares.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        shares
+    }
+
+    /// Самые активные часы, по возрастанию
+    fn active_hours(&self, n: usize) -> Vec<u32> {
+        let mut hours: Vec<(u32, u32)> = (0..24u32).map(|h| (h, self.hours[h as usize])).filter(|&(_, c)| c > 0).collect();
+        hours.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut hours: Vec<u32> = hours.into_iter().take(n).map(|(h, _)| h).collect();
+        hours.sort_unstable();
+        hours
+    }
+
+    /// Блок для промпта в пределах max_tokens; пустой профиль — пустая строка
+    fn render(&self, facts: &[(String, String)], max_tokens: usize, tokens: &TokenProfile) -> String {
+        let names = |terms: &HashMap<String, u32>| top(terms, TOP_TERMS).into_iter().map(|(t, _)| t).collect();
+        let sections: [(&str, &str, Vec<String>); 5] = [
+            ("Факты", "; ", facts.iter().map(|(k, v)| format!("{}: {}", k, v)).collect()),
+            ("Частые темы", ", ", names(&self.topics)),
+            ("Упоминает", ", ", names(&self.entities)),
+            (
+                "Эмоции",
+                ", ",
+                self.emotion_shares()
+                    .into_iter()
+                    .map(|(e, share)| format!("{} {:.0}%", e, share * 100.0))
+                    .collect(),
+            ),
+            (
+                "Активен в часы",
+                ", ",
+                self.active_hours(TOP_HOURS).into_iter().map(|h| h.to_string()).collect(),
+            ),
+        ];
+
+        let mut out = String::from(HEADER);
+        let mut counts = ScriptCounts::default();
+        counts.add(&out);
+        let mut filled = false;
+        for (label, separator, items) in sections {
+            let mut line = String::new();
+            for item in items {
+                let piece = if line.is_empty() {
+                    format!("\n- {}: {}", label, item)
+                } else {
+                    format!("{}{}", separator, item)
+                };
+                let mut next = counts;
+                next.add(&piece);
+                if tokens.tokens(&next) > max_tokens {
+                    break;
+                }
+                counts = next;
+                line.push_str(&piece);
+            }
+            filled |= !line.is_empty();
+            out.push_str(&line);
+        }
+        if filled {
+            out
+        } else {
+            String::new()
+        }
+    }
+}
+
+// ── PyO3 класс ──
+
+/// Профиль пользователя для промпта; наполняется observe_* по ходу разговора
+#[pyclass(frozen)]
+pub struct UserProfile {
+    user_id: String,
+    /// Источник фактов и хранилище по умолчанию
+    memory: Option<Py<MemoryEngine>>,
+    /// None — профиль только в памяти процесса
+    store: Option<Arc<Store>>,
+    data: Mutex<ProfileData>,
+    tokens: TokenProfile,
+}
+
+impl UserProfile {
+    fn with_store(user_id: String, store: Option<Arc<Store>>) -> PyResult<Self> {
+        let profile = Self {
+            user_id,
+            memory: None,
+            store,
+            data: Mutex::new(ProfileData::default()),
+            tokens: TokenProfile::default(),
+        };
+        profile.load()?;
+        Ok(profile)
+    }
+
+    fn require_store(&self) -> PyResult<&Store> {
+        self.store.as_deref().ok_or_else(|| {
+            PyValueError::new_err("UserProfile без хранилища: укажите memory, storage или data_dir")
+        })
+    }
+}
+
+#[pymethods]
+impl UserProfile {
+    /// Хранилище: memory (рядом с его данными), storage, data_dir или
+    /// memory.dir / data_dir из config; ничего из этого — профиль только в памяти
+    #[new]
+    #[pyo3(signature = (user_id, memory=None, *, storage=None, data_dir=None, config=None))]
+    fn py_new(
+        user_id: String,
+        memory: Option<Py<MemoryEngine>>,
+        storage: Option<PyRef<'_, Storage>>,
+        data_dir: Option<String>,
+        config: Option<PyRef<'_, CoreConfig>>,
+    ) -> PyResult<Self> {
+        let store = match (&memory, storage) {
+            (Some(memory), _) => Some(memory.get().store()),
+            (None, Some(storage)) => Some(storage.shared()),
+            (None, None) => match data_dir.or_else(|| config::settings(config).memory_dir()) {
+                Some(dir) => Some(storage::open(Path::new(&dir)).map_err(PersistenceError::new_err)?),
+                None => None,
+            },
+        };
+        let mut profile = Self::with_store(user_id, store)?;
+        profile.memory = memory;
+        Ok(profile)
+    }
+
+    #[getter]
+    fn user_id(&self) -> String {
+        self.user_id.clone()
+    }
+
+    /// Сообщений учтено в часах активности
+    #[getter]
+    fn messages(&self) -> u64 {
+        self.data.lock().messages
+    }
+
+    /// Эмоция реплики пользователя с уверенностью в [0, 1]
+    #[pyo3(signature = (emotion, confidence=1.0))]
+    fn observe_emotion(&self, emotion: &str, confidence: f64) -> PyResult<()> {
+        if !(0.0..=1.0).contains(&confidence) {
+            return Err(PyValueError::new_err(format!("confidence должна быть в [0, 1]: {}", confidence)));
+        }
+        self.data.lock().observe_emotion(emotion, confidence);
+        Ok(())
+    }
+
+    /// Тема и сущности нити (ThreadTracker.get_current_topic / get_entities)
+    #[pyo3(signature = (topic, entities=None))]
+    fn observe_topic(&self, topic: Option<&str>, entities: Option<Vec<String>>) {
+        self.data.lock().observe_topic(topic, &entities.unwrap_or_default());
+    }
+
+    /// Сообщение пользователя в момент timestamp (RFC 3339; час — по его смещению),
+    /// по умолчанию — сейчас по местному времени
+    #[pyo3(signature = (timestamp=None))]
+    fn observe_activity(&self, timestamp: Option<&str>) -> PyResult<()> {
+        let hour = match timestamp {
+            Some(ts) => DateTime::parse_from_rfc3339(ts)
+                .map_err(|e| PyValueError::new_err(format!("Некорректная отметка времени {:?}: {}", ts, e)))?
+                .hour(),
+            None => Local::now().hour(),
+        };
+        self.data.lock().observe_hour(hour);
+        Ok(())
+    }
+
+    /// Все сигналы сессии за реплику: эмоция, тема и сущности нити, час активности
+    fn observe_session(&self, session: PyRef<'_, Session>) {
+        let (_, _, emotion) = session.get_mood();
+        let topic = session.get_current_topic();
+        let entities = session.get_entities();
+        let hour = session.last_active_at().with_timezone(&Local).hour();
+        let mut data = self.data.lock();
+        data.observe_emotion(&emotion, 1.0);
+        data.observe_topic(topic.as_deref(), &entities);
+        data.observe_hour(hour);
+    }
+
+    /// Блок профиля для промпта не длиннее max_tokens; "" — профиль пуст
+    #[pyo3(signature = (max_tokens=120))]
+    fn render(&self, max_tokens: usize) -> String {
+        let facts = self.memory.as_ref().map(|m| m.get().facts()).unwrap_or_default();
+        self.data.lock().render(&facts, max_tokens, &self.tokens)
+    }
+
+    /// [(тема, счёт)] — самые частые
+    #[pyo3(signature = (n=TOP_TERMS))]
+    fn top_topics(&self, n: usize) -> Vec<(String, u32)> {
+        top(&self.data.lock().topics, n)
+    }
+
+    #[pyo3(signature = (n=TOP_TERMS))]
+    fn top_entities(&self, n: usize) -> Vec<(String, u32)> {
+        top(&self.data.lock().entities, n)
+    }
+
+    /// {эмоция: доля}; доли в сумме дают 1
+    fn emotion_stats(&self) -> HashMap<String, f64> {
+        self.data.lock().emotion_shares().into_iter().collect()
+    }
+
+    /// Самые активные часы суток, по возрастанию
+    #[pyo3(signature = (n=TOP_HOURS))]
+    fn active_hours(&self, n: usize) -> Vec<u32> {
+        self.data.lock().active_hours(n)
+    }
+
+    fn clear(&self) {
+        *self.data.lock() = ProfileData::default();
+    }
+
+    /// Сбой записи → PersistenceError; без хранилища → ValueError
+    fn save(&self) -> PyResult<()> {
+        let store = self.require_store()?;
+        let data = serde_json::to_vec(&*self.data.lock())
+            .map_err(|e| errors::persistence("сериализовать профиль для", store.path(), e))?;
+        store.put(PROFILES, &self.user_id, &data).map_err(|e| store.error("сохранить профиль в", e))?;
+        log::debug!("Профиль {} сохранён в {}", self.user_id, store.path().display());
+        Ok(())
+    }
+
+    /// Перечитать из хранилища; записи нет — профиль не меняется,
+    /// битая запись → PersistenceError
+    fn load(&self) -> PyResult<()> {
+        let Some(store) = self.store.as_deref() else {
+            return Ok(());
+        };
+        let Some(raw) = store.get(PROFILES, &self.user_id).map_err(|e| store.error("прочитать профиль из", e))? else {
+            return Ok(());
+        };
+        let data: ProfileData = serde_json::from_slice(&raw)
+            .map_err(|e| errors::persistence("разобрать профиль из", store.path(), e))?;
+        *self.data.lock() = data;
+        Ok(())
+    }
+
+    /// save без блокировки цикла asyncio
+    fn save_async(slf: Py<Self>, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().save())
+    }
+
+    fn load_async(slf: Py<Self>, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        async_io::spawn(py, move || slf.get().load())
+    }
+
+    fn __repr__(&self) -> String {
+        let data = self.data.lock();
+        format!(
+            "UserProfile(user_id={:?}, messages={}, topics={}, entities={})",
+            self.user_id,
+            data.messages,
+            data.topics.len(),
+            data.entities.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signals_and_render_budget() {
+        let mut data = ProfileData::default();
+        for _ in 0..3 {
+            data.observe_topic(Some("переезд"), &["Казань".to_string()]);
+        }
+        data.observe_topic(Some("работа"), &["Python".to_string()]);
+        data.observe_emotion("positive", 0.9);
+        data.observe_emotion("negative", 0.3);
+        for hour in [21, 21, 9] {
+            data.observe_hour(hour);
+        }
+        assert_eq!(top(&data.topics, 5), vec![("переезд".to_string(), 3), ("работа".to_string(), 1)]);
+        assert_eq!(data.active_hours(3), vec![9, 21]);
+        assert!((data.emotion_shares()[0].1 - 0.75).abs() < 1e-9);
+
+        let tokens = TokenProfile::default();
+        let facts = vec![("город".to_string(), "Казань".to_string())];
+        let full = data.render(&facts, 500, &tokens);
+        assert!(full.starts_with(HEADER));
+        assert!(full.contains("- Факты: город: Казань"));
+        assert!(full.contains("- Частые темы: переезд, работа"));
+        assert!(full.contains("- Эмоции: positive 75%, negative 25%"));
+        assert!(full.contains("- Активен в часы: 9, 21"));
+
+        // Малый бюджет: остаются первые разделы, оценка не превышает бюджет
+        let short = data.render(&facts, 30, &tokens);
+        assert!(short.contains("Факты") && !short.contains("Активен"));
+        assert!(tokens.estimate(&short) <= 30);
+        assert_eq!(ProfileData::default().render(&[], 100, &tokens), "");
+    }
+
+    #[test]
+    fn test_rarest_terms_evicted() {
+        let mut terms = HashMap::new();
+        bump(&mut terms, "частая");
+        bump(&mut terms, "частая");
+        for i in 0..MAX_TERMS + 10 {
+            bump(&mut terms, &format!("тема {}", i));
+        }
+        assert_eq!(terms.len(), MAX_TERMS);
+        assert_eq!(terms["частая"], 2);
+        bump(&mut terms, "  ");
+        assert_eq!(terms.len(), MAX_TERMS);
+    }
+
+    #[test]
+    fn test_save_and_reload() {
+        let dir = std::env::temp_dir().join(format!("kristina_profile_{}", std::process::id()));
+        let store = storage::open(&dir).unwrap();
+        let profile = UserProfile::with_store("u1".to_string(), Some(Arc::clone(&store))).unwrap();
+        profile.observe_topic(Some("переезд"), Some(vec!["Казань".to_string()]));
+        profile.observe_emotion("curious", 0.5).unwrap();
+        profile.observe_activity(Some("2026-03-01T08:30:00+03:00")).unwrap();
+        profile.save().unwrap();
+
+        let restored = UserProfile::with_store("u1".to_string(), Some(Arc::clone(&store))).unwrap();
+        assert_eq!(restored.top_topics(5), vec![("переезд".to_string(), 1)]);
+        assert_eq!(restored.active_hours(3), vec![8]);
+        assert_eq!(restored.messages(), 1);
+        // Другой пользователь — свой профиль
+        let other = UserProfile::with_store("u2".to_string(), Some(Arc::clone(&store))).unwrap();
+        assert_eq!(other.messages(), 0);
+
+        store.put(PROFILES, "u1", b"{not json").unwrap();
+        assert!(UserProfile::with_store("u1".to_string(), Some(store)).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}